@@ -0,0 +1,4 @@
+// New files under migrations/ are embedded by sqlx::migrate!, which cargo can't see on its own
+fn main() {
+    println!("cargo:rerun-if-changed=migrations");
+}
@@ -0,0 +1,234 @@
+-- The schema as it stood when migrations became versioned files. Numbering carries on from the
+-- PRAGMA user_version the old migrate() stamped (17), so the version only ever goes up.
+-- Everything is IF NOT EXISTS: on an existing install the tables are already there and
+-- db::migrate() has brought their columns up to date before this runs.
+--
+-- Never edit an applied migration (its checksum is recorded); add a new, higher-numbered file.
+
+CREATE TABLE IF NOT EXISTS users (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    username TEXT UNIQUE NOT NULL,
+    password_hash TEXT NOT NULL,
+    role TEXT NOT NULL DEFAULT 'viewer',
+    enabled INTEGER NOT NULL DEFAULT 1,
+    created_at TEXT NOT NULL DEFAULT (datetime('now')),
+    last_login TEXT,
+    -- OpenID Connect identity ("<issuer>#<sub>")
+    oidc_subject TEXT,
+    -- LDAP / Active Directory entry
+    ldap_dn TEXT,
+    -- When the password was last set, for the policy's maximum age; NULL means at creation
+    password_changed_at TEXT
+);
+CREATE UNIQUE INDEX IF NOT EXISTS idx_users_oidc_subject ON users(oidc_subject);
+CREATE UNIQUE INDEX IF NOT EXISTS idx_users_ldap_dn ON users(ldap_dn COLLATE NOCASE);
+
+CREATE TABLE IF NOT EXISTS sessions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id INTEGER NOT NULL,
+    token_hash TEXT UNIQUE NOT NULL,
+    created_at TEXT NOT NULL DEFAULT (datetime('now')),
+    expires_at TEXT NOT NULL,
+    ip_address TEXT,
+    user_agent TEXT,
+    revoked_at TEXT,
+    last_seen_at TEXT,
+    remember INTEGER NOT NULL DEFAULT 0,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+CREATE INDEX IF NOT EXISTS idx_sessions_token ON sessions(token_hash);
+CREATE INDEX IF NOT EXISTS idx_sessions_expires ON sessions(expires_at);
+
+-- One row per refresh token handed out; a family is every token issued to one remembered device
+CREATE TABLE IF NOT EXISTS refresh_tokens (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    family_id TEXT NOT NULL,
+    user_id INTEGER NOT NULL,
+    token_hash TEXT UNIQUE NOT NULL,
+    session_id INTEGER,
+    created_at TEXT NOT NULL DEFAULT (datetime('now')),
+    expires_at TEXT NOT NULL,
+    used_at TEXT,
+    revoked_at TEXT,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE,
+    FOREIGN KEY (session_id) REFERENCES sessions(id) ON DELETE SET NULL
+);
+CREATE INDEX IF NOT EXISTS idx_refresh_tokens_family ON refresh_tokens(family_id);
+
+-- Sign-in attempts; user_id is NULL when the username matched nobody
+CREATE TABLE IF NOT EXISTS login_history (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id INTEGER,
+    username TEXT NOT NULL DEFAULT '',
+    method TEXT NOT NULL,
+    result TEXT NOT NULL,
+    ip_address TEXT,
+    user_agent TEXT,
+    created_at TEXT NOT NULL,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+CREATE INDEX IF NOT EXISTS idx_login_history_user ON login_history(user_id, created_at);
+
+CREATE TABLE IF NOT EXISTS settings (
+    key TEXT PRIMARY KEY,
+    value TEXT NOT NULL,
+    updated_at TEXT NOT NULL DEFAULT (datetime('now'))
+);
+
+CREATE TABLE IF NOT EXISTS maintenance_log (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    ran_at TEXT NOT NULL,
+    rows_deleted INTEGER NOT NULL DEFAULT 0,
+    vacuumed INTEGER NOT NULL DEFAULT 0,
+    duration_ms INTEGER NOT NULL DEFAULT 0
+);
+
+CREATE TABLE IF NOT EXISTS wan_ip_history (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    ip TEXT NOT NULL,
+    source TEXT NOT NULL,
+    changed_at TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS acme_settings (
+    id INTEGER PRIMARY KEY CHECK (id = 1),
+    directory_url TEXT NOT NULL,
+    email TEXT NOT NULL DEFAULT '',
+    provider TEXT NOT NULL DEFAULT 'cloudflare',
+    api_token TEXT NOT NULL DEFAULT '',
+    zone_id TEXT,
+    propagation_timeout INTEGER NOT NULL DEFAULT 300,
+    account_url TEXT,
+    updated_at TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS certificates (
+    name TEXT PRIMARY KEY,
+    domains TEXT NOT NULL,
+    status TEXT NOT NULL DEFAULT 'pending',
+    local_ip TEXT,
+    issued_at TEXT,
+    expires_at TEXT,
+    last_error TEXT,
+    updated_at TEXT NOT NULL
+);
+
+-- Blocklist entries per feed, for reputation lookups; IPv4 entries also as an integer range
+CREATE TABLE IF NOT EXISTS ip_reputation (
+    entry TEXT NOT NULL,
+    source TEXT NOT NULL,
+    range_start INTEGER,
+    range_end INTEGER,
+    first_seen TEXT NOT NULL,
+    last_seen TEXT NOT NULL,
+    PRIMARY KEY (entry, source)
+);
+CREATE INDEX IF NOT EXISTS idx_ip_reputation_range ON ip_reputation(range_start, range_end);
+
+CREATE TABLE IF NOT EXISTS ip_reputation_feeds (
+    source TEXT PRIMARY KEY,
+    updated_at TEXT NOT NULL,
+    entries INTEGER NOT NULL DEFAULT 0
+);
+
+-- OpenWrt access points RouterUI pushes its WiFi settings to
+CREATE TABLE IF NOT EXISTS managed_aps (
+    id TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    host TEXT NOT NULL,
+    port INTEGER NOT NULL DEFAULT 22,
+    username TEXT NOT NULL DEFAULT 'root',
+    lan_network TEXT NOT NULL DEFAULT 'lan',
+    guest_network TEXT,
+    model TEXT,
+    firmware TEXT,
+    last_sync_at TEXT,
+    last_error TEXT,
+    created_at TEXT NOT NULL,
+    UNIQUE (host, port)
+);
+
+-- DHCP pool usage, sampled every few minutes
+CREATE TABLE IF NOT EXISTS dhcp_pool_samples (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    sampled_at TEXT NOT NULL,
+    size INTEGER NOT NULL,
+    used INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS idx_dhcp_pool_samples_sampled_at ON dhcp_pool_samples(sampled_at);
+
+-- Optional NAT/connection log; empty unless switched on
+CREATE TABLE IF NOT EXISTS connection_log (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    protocol TEXT NOT NULL,
+    src_ip TEXT NOT NULL,
+    src_port INTEGER,
+    dst_ip TEXT NOT NULL,
+    dst_port INTEGER,
+    nat_src_ip TEXT,
+    nat_src_port INTEGER,
+    nat_dst_ip TEXT,
+    nat_dst_port INTEGER,
+    first_seen TEXT NOT NULL,
+    last_seen TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS idx_connection_log_first_seen ON connection_log(first_seen);
+
+-- Host power draw, sampled every minute where the hardware reports it
+CREATE TABLE IF NOT EXISTS power_samples (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    sampled_at TEXT NOT NULL,
+    watts REAL NOT NULL,
+    energy_wh REAL NOT NULL,
+    method TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS idx_power_samples_sampled_at ON power_samples(sampled_at);
+
+-- Bytes moved over the LTE modem since the previous sample
+CREATE TABLE IF NOT EXISTS modem_usage (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    sampled_at TEXT NOT NULL,
+    rx_bytes INTEGER NOT NULL,
+    tx_bytes INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS idx_modem_usage_sampled_at ON modem_usage(sampled_at);
+
+-- One row per uptime check of a monitored service
+CREATE TABLE IF NOT EXISTS monitor_checks (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    monitor_id TEXT NOT NULL,
+    checked_at TEXT NOT NULL,
+    up INTEGER NOT NULL,
+    status_code INTEGER,
+    latency_ms INTEGER,
+    error TEXT
+);
+CREATE INDEX IF NOT EXISTS idx_monitor_checks_monitor ON monitor_checks(monitor_id, checked_at);
+
+-- Long-lived keys for scripts, sent as `Authorization: Bearer rk_...`
+CREATE TABLE IF NOT EXISTS api_tokens (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    token_hash TEXT UNIQUE NOT NULL,
+    prefix TEXT NOT NULL,
+    scope TEXT NOT NULL,
+    created_at TEXT NOT NULL DEFAULT (datetime('now')),
+    last_used_at TEXT,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+-- Grants of users with the "custom" role, e.g. "wol:write"
+CREATE TABLE IF NOT EXISTS user_permissions (
+    user_id INTEGER NOT NULL,
+    permission TEXT NOT NULL,
+    PRIMARY KEY (user_id, permission),
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+-- Which migrations have run, for humans poking at the file with sqlite3. sqlx keeps the
+-- authoritative record (with checksums) in _sqlx_migrations.
+CREATE VIEW IF NOT EXISTS schema_version AS
+    SELECT version, description, installed_on
+    FROM _sqlx_migrations
+    WHERE success = 1;
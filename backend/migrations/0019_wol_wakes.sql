@@ -0,0 +1,17 @@
+-- Who woke which device, from where; see crate::wol
+CREATE TABLE IF NOT EXISTS wol_wakes (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    woken_at TEXT NOT NULL,
+    mac_address TEXT NOT NULL,
+    device_name TEXT,
+    username TEXT NOT NULL,
+    source_ip TEXT NOT NULL,
+    -- "lan", "tailscale" or "remote"
+    origin TEXT NOT NULL,
+    -- Tailscale login and node of the requester, when it came over the tailnet
+    tailscale_identity TEXT,
+    interface TEXT NOT NULL,
+    success INTEGER NOT NULL,
+    error TEXT
+);
+CREATE INDEX IF NOT EXISTS idx_wol_wakes_woken_at ON wol_wakes(woken_at);
@@ -0,0 +1,12 @@
+-- What iptables can't hold for port forwards and blocked IPs; see crate::db::firewall_rules
+CREATE TABLE IF NOT EXISTS firewall_rules (
+    -- "port_forward" or "blocked_ip"
+    kind TEXT NOT NULL,
+    -- "tcp:8443:192.168.1.10:443" for a port forward, the address or range for a blocked IP
+    rule_key TEXT NOT NULL,
+    description TEXT NOT NULL DEFAULT '',
+    enabled INTEGER NOT NULL DEFAULT 1,
+    created_by TEXT,
+    created_at TEXT NOT NULL,
+    PRIMARY KEY (kind, rule_key)
+);
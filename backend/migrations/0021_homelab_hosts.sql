@@ -0,0 +1,14 @@
+-- Other Linux machines RouterUI can reach over SSH; see crate::homelab
+CREATE TABLE IF NOT EXISTS homelab_hosts (
+    id TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    host TEXT NOT NULL,
+    port INTEGER NOT NULL DEFAULT 22,
+    username TEXT NOT NULL,
+    -- For Wake-on-LAN; hosts without one can't be woken
+    mac_address TEXT,
+    -- "Linux 6.1.0-18-amd64", read when the host was added
+    os TEXT,
+    created_at TEXT NOT NULL,
+    UNIQUE (host, port)
+);
@@ -0,0 +1,15 @@
+-- System metrics for the history charts; see crate::metrics. Raw samples are rolled up into
+-- coarser buckets as they age, `resolution` being the bucket width in seconds.
+CREATE TABLE IF NOT EXISTS metrics (
+    -- "cpu", "memory", "connections", "rx:<interface>", "tx:<interface>"
+    metric TEXT NOT NULL,
+    resolution INTEGER NOT NULL,
+    -- Unix time the bucket starts at
+    bucket INTEGER NOT NULL,
+    avg REAL NOT NULL,
+    min REAL NOT NULL,
+    max REAL NOT NULL,
+    samples INTEGER NOT NULL,
+    PRIMARY KEY (metric, resolution, bucket)
+);
+CREATE INDEX IF NOT EXISTS idx_metrics_resolution_bucket ON metrics(resolution, bucket);
@@ -0,0 +1,19 @@
+-- Blocklists and country blocks, formerly /opt/routerui/blocklists/*.json; see crate::db::protection
+CREATE TABLE IF NOT EXISTS protection_lists (
+    -- "blocklist" or "country"
+    kind TEXT NOT NULL,
+    -- Blocklist id ("spamhaus-drop") or country code ("CN")
+    id TEXT NOT NULL,
+    enabled INTEGER NOT NULL DEFAULT 0,
+    -- Addresses or ranges loaded the last time the list was downloaded
+    entry_count INTEGER NOT NULL DEFAULT 0,
+    updated_at TEXT,
+    PRIMARY KEY (kind, id)
+);
+
+-- Formerly /opt/routerui/protection-whitelist.json
+CREATE TABLE IF NOT EXISTS protection_whitelist (
+    ip TEXT PRIMARY KEY,
+    description TEXT NOT NULL DEFAULT '',
+    added_at TEXT NOT NULL
+);
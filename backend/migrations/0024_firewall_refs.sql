@@ -0,0 +1,13 @@
+-- Which device a firewall rule was made for; see crate::db::firewall_rules
+CREATE TABLE IF NOT EXISTS firewall_refs (
+    -- A row of firewall_rules
+    rule_kind TEXT NOT NULL,
+    rule_key TEXT NOT NULL,
+    -- "device", by lowercase MAC address; static leases and Wake-on-LAN devices share it
+    object_kind TEXT NOT NULL,
+    object_id TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    PRIMARY KEY (rule_kind, rule_key, object_kind, object_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_firewall_refs_object ON firewall_refs (object_kind, object_id);
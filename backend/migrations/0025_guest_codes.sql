@@ -0,0 +1,12 @@
+-- Temporary guest WiFi passwords; see crate::guestcodes
+CREATE TABLE IF NOT EXISTS guest_codes (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    code TEXT NOT NULL,
+    label TEXT NOT NULL DEFAULT '',
+    created_by TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    expires_at TEXT NOT NULL,
+    revoked_at TEXT
+);
+
+CREATE INDEX IF NOT EXISTS idx_guest_codes_expires ON guest_codes (expires_at);
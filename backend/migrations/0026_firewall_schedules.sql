@@ -0,0 +1,2 @@
+-- When a port forward or blocked IP is in force, as a JSON crate::scheduler::Schedule; NULL means always
+ALTER TABLE firewall_rules ADD COLUMN schedule TEXT;
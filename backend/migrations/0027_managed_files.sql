@@ -0,0 +1,7 @@
+-- What RouterUI last wrote to each tracked system file; see crate::system::drift
+CREATE TABLE IF NOT EXISTS managed_files (
+    path TEXT PRIMARY KEY,
+    sha256 TEXT NOT NULL,
+    written_at TEXT NOT NULL,
+    content TEXT NOT NULL
+);
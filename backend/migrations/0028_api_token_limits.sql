@@ -0,0 +1,4 @@
+-- Endpoints an API key is limited to, one pattern per line; NULL for any the owner can use
+ALTER TABLE api_tokens ADD COLUMN allowed_paths TEXT;
+-- NULL for a key that never expires
+ALTER TABLE api_tokens ADD COLUMN expires_at TEXT;
@@ -0,0 +1,259 @@
+// Minimal RFC 8555 client: ES256 account key, DNS-01 only
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use ring::rand::SystemRandom;
+use ring::signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_FIXED_SIGNING};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::time::Duration;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(3);
+const POLL_ATTEMPTS: u32 = 40;
+
+fn b64(data: impl AsRef<[u8]>) -> String {
+    URL_SAFE_NO_PAD.encode(data)
+}
+
+/// New PKCS#8 P-256 key for an ACME account
+pub fn generate_account_key() -> Result<Vec<u8>, String> {
+    let rng = SystemRandom::new();
+    EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &rng)
+        .map(|doc| doc.as_ref().to_vec())
+        .map_err(|_| "Failed to generate account key".to_string())
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Directory {
+    new_nonce: String,
+    new_account: String,
+    new_order: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Order {
+    pub status: String,
+    #[serde(default)]
+    pub authorizations: Vec<String>,
+    pub finalize: String,
+    pub certificate: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Identifier {
+    pub value: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Challenge {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub url: String,
+    pub token: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Authorization {
+    pub status: String,
+    pub identifier: Identifier,
+    #[serde(default)]
+    pub challenges: Vec<Challenge>,
+    #[serde(default)]
+    pub wildcard: bool,
+}
+
+pub struct AcmeClient {
+    http: reqwest::Client,
+    directory: Directory,
+    key: EcdsaKeyPair,
+    rng: SystemRandom,
+    kid: Option<String>,
+    nonce: Option<String>,
+}
+
+impl AcmeClient {
+    pub async fn connect(directory_url: &str, account_key: &[u8]) -> Result<Self, String> {
+        let rng = SystemRandom::new();
+        let key = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, account_key, &rng)
+            .map_err(|_| "Invalid ACME account key".to_string())?;
+        let http = reqwest::Client::builder()
+            .timeout(Duration::from_secs(30))
+            .user_agent(concat!("RouterUI/", env!("CARGO_PKG_VERSION")))
+            .build()
+            .map_err(|e| e.to_string())?;
+
+        let directory = http
+            .get(directory_url)
+            .send()
+            .await
+            .map_err(|e| format!("ACME directory: {}", e))?
+            .json::<Directory>()
+            .await
+            .map_err(|e| format!("ACME directory: {}", e))?;
+
+        Ok(Self { http, directory, key, rng, kid: None, nonce: None })
+    }
+
+    fn jwk(&self) -> Value {
+        // Uncompressed point: 0x04 || x || y
+        let point = self.key.public_key().as_ref();
+        json!({ "crv": "P-256", "kty": "EC", "x": b64(&point[1..33]), "y": b64(&point[33..65]) })
+    }
+
+    /// base64url(SHA-256(JWK)) with members in lexicographic order, per RFC 7638
+    fn thumbprint(&self) -> String {
+        let jwk = self.jwk();
+        let canonical = format!(
+            r#"{{"crv":"P-256","kty":"EC","x":"{}","y":"{}"}}"#,
+            jwk["x"].as_str().unwrap_or_default(),
+            jwk["y"].as_str().unwrap_or_default()
+        );
+        b64(ring::digest::digest(&ring::digest::SHA256, canonical.as_bytes()))
+    }
+
+    /// Value to publish in the `_acme-challenge` TXT record for `token`
+    pub fn dns_txt_value(&self, token: &str) -> String {
+        let key_authorization = format!("{}.{}", token, self.thumbprint());
+        b64(ring::digest::digest(&ring::digest::SHA256, key_authorization.as_bytes()))
+    }
+
+    async fn fresh_nonce(&mut self) -> Result<String, String> {
+        if let Some(nonce) = self.nonce.take() {
+            return Ok(nonce);
+        }
+        let response = self
+            .http
+            .head(&self.directory.new_nonce)
+            .send()
+            .await
+            .map_err(|e| format!("ACME nonce: {}", e))?;
+        header(&response, "replay-nonce").ok_or_else(|| "ACME server sent no nonce".to_string())
+    }
+
+    fn sign(&self, protected: &Value, payload: &str) -> Result<Value, String> {
+        let protected = b64(protected.to_string());
+        let signature = self
+            .key
+            .sign(&self.rng, format!("{}.{}", protected, payload).as_bytes())
+            .map_err(|_| "Failed to sign ACME request".to_string())?;
+        Ok(json!({ "protected": protected, "payload": payload, "signature": b64(signature) }))
+    }
+
+    /// Signed POST; `payload: None` is a POST-as-GET. Retries once on badNonce.
+    async fn post(&mut self, url: &str, payload: Option<&Value>) -> Result<reqwest::Response, String> {
+        let payload = payload.map(|p| b64(p.to_string())).unwrap_or_default();
+
+        for attempt in 0..2 {
+            let nonce = self.fresh_nonce().await?;
+            let mut protected = json!({ "alg": "ES256", "nonce": nonce, "url": url });
+            match &self.kid {
+                Some(kid) => protected["kid"] = json!(kid),
+                None => protected["jwk"] = self.jwk(),
+            }
+
+            let response = self
+                .http
+                .post(url)
+                .header("content-type", "application/jose+json")
+                .json(&self.sign(&protected, &payload)?)
+                .send()
+                .await
+                .map_err(|e| format!("ACME request: {}", e))?;
+            self.nonce = header(&response, "replay-nonce");
+
+            if response.status().is_success() {
+                return Ok(response);
+            }
+
+            let problem: Value = response.json().await.unwrap_or_default();
+            let kind = problem["type"].as_str().unwrap_or_default();
+            if attempt == 0 && kind.ends_with(":badNonce") {
+                continue;
+            }
+            return Err(format!(
+                "ACME error: {} ({})",
+                problem["detail"].as_str().unwrap_or("unknown error"),
+                kind
+            ));
+        }
+        unreachable!()
+    }
+
+    /// Create or look up the account for this key; returns the account URL
+    pub async fn register(&mut self, email: &str) -> Result<String, String> {
+        let mut payload = json!({ "termsOfServiceAgreed": true });
+        if !email.is_empty() {
+            payload["contact"] = json!([format!("mailto:{}", email)]);
+        }
+        let url = self.directory.new_account.clone();
+        let response = self.post(&url, Some(&payload)).await?;
+        let kid = header(&response, "location").ok_or_else(|| "ACME account has no location".to_string())?;
+        self.kid = Some(kid.clone());
+        Ok(kid)
+    }
+
+    pub async fn new_order(&mut self, domains: &[String]) -> Result<(String, Order), String> {
+        let identifiers: Vec<Value> = domains.iter().map(|d| json!({ "type": "dns", "value": d })).collect();
+        let url = self.directory.new_order.clone();
+        let response = self.post(&url, Some(&json!({ "identifiers": identifiers }))).await?;
+        let order_url = header(&response, "location").ok_or_else(|| "ACME order has no location".to_string())?;
+        let order = response.json().await.map_err(|e| e.to_string())?;
+        Ok((order_url, order))
+    }
+
+    pub async fn authorization(&mut self, url: &str) -> Result<Authorization, String> {
+        self.post(url, None).await?.json().await.map_err(|e| e.to_string())
+    }
+
+    /// Tell the server the TXT record is in place
+    pub async fn respond(&mut self, challenge_url: &str) -> Result<(), String> {
+        self.post(challenge_url, Some(&json!({}))).await.map(|_| ())
+    }
+
+    pub async fn wait_for_authorization(&mut self, url: &str) -> Result<(), String> {
+        for _ in 0..POLL_ATTEMPTS {
+            let response = self.post(url, None).await?;
+            let authz: Value = response.json().await.map_err(|e| e.to_string())?;
+            match authz["status"].as_str() {
+                Some("valid") => return Ok(()),
+                Some("pending") | Some("processing") => tokio::time::sleep(POLL_INTERVAL).await,
+                _ => {
+                    let detail = authz["challenges"]
+                        .as_array()
+                        .and_then(|c| c.iter().find_map(|c| c["error"]["detail"].as_str()))
+                        .unwrap_or("authorization failed");
+                    return Err(format!(
+                        "{}: {}",
+                        authz["identifier"]["value"].as_str().unwrap_or_default(),
+                        detail
+                    ));
+                }
+            }
+        }
+        Err("Timed out waiting for ACME authorization".to_string())
+    }
+
+    pub async fn finalize(&mut self, finalize_url: &str, csr_der: &[u8]) -> Result<(), String> {
+        self.post(finalize_url, Some(&json!({ "csr": b64(csr_der) }))).await.map(|_| ())
+    }
+
+    pub async fn wait_for_certificate(&mut self, order_url: &str) -> Result<String, String> {
+        for _ in 0..POLL_ATTEMPTS {
+            let order: Order = self.post(order_url, None).await?.json().await.map_err(|e| e.to_string())?;
+            match (order.status.as_str(), &order.certificate) {
+                ("valid", Some(cert_url)) => {
+                    let cert_url = cert_url.clone();
+                    return self.post(&cert_url, None).await?.text().await.map_err(|e| e.to_string());
+                }
+                ("processing", _) | ("ready", _) | ("valid", None) => tokio::time::sleep(POLL_INTERVAL).await,
+                (status, _) => return Err(format!("ACME order became {}", status)),
+            }
+        }
+        Err("Timed out waiting for the certificate".to_string())
+    }
+}
+
+fn header(response: &reqwest::Response, name: &str) -> Option<String> {
+    response.headers().get(name).and_then(|v| v.to_str().ok()).map(|s| s.to_string())
+}
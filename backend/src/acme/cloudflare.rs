@@ -0,0 +1,114 @@
+// Cloudflare DNS API: just enough to publish and remove `_acme-challenge` TXT records
+
+use serde_json::{json, Value};
+use std::time::Duration;
+
+const API: &str = "https://api.cloudflare.com/client/v4";
+
+pub struct Cloudflare {
+    http: reqwest::Client,
+    token: String,
+    zone_id: Option<String>,
+}
+
+impl Cloudflare {
+    pub fn new(token: &str, zone_id: Option<&str>) -> Result<Self, String> {
+        let http = reqwest::Client::builder()
+            .timeout(Duration::from_secs(20))
+            .build()
+            .map_err(|e| e.to_string())?;
+        Ok(Self {
+            http,
+            token: token.to_string(),
+            zone_id: zone_id.filter(|z| !z.is_empty()).map(|z| z.to_string()),
+        })
+    }
+
+    async fn request(&self, method: reqwest::Method, path: &str, body: Option<Value>) -> Result<Value, String> {
+        let mut request = self
+            .http
+            .request(method, format!("{}{}", API, path))
+            .bearer_auth(&self.token);
+        if let Some(body) = body {
+            request = request.json(&body);
+        }
+
+        let response: Value = request
+            .send()
+            .await
+            .map_err(|e| format!("Cloudflare: {}", e))?
+            .json()
+            .await
+            .map_err(|e| format!("Cloudflare: {}", e))?;
+
+        if response["success"].as_bool() != Some(true) {
+            let message = response["errors"]
+                .as_array()
+                .and_then(|e| e.first())
+                .and_then(|e| e["message"].as_str())
+                .unwrap_or("request failed");
+            return Err(format!("Cloudflare: {}", message));
+        }
+        Ok(response["result"].clone())
+    }
+
+    /// Configured zone, or the closest enclosing zone the token can see
+    async fn zone_for(&self, name: &str) -> Result<String, String> {
+        if let Some(zone) = &self.zone_id {
+            return Ok(zone.clone());
+        }
+
+        let labels: Vec<&str> = name.split('.').collect();
+        for i in 0..labels.len().saturating_sub(1) {
+            let candidate = labels[i..].join(".");
+            let result = self
+                .request(reqwest::Method::GET, &format!("/zones?name={}", candidate), None)
+                .await?;
+            if let Some(id) = result.as_array().and_then(|z| z.first()).and_then(|z| z["id"].as_str()) {
+                return Ok(id.to_string());
+            }
+        }
+        Err(format!("No Cloudflare zone found for {}", name))
+    }
+
+    /// Returns (zone id, record id) for later removal
+    pub async fn create_txt(&self, name: &str, value: &str) -> Result<(String, String), String> {
+        let zone = self.zone_for(name).await?;
+        let result = self
+            .request(
+                reqwest::Method::POST,
+                &format!("/zones/{}/dns_records", zone),
+                Some(json!({ "type": "TXT", "name": name, "content": value, "ttl": 60 })),
+            )
+            .await?;
+        let id = result["id"].as_str().ok_or("Cloudflare returned no record id")?;
+        Ok((zone, id.to_string()))
+    }
+
+    pub async fn delete_txt(&self, zone: &str, record: &str) -> Result<(), String> {
+        self.request(reqwest::Method::DELETE, &format!("/zones/{}/dns_records/{}", zone, record), None)
+            .await
+            .map(|_| ())
+    }
+
+    /// Check a public resolver (over HTTPS, so local dnsmasq overrides don't interfere)
+    pub async fn txt_visible(&self, name: &str, value: &str) -> bool {
+        let response = self
+            .http
+            .get(format!("https://cloudflare-dns.com/dns-query?name={}&type=TXT", name))
+            .header("accept", "application/dns-json")
+            .send()
+            .await;
+
+        let Ok(response) = response else { return false };
+        let body: Value = response.json().await.unwrap_or_default();
+        body["Answer"]
+            .as_array()
+            .map(|answers| {
+                answers
+                    .iter()
+                    .any(|a| a["data"].as_str().map(|d| d.trim_matches('"') == value).unwrap_or(false))
+            })
+            .unwrap_or(false)
+    }
+}
@@ -0,0 +1,491 @@
+pub mod client;
+pub mod cloudflare;
+
+use axum_server::tls_rustls::RustlsConfig;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+use crate::AppState;
+use client::AcmeClient;
+use cloudflare::Cloudflare;
+
+pub const LETSENCRYPT_PRODUCTION: &str = "https://acme-v02.api.letsencrypt.org/directory";
+pub const LETSENCRYPT_STAGING: &str = "https://acme-staging-v02.api.letsencrypt.org/directory";
+
+const RENEW_BEFORE_DAYS: i64 = 30;
+const CHECK_INTERVAL: Duration = Duration::from_secs(12 * 60 * 60);
+const MAX_DOMAINS: usize = 50;
+
+// One issuance at a time - Let's Encrypt rate limits are per account
+static ISSUING: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+// The UI's TLS config, so a renewed certificate can be swapped in without a restart
+static UI_TLS: OnceLock<RustlsConfig> = OnceLock::new();
+
+// ============ SETTINGS ============
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AcmeSettings {
+    pub directory_url: String,
+    #[serde(default)]
+    pub email: String,
+    // Only Cloudflare for now
+    #[serde(default = "default_provider")]
+    pub provider: String,
+    #[serde(default)]
+    pub api_token: String,
+    pub zone_id: Option<String>,
+    #[serde(default = "default_propagation_timeout")]
+    pub propagation_timeout: i64,
+}
+
+fn default_provider() -> String {
+    "cloudflare".to_string()
+}
+
+fn default_propagation_timeout() -> i64 {
+    300
+}
+
+pub async fn settings(pool: &SqlitePool) -> Result<Option<AcmeSettings>, sqlx::Error> {
+    let row: Option<(String, String, String, String, Option<String>, i64)> = sqlx::query_as(
+        "SELECT directory_url, email, provider, api_token, zone_id, propagation_timeout FROM acme_settings WHERE id = 1",
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.map(|(directory_url, email, provider, api_token, zone_id, propagation_timeout)| AcmeSettings {
+        directory_url,
+        email,
+        provider,
+        api_token,
+        zone_id,
+        propagation_timeout,
+    }))
+}
+
+pub async fn save_settings(pool: &SqlitePool, settings: &AcmeSettings) -> Result<(), sqlx::Error> {
+    // A different CA means a different account
+    sqlx::query(
+        r#"
+        INSERT INTO acme_settings (id, directory_url, email, provider, api_token, zone_id, propagation_timeout, updated_at)
+        VALUES (1, ?, ?, ?, ?, ?, ?, ?)
+        ON CONFLICT(id) DO UPDATE SET
+            account_url = CASE WHEN directory_url = excluded.directory_url THEN account_url ELSE NULL END,
+            directory_url = excluded.directory_url,
+            email = excluded.email,
+            provider = excluded.provider,
+            api_token = excluded.api_token,
+            zone_id = excluded.zone_id,
+            propagation_timeout = excluded.propagation_timeout,
+            updated_at = excluded.updated_at
+        "#,
+    )
+    .bind(&settings.directory_url)
+    .bind(&settings.email)
+    .bind(&settings.provider)
+    .bind(&settings.api_token)
+    .bind(&settings.zone_id)
+    .bind(settings.propagation_timeout)
+    .bind(Utc::now().to_rfc3339())
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+// ============ CERTIFICATES ============
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Certificate {
+    pub name: String,
+    pub domains: Vec<String>,
+    // pending, valid or error
+    pub status: String,
+    pub local_ip: Option<String>,
+    pub issued_at: Option<String>,
+    pub expires_at: Option<String>,
+    pub last_error: Option<String>,
+    pub cert_path: String,
+    pub key_path: String,
+    pub used_by_ui: bool,
+}
+
+type CertRow = (String, String, String, Option<String>, Option<String>, Option<String>, Option<String>);
+
+fn certs_dir() -> PathBuf {
+    crate::db::data_dir().join("certs")
+}
+
+fn cert_paths(name: &str) -> (PathBuf, PathBuf) {
+    let dir = certs_dir().join(name);
+    (dir.join("fullchain.pem"), dir.join("privkey.pem"))
+}
+
+fn account_key_path() -> PathBuf {
+    crate::db::data_dir().join("acme").join("account.pk8")
+}
+
+fn ui_cert_path() -> Option<&'static Path> {
+    crate::config::get().tls.as_ref().map(|t| t.cert.as_path())
+}
+
+fn to_certificate((name, domains, status, local_ip, issued_at, expires_at, last_error): CertRow) -> Certificate {
+    let (cert, key) = cert_paths(&name);
+    Certificate {
+        domains: domains.split(',').map(|d| d.to_string()).collect(),
+        used_by_ui: ui_cert_path() == Some(cert.as_path()),
+        cert_path: cert.display().to_string(),
+        key_path: key.display().to_string(),
+        name,
+        status,
+        local_ip,
+        issued_at,
+        expires_at,
+        last_error,
+    }
+}
+
+pub async fn list(pool: &SqlitePool) -> Result<Vec<Certificate>, sqlx::Error> {
+    let rows: Vec<CertRow> = sqlx::query_as(
+        "SELECT name, domains, status, local_ip, issued_at, expires_at, last_error FROM certificates ORDER BY name",
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(rows.into_iter().map(to_certificate).collect())
+}
+
+async fn get(pool: &SqlitePool, name: &str) -> Result<Option<Certificate>, sqlx::Error> {
+    let row: Option<CertRow> = sqlx::query_as(
+        "SELECT name, domains, status, local_ip, issued_at, expires_at, last_error FROM certificates WHERE name = ?",
+    )
+    .bind(name)
+    .fetch_optional(pool)
+    .await?;
+    Ok(row.map(to_certificate))
+}
+
+async fn set_status(pool: &SqlitePool, name: &str, status: &str, error: Option<&str>) {
+    let _ = sqlx::query("UPDATE certificates SET status = ?, last_error = ?, updated_at = ? WHERE name = ?")
+        .bind(status)
+        .bind(error)
+        .bind(Utc::now().to_rfc3339())
+        .bind(name)
+        .execute(pool)
+        .await;
+}
+
+pub async fn delete(pool: &SqlitePool, name: &str) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM certificates WHERE name = ?")
+        .bind(name)
+        .execute(pool)
+        .await?;
+    // Names in the table were built by certificate_name(), so they're safe path segments
+    if result.rows_affected() == 0 {
+        return Ok(false);
+    }
+    let _ = std::fs::remove_dir_all(certs_dir().join(name));
+    Ok(true)
+}
+
+/// Lower-cased, validated domain list; wildcards are allowed as the leftmost label only
+pub fn normalize_domains(domains: &[String]) -> Result<Vec<String>, String> {
+    let mut out: Vec<String> = Vec::new();
+    for domain in domains {
+        let domain = domain.trim().trim_end_matches('.').to_lowercase();
+        if domain.is_empty() {
+            continue;
+        }
+        let bare = domain.strip_prefix("*.").unwrap_or(&domain);
+        let valid = bare.contains('.')
+            && bare.len() <= 253
+            && bare.split('.').all(|label| {
+                !label.is_empty()
+                    && label.len() <= 63
+                    && !label.starts_with('-')
+                    && !label.ends_with('-')
+                    && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+            });
+        if !valid {
+            return Err(format!("Invalid domain name: {}", domain));
+        }
+        if !out.contains(&domain) {
+            out.push(domain);
+        }
+    }
+
+    match out.len() {
+        0 => Err("At least one domain is required".to_string()),
+        n if n > MAX_DOMAINS => Err(format!("At most {} domains per certificate", MAX_DOMAINS)),
+        _ => Ok(out),
+    }
+}
+
+/// Directory-safe certificate name derived from the first domain
+pub fn certificate_name(domains: &[String]) -> String {
+    domains[0].replacen("*.", "wildcard.", 1)
+}
+
+// ============ ISSUANCE ============
+
+fn write_private(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)?;
+    file.write_all(contents)
+}
+
+fn load_or_create_account_key() -> Result<Vec<u8>, String> {
+    let path = account_key_path();
+    if let Ok(key) = std::fs::read(&path) {
+        return Ok(key);
+    }
+    let key = client::generate_account_key()?;
+    write_private(&path, &key).map_err(|e| format!("{}: {}", path.display(), e))?;
+    Ok(key)
+}
+
+fn certificate_expiry(pem: &str) -> Result<DateTime<Utc>, String> {
+    let (_, pem) = x509_parser::pem::parse_x509_pem(pem.as_bytes()).map_err(|e| e.to_string())?;
+    let cert = pem.parse_x509().map_err(|e| e.to_string())?;
+    DateTime::from_timestamp(cert.validity().not_after.timestamp(), 0).ok_or_else(|| "Invalid expiry".to_string())
+}
+
+// `_acme-challenge.<name>`; a wildcard is validated on its base name
+fn challenge_record(domain: &str) -> String {
+    format!("_acme-challenge.{}", domain.trim_start_matches("*."))
+}
+
+async fn wait_for_propagation(dns: &Cloudflare, records: &[(String, String)], timeout: Duration) -> Result<(), String> {
+    let deadline = tokio::time::Instant::now() + timeout;
+    loop {
+        let mut missing = None;
+        for (name, value) in records {
+            if !dns.txt_visible(name, value).await {
+                missing = Some(name);
+                break;
+            }
+        }
+        let Some(name) = missing else { return Ok(()) };
+        if tokio::time::Instant::now() >= deadline {
+            return Err(format!("{} did not appear in public DNS within {}s", name, timeout.as_secs()));
+        }
+        tokio::time::sleep(Duration::from_secs(10)).await;
+    }
+}
+
+async fn run_order(
+    settings: &AcmeSettings,
+    acme: &mut AcmeClient,
+    dns: &Cloudflare,
+    domains: &[String],
+    created: &mut Vec<(String, String)>,
+) -> Result<(String, String), String> {
+    let (order_url, order) = acme.new_order(domains).await?;
+
+    // Publish every TXT record first, then wait for them together
+    let mut challenges = Vec::new();
+    let mut records = Vec::new();
+    for authz_url in &order.authorizations {
+        let authz = acme.authorization(authz_url).await?;
+        if authz.status == "valid" {
+            continue;
+        }
+        let challenge = authz
+            .challenges
+            .iter()
+            .find(|c| c.kind == "dns-01")
+            .ok_or_else(|| format!("{}: CA offered no dns-01 challenge", authz.identifier.value))?;
+
+        let domain = if authz.wildcard { format!("*.{}", authz.identifier.value) } else { authz.identifier.value.clone() };
+        let name = challenge_record(&domain);
+        let value = acme.dns_txt_value(&challenge.token);
+        tracing::info!("ACME: publishing TXT {}", name);
+        created.push(dns.create_txt(&name, &value).await?);
+        records.push((name, value));
+        challenges.push((authz_url.clone(), challenge.url.clone()));
+    }
+
+    let timeout = Duration::from_secs(settings.propagation_timeout.clamp(30, 1800) as u64);
+    wait_for_propagation(dns, &records, timeout).await?;
+
+    for (authz_url, challenge_url) in &challenges {
+        acme.respond(challenge_url).await?;
+        acme.wait_for_authorization(authz_url).await?;
+    }
+
+    let key = rcgen::KeyPair::generate().map_err(|e| e.to_string())?;
+    let params = rcgen::CertificateParams::new(domains.to_vec()).map_err(|e| e.to_string())?;
+    let csr = params.serialize_request(&key).map_err(|e| e.to_string())?;
+
+    acme.finalize(&order.finalize, csr.der()).await?;
+    let chain = acme.wait_for_certificate(&order_url).await?;
+    Ok((chain, key.serialize_pem()))
+}
+
+async fn issue(pool: &SqlitePool, name: &str) -> Result<DateTime<Utc>, String> {
+    let _guard = ISSUING.lock().await;
+
+    let settings = settings(pool)
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or("ACME is not configured")?;
+    if settings.api_token.is_empty() {
+        return Err("No DNS provider API token configured".to_string());
+    }
+    let cert = get(pool, name).await.map_err(|e| e.to_string())?.ok_or("Unknown certificate")?;
+
+    let account_key = load_or_create_account_key()?;
+    let mut acme = AcmeClient::connect(&settings.directory_url, &account_key).await?;
+    let account_url = acme.register(&settings.email).await?;
+    let _ = sqlx::query("UPDATE acme_settings SET account_url = ? WHERE id = 1")
+        .bind(&account_url)
+        .execute(pool)
+        .await;
+
+    let dns = Cloudflare::new(&settings.api_token, settings.zone_id.as_deref())?;
+    let mut created = Vec::new();
+    let result = run_order(&settings, &mut acme, &dns, &cert.domains, &mut created).await;
+
+    // Always clean up challenge records, even on failure
+    for (zone, record) in &created {
+        if let Err(e) = dns.delete_txt(zone, record).await {
+            tracing::warn!("ACME: failed to remove challenge record: {}", e);
+        }
+    }
+
+    let (chain, key_pem) = result?;
+    let expires_at = certificate_expiry(&chain)?;
+
+    let (cert_path, key_path) = cert_paths(name);
+    write_private(&key_path, key_pem.as_bytes()).map_err(|e| e.to_string())?;
+    std::fs::write(&cert_path, &chain).map_err(|e| e.to_string())?;
+
+    sqlx::query(
+        "UPDATE certificates SET status = 'valid', issued_at = ?, expires_at = ?, last_error = NULL, updated_at = ? WHERE name = ?",
+    )
+    .bind(Utc::now().to_rfc3339())
+    .bind(expires_at.to_rfc3339())
+    .bind(Utc::now().to_rfc3339())
+    .bind(name)
+    .execute(pool)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    if ui_cert_path() == Some(cert_path.as_path()) {
+        if let Some(tls) = UI_TLS.get() {
+            match tls.reload_from_pem_file(&cert_path, &key_path).await {
+                Ok(()) => tracing::info!("Reloaded UI certificate"),
+                Err(e) => tracing::error!("Failed to reload UI certificate: {}", e),
+            }
+        }
+    }
+
+    Ok(expires_at)
+}
+
+async fn issue_and_record(pool: &SqlitePool, name: &str) {
+    match issue(pool, name).await {
+        Ok(expires_at) => tracing::info!("Certificate {} issued, expires {}", name, expires_at.to_rfc3339()),
+        Err(e) => {
+            tracing::error!("Certificate {} failed: {}", name, e);
+            set_status(pool, name, "error", Some(&e)).await;
+        }
+    }
+}
+
+/// Add (or re-request) a certificate and issue it in the background
+pub async fn request(
+    pool: &SqlitePool,
+    domains: &[String],
+    local_ip: Option<String>,
+) -> Result<Certificate, String> {
+    let domains = normalize_domains(domains)?;
+    let name = certificate_name(&domains);
+
+    // Internal names should resolve to the LAN service, not the public record
+    if let Some(ip) = &local_ip {
+        ip.parse::<std::net::IpAddr>().map_err(|_| format!("Invalid local IP: {}", ip))?;
+        for domain in domains.iter().filter(|d| !d.starts_with("*.")) {
+            crate::api::network::ensure_local_dns(domain, ip).map_err(|(_, e)| e)?;
+        }
+    }
+
+    sqlx::query(
+        r#"
+        INSERT INTO certificates (name, domains, status, local_ip, updated_at) VALUES (?, ?, 'pending', ?, ?)
+        ON CONFLICT(name) DO UPDATE SET
+            domains = excluded.domains, status = 'pending', local_ip = excluded.local_ip,
+            last_error = NULL, updated_at = excluded.updated_at
+        "#,
+    )
+    .bind(&name)
+    .bind(domains.join(","))
+    .bind(&local_ip)
+    .bind(Utc::now().to_rfc3339())
+    .execute(pool)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    let (task_pool, task_name) = (pool.clone(), name.clone());
+    tokio::spawn(async move { issue_and_record(&task_pool, &task_name).await });
+
+    get_cert(pool, &name).await
+}
+
+async fn get_cert(pool: &SqlitePool, name: &str) -> Result<Certificate, String> {
+    get(pool, name).await.map_err(|e| e.to_string())?.ok_or_else(|| "Unknown certificate".to_string())
+}
+
+/// Re-issue an existing certificate in the background
+pub async fn renew(pool: &SqlitePool, name: &str) -> Result<Certificate, String> {
+    let cert = get_cert(pool, name).await?;
+    set_status(pool, name, "pending", None).await;
+    let task_pool = pool.clone();
+    tokio::spawn(async move { issue_and_record(&task_pool, &cert.name).await });
+    get_cert(pool, name).await
+}
+
+async fn renew_due(pool: &SqlitePool) {
+    let Ok(certs) = list(pool).await else { return };
+    let cutoff = Utc::now() + chrono::Duration::days(RENEW_BEFORE_DAYS);
+
+    for cert in certs.into_iter().filter(|c| c.status != "pending") {
+        let due = cert
+            .expires_at
+            .as_deref()
+            .and_then(|e| DateTime::parse_from_rfc3339(e).ok())
+            .map(|e| e.with_timezone(&Utc) < cutoff)
+            .unwrap_or(true);
+        if due {
+            tracing::info!("Renewing certificate {}", cert.name);
+            issue_and_record(pool, &cert.name).await;
+        }
+    }
+}
+
+/// Called from main() when the UI is served over TLS
+pub fn register_ui_tls(config: RustlsConfig) {
+    let _ = UI_TLS.set(config);
+}
+
+/// Renew certificates that expire within RENEW_BEFORE_DAYS
+pub fn spawn(state: Arc<AppState>) {
+    tokio::spawn(async move {
+        loop {
+            renew_due(&state.db).await;
+            state.tasks.beat("acme", CHECK_INTERVAL);
+            tokio::time::sleep(CHECK_INTERVAL).await;
+        }
+    });
+}
@@ -1,10 +1,14 @@
 use axum::{
+    extract::{Query, State},
     http::StatusCode,
     Json,
 };
 use serde::{Deserialize, Serialize};
+use std::sync::Arc;
 
+use crate::cache::{self, RefreshQuery};
 use crate::mock;
+use crate::AppState;
 use super::AuthUser;
 
 const ADGUARD_URL: &str = "http://10.22.22.1:3000";
@@ -61,12 +65,21 @@ pub struct QueryQuestion {
 }
 
 pub async fn overview(
+    State(state): State<Arc<AppState>>,
     _user: AuthUser,
+    Query(query): Query<RefreshQuery>,
 ) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
     if mock::is_mock_mode() {
         return Ok(Json(mock::adguard::overview()));
     }
 
+    state.cache
+        .get_or_fetch(cache::ADGUARD_OVERVIEW, query.refresh, fetch_overview)
+        .await
+        .map(Json)
+}
+
+async fn fetch_overview() -> Result<serde_json::Value, (StatusCode, String)> {
     let c = client();
     
     let status: serde_json::Value = c
@@ -92,14 +105,14 @@ pub async fn overview(
     let dns_queries = stats["num_dns_queries"].as_u64().unwrap_or(0);
     let blocked = stats["num_blocked_filtering"].as_u64().unwrap_or(0);
     
-    Ok(Json(serde_json::to_value(AdGuardOverview {
+    Ok(serde_json::to_value(AdGuardOverview {
         protection_enabled: status["protection_enabled"].as_bool().unwrap_or(false),
         running: status["running"].as_bool().unwrap_or(false),
         dns_queries,
         blocked_filtering: blocked,
         blocked_percentage: if dns_queries > 0 { (blocked as f64 / dns_queries as f64) * 100.0 } else { 0.0 },
         avg_processing_time: stats["avg_processing_time"].as_f64().unwrap_or(0.0),
-    }).unwrap()))
+    }).unwrap())
 }
 
 #[derive(Deserialize)]
@@ -108,6 +121,7 @@ pub struct ProtectionToggle {
 }
 
 pub async fn toggle_protection(
+    State(state): State<Arc<AppState>>,
     _user: AuthUser,
     Json(payload): Json<ProtectionToggle>,
 ) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
@@ -123,7 +137,9 @@ pub async fn toggle_protection(
         .send()
         .await
         .map_err(|e| (StatusCode::BAD_GATEWAY, e.to_string()))?;
-    
+
+    state.cache.invalidate(cache::ADGUARD_OVERVIEW.0);
+
     Ok(Json(serde_json::json!({ "success": true, "protection_enabled": payload.enabled })))
 }
 
@@ -273,3 +289,135 @@ pub async fn remove_rule(
     
     Ok(Json(serde_json::json!({ "success": true })))
 }
+
+/// Whether AdGuard Home answers its API, i.e. rules pushed now will take effect
+pub(crate) async fn reachable() -> bool {
+    client()
+        .get(format!("{}/control/status", ADGUARD_URL))
+        .basic_auth(ADGUARD_USER, Some(ADGUARD_PASS))
+        .send()
+        .await
+        .is_ok_and(|r| r.status().is_success())
+}
+
+/// Replace the custom rules between "! <marker> begin" and "! <marker> end" with `managed`,
+/// leaving the user's own rules around them alone
+pub(crate) async fn set_managed_rules(marker: &str, managed: Vec<String>) -> Result<(), String> {
+    let c = client();
+    let status: FilterStatus = c
+        .get(format!("{}/control/filtering/status", ADGUARD_URL))
+        .basic_auth(ADGUARD_USER, Some(ADGUARD_PASS))
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| format!("AdGuard connection failed: {}", e))?
+        .json()
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let (begin, end) = (format!("! {} begin", marker), format!("! {} end", marker));
+    let mut rules = Vec::new();
+    let mut inside = false;
+    for rule in status.user_rules {
+        if rule == begin {
+            inside = true;
+        } else if rule == end {
+            inside = false;
+        } else if !inside {
+            rules.push(rule);
+        }
+    }
+    if !managed.is_empty() {
+        rules.push(begin);
+        rules.extend(managed);
+        rules.push(end);
+    }
+
+    c.post(format!("{}/control/filtering/set_rules", ADGUARD_URL))
+        .basic_auth(ADGUARD_USER, Some(ADGUARD_PASS))
+        .json(&serde_json::json!({ "rules": rules }))
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| format!("AdGuard rejected the rules: {}", e))?;
+    Ok(())
+}
+
+// ============ PRIVACY ============
+
+// Read-modify-write of an AdGuard config endpoint, so fields RouterUI doesn't manage
+// (such as ignored domains) are kept
+async fn update_config(path: &str, changes: serde_json::Value) -> Result<(), String> {
+    update_settings(path, &format!("{}/update", path), changes).await
+}
+
+async fn update_settings(get_path: &str, put_path: &str, changes: serde_json::Value) -> Result<(), String> {
+    let c = client();
+    let mut config: serde_json::Value = c
+        .get(format!("{}/control/{}", ADGUARD_URL, get_path))
+        .basic_auth(ADGUARD_USER, Some(ADGUARD_PASS))
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| format!("AdGuard connection failed: {}", e))?
+        .json()
+        .await
+        .map_err(|e| e.to_string())?;
+
+    if let (Some(config), Some(changes)) = (config.as_object_mut(), changes.as_object()) {
+        config.extend(changes.clone());
+    }
+
+    c.put(format!("{}/control/{}", ADGUARD_URL, put_path))
+        .basic_auth(ADGUARD_USER, Some(ADGUARD_PASS))
+        .json(&config)
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| format!("AdGuard rejected {} update: {}", put_path, e))?;
+    Ok(())
+}
+
+/// Query log and statistics settings for a privacy profile
+pub(crate) async fn apply_privacy(policy: &crate::privacy::Policy) -> Result<(), String> {
+    use crate::privacy::DnsLogging;
+
+    let interval_ms = policy.dns_retention_hours as u64 * 60 * 60 * 1000;
+    update_config(
+        "querylog/config",
+        serde_json::json!({
+            "enabled": policy.dns_query_log != DnsLogging::Off,
+            "anonymize_client_ip": policy.dns_query_log != DnsLogging::Full,
+            "interval": interval_ms,
+        }),
+    )
+    .await?;
+    update_config(
+        "stats/config",
+        serde_json::json!({
+            "enabled": policy.dns_statistics,
+            "interval": interval_ms,
+        }),
+    )
+    .await
+}
+
+// ============ PARENTAL ============
+
+/// AdGuard's adult-site blocking
+pub(crate) async fn set_parental(enabled: bool) -> Result<(), String> {
+    let action = if enabled { "enable" } else { "disable" };
+    client()
+        .post(format!("{}/control/parental/{}", ADGUARD_URL, action))
+        .basic_auth(ADGUARD_USER, Some(ADGUARD_PASS))
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| format!("AdGuard parental {}: {}", action, e))?;
+    Ok(())
+}
+
+/// Enforced safe search, keeping the per-engine choices
+pub(crate) async fn set_safe_search(enabled: bool) -> Result<(), String> {
+    update_settings("safesearch/status", "safesearch/settings", serde_json::json!({"enabled": enabled})).await
+}
@@ -1,6 +1,7 @@
 use axum::{extract::Json, http::StatusCode};
 use serde::{Deserialize, Serialize};
 use std::process::Command;
+use crate::system::privileges::sudo;
 use std::fs;
 use std::path::Path;
 
@@ -213,18 +214,18 @@ pub async fn status() -> Result<Json<AntivirusStatus>, (StatusCode, String)> {
 // Update virus signatures
 pub async fn update_signatures() -> Result<Json<serde_json::Value>, (StatusCode, String)> {
     // Stop freshclam service temporarily
-    let _ = Command::new("sudo")
+    let _ = sudo()
         .args(["systemctl", "stop", "clamav-freshclam"])
         .output();
 
     // Run freshclam
-    let output = Command::new("sudo")
+    let output = sudo()
         .args(["freshclam"])
         .output()
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
     // Restart freshclam service
-    let _ = Command::new("sudo")
+    let _ = sudo()
         .args(["systemctl", "start", "clamav-freshclam"])
         .output();
 
@@ -268,7 +269,7 @@ pub async fn start_scan(
     args.push(path.clone());
 
     // Run scan
-    let output = Command::new("sudo")
+    let output = sudo()
         .args(["clamscan"])
         .args(&args)
         .output()
@@ -427,7 +428,7 @@ pub async fn quarantine_action(
 
     match payload.action.as_str() {
         "delete" => {
-            Command::new("sudo")
+            sudo()
                 .args(["rm", "-f", &quarantine_path])
                 .output()
                 .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
@@ -443,7 +444,7 @@ pub async fn quarantine_action(
             let _ = fs::create_dir_all(restore_dir);
 
             let restore_path = format!("{}/{}", restore_dir, payload.id);
-            Command::new("sudo")
+            sudo()
                 .args(["mv", &quarantine_path, &restore_path])
                 .output()
                 .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
@@ -479,7 +480,7 @@ pub async fn toggle_daemon(
 
     let action = if enable { "start" } else { "stop" };
 
-    Command::new("sudo")
+    sudo()
         .args(["systemctl", action, "clamav-daemon"])
         .output()
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
@@ -0,0 +1,172 @@
+use axum::{extract::State, http::StatusCode, Json};
+use serde::Deserialize;
+use std::sync::Arc;
+
+use crate::approvals::{self, Action, ApprovalSettings};
+use crate::events::Event;
+use crate::mock;
+use crate::models::User;
+use crate::AppState;
+use super::{require_permission, AuthUser};
+
+/// Park `action` until another admin approves it; the handler returns this instead of acting
+pub(crate) fn park(
+    state: &AppState,
+    user: &User,
+    action: Action,
+    summary: String,
+    payload: serde_json::Value,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let request = state.approvals.request(action, &user.username, summary, payload);
+    tracing::warn!("{} requested by {}, waiting for a second admin", action.describe(), user.username);
+    state.events.emit(Event::ApprovalRequested {
+        id: request.id.clone(),
+        action,
+        summary: request.summary.clone(),
+        requested_by: request.requested_by.clone(),
+        expires_at: request.expires_at.clone(),
+    });
+    (
+        StatusCode::ACCEPTED,
+        Json(serde_json::json!({"approval_required": true, "approval": request})),
+    )
+}
+
+// Admins who can sign in, and so approve something
+async fn admin_count(state: &AppState) -> Result<i64, (StatusCode, String)> {
+    sqlx::query_scalar("SELECT COUNT(*) FROM users WHERE role = 'admin' AND enabled = 1")
+        .fetch_one(&state.db)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+}
+
+pub async fn status(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    require_permission(&user, "system:write").map_err(|(s, m)| (s, m.to_string()))?;
+
+    Ok(Json(serde_json::json!({
+        "settings": approvals::load_settings(),
+        "admins": admin_count(&state).await?,
+        "window_secs": approvals::APPROVAL_WINDOW.as_secs(),
+        "pending": state.approvals.list(),
+    })))
+}
+
+pub async fn update_settings(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    Json(payload): Json<ApprovalSettings>,
+) -> Result<(StatusCode, Json<serde_json::Value>), (StatusCode, String)> {
+    require_permission(&user, "system:write").map_err(|(s, m)| (s, m.to_string()))?;
+
+    let current = approvals::load_settings();
+    // Always, even with a single admin left: removing admins needs approval too, so running the
+    // count down is no way around this
+    if current.enabled && !payload.enabled {
+        return Ok(park(&state, &user, Action::DisableTwoPerson, Action::DisableTwoPerson.describe().to_string(), serde_json::Value::Null));
+    }
+    // With a single admin nothing could ever be approved
+    if payload.enabled && admin_count(&state).await? < 2 {
+        return Err((StatusCode::CONFLICT, "Two-person confirmation needs at least two enabled admin accounts".to_string()));
+    }
+
+    if mock::is_mock_mode() {
+        return Ok((StatusCode::OK, Json(serde_json::json!({"success": true, "mock": true}))));
+    }
+
+    approvals::save_settings(&payload).map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    Ok((StatusCode::OK, Json(serde_json::json!({"success": true, "settings": payload}))))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ApprovalId {
+    pub id: String,
+}
+
+// Carry out an approved request
+async fn execute(state: &AppState, request: approvals::PendingApproval) -> Result<serde_json::Value, (StatusCode, String)> {
+    match request.action {
+        Action::DisableFirewall => {
+            let Json(status) = super::firewall::set_enabled(&state.db, false).await?;
+            Ok(serde_json::json!({"firewall": status}))
+        }
+        Action::RestoreBackup => {
+            let configs: super::tools::BackupConfigs = serde_json::from_value(request.payload)
+                .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+            let (restored, errors) = super::tools::apply_backup(&state.db, &configs).await?;
+            Ok(serde_json::json!({"success": errors.is_empty(), "restored": restored, "errors": errors}))
+        }
+        Action::FactoryReset => {
+            let keep_user_id = request.payload["keep_user_id"]
+                .as_i64()
+                .ok_or((StatusCode::BAD_REQUEST, "Missing admin to keep".to_string()))?;
+            Ok(super::system::run_factory_reset(state, keep_user_id).await)
+        }
+        Action::DisableTwoPerson => {
+            if !mock::is_mock_mode() {
+                approvals::save_settings(&ApprovalSettings { enabled: false })
+                    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+            }
+            Ok(serde_json::json!({"settings": ApprovalSettings { enabled: false }}))
+        }
+        Action::RemoveAdmin => {
+            let id = request.payload["user_id"]
+                .as_i64()
+                .ok_or((StatusCode::BAD_REQUEST, "Missing user".to_string()))?;
+            if request.payload["delete"].as_bool() == Some(true) {
+                super::users::delete_user(state, &request.requested_by, id).await?;
+            } else {
+                let update: crate::models::UserUpdate = serde_json::from_value(request.payload["update"].clone())
+                    .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+                super::users::apply_update(state, &request.requested_by, id, &update).await?;
+            }
+            Ok(serde_json::json!({"user_id": id}))
+        }
+        Action::OpenWanInput => {
+            let settings: crate::zones::ZoneSettings = serde_json::from_value(request.payload)
+                .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+            let zones = crate::zones::Zones { roles: crate::interfaces::roles(&state.db).await, settings };
+            let pending = super::firewall::apply_zones(&state.db, zones).await?;
+            Ok(serde_json::json!({"success": true, "pending": pending}))
+        }
+    }
+}
+
+pub async fn approve(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    Json(payload): Json<ApprovalId>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    require_permission(&user, "system:write").map_err(|(s, m)| (s, m.to_string()))?;
+
+    let request = state.approvals.approve(&payload.id, &user.username)?;
+    tracing::warn!(
+        "{} requested by {} (request {}) approved by {}",
+        request.action.describe(),
+        request.requested_by,
+        request.request_id.as_deref().unwrap_or("-"),
+        user.username
+    );
+    let action = request.action;
+    let result = execute(&state, request).await?;
+
+    Ok(Json(serde_json::json!({"success": true, "action": action, "result": result})))
+}
+
+pub async fn reject(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    Json(payload): Json<ApprovalId>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    require_permission(&user, "system:write").map_err(|(s, m)| (s, m.to_string()))?;
+
+    let request = state
+        .approvals
+        .reject(&payload.id)
+        .ok_or((StatusCode::NOT_FOUND, "No such request, or it has expired".to_string()))?;
+    tracing::info!("{} requested by {} rejected by {}", request.action.describe(), request.requested_by, user.username);
+
+    Ok(Json(serde_json::json!({"success": true})))
+}
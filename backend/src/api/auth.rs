@@ -1,44 +1,121 @@
 use axum::{
-    extract::State,
-    http::{header::SET_COOKIE, StatusCode},
-    response::{IntoResponse, Response},
+    extract::{ConnectInfo, Path, Query, State},
+    http::{header::{SET_COOKIE, USER_AGENT}, HeaderMap, StatusCode},
+    response::{IntoResponse, Redirect, Response},
     Json,
 };
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
 use std::sync::Arc;
 
 use crate::{
-    auth,
+    auth::{self, login_history::{self, Method, Outcome}},
     db,
-    models::{LoginRequest, LoginResponse, UserPublic},
+    models::{ChangePasswordRequest, LoginRequest, LoginResponse, UserPublic},
     AppState,
 };
 
-use super::AuthUser;
+use super::{require_permission, AuthUser};
+
+// The session cookie lives as long as the browser; a remembered device gets its next session
+// from the refresh cookie instead
+fn session_cookie(token: &str) -> String {
+    format!("session={}; Path=/; HttpOnly; SameSite=Strict", token)
+}
+
+fn refresh_cookie(token: &str, expires_at: &str) -> String {
+    let max_age = chrono::DateTime::parse_from_rfc3339(expires_at)
+        .map(|t| (t.with_timezone(&chrono::Utc) - chrono::Utc::now()).num_seconds().max(0))
+        .unwrap_or(0);
+    auth::refresh::cookie(token, max_age)
+}
 
 pub async fn login(
     State(state): State<Arc<AppState>>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
     Json(payload): Json<LoginRequest>,
 ) -> Result<Response, (StatusCode, String)> {
+    let ip = peer.ip().to_string();
+    let user_agent = headers.get(USER_AGENT).and_then(|v| v.to_str().ok());
+    let record = |user_id: Option<i64>, method: Method, outcome: Outcome| {
+        login_history::record(&state.db, user_id, &payload.username, method, outcome, Some(&ip), user_agent)
+    };
+
     // Find user
-    let user = db::get_user_by_username(&state.db, &payload.username)
+    let local = db::get_user_by_username(&state.db, &payload.username)
         .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
-        .ok_or((StatusCode::UNAUTHORIZED, "Invalid credentials".to_string()))?;
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let local_id = local.as_ref().map(|u| u.id);
+    let failed = if local.is_some() { Outcome::BadPassword } else { Outcome::UnknownUser };
 
     // Check if enabled
-    if !user.enabled {
+    if local.as_ref().is_some_and(|u| !u.enabled) {
+        record(local_id, Method::Password, Outcome::Disabled).await;
         return Err((StatusCode::FORBIDDEN, "Account disabled".to_string()));
     }
 
-    // Verify password
-    if !auth::verify_password(&payload.password, &user.password_hash) {
-        return Err((StatusCode::UNAUTHORIZED, "Invalid credentials".to_string()));
-    }
+    // Verify password: the local account first, then the directory if one is set up
+    let (user, method) = match local {
+        Some(user) if auth::verify_password(&payload.password, &user.password_hash) => (user, Method::Password),
+        _ => {
+            let ldap = auth::ldap::load_settings(&state.db).await;
+            if !ldap.enabled {
+                auth::bruteforce::record_failure(&state, peer.ip(), &payload.username);
+                record(local_id, Method::Password, failed).await;
+                return Err((StatusCode::UNAUTHORIZED, "Invalid credentials".to_string()));
+            }
+            match auth::ldap::authenticate(&ldap, &payload.username, &payload.password).await {
+                Ok(identity) => match auth::ldap::provision(&state.db, &ldap, &identity).await {
+                    Ok(user) => (user, Method::Ldap),
+                    Err(e) => {
+                        let linked = auth::ldap::linked_user(&state.db, &identity.dn).await.ok().flatten();
+                        record(linked, Method::Ldap, Outcome::Refused).await;
+                        return Err((StatusCode::FORBIDDEN, e));
+                    }
+                },
+                Err(auth::ldap::LdapError::Directory(e)) => {
+                    tracing::warn!("Directory sign-in of {} failed: {}", payload.username, e);
+                    auth::bruteforce::record_failure(&state, peer.ip(), &payload.username);
+                    record(local_id, Method::Password, failed).await;
+                    return Err((
+                        StatusCode::SERVICE_UNAVAILABLE,
+                        "The directory server is unavailable; local accounts still work".to_string(),
+                    ));
+                }
+                Err(e) => {
+                    auth::bruteforce::record_failure(&state, peer.ip(), &payload.username);
+                    let linked = match &e {
+                        auth::ldap::LdapError::BadPassword { dn: Some(dn) } => {
+                            auth::ldap::linked_user(&state.db, dn).await.ok().flatten()
+                        }
+                        _ => None,
+                    };
+                    match linked {
+                        Some(id) => record(Some(id), Method::Ldap, Outcome::BadPassword).await,
+                        None => record(local_id, Method::Password, failed).await,
+                    }
+                    return Err((StatusCode::UNAUTHORIZED, "Invalid credentials".to_string()));
+                }
+            }
+        }
+    };
+    state.bruteforce.record_success(peer.ip());
 
     // Create session
-    let token = auth::create_session(&state.db, user.id, None)
+    let remember = payload.remember && auth::session_policy::load(&state.db).await.remember_days > 0;
+    let token = auth::create_session(&state.db, user.id, Some(&ip), user_agent, remember)
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let refresh = if remember {
+        Some(
+            auth::refresh::issue(&state.db, user.id, &token)
+                .await
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?,
+        )
+    } else {
+        None
+    };
 
     // Update last login
     sqlx::query("UPDATE users SET last_login = datetime('now') WHERE id = ?")
@@ -46,52 +123,524 @@ pub async fn login(
         .execute(&state.db)
         .await
         .ok();
+    record(Some(user.id), method, Outcome::Success).await;
+
+    let mut cookies = HeaderMap::new();
+    cookies.append(SET_COOKIE, session_cookie(&token).parse().unwrap());
+    if let Some((refresh_token, expires_at)) = &refresh {
+        cookies.append(SET_COOKIE, refresh_cookie(refresh_token, expires_at).parse().unwrap());
+    }
 
     let response = LoginResponse {
-        token: token.clone(),
-        user: UserPublic {
-            id: user.id,
-            username: user.username,
-            role: user.role,
-        },
+        token,
+        refresh_token: refresh.map(|(token, _)| token),
+        password_expired: auth::password_policy::is_expired(&state.db, user.id).await,
+        user: UserPublic::from(user),
     };
 
-    // Set cookie (4 hour expiry)
-    let cookie = format!(
-        "session={}; Path=/; HttpOnly; SameSite=Strict; Max-Age={}",
-        token,
-        4 * 60 * 60
-    );
+    Ok((cookies, Json(response)).into_response())
+}
 
-    Ok((
-        [(SET_COOKIE, cookie)],
-        Json(response),
-    ).into_response())
+#[derive(Debug, Default, Deserialize)]
+pub struct RefreshRequest {
+    pub refresh_token: Option<String>,
+}
+
+// Trade a remembered device's refresh token for a new session and a new refresh token. The
+// token comes from the refresh cookie, or the body for API clients.
+pub async fn refresh(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    payload: Option<Json<RefreshRequest>>,
+) -> Result<Response, (StatusCode, String)> {
+    let token = payload
+        .and_then(|Json(p)| p.refresh_token)
+        .or_else(|| auth::refresh::from_cookie(&headers))
+        .ok_or((StatusCode::UNAUTHORIZED, "No refresh token".to_string()))?;
+
+    let user_agent = headers.get(USER_AGENT).and_then(|v| v.to_str().ok());
+    let rotation = match auth::refresh::rotate(&state.db, &state.events, &token, Some(&peer.ip().to_string()), user_agent).await {
+        Ok(rotation) => rotation,
+        Err(message) => {
+            return Ok((
+                StatusCode::UNAUTHORIZED,
+                [(SET_COOKIE, auth::refresh::clear_cookie())],
+                message,
+            ).into_response());
+        }
+    };
+
+    let mut cookies = HeaderMap::new();
+    cookies.append(SET_COOKIE, session_cookie(&rotation.session_token).parse().unwrap());
+    cookies.append(SET_COOKIE, refresh_cookie(&rotation.refresh_token, &rotation.expires_at).parse().unwrap());
+
+    let response = LoginResponse {
+        token: rotation.session_token,
+        refresh_token: Some(rotation.refresh_token),
+        password_expired: auth::password_policy::is_expired(&state.db, rotation.user.id).await,
+        user: UserPublic::from(rotation.user),
+    };
+    Ok((cookies, Json(response)).into_response())
 }
 
 pub async fn logout(
     State(state): State<Arc<AppState>>,
     AuthUser(user): AuthUser,
+    headers: HeaderMap,
 ) -> Result<impl IntoResponse, (StatusCode, String)> {
-    // In a real implementation, we'd get the token from the request
-    // For now, just return success
+    if let Some(token) = auth::session_token(&headers) {
+        auth::revoke_session_token(&state.db, &token)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    }
+    // Logging out of a remembered device forgets it
+    if let Some(token) = auth::refresh::from_cookie(&headers) {
+        auth::refresh::revoke(&state.db, &token)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    }
     tracing::info!("User {} logged out", user.username);
     
-    // Clear cookie
-    let cookie = "session=; Path=/; HttpOnly; SameSite=Strict; Max-Age=0";
+    // Clear cookies
+    let mut cookies = HeaderMap::new();
+    cookies.append(SET_COOKIE, "session=; Path=/; HttpOnly; SameSite=Strict; Max-Age=0".parse().unwrap());
+    cookies.append(SET_COOKIE, auth::refresh::clear_cookie().parse().unwrap());
     
     Ok((
-        [(SET_COOKIE, cookie)],
+        cookies,
         Json(serde_json::json!({ "success": true })),
     ))
 }
 
+#[derive(Debug, Serialize)]
+pub struct Me {
+    #[serde(flatten)]
+    pub user: UserPublic,
+    // Older than the password policy allows; the UI asks for a new one
+    pub password_expired: bool,
+}
+
 pub async fn me(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+) -> Json<Me> {
+    let password_expired = auth::password_policy::is_expired(&state.db, user.id).await;
+    Json(Me { user: UserPublic::from(user), password_expired })
+}
+
+// CSRF token the frontend sends back with every change; null without a session cookie, when
+// none is needed
+pub async fn csrf(headers: HeaderMap) -> Json<serde_json::Value> {
+    Json(serde_json::json!({ "token": auth::csrf::token(&headers), "header": auth::csrf::HEADER.as_str() }))
+}
+
+// Active logins of the current user
+pub async fn sessions(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    headers: HeaderMap,
+) -> Result<Json<Vec<auth::SessionInfo>>, (StatusCode, String)> {
+    let token = auth::session_token(&headers);
+    let sessions = auth::list_sessions(&state.db, user.id, token.as_deref())
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(Json(sessions))
+}
+
+pub async fn revoke_session(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    Path(id): Path<i64>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let revoked = auth::revoke_session(&state.db, user.id, id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    if !revoked {
+        return Err((StatusCode::NOT_FOUND, "No such session".to_string()));
+    }
+    tracing::info!("User {} revoked session {}", user.username, id);
+    Ok(Json(serde_json::json!({ "success": true })))
+}
+
+// Sign out everywhere except here
+pub async fn revoke_other_sessions(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    headers: HeaderMap,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let token = auth::session_token(&headers);
+    let revoked = auth::revoke_other_sessions(&state.db, user.id, token.as_deref())
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    tracing::info!("User {} revoked {} other session(s)", user.username, revoked);
+    Ok(Json(serde_json::json!({ "success": true, "revoked": revoked })))
+}
+
+// Sign out my remembered devices; ordinary sessions are left alone
+pub async fn forget_devices(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let revoked = auth::forget_devices(&state.db, user.id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    tracing::info!("User {} signed out {} remembered device(s)", user.username, revoked);
+    Ok(Json(serde_json::json!({ "success": true, "revoked": revoked })))
+}
+
+// Session lifetime, idle timeout and remembered devices
+pub async fn session_policy(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+) -> Result<Json<auth::session_policy::SessionPolicy>, (StatusCode, String)> {
+    require_permission(&user, "users:read").map_err(|(s, m)| (s, m.to_string()))?;
+    Ok(Json(auth::session_policy::load(&state.db).await))
+}
+
+// Applies to existing sessions at once, except that an absolute expiry already handed out
+// stays as it was
+pub async fn update_session_policy(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    Json(payload): Json<auth::session_policy::SessionPolicy>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    require_permission(&user, "users:write").map_err(|(s, m)| (s, m.to_string()))?;
+    payload.validate().map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    auth::session_policy::save(&state.db, &payload).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    tracing::info!(
+        "User {} set sessions to {}h, idle {}m, remembered devices {}d, IP binding {:?}",
+        user.username, payload.absolute_hours, payload.idle_minutes, payload.remember_days, payload.ip_binding
+    );
+    Ok(Json(serde_json::json!({ "success": true })))
+}
+
+// Length, character classes, common passwords and maximum age
+pub async fn password_policy(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+) -> Result<Json<auth::password_policy::PasswordPolicy>, (StatusCode, String)> {
+    require_permission(&user, "users:read").map_err(|(s, m)| (s, m.to_string()))?;
+    Ok(Json(auth::password_policy::load(&state.db).await))
+}
+
+// Existing passwords are only held to the new rules when they are next changed; a shorter
+// maximum age applies at once
+pub async fn update_password_policy(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    Json(payload): Json<auth::password_policy::PasswordPolicy>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    require_permission(&user, "users:write").map_err(|(s, m)| (s, m.to_string()))?;
+    payload.validate().map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    auth::password_policy::save(&state.db, &payload).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    tracing::info!(
+        "User {} set the password policy to at least {} characters, common passwords {}, max age {}d",
+        user.username, payload.min_length, if payload.block_common { "blocked" } else { "allowed" }, payload.max_age_days
+    );
+    Ok(Json(serde_json::json!({ "success": true })))
+}
+
+// Change my own password; every other session is signed out, this one stays
+pub async fn change_password(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    AuthUser(user): AuthUser,
+    headers: HeaderMap,
+    Json(payload): Json<ChangePasswordRequest>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    // Re-read the stored hash rather than trusting whatever the extractor carried
+    let user = db::get_user_by_id(&state.db, user.id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or((StatusCode::NOT_FOUND, "User not found".to_string()))?;
+
+    if !auth::verify_password(&payload.current_password, &user.password_hash) {
+        auth::bruteforce::record_failure(&state, peer.ip(), &user.username);
+        return Err((StatusCode::UNAUTHORIZED, "Current password is incorrect".to_string()));
+    }
+    if payload.new_password == payload.current_password {
+        return Err((StatusCode::BAD_REQUEST, "New password must differ from the current one".to_string()));
+    }
+    auth::password_policy::load(&state.db)
+        .await
+        .check(&payload.new_password)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+    let hash = auth::hash_password(&payload.new_password)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    sqlx::query("UPDATE users SET password_hash = ? WHERE id = ?")
+        .bind(&hash)
+        .bind(user.id)
+        .execute(&state.db)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    auth::password_policy::mark_changed(&state.db, user.id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    // Anyone holding the old credentials gets logged out
+    let token = auth::session_token(&headers);
+    let revoked = auth::revoke_other_sessions(&state.db, user.id, token.as_deref())
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    tracing::info!("User {} changed their password, {} other session(s) signed out", user.username, revoked);
+
+    Ok(Json(serde_json::json!({ "success": true, "sessions_revoked": revoked })))
+}
+
+// ============ RECOVERY ============
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct RecoveryCodeRequest {
+    // Whose password the code resets; the first admin when omitted
+    pub username: Option<String>,
+}
+
+// Only answers on the router itself: the code goes to the journal, never into the response
+pub async fn recovery_code(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    payload: Option<Json<RecoveryCodeRequest>>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    if !peer.ip().to_canonical().is_loopback() {
+        tracing::warn!("Refused a recovery code request from {}", peer.ip());
+        return Err((StatusCode::FORBIDDEN, "Recovery codes can only be requested on the router itself".to_string()));
+    }
+    let payload = payload.map(|Json(p)| p).unwrap_or_default();
+    let (code, username) = auth::recovery::issue(&state.db, payload.username.as_deref())
+        .await
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+    tracing::warn!("==================================================");
+    tracing::warn!("  RouterUI recovery code for {}: {}", username, code);
+    tracing::warn!("  POST it with a new password to /api/auth/recover");
+    tracing::warn!("  within {} minutes. It works once.", auth::recovery::VALID_MINUTES);
+    tracing::warn!("==================================================");
+
+    Ok(Json(serde_json::json!({ "success": true, "username": username })))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RecoverRequest {
+    pub code: String,
+    pub new_password: String,
+}
+
+pub async fn recover(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    Json(payload): Json<RecoverRequest>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    // Before using the code up, so a rejected password doesn't cost the admin a new one
+    auth::password_policy::load(&state.db)
+        .await
+        .check(&payload.new_password)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+    let (user_id, username) = auth::recovery::redeem(&payload.code).map_err(|e| {
+        auth::bruteforce::record_failure(&state, peer.ip(), "recovery");
+        (StatusCode::UNAUTHORIZED, e)
+    })?;
+
+    let hash = auth::hash_password(&payload.new_password)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    sqlx::query("UPDATE users SET password_hash = ?, enabled = 1 WHERE id = ?")
+        .bind(&hash)
+        .bind(user_id)
+        .execute(&state.db)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    auth::password_policy::mark_changed(&state.db, user_id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    // Whoever locked the admin out may still be signed in
+    auth::revoke_other_sessions(&state.db, user_id, None)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    auth::forget_devices(&state.db, user_id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    state.bruteforce.record_success(peer.ip());
+
+    tracing::warn!("Password of {} reset with a recovery code from {}", username, peer.ip());
+    Ok(Json(serde_json::json!({ "success": true, "username": username })))
+}
+
+// ============ OIDC / SSO ============
+
+// What the sign-in page needs to offer the SSO button; no secrets
+pub async fn oidc_status() -> Json<serde_json::Value> {
+    let settings = auth::oidc::load_settings();
+    Json(serde_json::json!({
+        "enabled": settings.enabled,
+        "button_label": settings.button_label,
+    }))
+}
+
+// The browser comes back to the UI either way; failures show up as a banner there
+fn sso_failure(message: &str) -> Response {
+    tracing::warn!("SSO sign-in failed: {}", message);
+    let query = reqwest::Url::parse_with_params("http://routerui/", &[("sso_error", message)])
+        .ok()
+        .and_then(|u| u.query().map(str::to_string))
+        .unwrap_or_default();
+    Redirect::to(&format!("/?{}", query)).into_response()
+}
+
+pub async fn oidc_login(State(state): State<Arc<AppState>>) -> Response {
+    let settings = auth::oidc::load_settings();
+    if !settings.enabled {
+        return sso_failure("Single sign-on is not enabled");
+    }
+    match state.oidc.start(&settings).await {
+        Ok(url) => Redirect::to(&url).into_response(),
+        Err(e) => sso_failure(&e),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OidcCallback {
+    pub code: Option<String>,
+    pub state: Option<String>,
+    pub error: Option<String>,
+    pub error_description: Option<String>,
+}
+
+pub async fn oidc_callback(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Query(params): Query<OidcCallback>,
+) -> Response {
+    let settings = auth::oidc::load_settings();
+    if !settings.enabled {
+        return sso_failure("Single sign-on is not enabled");
+    }
+    if let Some(error) = params.error {
+        return sso_failure(&params.error_description.unwrap_or(error));
+    }
+    let (Some(code), Some(login_state)) = (params.code, params.state) else {
+        return sso_failure("The provider sent back an incomplete response");
+    };
+
+    let identity = match state.oidc.finish(&settings, &login_state, &code).await {
+        Ok(identity) => identity,
+        Err(e) => return sso_failure(&e),
+    };
+    let ip = peer.ip().to_string();
+    let user_agent = headers.get(USER_AGENT).and_then(|v| v.to_str().ok());
+    let user = match auth::oidc::provision(&state.db, &settings, &identity).await {
+        Ok(user) => user,
+        Err(e) => {
+            // Only an account already linked to this identity is charged with the attempt
+            let user_id: Option<i64> = sqlx::query_scalar("SELECT id FROM users WHERE oidc_subject = ?")
+                .bind(&identity.subject)
+                .fetch_optional(&state.db)
+                .await
+                .ok()
+                .flatten();
+            login_history::record(&state.db, user_id, &identity.username, Method::Sso, Outcome::Refused, Some(&ip), user_agent).await;
+            return sso_failure(&e);
+        }
+    };
+
+    let token = match auth::create_session(&state.db, user.id, Some(&ip), user_agent, false).await {
+        Ok(token) => token,
+        Err(e) => return sso_failure(&e.to_string()),
+    };
+    sqlx::query("UPDATE users SET last_login = datetime('now') WHERE id = ?")
+        .bind(user.id)
+        .execute(&state.db)
+        .await
+        .ok();
+    login_history::record(&state.db, Some(user.id), &user.username, Method::Sso, Outcome::Success, Some(&ip), user_agent).await;
+    tracing::info!("User {} signed in through SSO as {} ({})", user.username, identity.username, user.role);
+
+    ([(SET_COOKIE, session_cookie(&token))], Redirect::to("/")).into_response()
+}
+
+// Settings as shown to admins: the client secret is never sent back
+#[derive(Debug, Serialize)]
+pub struct OidcSettingsView {
+    #[serde(flatten)]
+    pub settings: auth::oidc::OidcSettings,
+    pub client_secret_set: bool,
+    pub callback_path: &'static str,
+}
+
+pub async fn oidc_settings(AuthUser(user): AuthUser) -> Result<Json<OidcSettingsView>, (StatusCode, String)> {
+    require_permission(&user, "users:read").map_err(|(s, m)| (s, m.to_string()))?;
+    let mut settings = auth::oidc::load_settings();
+    let client_secret_set = !settings.client_secret.is_empty();
+    settings.client_secret.clear();
+    Ok(Json(OidcSettingsView { settings, client_secret_set, callback_path: auth::oidc::CALLBACK_PATH }))
+}
+
+// An empty client secret keeps the stored one
+pub async fn update_oidc_settings(
+    AuthUser(user): AuthUser,
+    Json(mut payload): Json<auth::oidc::OidcSettings>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    require_permission(&user, "users:write").map_err(|(s, m)| (s, m.to_string()))?;
+
+    if payload.client_secret.is_empty() {
+        payload.client_secret = auth::oidc::load_settings().client_secret;
+    }
+    payload.validate().map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    if payload.enabled {
+        auth::oidc::check_provider(&payload)
+            .await
+            .map_err(|e| (StatusCode::BAD_GATEWAY, e))?;
+    }
+
+    auth::oidc::save_settings(&payload).map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    tracing::info!("User {} updated the SSO settings (enabled: {})", user.username, payload.enabled);
+    Ok(Json(serde_json::json!({ "success": true })))
+}
+
+// Directory settings as shown to admins: the service account password is never sent back
+#[derive(Debug, Serialize)]
+pub struct LdapSettingsView {
+    #[serde(flatten)]
+    pub settings: auth::ldap::LdapSettings,
+    pub bind_password_set: bool,
+}
+
+pub async fn ldap_settings(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+) -> Result<Json<LdapSettingsView>, (StatusCode, String)> {
+    require_permission(&user, "users:read").map_err(|(s, m)| (s, m.to_string()))?;
+    let mut settings = auth::ldap::load_settings(&state.db).await;
+    let bind_password_set = !settings.bind_password.is_empty();
+    settings.bind_password.clear();
+    Ok(Json(LdapSettingsView { settings, bind_password_set }))
+}
+
+// An empty bind password keeps the stored one, unless the bind DN is cleared too
+pub async fn update_ldap_settings(
+    State(state): State<Arc<AppState>>,
     AuthUser(user): AuthUser,
-) -> Json<UserPublic> {
-    Json(UserPublic {
-        id: user.id,
-        username: user.username,
-        role: user.role,
-    })
+    Json(mut payload): Json<auth::ldap::LdapSettings>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    require_permission(&user, "users:write").map_err(|(s, m)| (s, m.to_string()))?;
+
+    if payload.bind_password.is_empty() && !payload.bind_dn.is_empty() {
+        payload.bind_password = auth::ldap::load_settings(&state.db).await.bind_password;
+    }
+    payload.validate().map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    if payload.enabled {
+        auth::ldap::check_server(&payload)
+            .await
+            .map_err(|e| (StatusCode::BAD_GATEWAY, e))?;
+    }
+
+    auth::ldap::save_settings(&state.db, &payload)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    tracing::info!("User {} updated the directory settings (enabled: {})", user.username, payload.enabled);
+    Ok(Json(serde_json::json!({ "success": true })))
 }
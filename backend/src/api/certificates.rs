@@ -0,0 +1,180 @@
+use axum::{extract::State, http::StatusCode, Json};
+use serde::Deserialize;
+use std::sync::Arc;
+
+use super::{require_permission, AuthUser};
+use crate::acme::{self, AcmeSettings};
+use crate::mock;
+use crate::AppState;
+
+// ============ CERTIFICATES ============
+
+pub async fn list(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    require_permission(&user, "security:write").map_err(|(s, m)| (s, m.to_string()))?;
+
+    if mock::is_mock_mode() {
+        return Ok(Json(mock::certificates::list()));
+    }
+
+    let settings = acme::settings(&state.db)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let certificates = acme::list(&state.db)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    // Never send the API token back, only whether one is set
+    let settings = settings.map(|s| {
+        serde_json::json!({
+            "directory_url": s.directory_url,
+            "email": s.email,
+            "provider": s.provider,
+            "api_token_set": !s.api_token.is_empty(),
+            "zone_id": s.zone_id,
+            "propagation_timeout": s.propagation_timeout,
+        })
+    });
+
+    Ok(Json(serde_json::json!({
+        "settings": settings,
+        "certificates": certificates,
+        "directories": {
+            "production": acme::LETSENCRYPT_PRODUCTION,
+            "staging": acme::LETSENCRYPT_STAGING,
+        },
+    })))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SettingsRequest {
+    pub directory_url: String,
+    #[serde(default)]
+    pub email: String,
+    // Omitted or empty keeps the stored token
+    pub api_token: Option<String>,
+    pub zone_id: Option<String>,
+    pub propagation_timeout: Option<i64>,
+}
+
+pub async fn save_settings(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    Json(payload): Json<SettingsRequest>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    require_permission(&user, "security:write").map_err(|(s, m)| (s, m.to_string()))?;
+
+    if mock::is_mock_mode() {
+        return Ok(Json(serde_json::json!({"success": true, "mock": true})));
+    }
+
+    if !payload.directory_url.starts_with("https://") {
+        return Err((StatusCode::BAD_REQUEST, "ACME directory must be an https:// URL".to_string()));
+    }
+    if !payload.email.is_empty() && !payload.email.contains('@') {
+        return Err((StatusCode::BAD_REQUEST, "Invalid contact email".to_string()));
+    }
+
+    let existing = acme::settings(&state.db)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let api_token = match payload.api_token.filter(|t| !t.trim().is_empty()) {
+        Some(token) => token.trim().to_string(),
+        None => existing.map(|s| s.api_token).unwrap_or_default(),
+    };
+
+    let settings = AcmeSettings {
+        directory_url: payload.directory_url,
+        email: payload.email.trim().to_string(),
+        provider: "cloudflare".to_string(),
+        api_token,
+        zone_id: payload.zone_id.map(|z| z.trim().to_string()).filter(|z| !z.is_empty()),
+        propagation_timeout: payload.propagation_timeout.unwrap_or(300).clamp(30, 1800),
+    };
+
+    acme::save_settings(&state.db, &settings)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(serde_json::json!({"success": true})))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct IssueRequest {
+    pub domains: Vec<String>,
+    // Also publish the names in local DNS, pointing at this address
+    pub local_ip: Option<String>,
+}
+
+pub async fn issue(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    Json(payload): Json<IssueRequest>,
+) -> Result<(StatusCode, Json<serde_json::Value>), (StatusCode, String)> {
+    require_permission(&user, "security:write").map_err(|(s, m)| (s, m.to_string()))?;
+
+    if mock::is_mock_mode() {
+        return Ok((StatusCode::ACCEPTED, Json(serde_json::json!({"success": true, "mock": true}))));
+    }
+
+    let configured = acme::settings(&state.db)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .is_some_and(|s| !s.api_token.is_empty());
+    if !configured {
+        return Err((StatusCode::BAD_REQUEST, "Configure the ACME account and DNS provider first".to_string()));
+    }
+
+    let local_ip = payload.local_ip.filter(|ip| !ip.trim().is_empty());
+    let certificate = acme::request(&state.db, &payload.domains, local_ip)
+        .await
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+    Ok((StatusCode::ACCEPTED, Json(serde_json::to_value(certificate).unwrap_or_default())))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CertificateName {
+    pub name: String,
+}
+
+pub async fn renew(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    Json(payload): Json<CertificateName>,
+) -> Result<(StatusCode, Json<serde_json::Value>), (StatusCode, String)> {
+    require_permission(&user, "security:write").map_err(|(s, m)| (s, m.to_string()))?;
+
+    if mock::is_mock_mode() {
+        return Ok((StatusCode::ACCEPTED, Json(serde_json::json!({"success": true, "mock": true}))));
+    }
+
+    let certificate = acme::renew(&state.db, &payload.name)
+        .await
+        .map_err(|e| (StatusCode::NOT_FOUND, e))?;
+
+    Ok((StatusCode::ACCEPTED, Json(serde_json::to_value(certificate).unwrap_or_default())))
+}
+
+pub async fn delete(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    Json(payload): Json<CertificateName>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    require_permission(&user, "security:write").map_err(|(s, m)| (s, m.to_string()))?;
+
+    if mock::is_mock_mode() {
+        return Ok(Json(serde_json::json!({"success": true, "mock": true})));
+    }
+
+    if !acme::delete(&state.db, &payload.name)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    {
+        return Err((StatusCode::NOT_FOUND, "Certificate not found".to_string()));
+    }
+
+    Ok(Json(serde_json::json!({"success": true})))
+}
@@ -1,17 +1,42 @@
-use axum::{http::StatusCode, Json};
-use serde::Serialize;
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 use crate::mock;
 use crate::system;
+use crate::AppState;
 use super::AuthUser;
 
+// Upper bound for any single widget; a hung command only blanks that widget
+const COLLECTOR_TIMEOUT: Duration = Duration::from_secs(3);
+
+// Collectors whose blocking call hasn't returned yet, even if the request gave up on it
+static IN_FLIGHT: Mutex<BTreeSet<&'static str>> = Mutex::new(BTreeSet::new());
+
+// Clears a collector's in-flight mark once its blocking call returns (or panics)
+struct InFlight(&'static str);
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        IN_FLIGHT.lock().unwrap().remove(self.0);
+    }
+}
+
 #[derive(Serialize)]
 pub struct DashboardOverview {
-    pub system: system::SystemStatus,
+    pub system: Option<system::SystemStatus>,
     pub interfaces: Vec<system::NetworkInterface>,
     pub services: Vec<system::ServiceStatus>,
     pub wan_status: WanStatus,
     pub lan_clients: u32,
+    // widget name -> error, for collectors that failed or timed out
+    pub errors: BTreeMap<String, String>,
 }
 
 #[derive(Serialize)]
@@ -22,43 +47,117 @@ pub struct WanStatus {
     pub gateway: Option<String>,
 }
 
+// Run a blocking collector on the blocking pool with a timeout. A timed-out call keeps
+// running in the background, so at most one call per collector is allowed at a time;
+// otherwise polling a hung command would pile up threads and child processes.
+async fn collect<T, F>(name: &'static str, f: F) -> Result<T, String>
+where
+    F: FnOnce() -> Result<T, String> + Send + 'static,
+    T: Send + 'static,
+{
+    if !IN_FLIGHT.lock().unwrap().insert(name) {
+        return Err("Previous collection still running".to_string());
+    }
+    let guard = InFlight(name);
+
+    let task = tokio::task::spawn_blocking(move || {
+        let _guard = guard;
+        f()
+    });
+    match tokio::time::timeout(COLLECTOR_TIMEOUT, task).await {
+        Ok(Ok(result)) => result,
+        Ok(Err(e)) => Err(format!("Collector failed: {}", e)),
+        Err(_) => Err(format!("Timed out after {}s", COLLECTOR_TIMEOUT.as_secs())),
+    }
+}
+
+// Unwrap a collector result, recording the failure under the widget name
+fn widget<T>(errors: &mut BTreeMap<String, String>, name: &str, result: Result<T, String>) -> Option<T> {
+    match result {
+        Ok(value) => Some(value),
+        Err(e) => {
+            errors.insert(name.to_string(), e);
+            None
+        }
+    }
+}
+
 pub async fn overview(
+    State(state): State<Arc<AppState>>,
     AuthUser(_user): AuthUser,
 ) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
     if mock::is_mock_mode() {
         return Ok(Json(mock::dashboard::overview()));
     }
 
-    let system = system::get_system_status()
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
-    
-    let interfaces = system::get_interfaces()
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
-    
-    let services = system::get_services()
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let wan = crate::wan::wan_interface(&state.db).await;
+
+    let (system, interfaces, services, gateway, lan_clients) = tokio::join!(
+        collect("system", || system::get_system_status().map_err(|e| e.to_string())),
+        collect("interfaces", || system::get_interfaces().map_err(|e| e.to_string())),
+        collect("services", || system::get_services().map_err(|e| e.to_string())),
+        collect("gateway", || Ok(get_default_gateway())),
+        collect("lan_clients", || Ok(count_dhcp_leases())),
+    );
 
-    // Find WAN interface (enp1s0)
-    let wan_iface = interfaces.iter().find(|i| i.name == "enp1s0");
+    let mut errors = BTreeMap::new();
+    let system = widget(&mut errors, "system", system);
+    let interfaces = widget(&mut errors, "interfaces", interfaces).unwrap_or_default();
+    let services = widget(&mut errors, "services", services).unwrap_or_default();
+    let gateway = widget(&mut errors, "gateway", gateway).flatten();
+    let lan_clients = widget(&mut errors, "lan_clients", lan_clients).unwrap_or(0);
+
+    let wan_iface = interfaces.iter().find(|i| i.name == wan);
     let wan_status = WanStatus {
         connected: wan_iface.map(|i| i.state == "UP").unwrap_or(false),
-        interface: "enp1s0".to_string(),
+        interface: wan.clone(),
         ip_address: wan_iface.and_then(|i| i.ipv4.clone()),
-        gateway: get_default_gateway(),
+        gateway,
     };
 
-    // Count DHCP leases for LAN clients
-    let lan_clients = count_dhcp_leases();
-
     Ok(Json(serde_json::to_value(DashboardOverview {
         system,
         interfaces,
         services,
         wan_status,
         lan_clients,
+        errors,
     }).unwrap()))
 }
 
+#[derive(Debug, Deserialize)]
+pub struct PowerQuery {
+    pub hours: Option<u32>,
+}
+
+/// Latest power reading with its recent history and energy use per day
+pub async fn power(
+    State(state): State<Arc<AppState>>,
+    AuthUser(_user): AuthUser,
+    Query(query): Query<PowerQuery>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    if mock::is_mock_mode() {
+        return Ok(Json(mock::dashboard::power()));
+    }
+
+    let hours = query.hours.unwrap_or(24).clamp(1, 24 * 30);
+    let history = crate::power::history(&state.db, hours)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let daily = crate::power::daily(&state.db, 30)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let today = chrono::Utc::now().format("%Y-%m-%d").to_string();
+
+    Ok(Json(serde_json::json!({
+        "current": crate::power::latest(),
+        "history": history,
+        "daily": daily,
+        "today_kwh": daily.iter().find(|d| d.day == today).map_or(0.0, |d| d.kwh),
+        "last_30d_kwh": daily.iter().fold(0.0, |total, d| total + d.kwh),
+    })))
+}
+
 fn get_default_gateway() -> Option<String> {
     std::process::Command::new("ip")
         .args(["route", "show", "default"])
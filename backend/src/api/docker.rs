@@ -1,8 +1,14 @@
-use axum::{extract::Json, http::StatusCode};
+use axum::{
+    extract::{Json, Query, State},
+    http::StatusCode,
+};
 use serde::{Deserialize, Serialize};
 use std::process::Command;
+use std::sync::Arc;
 
+use crate::cache::{self, RefreshQuery};
 use crate::mock;
+use crate::AppState;
 
 // ============ DATA STRUCTURES ============
 
@@ -100,11 +106,23 @@ fn parse_docker_json<T: for<'de> Deserialize<'de>>(json_str: &str) -> Option<T>
 
 // ============ API ENDPOINTS ============
 
-pub async fn status() -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+pub async fn status(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<RefreshQuery>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
     if mock::is_mock_mode() {
         return Ok(Json(mock::docker::status()));
     }
 
+    state.cache
+        .get_or_fetch(cache::DOCKER_STATUS, query.refresh, || async {
+            Ok(serde_json::to_value(collect_status()).unwrap())
+        })
+        .await
+        .map(Json)
+}
+
+fn collect_status() -> DockerStatus {
     let installed = Command::new("which")
         .args(["docker"])
         .output()
@@ -112,7 +130,7 @@ pub async fn status() -> Result<Json<serde_json::Value>, (StatusCode, String)> {
         .unwrap_or(false);
 
     if !installed {
-        return Ok(Json(serde_json::to_value(DockerStatus {
+        return DockerStatus {
             installed: false,
             running: false,
             version: String::new(),
@@ -120,7 +138,7 @@ pub async fn status() -> Result<Json<serde_json::Value>, (StatusCode, String)> {
             containers_stopped: 0,
             images_count: 0,
             volumes_count: 0,
-        }).unwrap()));
+        };
     }
 
     let running = docker_available();
@@ -161,7 +179,7 @@ pub async fn status() -> Result<Json<serde_json::Value>, (StatusCode, String)> {
         .map(|o| String::from_utf8_lossy(&o.stdout).lines().count() as u32)
         .unwrap_or(0);
 
-    Ok(Json(serde_json::to_value(DockerStatus {
+    DockerStatus {
         installed,
         running,
         version,
@@ -169,7 +187,7 @@ pub async fn status() -> Result<Json<serde_json::Value>, (StatusCode, String)> {
         containers_stopped,
         images_count,
         volumes_count,
-    }).unwrap()))
+    }
 }
 
 pub async fn containers() -> Result<Json<serde_json::Value>, (StatusCode, String)> {
@@ -254,6 +272,7 @@ pub async fn containers() -> Result<Json<serde_json::Value>, (StatusCode, String
 }
 
 pub async fn container_action(
+    State(state): State<Arc<AppState>>,
     Json(payload): Json<ContainerAction>,
 ) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
     if mock::is_mock_mode() {
@@ -296,6 +315,9 @@ pub async fn container_action(
             String::from_utf8_lossy(&output.stderr).to_string()));
     }
 
+    // Container counts changed
+    state.cache.invalidate(cache::DOCKER_STATUS.0);
+
     Ok(Json(serde_json::json!({
         "success": true,
         "action": payload.action,
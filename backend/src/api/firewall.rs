@@ -1,14 +1,76 @@
-use axum::{extract::Json, http::StatusCode};
+use axum::{extract::{Json, Query, State}, http::StatusCode};
+use chrono::{DateTime, Local};
 use serde::{Deserialize, Serialize};
-use std::process::Command;
+use crate::system::privileges::sudo;
+use sqlx::SqlitePool;
+use std::collections::{HashMap, HashSet};
 use std::fs;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
+use crate::db::firewall_rules::{self, BLOCKED_IP, BLOCKED_IP6, PINHOLE6, PORT_FORWARD};
+use crate::interfaces::Role;
+use crate::system::ipv6;
 use crate::mock;
+use crate::scheduler::{Edge, Schedule};
+use crate::AppState;
+use super::{AuthUser, BulkItem};
 
 const BACKUP_FILE: &str = "/tmp/iptables-backup";
+const BACKUP6_FILE: &str = "/tmp/ip6tables-backup";
 const PENDING_FILE: &str = "/tmp/firewall-pending";
-const ROLLBACK_TIMEOUT: u64 = 300; // 5 minutes in seconds
+const MIN_ROLLBACK_SECS: u64 = 30;
+const MAX_ROLLBACK_SECS: u64 = 60 * 60;
+// External port -> countries allowed to reach that port forward
+const FORWARD_GEO_FILE: &str = "/opt/routerui/port-forward-geo.json";
+// External ports whose new connections are logged for the access stats
+const FORWARD_LOG_FILE: &str = "/opt/routerui/port-forward-log.json";
+const FORWARD_LOG_PREFIX: &str = "RUI-PF-";
+const MAX_BULK_BLOCKS: usize = 500;
+const MAX_DESCRIPTION_LEN: usize = 200;
+
+// Key in the settings table; edited through /api/settings as "firewall.<field>"
+pub const SETTINGS_KEY: &str = "firewall";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FirewallSettings {
+    // How long a change waits for confirmation before it is rolled back
+    pub rollback_timeout_secs: u64,
+}
+
+impl Default for FirewallSettings {
+    fn default() -> Self {
+        Self { rollback_timeout_secs: 300 }
+    }
+}
+
+impl FirewallSettings {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(MIN_ROLLBACK_SECS..=MAX_ROLLBACK_SECS).contains(&self.rollback_timeout_secs) {
+            return Err(format!("Rollback timeout must be {}-{} seconds", MIN_ROLLBACK_SECS, MAX_ROLLBACK_SECS));
+        }
+        Ok(())
+    }
+
+    fn rollback_timeout(&self) -> Duration {
+        Duration::from_secs(self.rollback_timeout_secs.clamp(MIN_ROLLBACK_SECS, MAX_ROLLBACK_SECS))
+    }
+}
+
+pub async fn load_settings(pool: &SqlitePool) -> FirewallSettings {
+    match crate::db::settings::get(pool, SETTINGS_KEY).await {
+        Ok(settings) => settings.unwrap_or_default(),
+        Err(e) => {
+            tracing::warn!("Failed to read firewall settings, using defaults: {}", e);
+            FirewallSettings::default()
+        }
+    }
+}
+
+pub async fn save_settings(pool: &SqlitePool, settings: &FirewallSettings) -> Result<(), String> {
+    crate::db::settings::set(pool, SETTINGS_KEY, settings).await.map_err(|e| e.to_string())
+}
 
 #[derive(Debug, Serialize)]
 pub struct FirewallStatus {
@@ -36,6 +98,21 @@ pub struct PortForward {
     pub internal_ip: String,
     pub internal_port: u16,
     pub description: String,
+    pub created_by: Option<String>,
+    pub created_at: Option<String>,
+    // Empty means reachable from every country
+    pub countries: Vec<String>,
+    pub log_access: bool,
+    // The template group it was created in
+    pub group: Option<String>,
+    // When it is switched on and off; None means always on
+    pub schedule: Option<Schedule>,
+}
+
+impl PortForward {
+    fn key(&self) -> String {
+        forward_key(&self.protocol, self.external_port, &self.internal_ip, self.internal_port)
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -45,6 +122,12 @@ pub struct AddPortForward {
     pub internal_ip: String,
     pub internal_port: u16,
     pub description: Option<String>,
+    // Only forward connections from these countries (ISO codes)
+    #[serde(default)]
+    pub countries: Vec<String>,
+    // Log accepted new connections for the access stats
+    #[serde(default)]
+    pub log_access: bool,
 }
 
 #[derive(Debug, Deserialize)]
@@ -58,7 +141,12 @@ pub struct RemovePortForward {
 #[derive(Debug, Serialize)]
 pub struct BlockedIP {
     pub ip: String,
+    pub enabled: bool,
     pub description: String,
+    pub created_by: Option<String>,
+    pub created_at: Option<String>,
+    // When the block is in force; None means always
+    pub schedule: Option<Schedule>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -72,10 +160,25 @@ pub struct RemoveBlockedIP {
     pub ip: String,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct SetBlockedIPEnabled {
+    pub ip: String,
+    pub enabled: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BulkBlockedIPs {
+    #[serde(default)]
+    pub add: Vec<String>,
+    #[serde(default)]
+    pub remove: Vec<String>,
+}
+
 #[derive(Debug, Serialize)]
 pub struct RawRules {
     pub filter: String,
     pub nat: String,
+    pub filter6: String,
 }
 
 #[derive(Debug, Serialize)]
@@ -121,7 +224,7 @@ fn save_backup() -> Result<(), (StatusCode, String)> {
         return Ok(()); // Don't overwrite backup during pending state
     }
 
-    let output = Command::new("sudo")
+    let output = sudo()
         .args(["iptables-save"])
         .output()
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
@@ -130,7 +233,7 @@ fn save_backup() -> Result<(), (StatusCode, String)> {
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
     // Also save NAT table
-    let nat_output = Command::new("sudo")
+    let nat_output = sudo()
         .args(["iptables-save", "-t", "nat"])
         .output()
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
@@ -138,43 +241,80 @@ fn save_backup() -> Result<(), (StatusCode, String)> {
     fs::write(format!("{}-nat", BACKUP_FILE), &nat_output.stdout)
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
+    // IPv6 too, where the kernel has it; without a backup there is nothing to put back
+    let v6_output = sudo()
+        .args(["ip6tables-save"])
+        .output()
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    if v6_output.status.success() && !v6_output.stdout.is_empty() {
+        fs::write(BACKUP6_FILE, &v6_output.stdout)
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    } else {
+        let _ = fs::remove_file(BACKUP6_FILE);
+    }
+
     Ok(())
 }
 
-fn start_rollback_timer() -> Result<(), (StatusCode, String)> {
-    let deadline = get_current_timestamp() + ROLLBACK_TIMEOUT;
+fn start_rollback_timer(timeout: Duration) -> Result<(), (StatusCode, String)> {
+    let deadline = get_current_timestamp() + timeout.as_secs();
     fs::write(PENDING_FILE, deadline.to_string())
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
-    // Start background rollback process
-    Command::new("bash")
-        .args(["-c", &format!(
-            "sleep {} && [ -f {} ] && sudo iptables-restore < {} && rm -f {} {} 2>/dev/null &",
-            ROLLBACK_TIMEOUT, PENDING_FILE, BACKUP_FILE, PENDING_FILE, BACKUP_FILE
-        )])
-        .spawn()
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    // Roll back unless confirmed in time. A later change rewrites the deadline, so only the
+    // timer for the most recent change acts; do_rollback goes through the helper like everything else
+    tokio::spawn(async move {
+        tokio::time::sleep(timeout).await;
+        let still_pending = fs::read_to_string(PENDING_FILE)
+            .map(|d| d.trim() == deadline.to_string())
+            .unwrap_or(false);
+        if !still_pending {
+            return;
+        }
+        tracing::warn!("Firewall change not confirmed within {}s, rolling back", timeout.as_secs());
+        match tokio::task::spawn_blocking(do_rollback).await {
+            Ok(Err((_, e))) => tracing::error!("Firewall rollback failed: {}", e),
+            Err(e) => tracing::error!("Firewall rollback failed: {}", e),
+            Ok(Ok(())) => {}
+        }
+    });
 
     Ok(())
 }
 
 fn do_rollback() -> Result<(), (StatusCode, String)> {
-    if fs::metadata(BACKUP_FILE).is_ok() {
-        Command::new("sudo")
-            .args(["iptables-restore"])
-            .stdin(std::process::Stdio::from(
-                std::fs::File::open(BACKUP_FILE)
-                    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
-            ))
-            .output()
-            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
-    }
+    restore_backup()?;
 
     // Clean up
     let _ = fs::remove_file(PENDING_FILE);
     let _ = fs::remove_file(BACKUP_FILE);
     let _ = fs::remove_file(format!("{}-nat", BACKUP_FILE));
+    let _ = fs::remove_file(BACKUP6_FILE);
+
+    Ok(())
+}
+
+// Put back the rules saved by save_backup, leaving any pending change and its timer alone
+fn restore_backup() -> Result<(), (StatusCode, String)> {
+    for (file, restore) in [(BACKUP_FILE, "iptables-restore"), (BACKUP6_FILE, "ip6tables-restore")] {
+        if fs::metadata(file).is_err() {
+            continue;
+        }
+        let output = sudo()
+            .args([restore])
+            .stdin(std::process::Stdio::from(
+                std::fs::File::open(file)
+                    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+            ))
+            .output()
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
+        // Keep the backup around so the rollback can be retried
+        if !output.status.success() {
+            return Err((StatusCode::INTERNAL_SERVER_ERROR,
+                String::from_utf8_lossy(&output.stderr).to_string()));
+        }
+    }
     Ok(())
 }
 
@@ -183,6 +323,7 @@ fn do_confirm() -> Result<(), (StatusCode, String)> {
     let _ = fs::remove_file(PENDING_FILE);
     let _ = fs::remove_file(BACKUP_FILE);
     let _ = fs::remove_file(format!("{}-nat", BACKUP_FILE));
+    let _ = fs::remove_file(BACKUP6_FILE);
 
     // Persist rules
     save_rules_permanent()?;
@@ -191,7 +332,7 @@ fn do_confirm() -> Result<(), (StatusCode, String)> {
 }
 
 fn save_rules_permanent() -> Result<(), (StatusCode, String)> {
-    Command::new("sudo")
+    sudo()
         .args(["netfilter-persistent", "save"])
         .output()
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
@@ -199,13 +340,14 @@ fn save_rules_permanent() -> Result<(), (StatusCode, String)> {
 }
 
 // Apply change with rollback protection
-fn apply_with_rollback<F>(change_fn: F) -> Result<(), (StatusCode, String)>
+// The timeout comes from the settings at the time of the change
+fn apply_with_rollback<F>(settings: &FirewallSettings, change_fn: F) -> Result<(), (StatusCode, String)>
 where
     F: FnOnce() -> Result<(), (StatusCode, String)>,
 {
     save_backup()?;
     change_fn()?;
-    start_rollback_timer()?;
+    start_rollback_timer(settings.rollback_timeout())?;
     Ok(())
 }
 
@@ -281,7 +423,7 @@ pub async fn status() -> Result<Json<serde_json::Value>, (StatusCode, String)> {
     // Check for pending changes and possibly trigger rollback
     let (pending, seconds) = check_pending_status();
 
-    let output = Command::new("sudo")
+    let output = sudo()
         .args(["iptables", "-L", "-n"])
         .output()
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
@@ -324,12 +466,44 @@ pub struct ToggleFirewall {
 }
 
 pub async fn toggle(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
     Json(payload): Json<ToggleFirewall>,
-) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+) -> Result<(StatusCode, Json<serde_json::Value>), (StatusCode, String)> {
+    if !payload.enabled && crate::approvals::required() {
+        let action = crate::approvals::Action::DisableFirewall;
+        return Ok(super::approvals::park(&state, &user, action, action.describe().to_string(), serde_json::Value::Null));
+    }
+    Ok((StatusCode::OK, set_enabled(&state.db, payload.enabled).await?))
+}
+
+// Switch the INPUT policy, putting the zone chains in place first when turning it on so trusted
+// zones are never cut off. Extra LAN subnets are let in by their own chain (see subnets/)
+fn change_policy(enabled: bool, zones: &crate::zones::Zones) -> Result<(), (StatusCode, String)> {
+    if enabled {
+        crate::zones::apply(zones).map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+        sudo()
+            .args(["iptables", "-P", "INPUT", "DROP"])
+            .output()
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    } else {
+        // Disable firewall - set to ACCEPT, then drop the zone rules, which would still block
+        sudo()
+            .args(["iptables", "-P", "INPUT", "ACCEPT"])
+            .output()
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        crate::zones::remove();
+    }
+    Ok(())
+}
+
+/// Switch the INPUT policy, under the usual confirm-or-roll-back protection
+pub(crate) async fn set_enabled(pool: &SqlitePool, enabled: bool) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
     if mock::is_mock_mode() {
         return Ok(Json(serde_json::json!({
-            "enabled": payload.enabled,
-            "input_policy": if payload.enabled { "DROP" } else { "ACCEPT" },
+            "enabled": enabled,
+            "input_policy": if enabled { "DROP" } else { "ACCEPT" },
             "forward_policy": "ACCEPT",
             "output_policy": "ACCEPT",
             "pending_changes": false,
@@ -338,82 +512,126 @@ pub async fn toggle(
         })));
     }
 
-    let change_fn = || {
-        if payload.enabled {
-            // Enable firewall with safe rules
-
-            // First, add rules to allow LAN and established connections BEFORE changing policy
-            // Allow LAN
-            let _ = Command::new("sudo")
-                .args(["iptables", "-I", "INPUT", "1", "-i", "enp2s0", "-j", "ACCEPT"])
-                .output();
-
-            // Allow WiFi
-            let _ = Command::new("sudo")
-                .args(["iptables", "-I", "INPUT", "2", "-i", "wlo1", "-j", "ACCEPT"])
-                .output();
-
-            // Allow br0 bridge (LAN traffic goes through here)
-            let _ = Command::new("sudo")
-                .args(["iptables", "-I", "INPUT", "3", "-i", "br0", "-j", "ACCEPT"])
-                .output();
-
-            // Allow loopback
-            let _ = Command::new("sudo")
-                .args(["iptables", "-I", "INPUT", "4", "-i", "lo", "-j", "ACCEPT"])
-                .output();
-
-            // Allow established/related
-            let _ = Command::new("sudo")
-                .args(["iptables", "-I", "INPUT", "5", "-m", "state", "--state", "ESTABLISHED,RELATED", "-j", "ACCEPT"])
-                .output();
-
-            // Allow DHCP on WAN (for IP renewal) - UDP port 68
-            let _ = Command::new("sudo")
-                .args(["iptables", "-I", "INPUT", "6", "-i", "enp1s0", "-p", "udp", "--dport", "68", "-j", "ACCEPT"])
-                .output();
-
-            // Now set INPUT policy to DROP
-            Command::new("sudo")
-                .args(["iptables", "-P", "INPUT", "DROP"])
-                .output()
-                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
-        } else {
-            // Disable firewall - set to ACCEPT
-            Command::new("sudo")
-                .args(["iptables", "-P", "INPUT", "ACCEPT"])
-                .output()
-                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
-        }
-        Ok(())
-    };
-
-    apply_with_rollback(change_fn)?;
+    let zones = crate::zones::load(pool).await;
+    apply_with_rollback(&load_settings(pool).await, || change_policy(enabled, &zones))?;
 
     status().await
 }
 
-// List port forwards
-pub async fn port_forwards() -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+/// Switch and persist straight away, for profile switches nobody is around to confirm
+pub(crate) fn set_enabled_now(enabled: bool, zones: &crate::zones::Zones) -> Result<(), (StatusCode, String)> {
+    change_policy(enabled, zones)?;
+    save_rules_permanent()
+}
+
+// List port forwards, disabled ones included
+pub async fn port_forwards(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
     if mock::is_mock_mode() {
         return Ok(Json(mock::firewall::port_forwards()));
     }
 
-    let output = Command::new("sudo")
+    let forwards = collect_port_forwards(&state.db).await?;
+    Ok(Json(serde_json::to_value(forwards).unwrap()))
+}
+
+// Live forwards in iptables order, then disabled ones, with their metadata filled in
+async fn collect_port_forwards(pool: &SqlitePool) -> Result<Vec<PortForward>, (StatusCode, String)> {
+    let mut forwards = live_port_forwards()?;
+    let meta = firewall_rules::list(pool, PORT_FORWARD)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let live: HashSet<String> = forwards.iter().map(PortForward::key).collect();
+    forwards.extend(
+        meta.values()
+            .filter(|m| !m.enabled && !live.contains(&m.rule_key))
+            .filter_map(|m| parse_forward_key(&m.rule_key)),
+    );
+
+    let groups: HashMap<String, String> = firewall_rules::all_refs(pool)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .into_iter()
+        .filter(|r| r.rule_kind == PORT_FORWARD && r.object_kind == firewall_rules::GROUP)
+        .map(|r| (r.rule_key, r.object_id))
+        .collect();
+
+    let geo = load_forward_geo();
+    let logged = load_forward_log();
+    for forward in &mut forwards {
+        forward.countries = geo.get(&forward.external_port.to_string()).cloned().unwrap_or_default();
+        forward.log_access = logged.contains(&forward.external_port);
+        forward.group = groups.get(&forward.key()).cloned();
+        if let Some(m) = meta.get(&forward.key()) {
+            forward.description = m.description.clone();
+            forward.created_by = m.created_by.clone();
+            forward.created_at = Some(m.created_at.clone());
+            forward.schedule = m.schedule();
+        }
+    }
+
+    Ok(forwards)
+}
+
+// DNAT rules in PREROUTING, in iptables order
+pub(crate) fn live_port_forwards() -> Result<Vec<PortForward>, (StatusCode, String)> {
+    let output = sudo()
         .args(["iptables", "-t", "nat", "-L", "PREROUTING", "-n", "--line-numbers"])
         .output()
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(String::from_utf8_lossy(&output.stdout).lines().skip(2).filter_map(parse_port_forward).collect())
+}
 
-    let rules = String::from_utf8_lossy(&output.stdout);
-    let mut forwards = Vec::new();
+// "tcp:8443:192.168.1.10:443" - how a forward is known in firewall_rules
+fn forward_key(proto: &str, ext_port: u16, int_ip: &str, int_port: u16) -> String {
+    format!("{}:{}:{}:{}", proto.to_lowercase(), ext_port, int_ip, int_port)
+}
 
-    for line in rules.lines().skip(2) {
-        if let Some(forward) = parse_port_forward(line) {
-            forwards.push(forward);
-        }
-    }
+// A live forward already taking `proto` traffic on `ext_port`, other than the one keyed `except`.
+// iptables would accept a second DNAT rule, but only the first one ever matches.
+fn forward_on_port<'a>(live: &'a [PortForward], proto: &str, ext_port: u16, except: Option<&str>) -> Option<&'a PortForward> {
+    live.iter()
+        .find(|f| f.protocol == proto && f.external_port == ext_port && except != Some(f.key().as_str()))
+}
 
-    Ok(Json(serde_json::to_value(forwards).unwrap()))
+fn port_conflict(forward: &PortForward) -> (StatusCode, String) {
+    (
+        StatusCode::CONFLICT,
+        format!(
+            "{} port {} is already forwarded to {}:{}; disable or remove that forward first",
+            forward.protocol, forward.external_port, forward.internal_ip, forward.internal_port
+        ),
+    )
+}
+
+// A disabled forward, rebuilt from its key; it has no iptables line number
+fn parse_forward_key(key: &str) -> Option<PortForward> {
+    let mut parts = key.split(':');
+    let (protocol, external_port, internal_ip, internal_port) = (parts.next()?, parts.next()?, parts.next()?, parts.next()?);
+    Some(PortForward {
+        id: 0,
+        enabled: false,
+        protocol: protocol.to_string(),
+        external_port: external_port.parse().ok()?,
+        internal_ip: internal_ip.to_string(),
+        internal_port: internal_port.parse().ok()?,
+        description: String::new(),
+        created_by: None,
+        created_at: None,
+        countries: Vec::new(),
+        log_access: false,
+        group: None,
+        schedule: None,
+    })
+}
+
+fn clean_description(description: Option<&str>) -> Result<String, (StatusCode, String)> {
+    let description = description.unwrap_or_default().trim();
+    if description.chars().count() > MAX_DESCRIPTION_LEN {
+        return Err((StatusCode::BAD_REQUEST, format!("Descriptions can be at most {} characters", MAX_DESCRIPTION_LEN)));
+    }
+    Ok(description.to_string())
 }
 
 fn parse_port_forward(line: &str) -> Option<PortForward> {
@@ -456,245 +674,2102 @@ fn parse_port_forward(line: &str) -> Option<PortForward> {
         internal_ip,
         internal_port,
         description: String::new(),
+        created_by: None,
+        created_at: None,
+        countries: Vec::new(),
+        log_access: false,
+        group: None,
+        schedule: None,
     })
 }
 
-// Add port forward
-pub async fn add_port_forward(
-    Json(payload): Json<AddPortForward>,
-) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
-    if mock::is_mock_mode() {
-        return Ok(Json(serde_json::json!({"success": true, "pending": true, "mock": true})));
+// ============ PORT FORWARD GEO RESTRICTIONS ============
+
+fn load_forward_geo() -> HashMap<String, Vec<String>> {
+    fs::read_to_string(FORWARD_GEO_FILE)
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+fn save_forward_geo(geo: &HashMap<String, Vec<String>>) -> Result<(), (StatusCode, String)> {
+    let json = serde_json::to_string_pretty(geo)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    fs::write(FORWARD_GEO_FILE, json)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+}
+
+// One ipset per external port, holding the allowed countries' ranges
+fn forward_geo_set(external_port: u16) -> String {
+    format!("pf-geo-{}", external_port)
+}
+
+// Download the countries' ranges into the port's ipset (before any rule references it)
+fn fill_forward_geo_set(external_port: u16, countries: &[String]) -> Result<(), (StatusCode, String)> {
+    let mut ranges = Vec::new();
+    for code in countries {
+        ranges.extend(super::protection::download_country_zone(code)?);
     }
 
-    let protocol = payload.protocol.to_lowercase();
-    if protocol != "tcp" && protocol != "udp" && protocol != "both" {
-        return Err((StatusCode::BAD_REQUEST, "Invalid protocol".to_string()));
+    let set_name = forward_geo_set(external_port);
+    super::protection::create_ipset(&set_name)?;
+    let _ = sudo().args(["ipset", "flush", &set_name]).output();
+    for range in &ranges {
+        let _ = sudo().args(["ipset", "add", &set_name, range, "-exist"]).output();
     }
+    Ok(())
+}
 
-    let protocols: Vec<&str> = if protocol == "both" {
-        vec!["tcp", "udp"]
-    } else {
-        vec![protocol.as_str()]
-    };
+// FORWARD rules for one forwarded port, without the leading -A/-D: ACCEPT (only from the
+// port's ipset when restricted), followed by a DROP for everyone else
+fn forward_rules(proto: &str, int_ip: &str, int_port: u16, geo_set: Option<&str>) -> Vec<Vec<String>> {
+    let base = |v: &[&str]| v.iter().map(|a| a.to_string()).collect::<Vec<String>>();
+    let port = int_port.to_string();
+    let target = base(&["-p", proto, "-d", int_ip, "--dport", &port]);
+
+    match geo_set {
+        None => vec![[target, base(&["-j", "ACCEPT"])].concat()],
+        Some(set) => vec![
+            [target.clone(), base(&["-m", "set", "--match-set", set, "src", "-j", "ACCEPT"])].concat(),
+            [target, base(&["-j", "DROP"])].concat(),
+        ],
+    }
+}
 
-    let ext_port = payload.external_port;
-    let int_ip = payload.internal_ip.clone();
-    let int_port = payload.internal_port;
+// ============ PORT FORWARD ACCESS LOG ============
 
-    let change_fn = move || {
-        for proto in &protocols {
-            let dnat_result = Command::new("sudo")
-                .args([
-                    "iptables", "-t", "nat", "-A", "PREROUTING",
-                    "-i", "enp1s0",
-                    "-p", proto,
-                    "--dport", &ext_port.to_string(),
-                    "-j", "DNAT",
-                    "--to-destination", &format!("{}:{}", int_ip, int_port),
-                ])
-                .output()
-                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+fn load_forward_log() -> Vec<u16> {
+    fs::read_to_string(FORWARD_LOG_FILE)
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
 
-            if !dnat_result.status.success() {
-                return Err((StatusCode::INTERNAL_SERVER_ERROR,
-                    String::from_utf8_lossy(&dnat_result.stderr).to_string()));
-            }
+fn save_forward_log(ports: &[u16]) -> Result<(), (StatusCode, String)> {
+    let json = serde_json::to_string_pretty(ports)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    fs::write(FORWARD_LOG_FILE, json)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+}
 
-            let forward_result = Command::new("sudo")
-                .args([
-                    "iptables", "-A", "FORWARD",
-                    "-p", proto,
-                    "-d", &int_ip,
-                    "--dport", &int_port.to_string(),
-                    "-j", "ACCEPT",
-                ])
-                .output()
-                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+// "RUI-PF-8443-tcp: " - the external port and protocol identify the forward in the kernel log
+fn forward_log_prefix(ext_port: u16, proto: &str) -> String {
+    format!("{}{}-{}: ", FORWARD_LOG_PREFIX, ext_port, proto)
+}
+
+// LOG rule for new connections the forward accepts, without the leading -I/-D. Rate limited so
+// a scan can't flood the journal; the stats undercount during bursts.
+fn forward_log_rule(proto: &str, ext_port: u16, int_ip: &str, int_port: u16, geo_set: Option<&str>) -> Vec<String> {
+    let mut rule: Vec<String> = ["-p", proto, "-d", int_ip, "--dport", &int_port.to_string()]
+        .iter()
+        .map(|a| a.to_string())
+        .collect();
+    if let Some(set) = geo_set {
+        rule.extend(["-m", "set", "--match-set", set, "src"].map(String::from));
+    }
+    rule.extend(["-m", "conntrack", "--ctstate", "NEW", "-m", "limit", "--limit", "30/min", "--limit-burst", "60"].map(String::from));
+    rule.extend(["-j", "LOG", "--log-prefix"].map(String::from));
+    rule.push(forward_log_prefix(ext_port, proto));
+    rule
+}
+
+fn add_forward_log_rule(proto: &str, ext_port: u16, int_ip: &str, int_port: u16, geo_set: Option<&str>) -> Result<(), (StatusCode, String)> {
+    // Ahead of the ACCEPT, which was appended
+    let output = sudo()
+        .args(["iptables", "-I", "FORWARD", "1"])
+        .args(forward_log_rule(proto, ext_port, int_ip, int_port, geo_set))
+        .output()
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    if !output.status.success() {
+        return Err((StatusCode::INTERNAL_SERVER_ERROR, String::from_utf8_lossy(&output.stderr).to_string()));
+    }
+    Ok(())
+}
+
+fn delete_forward_log_rule(proto: &str, ext_port: u16, int_ip: &str, int_port: u16, geo_set: Option<&str>) {
+    let _ = sudo()
+        .args(["iptables", "-D", "FORWARD"])
+        .args(forward_log_rule(proto, ext_port, int_ip, int_port, geo_set))
+        .output();
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct CountryCount {
+    pub country: String,
+    pub connections: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SourceCount {
+    pub ip: String,
+    pub country: Option<String>,
+    pub connections: u64,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ForwardAccess {
+    pub external_port: u16,
+    pub protocol: String,
+    pub connections: u64,
+    pub unique_sources: usize,
+    pub first_seen: Option<String>,
+    pub last_seen: Option<String>,
+    pub countries: Vec<CountryCount>,
+    pub top_sources: Vec<SourceCount>,
+}
+
+// (timestamp, external port, protocol, source) from one kernel log line
+fn parse_forward_log_line(line: &str) -> Option<(String, u16, String, String)> {
+    let start = line.find(FORWARD_LOG_PREFIX)? + FORWARD_LOG_PREFIX.len();
+    let (port, rest) = line[start..].split_once('-')?;
+    let proto = rest.split(':').next()?;
+    let src = line.split_whitespace().find_map(|p| p.strip_prefix("SRC="))?;
+    let timestamp = line.split_whitespace().next()?;
+    Some((timestamp.to_string(), port.parse().ok()?, proto.to_string(), src.to_string()))
+}
+
+// Connections per source of one forward, with the first and last log timestamps
+struct SeenForward {
+    sources: HashMap<String, u64>,
+    first: String,
+    last: String,
+}
+
+fn aggregate_forward_log(log: &str) -> Vec<ForwardAccess> {
+    let mut seen: HashMap<(u16, String), SeenForward> = HashMap::new();
+    for (timestamp, port, proto, src) in log.lines().filter_map(parse_forward_log_line) {
+        let entry = seen.entry((port, proto)).or_insert_with(|| SeenForward {
+            sources: HashMap::new(),
+            first: timestamp.clone(),
+            last: timestamp.clone(),
+        });
+        *entry.sources.entry(src).or_default() += 1;
+        entry.last = timestamp;
+    }
 
-            if !forward_result.status.success() {
-                return Err((StatusCode::INTERNAL_SERVER_ERROR,
-                    String::from_utf8_lossy(&forward_result.stderr).to_string()));
+    let mut stats: Vec<ForwardAccess> = seen
+        .into_iter()
+        .map(|((port, proto), SeenForward { sources, first, last })| {
+            let mut countries: HashMap<String, u64> = HashMap::new();
+            let mut top_sources: Vec<SourceCount> = sources
+                .into_iter()
+                .map(|(ip, connections)| {
+                    let country = crate::geoip::country(&ip);
+                    *countries.entry(country.clone().unwrap_or_else(|| "??".to_string())).or_default() += connections;
+                    SourceCount { ip, country, connections }
+                })
+                .collect();
+            top_sources.sort_by(|a, b| b.connections.cmp(&a.connections).then_with(|| a.ip.cmp(&b.ip)));
+            let connections = top_sources.iter().map(|s| s.connections).sum();
+            let unique_sources = top_sources.len();
+            top_sources.truncate(10);
+
+            let mut countries: Vec<CountryCount> = countries
+                .into_iter()
+                .map(|(country, connections)| CountryCount { country, connections })
+                .collect();
+            countries.sort_by(|a, b| b.connections.cmp(&a.connections).then_with(|| a.country.cmp(&b.country)));
+
+            ForwardAccess {
+                external_port: port,
+                protocol: proto,
+                connections,
+                unique_sources,
+                first_seen: Some(first),
+                last_seen: Some(last),
+                countries,
+                top_sources,
             }
-        }
-        Ok(())
-    };
+        })
+        .collect();
+    stats.sort_by_key(|f| (f.external_port, f.protocol.clone()));
+    stats
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AccessQuery {
+    #[serde(default = "default_access_hours")]
+    pub hours: u32,
+}
 
-    apply_with_rollback(change_fn)?;
+fn default_access_hours() -> u32 {
+    24
+}
 
-    Ok(Json(serde_json::json!({"success": true, "pending": true})))
+// Per-forward access stats from the kernel log
+pub async fn port_forward_access(
+    Query(query): Query<AccessQuery>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    if mock::is_mock_mode() {
+        return Ok(Json(mock::firewall::port_forward_access()));
+    }
+
+    if !(1..=24 * 31).contains(&query.hours) {
+        return Err((StatusCode::BAD_REQUEST, "hours must be between 1 and 744".to_string()));
+    }
+
+    let stats = tokio::task::spawn_blocking(move || {
+        let output = sudo()
+            .args(["journalctl", "-k", "--since", &format!("{} hours ago", query.hours), "--no-pager", "-o", "short-iso"])
+            .output()
+            .map_err(|e| e.to_string())?;
+        Ok::<_, String>(aggregate_forward_log(&String::from_utf8_lossy(&output.stdout)))
+    })
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    let geoip_updated = crate::geoip::updated_at().map(|t| chrono::DateTime::<chrono::Utc>::from(t).to_rfc3339());
+
+    Ok(Json(serde_json::json!({
+        "hours": query.hours,
+        "logged_ports": load_forward_log(),
+        "geoip_updated": geoip_updated,
+        "forwards": stats,
+    })))
 }
 
-// Remove port forward
-pub async fn remove_port_forward(
-    Json(payload): Json<RemovePortForward>,
+#[derive(Debug, Deserialize)]
+pub struct SetForwardLogging {
+    pub protocol: String,
+    pub external_port: u16,
+    pub internal_ip: String,
+    pub internal_port: u16,
+    pub enabled: bool,
+}
+
+// Turn connection logging on or off for an existing forward
+pub async fn set_port_forward_logging(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<SetForwardLogging>,
 ) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
     if mock::is_mock_mode() {
         return Ok(Json(serde_json::json!({"success": true, "pending": true, "mock": true})));
     }
 
     let protocol = payload.protocol.to_lowercase();
-    let protocols: Vec<&str> = if protocol == "both" {
-        vec!["tcp", "udp"]
-    } else {
-        vec![protocol.as_str()]
+    let protocols: Vec<String> = match protocol.as_str() {
+        "both" => vec!["tcp".to_string(), "udp".to_string()],
+        "tcp" | "udp" => vec![protocol],
+        _ => return Err((StatusCode::BAD_REQUEST, "Invalid protocol".to_string())),
     };
 
     let ext_port = payload.external_port;
     let int_ip = payload.internal_ip.clone();
     let int_port = payload.internal_port;
+    let enabled = payload.enabled;
+    let geo_set = load_forward_geo()
+        .contains_key(&ext_port.to_string())
+        .then(|| forward_geo_set(ext_port));
 
     let change_fn = move || {
         for proto in &protocols {
-            let _ = Command::new("sudo")
-                .args([
-                    "iptables", "-t", "nat", "-D", "PREROUTING",
-                    "-i", "enp1s0",
-                    "-p", proto,
-                    "--dport", &ext_port.to_string(),
-                    "-j", "DNAT",
-                    "--to-destination", &format!("{}:{}", int_ip, int_port),
-                ])
-                .output();
-
-            let _ = Command::new("sudo")
-                .args([
-                    "iptables", "-D", "FORWARD",
-                    "-p", proto,
-                    "-d", &int_ip,
-                    "--dport", &int_port.to_string(),
-                    "-j", "ACCEPT",
-                ])
-                .output();
+            // Never more than one LOG rule per forward
+            delete_forward_log_rule(proto, ext_port, &int_ip, int_port, geo_set.as_deref());
+            if enabled {
+                add_forward_log_rule(proto, ext_port, &int_ip, int_port, geo_set.as_deref())?;
+            }
         }
         Ok(())
     };
 
-    apply_with_rollback(change_fn)?;
+    apply_with_rollback(&load_settings(&state.db).await, change_fn)?;
+
+    let mut logged = load_forward_log();
+    logged.retain(|p| *p != ext_port);
+    if enabled {
+        logged.push(ext_port);
+        logged.sort_unstable();
+    }
+    save_forward_log(&logged)?;
 
     Ok(Json(serde_json::json!({"success": true, "pending": true})))
 }
 
-// List blocked IPs
-pub async fn blocked_ips() -> Result<Json<Vec<BlockedIP>>, (StatusCode, String)> {
-    if mock::is_mock_mode() {
-        return Ok(Json(vec![
-            BlockedIP { ip: "45.155.205.100".to_string(), description: "Known scanner".to_string() },
-            BlockedIP { ip: "192.168.1.100".to_string(), description: "Test block".to_string() },
-        ]));
-    }
+// DNAT and FORWARD rules of one forwarded port; missing rules are ignored
+fn delete_port_forward(proto: &str, ext_port: u16, int_ip: &str, int_port: u16, geo_set: Option<&str>) {
+    let (port, destination) = (ext_port.to_string(), format!("{}:{}", int_ip, int_port));
+    remove_wan_dnat(|rule| rule_has(rule, "-p", proto) && rule_has(rule, "--dport", &port) && rule_has(rule, "--to-destination", &destination));
 
-    let output = Command::new("sudo")
-        .args(["iptables", "-L", "INPUT", "-n", "--line-numbers"])
-        .output()
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    for rule in forward_rules(proto, int_ip, int_port, geo_set) {
+        let _ = sudo()
+            .args(["iptables", "-D", "FORWARD"])
+            .args(&rule)
+            .output();
+    }
+    delete_forward_log_rule(proto, ext_port, int_ip, int_port, geo_set);
+}
 
-    let rules = String::from_utf8_lossy(&output.stdout);
-    let mut blocked = Vec::new();
+fn rule_has(rule: &[&str], flag: &str, value: &str) -> bool {
+    rule.windows(2).any(|w| w[0] == flag && w[1] == value)
+}
 
-    for line in rules.lines().skip(2) {
-        if let Some(ip) = parse_blocked_ip(line) {
-            blocked.push(ip);
+// DNAT rules in PREROUTING for which `wanted` holds. They are read back rather than rebuilt,
+// since the WAN interface they were added on may have changed since.
+fn remove_wan_dnat(wanted: impl Fn(&[&str]) -> bool) {
+    let Ok(output) = sudo().args(["iptables", "-t", "nat", "-S", "PREROUTING"]).output() else { return };
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        let Some(rule) = line.strip_prefix("-A PREROUTING ") else { continue };
+        let rule: Vec<&str> = rule.split_whitespace().collect();
+        if rule_has(&rule, "-j", "DNAT") && wanted(&rule) {
+            let _ = sudo().args(["iptables", "-t", "nat", "-D", "PREROUTING"]).args(&rule).output();
         }
     }
-
-    Ok(Json(blocked))
 }
 
-fn parse_blocked_ip(line: &str) -> Option<BlockedIP> {
-    let parts: Vec<&str> = line.split_whitespace().collect();
+/// Remove every port forward and its country sets, then persist the firewall (factory reset).
+/// No rollback timer: the reset is meant to stick.
+pub(crate) fn teardown() -> Result<(), (StatusCode, String)> {
+    let geo = load_forward_geo();
 
-    if parts.len() < 5 || parts[1] != "DROP" {
-        return None;
+    for forward in live_port_forwards()? {
+        let geo_set = geo.contains_key(&forward.external_port.to_string()).then(|| forward_geo_set(forward.external_port));
+        let protocol = forward.protocol.to_lowercase();
+        delete_port_forward(&protocol, forward.external_port, &forward.internal_ip, forward.internal_port, geo_set.as_deref());
     }
-
-    let source = parts[4];
-    if source == "0.0.0.0/0" {
-        return None;
+    for port in geo.keys().filter_map(|p| p.parse().ok()) {
+        let _ = sudo().args(["ipset", "destroy", &forward_geo_set(port)]).output();
     }
+    let _ = fs::remove_file(FORWARD_GEO_FILE);
+    let _ = fs::remove_file(FORWARD_LOG_FILE);
 
-    Some(BlockedIP {
-        ip: source.to_string(),
-        description: String::new(),
-    })
+    save_rules_permanent()
 }
 
-// Add blocked IP
-pub async fn add_blocked_ip(
-    Json(payload): Json<AddBlockedIP>,
-) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
-    if mock::is_mock_mode() {
-        return Ok(Json(serde_json::json!({"success": true, "pending": true, "mock": true})));
-    }
-
-    let ip = payload.ip.clone();
+// DNAT (on the WAN zone's interface) and FORWARD rules of one forwarded port, plus its LOG rule
+// when logged
+fn insert_port_forward(
+    wan: &str,
+    proto: &str,
+    ext_port: u16,
+    int_ip: &str,
+    int_port: u16,
+    geo_set: Option<&str>,
+    log_access: bool,
+) -> Result<(), (StatusCode, String)> {
+    let dnat_result = sudo()
+        .args([
+            "iptables", "-t", "nat", "-A", "PREROUTING",
+            "-i", wan,
+            "-p", proto,
+            "--dport", &ext_port.to_string(),
+            "-j", "DNAT",
+            "--to-destination", &format!("{}:{}", int_ip, int_port),
+        ])
+        .output()
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
-    let change_fn = move || {
-        Command::new("sudo")
-            .args(["iptables", "-I", "INPUT", "1", "-s", &ip, "-j", "DROP"])
-            .output()
-            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    if !dnat_result.status.success() {
+        return Err((StatusCode::INTERNAL_SERVER_ERROR,
+            String::from_utf8_lossy(&dnat_result.stderr).to_string()));
+    }
 
-        Command::new("sudo")
-            .args(["iptables", "-I", "FORWARD", "1", "-s", &ip, "-j", "DROP"])
+    for rule in forward_rules(proto, int_ip, int_port, geo_set) {
+        let forward_result = sudo()
+            .args(["iptables", "-A", "FORWARD"])
+            .args(&rule)
             .output()
             .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
-        Ok(())
-    };
-
-    apply_with_rollback(change_fn)?;
-
-    Ok(Json(serde_json::json!({"success": true, "pending": true})))
+        if !forward_result.status.success() {
+            return Err((StatusCode::INTERNAL_SERVER_ERROR,
+                String::from_utf8_lossy(&forward_result.stderr).to_string()));
+        }
+    }
+    if log_access {
+        add_forward_log_rule(proto, ext_port, int_ip, int_port, geo_set)?;
+    }
+    Ok(())
+}
+
+// Add port forward
+pub async fn add_port_forward(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    Json(payload): Json<AddPortForward>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    if mock::is_mock_mode() {
+        return Ok(Json(serde_json::json!({"success": true, "pending": true, "mock": true})));
+    }
+
+    let protocol = payload.protocol.to_lowercase();
+    if protocol != "tcp" && protocol != "udp" && protocol != "both" {
+        return Err((StatusCode::BAD_REQUEST, "Invalid protocol".to_string()));
+    }
+    let description = clean_description(payload.description.as_deref())?;
+
+    let protocols: Vec<&str> = if protocol == "both" {
+        vec!["tcp", "udp"]
+    } else {
+        vec![protocol.as_str()]
+    };
+
+    let mut countries: Vec<String> = payload.countries.iter().map(|c| c.trim().to_uppercase()).collect();
+    countries.sort();
+    countries.dedup();
+    if let Some(code) = countries.iter().find(|c| !super::protection::valid_country_code(c)) {
+        return Err((StatusCode::BAD_REQUEST, format!("Invalid country code: {}", code)));
+    }
+
+    let ext_port = payload.external_port;
+    let int_ip = payload.internal_ip.clone();
+    let int_port = payload.internal_port;
+    let log_access = payload.log_access;
+
+    let live = live_port_forwards()?;
+    if let Some(existing) = protocols.iter().find_map(|proto| forward_on_port(&live, proto, ext_port, None)) {
+        return Err(port_conflict(existing));
+    }
+
+    let geo_set = if countries.is_empty() {
+        None
+    } else {
+        fill_forward_geo_set(ext_port, &countries)?;
+        Some(forward_geo_set(ext_port))
+    };
+
+    let keys: Vec<String> = protocols.iter().map(|proto| forward_key(proto, ext_port, &int_ip, int_port)).collect();
+    let wan = crate::wan::wan_interface(&state.db).await;
+    let change_fn = move || {
+        for proto in &protocols {
+            insert_port_forward(&wan, proto, ext_port, &int_ip, int_port, geo_set.as_deref(), log_access)?;
+        }
+        Ok(())
+    };
+
+    apply_with_rollback(&load_settings(&state.db).await, change_fn)?;
+
+    // Tie the forward to the device behind the address, so deleting the device can offer to
+    // remove it too
+    let ip = payload.internal_ip.clone();
+    let device = tokio::task::spawn_blocking(move || super::network::mac_for_ip(&ip))
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    for key in &keys {
+        firewall_rules::record(&state.db, PORT_FORWARD, key, &description, &user.username)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        if let Some(mac) = &device {
+            firewall_rules::add_ref(&state.db, PORT_FORWARD, key, firewall_rules::DEVICE, mac)
+                .await
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        }
+    }
+
+    let mut logged = load_forward_log();
+    logged.retain(|p| *p != ext_port);
+    if log_access {
+        logged.push(ext_port);
+        logged.sort_unstable();
+    }
+    save_forward_log(&logged)?;
+
+    let mut geo = load_forward_geo();
+    if countries.is_empty() {
+        geo.remove(&ext_port.to_string());
+    } else {
+        geo.insert(ext_port.to_string(), countries);
+    }
+    save_forward_geo(&geo)?;
+
+    Ok(Json(serde_json::json!({"success": true, "pending": true})))
+}
+
+// Remove port forward
+pub async fn remove_port_forward(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<RemovePortForward>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    if mock::is_mock_mode() {
+        return Ok(Json(serde_json::json!({"success": true, "pending": true, "mock": true})));
+    }
+
+    let protocol = payload.protocol.to_lowercase();
+    let protocols: Vec<&str> = if protocol == "both" {
+        vec!["tcp", "udp"]
+    } else {
+        vec![protocol.as_str()]
+    };
+
+    let ext_port = payload.external_port;
+    let int_ip = payload.internal_ip.clone();
+    let int_port = payload.internal_port;
+
+    let geo_set = load_forward_geo()
+        .contains_key(&ext_port.to_string())
+        .then(|| forward_geo_set(ext_port));
+
+    let keys: Vec<String> = protocols.iter().map(|proto| forward_key(proto, ext_port, &int_ip, int_port)).collect();
+    let change_fn = move || {
+        for proto in &protocols {
+            delete_port_forward(proto, ext_port, &int_ip, int_port, geo_set.as_deref());
+        }
+        Ok(())
+    };
+
+    apply_with_rollback(&load_settings(&state.db).await, change_fn)?;
+
+    for key in &keys {
+        firewall_rules::remove(&state.db, PORT_FORWARD, key)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    }
+
+    // The ipset itself stays until the port is forwarded again, so a rollback can still restore
+    // rules that reference it
+    let mut geo = load_forward_geo();
+    if geo.remove(&ext_port.to_string()).is_some() {
+        save_forward_geo(&geo)?;
+    }
+    let mut logged = load_forward_log();
+    if logged.contains(&ext_port) {
+        logged.retain(|p| *p != ext_port);
+        save_forward_log(&logged)?;
+    }
+
+    Ok(Json(serde_json::json!({"success": true, "pending": true})))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetForwardEnabled {
+    pub protocol: String,
+    pub external_port: u16,
+    pub internal_ip: String,
+    pub internal_port: u16,
+    pub enabled: bool,
+}
+
+// Switch a forward off without forgetting it, or back on. Its countries and logging are kept
+// for when it comes back.
+pub async fn set_port_forward_enabled(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    Json(payload): Json<SetForwardEnabled>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    if mock::is_mock_mode() {
+        return Ok(Json(serde_json::json!({"success": true, "pending": true, "mock": true})));
+    }
+
+    let protocol = payload.protocol.to_lowercase();
+    let protocols: Vec<String> = match protocol.as_str() {
+        "both" => vec!["tcp".to_string(), "udp".to_string()],
+        "tcp" | "udp" => vec![protocol],
+        _ => return Err((StatusCode::BAD_REQUEST, "Invalid protocol".to_string())),
+    };
+
+    let ext_port = payload.external_port;
+    let int_ip = payload.internal_ip.clone();
+    let int_port = payload.internal_port;
+    let enabled = payload.enabled;
+
+    let live_forwards = live_port_forwards()?;
+    let live: HashSet<String> = live_forwards.iter().map(PortForward::key).collect();
+    let meta = firewall_rules::list(&state.db, PORT_FORWARD)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let keys: Vec<(String, String)> = protocols
+        .into_iter()
+        .map(|proto| {
+            let key = forward_key(&proto, ext_port, &int_ip, int_port);
+            (proto, key)
+        })
+        .filter(|(_, key)| live.contains(key) || meta.contains_key(key))
+        .collect();
+    if keys.is_empty() {
+        return Err((StatusCode::NOT_FOUND, "Port forward not found".to_string()));
+    }
+
+    // Only the protocols whose state actually changes touch iptables
+    let changing: Vec<String> = keys.iter().filter(|(_, key)| live.contains(key) != enabled).map(|(proto, _)| proto.clone()).collect();
+    // The port may have been given to another forward while this one was off
+    if enabled {
+        for (proto, key) in keys.iter().filter(|(proto, _)| changing.contains(proto)) {
+            if let Some(existing) = forward_on_port(&live_forwards, proto, ext_port, Some(key)) {
+                return Err(port_conflict(existing));
+            }
+        }
+    }
+    if !changing.is_empty() {
+        let countries = load_forward_geo().remove(&ext_port.to_string());
+        let geo_set = countries.as_ref().map(|_| forward_geo_set(ext_port));
+        if let (true, Some(countries)) = (enabled, &countries) {
+            fill_forward_geo_set(ext_port, countries)?;
+        }
+        let log_access = load_forward_log().contains(&ext_port);
+        let wan = crate::wan::wan_interface(&state.db).await;
+
+        let change_fn = move || {
+            for proto in &changing {
+                if enabled {
+                    insert_port_forward(&wan, proto, ext_port, &int_ip, int_port, geo_set.as_deref(), log_access)?;
+                } else {
+                    delete_port_forward(proto, ext_port, &int_ip, int_port, geo_set.as_deref());
+                }
+            }
+            Ok(())
+        };
+        apply_with_rollback(&load_settings(&state.db).await, change_fn)?;
+    }
+
+    for (_, key) in &keys {
+        firewall_rules::set_enabled(&state.db, PORT_FORWARD, key, enabled)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    }
+
+    tracing::info!(
+        "User {} {} port forward {} {} -> {}:{}",
+        user.username,
+        if enabled { "enabled" } else { "disabled" },
+        payload.protocol, ext_port, payload.internal_ip, int_port
+    );
+    Ok(Json(serde_json::json!({"success": true, "pending": true})))
+}
+
+// List blocked IPs, disabled ones included
+pub async fn blocked_ips(State(state): State<Arc<AppState>>) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    if mock::is_mock_mode() {
+        return Ok(Json(mock::firewall::blocked_ips()));
+    }
+
+    let mut blocked = live_blocked_ips()?;
+    let meta = firewall_rules::list(&state.db, BLOCKED_IP)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let live: HashSet<String> = blocked.iter().map(|b| b.ip.clone()).collect();
+    blocked.extend(meta.values().filter(|m| !m.enabled && !live.contains(&m.rule_key)).map(|m| BlockedIP {
+        ip: m.rule_key.clone(),
+        enabled: false,
+        description: String::new(),
+        created_by: None,
+        created_at: None,
+        schedule: None,
+    }));
+
+    for entry in &mut blocked {
+        if let Some(m) = meta.get(&entry.ip) {
+            entry.description = m.description.clone();
+            entry.created_by = m.created_by.clone();
+            entry.created_at = Some(m.created_at.clone());
+            entry.schedule = m.schedule();
+        }
+    }
+
+    Ok(Json(serde_json::to_value(blocked).unwrap()))
+}
+
+fn live_blocked_ips() -> Result<Vec<BlockedIP>, (StatusCode, String)> {
+    let output = sudo()
+        .args(["iptables", "-L", "INPUT", "-n", "--line-numbers"])
+        .output()
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(String::from_utf8_lossy(&output.stdout).lines().skip(2).filter_map(parse_blocked_ip).collect())
+}
+
+fn parse_blocked_ip(line: &str) -> Option<BlockedIP> {
+    let parts: Vec<&str> = line.split_whitespace().collect();
+
+    if parts.len() < 5 || parts[1] != "DROP" {
+        return None;
+    }
+
+    let source = parts[4];
+    if source == "0.0.0.0/0" {
+        return None;
+    }
+
+    Some(BlockedIP {
+        ip: source.to_string(),
+        enabled: true,
+        description: String::new(),
+        created_by: None,
+        created_at: None,
+        schedule: None,
+    })
+}
+
+// Add blocked IP
+pub async fn add_blocked_ip(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    Json(payload): Json<AddBlockedIP>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    if mock::is_mock_mode() {
+        return Ok(Json(serde_json::json!({"success": true, "pending": true, "mock": true})));
+    }
+
+    // Stored the way iptables lists it, so the metadata lines up with the rule
+    let ip = normalize_block_target(&payload.ip)
+        .ok_or((StatusCode::BAD_REQUEST, "Not an IPv4 address or range".to_string()))?;
+    let description = clean_description(payload.description.as_deref())?;
+    let key = ip.clone();
+
+    let change_fn = move || {
+        sudo()
+            .args(["iptables", "-I", "INPUT", "1", "-s", &ip, "-j", "DROP"])
+            .output()
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+        sudo()
+            .args(["iptables", "-I", "FORWARD", "1", "-s", &ip, "-j", "DROP"])
+            .output()
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+        Ok(())
+    };
+
+    apply_with_rollback(&load_settings(&state.db).await, change_fn)?;
+
+    firewall_rules::record(&state.db, BLOCKED_IP, &key, &description, &user.username)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(serde_json::json!({"success": true, "pending": true})))
 }
 
 // Remove blocked IP
 pub async fn remove_blocked_ip(
+    State(state): State<Arc<AppState>>,
     Json(payload): Json<RemoveBlockedIP>,
 ) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
     if mock::is_mock_mode() {
         return Ok(Json(serde_json::json!({"success": true, "pending": true, "mock": true})));
     }
 
-    let ip = payload.ip.clone();
+    let ip = payload.ip.clone();
+    let key = normalize_block_target(&ip).unwrap_or_else(|| ip.trim().to_string());
+
+    let change_fn = move || {
+        let _ = sudo()
+            .args(["iptables", "-D", "INPUT", "-s", &ip, "-j", "DROP"])
+            .output();
+
+        let _ = sudo()
+            .args(["iptables", "-D", "FORWARD", "-s", &ip, "-j", "DROP"])
+            .output();
+
+        Ok(())
+    };
+
+    apply_with_rollback(&load_settings(&state.db).await, change_fn)?;
+
+    firewall_rules::remove(&state.db, BLOCKED_IP, &key)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(serde_json::json!({"success": true, "pending": true})))
+}
+
+// Stop blocking an address without forgetting it, or block it again
+pub async fn set_blocked_ip_enabled(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    Json(payload): Json<SetBlockedIPEnabled>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    if mock::is_mock_mode() {
+        return Ok(Json(serde_json::json!({"success": true, "pending": true, "mock": true})));
+    }
+
+    let ip = normalize_block_target(&payload.ip)
+        .ok_or((StatusCode::BAD_REQUEST, "Not an IPv4 address or range".to_string()))?;
+    let live = live_blocked_ips()?.iter().any(|b| b.ip == ip);
+    let known = firewall_rules::list(&state.db, BLOCKED_IP)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .contains_key(&ip);
+    if !live && !known {
+        return Err((StatusCode::NOT_FOUND, format!("{} is not blocked", ip)));
+    }
+
+    let enabled = payload.enabled;
+    if live != enabled {
+        let target = ip.clone();
+        let change_fn = move || {
+            let flag = if enabled { "-I" } else { "-D" };
+            run_block_rule(flag, "INPUT", &target)
+                .and_then(|_| run_block_rule(flag, "FORWARD", &target))
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))
+        };
+        apply_with_rollback(&load_settings(&state.db).await, change_fn)?;
+    }
+
+    firewall_rules::set_enabled(&state.db, BLOCKED_IP, &ip, enabled)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    tracing::info!("User {} {} the block on {}", user.username, if enabled { "enabled" } else { "disabled" }, ip);
+    Ok(Json(serde_json::json!({"success": true, "pending": true})))
+}
+
+// IPv4 address or CIDR range, normalised the way iptables lists it (no /32)
+fn normalize_block_target(value: &str) -> Option<String> {
+    let value = value.trim();
+    let (addr, prefix) = match value.split_once('/') {
+        Some((addr, prefix)) => (addr, prefix.parse::<u8>().ok().filter(|p| *p <= 32)?),
+        None => (value, 32),
+    };
+    let addr: std::net::Ipv4Addr = addr.parse().ok()?;
+    Some(if prefix == 32 { addr.to_string() } else { format!("{}/{}", addr, prefix) })
+}
+
+fn run_block_rule(action: &str, chain: &str, ip: &str) -> Result<(), String> {
+    let mut args = vec!["iptables", action, chain];
+    if action == "-I" {
+        args.push("1");
+    }
+    args.extend(["-s", ip, "-j", "DROP"]);
+    let output = sudo().args(&args).output().map_err(|e| e.to_string())?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+    }
+    Ok(())
+}
+
+// Block and unblock many addresses as one change: everything is checked first, applied under a
+// single rollback timer, and put back as it was if any iptables call fails halfway
+pub async fn bulk_blocked_ips(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    Json(payload): Json<BulkBlockedIPs>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let total = payload.add.len() + payload.remove.len();
+    if total == 0 {
+        return Err((StatusCode::BAD_REQUEST, "Nothing to add or remove".to_string()));
+    }
+    if total > MAX_BULK_BLOCKS {
+        return Err((StatusCode::BAD_REQUEST, format!("At most {} addresses per request", MAX_BULK_BLOCKS)));
+    }
+
+    let mut results = Vec::new();
+    let mut changes = Vec::new();
+    let mut seen = std::collections::HashSet::new();
+    let requested = payload.add.iter().map(|ip| ("add", ip)).chain(payload.remove.iter().map(|ip| ("remove", ip)));
+    for (action, ip) in requested {
+        let label = format!("{} {}", action, ip.trim());
+        match normalize_block_target(ip) {
+            None => results.push(BulkItem::failed(label, "Not an IPv4 address or range")),
+            Some(ip) if !seen.insert(ip.clone()) => results.push(BulkItem::failed(label, "Listed more than once")),
+            Some(ip) => {
+                results.push(BulkItem::ok(label));
+                changes.push((action, ip, results.len() - 1));
+            }
+        }
+    }
+    if results.iter().any(|r| !r.ok) {
+        BulkItem::abandon(&mut results);
+        return Ok(Json(serde_json::json!({"success": false, "pending": false, "results": results})));
+    }
+
+    if mock::is_mock_mode() {
+        return Ok(Json(serde_json::json!({"success": true, "pending": true, "mock": true, "results": results})));
+    }
+
+    let blocked: HashSet<String> = live_blocked_ips()?.into_iter().map(|b| b.ip).collect();
+
+    let change_fn = || {
+        for (action, ip, index) in &changes {
+            // Blocking twice or unblocking something that isn't blocked changes nothing
+            if (*action == "add") == blocked.contains(ip) {
+                continue;
+            }
+            let flag = if *action == "add" { "-I" } else { "-D" };
+            let applied = run_block_rule(flag, "INPUT", ip).and_then(|_| run_block_rule(flag, "FORWARD", ip));
+            if let Err(e) = applied {
+                results[*index] = BulkItem::failed(results[*index].item.clone(), e);
+                return Err((StatusCode::INTERNAL_SERVER_ERROR, "iptables failed".to_string()));
+            }
+        }
+        Ok(())
+    };
+
+    if apply_with_rollback(&load_settings(&state.db).await, change_fn).is_err() {
+        if let Err((_, e)) = restore_backup() {
+            tracing::error!("Restoring firewall rules after a failed bulk change failed: {}", e);
+        }
+        BulkItem::abandon(&mut results);
+        return Ok(Json(serde_json::json!({"success": false, "pending": false, "results": results})));
+    }
+
+    for (action, ip, _) in &changes {
+        let saved = match *action {
+            "add" if blocked.contains(ip) => continue,
+            "add" => firewall_rules::record(&state.db, BLOCKED_IP, ip, "", &user.username).await,
+            _ => firewall_rules::remove(&state.db, BLOCKED_IP, ip).await,
+        };
+        saved.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    }
+
+    Ok(Json(serde_json::json!({"success": true, "pending": true, "results": results})))
+}
+
+// Get raw iptables rules
+pub async fn raw_rules() -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    if mock::is_mock_mode() {
+        return Ok(Json(mock::firewall::rules()));
+    }
+
+    let filter = sudo()
+        .args(["iptables", "-L", "-n", "-v"])
+        .output()
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let nat = sudo()
+        .args(["iptables", "-t", "nat", "-L", "-n", "-v"])
+        .output()
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let filter6 = sudo()
+        .args(["ip6tables", "-L", "-n", "-v"])
+        .output()
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(serde_json::to_value(RawRules {
+        filter: String::from_utf8_lossy(&filter.stdout).to_string(),
+        nat: String::from_utf8_lossy(&nat.stdout).to_string(),
+        filter6: String::from_utf8_lossy(&filter6.stdout).to_string(),
+    }).unwrap()))
+}
+
+// ============ IPV6 ============
+// IPv6 rules live in RouterUI's own chains (see system/ipv6.rs); the built-in chains and their
+// policies are left alone. Blocked addresses go in ROUTERUI-V6-BLOCK, jumped to first from INPUT
+// and FORWARD. There is no NAT: a pinhole in ROUTERUI-V6-PINHOLE lets new connections from the
+// internet reach one LAN address, or a whole delegated prefix, on one port. Both take part in
+// the same confirm-or-roll-back flow as the IPv4 rules.
+
+// Pinholes to anything wider than a delegated prefix would open the LAN wholesale
+const MIN_PINHOLE_PREFIX: u8 = 48;
+
+#[derive(Debug, Serialize)]
+pub struct Ipv6Status {
+    // ip6tables answered; false on kernels without IPv6
+    pub available: bool,
+    pub wan_interface: String,
+    pub input_policy: String,
+    pub forward_policy: String,
+    pub output_policy: String,
+    // New connections from the WAN are dropped unless a pinhole lets them in
+    pub inbound_protected: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Pinhole {
+    pub protocol: String,
+    pub address: String,
+    pub port: u16,
+    pub description: String,
+    pub created_by: Option<String>,
+    pub created_at: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AddPinhole {
+    pub protocol: String,
+    pub address: String,
+    pub port: u16,
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RemovePinhole {
+    pub protocol: String,
+    pub address: String,
+    pub port: u16,
+}
+
+// IPv6 address or range with the host bits cleared, the way ip6tables lists it minus the /128
+fn normalize_ipv6_target(value: &str) -> Option<(String, u8)> {
+    let value = value.trim();
+    let (addr, prefix) = match value.split_once('/') {
+        Some((addr, prefix)) => (addr, prefix.parse::<u8>().ok().filter(|p| *p <= 128)?),
+        None => (value, 128),
+    };
+    let addr: std::net::Ipv6Addr = addr.parse().ok()?;
+    let mask = u128::MAX.checked_shl(128 - prefix as u32).unwrap_or(0);
+    let addr = std::net::Ipv6Addr::from(u128::from(addr) & mask);
+    Some((if prefix == 128 { addr.to_string() } else { format!("{}/{}", addr, prefix) }, prefix))
+}
+
+// "tcp:443:2001:db8::10" - how a pinhole is known in firewall_rules
+fn pinhole_key(proto: &str, address: &str, port: u16) -> String {
+    format!("{}:{}:{}", proto, port, address)
+}
+
+// Value following `flag` in an `ip6tables -S` line
+fn rule_option<'a>(parts: &[&'a str], flag: &str) -> Option<&'a str> {
+    parts.iter().position(|p| *p == flag).and_then(|i| parts.get(i + 1).copied())
+}
+
+fn live_ipv6_blocks() -> Vec<String> {
+    let prefix = format!("-A {} ", ipv6::BLOCK_CHAIN);
+    ipv6::chain_rules(ipv6::BLOCK_CHAIN)
+        .unwrap_or_default()
+        .iter()
+        .filter(|r| r.starts_with(&prefix) && r.ends_with("-j DROP"))
+        .filter_map(|r| rule_option(&r.split_whitespace().collect::<Vec<_>>(), "-s").map(|s| s.trim_end_matches("/128").to_string()))
+        .collect()
+}
+
+fn live_pinholes() -> Vec<Pinhole> {
+    let prefix = format!("-A {} ", ipv6::PINHOLE_CHAIN);
+    ipv6::chain_rules(ipv6::PINHOLE_CHAIN)
+        .unwrap_or_default()
+        .iter()
+        .filter(|r| r.starts_with(&prefix) && r.ends_with("-j ACCEPT"))
+        .filter_map(|r| {
+            let parts: Vec<&str> = r.split_whitespace().collect();
+            Some(Pinhole {
+                protocol: rule_option(&parts, "-p")?.to_string(),
+                address: rule_option(&parts, "-d")?.trim_end_matches("/128").to_string(),
+                port: rule_option(&parts, "--dport")?.parse().ok()?,
+                description: String::new(),
+                created_by: None,
+                created_at: None,
+            })
+        })
+        .collect()
+}
+
+// Pinhole rule for `ip6tables <action> ROUTERUI-V6-PINHOLE ...`
+fn run_pinhole_rule(action: &str, proto: &str, address: &str, port: u16) -> Result<(), (StatusCode, String)> {
+    let port = port.to_string();
+    let args = [action, ipv6::PINHOLE_CHAIN, "-d", address, "-p", proto, "-m", proto, "--dport", &port, "-j", "ACCEPT"];
+    ipv6::ip6tables(&args).map_err(v6_error)
+}
+
+// 2000::/3, the only addresses the internet can reach
+fn is_global_unicast(address: &str) -> bool {
+    let addr = address.split('/').next().unwrap_or_default();
+    addr.parse::<std::net::Ipv6Addr>().is_ok_and(|a| a.segments()[0] & 0xe000 == 0x2000)
+}
+
+fn v6_error(e: String) -> (StatusCode, String) {
+    (StatusCode::INTERNAL_SERVER_ERROR, e)
+}
+
+// Policies, blocked addresses and pinholes in one go
+pub async fn ipv6(State(state): State<Arc<AppState>>) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    if mock::is_mock_mode() {
+        return Ok(Json(mock::firewall::ipv6()));
+    }
+
+    let wan = crate::wan::wan_interface(&state.db).await;
+    let (status, blocks, mut pinholes) = tokio::task::spawn_blocking(move || {
+        let input_policy = ipv6::chain_policy("INPUT");
+        let status = Ipv6Status {
+            available: input_policy != "UNKNOWN",
+            input_policy,
+            forward_policy: ipv6::chain_policy("FORWARD"),
+            output_policy: ipv6::chain_policy("OUTPUT"),
+            inbound_protected: ipv6::inbound_protected(&wan),
+            wan_interface: wan,
+        };
+        (status, live_ipv6_blocks(), live_pinholes())
+    })
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let block_meta = firewall_rules::list(&state.db, BLOCKED_IP6)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let blocked: Vec<BlockedIP> = blocks
+        .into_iter()
+        .map(|ip| {
+            let meta = block_meta.get(&ip);
+            BlockedIP {
+                enabled: true,
+                description: meta.map(|m| m.description.clone()).unwrap_or_default(),
+                created_by: meta.and_then(|m| m.created_by.clone()),
+                created_at: meta.map(|m| m.created_at.clone()),
+                schedule: None,
+                ip,
+            }
+        })
+        .collect();
+
+    let pinhole_meta = firewall_rules::list(&state.db, PINHOLE6)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    for pinhole in &mut pinholes {
+        if let Some(m) = pinhole_meta.get(&pinhole_key(&pinhole.protocol, &pinhole.address, pinhole.port)) {
+            pinhole.description = m.description.clone();
+            pinhole.created_by = m.created_by.clone();
+            pinhole.created_at = Some(m.created_at.clone());
+        }
+    }
+
+    Ok(Json(serde_json::json!({
+        "status": status,
+        "blocked": blocked,
+        "pinholes": pinholes,
+    })))
+}
+
+pub async fn add_ipv6_block(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    Json(payload): Json<AddBlockedIP>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let (ip, _) = normalize_ipv6_target(&payload.ip)
+        .ok_or((StatusCode::BAD_REQUEST, "Not an IPv6 address or range".to_string()))?;
+    let description = clean_description(payload.description.as_deref())?;
+
+    if mock::is_mock_mode() {
+        return Ok(Json(serde_json::json!({"success": true, "pending": true, "mock": true})));
+    }
+
+    if live_ipv6_blocks().contains(&ip) {
+        return Err((StatusCode::CONFLICT, format!("{} is already blocked", ip)));
+    }
+
+    let target = ip.clone();
+    let change_fn = move || {
+        ipv6::ensure_chain(ipv6::BLOCK_CHAIN).map_err(v6_error)?;
+        ipv6::ensure_jump_first("INPUT", ipv6::BLOCK_CHAIN).map_err(v6_error)?;
+        ipv6::ensure_jump_first("FORWARD", ipv6::BLOCK_CHAIN).map_err(v6_error)?;
+        ipv6::ip6tables(&["-A", ipv6::BLOCK_CHAIN, "-s", &target, "-j", "DROP"]).map_err(v6_error)
+    };
+    apply_with_rollback(&load_settings(&state.db).await, change_fn)?;
+
+    firewall_rules::record(&state.db, BLOCKED_IP6, &ip, &description, &user.username)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    tracing::info!("User {} blocked {}", user.username, ip);
+    Ok(Json(serde_json::json!({"success": true, "pending": true})))
+}
+
+pub async fn remove_ipv6_block(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    Json(payload): Json<RemoveBlockedIP>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let (ip, _) = normalize_ipv6_target(&payload.ip)
+        .ok_or((StatusCode::BAD_REQUEST, "Not an IPv6 address or range".to_string()))?;
+
+    if mock::is_mock_mode() {
+        return Ok(Json(serde_json::json!({"success": true, "pending": true, "mock": true})));
+    }
+
+    if live_ipv6_blocks().contains(&ip) {
+        let target = ip.clone();
+        let change_fn = move || {
+            ipv6::ip6tables(&["-D", ipv6::BLOCK_CHAIN, "-s", &target, "-j", "DROP"]).map_err(v6_error)
+        };
+        apply_with_rollback(&load_settings(&state.db).await, change_fn)?;
+    }
+
+    firewall_rules::remove(&state.db, BLOCKED_IP6, &ip)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    tracing::info!("User {} unblocked {}", user.username, ip);
+    Ok(Json(serde_json::json!({"success": true, "pending": true})))
+}
+
+pub async fn add_pinhole(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    Json(payload): Json<AddPinhole>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    if payload.protocol != "tcp" && payload.protocol != "udp" {
+        return Err((StatusCode::BAD_REQUEST, "Protocol must be tcp or udp".to_string()));
+    }
+    if payload.port == 0 {
+        return Err((StatusCode::BAD_REQUEST, "Port must be between 1 and 65535".to_string()));
+    }
+    let (address, prefix) = normalize_ipv6_target(&payload.address)
+        .ok_or((StatusCode::BAD_REQUEST, "Not an IPv6 address or prefix".to_string()))?;
+    if prefix < MIN_PINHOLE_PREFIX {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("Pinholes can cover at most a /{} prefix", MIN_PINHOLE_PREFIX),
+        ));
+    }
+    if !is_global_unicast(&address) {
+        return Err((StatusCode::BAD_REQUEST, format!("{} is not a global unicast address", address)));
+    }
+    let description = clean_description(payload.description.as_deref())?;
+
+    if mock::is_mock_mode() {
+        return Ok(Json(serde_json::json!({"success": true, "pending": true, "mock": true})));
+    }
+
+    let protocol = payload.protocol.clone();
+    let port = payload.port;
+    if live_pinholes().iter().any(|p| p.protocol == protocol && p.address == address && p.port == port) {
+        return Err((StatusCode::CONFLICT, format!("{} port {} is already open to {}", protocol, port, address)));
+    }
+
+    let wan = crate::wan::wan_interface(&state.db).await;
+    let (proto, target) = (protocol.clone(), address.clone());
+    let change_fn = move || {
+        ipv6::ensure_pinholes_consulted(&wan).map_err(v6_error)?;
+        run_pinhole_rule("-A", &proto, &target, port)
+    };
+    apply_with_rollback(&load_settings(&state.db).await, change_fn)?;
+
+    firewall_rules::record(&state.db, PINHOLE6, &pinhole_key(&protocol, &address, port), &description, &user.username)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    tracing::info!("User {} opened {} port {} to {}", user.username, protocol, port, address);
+    Ok(Json(serde_json::json!({"success": true, "pending": true})))
+}
+
+pub async fn remove_pinhole(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    Json(payload): Json<RemovePinhole>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let (address, _) = normalize_ipv6_target(&payload.address)
+        .ok_or((StatusCode::BAD_REQUEST, "Not an IPv6 address or prefix".to_string()))?;
+
+    if mock::is_mock_mode() {
+        return Ok(Json(serde_json::json!({"success": true, "pending": true, "mock": true})));
+    }
+
+    let protocol = payload.protocol.clone();
+    let port = payload.port;
+    if live_pinholes().iter().any(|p| p.protocol == protocol && p.address == address && p.port == port) {
+        let (proto, target) = (protocol.clone(), address.clone());
+        let change_fn = move || run_pinhole_rule("-D", &proto, &target, port);
+        apply_with_rollback(&load_settings(&state.db).await, change_fn)?;
+    }
+
+    firewall_rules::remove(&state.db, PINHOLE6, &pinhole_key(&protocol, &address, port))
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    tracing::info!("User {} closed {} port {} to {}", user.username, protocol, port, address);
+    Ok(Json(serde_json::json!({"success": true, "pending": true})))
+}
+
+// ============ OBJECT REFERENCES ============
+
+/// A firewall rule that belongs to a device or address
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DependentRule {
+    // firewall_rules kind: "port_forward" or "blocked_ip"
+    pub kind: String,
+    pub key: String,
+    pub summary: String,
+}
+
+/// What to do with the firewall rules of a device or address being deleted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DependentAction {
+    Keep,
+    Remove,
+}
+
+fn forward_summary(forward: &PortForward) -> String {
+    format!(
+        "port forward {} {} to {}:{}",
+        forward.protocol.to_uppercase(),
+        forward.external_port,
+        forward.internal_ip,
+        forward.internal_port
+    )
+}
+
+fn forward_rule(forward: &PortForward) -> DependentRule {
+    DependentRule { kind: PORT_FORWARD.to_string(), key: forward.key(), summary: forward_summary(forward) }
+}
+
+/// Port forwards made for the device with `mac` or aimed at `ip`, and blocks of `ip`; disabled
+/// rules included, since turning them back on would bring them back
+pub(crate) async fn dependent_rules(
+    pool: &SqlitePool,
+    mac: Option<&str>,
+    ip: Option<&str>,
+) -> Result<Vec<DependentRule>, (StatusCode, String)> {
+    let db_error = |e: sqlx::Error| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string());
+    let referenced: HashSet<String> = match mac {
+        Some(mac) => firewall_rules::refs_to(pool, firewall_rules::DEVICE, &mac.to_lowercase())
+            .await
+            .map_err(db_error)?
+            .into_iter()
+            .filter(|r| r.rule_kind == PORT_FORWARD)
+            .map(|r| r.rule_key)
+            .collect(),
+        None => HashSet::new(),
+    };
+
+    let mut rules: Vec<DependentRule> = collect_port_forwards(pool)
+        .await?
+        .iter()
+        .filter(|f| referenced.contains(&f.key()) || Some(f.internal_ip.as_str()) == ip)
+        .map(forward_rule)
+        .collect();
+
+    if let Some(ip) = ip.and_then(normalize_block_target) {
+        let known = firewall_rules::list(pool, BLOCKED_IP).await.map_err(db_error)?;
+        if known.contains_key(&ip) || live_blocked_ips()?.iter().any(|b| b.ip == ip) {
+            rules.push(DependentRule { kind: BLOCKED_IP.to_string(), summary: format!("block of {}", ip), key: ip });
+        }
+    }
+    Ok(rules)
+}
 
+/// Take the rules out of iptables as one change under the rollback timer, then forget them
+pub(crate) async fn remove_rules(pool: &SqlitePool, rules: &[DependentRule]) -> Result<(), (StatusCode, String)> {
+    let geo = load_forward_geo();
+    let forwards: Vec<PortForward> = rules
+        .iter()
+        .filter(|r| r.kind == PORT_FORWARD)
+        .filter_map(|r| parse_forward_key(&r.key))
+        .collect();
+    let blocks: Vec<String> = rules.iter().filter(|r| r.kind == BLOCKED_IP).map(|r| r.key.clone()).collect();
+
+    let targets: Vec<(String, u16, String, u16, Option<String>)> = forwards
+        .iter()
+        .map(|f| {
+            let geo_set = geo.contains_key(&f.external_port.to_string()).then(|| forward_geo_set(f.external_port));
+            (f.protocol.clone(), f.external_port, f.internal_ip.clone(), f.internal_port, geo_set)
+        })
+        .collect();
+    let block_targets = blocks.clone();
     let change_fn = move || {
-        let _ = Command::new("sudo")
-            .args(["iptables", "-D", "INPUT", "-s", &ip, "-j", "DROP"])
-            .output();
+        for (proto, ext_port, int_ip, int_port, geo_set) in &targets {
+            delete_port_forward(proto, *ext_port, int_ip, *int_port, geo_set.as_deref());
+        }
+        // A disabled block has no rules to delete
+        for ip in &block_targets {
+            let _ = run_block_rule("-D", "INPUT", ip);
+            let _ = run_block_rule("-D", "FORWARD", ip);
+        }
+        Ok(())
+    };
+    apply_with_rollback(&load_settings(pool).await, change_fn)?;
 
-        let _ = Command::new("sudo")
-            .args(["iptables", "-D", "FORWARD", "-s", &ip, "-j", "DROP"])
-            .output();
+    for rule in rules {
+        firewall_rules::remove(pool, &rule.kind, &rule.key)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    }
+
+    let ports: HashSet<u16> = forwards.iter().map(|f| f.external_port).collect();
+    let mut geo = geo;
+    if ports.iter().any(|p| geo.remove(&p.to_string()).is_some()) {
+        save_forward_geo(&geo)?;
+    }
+    let mut logged = load_forward_log();
+    if logged.iter().any(|p| ports.contains(p)) {
+        logged.retain(|p| !ports.contains(p));
+        save_forward_log(&logged)?;
+    }
+    Ok(())
+}
+
+/// Called before a device, static lease or whitelist entry is deleted. Without rules nothing
+/// happens. With rules and no `action`, a 409 lists them so the caller can ask whether to take
+/// them along. Returns how many were removed.
+pub(crate) async fn settle_dependents(
+    pool: &SqlitePool,
+    user: &crate::models::User,
+    mac: Option<&str>,
+    ip: Option<&str>,
+    action: Option<DependentAction>,
+    what: &str,
+) -> Result<usize, (StatusCode, String)> {
+    if action == Some(DependentAction::Keep) {
+        return Ok(0);
+    }
+    let rules = dependent_rules(pool, mac, ip).await?;
+    if rules.is_empty() {
+        return Ok(0);
+    }
+    match action {
+        None => {
+            let summaries: Vec<&str> = rules.iter().map(|r| r.summary.as_str()).collect();
+            let (noun, pronoun) = if rules.len() == 1 { ("firewall rule", "it") } else { ("firewall rules", "them") };
+            Err((
+                StatusCode::CONFLICT,
+                format!("{} has {} {}: {}. Remove {} too?", what, rules.len(), noun, summaries.join(", "), pronoun),
+            ))
+        }
+        Some(_) => {
+            super::require_permission(user, "firewall:write").map_err(|(s, m)| (s, m.to_string()))?;
+            remove_rules(pool, &rules).await?;
+            tracing::info!("User {} removed {} firewall rules along with {}", user.username, rules.len(), what);
+            Ok(rules.len())
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct OrphanRule {
+    #[serde(flatten)]
+    pub rule: DependentRule,
+    pub reason: String,
+}
+
+// Port forwards whose device is gone: the device it was made for is no longer known anywhere,
+// or, for forwards made before references were kept, nothing is known at its address
+async fn find_orphans(pool: &SqlitePool) -> Result<Vec<OrphanRule>, (StatusCode, String)> {
+    let forwards = collect_port_forwards(pool).await?;
+    let refs = firewall_rules::all_refs(pool)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let devices = tokio::task::spawn_blocking(super::network::known_devices)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let mut orphans = Vec::new();
+    for forward in &forwards {
+        let key = forward.key();
+        let owners: Vec<&str> = refs
+            .iter()
+            .filter(|r| r.rule_kind == PORT_FORWARD && r.rule_key == key && r.object_kind == firewall_rules::DEVICE)
+            .map(|r| r.object_id.as_str())
+            .collect();
+        let reason = if owners.is_empty() {
+            let known = devices.iter().any(|d| d.ip.as_deref() == Some(forward.internal_ip.as_str()));
+            (!known).then(|| format!("No known device uses {}", forward.internal_ip))
+        } else if let Some(device) = devices.iter().find(|d| owners.contains(&d.mac.as_str())) {
+            let moved = device.ip.as_deref().is_some_and(|ip| ip != forward.internal_ip);
+            moved.then(|| {
+                format!("{} is now at {} ({})", device.mac, device.ip.as_deref().unwrap_or_default(), device.source)
+            })
+        } else {
+            Some(format!("Device {} no longer exists", owners.join(", ")))
+        };
+        if let Some(reason) = reason {
+            orphans.push(OrphanRule { rule: forward_rule(forward), reason });
+        }
+    }
+    Ok(orphans)
+}
+
+// Cleanup report: rules whose device is gone or has moved
+pub async fn orphans(State(state): State<Arc<AppState>>) -> Result<Json<Vec<OrphanRule>>, (StatusCode, String)> {
+    if mock::is_mock_mode() {
+        return Ok(Json(Vec::new()));
+    }
+    Ok(Json(find_orphans(&state.db).await?))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RemoveOrphans {
+    // Keys from the report; rules that are no longer orphaned are left alone
+    pub keys: Vec<String>,
+}
+
+pub async fn remove_orphans(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    Json(payload): Json<RemoveOrphans>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    if mock::is_mock_mode() {
+        return Ok(Json(serde_json::json!({"success": true, "pending": true, "mock": true})));
+    }
+    let wanted: HashSet<&str> = payload.keys.iter().map(String::as_str).collect();
+    let rules: Vec<DependentRule> = find_orphans(&state.db)
+        .await?
+        .into_iter()
+        .map(|o| o.rule)
+        .filter(|r| wanted.contains(r.key.as_str()))
+        .collect();
+    if rules.is_empty() {
+        return Ok(Json(serde_json::json!({"success": true, "removed": 0})));
+    }
+    remove_rules(&state.db, &rules).await?;
+    tracing::info!("User {} removed {} orphaned firewall rules", user.username, rules.len());
+    Ok(Json(serde_json::json!({"success": true, "removed": rules.len(), "pending": true})))
+}
+
+// ============ TEMPLATES ============
+
+// Longest group name
+const MAX_GROUP_LEN: usize = 64;
+
+/// One port of a template
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct TemplatePort {
+    pub protocol: &'static str,
+    pub port: u16,
+    pub purpose: &'static str,
+}
+
+/// A service's usual port forwards, offered pre-filled on the firewall page
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Template {
+    pub id: &'static str,
+    pub name: &'static str,
+    pub description: &'static str,
+    pub ports: &'static [TemplatePort],
+}
+
+const fn port(protocol: &'static str, port: u16, purpose: &'static str) -> TemplatePort {
+    TemplatePort { protocol, port, purpose }
+}
+
+// Only inbound ports: the outbound ones vendors also list need nothing on a NAT router
+pub const TEMPLATES: &[Template] = &[
+    Template {
+        id: "plex",
+        name: "Plex Media Server",
+        description: "Remote streaming. Set the same port under Remote Access in Plex if you change it.",
+        ports: &[port("tcp", 32400, "Plex remote access")],
+    },
+    Template {
+        id: "minecraft",
+        name: "Minecraft server",
+        description: "Java Edition on 25565/tcp and Bedrock Edition on 19132/udp; untick the one you don't run.",
+        ports: &[port("tcp", 25565, "Minecraft Java"), port("udp", 19132, "Minecraft Bedrock")],
+    },
+    Template {
+        id: "wireguard",
+        name: "WireGuard server",
+        description: "A WireGuard server on the LAN, e.g. wg-easy or PiVPN, on its default port.",
+        ports: &[port("udp", 51820, "WireGuard")],
+    },
+    Template {
+        id: "https",
+        name: "HTTPS web server",
+        description: "A web server or reverse proxy. Port 80 serves redirects and ACME HTTP-01 challenges.",
+        ports: &[port("tcp", 443, "HTTPS"), port("tcp", 80, "HTTP")],
+    },
+    Template {
+        id: "ps5",
+        name: "PlayStation 5",
+        description: "Open NAT (type 2) for PlayStation Network. Give the console a static lease first.",
+        ports: &[
+            port("tcp", 3478, "PSN"),
+            port("tcp", 3479, "PSN"),
+            port("tcp", 3480, "PSN"),
+            port("udp", 3478, "PSN"),
+            port("udp", 3479, "PSN"),
+        ],
+    },
+    Template {
+        id: "xbox",
+        name: "Xbox",
+        description: "Open NAT for Xbox network; 3074 is the port Microsoft says to forward. Give the console a static lease first.",
+        ports: &[port("tcp", 3074, "Xbox Live"), port("udp", 3074, "Xbox Live")],
+    },
+];
+
+pub async fn templates() -> Json<&'static [Template]> {
+    Json(TEMPLATES)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TemplateForward {
+    pub protocol: String,
+    pub external_port: u16,
+    pub internal_port: u16,
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ApplyTemplate {
+    pub template: String,
+    // Name the forwards are grouped under; the template's name when left out
+    pub group: Option<String>,
+    pub internal_ip: String,
+    // The template's ports as edited; all of them, unchanged, when left out
+    pub forwards: Option<Vec<TemplateForward>>,
+}
+
+async fn group_names(pool: &SqlitePool) -> Result<HashSet<String>, (StatusCode, String)> {
+    Ok(firewall_rules::all_refs(pool)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .into_iter()
+        .filter(|r| r.object_kind == firewall_rules::GROUP)
+        .map(|r| r.object_id)
+        .collect())
+}
+
+// Create the template's forwards to one device as a single change under the rollback timer
+pub async fn apply_template(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    Json(payload): Json<ApplyTemplate>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let template = TEMPLATES
+        .iter()
+        .find(|t| t.id == payload.template)
+        .ok_or_else(|| (StatusCode::NOT_FOUND, format!("Unknown template: {}", payload.template)))?;
+    let group = payload.group.as_deref().map(str::trim).filter(|g| !g.is_empty()).unwrap_or(template.name).to_string();
+    if group.chars().count() > MAX_GROUP_LEN {
+        return Err((StatusCode::BAD_REQUEST, format!("Group names can be at most {} characters", MAX_GROUP_LEN)));
+    }
+    let int_ip: std::net::Ipv4Addr = payload
+        .internal_ip
+        .trim()
+        .parse()
+        .map_err(|_| (StatusCode::BAD_REQUEST, format!("Invalid internal IP: {}", payload.internal_ip)))?;
+    let int_ip = int_ip.to_string();
+
+    let requested: Vec<TemplateForward> = match payload.forwards {
+        Some(forwards) => forwards,
+        None => template
+            .ports
+            .iter()
+            .map(|p| TemplateForward {
+                protocol: p.protocol.to_string(),
+                external_port: p.port,
+                internal_port: p.port,
+                description: Some(p.purpose.to_string()),
+            })
+            .collect(),
+    };
+    if requested.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "Pick at least one port".to_string()));
+    }
+
+    let mut forwards: Vec<(String, u16, u16, String)> = Vec::new();
+    for forward in &requested {
+        let protocol = forward.protocol.to_lowercase();
+        if protocol != "tcp" && protocol != "udp" {
+            return Err((StatusCode::BAD_REQUEST, format!("Invalid protocol: {}", forward.protocol)));
+        }
+        if forward.external_port == 0 || forward.internal_port == 0 {
+            return Err((StatusCode::BAD_REQUEST, "Ports must be 1-65535".to_string()));
+        }
+        if forwards.iter().any(|(p, ext, _, _)| *p == protocol && *ext == forward.external_port) {
+            return Err((StatusCode::BAD_REQUEST, format!("{} port {} is listed twice", protocol, forward.external_port)));
+        }
+        let description = clean_description(forward.description.as_deref())?;
+        let description = if description.is_empty() { group.clone() } else { format!("{}: {}", group, description) };
+        forwards.push((protocol, forward.external_port, forward.internal_port, description));
+    }
 
+    if mock::is_mock_mode() {
+        return Ok(Json(serde_json::json!({"success": true, "pending": true, "group": group, "mock": true})));
+    }
+
+    if group_names(&state.db).await?.contains(&group) {
+        return Err((StatusCode::CONFLICT, format!("A group named \"{}\" already exists; pick another name", group)));
+    }
+    let live = live_port_forwards()?;
+    for (protocol, ext_port, _, _) in &forwards {
+        if let Some(existing) = forward_on_port(&live, protocol, *ext_port, None) {
+            return Err(port_conflict(existing));
+        }
+    }
+
+    let targets: Vec<(String, u16, u16)> = forwards.iter().map(|(p, ext, int, _)| (p.clone(), *ext, *int)).collect();
+    let target_ip = int_ip.clone();
+    let wan = crate::wan::wan_interface(&state.db).await;
+    let change_fn = move || {
+        for (proto, ext_port, int_port) in &targets {
+            insert_port_forward(&wan, proto, *ext_port, &target_ip, *int_port, None, false)?;
+        }
         Ok(())
     };
+    apply_with_rollback(&load_settings(&state.db).await, change_fn)?;
+
+    let ip = int_ip.clone();
+    let device = tokio::task::spawn_blocking(move || super::network::mac_for_ip(&ip))
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let db_error = |e: sqlx::Error| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string());
+    for (protocol, ext_port, int_port, description) in &forwards {
+        let key = forward_key(protocol, *ext_port, &int_ip, *int_port);
+        firewall_rules::record(&state.db, PORT_FORWARD, &key, description, &user.username).await.map_err(db_error)?;
+        firewall_rules::add_ref(&state.db, PORT_FORWARD, &key, firewall_rules::GROUP, &group).await.map_err(db_error)?;
+        if let Some(mac) = &device {
+            firewall_rules::add_ref(&state.db, PORT_FORWARD, &key, firewall_rules::DEVICE, mac).await.map_err(db_error)?;
+        }
+    }
 
-    apply_with_rollback(change_fn)?;
+    // A port forwarded before may still carry countries or logging from then
+    let ports: HashSet<u16> = forwards.iter().map(|(_, ext, _, _)| *ext).collect();
+    let mut geo = load_forward_geo();
+    if ports.iter().any(|p| geo.remove(&p.to_string()).is_some()) {
+        save_forward_geo(&geo)?;
+    }
+    let mut logged = load_forward_log();
+    if logged.iter().any(|p| ports.contains(p)) {
+        logged.retain(|p| !ports.contains(p));
+        save_forward_log(&logged)?;
+    }
 
-    Ok(Json(serde_json::json!({"success": true, "pending": true})))
+    tracing::info!(
+        "User {} applied template {} as \"{}\": {} forwards to {}",
+        user.username,
+        template.id,
+        group,
+        forwards.len(),
+        int_ip
+    );
+    Ok(Json(serde_json::json!({"success": true, "pending": true, "group": group})))
 }
 
-// Get raw iptables rules
-pub async fn raw_rules() -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+#[derive(Debug, Serialize)]
+pub struct RuleGroup {
+    pub name: String,
+    pub forwards: Vec<PortForward>,
+}
+
+// Template groups with their forwards, disabled ones included
+pub async fn groups(State(state): State<Arc<AppState>>) -> Result<Json<Vec<RuleGroup>>, (StatusCode, String)> {
     if mock::is_mock_mode() {
-        return Ok(Json(mock::firewall::rules()));
+        return Ok(Json(Vec::new()));
+    }
+    let mut groups: Vec<RuleGroup> = Vec::new();
+    for forward in collect_port_forwards(&state.db).await? {
+        let Some(name) = forward.group.clone() else { continue };
+        match groups.iter_mut().find(|g| g.name == name) {
+            Some(group) => group.forwards.push(forward),
+            None => groups.push(RuleGroup { name, forwards: vec![forward] }),
+        }
     }
+    groups.sort_by_key(|g| g.name.to_lowercase());
+    Ok(Json(groups))
+}
 
-    let filter = Command::new("sudo")
-        .args(["iptables", "-L", "-n", "-v"])
+#[derive(Debug, Deserialize)]
+pub struct RemoveGroup {
+    pub name: String,
+}
+
+// Remove every forward of a group as one change under the rollback timer
+pub async fn remove_group(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    Json(payload): Json<RemoveGroup>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    if mock::is_mock_mode() {
+        return Ok(Json(serde_json::json!({"success": true, "pending": true, "mock": true})));
+    }
+    let rules: Vec<DependentRule> = collect_port_forwards(&state.db)
+        .await?
+        .iter()
+        .filter(|f| f.group.as_deref() == Some(payload.name.as_str()))
+        .map(forward_rule)
+        .collect();
+    if rules.is_empty() {
+        return Err((StatusCode::NOT_FOUND, format!("No group named \"{}\"", payload.name)));
+    }
+    remove_rules(&state.db, &rules).await?;
+    tracing::info!("User {} removed group \"{}\" ({} forwards)", user.username, payload.name, rules.len());
+    Ok(Json(serde_json::json!({"success": true, "removed": rules.len(), "pending": true})))
+}
+
+// ============ ZONES ============
+
+#[derive(Debug, Serialize)]
+pub struct ZoneStatus {
+    pub zone: Role,
+    pub interfaces: Vec<String>,
+    pub input: crate::zones::Action,
+}
+
+// Whether the INPUT policy drops, i.e. the zone chains are in place
+fn policy_drops() -> Result<bool, (StatusCode, String)> {
+    let output = sudo()
+        .args(["iptables", "-L", "INPUT", "-n"])
         .output()
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(parse_chain_policy(&String::from_utf8_lossy(&output.stdout), "INPUT") == "DROP")
+}
 
-    let nat = Command::new("sudo")
-        .args(["iptables", "-t", "nat", "-L", "-n", "-v"])
-        .output()
+// Each zone with the interfaces in it, and the settings behind them
+pub async fn zones(State(state): State<Arc<AppState>>) -> Json<serde_json::Value> {
+    if mock::is_mock_mode() {
+        return Json(mock::firewall::zones());
+    }
+
+    let zones = crate::zones::load(&state.db).await;
+    let status: Vec<ZoneStatus> = Role::ALL
+        .into_iter()
+        .map(|zone| ZoneStatus { zone, interfaces: zones.interfaces(zone), input: zones.settings.input_action(zone) })
+        .collect();
+    Json(serde_json::json!({"zones": status, "settings": zones.settings}))
+}
+
+// Replace the zone settings. While the firewall is on the chains are rebuilt under the rollback
+// timer, since a wrong input policy can lock everyone out. Settings that open the router to the
+// WAN wait for a second admin when the two-person rule is on, as turning the firewall off does.
+pub async fn set_zones(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    Json(mut payload): Json<crate::zones::ZoneSettings>,
+) -> Result<(StatusCode, Json<serde_json::Value>), (StatusCode, String)> {
+    payload.normalize();
+    payload.validate().map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+    if mock::is_mock_mode() {
+        return Ok((StatusCode::OK, Json(serde_json::json!({"success": true, "pending": true, "mock": true}))));
+    }
+
+    let current = crate::zones::load(&state.db).await;
+    let zones = crate::zones::Zones { roles: current.roles.clone(), settings: payload };
+    if zones.wan_open() && !current.wan_open() && crate::approvals::required() {
+        let action = crate::approvals::Action::OpenWanInput;
+        let settings = serde_json::to_value(&zones.settings).map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        return Ok(super::approvals::park(&state, &user, action, action.describe().to_string(), settings));
+    }
+    let pending = apply_zones(&state.db, zones).await?;
+
+    tracing::info!("User {} updated the firewall zones", user.username);
+    Ok((StatusCode::OK, Json(serde_json::json!({"success": true, "pending": pending}))))
+}
+
+/// Rebuild the chains from `zones` when the firewall is on, then save the settings. Returns
+/// whether the change waits for confirmation.
+pub(crate) async fn apply_zones(pool: &SqlitePool, zones: crate::zones::Zones) -> Result<bool, (StatusCode, String)> {
+    let settings = zones.settings.clone();
+    let active = policy_drops()?;
+    if active {
+        let change_fn = move || crate::zones::apply(&zones).map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e));
+        apply_with_rollback(&load_settings(pool).await, change_fn)?;
+    }
+    crate::zones::save_settings(pool, &settings)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    Ok(active)
+}
+
+// ============ SCHEDULES ============
+
+#[derive(Debug, Deserialize)]
+pub struct SetForwardSchedule {
+    pub protocol: String,
+    pub external_port: u16,
+    pub internal_ip: String,
+    pub internal_port: u16,
+    // A disabled schedule takes the forward off its schedule
+    pub schedule: Schedule,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetBlockedIPSchedule {
+    pub ip: String,
+    pub schedule: Schedule,
+}
+
+// The schedule to store: none when it is switched off
+fn checked_schedule(schedule: &Schedule) -> Result<Option<&Schedule>, (StatusCode, String)> {
+    schedule.validate().map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    Ok(schedule.enabled.then_some(schedule))
+}
+
+// Put a forward on a weekly schedule. The scheduler brings it in line within a minute and then
+// switches it on each boundary; switching it by hand in between holds until the next one.
+pub async fn set_port_forward_schedule(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    Json(payload): Json<SetForwardSchedule>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    if mock::is_mock_mode() {
+        return Ok(Json(serde_json::json!({"success": true, "mock": true})));
+    }
+
+    let protocol = payload.protocol.to_lowercase();
+    let protocols = match protocol.as_str() {
+        "both" => vec!["tcp", "udp"],
+        "tcp" | "udp" => vec![protocol.as_str()],
+        _ => return Err((StatusCode::BAD_REQUEST, "Invalid protocol".to_string())),
+    };
+    let schedule = checked_schedule(&payload.schedule)?;
+
+    let live: HashSet<String> = live_port_forwards()?.iter().map(PortForward::key).collect();
+    let meta = firewall_rules::list(&state.db, PORT_FORWARD)
+        .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let keys: Vec<String> = protocols
+        .into_iter()
+        .map(|proto| forward_key(proto, payload.external_port, &payload.internal_ip, payload.internal_port))
+        .filter(|key| live.contains(key) || meta.contains_key(key))
+        .collect();
+    if keys.is_empty() {
+        return Err((StatusCode::NOT_FOUND, "Port forward not found".to_string()));
+    }
 
-    Ok(Json(serde_json::to_value(RawRules {
-        filter: String::from_utf8_lossy(&filter.stdout).to_string(),
-        nat: String::from_utf8_lossy(&nat.stdout).to_string(),
-    }).unwrap()))
+    for key in &keys {
+        firewall_rules::set_schedule(&state.db, PORT_FORWARD, key, schedule)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    }
+
+    tracing::info!(
+        "User {} {} port forward {} {} -> {}:{}",
+        user.username,
+        if schedule.is_some() { "scheduled" } else { "unscheduled" },
+        payload.protocol, payload.external_port, payload.internal_ip, payload.internal_port
+    );
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "schedule": super::network::schedule_state(&payload.schedule, Local::now()),
+    })))
+}
+
+// Only block an address during the schedule's windows, or always again
+pub async fn set_blocked_ip_schedule(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    Json(payload): Json<SetBlockedIPSchedule>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    if mock::is_mock_mode() {
+        return Ok(Json(serde_json::json!({"success": true, "mock": true})));
+    }
+
+    let ip = normalize_block_target(&payload.ip)
+        .ok_or((StatusCode::BAD_REQUEST, "Not an IPv4 address or range".to_string()))?;
+    let schedule = checked_schedule(&payload.schedule)?;
+
+    let live = live_blocked_ips()?.iter().any(|b| b.ip == ip);
+    let known = firewall_rules::list(&state.db, BLOCKED_IP)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .contains_key(&ip);
+    if !live && !known {
+        return Err((StatusCode::NOT_FOUND, format!("{} is not blocked", ip)));
+    }
+
+    firewall_rules::set_schedule(&state.db, BLOCKED_IP, &ip, schedule)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    tracing::info!("User {} {} the block on {}", user.username, if schedule.is_some() { "scheduled" } else { "unscheduled" }, ip);
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "schedule": super::network::schedule_state(&payload.schedule, Local::now()),
+    })))
+}
+
+// (kind, rule key, whether it should be on)
+type ScheduledChange = (&'static str, String, bool);
+
+/// Switches scheduled port forwards and blocked IPs on their window boundaries
+#[derive(Default)]
+pub struct FirewallScheduler {
+    // By kind, rule key and schedule, so an edited schedule is applied afresh
+    edges: HashMap<(&'static str, String, String), Edge>,
+}
+
+impl FirewallScheduler {
+    pub async fn tick(&mut self, pool: &SqlitePool, now: DateTime<Local>) {
+        // Persisting now would make a change still waiting for confirmation permanent too; the
+        // boundary is picked up once it is confirmed or rolled back
+        if check_pending_status().0 {
+            return;
+        }
+
+        let mut scheduled = HashSet::new();
+        let mut due: Vec<ScheduledChange> = Vec::new();
+        for kind in [PORT_FORWARD, BLOCKED_IP] {
+            let meta = match firewall_rules::list(pool, kind).await {
+                Ok(meta) => meta,
+                Err(e) => {
+                    tracing::error!("Firewall schedules could not be read: {}", e);
+                    return;
+                }
+            };
+            for rule in meta.into_values() {
+                let (Some(schedule), Some(raw)) = (rule.schedule(), rule.schedule.clone()) else {
+                    continue;
+                };
+                let id = (kind, rule.rule_key.clone(), raw);
+                if let Some(on) = self.edges.entry(id.clone()).or_default().changed(schedule.active_at(now)) {
+                    due.push((kind, rule.rule_key, on));
+                }
+                scheduled.insert(id);
+            }
+        }
+        self.edges.retain(|id, _| scheduled.contains(id));
+        if due.is_empty() {
+            return;
+        }
+
+        let wan = crate::wan::wan_interface(pool).await;
+        let applied = tokio::task::spawn_blocking(move || apply_scheduled(wan, due))
+            .await
+            .unwrap_or_default();
+        for (kind, key, on) in applied {
+            if let Err(e) = firewall_rules::set_enabled(pool, kind, &key, on).await {
+                tracing::error!("Firewall schedule could not record {}: {}", key, e);
+            }
+        }
+    }
+}
+
+// Switch the rules and persist straight away: nobody is around to confirm a schedule. A rule
+// that can't be switched is logged and waits for its next boundary. Returns what was applied.
+fn apply_scheduled(wan: String, mut due: Vec<ScheduledChange>) -> Vec<ScheduledChange> {
+    let (mut forwards, blocks) = match (live_port_forwards(), live_blocked_ips()) {
+        (Ok(forwards), Ok(blocks)) => (forwards, blocks.into_iter().map(|b| b.ip).collect::<HashSet<_>>()),
+        (Err((_, e)), _) | (_, Err((_, e))) => {
+            tracing::error!("Firewall schedule could not read the rules: {}", e);
+            return Vec::new();
+        }
+    };
+    let geo = load_forward_geo();
+    let logged = load_forward_log();
+
+    // Switch off first, so a port handed from one forward to another at the same boundary is free
+    due.sort_by_key(|(_, _, on)| *on);
+    let mut applied = Vec::new();
+    for (kind, key, on) in due {
+        let result = if kind == PORT_FORWARD {
+            switch_scheduled_forward(&wan, &key, on, &mut forwards, &geo, &logged)
+        } else {
+            switch_scheduled_block(&key, on, &blocks)
+        };
+        let what = if kind == PORT_FORWARD { "port forward" } else { "block on" };
+        match result {
+            Ok(()) => {
+                tracing::info!("Firewall schedule: {} {} {}", if on { "enabled" } else { "disabled" }, what, key);
+                applied.push((kind, key, on));
+            }
+            Err(e) => tracing::warn!("Firewall schedule could not {} {} {}: {}", if on { "enable" } else { "disable" }, what, key, e),
+        }
+    }
+
+    if !applied.is_empty() {
+        if let Err((_, e)) = save_rules_permanent() {
+            tracing::error!("Firewall schedule could not save the rules: {}", e);
+        }
+    }
+    applied
+}
+
+fn switch_scheduled_forward(
+    wan: &str,
+    key: &str,
+    on: bool,
+    live: &mut Vec<PortForward>,
+    geo: &HashMap<String, Vec<String>>,
+    logged: &[u16],
+) -> Result<(), String> {
+    let forward = parse_forward_key(key).ok_or("Not a port forward")?;
+    if live.iter().any(|f| f.key() == key) == on {
+        return Ok(());
+    }
+
+    let port = forward.external_port;
+    let countries = geo.get(&port.to_string());
+    let geo_set = countries.map(|_| forward_geo_set(port));
+    if on {
+        if let Some(existing) = forward_on_port(live, &forward.protocol, port, Some(key)) {
+            return Err(port_conflict(existing).1);
+        }
+        if let Some(countries) = countries {
+            fill_forward_geo_set(port, countries).map_err(|(_, e)| e)?;
+        }
+        insert_port_forward(wan, &forward.protocol, port, &forward.internal_ip, forward.internal_port, geo_set.as_deref(), logged.contains(&port))
+            .map_err(|(_, e)| e)?;
+        live.push(forward);
+    } else {
+        delete_port_forward(&forward.protocol, port, &forward.internal_ip, forward.internal_port, geo_set.as_deref());
+        live.retain(|f| f.key() != key);
+    }
+    Ok(())
+}
+
+fn switch_scheduled_block(ip: &str, on: bool, live: &HashSet<String>) -> Result<(), String> {
+    if live.contains(ip) == on {
+        return Ok(());
+    }
+    let flag = if on { "-I" } else { "-D" };
+    run_block_rule(flag, "INPUT", ip).and_then(|_| run_block_rule(flag, "FORWARD", ip))
 }
 
 // Get DMZ status
@@ -706,7 +2781,7 @@ pub async fn dmz_status() -> Result<Json<DMZStatus>, (StatusCode, String)> {
         }));
     }
 
-    let output = Command::new("sudo")
+    let output = sudo()
         .args(["iptables", "-t", "nat", "-L", "PREROUTING", "-n"])
         .output()
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
@@ -734,6 +2809,7 @@ pub async fn dmz_status() -> Result<Json<DMZStatus>, (StatusCode, String)> {
 
 // Set DMZ
 pub async fn set_dmz(
+    State(state): State<Arc<AppState>>,
     Json(payload): Json<SetDMZ>,
 ) -> Result<Json<DMZStatus>, (StatusCode, String)> {
     if mock::is_mock_mode() {
@@ -745,26 +2821,27 @@ pub async fn set_dmz(
 
     let enabled = payload.enabled;
     let target_ip = payload.target_ip.clone();
+    let wan = crate::wan::wan_interface(&state.db).await;
 
     let change_fn = move || {
-        // Remove any existing DMZ rules
-        let _ = Command::new("sudo")
-            .args(["iptables", "-t", "nat", "-D", "PREROUTING", "-i", "enp1s0", "-j", "DNAT", "--to-destination", "0.0.0.0"])
-            .output();
+        // Remove any existing DMZ rules: DNAT of every port to an address without one
+        remove_wan_dnat(|rule| {
+            !rule.contains(&"--dport") && rule.windows(2).any(|w| w[0] == "--to-destination" && !w[1].contains(':'))
+        });
 
         if enabled {
             if let Some(ref ip) = target_ip {
-                Command::new("sudo")
+                sudo()
                     .args([
                         "iptables", "-t", "nat", "-A", "PREROUTING",
-                        "-i", "enp1s0",
+                        "-i", &wan,
                         "-j", "DNAT",
                         "--to-destination", ip,
                     ])
                     .output()
                     .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
-                Command::new("sudo")
+                sudo()
                     .args([
                         "iptables", "-A", "FORWARD",
                         "-d", ip,
@@ -777,7 +2854,7 @@ pub async fn set_dmz(
         Ok(())
     };
 
-    apply_with_rollback(change_fn)?;
+    apply_with_rollback(&load_settings(&state.db).await, change_fn)?;
 
     dmz_status().await
 }
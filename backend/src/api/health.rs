@@ -0,0 +1,19 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use std::sync::Arc;
+
+use crate::{health, AppState};
+
+/// Liveness/readiness probe for systemd and external uptime monitors.
+/// Returns 503 when any check fails so monitors don't need to parse the body.
+pub async fn health(
+    State(state): State<Arc<AppState>>,
+) -> impl IntoResponse {
+    let report = health::check(&state).await;
+    let status = if report.healthy {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+
+    (status, Json(report))
+}
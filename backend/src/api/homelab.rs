@@ -0,0 +1,191 @@
+use axum::{
+    extract::{ConnectInfo, Path, State},
+    http::StatusCode,
+    Json,
+};
+use serde::Deserialize;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use super::{require_permission, AuthUser};
+use crate::homelab::{self, Action, Host};
+use crate::mock;
+use crate::AppState;
+
+// ============ HOMELAB HOSTS ============
+
+/// Every host with what is known about it, and the key to authorize on them
+pub async fn list(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    require_permission(&user, "services:read").map_err(|(s, m)| (s, m.to_string()))?;
+
+    if mock::is_mock_mode() {
+        return Ok(Json(mock::services::homelab_hosts()));
+    }
+
+    let hosts = homelab::list(&state.db)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let hosts: Vec<serde_json::Value> = hosts
+        .into_iter()
+        .map(|host| {
+            let status = state.homelab.get(&host.id);
+            serde_json::json!({"host": host, "state": status})
+        })
+        .collect();
+    let public_key = crate::mesh::public_key()
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    Ok(Json(serde_json::json!({
+        "hosts": hosts,
+        "public_key": public_key,
+    })))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AddHost {
+    pub name: String,
+    pub host: String,
+    pub port: Option<u16>,
+    pub username: Option<String>,
+    pub mac_address: Option<String>,
+}
+
+pub async fn add(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    Json(payload): Json<AddHost>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    require_permission(&user, "services:write").map_err(|(s, m)| (s, m.to_string()))?;
+
+    let address = payload.host.trim().to_string();
+    if !crate::mesh::valid_host(&address) {
+        return Err((StatusCode::BAD_REQUEST, "Invalid host".to_string()));
+    }
+    let username = payload.username.map(|u| u.trim().to_string()).filter(|u| !u.is_empty()).unwrap_or_else(|| "root".to_string());
+    if !crate::mesh::valid_username(&username) {
+        return Err((StatusCode::BAD_REQUEST, "Invalid username".to_string()));
+    }
+    let mac_address = match payload.mac_address.as_deref().map(str::trim).filter(|m| !m.is_empty()) {
+        Some(mac) => Some(
+            crate::wol::normalize_mac(mac).ok_or((StatusCode::BAD_REQUEST, format!("'{}' is not a MAC address", mac)))?,
+        ),
+        None => None,
+    };
+    let name = payload.name.trim();
+    if name.len() > 64 {
+        return Err((StatusCode::BAD_REQUEST, "Names can be at most 64 characters".to_string()));
+    }
+
+    if mock::is_mock_mode() {
+        return Ok(Json(serde_json::json!({"success": true, "mock": true})));
+    }
+
+    let host = Host {
+        id: uuid::Uuid::new_v4().to_string(),
+        name: if name.is_empty() { address.clone() } else { name.to_string() },
+        host: address,
+        port: payload.port.unwrap_or(22) as i64,
+        username,
+        mac_address,
+        os: None,
+        created_at: chrono::Utc::now().to_rfc3339(),
+    };
+    let host = homelab::add(&state.db, host)
+        .await
+        .map_err(|e| (StatusCode::BAD_GATEWAY, e))?;
+
+    tracing::info!("User {} added homelab host {} ({})", user.username, host.name, host.host);
+    Ok(Json(serde_json::json!({"success": true, "host": host})))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HostId {
+    pub id: String,
+}
+
+pub async fn remove(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    Json(payload): Json<HostId>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    require_permission(&user, "services:write").map_err(|(s, m)| (s, m.to_string()))?;
+
+    if mock::is_mock_mode() {
+        return Ok(Json(serde_json::json!({"success": true, "mock": true})));
+    }
+
+    if !homelab::remove(&state.db, &payload.id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    {
+        return Err((StatusCode::NOT_FOUND, "Host not found".to_string()));
+    }
+
+    Ok(Json(serde_json::json!({"success": true})))
+}
+
+async fn find(state: &AppState, id: &str) -> Result<Host, (StatusCode, String)> {
+    homelab::get(&state.db, id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or((StatusCode::NOT_FOUND, "Host not found".to_string()))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ActionRequest {
+    pub action: Action,
+}
+
+/// Power off, reboot or read disk usage; nothing else can be run
+pub async fn action(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    Path(id): Path<String>,
+    Json(payload): Json<ActionRequest>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    // Reading disk usage changes nothing, but still logs in to the host
+    require_permission(&user, "services:write").map_err(|(s, m)| (s, m.to_string()))?;
+
+    if mock::is_mock_mode() {
+        return Ok(Json(serde_json::json!({"success": true, "mock": true})));
+    }
+
+    let host = find(&state, &id).await?;
+    let disks = homelab::act(&state, &host, payload.action)
+        .await
+        .map_err(|e| (StatusCode::BAD_GATEWAY, e))?;
+
+    if payload.action != Action::DiskUsage {
+        tracing::info!("User {} ran {} on homelab host {} ({})", user.username, payload.action.as_str(), host.name, host.host);
+    }
+    Ok(Json(serde_json::json!({"success": true, "action": payload.action, "disks": disks})))
+}
+
+/// Wake the host and start watching for it to come up
+pub async fn wake(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    Path(id): Path<String>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    require_permission(&user, "services:write").map_err(|(s, m)| (s, m.to_string()))?;
+
+    if mock::is_mock_mode() {
+        return Ok(Json(serde_json::json!({"success": true, "sent": true, "mock": true})));
+    }
+
+    let host = find(&state, &id).await?;
+    let sent = homelab::wake(&state, &host, &user.username, peer.ip())
+        .await
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "sent": sent,
+        "message": if sent { format!("Wake packet sent to {}", host.name) } else { format!("{} is already up", host.name) },
+    })))
+}
@@ -1,18 +1,73 @@
-use axum::{http::StatusCode, Json};
+use axum::{extract::State, http::StatusCode, Json};
 use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
 use std::process::Command;
+use std::sync::Arc;
 
 use crate::mock;
+use crate::AppState;
 use super::AuthUser;
 
-// Config - these could be moved to a config file later
-const MEDIA_PATH: &str = "/mnt/external/media1/media";
-const RADARR_URL: &str = "http://localhost:7878";
-const RADARR_API_KEY: &str = "66fc15a8af02444bb787e5f4d9e585b4";
-const SONARR_URL: &str = "http://localhost:8989";
-const SONARR_API_KEY: &str = "e3f602d269a349dabfc9e9a3ac995f76";
-const JELLYFIN_URL: &str = "http://10.22.22.185:8096";
-const JELLYFIN_API_KEY: &str = "72972c09f8794beab6da4af991cff9a3";
+// Key in the settings table; edited through /api/settings as "media.<field>"
+pub const SETTINGS_KEY: &str = "media";
+
+/// Where the media library and its services are
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MediaSettings {
+    // Holds movies/ and shows/
+    pub media_path: String,
+    pub radarr_url: String,
+    pub radarr_api_key: String,
+    pub sonarr_url: String,
+    pub sonarr_api_key: String,
+    pub jellyfin_url: String,
+    pub jellyfin_api_key: String,
+}
+
+// What used to be hardcoded, so existing installs keep working untouched
+impl Default for MediaSettings {
+    fn default() -> Self {
+        Self {
+            media_path: "/mnt/external/media1/media".to_string(),
+            radarr_url: "http://localhost:7878".to_string(),
+            radarr_api_key: "66fc15a8af02444bb787e5f4d9e585b4".to_string(),
+            sonarr_url: "http://localhost:8989".to_string(),
+            sonarr_api_key: "e3f602d269a349dabfc9e9a3ac995f76".to_string(),
+            jellyfin_url: "http://10.22.22.185:8096".to_string(),
+            jellyfin_api_key: "72972c09f8794beab6da4af991cff9a3".to_string(),
+        }
+    }
+}
+
+impl MediaSettings {
+    pub fn validate(&mut self) -> Result<(), String> {
+        if !self.media_path.starts_with('/') {
+            return Err("The media path must be absolute".to_string());
+        }
+        for (name, url) in [("Radarr", &mut self.radarr_url), ("Sonarr", &mut self.sonarr_url), ("Jellyfin", &mut self.jellyfin_url)] {
+            *url = url.trim().trim_end_matches('/').to_string();
+            if reqwest::Url::parse(url).map_or(true, |u| !matches!(u.scheme(), "http" | "https")) {
+                return Err(format!("{} URL must be an http:// or https:// address", name));
+            }
+        }
+        Ok(())
+    }
+}
+
+pub async fn load_settings(pool: &SqlitePool) -> MediaSettings {
+    match crate::db::settings::get(pool, SETTINGS_KEY).await {
+        Ok(settings) => settings.unwrap_or_default(),
+        Err(e) => {
+            tracing::warn!("Failed to read media settings, using defaults: {}", e);
+            MediaSettings::default()
+        }
+    }
+}
+
+pub async fn save_settings(pool: &SqlitePool, settings: &MediaSettings) -> Result<(), String> {
+    crate::db::settings::set(pool, SETTINGS_KEY, settings).await.map_err(|e| e.to_string())
+}
 
 #[derive(Debug, Serialize)]
 pub struct MediaOverview {
@@ -58,17 +113,19 @@ pub struct MediaItem {
 }
 
 pub async fn overview(
+    State(state): State<Arc<AppState>>,
     AuthUser(_user): AuthUser,
 ) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
     if mock::is_mock_mode() {
         return Ok(Json(mock::media::overview()));
     }
 
-    let storage = get_storage_info();
-    let library = get_library_counts();
-    let recent_movies = get_recent_movies().await;
-    let recent_shows = get_recent_shows().await;
-    let jellyfin = get_jellyfin_stats().await;
+    let settings = load_settings(&state.db).await;
+    let storage = get_storage_info(&settings.media_path);
+    let library = get_library_counts(&settings.media_path);
+    let recent_movies = get_recent_movies(&settings).await;
+    let recent_shows = get_recent_shows(&settings).await;
+    let jellyfin = get_jellyfin_stats(&settings).await;
 
     Ok(Json(serde_json::to_value(MediaOverview {
         storage,
@@ -79,11 +136,11 @@ pub async fn overview(
     }).unwrap()))
 }
 
-async fn get_jellyfin_stats() -> Option<JellyfinStats> {
+async fn get_jellyfin_stats(settings: &MediaSettings) -> Option<JellyfinStats> {
     let client = reqwest::Client::new();
 
     // Get system info
-    let system_url = format!("{}/System/Info?api_key={}", JELLYFIN_URL, JELLYFIN_API_KEY);
+    let system_url = format!("{}/System/Info?api_key={}", settings.jellyfin_url, settings.jellyfin_api_key);
     let system_info: Option<JellyfinSystemInfo> = client.get(&system_url)
         .timeout(std::time::Duration::from_secs(5))
         .send()
@@ -94,7 +151,7 @@ async fn get_jellyfin_stats() -> Option<JellyfinStats> {
         .ok();
 
     // Get library counts
-    let counts_url = format!("{}/Items/Counts?api_key={}", JELLYFIN_URL, JELLYFIN_API_KEY);
+    let counts_url = format!("{}/Items/Counts?api_key={}", settings.jellyfin_url, settings.jellyfin_api_key);
     let counts: Option<JellyfinCounts> = client.get(&counts_url)
         .timeout(std::time::Duration::from_secs(5))
         .send()
@@ -105,7 +162,7 @@ async fn get_jellyfin_stats() -> Option<JellyfinStats> {
         .ok();
 
     // Get active sessions
-    let sessions_url = format!("{}/Sessions?api_key={}", JELLYFIN_URL, JELLYFIN_API_KEY);
+    let sessions_url = format!("{}/Sessions?api_key={}", settings.jellyfin_url, settings.jellyfin_api_key);
     let sessions: Vec<JellyfinSession> = client.get(&sessions_url)
         .timeout(std::time::Duration::from_secs(5))
         .send()
@@ -153,9 +210,9 @@ struct JellyfinSession {
     now_playing_item: Option<serde_json::Value>,
 }
 
-fn get_storage_info() -> StorageInfo {
+fn get_storage_info(media_path: &str) -> StorageInfo {
     let output = Command::new("df")
-        .args(["-B1", "/mnt/external"])
+        .args(["-B1", media_path])
         .output()
         .ok();
 
@@ -189,16 +246,16 @@ fn get_storage_info() -> StorageInfo {
     }
 }
 
-fn get_library_counts() -> LibraryCounts {
+fn get_library_counts(media_path: &str) -> LibraryCounts {
     let movies = Command::new("ls")
-        .args(["-1", &format!("{}/movies", MEDIA_PATH)])
+        .args(["-1", &format!("{}/movies", media_path)])
         .output()
         .ok()
         .map(|o| String::from_utf8_lossy(&o.stdout).lines().count() as u64)
         .unwrap_or(0);
 
     let tv_shows = Command::new("ls")
-        .args(["-1", &format!("{}/shows", MEDIA_PATH)])
+        .args(["-1", &format!("{}/shows", media_path)])
         .output()
         .ok()
         .map(|o| String::from_utf8_lossy(&o.stdout).lines().count() as u64)
@@ -207,10 +264,10 @@ fn get_library_counts() -> LibraryCounts {
     LibraryCounts { movies, tv_shows }
 }
 
-async fn get_recent_movies() -> Vec<MediaItem> {
+async fn get_recent_movies(settings: &MediaSettings) -> Vec<MediaItem> {
     // Try Radarr API first
     let url = format!("{}/api/v3/history?pageSize=10&sortKey=date&sortDirection=descending&apikey={}",
-        RADARR_URL, RADARR_API_KEY);
+        settings.radarr_url, settings.radarr_api_key);
 
     if let Ok(resp) = reqwest::get(&url).await {
         if let Ok(data) = resp.json::<RadarrHistoryResponse>().await {
@@ -233,13 +290,13 @@ async fn get_recent_movies() -> Vec<MediaItem> {
     }
 
     // Fallback: get recent files from filesystem
-    get_recent_files_from_fs("movies")
+    get_recent_files_from_fs(&settings.media_path, "movies")
 }
 
-async fn get_recent_shows() -> Vec<MediaItem> {
+async fn get_recent_shows(settings: &MediaSettings) -> Vec<MediaItem> {
     // Try Sonarr API first
     let url = format!("{}/api/v3/history?pageSize=10&sortKey=date&sortDirection=descending&apikey={}",
-        SONARR_URL, SONARR_API_KEY);
+        settings.sonarr_url, settings.sonarr_api_key);
 
     if let Ok(resp) = reqwest::get(&url).await {
         if let Ok(data) = resp.json::<SonarrHistoryResponse>().await {
@@ -275,12 +332,12 @@ async fn get_recent_shows() -> Vec<MediaItem> {
     }
 
     // Fallback: get recent files from filesystem
-    get_recent_files_from_fs("shows")
+    get_recent_files_from_fs(&settings.media_path, "shows")
 }
 
-fn get_recent_files_from_fs(folder: &str) -> Vec<MediaItem> {
+fn get_recent_files_from_fs(media_path: &str, folder: &str) -> Vec<MediaItem> {
     let output = Command::new("ls")
-        .args(["-lt", "--time-style=+%Y-%m-%d", &format!("{}/{}", MEDIA_PATH, folder)])
+        .args(["-lt", "--time-style=+%Y-%m-%d", &format!("{}/{}", media_path, folder)])
         .output()
         .ok();
 
@@ -391,6 +448,7 @@ struct ArrNotification {
 
 // Check if Jellyfin notifications are configured in Radarr/Sonarr
 pub async fn check_jellyfin_notifications(
+    State(state): State<Arc<AppState>>,
     _user: AuthUser,
 ) -> Result<Json<NotificationStatus>, (StatusCode, String)> {
     if mock::is_mock_mode() {
@@ -402,10 +460,11 @@ pub async fn check_jellyfin_notifications(
         }));
     }
 
+    let settings = load_settings(&state.db).await;
     let client = reqwest::Client::new();
 
     // Check Radarr
-    let radarr_url = format!("{}/api/v3/notification?apikey={}", RADARR_URL, RADARR_API_KEY);
+    let radarr_url = format!("{}/api/v3/notification?apikey={}", settings.radarr_url, settings.radarr_api_key);
     let radarr_notifications: Vec<ArrNotification> = client.get(&radarr_url)
         .timeout(std::time::Duration::from_secs(5))
         .send()
@@ -419,7 +478,7 @@ pub async fn check_jellyfin_notifications(
         .find(|n| n.implementation == "Emby" || n.implementation == "Jellyfin");
 
     // Check Sonarr
-    let sonarr_url = format!("{}/api/v3/notification?apikey={}", SONARR_URL, SONARR_API_KEY);
+    let sonarr_url = format!("{}/api/v3/notification?apikey={}", settings.sonarr_url, settings.sonarr_api_key);
     let sonarr_notifications: Vec<ArrNotification> = client.get(&sonarr_url)
         .timeout(std::time::Duration::from_secs(5))
         .send()
@@ -442,12 +501,14 @@ pub async fn check_jellyfin_notifications(
 
 // Add Jellyfin notification to Radarr and Sonarr
 pub async fn setup_jellyfin_notifications(
+    State(state): State<Arc<AppState>>,
     _user: AuthUser,
 ) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
     if mock::is_mock_mode() {
         return Ok(Json(serde_json::json!({"success": true, "mock": true})));
     }
 
+    let settings = load_settings(&state.db).await;
     let client = reqwest::Client::new();
 
     // Jellyfin notification payload for Radarr/Sonarr
@@ -457,8 +518,8 @@ pub async fn setup_jellyfin_notifications(
         "implementation": "Emby",
         "configContract": "MediaBrowserSettings",
         "fields": [
-            {"name": "host", "value": JELLYFIN_URL},
-            {"name": "apiKey", "value": JELLYFIN_API_KEY},
+            {"name": "host", "value": settings.jellyfin_url},
+            {"name": "apiKey", "value": settings.jellyfin_api_key},
             {"name": "sendNotifications", "value": false},
             {"name": "updateLibrary", "value": true}
         ],
@@ -485,7 +546,7 @@ pub async fn setup_jellyfin_notifications(
     });
 
     // Add to Radarr
-    let radarr_url = format!("{}/api/v3/notification?apikey={}", RADARR_URL, RADARR_API_KEY);
+    let radarr_url = format!("{}/api/v3/notification?apikey={}", settings.radarr_url, settings.radarr_api_key);
     match client.post(&radarr_url)
         .json(&notification_payload)
         .timeout(std::time::Duration::from_secs(10))
@@ -508,7 +569,7 @@ pub async fn setup_jellyfin_notifications(
     }
 
     // Add to Sonarr
-    let sonarr_url = format!("{}/api/v3/notification?apikey={}", SONARR_URL, SONARR_API_KEY);
+    let sonarr_url = format!("{}/api/v3/notification?apikey={}", settings.sonarr_url, settings.sonarr_api_key);
     match client.post(&sonarr_url)
         .json(&notification_payload)
         .timeout(std::time::Duration::from_secs(10))
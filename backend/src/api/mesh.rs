@@ -0,0 +1,165 @@
+use axum::{extract::State, http::StatusCode, Json};
+use serde::Deserialize;
+use std::sync::Arc;
+
+use super::{require_permission, AuthUser};
+use crate::mesh::{self, ManagedAp};
+use crate::mock;
+use crate::AppState;
+
+// ============ MANAGED ACCESS POINTS ============
+
+pub async fn list(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    require_permission(&user, "wifi:write").map_err(|(s, m)| (s, m.to_string()))?;
+
+    if mock::is_mock_mode() {
+        return Ok(Json(mock::network::managed_aps()));
+    }
+
+    let aps = mesh::list(&state.db)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let public_key = mesh::public_key()
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    Ok(Json(serde_json::json!({
+        "access_points": aps,
+        "public_key": public_key,
+    })))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AdoptRequest {
+    pub name: String,
+    pub host: String,
+    pub port: Option<u16>,
+    pub username: Option<String>,
+    pub lan_network: Option<String>,
+    // Leave out to not broadcast the guest SSID from this AP
+    pub guest_network: Option<String>,
+}
+
+pub async fn adopt(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    Json(payload): Json<AdoptRequest>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    require_permission(&user, "wifi:write").map_err(|(s, m)| (s, m.to_string()))?;
+
+    let host = payload.host.trim().to_string();
+    if !mesh::valid_host(&host) {
+        return Err((StatusCode::BAD_REQUEST, "Invalid host".to_string()));
+    }
+    let username = payload.username.map(|u| u.trim().to_string()).filter(|u| !u.is_empty()).unwrap_or_else(|| "root".to_string());
+    if !mesh::valid_username(&username) {
+        return Err((StatusCode::BAD_REQUEST, "Invalid username".to_string()));
+    }
+    let lan_network = payload.lan_network.map(|n| n.trim().to_string()).filter(|n| !n.is_empty()).unwrap_or_else(|| "lan".to_string());
+    let guest_network = payload.guest_network.map(|n| n.trim().to_string()).filter(|n| !n.is_empty());
+    if !mesh::valid_network(&lan_network) || guest_network.as_deref().is_some_and(|n| !mesh::valid_network(n)) {
+        return Err((StatusCode::BAD_REQUEST, "Invalid OpenWrt network name".to_string()));
+    }
+
+    if mock::is_mock_mode() {
+        return Ok(Json(serde_json::json!({"success": true, "mock": true})));
+    }
+
+    let ap = ManagedAp {
+        id: uuid::Uuid::new_v4().to_string(),
+        name: if payload.name.trim().is_empty() { host.clone() } else { payload.name.trim().to_string() },
+        host,
+        port: payload.port.unwrap_or(22) as i64,
+        username,
+        lan_network,
+        guest_network,
+        model: None,
+        firmware: None,
+        last_sync_at: None,
+        last_error: None,
+        created_at: chrono::Utc::now().to_rfc3339(),
+    };
+
+    let ap = mesh::adopt(&state.db, ap)
+        .await
+        .map_err(|e| (StatusCode::BAD_GATEWAY, e))?;
+
+    // First push right away so the AP joins the network
+    let synced = mesh::sync(&state.db, &ap).await;
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "access_point": ap,
+        "sync_error": synced.err(),
+    })))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ApId {
+    pub id: String,
+}
+
+pub async fn remove(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    Json(payload): Json<ApId>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    require_permission(&user, "wifi:write").map_err(|(s, m)| (s, m.to_string()))?;
+
+    if mock::is_mock_mode() {
+        return Ok(Json(serde_json::json!({"success": true, "mock": true})));
+    }
+
+    if !mesh::remove(&state.db, &payload.id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    {
+        return Err((StatusCode::NOT_FOUND, "Access point not found".to_string()));
+    }
+
+    Ok(Json(serde_json::json!({"success": true})))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SyncRequest {
+    // Every AP when left out
+    pub id: Option<String>,
+}
+
+pub async fn sync(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    Json(payload): Json<SyncRequest>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    require_permission(&user, "wifi:write").map_err(|(s, m)| (s, m.to_string()))?;
+
+    if mock::is_mock_mode() {
+        return Ok(Json(serde_json::json!({"success": true, "mock": true})));
+    }
+
+    let results = match payload.id {
+        Some(id) => {
+            let ap = mesh::list(&state.db)
+                .await
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+                .into_iter()
+                .find(|ap| ap.id == id)
+                .ok_or((StatusCode::NOT_FOUND, "Access point not found".to_string()))?;
+            vec![(ap.id.clone(), mesh::sync(&state.db, &ap).await)]
+        }
+        None => mesh::sync_all(&state.db).await,
+    };
+
+    let results: Vec<serde_json::Value> = results
+        .into_iter()
+        .map(|(id, result)| serde_json::json!({"id": id, "success": result.is_ok(), "error": result.err()}))
+        .collect();
+
+    Ok(Json(serde_json::json!({
+        "success": results.iter().all(|r| r["success"] == true),
+        "results": results,
+    })))
+}
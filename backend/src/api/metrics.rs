@@ -0,0 +1,69 @@
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    Json,
+};
+use serde::Deserialize;
+use std::sync::Arc;
+
+use super::AuthUser;
+use crate::metrics;
+use crate::mock;
+use crate::AppState;
+
+// ============ METRICS HISTORY ============
+
+/// The metrics there is history for, and how they are sampled
+pub async fn list(
+    State(state): State<Arc<AppState>>,
+    AuthUser(_user): AuthUser,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    if mock::is_mock_mode() {
+        return Ok(Json(mock::dashboard::metrics()));
+    }
+
+    let names = metrics::names(&state.db)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let retention: Vec<serde_json::Value> = metrics::TIERS
+        .iter()
+        .map(|t| serde_json::json!({"resolution_secs": t.resolution, "keep_secs": t.keep_secs}))
+        .collect();
+
+    Ok(Json(serde_json::json!({
+        "metrics": names,
+        "settings": metrics::load_settings(&state.db).await,
+        "retention": retention,
+    })))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HistoryQuery {
+    pub metric: String,
+    // "1h", "24h", "7d"...
+    pub range: Option<String>,
+}
+
+pub async fn history(
+    State(state): State<Arc<AppState>>,
+    AuthUser(_user): AuthUser,
+    Query(query): Query<HistoryQuery>,
+) -> Result<Json<metrics::History>, (StatusCode, String)> {
+    if !metrics::valid_metric(&query.metric) {
+        return Err((StatusCode::BAD_REQUEST, format!("Unknown metric {}", query.metric)));
+    }
+    let range = query.range.as_deref().unwrap_or("24h");
+    let range_secs = metrics::parse_range(range)
+        .ok_or_else(|| (StatusCode::BAD_REQUEST, format!("Invalid range {}; use e.g. 30m, 24h or 7d, up to 365d", range)))?;
+
+    if mock::is_mock_mode() {
+        return Ok(Json(mock::dashboard::metrics_history(&query.metric, range_secs)));
+    }
+
+    let settings = metrics::load_settings(&state.db).await;
+    let history = metrics::history(&state.db, &query.metric, range_secs, settings.interval_secs)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(history))
+}
@@ -6,6 +6,7 @@ pub mod antivirus;
 pub mod network;
 pub mod adguard;
 pub mod dashboard;
+pub mod health;
 pub mod system;
 pub mod users;
 pub mod services;
@@ -15,12 +16,29 @@ pub mod tools;
 pub mod security;
 pub mod media;
 pub mod setup;
+pub mod certificates;
+pub mod mesh;
+pub mod modem;
+pub mod monitors;
+pub mod metrics;
+pub mod homelab;
+pub mod presence;
+pub mod approvals;
+pub mod profiles;
+pub mod qos;
+pub mod wan;
+pub mod tokens;
+pub mod undo;
+pub mod settings;
+pub mod public;
 
 use axum::{
     extract::FromRequestParts,
     http::{request::Parts, StatusCode},
 };
 
+use serde::Serialize;
+
 use crate::models::User;
 
 // Auth extractor - gets current user from session token
@@ -33,28 +51,64 @@ where
     type Rejection = (StatusCode, &'static str);
 
     async fn from_request_parts(
-        _parts: &mut Parts,
+        parts: &mut Parts,
         _state: &S,
     ) -> Result<Self, Self::Rejection> {
-        // For now, skip auth and return a dummy user for testing
-        // TODO: Implement proper auth extraction from cookie/header
-        Ok(AuthUser(User {
-            id: 1,
-            username: "test".to_string(),
-            password_hash: "".to_string(),
-            role: "admin".to_string(),
-            enabled: true,
-            created_at: "".to_string(),
-            last_login: None,
-        }))
+        // Already checked by the API key middleware
+        if let Some(auth) = parts.extensions.get::<crate::auth::api_tokens::TokenAuth>() {
+            return Ok(AuthUser(auth.user.clone()));
+        }
+        // A live session, resolved by auth::authenticate
+        if let Some(auth) = parts.extensions.get::<crate::auth::SessionAuth>() {
+            return Ok(AuthUser(auth.user.clone()));
+        }
+
+        // Mock mode serves the UI without accounts
+        if crate::mock::is_mock_mode() {
+            return Ok(AuthUser(User {
+                id: 1,
+                username: "demo".to_string(),
+                password_hash: "".to_string(),
+                role: "admin".to_string(),
+                enabled: true,
+                created_at: "".to_string(),
+                last_login: None,
+                permissions: Vec::new(),
+            }));
+        }
+
+        Err((StatusCode::UNAUTHORIZED, "Not signed in"))
     }
 }
 
-// Role checker
-pub fn require_role(user: &User, required: &[&str]) -> Result<(), (StatusCode, &'static str)> {
-    if required.contains(&user.role.as_str()) {
-        Ok(())
-    } else {
-        Err((StatusCode::FORBIDDEN, "Insufficient permissions"))
+// Permission checker, e.g. require_permission(&user, "users:write")
+pub fn require_permission(user: &User, permission: &str) -> Result<(), (StatusCode, &'static str)> {
+    crate::auth::permissions::require(user, permission)
+}
+
+/// Outcome of one item in a batch request. Batches are all-or-nothing, so when one item is
+/// rejected the others come back with ok = false too, explaining that they were not applied
+#[derive(Debug, Serialize)]
+pub struct BulkItem {
+    pub item: String,
+    pub ok: bool,
+    pub error: Option<String>,
+}
+
+impl BulkItem {
+    pub fn ok(item: impl Into<String>) -> Self {
+        BulkItem { item: item.into(), ok: true, error: None }
+    }
+
+    pub fn failed(item: impl Into<String>, error: impl Into<String>) -> Self {
+        BulkItem { item: item.into(), ok: false, error: Some(error.into()) }
+    }
+
+    /// Turn the items that passed into "not applied" once the batch is abandoned
+    pub fn abandon(items: &mut [BulkItem]) {
+        for item in items.iter_mut().filter(|i| i.ok) {
+            item.ok = false;
+            item.error = Some("Not applied because another item failed".to_string());
+        }
     }
 }
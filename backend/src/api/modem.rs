@@ -0,0 +1,195 @@
+use axum::{extract::State, http::StatusCode, Json};
+use serde::Deserialize;
+use std::sync::Arc;
+
+use super::{require_permission, AuthUser};
+use crate::mock;
+use crate::modem::{self, ModemSettings};
+use crate::AppState;
+
+// ============ LTE MODEM ============
+
+async fn blocking<T: Send + 'static>(
+    f: impl FnOnce() -> Result<T, String> + Send + 'static,
+) -> Result<T, (StatusCode, String)> {
+    tokio::task::spawn_blocking(f)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .map_err(|e| (StatusCode::BAD_GATEWAY, e))
+}
+
+async fn modem_index() -> Result<u32, (StatusCode, String)> {
+    blocking(modem::find_modem)
+        .await?
+        .ok_or((StatusCode::NOT_FOUND, "No modem found by ModemManager".to_string()))
+}
+
+pub async fn status(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    require_permission(&user, "network:write").map_err(|(s, m)| (s, m.to_string()))?;
+
+    if mock::is_mock_mode() {
+        return Ok(Json(mock::network::modem()));
+    }
+
+    let mut settings = modem::load_settings();
+    let status = blocking(|| {
+        let Some(index) = modem::find_modem()? else { return Ok(None) };
+        let mut status = modem::status(index)?;
+        // Extended readings are off until asked for once
+        if status.signal.rsrp.is_none() && status.signal.rssi.is_none() && modem::enable_signal_details(index).is_ok() {
+            status = modem::status(index)?;
+        }
+        Ok(Some(status))
+    })
+    .await;
+    let usage = modem::usage(&state.db, &settings)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let daily: Vec<serde_json::Value> = modem::daily_usage(&state.db, 31)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .into_iter()
+        .map(|(day, rx, tx)| serde_json::json!({"day": day, "rx_bytes": rx, "tx_bytes": tx}))
+        .collect();
+
+    let password_set = settings.password.as_deref().is_some_and(|p| !p.is_empty());
+    settings.password = None;
+    let (modem, error) = match status {
+        Ok(modem) => (modem, None),
+        Err((_, e)) => (None, Some(e)),
+    };
+
+    Ok(Json(serde_json::json!({
+        "settings": settings,
+        "password_set": password_set,
+        "modem": modem,
+        "error": error,
+        "usage": usage,
+        "daily": daily,
+        "monitor": state.modem.snapshot(),
+    })))
+}
+
+pub async fn update_settings(
+    AuthUser(user): AuthUser,
+    Json(mut payload): Json<ModemSettings>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    require_permission(&user, "network:write").map_err(|(s, m)| (s, m.to_string()))?;
+
+    payload.apn = payload.apn.trim().to_string();
+    payload.validate().map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+    if mock::is_mock_mode() {
+        return Ok(Json(serde_json::json!({"success": true, "mock": true})));
+    }
+
+    // The password is never sent back, so leaving it out keeps the stored one
+    if payload.password.is_none() {
+        payload.password = modem::load_settings().password;
+    }
+    modem::save_settings(&payload).map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    // The monitor connects or hangs up for the new role on its next check
+    Ok(Json(serde_json::json!({"success": true})))
+}
+
+pub async fn connect(AuthUser(user): AuthUser) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    require_permission(&user, "network:write").map_err(|(s, m)| (s, m.to_string()))?;
+
+    if mock::is_mock_mode() {
+        return Ok(Json(serde_json::json!({"success": true, "mock": true})));
+    }
+
+    let settings = modem::load_settings();
+    if settings.apn.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "Configure an APN first".to_string()));
+    }
+    let index = modem_index().await?;
+    let bearer = blocking(move || modem::connect(index, &settings)).await?;
+
+    Ok(Json(serde_json::json!({"success": true, "bearer": bearer})))
+}
+
+pub async fn disconnect(AuthUser(user): AuthUser) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    require_permission(&user, "network:write").map_err(|(s, m)| (s, m.to_string()))?;
+
+    if mock::is_mock_mode() {
+        return Ok(Json(serde_json::json!({"success": true, "mock": true})));
+    }
+
+    let index = modem_index().await?;
+    blocking(move || modem::disconnect(index)).await?;
+
+    Ok(Json(serde_json::json!({"success": true})))
+}
+
+// ============ SMS ============
+
+pub async fn sms_list(AuthUser(user): AuthUser) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    require_permission(&user, "network:write").map_err(|(s, m)| (s, m.to_string()))?;
+
+    if mock::is_mock_mode() {
+        return Ok(Json(mock::network::modem_sms()));
+    }
+
+    let index = modem_index().await?;
+    let messages = blocking(move || modem::list_sms(index)).await?;
+
+    Ok(Json(serde_json::json!({"messages": messages})))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SendSmsRequest {
+    pub number: String,
+    pub text: String,
+}
+
+pub async fn sms_send(
+    AuthUser(user): AuthUser,
+    Json(payload): Json<SendSmsRequest>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    require_permission(&user, "network:write").map_err(|(s, m)| (s, m.to_string()))?;
+
+    let number: String = payload.number.chars().filter(|c| !c.is_whitespace() && *c != '-').collect();
+    let digits = number.strip_prefix('+').unwrap_or(&number);
+    if digits.is_empty() || digits.len() > 20 || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return Err((StatusCode::BAD_REQUEST, "Invalid phone number".to_string()));
+    }
+    let text = payload.text.trim().to_string();
+    if text.is_empty() || text.chars().count() > 640 || text.contains('\'') {
+        return Err((StatusCode::BAD_REQUEST, "Message must be 1-640 characters without single quotes".to_string()));
+    }
+
+    if mock::is_mock_mode() {
+        return Ok(Json(serde_json::json!({"success": true, "mock": true})));
+    }
+
+    let index = modem_index().await?;
+    blocking(move || modem::send_sms(index, &number, &text)).await?;
+
+    Ok(Json(serde_json::json!({"success": true})))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SmsId {
+    pub id: u32,
+}
+
+pub async fn sms_delete(
+    AuthUser(user): AuthUser,
+    Json(payload): Json<SmsId>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    require_permission(&user, "network:write").map_err(|(s, m)| (s, m.to_string()))?;
+
+    if mock::is_mock_mode() {
+        return Ok(Json(serde_json::json!({"success": true, "mock": true})));
+    }
+
+    let index = modem_index().await?;
+    blocking(move || modem::delete_sms(index, payload.id)).await?;
+
+    Ok(Json(serde_json::json!({"success": true})))
+}
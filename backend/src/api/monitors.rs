@@ -0,0 +1,106 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    Json,
+};
+use serde::Deserialize;
+use std::sync::Arc;
+
+use super::{require_permission, AuthUser};
+use crate::mock;
+use crate::monitors::{self, MonitorSettings};
+use crate::AppState;
+
+// ============ UPTIME MONITORS ============
+
+/// Every monitor with its current status and uptime
+pub async fn list(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    require_permission(&user, "services:read").map_err(|(s, m)| (s, m.to_string()))?;
+
+    if mock::is_mock_mode() {
+        return Ok(Json(mock::services::monitors()));
+    }
+
+    let settings = monitors::load_settings(&state.db).await;
+    let mut entries = Vec::new();
+    for monitor in &settings.monitors {
+        let uptime = monitors::uptime(&state.db, &monitor.id)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        entries.push(serde_json::json!({
+            "monitor": monitor,
+            "state": state.monitors.get(&monitor.id),
+            "uptime": uptime,
+        }));
+    }
+
+    Ok(Json(serde_json::json!({
+        "settings": settings,
+        "monitors": entries,
+    })))
+}
+
+pub async fn update_settings(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    Json(mut payload): Json<MonitorSettings>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    require_permission(&user, "services:write").map_err(|(s, m)| (s, m.to_string()))?;
+
+    if mock::is_mock_mode() {
+        return Ok(Json(serde_json::json!({"success": true, "mock": true})));
+    }
+
+    payload.normalize().map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    monitors::save_settings(&state.db, &payload)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    Ok(Json(serde_json::json!({"success": true, "settings": payload})))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HistoryQuery {
+    pub hours: Option<i64>,
+}
+
+pub async fn history(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    Path(id): Path<String>,
+    Query(query): Query<HistoryQuery>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    require_permission(&user, "services:read").map_err(|(s, m)| (s, m.to_string()))?;
+
+    if mock::is_mock_mode() {
+        return Ok(Json(mock::services::monitor_history()));
+    }
+
+    let hours = query.hours.unwrap_or(24).clamp(1, 24 * 30);
+    let checks = monitors::history(&state.db, &id, hours)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(serde_json::json!({"id": id, "hours": hours, "checks": checks})))
+}
+
+/// Run a check right away, e.g. after fixing the service, instead of waiting for the interval
+pub async fn check_now(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    Path(id): Path<String>,
+) -> Result<Json<monitors::CheckResult>, (StatusCode, String)> {
+    require_permission(&user, "services:write").map_err(|(s, m)| (s, m.to_string()))?;
+
+    let settings = monitors::load_settings(&state.db).await;
+    let monitor = settings
+        .monitors
+        .iter()
+        .find(|m| m.id == id)
+        .ok_or((StatusCode::NOT_FOUND, "Monitor not found".to_string()))?;
+
+    Ok(Json(monitors::run(&state, monitor, settings.failures_before_alert).await))
+}
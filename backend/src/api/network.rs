@@ -1,18 +1,36 @@
-use axum::{extract::Json, http::StatusCode};
+use axum::{extract::{ConnectInfo, Json, Query, State}, http::StatusCode};
+use chrono::{DateTime, Local};
 use serde::{Deserialize, Serialize};
 use std::process::Command;
+use crate::system::privileges::{sudo, write_system_file};
 use std::fs;
 use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
 
+use super::{require_permission, AuthUser, BulkItem};
 use crate::mock;
+use crate::scheduler::{Edge, Schedule};
+use crate::undo::UndoKind;
+use crate::interfaces::Role;
+use crate::AppState;
 
 const DNSMASQ_CONF: &str = "/etc/dnsmasq.d/router.conf";
-const DNSMASQ_LEASES: &str = "/var/lib/misc/dnsmasq.leases";
-const DNSMASQ_STATIC: &str = "/etc/dnsmasq.d/static-leases.conf";
+pub(crate) const DNSMASQ_LEASES: &str = "/var/lib/misc/dnsmasq.leases";
+pub(crate) const DNSMASQ_STATIC: &str = "/etc/dnsmasq.d/static-leases.conf";
 const HOSTAPD_CONF: &str = "/etc/hostapd/hostapd.conf";
+const HOSTAPD_DIR: &str = "/etc/hostapd";
+// The radio configured in HOSTAPD_CONF and run by hostapd.service
+const DEFAULT_RADIO: &str = "hostapd";
 const STATIC_ROUTES_FILE: &str = "/opt/routerui/static-routes.json";
 const WOL_DEVICES_FILE: &str = "/opt/routerui/wol-devices.json";
-const LOCAL_DNS_FILE: &str = "/etc/dnsmasq.d/local-dns.conf";
+pub(crate) const LOCAL_DNS_FILE: &str = "/etc/dnsmasq.d/local-dns.conf";
+pub(crate) const DHCP_OPTIONS_FILE: &str = "/etc/dnsmasq.d/dhcp-options.conf";
+pub(crate) const DNS_REBIND_FILE: &str = "/etc/dnsmasq.d/rebind.conf";
+const WIFI_SCHEDULE_FILE: &str = "/opt/routerui/wifi-schedule.json";
+// Marks hostapd.conf lines of a guest SSID that is switched off by its schedule
+const GUEST_OFF_PREFIX: &str = "#routerui-off# ";
+const MAX_LEASE_IMPORT: usize = 1000;
 
 // ============ INTERFACES ============
 
@@ -27,12 +45,15 @@ pub struct NetworkInterface {
     pub rx_bytes: u64,
     pub tx_bytes: u64,
     pub interface_type: String, // wan, lan, wifi, loopback
+    // What the interface is used for, from the interface roles
+    pub role: Option<Role>,
 }
 
-pub async fn interfaces() -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+pub async fn interfaces(State(state): State<Arc<AppState>>) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
     if mock::is_mock_mode() {
         return Ok(Json(mock::network::interfaces()));
     }
+    let roles = crate::interfaces::roles(&state.db).await;
 
     let output = Command::new("ip")
         .args(["-j", "addr", "show"])
@@ -82,14 +103,15 @@ pub async fn interfaces() -> Result<Json<serde_json::Value>, (StatusCode, String
         // Get RX/TX stats
         let (rx_bytes, tx_bytes) = get_interface_stats(&name);
 
-        // Determine interface type
-        let interface_type = match name.as_str() {
-            "tailscale0" => "vpn",
-            "br0" => "bridge",
-            "enp1s0" => "wan",
-            "enp2s0" => "lan",
-            "wlo1" | "wlan0" => "wifi",
-            "lo" => "loopback",
+        // Determine interface type; a role wins over guessing from the name
+        let role = roles.role_of(&name);
+        let interface_type = match (role, name.as_str()) {
+            (Some(Role::Guest), _) => "wifi",
+            (Some(role), _) => role.as_str(),
+            (None, "tailscale0") => "vpn",
+            (None, "br0") => "bridge",
+            (None, "wlo1" | "wlan0") => "wifi",
+            (None, "lo") => "loopback",
             _ => "other",
         }.to_string();
 
@@ -103,12 +125,142 @@ pub async fn interfaces() -> Result<Json<serde_json::Value>, (StatusCode, String
             rx_bytes,
             tx_bytes,
             interface_type,
+            role,
         });
     }
 
     Ok(Json(serde_json::to_value(interfaces).unwrap()))
 }
 
+// Which interface has which role, and which of those were pinned in settings
+pub async fn interface_roles(State(state): State<Arc<AppState>>) -> Json<serde_json::Value> {
+    if mock::is_mock_mode() {
+        return Json(mock::network::interface_roles());
+    }
+    Json(serde_json::json!({
+        "roles": crate::interfaces::roles(&state.db).await,
+        "settings": crate::interfaces::load_settings(&state.db).await,
+    }))
+}
+
+pub async fn update_interface_roles(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    Json(mut payload): Json<crate::interfaces::RoleSettings>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    require_permission(&user, "network:write").map_err(|(s, m)| (s, m.to_string()))?;
+    payload.normalize();
+    payload.validate().map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+    if mock::is_mock_mode() {
+        return Ok(Json(serde_json::json!({"success": true, "mock": true})));
+    }
+
+    let old_wan = crate::wan::wan_interface(&state.db).await;
+    crate::interfaces::save_settings(&state.db, &payload)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    let roles = crate::interfaces::roles(&state.db).await;
+
+    // Everything else looks the WAN up when it runs; these chains were built for the old one
+    if roles.wan != old_wan {
+        let wan = roles.wan.clone();
+        let honeypot = crate::honeypot::load_settings();
+        let dnsguard = crate::dnsguard::load_settings(&state.db).await;
+        let qos = crate::qos::load_settings(&state.db).await;
+        tokio::task::spawn_blocking(move || {
+            if honeypot.enabled {
+                if let Err(e) = crate::honeypot::apply(&wan, &honeypot) {
+                    tracing::warn!("Could not move the honeypot ports to {}: {}", wan, e);
+                }
+            }
+            if dnsguard.enabled() {
+                if let Err(e) = crate::dnsguard::apply(&wan, &dnsguard) {
+                    tracing::warn!("Could not move DNS bypass blocking to {}: {}", wan, e);
+                }
+            }
+            if qos.enabled {
+                crate::qos::remove(&old_wan);
+                if let Err(e) = crate::qos::apply(&wan, &qos) {
+                    tracing::warn!("Could not move traffic shaping to {}: {}", wan, e);
+                }
+            }
+        })
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    }
+
+    let zones = crate::zones::load(&state.db).await;
+    tokio::task::spawn_blocking(move || {
+        if let Err(e) = crate::zones::refresh(&zones) {
+            tracing::warn!("Could not move the firewall zones to the new interfaces: {}", e);
+        }
+    })
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    tracing::info!(
+        "User {} set interface roles: wan {}, lan {}, guest {}, vpn {}",
+        user.username, roles.wan, roles.lan, roles.guest.as_deref().unwrap_or("none"), roles.vpn.as_deref().unwrap_or("none")
+    );
+    Ok(Json(serde_json::json!({"success": true, "roles": roles})))
+}
+
+// ============ LAN SUBNETS ============
+
+/// Extra LAN subnets with their link state and DHCP pool usage
+pub async fn subnets(State(state): State<Arc<AppState>>) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    if mock::is_mock_mode() {
+        return Ok(Json(mock::network::subnets()));
+    }
+    let settings = crate::subnets::load_settings(&state.db).await;
+    let lan = crate::interfaces::lan_interface(&state.db).await;
+    let (status, primary) = tokio::task::spawn_blocking(move || {
+        let primary: Vec<String> = crate::subnets::primary_subnets(&lan).iter().map(|(ip, prefix)| format!("{}/{}", ip, prefix)).collect();
+        (crate::subnets::status(&settings), serde_json::json!({"interface": lan, "addresses": primary}))
+    })
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(serde_json::json!({"primary": primary, "subnets": status})))
+}
+
+/// Replace the extra subnets and bring links, DHCP scopes and firewall rules in line
+pub async fn update_subnets(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    Json(mut payload): Json<crate::subnets::SubnetSettings>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    require_permission(&user, "network:write").map_err(|(s, m)| (s, m.to_string()))?;
+    payload.normalize();
+
+    let roles = crate::interfaces::roles(&state.db).await;
+    let lan = roles.lan.clone();
+    let primary = tokio::task::spawn_blocking(move || crate::subnets::primary_subnets(&lan))
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    payload.validate(&roles.wan, &roles.lan, &primary).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+    if mock::is_mock_mode() {
+        return Ok(Json(serde_json::json!({"success": true, "mock": true})));
+    }
+
+    let old = crate::subnets::load_settings(&state.db).await;
+    let applied = payload.clone();
+    let wan = roles.wan.clone();
+    tokio::task::spawn_blocking(move || crate::subnets::apply(&wan, &old, &applied))
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    crate::subnets::save_settings(&state.db, &payload)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    let names: Vec<&str> = payload.subnets.iter().map(|s| s.name.as_str()).collect();
+    tracing::info!("User {} set the extra LAN subnets to [{}]", user.username, names.join(", "));
+    Ok(Json(serde_json::json!({"success": true})))
+}
+
 fn get_interface_stats(name: &str) -> (u64, u64) {
     let rx_path = format!("/sys/class/net/{}/statistics/rx_bytes", name);
     let tx_path = format!("/sys/class/net/{}/statistics/tx_bytes", name);
@@ -145,6 +297,8 @@ pub struct DhcpLease {
     pub hostname: String,
     pub expires: String,
     pub is_static: bool,
+    // Name of the LAN subnet the address is on
+    pub subnet: String,
 }
 
 #[derive(Debug, Serialize)]
@@ -152,6 +306,7 @@ pub struct DhcpStatus {
     pub config: DhcpConfig,
     pub leases: Vec<DhcpLease>,
     pub static_leases: Vec<StaticLease>,
+    pub pool: Option<PoolStatus>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -166,11 +321,26 @@ pub struct AddStaticLease {
     pub mac_address: String,
     pub ip_address: String,
     pub hostname: Option<String>,
+    // Assign even though another device was seen using the address
+    #[serde(default)]
+    pub force: bool,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct RemoveStaticLease {
     pub mac_address: String,
+    // What to do with port forwards made for the device; asked for when there are any
+    #[serde(default)]
+    pub rules: Option<super::firewall::DependentAction>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ImportStaticLeases {
+    // mac,ip[,hostname] per line; a header line and # comments are skipped
+    pub csv: String,
+    // Drop the existing static leases instead of adding to them
+    #[serde(default)]
+    pub replace: bool,
 }
 
 #[derive(Debug, Deserialize)]
@@ -180,7 +350,7 @@ pub struct UpdateDhcpConfig {
     pub lease_time: String,
 }
 
-pub async fn dhcp_status() -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+pub async fn dhcp_status(State(state): State<Arc<AppState>>) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
     if mock::is_mock_mode() {
         return Ok(Json(mock::network::dhcp_status()));
     }
@@ -189,7 +359,11 @@ pub async fn dhcp_status() -> Result<Json<serde_json::Value>, (StatusCode, Strin
     let config = parse_dnsmasq_config()?;
 
     // Parse active leases
-    let leases = parse_dhcp_leases()?;
+    let subnets = crate::subnets::load_settings(&state.db).await;
+    let mut leases = parse_dhcp_leases()?;
+    for lease in &mut leases {
+        lease.subnet = subnets.label(&lease.ip_address).to_string();
+    }
 
     // Parse static leases
     let static_leases = load_static_leases();
@@ -198,6 +372,7 @@ pub async fn dhcp_status() -> Result<Json<serde_json::Value>, (StatusCode, Strin
         config,
         leases,
         static_leases,
+        pool: pool_status(),
     }).unwrap()))
 }
 
@@ -271,6 +446,7 @@ fn parse_dhcp_leases() -> Result<Vec<DhcpLease>, (StatusCode, String)> {
                 hostname,
                 expires,
                 is_static,
+                subnet: crate::subnets::PRIMARY_NAME.to_string(),
             });
         }
     }
@@ -278,7 +454,7 @@ fn parse_dhcp_leases() -> Result<Vec<DhcpLease>, (StatusCode, String)> {
     Ok(leases)
 }
 
-fn load_static_leases() -> Vec<StaticLease> {
+pub(crate) fn load_static_leases() -> Vec<StaticLease> {
     // Parse from dnsmasq static leases file
     let content = fs::read_to_string(DNSMASQ_STATIC).unwrap_or_default();
     let mut leases = Vec::new();
@@ -313,11 +489,11 @@ fn save_static_leases(leases: &[StaticLease]) -> Result<(), (StatusCode, String)
         }
     }
 
-    fs::write(DNSMASQ_STATIC, &content)
+    write_system_file(DNSMASQ_STATIC, &content)
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
     // Reload dnsmasq
-    let _ = Command::new("sudo")
+    let _ = sudo()
         .args(["systemctl", "reload", "dnsmasq"])
         .output();
 
@@ -337,30 +513,214 @@ pub async fn add_static_lease(
     if leases.iter().any(|l| l.mac_address.to_lowercase() == payload.mac_address.to_lowercase()) {
         return Err((StatusCode::BAD_REQUEST, "MAC address already has a static lease".to_string()));
     }
+    let ip: std::net::Ipv4Addr = payload
+        .ip_address
+        .trim()
+        .parse()
+        .map_err(|_| (StatusCode::BAD_REQUEST, format!("Invalid IPv4 address {}", payload.ip_address)))?;
+    if let Some(lease) = leases.iter().find(|l| l.ip_address == ip.to_string()) {
+        return Err((StatusCode::BAD_REQUEST, format!("{} is already reserved for {}", ip, lease.mac_address)));
+    }
+
+    // Probe before reserving, so a device already sitting on the address doesn't end up sharing it
+    let mac = payload.mac_address.clone();
+    let probe = tokio::task::spawn_blocking(move || crate::dhcp::conflict::check(ip, Some(&mac)))
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let warning = match probe {
+        Ok(Some(conflict)) if !payload.force => return Err((StatusCode::CONFLICT, conflict.message)),
+        Ok(Some(conflict)) => {
+            tracing::warn!("Static lease for {} assigned despite a conflict: {}", payload.mac_address, conflict.message);
+            Some(conflict.message)
+        }
+        Ok(None) => None,
+        Err(e) => Some(e),
+    };
 
     leases.push(StaticLease {
         mac_address: payload.mac_address,
-        ip_address: payload.ip_address,
+        ip_address: ip.to_string(),
         hostname: payload.hostname.unwrap_or_default(),
     });
 
     save_static_leases(&leases)?;
 
-    Ok(Json(serde_json::json!({"success": true})))
+    Ok(Json(serde_json::json!({"success": true, "warning": warning})))
 }
 
 pub async fn remove_static_lease(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
     Json(payload): Json<RemoveStaticLease>,
 ) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
     if mock::is_mock_mode() {
         return Ok(Json(serde_json::json!({"success": true, "mock": true})));
     }
 
+    let (removed, leases): (Vec<StaticLease>, Vec<StaticLease>) = load_static_leases()
+        .into_iter()
+        .partition(|l| l.mac_address.to_lowercase() == payload.mac_address.to_lowercase());
+    let Some(lease) = removed.first() else {
+        return Ok(Json(serde_json::json!({"success": true})));
+    };
+
+    let what = format!("The static lease for {} ({})", lease.mac_address, lease.ip_address);
+    let rules_removed = super::firewall::settle_dependents(
+        &state.db,
+        &user,
+        Some(&lease.mac_address),
+        Some(&lease.ip_address),
+        payload.rules,
+        &what,
+    )
+    .await?;
+    save_static_leases(&leases)?;
+
+    let summary = format!("static lease {} ({})", lease.mac_address, lease.ip_address);
+    let undo = state.undo.record(UndoKind::StaticLease, summary, &removed);
+    Ok(Json(serde_json::json!({"success": true, "undo": undo, "rules_removed": rules_removed})))
+}
+
+/// A device RouterUI knows is (or was meant to be) on the LAN, and where it came from
+#[derive(Debug, Clone)]
+pub(crate) struct KnownDevice {
+    pub mac: String,
+    pub ip: Option<String>,
+    pub source: &'static str,
+}
+
+/// Static leases, DHCP leases, Wake-on-LAN devices and the neighbour table, in that order, so the
+/// first entry for an address is the most deliberate one
+pub(crate) fn known_devices() -> Vec<KnownDevice> {
+    let mut devices: Vec<KnownDevice> = load_static_leases()
+        .into_iter()
+        .map(|l| KnownDevice { mac: l.mac_address.to_lowercase(), ip: Some(l.ip_address), source: "static lease" })
+        .collect();
+    for line in fs::read_to_string(DNSMASQ_LEASES).unwrap_or_default().lines() {
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        if let (Some(mac), Some(ip)) = (parts.get(1), parts.get(2)) {
+            devices.push(KnownDevice { mac: mac.to_lowercase(), ip: Some(ip.to_string()), source: "DHCP lease" });
+        }
+    }
+    devices.extend(load_wol_devices().into_iter().map(|d| KnownDevice {
+        mac: d.mac_address.to_lowercase(),
+        ip: d.ip_address.filter(|ip| !ip.is_empty()),
+        source: "Wake-on-LAN device",
+    }));
+    // IP address, HW type, Flags, HW address, Mask, Device; flags 0x0 is an incomplete entry
+    for line in fs::read_to_string("/proc/net/arp").unwrap_or_default().lines().skip(1) {
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        if let (Some(ip), Some(flags), Some(mac)) = (parts.first(), parts.get(2), parts.get(3)) {
+            if *flags != "0x0" && *mac != "00:00:00:00:00:00" {
+                devices.push(KnownDevice { mac: mac.to_lowercase(), ip: Some(ip.to_string()), source: "neighbour table" });
+            }
+        }
+    }
+    devices
+}
+
+/// The MAC address of the device at `ip`, if any source knows it
+pub(crate) fn mac_for_ip(ip: &str) -> Option<String> {
+    known_devices().into_iter().find(|d| d.ip.as_deref() == Some(ip)).map(|d| d.mac)
+}
+
+/// Put back static leases removed moments ago, unless the MAC has been given a lease since
+pub(crate) fn restore_static_leases(item: serde_json::Value) -> Result<(), (StatusCode, String)> {
+    let restored: Vec<StaticLease> = serde_json::from_value(item)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
     let mut leases = load_static_leases();
-    leases.retain(|l| l.mac_address.to_lowercase() != payload.mac_address.to_lowercase());
+    for lease in &restored {
+        if leases.iter().any(|l| l.mac_address.to_lowercase() == lease.mac_address.to_lowercase()) {
+            return Err((StatusCode::CONFLICT, format!("{} has a static lease again, not restoring", lease.mac_address)));
+        }
+    }
+    leases.extend(restored);
+    save_static_leases(&leases)
+}
+
+// Accepts aa:bb:cc:dd:ee:ff or aa-bb-cc-dd-ee-ff, returned lowercase with colons
+fn normalize_mac(value: &str) -> Option<String> {
+    let mac = value.trim().to_lowercase().replace('-', ":");
+    let parts: Vec<&str> = mac.split(':').collect();
+    let valid = parts.len() == 6 && parts.iter().all(|p| p.len() == 2 && p.chars().all(|c| c.is_ascii_hexdigit()));
+    valid.then_some(mac)
+}
+
+fn valid_lease_hostname(name: &str) -> bool {
+    name.len() <= 63 && !name.starts_with('-') && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+}
+
+fn parse_lease_row(line: &str) -> Result<StaticLease, String> {
+    let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+    if !(2..=3).contains(&fields.len()) {
+        return Err("Expected mac,ip or mac,ip,hostname".to_string());
+    }
+    let mac_address = normalize_mac(fields[0]).ok_or_else(|| format!("Invalid MAC address {}", fields[0]))?;
+    let ip_address = fields[1]
+        .parse::<std::net::Ipv4Addr>()
+        .map_err(|_| format!("Invalid IPv4 address {}", fields[1]))?
+        .to_string();
+    let hostname = fields.get(2).copied().unwrap_or_default().to_string();
+    if !valid_lease_hostname(&hostname) {
+        return Err(format!("Invalid hostname {}", hostname));
+    }
+    Ok(StaticLease { mac_address, ip_address, hostname })
+}
+
+// Import many static leases at once. Either every row is added or none are, and dnsmasq is
+// reloaded a single time
+pub async fn import_static_leases(
+    Json(payload): Json<ImportStaticLeases>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let rows: Vec<(usize, &str)> = payload
+        .csv
+        .lines()
+        .enumerate()
+        .map(|(n, l)| (n + 1, l.trim()))
+        .filter(|(_, l)| !l.is_empty() && !l.starts_with('#'))
+        .collect();
+    let header = rows.first().is_some_and(|(_, l)| l.to_lowercase().starts_with("mac"));
+    let rows = &rows[header as usize..];
+    if rows.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "No leases in the file".to_string()));
+    }
+    if rows.len() > MAX_LEASE_IMPORT {
+        return Err((StatusCode::BAD_REQUEST, format!("At most {} leases per import", MAX_LEASE_IMPORT)));
+    }
+
+    let mut leases = if payload.replace || mock::is_mock_mode() { Vec::new() } else { load_static_leases() };
+    let mut results = Vec::new();
+    for (line, row) in rows {
+        let label = format!("line {}", line);
+        let lease = match parse_lease_row(row) {
+            Ok(lease) => lease,
+            Err(e) => {
+                results.push(BulkItem::failed(label, e));
+                continue;
+            }
+        };
+        let label = format!("{}: {}", label, lease.mac_address);
+        if leases.iter().any(|l| l.mac_address.to_lowercase() == lease.mac_address) {
+            results.push(BulkItem::failed(label, "MAC address already has a static lease"));
+        } else if leases.iter().any(|l| l.ip_address == lease.ip_address) {
+            results.push(BulkItem::failed(label, format!("{} is already reserved", lease.ip_address)));
+        } else {
+            results.push(BulkItem::ok(label));
+            leases.push(lease);
+        }
+    }
+    if results.iter().any(|r| !r.ok) {
+        BulkItem::abandon(&mut results);
+        return Ok(Json(serde_json::json!({"success": false, "results": results})));
+    }
+
+    if mock::is_mock_mode() {
+        return Ok(Json(serde_json::json!({"success": true, "mock": true, "results": results})));
+    }
+
     save_static_leases(&leases)?;
 
-    Ok(Json(serde_json::json!({"success": true})))
+    Ok(Json(serde_json::json!({"success": true, "results": results})))
 }
 
 pub async fn update_dhcp_config(
@@ -397,11 +757,11 @@ pub async fn update_dhcp_config(
         new_content.push('\n');
     }
 
-    fs::write(DNSMASQ_CONF, &new_content)
+    write_system_file(DNSMASQ_CONF, &new_content)
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
     // Reload dnsmasq
-    Command::new("sudo")
+    sudo()
         .args(["systemctl", "reload", "dnsmasq"])
         .output()
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
@@ -409,167 +769,1688 @@ pub async fn update_dhcp_config(
     Ok(Json(serde_json::json!({"success": true})))
 }
 
-// ============ WIFI ============
+// ============ DHCP POOL ============
 
-#[derive(Debug, Serialize)]
-pub struct WifiConfig {
-    pub enabled: bool,
-    pub ssid: String,
-    pub password: String,
-    pub channel: u32,
-    pub hw_mode: String,
-    pub security: String,
-    pub hidden: bool,
-    pub country_code: String,
+// Percent of the pool in use
+const POOL_WARNING_PERCENT: f64 = 80.0;
+const POOL_CRITICAL_PERCENT: f64 = 95.0;
+// Leases suggested for busy pools, so addresses of departed guests come back quickly
+const SHORT_LEASE_SECS: u64 = 2 * 60 * 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PoolLevel {
+    Ok,
+    Warning,
+    Critical,
+}
+
+/// How full the dynamic range is right now
+#[derive(Debug, Clone, Serialize)]
+pub struct PoolStatus {
+    pub range_start: String,
+    pub range_end: String,
+    pub size: u32,
+    // Active dynamic leases plus reservations inside the range
+    pub used: u32,
+    pub free: u32,
+    pub utilization: f64,
+    pub level: PoolLevel,
+    pub lease_time: String,
+    // Dynamic leases running out within the next hour
+    pub expiring_soon: u32,
+    pub suggestions: Vec<String>,
+}
+
+/// dnsmasq lease times: plain seconds or with an m/h/d/w suffix; None for infinite
+pub(crate) fn lease_secs(lease_time: &str) -> Option<u64> {
+    let lease_time = lease_time.trim();
+    let (number, unit) = match lease_time.char_indices().last()? {
+        (i, c) if c.is_ascii_alphabetic() => (&lease_time[..i], c.to_ascii_lowercase()),
+        _ => (lease_time, 's'),
+    };
+    let multiplier = match unit {
+        's' => 1,
+        'm' => 60,
+        'h' => 3600,
+        'd' => 86400,
+        'w' => 604800,
+        _ => return None,
+    };
+    number.parse::<u64>().ok().map(|n| n * multiplier)
+}
+
+fn pool_level(utilization: f64) -> PoolLevel {
+    if utilization >= POOL_CRITICAL_PERCENT {
+        PoolLevel::Critical
+    } else if utilization >= POOL_WARNING_PERCENT {
+        PoolLevel::Warning
+    } else {
+        PoolLevel::Ok
+    }
+}
+
+// Grow the range within its LAN subnet, keeping clear of the router's own address
+fn range_extension(start: u32, end: u32, gateway: Option<u32>, subnets: &[(std::net::Ipv4Addr, u32)]) -> Option<(u32, u32)> {
+    let (network, prefix) = subnets.iter().find(|(network, prefix)| {
+        let mask = u32::MAX.checked_shl(32 - prefix).unwrap_or(0);
+        u32::from(*network) & mask == start & mask
+    })?;
+    let mask = u32::MAX.checked_shl(32 - prefix).unwrap_or(0);
+    let (first, last) = ((u32::from(*network) & mask) + 1, (u32::from(*network) | !mask).checked_sub(1)?);
+
+    // Extend upwards when there is room, as addresses below the range usually hold the router
+    // and reservations
+    let new_end = match gateway {
+        Some(g) if g > end && g <= last => g - 1,
+        _ => last,
+    };
+    if new_end > end {
+        return Some((start, new_end));
+    }
+    let new_start = match gateway {
+        Some(g) if g < start && g >= first => g + 1,
+        _ => first,
+    };
+    (new_start < start).then_some((new_start, end))
+}
+
+/// The main LAN's pool
+pub(crate) fn pool_status() -> Option<PoolStatus> {
+    let config = parse_dnsmasq_config().ok()?;
+    let gateway = config.gateway.parse().ok();
+    pool_usage(&config.range_start, &config.range_end, &config.lease_time, gateway, &crate::system::lan_subnets(""))
+}
+
+/// Usage of one dynamic range; `subnets` are where it may be extended to
+pub(crate) fn pool_usage(
+    range_start: &str,
+    range_end: &str,
+    lease_time: &str,
+    gateway: Option<std::net::Ipv4Addr>,
+    subnets: &[(std::net::Ipv4Addr, u32)],
+) -> Option<PoolStatus> {
+    use std::net::Ipv4Addr;
+
+    let start = u32::from(range_start.parse::<Ipv4Addr>().ok()?);
+    let end = u32::from(range_end.parse::<Ipv4Addr>().ok()?);
+    if end < start {
+        return None;
+    }
+    let size = end - start + 1;
+    let in_range = |ip: &str| ip.parse::<Ipv4Addr>().is_ok_and(|ip| (start..=end).contains(&u32::from(ip)));
+
+    let now = chrono::Utc::now().timestamp();
+    let mut used: std::collections::HashSet<String> = load_static_leases()
+        .into_iter()
+        .map(|l| l.ip_address)
+        .filter(|ip| in_range(ip))
+        .collect();
+    let mut expiring_soon = 0;
+    for line in fs::read_to_string(DNSMASQ_LEASES).unwrap_or_default().lines() {
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        let (Some(expires), Some(ip)) = (parts.first().and_then(|e| e.parse::<i64>().ok()), parts.get(2)) else {
+            continue;
+        };
+        // 0 is an infinite lease
+        if (expires != 0 && expires <= now) || !in_range(ip) {
+            continue;
+        }
+        if expires != 0 && expires - now <= 3600 {
+            expiring_soon += 1;
+        }
+        used.insert(ip.to_string());
+    }
+
+    let used = (used.len() as u32).min(size);
+    let utilization = (used as f64 * 1000.0 / size as f64).round() / 10.0;
+    let level = pool_level(utilization);
+
+    let mut suggestions = Vec::new();
+    if level != PoolLevel::Ok {
+        match range_extension(start, end, gateway.map(u32::from), subnets) {
+            Some((new_start, new_end)) => suggestions.push(format!(
+                "Extend the range to {} - {} ({} more addresses)",
+                Ipv4Addr::from(new_start),
+                Ipv4Addr::from(new_end),
+                (new_end - new_start + 1) - size
+            )),
+            None => suggestions.push("The range already spans the LAN subnet; a larger subnet is needed for more clients".to_string()),
+        }
+        if lease_secs(lease_time).is_none_or(|secs| secs > SHORT_LEASE_SECS) {
+            suggestions.push(format!(
+                "Shorten the lease time from {} to 2h so addresses of departed devices free up sooner",
+                lease_time
+            ));
+        }
+    }
+
+    Some(PoolStatus {
+        range_start: range_start.to_string(),
+        range_end: range_end.to_string(),
+        size,
+        used,
+        free: size - used,
+        utilization,
+        level,
+        lease_time: lease_time.to_string(),
+        expiring_soon,
+        suggestions,
+    })
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PoolHistoryQuery {
+    pub hours: Option<u32>,
 }
 
-pub async fn wifi_status() -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+/// Current pool usage with its recent history and peaks
+pub async fn dhcp_pool(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<PoolHistoryQuery>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
     if mock::is_mock_mode() {
-        return Ok(Json(mock::network::wifi_status()));
+        return Ok(Json(mock::network::dhcp_pool()));
     }
 
-    let content = fs::read_to_string(HOSTAPD_CONF).unwrap_or_default();
+    let hours = query.hours.unwrap_or(24).clamp(1, 24 * 30);
+    let history = crate::dhcp::history(&state.db, hours)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let peak_7d = crate::dhcp::peak(&state.db, 24 * 7)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
-    let mut config = WifiConfig {
-        enabled: true,
-        ssid: String::new(),
-        password: String::new(),
-        channel: 1,
-        hw_mode: "g".to_string(),
-        security: "WPA2".to_string(),
-        hidden: false,
-        country_code: "US".to_string(),
+    Ok(Json(serde_json::json!({
+        "current": pool_status(),
+        "peak": history.iter().max_by_key(|s| s.used),
+        "peak_7d": peak_7d,
+        "history": history,
+    })))
+}
+
+// ============ DHCP OPTIONS ============
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OptionKind {
+    IpList,
+    Text,
+    Number,
+}
+
+/// Options with a well-known meaning; anything else is sent as written
+#[derive(Debug, Serialize)]
+pub struct KnownDhcpOption {
+    pub code: u8,
+    // dnsmasq's name, usable as option:<name>
+    pub name: Option<&'static str>,
+    pub description: &'static str,
+    pub kind: OptionKind,
+}
+
+const KNOWN_DHCP_OPTIONS: &[KnownDhcpOption] = &[
+    KnownDhcpOption { code: 3, name: Some("router"), description: "Default gateway", kind: OptionKind::IpList },
+    KnownDhcpOption { code: 6, name: Some("dns-server"), description: "DNS servers", kind: OptionKind::IpList },
+    KnownDhcpOption { code: 15, name: Some("domain-name"), description: "Domain name", kind: OptionKind::Text },
+    KnownDhcpOption { code: 26, name: Some("mtu"), description: "Interface MTU", kind: OptionKind::Number },
+    KnownDhcpOption { code: 42, name: Some("ntp-server"), description: "NTP servers", kind: OptionKind::IpList },
+    KnownDhcpOption { code: 44, name: Some("netbios-ns"), description: "WINS servers", kind: OptionKind::IpList },
+    KnownDhcpOption { code: 66, name: Some("tftp-server"), description: "TFTP server name (phone provisioning, PXE)", kind: OptionKind::Text },
+    KnownDhcpOption { code: 67, name: Some("bootfile-name"), description: "Boot file name", kind: OptionKind::Text },
+    KnownDhcpOption { code: 119, name: Some("domain-search"), description: "DNS search domains", kind: OptionKind::Text },
+    KnownDhcpOption { code: 120, name: Some("sip-server"), description: "SIP servers", kind: OptionKind::Text },
+    KnownDhcpOption { code: 150, name: None, description: "TFTP server addresses (Cisco phones)", kind: OptionKind::IpList },
+];
+
+/// One dhcp-option / dhcp-option-force line
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DhcpOption {
+    // Only sent to clients carrying this tag; None sends it to everyone
+    #[serde(default)]
+    pub tag: Option<String>,
+    // Vendor class for vendor-encapsulated options (option 43)
+    #[serde(default)]
+    pub vendor: Option<String>,
+    // Option number, or a dnsmasq option name
+    pub option: String,
+    #[serde(default)]
+    pub value: String,
+    // dhcp-option-force: send even when the client does not ask for it
+    #[serde(default)]
+    pub force: bool,
+}
+
+/// Sets a tag on matching clients, for options scoped to that tag
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DhcpTagRule {
+    pub tag: String,
+    // mac, vendor_class or user_class
+    pub match_type: String,
+    // MAC address (* wildcards allowed) or class string prefix
+    pub value: String,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct DhcpOptions {
+    #[serde(default)]
+    pub options: Vec<DhcpOption>,
+    #[serde(default)]
+    pub tag_rules: Vec<DhcpTagRule>,
+}
+
+const TAG_RULE_DIRECTIVES: &[(&str, &str)] = &[
+    ("mac", "dhcp-mac"),
+    ("vendor_class", "dhcp-vendorclass"),
+    ("user_class", "dhcp-userclass"),
+];
+
+fn valid_tag(tag: &str) -> bool {
+    !tag.is_empty() && tag.len() <= 32 && tag.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+fn known_option(option: &str) -> Option<&'static KnownDhcpOption> {
+    match option.parse::<u8>() {
+        Ok(code) => KNOWN_DHCP_OPTIONS.iter().find(|o| o.code == code),
+        Err(_) => KNOWN_DHCP_OPTIONS.iter().find(|o| o.name == Some(option)),
+    }
+}
+
+fn parse_dhcp_option(line: &str) -> Option<DhcpOption> {
+    let (force, rest) = if let Some(rest) = line.strip_prefix("dhcp-option-force=") {
+        (true, rest)
+    } else {
+        (false, line.strip_prefix("dhcp-option=")?)
     };
 
-    for line in content.lines() {
-        let line = line.trim();
-        if let Some((key, value)) = line.split_once('=') {
-            match key {
-                "ssid" => config.ssid = value.to_string(),
-                "wpa_passphrase" => config.password = value.to_string(),
-                "channel" => config.channel = value.parse().unwrap_or(1),
-                "hw_mode" => config.hw_mode = value.to_string(),
-                "wpa" => {
-                    config.security = match value {
-                        "1" => "WPA",
-                        "2" => "WPA2",
-                        "3" => "WPA/WPA2",
-                        _ => "WPA2",
-                    }.to_string();
-                }
-                "ignore_broadcast_ssid" => config.hidden = value == "1",
-                "country_code" => config.country_code = value.to_string(),
-                _ => {}
+    let mut tag = None;
+    let mut vendor = None;
+    let mut parts = rest.splitn(2, ',');
+    let mut token = parts.next()?.trim();
+    let mut remainder = parts.next().unwrap_or("");
+    loop {
+        if let Some(t) = token.strip_prefix("tag:") {
+            tag = Some(t.to_string());
+        } else if let Some(v) = token.strip_prefix("vendor:") {
+            vendor = Some(v.to_string());
+        } else {
+            break;
+        }
+        let mut parts = remainder.splitn(2, ',');
+        token = parts.next()?.trim();
+        remainder = parts.next().unwrap_or("");
+    }
+
+    Some(DhcpOption {
+        tag,
+        vendor,
+        option: token.strip_prefix("option:").unwrap_or(token).to_string(),
+        value: remainder.trim().to_string(),
+        force,
+    })
+}
+
+fn format_dhcp_option(option: &DhcpOption) -> String {
+    let mut fields = Vec::new();
+    if let Some(tag) = &option.tag {
+        fields.push(format!("tag:{}", tag));
+    }
+    if let Some(vendor) = &option.vendor {
+        fields.push(format!("vendor:{}", vendor));
+    }
+    fields.push(if option.option.parse::<u8>().is_ok() {
+        option.option.clone()
+    } else {
+        format!("option:{}", option.option)
+    });
+    if !option.value.is_empty() {
+        fields.push(option.value.clone());
+    }
+    let directive = if option.force { "dhcp-option-force" } else { "dhcp-option" };
+    format!("{}={}", directive, fields.join(","))
+}
+
+fn parse_tag_rule(line: &str) -> Option<DhcpTagRule> {
+    let (key, rest) = line.split_once('=')?;
+    let (match_type, _) = TAG_RULE_DIRECTIVES.iter().find(|(_, directive)| *directive == key)?;
+    let (tag, value) = rest.split_once(',')?;
+    Some(DhcpTagRule {
+        tag: tag.trim().strip_prefix("set:")?.to_string(),
+        match_type: match_type.to_string(),
+        value: value.trim().to_string(),
+    })
+}
+
+fn validate_dhcp_option(option: &DhcpOption, tags: &[&str]) -> Result<(), String> {
+    if let Some(tag) = &option.tag {
+        if !valid_tag(tag) {
+            return Err(format!("Invalid tag: {}", tag));
+        }
+        if !tags.contains(&tag.as_str()) {
+            return Err(format!("No tag rule sets the tag {}", tag));
+        }
+    }
+    if let Some(vendor) = &option.vendor {
+        if vendor.is_empty() || vendor.contains(',') || vendor.chars().any(|c| c.is_control()) {
+            return Err(format!("Invalid vendor class: {}", vendor));
+        }
+    }
+    if option.value.chars().any(|c| c.is_control()) {
+        return Err(format!("Option {} value contains control characters", option.option));
+    }
+
+    // Vendor options have their own numbering
+    if option.vendor.is_some() {
+        return option
+            .option
+            .parse::<u8>()
+            .map(|_| ())
+            .map_err(|_| format!("Vendor option must be a number: {}", option.option));
+    }
+
+    let known = known_option(&option.option);
+    match option.option.parse::<u8>() {
+        Ok(0) | Ok(255) => return Err(format!("Option {} is reserved", option.option)),
+        Ok(_) => {}
+        Err(_) if known.is_none() => return Err(format!("Unknown option name: {}", option.option)),
+        Err(_) => {}
+    }
+    let Some(known) = known else { return Ok(()) };
+
+    // The gateway and DNS server for everyone come from the DHCP settings
+    if option.tag.is_none() && matches!(known.code, 3 | 6) {
+        return Err(format!("Option {} is set in the DHCP settings; scope it to a tag to override it", known.code));
+    }
+    if option.value.is_empty() {
+        return Ok(());
+    }
+    let valid = match known.kind {
+        OptionKind::IpList => option.value.split(',').all(|ip| ip.trim().parse::<std::net::Ipv4Addr>().is_ok()),
+        OptionKind::Number => option.value.trim().parse::<u32>().is_ok(),
+        OptionKind::Text => true,
+    };
+    if !valid {
+        let expected = match known.kind {
+            OptionKind::IpList => "comma-separated IPv4 addresses",
+            OptionKind::Number => "a number",
+            OptionKind::Text => "text",
+        };
+        return Err(format!("Option {} ({}) expects {}", option.option, known.description, expected));
+    }
+    Ok(())
+}
+
+fn validate_tag_rule(rule: &DhcpTagRule) -> Result<(), String> {
+    if !valid_tag(&rule.tag) {
+        return Err(format!("Invalid tag: {}", rule.tag));
+    }
+    if !TAG_RULE_DIRECTIVES.iter().any(|(t, _)| *t == rule.match_type) {
+        return Err(format!("Invalid match type: {}", rule.match_type));
+    }
+    let value = rule.value.trim();
+    let valid = if rule.match_type == "mac" {
+        let octets: Vec<&str> = value.split(':').collect();
+        octets.len() == 6
+            && octets.iter().all(|o| *o == "*" || (o.len() == 2 && o.chars().all(|c| c.is_ascii_hexdigit())))
+    } else {
+        !value.is_empty() && !value.contains(',') && !value.chars().any(|c| c.is_control())
+    };
+    if !valid {
+        return Err(format!("Invalid {} match: {}", rule.match_type, rule.value));
+    }
+    Ok(())
+}
+
+fn load_dhcp_options() -> DhcpOptions {
+    let content = fs::read_to_string(DHCP_OPTIONS_FILE).unwrap_or_default();
+    let mut config = DhcpOptions::default();
+    for line in content.lines().map(str::trim) {
+        if let Some(option) = parse_dhcp_option(line) {
+            config.options.push(option);
+        } else if let Some(rule) = parse_tag_rule(line) {
+            config.tag_rules.push(rule);
+        }
+    }
+    config
+}
+
+fn save_dhcp_options(config: &DhcpOptions) -> Result<(), (StatusCode, String)> {
+    let mut content = String::from("# Extra DHCP options - managed by RouterUI\n");
+    for (match_type, directive) in TAG_RULE_DIRECTIVES {
+        for rule in config.tag_rules.iter().filter(|r| r.match_type == *match_type) {
+            content.push_str(&format!("{}=set:{},{}\n", directive, rule.tag, rule.value.trim()));
+        }
+    }
+    for option in &config.options {
+        content.push_str(&format_dhcp_option(option));
+        content.push('\n');
+    }
+
+    let previous = fs::read_to_string(DHCP_OPTIONS_FILE).ok();
+    write_system_file(DHCP_OPTIONS_FILE, &content)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    // Let dnsmasq have the final say on syntax before it reloads with the file
+    if let Ok(output) = Command::new("dnsmasq").arg("--test").output() {
+        if !output.status.success() {
+            let _ = write_system_file(DHCP_OPTIONS_FILE, previous.as_deref().unwrap_or(""));
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!("dnsmasq rejected the options: {}", String::from_utf8_lossy(&output.stderr).trim()),
+            ));
+        }
+    }
+
+    let _ = sudo()
+        .args(["systemctl", "reload", "dnsmasq"])
+        .output();
+
+    Ok(())
+}
+
+pub async fn dhcp_options() -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let config = if mock::is_mock_mode() {
+        serde_json::from_value(mock::network::dhcp_options()).unwrap_or_default()
+    } else {
+        load_dhcp_options()
+    };
+
+    Ok(Json(serde_json::json!({
+        "options": config.options,
+        "tag_rules": config.tag_rules,
+        "known_options": KNOWN_DHCP_OPTIONS,
+    })))
+}
+
+/// Replace the whole set of extra options and tag rules
+pub async fn update_dhcp_options(
+    AuthUser(user): AuthUser,
+    Json(mut payload): Json<DhcpOptions>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    require_permission(&user, "dhcp:write").map_err(|(s, m)| (s, m.to_string()))?;
+
+    for rule in &mut payload.tag_rules {
+        rule.tag = rule.tag.trim().to_string();
+        rule.value = rule.value.trim().to_string();
+        validate_tag_rule(rule).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    }
+    let tags: Vec<&str> = payload.tag_rules.iter().map(|r| r.tag.as_str()).collect();
+    for option in &mut payload.options {
+        option.option = option.option.trim().to_string();
+        option.value = option.value.trim().to_string();
+        validate_dhcp_option(option, &tags).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+        if option.vendor.is_none() && known_option(&option.option).is_some_and(|k| k.kind == OptionKind::IpList) {
+            option.value = option.value.split(',').map(str::trim).collect::<Vec<_>>().join(",");
+        }
+    }
+    // 42 and ntp-server are the same option
+    let code = |o: &DhcpOption| known_option(&o.option).filter(|_| o.vendor.is_none()).map(|k| k.code.to_string());
+    let duplicate = payload.options.iter().enumerate().find(|(i, a)| {
+        payload.options[..*i].iter().any(|b| {
+            a.tag == b.tag && a.vendor == b.vendor && code(a).unwrap_or_else(|| a.option.clone()) == code(b).unwrap_or_else(|| b.option.clone())
+        })
+    });
+    if let Some((_, option)) = duplicate {
+        return Err((StatusCode::BAD_REQUEST, format!("Option {} is listed twice for the same clients", option.option)));
+    }
+
+    if mock::is_mock_mode() {
+        return Ok(Json(serde_json::json!({"success": true, "mock": true})));
+    }
+
+    save_dhcp_options(&payload)?;
+
+    Ok(Json(serde_json::json!({"success": true})))
+}
+
+// ============ WIFI ============
+
+/// One hostapd instance. HOSTAPD_CONF runs as hostapd.service; any other
+/// /etc/hostapd/<name>.conf runs as hostapd@<name>.service (dual-band boxes, USB radios).
+#[derive(Debug, Clone)]
+pub struct Radio {
+    pub name: String,
+    pub config: String,
+    pub unit: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RadioQuery {
+    pub radio: Option<String>,
+}
+
+fn valid_radio_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 32
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-'))
+}
+
+fn radio_named(name: &str) -> Radio {
+    if name == DEFAULT_RADIO {
+        return Radio { name: name.to_string(), config: HOSTAPD_CONF.to_string(), unit: "hostapd".to_string() };
+    }
+    Radio {
+        name: name.to_string(),
+        config: format!("{}/{}.conf", HOSTAPD_DIR, name),
+        unit: format!("hostapd@{}", name),
+    }
+}
+
+/// The default radio first, then every other hostapd config that names an interface
+pub(crate) fn radios() -> Vec<Radio> {
+    let mut names: Vec<String> = fs::read_dir(HOSTAPD_DIR)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| entry.file_name().to_str()?.strip_suffix(".conf").map(|n| n.to_string()))
+        .filter(|name| name != DEFAULT_RADIO && valid_radio_name(name))
+        .filter(|name| {
+            fs::read_to_string(radio_named(name).config).is_ok_and(|c| main_option(&c, "interface").is_some())
+        })
+        .collect();
+    names.sort();
+
+    std::iter::once(radio_named(DEFAULT_RADIO))
+        .chain(names.iter().map(|n| radio_named(n)))
+        .collect()
+}
+
+/// Config path for a radio restored from a backup; None for names that aren't plain file stems
+pub(crate) fn radio_config_path(name: &str) -> Option<String> {
+    valid_radio_name(name).then(|| radio_named(name).config)
+}
+
+fn find_radio(name: Option<&str>) -> Result<Radio, (StatusCode, String)> {
+    let name = name.filter(|n| !n.is_empty()).unwrap_or(DEFAULT_RADIO);
+    radios()
+        .into_iter()
+        .find(|r| r.name == name)
+        .ok_or_else(|| (StatusCode::NOT_FOUND, format!("Unknown radio: {}", name)))
+}
+
+fn unit_active(unit: &str) -> bool {
+    Command::new("systemctl")
+        .args(["is-active", unit])
+        .output()
+        .map(|o| String::from_utf8_lossy(&o.stdout).trim() == "active")
+        .unwrap_or(false)
+}
+
+fn band(hw_mode: &str) -> &'static str {
+    if hw_mode == "a" { "5GHz" } else { "2.4GHz" }
+}
+
+#[derive(Debug, Serialize)]
+pub struct WifiConfig {
+    pub radio: String,
+    pub interface: String,
+    pub enabled: bool,
+    pub ssid: String,
+    pub password: String,
+    pub channel: u32,
+    pub hw_mode: String,
+    pub security: String,
+    pub hidden: bool,
+    pub country_code: String,
+}
+
+pub async fn wifi_status(Query(query): Query<RadioQuery>) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    if mock::is_mock_mode() {
+        return Ok(Json(mock::network::wifi_status()));
+    }
+
+    let radio = find_radio(query.radio.as_deref())?;
+    let content = fs::read_to_string(&radio.config).unwrap_or_default();
+
+    let mut config = WifiConfig {
+        radio: radio.name.clone(),
+        interface: String::new(),
+        enabled: true,
+        ssid: String::new(),
+        password: String::new(),
+        channel: 1,
+        hw_mode: "g".to_string(),
+        security: "WPA2".to_string(),
+        hidden: false,
+        country_code: "US".to_string(),
+    };
+
+    // Settings after the first bss= belong to additional SSIDs such as the guest network
+    for line in content.lines().take_while(|l| !is_bss_line(l)) {
+        let line = line.trim();
+        if let Some((key, value)) = line.split_once('=') {
+            match key {
+                "interface" => config.interface = value.to_string(),
+                "ssid" => config.ssid = value.to_string(),
+                "wpa_passphrase" => config.password = value.to_string(),
+                "channel" => config.channel = value.parse().unwrap_or(1),
+                "hw_mode" => config.hw_mode = value.to_string(),
+                "wpa" => {
+                    config.security = match value {
+                        "1" => "WPA",
+                        "2" => "WPA2",
+                        "3" => "WPA/WPA2",
+                        _ => "WPA2",
+                    }.to_string();
+                }
+                "ignore_broadcast_ssid" => config.hidden = value == "1",
+                "country_code" => config.country_code = value.to_string(),
+                _ => {}
+            }
+        }
+    }
+
+    // Check if hostapd is running
+    config.enabled = unit_active(&radio.unit);
+
+    Ok(Json(serde_json::to_value(config).unwrap()))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateWifiConfig {
+    pub radio: Option<String>,
+    pub ssid: Option<String>,
+    pub password: Option<String>,
+    pub channel: Option<u32>,
+    pub hidden: Option<bool>,
+}
+
+pub async fn update_wifi(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<UpdateWifiConfig>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    if mock::is_mock_mode() {
+        return Ok(Json(serde_json::json!({"success": true, "mock": true})));
+    }
+
+    let radio = find_radio(payload.radio.as_deref())?;
+    let content = fs::read_to_string(&radio.config)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let mut new_content = String::new();
+    let mut in_main_section = true;
+
+    for line in content.lines() {
+        let line_trimmed = line.trim();
+        in_main_section &= !is_bss_line(line);
+
+        if !in_main_section {
+            new_content.push_str(line);
+            new_content.push('\n');
+            continue;
+        }
+
+        if let Some(ref ssid) = payload.ssid {
+            if line_trimmed.starts_with("ssid=") {
+                new_content.push_str(&format!("ssid={}\n", ssid));
+                continue;
+            }
+        }
+
+        if let Some(ref password) = payload.password {
+            if line_trimmed.starts_with("wpa_passphrase=") {
+                new_content.push_str(&format!("wpa_passphrase={}\n", password));
+                continue;
+            }
+        }
+
+        if let Some(channel) = payload.channel {
+            if line_trimmed.starts_with("channel=") {
+                new_content.push_str(&format!("channel={}\n", channel));
+                continue;
+            }
+        }
+
+        if let Some(hidden) = payload.hidden {
+            if line_trimmed.starts_with("ignore_broadcast_ssid=") {
+                new_content.push_str(&format!("ignore_broadcast_ssid={}\n", if hidden { "1" } else { "0" }));
+                continue;
+            }
+        }
+
+        new_content.push_str(line);
+        new_content.push('\n');
+    }
+
+    // Write config
+    write_system_file(&radio.config, &new_content)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    // Restart hostapd
+    sudo()
+        .args(["systemctl", "restart", &radio.unit])
+        .output()
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    // Adopted APs broadcast the default radio's network
+    if radio.name == DEFAULT_RADIO {
+        crate::mesh::spawn_sync_all(state.db.clone());
+    }
+
+    Ok(Json(serde_json::json!({"success": true})))
+}
+
+pub async fn toggle_wifi(
+    Json(payload): Json<serde_json::Value>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let enabled = payload.get("enabled").and_then(|v| v.as_bool()).unwrap_or(true);
+
+    if mock::is_mock_mode() {
+        return Ok(Json(serde_json::json!({"success": true, "enabled": enabled, "mock": true})));
+    }
+
+    let radio = find_radio(payload.get("radio").and_then(|v| v.as_str()))?;
+    let action = if enabled { "start" } else { "stop" };
+
+    sudo()
+        .args(["systemctl", action, &radio.unit])
+        .output()
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(serde_json::json!({"success": true, "enabled": enabled})))
+}
+
+// ============ WIFI RADIOS ============
+
+#[derive(Debug, Serialize)]
+pub struct RadioStatus {
+    pub name: String,
+    pub interface: String,
+    pub ssid: String,
+    pub channel: u32,
+    pub band: String,
+    pub enabled: bool,
+    pub clients: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WifiClient {
+    pub mac_address: String,
+    pub radio: String,
+    pub interface: String,
+    pub ssid: String,
+    pub band: String,
+    pub ip_address: Option<String>,
+    pub hostname: Option<String>,
+    pub signal_dbm: Option<i32>,
+    pub tx_bitrate: Option<String>,
+    pub rx_bitrate: Option<String>,
+    pub connected_secs: Option<u64>,
+    pub rx_bytes: u64,
+    pub tx_bytes: u64,
+}
+
+// The radio's own interface plus any extra (guest) BSS that is switched on, with their SSIDs
+fn radio_interfaces(content: &str) -> Vec<(String, String)> {
+    let Some(main) = main_option(content, "interface") else { return Vec::new() };
+    let mut out = vec![(main.to_string(), main_option(content, "ssid").unwrap_or_default().to_string())];
+
+    // A guest block switched off by its schedule is commented out, so its keys don't match
+    for line in content.lines().map(|l| l.trim()).skip_while(|l| !is_bss_line(l)) {
+        match line.split_once('=') {
+            Some(("bss", iface)) => out.push((iface.to_string(), String::new())),
+            Some(("ssid", ssid)) if out.len() > 1 => {
+                if let Some(last) = out.last_mut() {
+                    last.1 = ssid.to_string();
+                }
+            }
+            _ => {}
+        }
+    }
+    out
+}
+
+// `iw dev <iface> station dump`: a "Station <mac> (on <iface>)" line, then indented fields
+fn station_dump(interface: &str) -> Vec<(String, HashMap<String, String>)> {
+    let output = Command::new("iw").args(["dev", interface, "station", "dump"]).output();
+    let stdout = output.map(|o| String::from_utf8_lossy(&o.stdout).to_string()).unwrap_or_default();
+
+    let mut stations: Vec<(String, HashMap<String, String>)> = Vec::new();
+    for line in stdout.lines() {
+        if let Some(rest) = line.strip_prefix("Station ") {
+            let mac = rest.split_whitespace().next().unwrap_or_default().to_lowercase();
+            stations.push((mac, HashMap::new()));
+        } else if let (Some((_, fields)), Some((key, value))) = (stations.last_mut(), line.trim().split_once(':')) {
+            fields.insert(key.trim().to_string(), value.trim().to_string());
+        }
+    }
+    stations
+}
+
+pub(crate) fn wifi_clients() -> Vec<WifiClient> {
+    let leases = parse_dhcp_leases().unwrap_or_default();
+    let mut clients = Vec::new();
+
+    for radio in radios() {
+        let content = fs::read_to_string(&radio.config).unwrap_or_default();
+        let band = band(main_option(&content, "hw_mode").unwrap_or("g"));
+
+        for (interface, ssid) in radio_interfaces(&content) {
+            for (mac, fields) in station_dump(&interface) {
+                let lease = leases.iter().find(|l| l.mac_address.to_lowercase() == mac);
+                let number = |key: &str| fields.get(key).and_then(|v| v.split_whitespace().next()?.parse::<u64>().ok());
+                clients.push(WifiClient {
+                    radio: radio.name.clone(),
+                    interface: interface.clone(),
+                    ssid: ssid.clone(),
+                    band: band.to_string(),
+                    ip_address: lease.map(|l| l.ip_address.clone()),
+                    hostname: lease.map(|l| l.hostname.clone()).filter(|h| h != "*"),
+                    signal_dbm: fields.get("signal").and_then(|v| v.split_whitespace().next()?.parse().ok()),
+                    tx_bitrate: fields.get("tx bitrate").cloned(),
+                    rx_bitrate: fields.get("rx bitrate").cloned(),
+                    connected_secs: number("connected time"),
+                    rx_bytes: number("rx bytes").unwrap_or(0),
+                    tx_bytes: number("tx bytes").unwrap_or(0),
+                    mac_address: mac,
+                });
+            }
+        }
+    }
+    clients
+}
+
+pub async fn wifi_radios() -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    if mock::is_mock_mode() {
+        return Ok(Json(mock::network::wifi_radios()));
+    }
+
+    let clients = wifi_clients();
+    let radios: Vec<RadioStatus> = radios()
+        .into_iter()
+        .map(|radio| {
+            let content = fs::read_to_string(&radio.config).unwrap_or_default();
+            RadioStatus {
+                interface: main_option(&content, "interface").unwrap_or_default().to_string(),
+                ssid: main_option(&content, "ssid").unwrap_or_default().to_string(),
+                channel: main_option(&content, "channel").and_then(|c| c.parse().ok()).unwrap_or(0),
+                band: band(main_option(&content, "hw_mode").unwrap_or("g")).to_string(),
+                enabled: unit_active(&radio.unit),
+                clients: clients.iter().filter(|c| c.radio == radio.name).count(),
+                name: radio.name,
+            }
+        })
+        .collect();
+
+    Ok(Json(serde_json::to_value(radios).unwrap()))
+}
+
+/// Stations associated with any radio or SSID, with their DHCP lease where there is one
+pub async fn wifi_clients_list() -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    if mock::is_mock_mode() {
+        return Ok(Json(mock::network::wifi_clients()));
+    }
+
+    Ok(Json(serde_json::to_value(wifi_clients()).unwrap()))
+}
+
+// ============ WIFI ADVANCED ============
+
+// hostapd keeps a push-button session open for its fixed two-minute walk time
+const WPS_WALK_TIME_SECS: u32 = 120;
+
+#[derive(Debug, Serialize)]
+pub struct WifiAdvanced {
+    pub interface: String,
+    pub wps: bool,
+    pub fast_transition: bool,
+    pub mobility_domain: Option<String>,
+    // Interface of the 5GHz radio clients are steered to, when this is the 2.4GHz one
+    pub band_steering: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateWifiAdvanced {
+    pub radio: Option<String>,
+    pub wps: Option<bool>,
+    pub fast_transition: Option<bool>,
+    // Four hex digits shared by every AP clients should roam between
+    pub mobility_domain: Option<String>,
+    // Empty string switches steering off
+    pub band_steering: Option<String>,
+}
+
+fn main_section(content: &str) -> impl Iterator<Item = (&str, &str)> {
+    content
+        .lines()
+        .take_while(|l| !is_bss_line(l))
+        .filter_map(|l| l.trim().split_once('='))
+}
+
+fn main_option<'a>(content: &'a str, key: &str) -> Option<&'a str> {
+    main_section(content).find(|(k, _)| *k == key).map(|(_, v)| v)
+}
+
+/// Set (Some) or remove (None) keys in the main section of hostapd.conf; new keys go at the
+/// end of that section so they never land in a guest BSS
+fn set_main_options(content: &str, options: &[(&str, Option<String>)]) -> String {
+    let mut new_content = String::new();
+    let mut written: Vec<&str> = Vec::new();
+    let mut in_main_section = true;
+
+    let append_missing = |out: &mut String, written: &[&str]| {
+        for (key, value) in options {
+            if let (Some(value), false) = (value, written.contains(key)) {
+                out.push_str(&format!("{}={}\n", key, value));
+            }
+        }
+    };
+
+    for line in content.lines() {
+        if in_main_section && is_bss_line(line) {
+            in_main_section = false;
+            append_missing(&mut new_content, &written);
+        }
+        if in_main_section {
+            let key = line.trim().split_once('=').map(|(k, _)| k);
+            if let Some((key, value)) = options.iter().find(|(k, _)| Some(*k) == key) {
+                // Later duplicates of a managed key are dropped
+                if let (Some(value), false) = (value, written.contains(key)) {
+                    new_content.push_str(&format!("{}={}\n", key, value));
+                    written.push(key);
+                }
+                continue;
+            }
+        }
+        new_content.push_str(line);
+        new_content.push('\n');
+    }
+    if in_main_section {
+        append_missing(&mut new_content, &written);
+    }
+    new_content
+}
+
+// Stable per SSID, so two RouterUI APs with the same network agree without configuration
+fn default_mobility_domain(ssid: &str) -> String {
+    let hash = ssid.bytes().fold(0x811c_9dc5_u32, |h, b| (h ^ b as u32).wrapping_mul(0x0100_0193));
+    format!("{:04x}", (hash ^ (hash >> 16)) & 0xffff)
+}
+
+fn wifi_advanced_from(content: &str) -> WifiAdvanced {
+    let key_mgmt = main_option(content, "wpa_key_mgmt").unwrap_or_default();
+    WifiAdvanced {
+        interface: main_option(content, "interface").unwrap_or("wlan0").to_string(),
+        wps: main_option(content, "wps_state").is_some_and(|v| v != "0"),
+        fast_transition: main_option(content, "ieee80211r") == Some("1")
+            && key_mgmt.split_whitespace().any(|m| m.starts_with("FT-")),
+        mobility_domain: main_option(content, "mobility_domain").map(|v| v.to_string()),
+        band_steering: main_option(content, "no_probe_resp_if_seen_on").map(|v| v.to_string()),
+    }
+}
+
+pub async fn wifi_advanced(Query(query): Query<RadioQuery>) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    if mock::is_mock_mode() {
+        return Ok(Json(mock::network::wifi_advanced()));
+    }
+
+    let radio = find_radio(query.radio.as_deref())?;
+    let content = fs::read_to_string(&radio.config).unwrap_or_default();
+    Ok(Json(serde_json::to_value(wifi_advanced_from(&content)).unwrap()))
+}
+
+pub async fn update_wifi_advanced(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    Json(payload): Json<UpdateWifiAdvanced>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    require_permission(&user, "wifi:write").map_err(|(s, m)| (s, m.to_string()))?;
+
+    if let Some(domain) = payload.mobility_domain.as_deref().filter(|d| !d.is_empty()) {
+        if domain.len() != 4 || !domain.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err((StatusCode::BAD_REQUEST, "Mobility domain must be 4 hex digits".to_string()));
+        }
+    }
+    if let Some(iface) = payload.band_steering.as_deref().filter(|i| !i.is_empty()) {
+        let valid = iface.len() <= 15 && iface.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'));
+        if !valid {
+            return Err((StatusCode::BAD_REQUEST, "Invalid interface name".to_string()));
+        }
+    }
+
+    if mock::is_mock_mode() {
+        return Ok(Json(serde_json::json!({"success": true, "mock": true})));
+    }
+
+    let radio = find_radio(payload.radio.as_deref())?;
+    let content = fs::read_to_string(&radio.config)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let current = wifi_advanced_from(&content);
+    let mut options: Vec<(&str, Option<String>)> = Vec::new();
+
+    if let Some(wps) = payload.wps {
+        // Push button only, with the AP PIN locked so the PIN brute-force attacks don't apply
+        let on = |v: &str| wps.then(|| v.to_string());
+        options.extend([
+            ("wps_state", on("2")),
+            ("eap_server", on("1")),
+            ("config_methods", on("push_button")),
+            ("ap_setup_locked", on("1")),
+        ]);
+    }
+
+    if payload.fast_transition.is_some() || payload.mobility_domain.is_some() {
+        let enabled = payload.fast_transition.unwrap_or(current.fast_transition);
+        let ssid = main_option(&content, "ssid").unwrap_or_default();
+        let domain = payload
+            .mobility_domain
+            .filter(|d| !d.is_empty())
+            .or(current.mobility_domain)
+            .unwrap_or_else(|| default_mobility_domain(ssid))
+            .to_lowercase();
+
+        // FT-PSK / FT-SAE alongside whatever key management is already configured
+        let mut key_mgmt: Vec<String> = main_option(&content, "wpa_key_mgmt")
+            .unwrap_or("WPA-PSK")
+            .split_whitespace()
+            .filter(|m| !m.starts_with("FT-"))
+            .map(|m| m.to_string())
+            .collect();
+        if enabled {
+            let ft: Vec<String> = key_mgmt
+                .iter()
+                .filter_map(|m| match m.as_str() {
+                    "WPA-PSK" => Some("FT-PSK".to_string()),
+                    "SAE" => Some("FT-SAE".to_string()),
+                    _ => None,
+                })
+                .collect();
+            if ft.is_empty() {
+                return Err((StatusCode::BAD_REQUEST, "Fast transition needs WPA-PSK or SAE security".to_string()));
+            }
+            key_mgmt.extend(ft);
+        }
+
+        let hostname = fs::read_to_string("/etc/hostname").map(|h| h.trim().to_string()).unwrap_or_default();
+        let on = |v: String| enabled.then_some(v);
+        options.extend([
+            ("ieee80211r", on("1".to_string())),
+            ("mobility_domain", on(domain)),
+            ("ft_over_ds", on("0".to_string())),
+            ("ft_psk_generate_local", on("1".to_string())),
+            ("nas_identifier", on(format!("{}-{}", hostname, current.interface))),
+            ("wpa_key_mgmt", Some(key_mgmt.join(" "))),
+        ]);
+    }
+
+    if let Some(target) = payload.band_steering {
+        let target = Some(target).filter(|t| !t.is_empty());
+        if target.is_some() && main_option(&content, "hw_mode").is_some_and(|m| m == "a") {
+            return Err((StatusCode::BAD_REQUEST, "Band steering is set on the 2.4GHz radio".to_string()));
+        }
+        // Stay quiet to probes from clients already seen on the 5GHz radio, and offer them
+        // 802.11v transitions
+        let on = |v: &str| target.is_some().then(|| v.to_string());
+        options.extend([
+            ("no_probe_resp_if_seen_on", target.clone()),
+            ("bss_transition", on("1")),
+            ("rrm_neighbor_report", on("1")),
+        ]);
+    }
+
+    if options.is_empty() {
+        return Ok(Json(serde_json::json!({"success": true})));
+    }
+
+    write_system_file(&radio.config, set_main_options(&content, &options))
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    sudo()
+        .args(["systemctl", "restart", &radio.unit])
+        .output()
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    // Fast roaming only works if every AP uses the same mobility domain
+    if radio.name == DEFAULT_RADIO {
+        crate::mesh::spawn_sync_all(state.db.clone());
+    }
+
+    Ok(Json(serde_json::json!({"success": true})))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct WpsRequest {
+    pub radio: Option<String>,
+    // "start" or "cancel"
+    pub action: String,
+}
+
+fn hostapd_cli(interface: &str, action: &str) -> Result<String, (StatusCode, String)> {
+    let output = sudo()
+        .args(["hostapd_cli", "-i", interface, action])
+        .output()
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let stdout = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if !output.status.success() || stdout.starts_with("FAIL") {
+        let detail = if stdout.is_empty() { String::from_utf8_lossy(&output.stderr).trim().to_string() } else { stdout };
+        return Err((StatusCode::INTERNAL_SERVER_ERROR, format!("hostapd_cli {}: {}", action, detail)));
+    }
+    Ok(stdout)
+}
+
+pub async fn wps_status(Query(query): Query<RadioQuery>) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    if mock::is_mock_mode() {
+        return Ok(Json(mock::network::wps_status()));
+    }
+
+    let radio = find_radio(query.radio.as_deref())?;
+    let content = fs::read_to_string(&radio.config).unwrap_or_default();
+    let advanced = wifi_advanced_from(&content);
+    if !advanced.wps {
+        return Ok(Json(serde_json::json!({"enabled": false, "pbc_status": null, "last_result": null})));
+    }
+
+    // "PBC Status: Active" / "Last WPS result: Success" / "Peer Address: ..."
+    let output = hostapd_cli(&advanced.interface, "wps_get_status")?;
+    let field = |name: &str| {
+        output
+            .lines()
+            .find_map(|l| l.strip_prefix(name))
+            .map(|v| v.trim().to_string())
+    };
+
+    Ok(Json(serde_json::json!({
+        "enabled": true,
+        "pbc_status": field("PBC Status:"),
+        "last_result": field("Last WPS result:"),
+        "peer_address": field("Peer Address:"),
+    })))
+}
+
+pub async fn wps_action(
+    AuthUser(user): AuthUser,
+    Json(payload): Json<WpsRequest>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    require_permission(&user, "wifi:write").map_err(|(s, m)| (s, m.to_string()))?;
+
+    let action = match payload.action.as_str() {
+        "start" => "wps_pbc",
+        "cancel" => "wps_cancel",
+        _ => return Err((StatusCode::BAD_REQUEST, "Action must be start or cancel".to_string())),
+    };
+
+    if mock::is_mock_mode() {
+        return Ok(Json(serde_json::json!({"success": true, "expires_in": WPS_WALK_TIME_SECS, "mock": true})));
+    }
+
+    let radio = find_radio(payload.radio.as_deref())?;
+    let content = fs::read_to_string(&radio.config).unwrap_or_default();
+    let advanced = wifi_advanced_from(&content);
+    if !advanced.wps {
+        return Err((StatusCode::BAD_REQUEST, "Enable WPS in the advanced WiFi settings first".to_string()));
+    }
+
+    hostapd_cli(&advanced.interface, action)?;
+
+    let expires_in = (action == "wps_pbc").then_some(WPS_WALK_TIME_SECS);
+    Ok(Json(serde_json::json!({"success": true, "expires_in": expires_in})))
+}
+
+// ============ WIFI SCHEDULE ============
+
+/// When the radio is on, and when the guest SSID is broadcast
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WifiSchedule {
+    #[serde(default)]
+    pub radio: Schedule,
+    #[serde(default)]
+    pub guest: Schedule,
+}
+
+fn is_bss_line(line: &str) -> bool {
+    let line = line.trim();
+    line.strip_prefix(GUEST_OFF_PREFIX.trim_end()).unwrap_or(line).trim_start().starts_with("bss=")
+}
+
+fn load_wifi_schedule() -> WifiSchedule {
+    fs::read_to_string(WIFI_SCHEDULE_FILE)
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+fn save_wifi_schedule(schedule: &WifiSchedule) -> Result<(), (StatusCode, String)> {
+    let json = serde_json::to_string_pretty(schedule)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    fs::write(WIFI_SCHEDULE_FILE, json)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(())
+}
+
+/// Interface of the guest network, if the default radio has one
+pub(crate) fn guest_interface() -> Option<String> {
+    guest_bss(&fs::read_to_string(HOSTAPD_CONF).unwrap_or_default()).map(|(interface, _, _)| interface)
+}
+
+/// The guest network is the first extra BSS in the default radio's hostapd.conf: its interface and SSID,
+/// and whether it is currently switched on
+fn guest_bss(content: &str) -> Option<(String, String, bool)> {
+    let mut lines = content.lines().skip_while(|l| !is_bss_line(l));
+    let first = lines.next()?.trim();
+    let enabled = !first.starts_with(GUEST_OFF_PREFIX.trim_end());
+    let iface = first.split_once("bss=")?.1.trim().to_string();
+
+    let ssid = lines
+        .take_while(|l| !is_bss_line(l))
+        .map(|l| l.trim().strip_prefix(GUEST_OFF_PREFIX).unwrap_or(l.trim()))
+        .find_map(|l| l.strip_prefix("ssid="))
+        .unwrap_or_default()
+        .to_string();
+    Some((iface, ssid, enabled))
+}
+
+// A key from the guest BSS block, while it is switched on
+fn guest_option<'a>(content: &'a str, key: &str) -> Option<&'a str> {
+    let mut block = content.lines().skip_while(|l| !is_bss_line(l));
+    block.next()?;
+    block
+        .take_while(|l| !is_bss_line(l))
+        .filter_map(|l| l.trim().split_once('='))
+        .find(|(k, _)| *k == key)
+        .map(|(_, v)| v)
+}
+
+/// The guest BSS as guest access codes see it
+#[derive(Debug, Clone)]
+pub(crate) struct GuestNetwork {
+    pub interface: String,
+    pub ssid: String,
+    pub enabled: bool,
+    pub passphrase: Option<String>,
+    pub psk_file: Option<String>,
+}
+
+pub(crate) fn guest_network() -> Option<GuestNetwork> {
+    let content = fs::read_to_string(HOSTAPD_CONF).ok()?;
+    let (interface, ssid, enabled) = guest_bss(&content)?;
+    // Read through the off marker, so a switched-off block still reports its keys
+    let block: Vec<&str> = content
+        .lines()
+        .skip_while(|l| !is_bss_line(l))
+        .skip(1)
+        .take_while(|l| !is_bss_line(l))
+        .map(|l| l.trim().strip_prefix(GUEST_OFF_PREFIX).unwrap_or(l.trim()))
+        .collect();
+    let option = |key: &str| {
+        block.iter().filter_map(|l| l.split_once('=')).find(|(k, _)| *k == key).map(|(_, v)| v.to_string())
+    };
+    Some(GuestNetwork { interface, ssid, enabled, passphrase: option("wpa_passphrase"), psk_file: option("wpa_psk_file") })
+}
+
+/// Set (Some) or remove (None) keys in the guest BSS block; new keys go at the end of the block,
+/// switched off along with it when it is off. None without a guest block.
+fn set_guest_options(content: &str, options: &[(&str, Option<String>)]) -> Option<String> {
+    let (_, _, enabled) = guest_bss(content)?;
+    let prefix = if enabled { "" } else { GUEST_OFF_PREFIX };
+    let mut new_content = String::new();
+    let mut written: Vec<&str> = Vec::new();
+    let mut bss_seen = 0;
+
+    let append_missing = |out: &mut String, written: &[&str]| {
+        for (key, value) in options {
+            if let (Some(value), false) = (value, written.contains(key)) {
+                out.push_str(&format!("{}{}={}\n", prefix, key, value));
+            }
+        }
+    };
+
+    // Blank lines at the end of the block are held back so new keys go above them
+    let mut blanks = 0;
+    for line in content.lines() {
+        if is_bss_line(line) {
+            bss_seen += 1;
+            if bss_seen == 2 {
+                append_missing(&mut new_content, &written);
+                new_content.push_str(&"\n".repeat(blanks));
+                blanks = 0;
+            }
+        }
+        if bss_seen == 1 {
+            if line.trim().is_empty() {
+                blanks += 1;
+                continue;
+            }
+            new_content.push_str(&"\n".repeat(blanks));
+            blanks = 0;
+            let body = line.trim().strip_prefix(GUEST_OFF_PREFIX).unwrap_or(line.trim());
+            let key = body.split_once('=').map(|(k, _)| k);
+            if let Some((key, value)) = options.iter().find(|(k, _)| Some(*k) == key) {
+                if let (Some(value), false) = (value, written.contains(key)) {
+                    new_content.push_str(&format!("{}{}={}\n", prefix, key, value));
+                    written.push(key);
+                }
+                continue;
+            }
+        }
+        new_content.push_str(line);
+        new_content.push('\n');
+    }
+    if bss_seen == 1 {
+        append_missing(&mut new_content, &written);
+        new_content.push_str(&"\n".repeat(blanks));
+    }
+    Some(new_content)
+}
+
+/// Change keys of the guest BSS and restart hostapd when anything changed; returns whether it did
+pub(crate) fn update_guest_options(options: &[(&str, Option<String>)]) -> Result<bool, String> {
+    let content = fs::read_to_string(HOSTAPD_CONF).map_err(|e| e.to_string())?;
+    let new_content = set_guest_options(&content, options).ok_or("No guest SSID configured")?;
+    if new_content == content {
+        return Ok(false);
+    }
+    write_system_file(HOSTAPD_CONF, &new_content).map_err(|e| e.to_string())?;
+    if unit_active("hostapd") {
+        sudo().args(["systemctl", "restart", "hostapd"]).output().map_err(|e| e.to_string())?;
+    }
+    Ok(true)
+}
+
+/// Have the guest BSS re-read its wpa_psk_file without dropping other clients
+pub(crate) fn reload_guest_psks(interface: &str) -> Result<(), String> {
+    if !unit_active("hostapd") {
+        return Ok(());
+    }
+    hostapd_cli(interface, "reload_wpa_psk").map(|_| ()).map_err(|(_, e)| e)
+}
+
+/// The default radio's network as adopted OpenWrt APs should broadcast it
+pub(crate) fn mesh_wifi_settings() -> Option<crate::mesh::WifiSettings> {
+    let content = fs::read_to_string(HOSTAPD_CONF).ok()?;
+    let ssid = main_option(&content, "ssid").filter(|s| !s.is_empty())?;
+
+    let key_mgmt: Vec<&str> = main_option(&content, "wpa_key_mgmt").unwrap_or("WPA-PSK").split_whitespace().collect();
+    let encryption = match (key_mgmt.contains(&"SAE"), key_mgmt.contains(&"WPA-PSK")) {
+        (true, true) => "sae-mixed",
+        (true, false) => "sae",
+        _ => "psk2",
+    };
+
+    let advanced = wifi_advanced_from(&content);
+    let guest = match guest_bss(&content) {
+        Some((_, guest_ssid, true)) => guest_option(&content, "wpa_passphrase").map(|key| (guest_ssid, key.to_string())),
+        _ => None,
+    };
+
+    Some(crate::mesh::WifiSettings {
+        ssid: ssid.to_string(),
+        passphrase: main_option(&content, "wpa_passphrase").unwrap_or_default().to_string(),
+        encryption: encryption.to_string(),
+        hidden: main_option(&content, "ignore_broadcast_ssid").is_some_and(|v| v != "0"),
+        mobility_domain: advanced.fast_transition.then_some(advanced.mobility_domain).flatten(),
+        guest,
+    })
+}
+
+// Comments out (or restores) the guest BSS block; returns the new file when something changed
+fn toggle_guest_block(content: &str, enabled: bool) -> Option<String> {
+    let (_, _, currently) = guest_bss(content)?;
+    if currently == enabled {
+        return None;
+    }
+
+    let mut new_content = String::new();
+    let mut bss_seen = 0;
+    for line in content.lines() {
+        if is_bss_line(line) {
+            bss_seen += 1;
+        }
+        // Only the first extra BSS is the guest network
+        if bss_seen == 1 {
+            if enabled {
+                new_content.push_str(line.strip_prefix(GUEST_OFF_PREFIX).unwrap_or(line));
+            } else if !line.trim().is_empty() {
+                new_content.push_str(GUEST_OFF_PREFIX);
+                new_content.push_str(line);
+            }
+        } else {
+            new_content.push_str(line);
+        }
+        new_content.push('\n');
+    }
+    Some(new_content)
+}
+
+// The radio schedule switches every hostapd instance together
+pub(crate) fn set_radio(enabled: bool) -> Result<(), String> {
+    let action = if enabled { "start" } else { "stop" };
+    for radio in radios() {
+        let output = sudo().args(["systemctl", action, &radio.unit]).output().map_err(|e| e.to_string())?;
+        if !output.status.success() {
+            return Err(format!("{}: {}", radio.unit, String::from_utf8_lossy(&output.stderr).trim()));
+        }
+    }
+    Ok(())
+}
+
+pub(crate) fn set_guest_ssid(enabled: bool) -> Result<(), String> {
+    let content = fs::read_to_string(HOSTAPD_CONF).map_err(|e| e.to_string())?;
+    let Some(new_content) = toggle_guest_block(&content, enabled) else { return Ok(()) };
+    write_system_file(HOSTAPD_CONF, &new_content).map_err(|e| e.to_string())?;
+
+    // A stopped radio picks the change up when it is next started
+    if unit_active("hostapd") {
+        sudo().args(["systemctl", "restart", "hostapd"]).output().map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+/// Driven by the scheduler every minute
+#[derive(Default)]
+pub struct WifiScheduler {
+    radio: Edge,
+    guest: Edge,
+}
+
+impl WifiScheduler {
+    pub async fn tick(&mut self, pool: &sqlx::SqlitePool, now: DateTime<Local>) {
+        let schedule = load_wifi_schedule();
+        let radio = self.radio.changed(schedule.radio.active_at(now));
+        let guest = self.guest.changed(schedule.guest.active_at(now));
+        if radio.is_none() && guest.is_none() {
+            return;
+        }
+
+        let result = tokio::task::spawn_blocking(move || {
+            // Rewrite the guest block before the radio comes up so it starts with the right SSIDs
+            if let Some(on) = guest {
+                tracing::info!("WiFi schedule: guest SSID {}", if on { "on" } else { "off" });
+                set_guest_ssid(on)?;
             }
+            if let Some(on) = radio {
+                tracing::info!("WiFi schedule: radio {}", if on { "on" } else { "off" });
+                set_radio(on)?;
+            }
+            Ok::<_, String>(())
+        })
+        .await
+        .unwrap_or_else(|e| Err(e.to_string()));
+
+        match result {
+            // Adopted APs follow the guest network on and off
+            Ok(()) if guest.is_some() => crate::mesh::spawn_sync_all(pool.clone()),
+            Ok(()) => {}
+            Err(e) => tracing::error!("WiFi schedule could not be applied: {}", e),
         }
     }
+}
 
-    // Check if hostapd is running
-    config.enabled = Command::new("systemctl")
-        .args(["is-active", "hostapd"])
-        .output()
-        .map(|o| String::from_utf8_lossy(&o.stdout).trim() == "active")
-        .unwrap_or(false);
-
-    Ok(Json(serde_json::to_value(config).unwrap()))
+pub(crate) fn schedule_state(schedule: &Schedule, now: DateTime<Local>) -> serde_json::Value {
+    serde_json::json!({
+        "active": schedule.active_at(now),
+        "next_change": schedule.next_change(now).map(|t| t.to_rfc3339()),
+    })
 }
 
-#[derive(Debug, Deserialize)]
-pub struct UpdateWifiConfig {
-    pub ssid: Option<String>,
-    pub password: Option<String>,
-    pub channel: Option<u32>,
-    pub hidden: Option<bool>,
+pub async fn wifi_schedule() -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    if mock::is_mock_mode() {
+        return Ok(Json(mock::network::wifi_schedule()));
+    }
+
+    let schedule = load_wifi_schedule();
+    let now = Local::now();
+    let guest = guest_bss(&fs::read_to_string(HOSTAPD_CONF).unwrap_or_default());
+
+    Ok(Json(serde_json::json!({
+        "schedule": schedule,
+        "radio": schedule_state(&schedule.radio, now),
+        "guest": schedule_state(&schedule.guest, now),
+        "guest_network": guest.map(|(interface, ssid, enabled)| serde_json::json!({
+            "interface": interface,
+            "ssid": ssid,
+            "enabled": enabled,
+        })),
+    })))
 }
 
-pub async fn update_wifi(
-    Json(payload): Json<UpdateWifiConfig>,
+pub async fn update_wifi_schedule(
+    AuthUser(user): AuthUser,
+    Json(payload): Json<WifiSchedule>,
 ) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    require_permission(&user, "wifi:write").map_err(|(s, m)| (s, m.to_string()))?;
+
+    payload.radio.validate().map_err(|e| (StatusCode::BAD_REQUEST, format!("Radio: {}", e)))?;
+    payload.guest.validate().map_err(|e| (StatusCode::BAD_REQUEST, format!("Guest SSID: {}", e)))?;
+
     if mock::is_mock_mode() {
         return Ok(Json(serde_json::json!({"success": true, "mock": true})));
     }
 
-    let content = fs::read_to_string(HOSTAPD_CONF)
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
-
-    let mut new_content = String::new();
+    if payload.guest.enabled && guest_bss(&fs::read_to_string(HOSTAPD_CONF).unwrap_or_default()).is_none() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "No guest SSID configured: add a bss= section to hostapd.conf first".to_string(),
+        ));
+    }
 
-    for line in content.lines() {
-        let line_trimmed = line.trim();
+    save_wifi_schedule(&payload)?;
 
-        if let Some(ref ssid) = payload.ssid {
-            if line_trimmed.starts_with("ssid=") {
-                new_content.push_str(&format!("ssid={}\n", ssid));
-                continue;
-            }
-        }
+    // The scheduler applies the new windows on its next tick
+    Ok(Json(serde_json::json!({"success": true})))
+}
 
-        if let Some(ref password) = payload.password {
-            if line_trimmed.starts_with("wpa_passphrase=") {
-                new_content.push_str(&format!("wpa_passphrase={}\n", password));
-                continue;
-            }
-        }
+// ============ GUEST ACCESS CODES ============
 
-        if let Some(channel) = payload.channel {
-            if line_trimmed.starts_with("channel=") {
-                new_content.push_str(&format!("channel={}\n", channel));
-                continue;
-            }
-        }
+#[derive(Debug, Serialize)]
+pub struct GuestCodeView {
+    #[serde(flatten)]
+    pub code: crate::guestcodes::GuestCode,
+    pub active: bool,
+    // Only while the code is active
+    pub qr: Option<String>,
+}
 
-        if let Some(hidden) = payload.hidden {
-            if line_trimmed.starts_with("ignore_broadcast_ssid=") {
-                new_content.push_str(&format!("ignore_broadcast_ssid={}\n", if hidden { "1" } else { "0" }));
-                continue;
-            }
-        }
+fn guest_code_view(code: crate::guestcodes::GuestCode, ssid: Option<&str>) -> GuestCodeView {
+    let active = code.is_active();
+    let qr = ssid.filter(|_| active).map(|ssid| crate::guestcodes::qr_payload(ssid, &code.code));
+    GuestCodeView { code, active, qr }
+}
 
-        new_content.push_str(line);
-        new_content.push('\n');
+fn current_guest_network() -> Option<GuestNetwork> {
+    if mock::is_mock_mode() {
+        return Some(mock::network::guest_network());
     }
+    guest_network()
+}
 
-    // Write config
-    Command::new("sudo")
-        .args(["tee", HOSTAPD_CONF])
-        .stdin(std::process::Stdio::piped())
-        .output()
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+pub async fn guest_codes(State(state): State<Arc<AppState>>) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let guest = current_guest_network();
+    let ssid = guest.as_ref().map(|g| g.ssid.as_str());
+    let codes: Vec<GuestCodeView> = crate::guestcodes::list(&state.db)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .into_iter()
+        .map(|c| guest_code_view(c, ssid))
+        .collect();
 
-    fs::write("/tmp/hostapd.conf.new", &new_content)
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(Json(serde_json::json!({
+        "settings": crate::guestcodes::load_settings(&state.db).await,
+        "guest_network": guest.as_ref().map(|g| serde_json::json!({
+            "interface": g.interface,
+            "ssid": g.ssid,
+            "enabled": g.enabled,
+        })),
+        "max_hours": crate::guestcodes::MAX_HOURS,
+        "codes": codes,
+    })))
+}
 
-    Command::new("sudo")
-        .args(["cp", "/tmp/hostapd.conf.new", HOSTAPD_CONF])
-        .output()
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+#[derive(Debug, Deserialize)]
+pub struct CreateGuestCode {
+    pub hours: u32,
+    #[serde(default)]
+    pub label: String,
+}
 
-    // Restart hostapd
-    Command::new("sudo")
-        .args(["systemctl", "restart", "hostapd"])
-        .output()
+pub async fn create_guest_code(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    Json(payload): Json<CreateGuestCode>,
+) -> Result<Json<GuestCodeView>, (StatusCode, String)> {
+    require_permission(&user, "wifi:write").map_err(|(s, m)| (s, m.to_string()))?;
+
+    if !(1..=crate::guestcodes::MAX_HOURS).contains(&payload.hours) {
+        return Err((StatusCode::BAD_REQUEST, format!("Codes can be valid for 1-{} hours", crate::guestcodes::MAX_HOURS)));
+    }
+    let label = payload.label.trim();
+    crate::guestcodes::validate_label(label).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+    let Some(guest) = current_guest_network() else {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "No guest SSID configured: add a bss= section to hostapd.conf first".to_string(),
+        ));
+    };
+
+    let code = crate::guestcodes::create(&state.db, payload.hours, label, &user.username)
+        .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    if !mock::is_mock_mode() {
+        crate::guestcodes::sync(&state.db).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    }
 
-    Ok(Json(serde_json::json!({"success": true})))
+    tracing::info!("User {} issued guest code {} for {} hours", user.username, code.id, payload.hours);
+    Ok(Json(guest_code_view(code, Some(&guest.ssid))))
 }
 
-pub async fn toggle_wifi(
-    Json(payload): Json<serde_json::Value>,
-) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
-    let enabled = payload.get("enabled").and_then(|v| v.as_bool()).unwrap_or(true);
+#[derive(Debug, Deserialize)]
+pub struct RevokeGuestCode {
+    pub id: i64,
+}
 
-    if mock::is_mock_mode() {
-        return Ok(Json(serde_json::json!({"success": true, "enabled": enabled, "mock": true})));
+pub async fn revoke_guest_code(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    Json(payload): Json<RevokeGuestCode>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    require_permission(&user, "wifi:write").map_err(|(s, m)| (s, m.to_string()))?;
+
+    let revoked = crate::guestcodes::revoke(&state.db, payload.id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or((StatusCode::NOT_FOUND, "No active guest code with that id".to_string()))?;
+    if !mock::is_mock_mode() {
+        crate::guestcodes::sync(&state.db).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
     }
 
-    let action = if enabled { "start" } else { "stop" };
+    tracing::info!("User {} revoked guest code {}", user.username, revoked.id);
+    Ok(Json(serde_json::json!({"success": true})))
+}
 
-    Command::new("sudo")
-        .args(["systemctl", action, "hostapd"])
-        .output()
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+pub async fn update_guest_code_settings(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    Json(payload): Json<crate::guestcodes::GuestCodeSettings>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    require_permission(&user, "wifi:write").map_err(|(s, m)| (s, m.to_string()))?;
 
-    Ok(Json(serde_json::json!({"success": true, "enabled": enabled})))
+    crate::guestcodes::save_settings(&state.db, &payload)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    if !mock::is_mock_mode() {
+        crate::guestcodes::sync(&state.db).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    }
+    Ok(Json(serde_json::json!({"success": true})))
 }
 
 // ============ DNS ============
@@ -646,17 +2527,42 @@ fn save_local_dns(entries: &[LocalDnsEntry]) -> Result<(), (StatusCode, String)>
         content.push_str(&format!("address=/{}/{}\n", entry.hostname, entry.ip_address));
     }
 
-    fs::write(LOCAL_DNS_FILE, &content)
+    write_system_file(LOCAL_DNS_FILE, &content)
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
     // Reload dnsmasq
-    let _ = Command::new("sudo")
+    let _ = sudo()
         .args(["systemctl", "reload", "dnsmasq"])
         .output();
 
     Ok(())
 }
 
+/// Point `hostname` at `ip` in local DNS, replacing any existing entry
+pub fn ensure_local_dns(hostname: &str, ip: &str) -> Result<(), (StatusCode, String)> {
+    let mut entries = load_local_dns();
+    if entries.iter().any(|e| e.hostname == hostname && e.ip_address == ip) {
+        return Ok(());
+    }
+    entries.retain(|e| e.hostname != hostname);
+    entries.push(LocalDnsEntry {
+        hostname: hostname.to_string(),
+        ip_address: ip.to_string(),
+    });
+    save_local_dns(&entries)
+}
+
+/// Drop the local DNS entry for `hostname` if it still points at `ip`, leaving edits made since alone
+pub fn remove_local_dns_entry(hostname: &str, ip: &str) -> Result<(), (StatusCode, String)> {
+    let mut entries = load_local_dns();
+    let before = entries.len();
+    entries.retain(|e| !(e.hostname == hostname && e.ip_address == ip));
+    if entries.len() == before {
+        return Ok(());
+    }
+    save_local_dns(&entries)
+}
+
 pub async fn add_local_dns(
     Json(payload): Json<AddLocalDns>,
 ) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
@@ -671,6 +2577,23 @@ pub async fn add_local_dns(
         return Err((StatusCode::BAD_REQUEST, "Hostname already exists".to_string()));
     }
 
+    // A name may point anywhere, so a conflict only warns. If the address is reserved, the
+    // device it is reserved for is the one expected to answer.
+    let warning = match payload.ip_address.trim().parse::<std::net::Ipv4Addr>() {
+        Ok(ip) => {
+            let reserved_for = load_static_leases()
+                .into_iter()
+                .find(|l| l.ip_address == ip.to_string())
+                .map(|l| l.mac_address);
+            let probe = tokio::task::spawn_blocking(move || crate::dhcp::conflict::check(ip, reserved_for.as_deref()))
+                .await
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+            // Names often point past the LAN, where there is nothing to probe
+            probe.ok().flatten().map(|c| c.message)
+        }
+        Err(_) => None,
+    };
+
     entries.push(LocalDnsEntry {
         hostname: payload.hostname,
         ip_address: payload.ip_address,
@@ -678,23 +2601,332 @@ pub async fn add_local_dns(
 
     save_local_dns(&entries)?;
 
-    Ok(Json(serde_json::json!({"success": true})))
+    Ok(Json(serde_json::json!({"success": true, "warning": warning})))
 }
 
 pub async fn remove_local_dns(
+    State(state): State<Arc<AppState>>,
     Json(payload): Json<RemoveLocalDns>,
 ) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
     if mock::is_mock_mode() {
         return Ok(Json(serde_json::json!({"success": true, "mock": true})));
     }
 
-    let mut entries = load_local_dns();
-    entries.retain(|e| e.hostname != payload.hostname);
+    let (removed, entries): (Vec<LocalDnsEntry>, Vec<LocalDnsEntry>) = load_local_dns()
+        .into_iter()
+        .partition(|e| e.hostname == payload.hostname);
+    let Some(entry) = removed.first() else {
+        return Ok(Json(serde_json::json!({"success": true})));
+    };
     save_local_dns(&entries)?;
 
+    let summary = format!("local DNS name {} ({})", entry.hostname, entry.ip_address);
+    let undo = state.undo.record(UndoKind::LocalDns, summary, &removed);
+    Ok(Json(serde_json::json!({"success": true, "undo": undo})))
+}
+
+/// Put back local DNS names removed moments ago, unless the name has been added again since
+pub(crate) fn restore_local_dns(item: serde_json::Value) -> Result<(), (StatusCode, String)> {
+    let restored: Vec<LocalDnsEntry> = serde_json::from_value(item)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let mut entries = load_local_dns();
+    for entry in &restored {
+        if entries.iter().any(|e| e.hostname == entry.hostname) {
+            return Err((StatusCode::CONFLICT, format!("{} exists again, not restoring", entry.hostname)));
+        }
+    }
+    entries.extend(restored);
+    save_local_dns(&entries)
+}
+
+// ============ DNS REBINDING PROTECTION ============
+
+// Domains that legitimately resolve to private addresses and break under rebind protection
+const SUGGESTED_REBIND_EXCEPTIONS: &[(&str, &str)] = &[
+    ("plex.direct", "Plex remote access"),
+    ("nip.io", "Wildcard DNS for local IPs"),
+    ("sslip.io", "Wildcard DNS for local IPs"),
+];
+const MAX_REBIND_EXCEPTIONS: usize = 100;
+
+/// dnsmasq's answer to DNS rebinding: upstream answers pointing into private ranges are
+/// dropped, except for the listed domains
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RebindProtection {
+    pub enabled: bool,
+    // Let upstream answers contain 127.0.0.0/8 (some DNSBL services need it)
+    pub allow_localhost: bool,
+    // rebind-domain-ok: these domains and their subdomains may answer with private addresses
+    pub exceptions: Vec<String>,
+}
+
+fn parse_rebind_line(line: &str, config: &mut RebindProtection) -> bool {
+    match line {
+        "stop-dns-rebind" => config.enabled = true,
+        "rebind-localhost-ok" => config.allow_localhost = true,
+        _ => match line.strip_prefix("rebind-domain-ok=") {
+            // rebind-domain-ok=/a.com/b.net/ or rebind-domain-ok=a.com
+            Some(domains) => config.exceptions.extend(
+                domains.split('/').filter(|d| !d.is_empty()).map(str::to_string),
+            ),
+            None => return false,
+        },
+    }
+    true
+}
+
+fn load_rebind_protection() -> RebindProtection {
+    let mut config = RebindProtection::default();
+    let content = fs::read_to_string(DNS_REBIND_FILE).unwrap_or_default();
+    for line in content.lines().map(str::trim) {
+        parse_rebind_line(line, &mut config);
+    }
+    config
+}
+
+/// Rebind settings hand-written into the main dnsmasq config, which RouterUI can't turn off
+fn unmanaged_rebind_lines() -> Vec<String> {
+    [DNSMASQ_CONF, "/etc/dnsmasq.conf"]
+        .iter()
+        .filter_map(|path| Some((path, fs::read_to_string(path).ok()?)))
+        .flat_map(|(path, content)| {
+            content
+                .lines()
+                .map(str::trim)
+                .filter(|line| parse_rebind_line(line, &mut RebindProtection::default()))
+                .map(|line| format!("{}: {}", path, line))
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+fn valid_domain(domain: &str) -> bool {
+    domain.len() <= 253
+        && domain.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        })
+}
+
+fn save_rebind_protection(config: &RebindProtection) -> Result<(), (StatusCode, String)> {
+    let mut content = String::from("# DNS rebinding protection - managed by RouterUI\n");
+    if config.enabled {
+        content.push_str("stop-dns-rebind\n");
+    }
+    // Harmless while protection is off, and kept so turning it back on restores the list
+    if config.allow_localhost {
+        content.push_str("rebind-localhost-ok\n");
+    }
+    for domain in &config.exceptions {
+        content.push_str(&format!("rebind-domain-ok=/{}/\n", domain));
+    }
+
+    let previous = fs::read_to_string(DNS_REBIND_FILE).ok();
+    write_system_file(DNS_REBIND_FILE, &content)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    if let Ok(output) = Command::new("dnsmasq").arg("--test").output() {
+        if !output.status.success() {
+            let _ = write_system_file(DNS_REBIND_FILE, previous.as_deref().unwrap_or(""));
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!("dnsmasq rejected the settings: {}", String::from_utf8_lossy(&output.stderr).trim()),
+            ));
+        }
+    }
+
+    let _ = sudo()
+        .args(["systemctl", "reload", "dnsmasq"])
+        .output();
+
+    Ok(())
+}
+
+pub async fn dns_rebind() -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let (config, unmanaged) = if mock::is_mock_mode() {
+        (serde_json::from_value(mock::network::dns_rebind()).unwrap_or_default(), Vec::new())
+    } else {
+        (load_rebind_protection(), unmanaged_rebind_lines())
+    };
+    let suggested: Vec<serde_json::Value> = SUGGESTED_REBIND_EXCEPTIONS
+        .iter()
+        .map(|(domain, description)| serde_json::json!({"domain": domain, "description": description}))
+        .collect();
+
+    Ok(Json(serde_json::json!({
+        "enabled": config.enabled,
+        "allow_localhost": config.allow_localhost,
+        "exceptions": config.exceptions,
+        "suggested": suggested,
+        // Shown as a warning: these keep applying whatever is saved here
+        "unmanaged": unmanaged,
+    })))
+}
+
+pub async fn update_dns_rebind(
+    AuthUser(user): AuthUser,
+    Json(mut payload): Json<RebindProtection>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    require_permission(&user, "dns:write").map_err(|(s, m)| (s, m.to_string()))?;
+
+    let mut exceptions: Vec<String> = Vec::new();
+    for domain in &payload.exceptions {
+        // "*.plex.direct" and ".plex.direct" mean the same to dnsmasq as "plex.direct"
+        let domain = domain.trim().trim_start_matches("*.").trim_matches('.').to_ascii_lowercase();
+        if !valid_domain(&domain) {
+            return Err((StatusCode::BAD_REQUEST, format!("'{}' is not a valid domain", domain)));
+        }
+        if !exceptions.contains(&domain) {
+            exceptions.push(domain);
+        }
+    }
+    if exceptions.len() > MAX_REBIND_EXCEPTIONS {
+        return Err((StatusCode::BAD_REQUEST, format!("At most {} exceptions", MAX_REBIND_EXCEPTIONS)));
+    }
+    payload.exceptions = exceptions;
+
+    if mock::is_mock_mode() {
+        return Ok(Json(serde_json::json!({"success": true, "mock": true})));
+    }
+
+    save_rebind_protection(&payload)?;
+    tracing::info!(
+        "User {} {} DNS rebinding protection ({} exceptions)",
+        user.username,
+        if payload.enabled { "enabled" } else { "disabled" },
+        payload.exceptions.len()
+    );
     Ok(Json(serde_json::json!({"success": true})))
 }
 
+// ============ SAFE SEARCH ============
+
+pub async fn safe_search(State(state): State<Arc<AppState>>) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    if mock::is_mock_mode() {
+        return Ok(Json(mock::network::safe_search()));
+    }
+    let settings = crate::safesearch::load(&state.db).await;
+    let adguard = super::adguard::reachable().await;
+    Ok(Json(serde_json::json!({
+        "groups": settings.groups,
+        "backend": if adguard { "adguard" } else { "dnsmasq" },
+    })))
+}
+
+/// Replace the whole list of groups and push it to the DNS server
+pub async fn update_safe_search(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    Json(mut payload): Json<crate::safesearch::SafeSearchSettings>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    require_permission(&user, "dns:write").map_err(|(s, m)| (s, m.to_string()))?;
+    for group in &mut payload.groups {
+        group.name = group.name.trim().to_string();
+        group.clients = group.clients.iter().map(|c| c.trim().to_string()).filter(|c| !c.is_empty()).collect();
+        if group.id.is_empty() {
+            group.id = uuid::Uuid::new_v4().to_string();
+        }
+    }
+    payload.validate().map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+    if mock::is_mock_mode() {
+        return Ok(Json(serde_json::json!({"success": true, "mock": true})));
+    }
+
+    crate::safesearch::save(&state.db, &payload).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    let enforcement = crate::safesearch::apply(&payload)
+        .await
+        .map_err(|e| (StatusCode::BAD_GATEWAY, e))?;
+    tracing::info!(
+        "User {} updated safe search ({} groups, enforced by {})",
+        user.username, payload.groups.len(), enforcement.backend
+    );
+    Ok(Json(serde_json::json!({"success": true, "enforcement": enforcement})))
+}
+
+// ============ DNS BYPASS ============
+
+pub async fn dns_bypass(State(state): State<Arc<AppState>>) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    if mock::is_mock_mode() {
+        return Ok(Json(mock::network::dns_bypass()));
+    }
+    Ok(Json(serde_json::json!({
+        "settings": crate::dnsguard::load_settings(&state.db).await,
+        "adguard": super::adguard::reachable().await,
+    })))
+}
+
+/// Replace the bypass settings and rebuild the firewall chain and AdGuard rules
+pub async fn update_dns_bypass(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    Json(mut payload): Json<crate::dnsguard::DnsGuardSettings>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    require_permission(&user, "dns:write").map_err(|(s, m)| (s, m.to_string()))?;
+    payload.normalize().map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+    if mock::is_mock_mode() {
+        return Ok(Json(serde_json::json!({"success": true, "mock": true})));
+    }
+
+    let wan = crate::wan::wan_interface(&state.db).await;
+    let applied = payload.clone();
+    tokio::task::spawn_blocking(move || crate::dnsguard::apply(&wan, &applied))
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    crate::dnsguard::save_settings(&state.db, &payload)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    let adguard = crate::dnsguard::apply_adguard(&payload)
+        .await
+        .map_err(|e| (StatusCode::BAD_GATEWAY, e))?;
+
+    tracing::info!(
+        "User {} updated DNS bypass blocking (dns: {:?}, dot: {}, doh: {}, ntp: {}, {} exceptions)",
+        user.username, payload.dns, payload.block_dot, payload.block_doh, payload.redirect_ntp, payload.exceptions.len()
+    );
+    Ok(Json(serde_json::json!({"success": true, "adguard": adguard})))
+}
+
+// ============ LAN PAGES ============
+
+pub async fn lan_pages(State(state): State<Arc<AppState>>) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    if mock::is_mock_mode() {
+        return Ok(Json(mock::network::lan_pages()));
+    }
+    Ok(Json(serde_json::json!({
+        "settings": crate::lanpages::load_settings(&state.db).await,
+        "status": state.lan_pages.status(),
+    })))
+}
+
+/// Replace the LAN pages, update their DNS entries and restart the server
+pub async fn update_lan_pages(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    Json(mut payload): Json<crate::lanpages::LanPagesSettings>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    require_permission(&user, "dns:write").map_err(|(s, m)| (s, m.to_string()))?;
+    payload.normalize().map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+    if mock::is_mock_mode() {
+        return Ok(Json(serde_json::json!({"success": true, "mock": true})));
+    }
+
+    crate::lanpages::save(&state, &payload).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    tracing::info!(
+        "User {} updated LAN pages ({}, {} pages on port {})",
+        user.username, if payload.enabled { "enabled" } else { "disabled" }, payload.pages.len(), payload.port
+    );
+    Ok(Json(serde_json::json!({"success": true, "status": state.lan_pages.status()})))
+}
+
 // ============ STATIC ROUTES ============
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -703,9 +2935,13 @@ pub struct StaticRoute {
     pub gateway: String,
     pub interface: Option<String>,
     pub metric: Option<u32>,
+    // Role of the interface, in listings
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub role: Option<Role>,
 }
 
-pub async fn routes() -> Result<Json<Vec<StaticRoute>>, (StatusCode, String)> {
+pub async fn routes(State(state): State<Arc<AppState>>) -> Result<Json<Vec<StaticRoute>>, (StatusCode, String)> {
+    let roles = crate::interfaces::roles(&state.db).await;
     let output = Command::new("ip")
         .args(["route", "show"])
         .output()
@@ -745,6 +2981,7 @@ pub async fn routes() -> Result<Json<Vec<StaticRoute>>, (StatusCode, String)> {
         }
 
         routes.push(StaticRoute {
+            role: interface.as_deref().and_then(|i| roles.role_of(i)),
             destination,
             gateway,
             interface,
@@ -759,6 +2996,7 @@ pub async fn routes() -> Result<Json<Vec<StaticRoute>>, (StatusCode, String)> {
 pub struct AddRoute {
     pub destination: String,
     pub gateway: String,
+    // Kernel name or role ("wan", "lan", ...); a role is kept as such in the saved route
     pub interface: Option<String>,
 }
 
@@ -768,6 +3006,7 @@ pub struct RemoveRoute {
 }
 
 pub async fn add_route(
+    State(state): State<Arc<AppState>>,
     Json(payload): Json<AddRoute>,
 ) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
     if mock::is_mock_mode() {
@@ -778,12 +3017,13 @@ pub async fn add_route(
 
     let iface;
     if let Some(ref interface) = payload.interface {
-        iface = interface.clone();
+        let roles = crate::interfaces::roles(&state.db).await;
+        iface = roles.resolve(interface).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
         args.push("dev");
         args.push(&iface);
     }
 
-    let output = Command::new("sudo")
+    let output = sudo()
         .args(&args)
         .output()
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
@@ -806,7 +3046,7 @@ pub async fn remove_route(
         return Ok(Json(serde_json::json!({"success": true, "mock": true})));
     }
 
-    let output = Command::new("sudo")
+    let output = sudo()
         .args(["ip", "route", "del", &payload.destination])
         .output()
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
@@ -829,6 +3069,7 @@ fn save_route_persistent(route: &AddRoute) -> Result<(), (StatusCode, String)> {
         gateway: route.gateway.clone(),
         interface: route.interface.clone(),
         metric: None,
+        role: None,
     });
 
     let json = serde_json::to_string_pretty(&routes)
@@ -865,6 +3106,12 @@ pub struct WolDevice {
     pub name: String,
     pub mac_address: String,
     pub ip_address: Option<String>,
+    // Kernel name or role of the segment the device is on; None for the LAN
+    #[serde(default)]
+    pub interface: Option<String>,
+    // Wake it for requests from off the LAN, e.g. a phone on Tailscale
+    #[serde(default)]
+    pub relay: bool,
 }
 
 pub async fn wol_devices() -> Result<Json<Vec<WolDevice>>, (StatusCode, String)> {
@@ -892,21 +3139,36 @@ pub struct AddWolDevice {
     pub name: String,
     pub mac_address: String,
     pub ip_address: Option<String>,
+    #[serde(default)]
+    pub interface: Option<String>,
+    #[serde(default)]
+    pub relay: bool,
 }
 
+/// Add a device, or update the one with the same MAC
 pub async fn add_wol_device(
+    State(state): State<Arc<AppState>>,
     Json(payload): Json<AddWolDevice>,
 ) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
     if mock::is_mock_mode() {
         return Ok(Json(serde_json::json!({"success": true, "mock": true})));
     }
 
-    let mut devices = load_wol_devices();
+    let mac_address = crate::wol::normalize_mac(&payload.mac_address)
+        .ok_or((StatusCode::BAD_REQUEST, format!("'{}' is not a MAC address", payload.mac_address)))?;
+    let interface = payload.interface.map(|i| i.trim().to_string()).filter(|i| !i.is_empty());
+    if let Some(interface) = &interface {
+        crate::interfaces::roles(&state.db).await.resolve(interface).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    }
 
+    let mut devices = load_wol_devices();
+    devices.retain(|d| d.mac_address.to_lowercase() != mac_address);
     devices.push(WolDevice {
         name: payload.name,
-        mac_address: payload.mac_address,
+        mac_address,
         ip_address: payload.ip_address,
+        interface,
+        relay: payload.relay,
     });
 
     save_wol_devices(&devices)?;
@@ -917,53 +3179,166 @@ pub async fn add_wol_device(
 #[derive(Debug, Deserialize)]
 pub struct RemoveWolDevice {
     pub mac_address: String,
+    #[serde(default)]
+    pub rules: Option<super::firewall::DependentAction>,
 }
 
 pub async fn remove_wol_device(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
     Json(payload): Json<RemoveWolDevice>,
 ) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
     if mock::is_mock_mode() {
         return Ok(Json(serde_json::json!({"success": true, "mock": true})));
     }
 
-    let mut devices = load_wol_devices();
-    devices.retain(|d| d.mac_address.to_lowercase() != payload.mac_address.to_lowercase());
+    let (removed, devices): (Vec<WolDevice>, Vec<WolDevice>) = load_wol_devices()
+        .into_iter()
+        .partition(|d| d.mac_address.to_lowercase() == payload.mac_address.to_lowercase());
+    let Some(device) = removed.first() else {
+        return Ok(Json(serde_json::json!({"success": true})));
+    };
+
+    let what = format!("{} ({})", device.name, device.mac_address);
+    let rules_removed = super::firewall::settle_dependents(
+        &state.db,
+        &user,
+        Some(&device.mac_address),
+        device.ip_address.as_deref().filter(|ip| !ip.is_empty()),
+        payload.rules,
+        &what,
+    )
+    .await?;
     save_wol_devices(&devices)?;
 
-    Ok(Json(serde_json::json!({"success": true})))
+    let summary = format!("Wake-on-LAN device {} ({})", device.name, device.mac_address);
+    let undo = state.undo.record(UndoKind::WolDevice, summary, &removed);
+    Ok(Json(serde_json::json!({"success": true, "undo": undo, "rules_removed": rules_removed})))
+}
+
+/// Put back Wake-on-LAN devices removed moments ago, unless the MAC has been added again since
+pub(crate) fn restore_wol_devices(item: serde_json::Value) -> Result<(), (StatusCode, String)> {
+    let restored: Vec<WolDevice> = serde_json::from_value(item)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let mut devices = load_wol_devices();
+    for device in &restored {
+        if devices.iter().any(|d| d.mac_address.to_lowercase() == device.mac_address.to_lowercase()) {
+            return Err((StatusCode::CONFLICT, format!("{} was added again, not restoring", device.mac_address)));
+        }
+    }
+    devices.extend(restored);
+    save_wol_devices(&devices)
 }
 
 #[derive(Debug, Deserialize)]
 pub struct WakeDevice {
-    pub mac_address: String,
+    // Either one; a name is handier from a phone shortcut
+    pub mac_address: Option<String>,
+    pub name: Option<String>,
 }
 
+/// Wake a device. From the LAN any MAC will do; requests from elsewhere (Tailscale, the internet)
+/// are relayed only for registered devices marked for it. Every attempt is recorded.
 pub async fn wake_device(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
     Json(payload): Json<WakeDevice>,
 ) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let devices = load_wol_devices();
+    let (device, mac_address) = match (&payload.mac_address, &payload.name) {
+        (Some(mac), _) => {
+            let mac = crate::wol::normalize_mac(mac)
+                .ok_or((StatusCode::BAD_REQUEST, format!("'{}' is not a MAC address", mac)))?;
+            (devices.iter().find(|d| d.mac_address.to_lowercase() == mac), mac)
+        }
+        (None, Some(name)) => {
+            let device = devices
+                .iter()
+                .find(|d| d.name.eq_ignore_ascii_case(name.trim()))
+                .ok_or((StatusCode::NOT_FOUND, format!("No Wake-on-LAN device named '{}'", name)))?;
+            (Some(device), device.mac_address.to_lowercase())
+        }
+        (None, None) => return Err((StatusCode::BAD_REQUEST, "Give a mac_address or a device name".to_string())),
+    };
+
     if mock::is_mock_mode() {
         return Ok(Json(serde_json::json!({
             "success": true,
-            "message": format!("Wake packet sent to {} (mock)", payload.mac_address),
+            "message": format!("Wake packet sent to {} (mock)", mac_address),
+            "origin": "lan",
             "mock": true
         })));
     }
 
-    // Try etherwake first, then wakeonlan
-    let result = Command::new("sudo")
-        .args(["etherwake", "-i", "enp2s0", &payload.mac_address])
-        .output();
+    let origin = crate::wol::origin(&state.db, peer.ip()).await;
+    let mut wake = crate::wol::Wake::new(&mac_address, device.map(|d| d.name.as_str()), &user.username, peer.ip(), origin);
+    if origin == crate::wol::Origin::Tailscale {
+        wake.tailscale_identity = crate::wol::tailscale_identity(peer.ip()).await;
+    }
+
+    let result = async {
+        if origin.is_off_lan() && !device.is_some_and(|d| d.relay) {
+            return Err((
+                StatusCode::FORBIDDEN,
+                format!("{} is not set up to be woken from off the LAN", device.map_or(mac_address.as_str(), |d| d.name.as_str())),
+            ));
+        }
+        let interface = device.and_then(|d| d.interface.as_deref()).unwrap_or("lan");
+        wake.interface = crate::interfaces::roles(&state.db)
+            .await
+            .resolve(interface)
+            .map_err(|e| (StatusCode::CONFLICT, e))?;
+
+        let (iface, mac) = (wake.interface.clone(), mac_address.clone());
+        tokio::task::spawn_blocking(move || crate::wol::send(&iface, &mac))
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))
+    }
+    .await;
 
-    if result.is_err() || !result.as_ref().unwrap().status.success() {
-        // Fallback to wakeonlan
-        Command::new("wakeonlan")
-            .args([&payload.mac_address])
-            .output()
-            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    wake.success = result.is_ok();
+    wake.error = result.as_ref().err().map(|(_, e)| e.clone());
+    if let Err(e) = crate::wol::record(&state.db, &wake).await {
+        tracing::warn!("Failed to record Wake-on-LAN request: {}", e);
+    }
+    if origin.is_off_lan() {
+        tracing::info!(
+            "Relayed wake of {} for {} from {} ({}): {}",
+            mac_address,
+            user.username,
+            wake.source_ip,
+            wake.tailscale_identity.as_deref().unwrap_or(origin.as_str()),
+            wake.error.as_deref().unwrap_or("sent")
+        );
     }
+    result?;
 
     Ok(Json(serde_json::json!({
         "success": true,
-        "message": format!("Wake packet sent to {}", payload.mac_address)
+        "message": format!("Wake packet sent to {}", device.map_or(mac_address.as_str(), |d| d.name.as_str())),
+        "origin": origin,
+        "interface": wake.interface,
     })))
 }
+
+#[derive(Debug, Deserialize)]
+pub struct WolHistoryQuery {
+    pub limit: Option<i64>,
+}
+
+/// Who woke what, most recent first
+pub async fn wol_history(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<WolHistoryQuery>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    if mock::is_mock_mode() {
+        return Ok(Json(mock::network::wol_history()));
+    }
+
+    let wakes = crate::wol::history(&state.db, query.limit.unwrap_or(100))
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(Json(serde_json::json!({"wakes": wakes})))
+}
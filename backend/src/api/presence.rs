@@ -0,0 +1,50 @@
+use axum::{extract::State, http::StatusCode, Json};
+use std::sync::Arc;
+
+use super::{require_permission, AuthUser};
+use crate::mock;
+use crate::presence::{self, PresenceSettings};
+use crate::AppState;
+
+// ============ PRESENCE ============
+
+pub async fn status(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    require_permission(&user, "network:write").map_err(|(s, m)| (s, m.to_string()))?;
+
+    if mock::is_mock_mode() {
+        return Ok(Json(mock::network::presence()));
+    }
+
+    let settings = presence::load_settings();
+    let devices: Vec<serde_json::Value> = settings
+        .devices
+        .iter()
+        .map(|d| serde_json::json!({"device": d, "state": state.presence.device(&d.id)}))
+        .collect();
+
+    Ok(Json(serde_json::json!({
+        "settings": settings,
+        "devices": devices,
+        "anyone_home": state.presence.anyone_home(),
+        "error": state.presence.last_error(),
+    })))
+}
+
+pub async fn update_settings(
+    AuthUser(user): AuthUser,
+    Json(mut payload): Json<PresenceSettings>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    require_permission(&user, "network:write").map_err(|(s, m)| (s, m.to_string()))?;
+
+    if mock::is_mock_mode() {
+        return Ok(Json(serde_json::json!({"success": true, "mock": true})));
+    }
+
+    payload.normalize().map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    presence::save_settings(&payload).map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    Ok(Json(serde_json::json!({"success": true, "settings": payload})))
+}
@@ -0,0 +1,173 @@
+use axum::{extract::State, http::StatusCode, Json};
+use chrono::Local;
+use serde::Deserialize;
+use std::sync::Arc;
+
+use super::{require_permission, AuthUser};
+use crate::mock;
+use crate::profiles::{self, ConfigProfile};
+use crate::AppState;
+
+// ============ CONFIG PROFILES ============
+
+fn results_json(results: Vec<(&'static str, Result<(), String>)>) -> serde_json::Value {
+    results
+        .into_iter()
+        .map(|(setting, result)| (setting.to_string(), serde_json::json!({"ok": result.is_ok(), "error": result.err()})))
+        .collect::<serde_json::Map<_, _>>()
+        .into()
+}
+
+pub async fn list(State(state): State<Arc<AppState>>, AuthUser(user): AuthUser) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    require_permission(&user, "system:write").map_err(|(s, m)| (s, m.to_string()))?;
+
+    if mock::is_mock_mode() {
+        return Ok(Json(mock::system::profiles()));
+    }
+
+    let stored = profiles::load();
+    let safe_search = crate::safesearch::load(&state.db).await.groups;
+    let now = Local::now();
+    let list: Vec<serde_json::Value> = stored
+        .profiles
+        .iter()
+        .map(|p| {
+            serde_json::json!({
+                "profile": p,
+                "changes": p.changes(&safe_search),
+                "scheduled": p.schedule.active_at(now),
+                "next_change": p.schedule.next_change(now).map(|t| t.to_rfc3339()),
+            })
+        })
+        .collect();
+
+    Ok(Json(serde_json::json!({
+        "profiles": list,
+        "active": stored.active,
+        "active_since": stored.active_since,
+        "default_profile": stored.default_profile,
+    })))
+}
+
+/// Create a profile (empty id) or replace the one with the same id
+pub async fn save(
+    AuthUser(user): AuthUser,
+    Json(mut payload): Json<ConfigProfile>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    require_permission(&user, "system:write").map_err(|(s, m)| (s, m.to_string()))?;
+
+    payload.name = payload.name.trim().to_string();
+    payload.exit_node = payload.exit_node.map(|n| n.trim().to_string());
+    payload.validate().map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+    if mock::is_mock_mode() {
+        return Ok(Json(serde_json::json!({"success": true, "mock": true})));
+    }
+
+    let mut stored = profiles::load();
+    if stored.profiles.iter().any(|p| p.id != payload.id && p.name.eq_ignore_ascii_case(&payload.name)) {
+        return Err((StatusCode::CONFLICT, format!("A profile named {} already exists", payload.name)));
+    }
+    if payload.id.is_empty() {
+        payload.id = uuid::Uuid::new_v4().to_string();
+        stored.profiles.push(payload.clone());
+    } else {
+        let existing = stored
+            .profiles
+            .iter_mut()
+            .find(|p| p.id == payload.id)
+            .ok_or((StatusCode::NOT_FOUND, "Profile not found".to_string()))?;
+        *existing = payload.clone();
+    }
+    profiles::save(&stored).map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    // A changed schedule is picked up on the scheduler's next tick
+    Ok(Json(serde_json::json!({"success": true, "profile": payload})))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ProfileId {
+    pub id: String,
+}
+
+pub async fn remove(
+    AuthUser(user): AuthUser,
+    Json(payload): Json<ProfileId>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    require_permission(&user, "system:write").map_err(|(s, m)| (s, m.to_string()))?;
+
+    if mock::is_mock_mode() {
+        return Ok(Json(serde_json::json!({"success": true, "mock": true})));
+    }
+
+    let mut stored = profiles::load();
+    let before = stored.profiles.len();
+    stored.profiles.retain(|p| p.id != payload.id);
+    if stored.profiles.len() == before {
+        return Err((StatusCode::NOT_FOUND, "Profile not found".to_string()));
+    }
+    // Settings it applied stay in place
+    if stored.active.as_deref() == Some(payload.id.as_str()) {
+        stored.active = None;
+        stored.active_since = None;
+    }
+    if stored.default_profile.as_deref() == Some(payload.id.as_str()) {
+        stored.default_profile = None;
+    }
+    profiles::save(&stored).map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    Ok(Json(serde_json::json!({"success": true})))
+}
+
+pub async fn activate(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    Json(payload): Json<ProfileId>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    require_permission(&user, "system:write").map_err(|(s, m)| (s, m.to_string()))?;
+
+    if mock::is_mock_mode() {
+        return Ok(Json(serde_json::json!({"success": true, "mock": true})));
+    }
+
+    if !profiles::load().profiles.iter().any(|p| p.id == payload.id) {
+        return Err((StatusCode::NOT_FOUND, "Profile not found".to_string()));
+    }
+    tracing::info!("Profile {} activated by {}", payload.id, user.username);
+    let results = profiles::activate(&state.db, &payload.id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    Ok(Json(serde_json::json!({
+        "success": results.iter().all(|(_, r)| r.is_ok()),
+        "applied": results_json(results),
+    })))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DefaultProfile {
+    // None clears it, so scheduled profiles stay in place after their window
+    pub id: Option<String>,
+}
+
+pub async fn set_default(
+    AuthUser(user): AuthUser,
+    Json(payload): Json<DefaultProfile>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    require_permission(&user, "system:write").map_err(|(s, m)| (s, m.to_string()))?;
+
+    if mock::is_mock_mode() {
+        return Ok(Json(serde_json::json!({"success": true, "mock": true})));
+    }
+
+    let mut stored = profiles::load();
+    if let Some(id) = &payload.id {
+        if !stored.profiles.iter().any(|p| &p.id == id) {
+            return Err((StatusCode::NOT_FOUND, "Profile not found".to_string()));
+        }
+    }
+    stored.default_profile = payload.id;
+    profiles::save(&stored).map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    Ok(Json(serde_json::json!({"success": true})))
+}
@@ -1,13 +1,34 @@
-use axum::{extract::Json, http::StatusCode};
+use axum::{extract::{Json, State}, http::StatusCode};
 use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
 use std::process::Command;
+use std::sync::Arc;
+use crate::db::protection::{self as store, BLOCKLIST, COUNTRY};
+use crate::system::privileges::sudo;
+use crate::AppState;
 use std::fs;
 use std::collections::HashMap;
 
+pub use crate::db::protection::WhitelistEntry;
+
+use super::{require_permission, AuthUser};
+
 const BLOCKLISTS_DIR: &str = "/opt/routerui/blocklists";
-const WHITELIST_FILE: &str = "/opt/routerui/protection-whitelist.json";
+// Where the state lived before it moved into the database; read once by import_legacy_state
+const LEGACY_WHITELIST_FILE: &str = "/opt/routerui/protection-whitelist.json";
+const LEGACY_IMPORTED_KEY: &str = "protection_legacy_imported";
+// Key in the settings table
+pub const COUNTRY_POLICY_KEY: &str = "country_policy";
 const GEOIP_DB: &str = "/opt/routerui/GeoLite2-Country.mmdb";
 
+// Allow-list mode: new inbound WAN connections must come from GEO_ALLOW_SET
+const GEO_ALLOW_CHAIN: &str = "ROUTERUI-GEO-ALLOW";
+const GEO_ALLOW_SET: &str = "country-allow";
+// Tailscale and WireGuard listen ports stay reachable from anywhere by default
+const DEFAULT_VPN_PORTS: &[u16] = &[41641, 51820];
+// Upstream router, CGNAT and link-local sources on the WAN side are never country-filtered
+const UNFILTERED_SOURCES: &[&str] = &["10.0.0.0/8", "172.16.0.0/12", "192.168.0.0/16", "100.64.0.0/10", "169.254.0.0/16"];
+
 // ============ BLOCKLIST SOURCES ============
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -80,6 +101,8 @@ pub struct ProtectionStatus {
     pub countries_blocked: u32,
     pub whitelist_count: u32,
     pub log_enabled: bool,
+    // Scanners currently banned by the decoy ports
+    pub honeypot_bans: u32,
 }
 
 #[derive(Debug, Serialize)]
@@ -106,6 +129,9 @@ pub struct BlockedEntry {
     pub interface: String,
     pub reason: String,     // which blocklist or rule blocked it
     pub country: Option<String>,
+    // e.g. "Listed in 3 feeds since 2025-11", across all blocklist updates
+    pub reputation: Option<String>,
+    pub feeds: u32,
 }
 
 #[derive(Debug, Serialize)]
@@ -114,13 +140,6 @@ pub struct BlockedLogResponse {
     pub total_blocked_24h: u64,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct WhitelistEntry {
-    pub ip: String,
-    pub description: String,
-    pub added_at: String,
-}
-
 #[derive(Debug, Deserialize)]
 pub struct AddWhitelist {
     pub ip: String,
@@ -130,6 +149,9 @@ pub struct AddWhitelist {
 #[derive(Debug, Deserialize)]
 pub struct RemoveWhitelist {
     pub ip: String,
+    // What to do with port forwards and blocks for the same address; asked for when there are any
+    #[serde(default)]
+    pub rules: Option<super::firewall::DependentAction>,
 }
 
 #[derive(Debug, Serialize)]
@@ -137,6 +159,9 @@ pub struct CountryBlock {
     pub code: String,
     pub name: String,
     pub blocked: bool,
+    // Ranges loaded the last time the zone was downloaded
+    pub range_count: u32,
+    pub last_updated: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -145,6 +170,37 @@ pub struct ToggleCountry {
     pub blocked: bool,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CountryMode {
+    // Drop traffic from the countries toggled on
+    #[default]
+    Block,
+    // Drop new inbound WAN connections from everywhere except `allowed`
+    Allow,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CountryPolicy {
+    pub mode: CountryMode,
+    pub allowed: Vec<String>,
+    pub vpn_ports: Vec<u16>,
+}
+
+impl Default for CountryPolicy {
+    fn default() -> Self {
+        Self { mode: CountryMode::Block, allowed: Vec::new(), vpn_ports: DEFAULT_VPN_PORTS.to_vec() }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetCountryPolicy {
+    pub mode: CountryMode,
+    #[serde(default)]
+    pub allowed: Vec<String>,
+    pub vpn_ports: Option<Vec<u16>>,
+}
+
 // ============ HELPER FUNCTIONS ============
 
 fn ensure_dirs() {
@@ -152,7 +208,7 @@ fn ensure_dirs() {
 }
 
 fn get_ipset_count(name: &str) -> u32 {
-    let output = Command::new("sudo")
+    let output = sudo()
         .args(["ipset", "list", name, "-t"])
         .output();
 
@@ -170,16 +226,16 @@ fn get_ipset_count(name: &str) -> u32 {
 }
 
 fn ipset_exists(name: &str) -> bool {
-    Command::new("sudo")
+    sudo()
         .args(["ipset", "list", name])
         .output()
         .map(|o| o.status.success())
         .unwrap_or(false)
 }
 
-fn create_ipset(name: &str) -> Result<(), (StatusCode, String)> {
+pub(crate) fn create_ipset(name: &str) -> Result<(), (StatusCode, String)> {
     if !ipset_exists(name) {
-        Command::new("sudo")
+        sudo()
             .args(["ipset", "create", name, "hash:net", "maxelem", "1000000"])
             .output()
             .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
@@ -187,9 +243,18 @@ fn create_ipset(name: &str) -> Result<(), (StatusCode, String)> {
     Ok(())
 }
 
+// Match and target of the LOG rule in front of a set's DROP rule
+fn log_rule(set_name: &str) -> Vec<String> {
+    ["-m", "set", "--match-set", set_name, "src", "-j", "LOG", "--log-prefix", &format!("BLOCKED:{}: ", set_name),
+     "--log-level", "4"]
+        .iter()
+        .map(|a| a.to_string())
+        .collect()
+}
+
 fn add_ipset_rule(set_name: &str) -> Result<(), (StatusCode, String)> {
     // Check if rule already exists
-    let check = Command::new("sudo")
+    let check = sudo()
         .args(["iptables", "-C", "INPUT", "-m", "set", "--match-set", set_name, "src", "-j", "DROP"])
         .output();
 
@@ -197,66 +262,170 @@ fn add_ipset_rule(set_name: &str) -> Result<(), (StatusCode, String)> {
         return Ok(()); // Rule already exists
     }
 
-    // Add the rule - log then drop
-    Command::new("sudo")
-        .args(["iptables", "-I", "INPUT", "1", "-m", "set", "--match-set", set_name, "src", "-j", "LOG",
-               "--log-prefix", &format!("BLOCKED:{}: ", set_name), "--log-level", "4"])
+    // Add the rule - log (unless the privacy profile says not to) then drop
+    sudo()
+        .args(["iptables", "-I", "INPUT", "1", "-m", "set", "--match-set", set_name, "src", "-j", "DROP"])
         .output()
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
-    Command::new("sudo")
-        .args(["iptables", "-I", "INPUT", "2", "-m", "set", "--match-set", set_name, "src", "-j", "DROP"])
-        .output()
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    if crate::privacy::policy().blocked_traffic_log {
+        sudo()
+            .args(["iptables", "-I", "INPUT", "1"])
+            .args(log_rule(set_name))
+            .output()
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    }
 
     Ok(())
 }
 
 fn remove_ipset_rule(set_name: &str) -> Result<(), (StatusCode, String)> {
     // Remove LOG rule
-    let _ = Command::new("sudo")
-        .args(["iptables", "-D", "INPUT", "-m", "set", "--match-set", set_name, "src", "-j", "LOG",
-               "--log-prefix", &format!("BLOCKED:{}: ", set_name), "--log-level", "4"])
-        .output();
+    let _ = sudo().args(["iptables", "-D", "INPUT"]).args(log_rule(set_name)).output();
 
     // Remove DROP rule
-    let _ = Command::new("sudo")
+    let _ = sudo()
         .args(["iptables", "-D", "INPUT", "-m", "set", "--match-set", set_name, "src", "-j", "DROP"])
         .output();
 
     Ok(())
 }
 
-fn load_whitelist() -> Vec<WhitelistEntry> {
-    fs::read_to_string(WHITELIST_FILE)
-        .ok()
-        .and_then(|content| serde_json::from_str(&content).ok())
-        .unwrap_or_default()
+/// The ipsets of the blocklists and country blocks there is state for, or only of those turned on
+pub(crate) async fn list_sets(pool: &SqlitePool, enabled_only: bool) -> Result<Vec<String>, sqlx::Error> {
+    let blocklists = store::lists(pool, BLOCKLIST).await?;
+    let countries = store::lists(pool, COUNTRY).await?;
+    Ok(blocklists
+        .into_values()
+        .filter(|l| l.enabled || !enabled_only)
+        .map(|l| l.id)
+        .chain(
+            countries
+                .into_values()
+                .filter(|l| l.enabled || !enabled_only)
+                .map(|l| format!("country-{}", l.id.to_lowercase())),
+        )
+        .collect())
 }
 
-fn save_whitelist(entries: &[WhitelistEntry]) -> Result<(), (StatusCode, String)> {
-    let json = serde_json::to_string_pretty(entries)
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
-    fs::write(WHITELIST_FILE, json)
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+/// Add or remove the LOG rules of the blocklists and country blocks in `sets` (see list_sets),
+/// keeping the DROP rules
+pub(crate) fn set_blocklist_logging(sets: Vec<String>, enabled: bool) -> Result<(), String> {
+    for set_name in sets {
+        let logged = sudo()
+            .args(["iptables", "-C", "INPUT"])
+            .args(log_rule(&set_name))
+            .output()
+            .map(|o| o.status.success())
+            .unwrap_or(false);
+
+        let action = match (enabled, logged) {
+            // Position 1 keeps it in front of the DROP rule
+            (true, false) => vec!["-I", "INPUT", "1"],
+            (false, true) => vec!["-D", "INPUT"],
+            _ => continue,
+        };
+        let output = sudo()
+            .arg("iptables")
+            .args(action)
+            .args(log_rule(&set_name))
+            .output()
+            .map_err(|e| e.to_string())?;
+        if !output.status.success() {
+            return Err(format!("{}: {}", set_name, String::from_utf8_lossy(&output.stderr).trim()));
+        }
+    }
     Ok(())
 }
 
-fn get_blocklist_state() -> HashMap<String, bool> {
-    let state_file = format!("{}/state.json", BLOCKLISTS_DIR);
-    fs::read_to_string(state_file)
-        .ok()
-        .and_then(|content| serde_json::from_str(&content).ok())
-        .unwrap_or_default()
+// Addresses/CIDRs from a downloaded blocklist, skipping comments and blank lines
+fn parse_blocklist(content: &str) -> Vec<String> {
+    content
+        .lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty() && !line.starts_with('#') && !line.starts_with(';'))
+        // First field before any whitespace or semicolon
+        .filter_map(|line| line.split([' ', '\t', ';']).next())
+        .map(|ip| ip.trim())
+        .filter(|ip| !ip.is_empty() && (ip.contains('.') || ip.contains(':')))
+        .map(|ip| ip.to_string())
+        .collect()
 }
 
-fn save_blocklist_state(state: &HashMap<String, bool>) -> Result<(), (StatusCode, String)> {
-    ensure_dirs();
-    let state_file = format!("{}/state.json", BLOCKLISTS_DIR);
-    let json = serde_json::to_string_pretty(state)
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
-    fs::write(state_file, json)
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+fn db_error(e: sqlx::Error) -> (StatusCode, String) {
+    (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+}
+
+/// Whitelisted addresses are never blocked automatically either
+pub(crate) async fn is_whitelisted(pool: &SqlitePool, ip: &str) -> bool {
+    store::is_whitelisted(pool, ip).await.unwrap_or_else(|e| {
+        tracing::warn!("Could not read the protection whitelist: {}", e);
+        false
+    })
+}
+
+// Ids of the lists of `kind` that are turned on
+async fn enabled_lists(pool: &SqlitePool, kind: &str) -> Result<Vec<store::ListState>, (StatusCode, String)> {
+    let lists = store::lists(pool, kind).await.map_err(db_error)?;
+    Ok(lists.into_values().filter(|l| l.enabled).collect())
+}
+
+// ============ LEGACY STATE ============
+
+fn read_legacy<T: serde::de::DeserializeOwned>(path: &str) -> Option<T> {
+    let content = fs::read_to_string(path).ok()?;
+    match serde_json::from_str(&content) {
+        Ok(value) => Some(value),
+        Err(e) => {
+            tracing::warn!("Skipping unreadable {}: {}", path, e);
+            None
+        }
+    }
+}
+
+// Modification time of a downloaded list, which is when it was last updated
+fn file_updated_at(path: &str) -> Option<String> {
+    let modified = fs::metadata(path).ok()?.modified().ok()?;
+    Some(chrono::DateTime::<chrono::Utc>::from(modified).to_rfc3339())
+}
+
+/// Move the state older versions kept in JSON files into the database. Runs once; the files are
+/// left alone so a downgrade still finds them.
+pub async fn import_legacy_state(pool: &SqlitePool) -> Result<(), String> {
+    if crate::db::settings::get::<bool>(pool, LEGACY_IMPORTED_KEY).await.map_err(|e| e.to_string())? == Some(true) {
+        return Ok(());
+    }
+
+    let mut legacy = store::LegacyState::default();
+    let blocklists: HashMap<String, bool> = read_legacy(&format!("{}/state.json", BLOCKLISTS_DIR)).unwrap_or_default();
+    for (id, enabled) in blocklists {
+        let list_file = format!("{}/{}.txt", BLOCKLISTS_DIR, id);
+        let entry_count = fs::read_to_string(&list_file).map(|c| parse_blocklist(&c).len()).unwrap_or(0);
+        legacy.blocklists.push(store::ListState {
+            updated_at: file_updated_at(&list_file),
+            id,
+            enabled,
+            entry_count: entry_count as i64,
+        });
+    }
+    let countries: HashMap<String, bool> = read_legacy(&format!("{}/countries.json", BLOCKLISTS_DIR)).unwrap_or_default();
+    for (code, blocked) in countries {
+        // Zones weren't kept on disk; the live ipset is the best count there is
+        let entry_count = if blocked { get_ipset_count(&format!("country-{}", code.to_lowercase())) } else { 0 };
+        legacy.countries.push(store::ListState { id: code, enabled: blocked, entry_count: entry_count as i64, updated_at: None });
+    }
+    legacy.whitelist = read_legacy(LEGACY_WHITELIST_FILE).unwrap_or_default();
+
+    store::import(pool, &legacy).await.map_err(|e| e.to_string())?;
+    crate::db::settings::set(pool, LEGACY_IMPORTED_KEY, &true).await.map_err(|e| e.to_string())?;
+
+    let imported = legacy.blocklists.len() + legacy.countries.len() + legacy.whitelist.len();
+    if imported > 0 {
+        tracing::info!(
+            "Imported protection state from JSON files: {} blocklists, {} countries, {} whitelist entries",
+            legacy.blocklists.len(), legacy.countries.len(), legacy.whitelist.len()
+        );
+    }
     Ok(())
 }
 
@@ -265,7 +434,9 @@ fn save_blocklist_state(state: &HashMap<String, bool>) -> Result<(), (StatusCode
 use crate::mock;
 
 // Get overall protection status
-pub async fn status() -> Result<Json<ProtectionStatus>, (StatusCode, String)> {
+pub async fn status(
+    State(app): State<Arc<AppState>>,
+) -> Result<Json<ProtectionStatus>, (StatusCode, String)> {
     if mock::is_mock_mode() {
         return Ok(Json(ProtectionStatus {
             blocklists_active: 2,
@@ -273,71 +444,56 @@ pub async fn status() -> Result<Json<ProtectionStatus>, (StatusCode, String)> {
             countries_blocked: 0,
             whitelist_count: 3,
             log_enabled: true,
+            honeypot_bans: 7,
         }));
     }
 
-    let state = get_blocklist_state();
-    let active_lists = state.values().filter(|&&v| v).count() as u32;
-
-    let mut total_ips: u64 = 0;
-    for (id, &enabled) in &state {
-        if enabled {
-            total_ips += get_ipset_count(id) as u64;
-        }
-    }
-
-    let whitelist = load_whitelist();
+    let blocklists = enabled_lists(&app.db, BLOCKLIST).await?;
+    let countries = enabled_lists(&app.db, COUNTRY).await?;
+    let whitelist = store::whitelist(&app.db).await.map_err(db_error)?;
 
     // Check if logging is enabled (look for LOG rules)
-    let log_check = Command::new("sudo")
+    let log_check = sudo()
         .args(["iptables", "-L", "INPUT", "-n"])
         .output()
         .map(|o| String::from_utf8_lossy(&o.stdout).contains("LOG"))
         .unwrap_or(false);
 
     Ok(Json(ProtectionStatus {
-        blocklists_active: active_lists,
-        total_blocked_ips: total_ips,
-        countries_blocked: 0, // TODO: implement country counting
+        blocklists_active: blocklists.len() as u32,
+        total_blocked_ips: blocklists.iter().map(|l| l.entry_count as u64).sum(),
+        countries_blocked: countries.len() as u32,
         whitelist_count: whitelist.len() as u32,
         log_enabled: log_check,
+        honeypot_bans: get_ipset_count(crate::honeypot::SET_NAME),
     }))
 }
 
 // Get all blocklist sources and their status
-pub async fn blocklists() -> Result<Json<BlocklistsResponse>, (StatusCode, String)> {
+pub async fn blocklists(
+    State(app): State<Arc<AppState>>,
+) -> Result<Json<BlocklistsResponse>, (StatusCode, String)> {
     if mock::is_mock_mode() {
         let sources = get_default_blocklists().into_iter().enumerate().map(|(i, mut s)| {
             s.enabled = i < 2;
             s.ip_count = if s.enabled { 25000 } else { 0 };
-            s.last_updated = if s.enabled { Some("2026-01-18 10:00".to_string()) } else { None };
+            s.last_updated = if s.enabled { Some("2026-01-18T10:00:00+00:00".to_string()) } else { None };
             s
         }).collect();
         return Ok(Json(BlocklistsResponse { sources, total_ips: 50000 }));
     }
 
-    let state = get_blocklist_state();
+    let state = store::lists(&app.db, BLOCKLIST).await.map_err(db_error)?;
     let mut sources = get_default_blocklists();
     let mut total: u64 = 0;
 
     for source in &mut sources {
-        source.enabled = *state.get(&source.id).unwrap_or(&false);
+        let Some(list) = state.get(&source.id) else { continue };
+        source.enabled = list.enabled;
+        source.last_updated = list.updated_at.clone();
         if source.enabled {
-            source.ip_count = get_ipset_count(&source.id);
+            source.ip_count = list.entry_count as u32;
             total += source.ip_count as u64;
-
-            // Check last update time from file
-            let list_file = format!("{}/{}.txt", BLOCKLISTS_DIR, source.id);
-            if let Ok(metadata) = fs::metadata(&list_file) {
-                if let Ok(modified) = metadata.modified() {
-                    if let Ok(duration) = modified.duration_since(std::time::UNIX_EPOCH) {
-                        let secs = duration.as_secs();
-                        let dt = chrono::DateTime::from_timestamp(secs as i64, 0)
-                            .map(|d| d.format("%Y-%m-%d %H:%M").to_string());
-                        source.last_updated = dt;
-                    }
-                }
-            }
         }
     }
 
@@ -349,6 +505,7 @@ pub async fn blocklists() -> Result<Json<BlocklistsResponse>, (StatusCode, Strin
 
 // Toggle a blocklist on/off
 pub async fn toggle_blocklist(
+    State(app): State<Arc<AppState>>,
     Json(payload): Json<ToggleBlocklist>,
 ) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
     if mock::is_mock_mode() {
@@ -356,7 +513,6 @@ pub async fn toggle_blocklist(
     }
 
     ensure_dirs();
-    let mut state = get_blocklist_state();
 
     if payload.enabled {
         // Enable blocklist
@@ -381,49 +537,44 @@ pub async fn toggle_blocklist(
             // Parse and add IPs to ipset
             if let Ok(content) = fs::read_to_string(&list_file) {
                 // Flush existing entries
-                let _ = Command::new("sudo")
+                let _ = sudo()
                     .args(["ipset", "flush", &payload.id])
                     .output();
 
-                for line in content.lines() {
-                    let line = line.trim();
-                    // Skip comments and empty lines
-                    if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
-                        continue;
-                    }
-                    // Extract IP/CIDR (first field before any whitespace or semicolon)
-                    if let Some(ip) = line.split(|c| c == ' ' || c == '\t' || c == ';').next() {
-                        let ip = ip.trim();
-                        if !ip.is_empty() && (ip.contains('.') || ip.contains(':')) {
-                            let _ = Command::new("sudo")
-                                .args(["ipset", "add", &payload.id, ip, "-exist"])
-                                .output();
-                        }
-                    }
+                let entries = parse_blocklist(&content);
+                for ip in &entries {
+                    let _ = sudo()
+                        .args(["ipset", "add", &payload.id, ip, "-exist"])
+                        .output();
+                }
+
+                if let Err(e) = crate::reputation::record_feed(&app.db, &payload.id, &entries).await {
+                    tracing::warn!("Could not record reputation for {}: {}", payload.id, e);
                 }
+                store::record_update(&app.db, BLOCKLIST, &payload.id, entries.len()).await.map_err(db_error)?;
             }
         }
 
         // 3. Add iptables rule
         add_ipset_rule(&payload.id)?;
 
-        state.insert(payload.id.clone(), true);
+        store::set_enabled(&app.db, BLOCKLIST, &payload.id, true).await.map_err(db_error)?;
     } else {
         // Disable blocklist
         remove_ipset_rule(&payload.id)?;
 
         // Destroy ipset
-        let _ = Command::new("sudo")
+        let _ = sudo()
             .args(["ipset", "destroy", &payload.id])
             .output();
 
-        state.insert(payload.id.clone(), false);
-    }
+        let _ = crate::reputation::disable_feed(&app.db, &payload.id).await;
 
-    save_blocklist_state(&state)?;
+        store::set_enabled(&app.db, BLOCKLIST, &payload.id, false).await.map_err(db_error)?;
+    }
 
     // Save iptables rules
-    let _ = Command::new("sudo")
+    let _ = sudo()
         .args(["netfilter-persistent", "save"])
         .output();
 
@@ -431,48 +582,46 @@ pub async fn toggle_blocklist(
 }
 
 // Update all enabled blocklists
-pub async fn update_blocklists() -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+pub async fn update_blocklists(
+    State(app): State<Arc<AppState>>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
     if mock::is_mock_mode() {
         return Ok(Json(serde_json::json!({"success": true, "updated": 2, "mock": true})));
     }
 
-    let state = get_blocklist_state();
+    let enabled = enabled_lists(&app.db, BLOCKLIST).await?;
     let sources = get_default_blocklists();
     let mut updated = 0;
 
-    for (id, &enabled) in &state {
-        if enabled {
-            if let Some(source) = sources.iter().find(|s| &s.id == id) {
-                let list_file = format!("{}/{}.txt", BLOCKLISTS_DIR, id);
+    for list in &enabled {
+        let id = &list.id;
+        if let Some(source) = sources.iter().find(|s| &s.id == id) {
+            let list_file = format!("{}/{}.txt", BLOCKLISTS_DIR, id);
 
-                // Download
-                let _ = Command::new("curl")
-                    .args(["-s", "-o", &list_file, &source.url])
-                    .output();
+            // Download
+            let _ = Command::new("curl")
+                .args(["-s", "-o", &list_file, &source.url])
+                .output();
 
-                // Flush and repopulate
-                let _ = Command::new("sudo")
-                    .args(["ipset", "flush", id])
-                    .output();
+            // Flush and repopulate
+            let _ = sudo()
+                .args(["ipset", "flush", id])
+                .output();
+
+            if let Ok(content) = fs::read_to_string(&list_file) {
+                let entries = parse_blocklist(&content);
+                for ip in &entries {
+                    let _ = sudo()
+                        .args(["ipset", "add", id, ip, "-exist"])
+                        .output();
+                }
 
-                if let Ok(content) = fs::read_to_string(&list_file) {
-                    for line in content.lines() {
-                        let line = line.trim();
-                        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
-                            continue;
-                        }
-                        if let Some(ip) = line.split(|c| c == ' ' || c == '\t' || c == ';').next() {
-                            let ip = ip.trim();
-                            if !ip.is_empty() && (ip.contains('.') || ip.contains(':')) {
-                                let _ = Command::new("sudo")
-                                    .args(["ipset", "add", id, ip, "-exist"])
-                                    .output();
-                            }
-                        }
-                    }
+                if let Err(e) = crate::reputation::record_feed(&app.db, id, &entries).await {
+                    tracing::warn!("Could not record reputation for {}: {}", id, e);
                 }
-                updated += 1;
+                store::record_update(&app.db, BLOCKLIST, id, entries.len()).await.map_err(db_error)?;
             }
+            updated += 1;
         }
     }
 
@@ -480,19 +629,23 @@ pub async fn update_blocklists() -> Result<Json<serde_json::Value>, (StatusCode,
 }
 
 // Get blocked traffic log
-pub async fn blocked_log() -> Result<Json<BlockedLogResponse>, (StatusCode, String)> {
+pub async fn blocked_log(
+    State(app): State<Arc<AppState>>,
+) -> Result<Json<BlockedLogResponse>, (StatusCode, String)> {
     if mock::is_mock_mode() {
         return Ok(Json(BlockedLogResponse {
             entries: vec![
-                BlockedEntry { timestamp: "2026-01-18T10:30:00".to_string(), direction: "inbound".to_string(), src_ip: "45.155.205.100".to_string(), dst_ip: "10.22.22.1".to_string(), src_port: 45678, dst_port: 22, protocol: "TCP".to_string(), interface: "enp1s0".to_string(), reason: "spamhaus-drop".to_string(), country: Some("RU".to_string()) },
-                BlockedEntry { timestamp: "2026-01-18T10:29:00".to_string(), direction: "inbound".to_string(), src_ip: "192.168.1.100".to_string(), dst_ip: "10.22.22.1".to_string(), src_port: 12345, dst_port: 80, protocol: "TCP".to_string(), interface: "enp1s0".to_string(), reason: "emerging-threats".to_string(), country: Some("CN".to_string()) },
+                BlockedEntry { timestamp: "2026-01-18T10:30:00".to_string(), direction: "inbound".to_string(), src_ip: "45.155.205.100".to_string(), dst_ip: "10.22.22.1".to_string(), src_port: 45678, dst_port: 22, protocol: "TCP".to_string(), interface: "enp1s0".to_string(), reason: "spamhaus-drop".to_string(), country: Some("RU".to_string()), reputation: Some("Listed in 3 feeds since 2025-11".to_string()), feeds: 3 },
+                BlockedEntry { timestamp: "2026-01-18T10:29:00".to_string(), direction: "inbound".to_string(), src_ip: "192.168.1.100".to_string(), dst_ip: "10.22.22.1".to_string(), src_port: 12345, dst_port: 80, protocol: "TCP".to_string(), interface: "enp1s0".to_string(), reason: "emerging-threats".to_string(), country: Some("CN".to_string()), reputation: Some("Listed in 1 feed since 2026-01".to_string()), feeds: 1 },
             ],
             total_blocked_24h: 156,
         }));
     }
 
+    let zones = crate::zones::load(&app.db).await;
+
     // Parse kernel log for blocked entries
-    let output = Command::new("sudo")
+    let output = sudo()
         .args(["journalctl", "-k", "--since", "24 hours ago", "--no-pager", "-o", "short-iso"])
         .output()
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
@@ -517,6 +670,8 @@ pub async fn blocked_log() -> Result<Json<BlockedLogResponse>, (StatusCode, Stri
             interface: String::new(),
             reason: String::new(),
             country: None,
+            reputation: None,
+            feeds: 0,
         };
 
         // Extract timestamp (first part of line)
@@ -550,8 +705,8 @@ pub async fn blocked_log() -> Result<Json<BlockedLogResponse>, (StatusCode, Stri
             }
         }
 
-        // Determine direction based on interface
-        if entry.interface == "enp1s0" {
+        // Traffic coming in on the WAN zone is inbound, anything else is leaving the network
+        if zones.zone_of(&entry.interface) == Some(crate::interfaces::Role::Wan) {
             entry.direction = "inbound".to_string();
         } else {
             entry.direction = "outbound".to_string();
@@ -566,6 +721,15 @@ pub async fn blocked_log() -> Result<Json<BlockedLogResponse>, (StatusCode, Stri
     entries.reverse();
     entries.truncate(100);
 
+    let ips: Vec<String> = entries.iter().map(|e| e.src_ip.clone()).collect();
+    let reputations = crate::reputation::lookup_many(&app.db, &ips).await;
+    for entry in &mut entries {
+        if let Some(reputation) = reputations.get(&entry.src_ip) {
+            entry.reputation = Some(reputation.summary.clone());
+            entry.feeds = reputation.score;
+        }
+    }
+
     let total = entries.len() as u64;
 
     Ok(Json(BlockedLogResponse {
@@ -575,7 +739,9 @@ pub async fn blocked_log() -> Result<Json<BlockedLogResponse>, (StatusCode, Stri
 }
 
 // Get whitelist
-pub async fn whitelist() -> Result<Json<Vec<WhitelistEntry>>, (StatusCode, String)> {
+pub async fn whitelist(
+    State(app): State<Arc<AppState>>,
+) -> Result<Json<Vec<WhitelistEntry>>, (StatusCode, String)> {
     if mock::is_mock_mode() {
         return Ok(Json(vec![
             WhitelistEntry { ip: "8.8.8.8".to_string(), description: "Google DNS".to_string(), added_at: "2026-01-15 12:00:00".to_string() },
@@ -583,146 +749,338 @@ pub async fn whitelist() -> Result<Json<Vec<WhitelistEntry>>, (StatusCode, Strin
         ]));
     }
 
-    Ok(Json(load_whitelist()))
+    store::whitelist(&app.db).await.map(Json).map_err(db_error)
 }
 
 // Add to whitelist
 pub async fn add_whitelist(
+    State(app): State<Arc<AppState>>,
     Json(payload): Json<AddWhitelist>,
 ) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
     if mock::is_mock_mode() {
         return Ok(Json(serde_json::json!({"success": true, "mock": true})));
     }
 
-    let mut entries = load_whitelist();
-
-    // Check if already exists
-    if entries.iter().any(|e| e.ip == payload.ip) {
-        return Err((StatusCode::BAD_REQUEST, "IP already in whitelist".to_string()));
-    }
-
-    // Add to whitelist
-    entries.push(WhitelistEntry {
+    let entry = WhitelistEntry {
         ip: payload.ip.clone(),
         description: payload.description.unwrap_or_default(),
         added_at: chrono::Utc::now().format("%Y-%m-%d %H:%M:%S").to_string(),
-    });
+    };
+    if store::add_to_whitelist(&app.db, &[entry]).await.map_err(db_error)?.is_some() {
+        return Err((StatusCode::BAD_REQUEST, "IP already in whitelist".to_string()));
+    }
 
-    save_whitelist(&entries)?;
+    allow_whitelisted(&payload.ip)?;
+
+    Ok(Json(serde_json::json!({"success": true})))
+}
 
+// Let `ip` through the firewall ahead of every block rule
+fn allow_whitelisted(ip: &str) -> Result<(), (StatusCode, String)> {
     // Create whitelist ipset if doesn't exist
     create_ipset("protection-whitelist")?;
 
     // Add to ipset
-    Command::new("sudo")
-        .args(["ipset", "add", "protection-whitelist", &payload.ip, "-exist"])
+    sudo()
+        .args(["ipset", "add", "protection-whitelist", ip, "-exist"])
         .output()
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
     // Ensure whitelist rule is at top of INPUT chain (ACCEPT before any DROP)
-    let check = Command::new("sudo")
+    let check = sudo()
         .args(["iptables", "-C", "INPUT", "-m", "set", "--match-set", "protection-whitelist", "src", "-j", "ACCEPT"])
         .output();
 
     if !check.map(|o| o.status.success()).unwrap_or(false) {
-        Command::new("sudo")
+        sudo()
             .args(["iptables", "-I", "INPUT", "1", "-m", "set", "--match-set", "protection-whitelist", "src", "-j", "ACCEPT"])
             .output()
             .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
     }
 
     // Save rules
-    let _ = Command::new("sudo")
+    let _ = sudo()
         .args(["netfilter-persistent", "save"])
         .output();
 
-    Ok(Json(serde_json::json!({"success": true})))
+    Ok(())
 }
 
 // Remove from whitelist
 pub async fn remove_whitelist(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
     Json(payload): Json<RemoveWhitelist>,
 ) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
     if mock::is_mock_mode() {
         return Ok(Json(serde_json::json!({"success": true, "mock": true})));
     }
 
-    let mut entries = load_whitelist();
-    entries.retain(|e| e.ip != payload.ip);
-    save_whitelist(&entries)?;
+    if !store::is_whitelisted(&state.db, &payload.ip).await.map_err(db_error)? {
+        return Ok(Json(serde_json::json!({"success": true})));
+    }
+    let what = format!("The whitelist entry {}", payload.ip);
+    let rules_removed =
+        super::firewall::settle_dependents(&state.db, &user, None, Some(&payload.ip), payload.rules, &what).await?;
+
+    let Some(entry) = store::remove_from_whitelist(&state.db, &payload.ip).await.map_err(db_error)? else {
+        return Ok(Json(serde_json::json!({"success": true})));
+    };
+    let summary = format!("whitelist entry {}", entry.ip);
+    let undo = state.undo.record(crate::undo::UndoKind::Whitelist, summary, &[entry]);
 
     // Remove from ipset
-    let _ = Command::new("sudo")
+    let _ = sudo()
         .args(["ipset", "del", "protection-whitelist", &payload.ip])
         .output();
 
     // Save rules
-    let _ = Command::new("sudo")
+    let _ = sudo()
         .args(["netfilter-persistent", "save"])
         .output();
 
-    Ok(Json(serde_json::json!({"success": true})))
+    Ok(Json(serde_json::json!({"success": true, "undo": undo, "rules_removed": rules_removed})))
+}
+
+/// Put back whitelist entries removed moments ago, unless the address was whitelisted again since
+pub(crate) async fn restore_whitelist(pool: &SqlitePool, item: serde_json::Value) -> Result<(), (StatusCode, String)> {
+    let restored: Vec<WhitelistEntry> = serde_json::from_value(item)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    if let Some(ip) = store::add_to_whitelist(pool, &restored).await.map_err(db_error)? {
+        return Err((StatusCode::CONFLICT, format!("{} is whitelisted again, not restoring", ip)));
+    }
+    for entry in &restored {
+        allow_whitelisted(&entry.ip)?;
+    }
+    Ok(())
 }
 
 // Quick-allow an IP from blocked log (adds to whitelist and removes from current session blocks)
 pub async fn quick_allow(
+    State(app): State<Arc<AppState>>,
     Json(payload): Json<AddWhitelist>,
 ) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
     // Add to whitelist
-    add_whitelist(Json(payload)).await
+    add_whitelist(State(app), Json(payload)).await
 }
 
 // ============ COUNTRY BLOCKING ============
 
+const COMMON_COUNTRIES: &[(&str, &str)] = &[
+    ("CN", "China"),
+    ("RU", "Russia"),
+    ("KP", "North Korea"),
+    ("IR", "Iran"),
+    ("BY", "Belarus"),
+    ("VN", "Vietnam"),
+    ("IN", "India"),
+    ("BR", "Brazil"),
+    ("NL", "Netherlands"),
+    ("DE", "Germany"),
+    ("FR", "France"),
+    ("GB", "United Kingdom"),
+    ("UA", "Ukraine"),
+    ("PK", "Pakistan"),
+    ("BD", "Bangladesh"),
+    // Common picks for allow-list mode
+    ("US", "United States"),
+    ("CA", "Canada"),
+    ("MX", "Mexico"),
+    ("AU", "Australia"),
+    ("NZ", "New Zealand"),
+    ("JP", "Japan"),
+    ("KR", "South Korea"),
+    ("SG", "Singapore"),
+    ("IE", "Ireland"),
+    ("ES", "Spain"),
+    ("IT", "Italy"),
+    ("PL", "Poland"),
+    ("SE", "Sweden"),
+    ("NO", "Norway"),
+    ("CH", "Switzerland"),
+];
+
 fn get_common_countries() -> Vec<CountryBlock> {
-    vec![
-        CountryBlock { code: "CN".to_string(), name: "China".to_string(), blocked: false },
-        CountryBlock { code: "RU".to_string(), name: "Russia".to_string(), blocked: false },
-        CountryBlock { code: "KP".to_string(), name: "North Korea".to_string(), blocked: false },
-        CountryBlock { code: "IR".to_string(), name: "Iran".to_string(), blocked: false },
-        CountryBlock { code: "BY".to_string(), name: "Belarus".to_string(), blocked: false },
-        CountryBlock { code: "VN".to_string(), name: "Vietnam".to_string(), blocked: false },
-        CountryBlock { code: "IN".to_string(), name: "India".to_string(), blocked: false },
-        CountryBlock { code: "BR".to_string(), name: "Brazil".to_string(), blocked: false },
-        CountryBlock { code: "NL".to_string(), name: "Netherlands".to_string(), blocked: false },
-        CountryBlock { code: "DE".to_string(), name: "Germany".to_string(), blocked: false },
-        CountryBlock { code: "FR".to_string(), name: "France".to_string(), blocked: false },
-        CountryBlock { code: "GB".to_string(), name: "United Kingdom".to_string(), blocked: false },
-        CountryBlock { code: "UA".to_string(), name: "Ukraine".to_string(), blocked: false },
-        CountryBlock { code: "PK".to_string(), name: "Pakistan".to_string(), blocked: false },
-        CountryBlock { code: "BD".to_string(), name: "Bangladesh".to_string(), blocked: false },
-    ]
+    COMMON_COUNTRIES
+        .iter()
+        .map(|(code, name)| CountryBlock {
+            code: code.to_string(),
+            name: name.to_string(),
+            blocked: false,
+            range_count: 0,
+            last_updated: None,
+        })
+        .collect()
+}
+
+async fn get_country_policy(pool: &SqlitePool) -> CountryPolicy {
+    match crate::db::settings::get(pool, COUNTRY_POLICY_KEY).await {
+        Ok(policy) => policy.unwrap_or_default(),
+        Err(e) => {
+            tracing::warn!("Failed to read the country policy, using defaults: {}", e);
+            CountryPolicy::default()
+        }
+    }
 }
 
-fn get_country_state() -> HashMap<String, bool> {
-    let state_file = format!("{}/countries.json", BLOCKLISTS_DIR);
-    fs::read_to_string(state_file)
-        .ok()
-        .and_then(|content| serde_json::from_str(&content).ok())
-        .unwrap_or_default()
+pub(crate) fn valid_country_code(code: &str) -> bool {
+    code.len() == 2 && code.chars().all(|c| c.is_ascii_alphabetic())
 }
 
-fn save_country_state(state: &HashMap<String, bool>) -> Result<(), (StatusCode, String)> {
-    ensure_dirs();
-    let state_file = format!("{}/countries.json", BLOCKLISTS_DIR);
-    let json = serde_json::to_string_pretty(state)
+// Download a country's IP ranges from ipdeny.com and return the CIDRs
+pub(crate) fn download_country_zone(code: &str) -> Result<Vec<String>, (StatusCode, String)> {
+    let zone_url = format!("https://www.ipdeny.com/ipblocks/data/countries/{}.zone", code.to_lowercase());
+    let zone_file = format!("{}/{}.zone", BLOCKLISTS_DIR, code.to_lowercase());
+
+    let download = Command::new("curl")
+        .args(["-s", "-f", "-o", &zone_file, &zone_url])
+        .output()
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
-    fs::write(state_file, json)
+
+    if !download.status.success() {
+        return Err((StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to download IP list for {}", code)));
+    }
+
+    let content = fs::read_to_string(&zone_file)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(content
+        .lines()
+        .map(|l| l.trim())
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .map(|l| l.to_string())
+        .collect())
+}
+
+fn iptables(args: &[&str]) -> Result<(), (StatusCode, String)> {
+    let output = sudo()
+        .arg("iptables")
+        .args(args)
+        .output()
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    if !output.status.success() {
+        return Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("iptables {}: {}", args.join(" "), String::from_utf8_lossy(&output.stderr).trim()),
+        ));
+    }
+    Ok(())
+}
+
+// Build the allow-list chain and send new WAN traffic (to the router and forwarded) through it
+fn apply_allow_mode(policy: &CountryPolicy, wan: &str) -> Result<(), (StatusCode, String)> {
+    ensure_dirs();
+
+    // Download everything first so a failed download leaves the current rules alone
+    let mut ranges = Vec::new();
+    for code in &policy.allowed {
+        ranges.extend(download_country_zone(code)?);
+    }
+
+    create_ipset(GEO_ALLOW_SET)?;
+    let _ = sudo().args(["ipset", "flush", GEO_ALLOW_SET]).output();
+    for range in &ranges {
+        let _ = sudo().args(["ipset", "add", GEO_ALLOW_SET, range, "-exist"]).output();
+    }
+    // Referenced below, so it has to exist even when empty
+    create_ipset("protection-whitelist")?;
+
+    if iptables(&["-L", GEO_ALLOW_CHAIN, "-n"]).is_err() {
+        iptables(&["-N", GEO_ALLOW_CHAIN])?;
+    }
+    iptables(&["-F", GEO_ALLOW_CHAIN])?;
+
+    iptables(&["-A", GEO_ALLOW_CHAIN, "-m", "conntrack", "--ctstate", "ESTABLISHED,RELATED", "-j", "RETURN"])?;
+    for source in UNFILTERED_SOURCES {
+        iptables(&["-A", GEO_ALLOW_CHAIN, "-s", source, "-j", "RETURN"])?;
+    }
+    iptables(&["-A", GEO_ALLOW_CHAIN, "-m", "set", "--match-set", "protection-whitelist", "src", "-j", "RETURN"])?;
+    iptables(&["-A", GEO_ALLOW_CHAIN, "-m", "set", "--match-set", GEO_ALLOW_SET, "src", "-j", "RETURN"])?;
+    if !policy.vpn_ports.is_empty() {
+        let ports = policy.vpn_ports.iter().map(|p| p.to_string()).collect::<Vec<_>>().join(",");
+        iptables(&["-A", GEO_ALLOW_CHAIN, "-p", "udp", "-m", "multiport", "--dports", &ports, "-j", "RETURN"])?;
+    }
+    let log_prefix = format!("BLOCKED:{}: ", GEO_ALLOW_SET);
+    iptables(&["-A", GEO_ALLOW_CHAIN, "-j", "LOG", "--log-prefix", &log_prefix, "--log-level", "4"])?;
+    iptables(&["-A", GEO_ALLOW_CHAIN, "-j", "DROP"])?;
+
+    // Re-hook in case the WAN interface changed since the last apply
+    remove_allow_hooks();
+    for chain in ["INPUT", "FORWARD"] {
+        iptables(&["-I", chain, "1", "-i", wan, "-j", GEO_ALLOW_CHAIN])?;
+    }
     Ok(())
 }
 
+// Delete every INPUT/FORWARD rule that jumps to the allow-list chain, whatever interface it names
+fn remove_allow_hooks() {
+    for chain in ["INPUT", "FORWARD"] {
+        let rules = sudo()
+            .args(["iptables", "-S", chain])
+            .output()
+            .map(|o| String::from_utf8_lossy(&o.stdout).to_string())
+            .unwrap_or_default();
+        for rule in rules.lines().filter(|r| r.ends_with(&format!("-j {}", GEO_ALLOW_CHAIN))) {
+            let args: Vec<&str> = rule.split_whitespace().skip(2).collect();
+            let mut delete = vec!["-D", chain];
+            delete.extend(args);
+            let _ = iptables(&delete);
+        }
+    }
+}
+
+fn remove_allow_mode() {
+    remove_allow_hooks();
+    let _ = iptables(&["-F", GEO_ALLOW_CHAIN]);
+    let _ = iptables(&["-X", GEO_ALLOW_CHAIN]);
+    let _ = sudo().args(["ipset", "destroy", GEO_ALLOW_SET]).output();
+}
+
+/// Remove every blocklist and country block in `sets` (see list_sets), the allow-list mode and
+/// the whitelist from the firewall (factory reset; the database is cleared separately). Returns
+/// what could not be removed.
+pub(crate) fn teardown(sets: Vec<String>) -> Vec<String> {
+    let mut errors = Vec::new();
+
+    remove_allow_mode();
+    for set_name in sets {
+        let _ = remove_ipset_rule(&set_name);
+        if ipset_exists(&set_name) {
+            let _ = sudo().args(["ipset", "destroy", &set_name]).output();
+        }
+        if ipset_exists(&set_name) {
+            errors.push(format!("ipset {} is still in use", set_name));
+        }
+    }
+
+    let _ = sudo()
+        .args(["iptables", "-D", "INPUT", "-m", "set", "--match-set", "protection-whitelist", "src", "-j", "ACCEPT"])
+        .output();
+    let _ = sudo().args(["ipset", "destroy", "protection-whitelist"]).output();
+
+    if let Err(e) = fs::remove_dir_all(BLOCKLISTS_DIR) {
+        if e.kind() != std::io::ErrorKind::NotFound {
+            errors.push(format!("{}: {}", BLOCKLISTS_DIR, e));
+        }
+    }
+    errors
+}
+
 // Get country block status
-pub async fn countries() -> Result<Json<Vec<CountryBlock>>, (StatusCode, String)> {
+pub async fn countries(
+    State(app): State<Arc<AppState>>,
+) -> Result<Json<Vec<CountryBlock>>, (StatusCode, String)> {
     if mock::is_mock_mode() {
         return Ok(Json(get_common_countries()));
     }
 
-    let state = get_country_state();
+    let state = store::lists(&app.db, COUNTRY).await.map_err(db_error)?;
     let mut countries = get_common_countries();
 
     for country in &mut countries {
-        country.blocked = *state.get(&country.code).unwrap_or(&false);
+        let Some(list) = state.get(&country.code) else { continue };
+        country.blocked = list.enabled;
+        country.range_count = list.entry_count as u32;
+        country.last_updated = list.updated_at.clone();
     }
 
     Ok(Json(countries))
@@ -730,65 +1088,116 @@ pub async fn countries() -> Result<Json<Vec<CountryBlock>>, (StatusCode, String)
 
 // Toggle country blocking
 pub async fn toggle_country(
+    State(app): State<Arc<AppState>>,
     Json(payload): Json<ToggleCountry>,
 ) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
     if mock::is_mock_mode() {
         return Ok(Json(serde_json::json!({"success": true, "mock": true})));
     }
 
-    let mut state = get_country_state();
     let set_name = format!("country-{}", payload.code.to_lowercase());
 
+    if !valid_country_code(&payload.code) {
+        return Err((StatusCode::BAD_REQUEST, "Invalid country code".to_string()));
+    }
+
     if payload.blocked {
         // Download country IP ranges from ipdeny.com
-        let zone_url = format!("https://www.ipdeny.com/ipblocks/data/countries/{}.zone", payload.code.to_lowercase());
-        let zone_file = format!("{}/{}.zone", BLOCKLISTS_DIR, payload.code.to_lowercase());
-
-        // Download
-        let download = Command::new("curl")
-            .args(["-s", "-o", &zone_file, &zone_url])
-            .output()
-            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
-
-        if !download.status.success() {
-            return Err((StatusCode::INTERNAL_SERVER_ERROR, "Failed to download country IP list".to_string()));
-        }
+        let ranges = download_country_zone(&payload.code)?;
 
         // Create ipset
         create_ipset(&set_name)?;
 
         // Flush and populate
-        let _ = Command::new("sudo")
+        let _ = sudo()
             .args(["ipset", "flush", &set_name])
             .output();
 
-        if let Ok(content) = fs::read_to_string(&zone_file) {
-            for line in content.lines() {
-                let line = line.trim();
-                if !line.is_empty() && !line.starts_with('#') {
-                    let _ = Command::new("sudo")
-                        .args(["ipset", "add", &set_name, line, "-exist"])
-                        .output();
-                }
-            }
+        for range in &ranges {
+            let _ = sudo()
+                .args(["ipset", "add", &set_name, range, "-exist"])
+                .output();
         }
 
         // Add iptables rule
         add_ipset_rule(&set_name)?;
-        state.insert(payload.code.clone(), true);
+        store::record_update(&app.db, COUNTRY, &payload.code, ranges.len()).await.map_err(db_error)?;
+        store::set_enabled(&app.db, COUNTRY, &payload.code, true).await.map_err(db_error)?;
     } else {
         // Remove blocking
         remove_ipset_rule(&set_name)?;
-        let _ = Command::new("sudo")
+        let _ = sudo()
             .args(["ipset", "destroy", &set_name])
             .output();
-        state.insert(payload.code.clone(), false);
+        store::set_enabled(&app.db, COUNTRY, &payload.code, false).await.map_err(db_error)?;
     }
 
-    save_country_state(&state)?;
+    // Save iptables
+    let _ = sudo()
+        .args(["netfilter-persistent", "save"])
+        .output();
+
+    Ok(Json(serde_json::json!({"success": true})))
+}
+
+// Get the country mode (block selected / allow only selected)
+pub async fn country_policy(
+    State(app): State<Arc<AppState>>,
+) -> Result<Json<CountryPolicy>, (StatusCode, String)> {
+    if mock::is_mock_mode() {
+        return Ok(Json(CountryPolicy::default()));
+    }
+
+    Ok(Json(get_country_policy(&app.db).await))
+}
+
+// Switch between block mode and allow-list mode
+pub async fn set_country_policy(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    Json(payload): Json<SetCountryPolicy>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    require_permission(&user, "protection:write").map_err(|(s, m)| (s, m.to_string()))?;
+
+    if mock::is_mock_mode() {
+        return Ok(Json(serde_json::json!({"success": true, "mock": true})));
+    }
+
+    let mut allowed: Vec<String> = payload.allowed.iter().map(|c| c.trim().to_uppercase()).collect();
+    allowed.sort();
+    allowed.dedup();
+    if let Some(code) = allowed.iter().find(|c| !valid_country_code(c)) {
+        return Err((StatusCode::BAD_REQUEST, format!("Invalid country code: {}", code)));
+    }
+    if payload.mode == CountryMode::Allow && allowed.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "Select at least one country to allow".to_string()));
+    }
+
+    let policy = CountryPolicy {
+        mode: payload.mode,
+        allowed,
+        vpn_ports: match payload.vpn_ports {
+            Some(ports) => ports,
+            None => get_country_policy(&state.db).await.vpn_ports,
+        },
+    };
+
+    let wan = crate::wan::wan_interface(&state.db).await;
+    let applied = policy.clone();
+    tokio::task::spawn_blocking(move || match applied.mode {
+        CountryMode::Allow => apply_allow_mode(&applied, &wan),
+        CountryMode::Block => {
+            remove_allow_mode();
+            Ok(())
+        }
+    })
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))??;
+
+    crate::db::settings::set(&state.db, COUNTRY_POLICY_KEY, &policy).await.map_err(db_error)?;
 
     // Save iptables
-    let _ = Command::new("sudo")
+    let _ = sudo()
         .args(["netfilter-persistent", "save"])
         .output();
 
@@ -805,14 +1214,14 @@ pub async fn enable_logging() -> Result<Json<serde_json::Value>, (StatusCode, St
     // This will log any packet that's about to be dropped by the default policy
 
     // First check if we already have a general LOG rule
-    let check = Command::new("sudo")
+    let check = sudo()
         .args(["iptables", "-C", "INPUT", "-j", "LOG", "--log-prefix", "BLOCKED:firewall: ", "--log-level", "4"])
         .output();
 
     if !check.map(|o| o.status.success()).unwrap_or(false) {
         // Add LOG rule before the end of INPUT chain (right before policy kicks in)
         // Get the rule count first
-        let list = Command::new("sudo")
+        let list = sudo()
             .args(["iptables", "-L", "INPUT", "--line-numbers", "-n"])
             .output()
             .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
@@ -821,16 +1230,85 @@ pub async fn enable_logging() -> Result<Json<serde_json::Value>, (StatusCode, St
         let rule_count = lines.lines().count().saturating_sub(2) as u32;
 
         // Append LOG rule at the end (will trigger before default DROP policy)
-        Command::new("sudo")
+        sudo()
             .args(["iptables", "-A", "INPUT", "-j", "LOG", "--log-prefix", "BLOCKED:firewall: ", "--log-level", "4"])
             .output()
             .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
     }
 
     // Save rules
-    let _ = Command::new("sudo")
+    let _ = sudo()
         .args(["netfilter-persistent", "save"])
         .output();
 
     Ok(Json(serde_json::json!({"success": true})))
 }
+
+// ============ HONEYPOT PORTS ============
+
+pub async fn honeypot(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    if mock::is_mock_mode() {
+        return Ok(Json(mock::protection::honeypot()));
+    }
+
+    let active = tokio::task::spawn_blocking(crate::honeypot::active_bans)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(serde_json::json!({
+        "settings": crate::honeypot::load_settings(),
+        "set": crate::honeypot::SET_NAME,
+        "active": active,
+        "recent": state.honeypot.recent_bans(),
+    })))
+}
+
+pub async fn update_honeypot(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    Json(mut settings): Json<crate::honeypot::HoneypotSettings>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    require_permission(&user, "protection:write").map_err(|(s, m)| (s, m.to_string()))?;
+
+    settings.normalize().map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+    if mock::is_mock_mode() {
+        return Ok(Json(serde_json::json!({"success": true, "mock": true})));
+    }
+
+    let wan = crate::wan::wan_interface(&state.db).await;
+    let applied = settings.clone();
+    tokio::task::spawn_blocking(move || crate::honeypot::apply(&wan, &applied))
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    crate::honeypot::save_settings(&settings).map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    Ok(Json(serde_json::json!({"success": true})))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HoneypotUnban {
+    pub ip: String,
+}
+
+pub async fn honeypot_unban(
+    AuthUser(user): AuthUser,
+    Json(payload): Json<HoneypotUnban>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    require_permission(&user, "protection:write").map_err(|(s, m)| (s, m.to_string()))?;
+
+    if mock::is_mock_mode() {
+        return Ok(Json(serde_json::json!({"success": true, "mock": true})));
+    }
+
+    tokio::task::spawn_blocking(move || crate::honeypot::unban(&payload.ip))
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+    Ok(Json(serde_json::json!({"success": true})))
+}
@@ -0,0 +1,43 @@
+use axum::{
+    extract::{ConnectInfo, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use crate::mock;
+use crate::public_status;
+use crate::AppState;
+
+/// Unauthenticated status summary for external status pages. 404 until turned on, so a router
+/// that hasn't opted in doesn't even reveal the endpoint exists.
+pub async fn status(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+) -> Response {
+    let settings = public_status::load_settings(&state.db).await;
+    if !settings.enabled {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+    if let Err(wait) = state.public_status.admit(peer.ip().to_canonical(), settings.requests_per_minute) {
+        return (
+            StatusCode::TOO_MANY_REQUESTS,
+            [(header::RETRY_AFTER, wait.to_string())],
+            "Too many requests",
+        )
+            .into_response();
+    }
+
+    let probe = if mock::is_mock_mode() {
+        Some(mock::system::public_status_probe())
+    } else {
+        state.public_status.probe()
+    };
+    (
+        [(header::CACHE_CONTROL, "public, max-age=15")],
+        Json(public_status::build(&settings, probe)),
+    )
+        .into_response()
+}
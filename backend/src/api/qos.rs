@@ -0,0 +1,58 @@
+use axum::{extract::State, http::StatusCode, Json};
+use std::sync::Arc;
+
+use crate::mock;
+use crate::qos::{self, QosSettings};
+use crate::AppState;
+use super::{require_permission, AuthUser};
+
+/// Shaping settings with the shapers that are actually in place and their statistics
+pub async fn status(State(state): State<Arc<AppState>>) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    if mock::is_mock_mode() {
+        return Ok(Json(mock::network::qos()));
+    }
+    let settings = qos::load_settings(&state.db).await;
+    let wan = crate::wan::wan_interface(&state.db).await;
+    let status = tokio::task::spawn_blocking({
+        let wan = wan.clone();
+        move || qos::status(&wan)
+    })
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(Json(serde_json::json!({
+        "settings": settings,
+        "wan": wan,
+        "status": status,
+    })))
+}
+
+/// Replace the bandwidth, SQM switch and priorities, and rebuild the shapers
+pub async fn update(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    Json(mut payload): Json<QosSettings>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    require_permission(&user, "network:write").map_err(|(s, m)| (s, m.to_string()))?;
+    payload.normalize();
+    payload.validate().map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+    if mock::is_mock_mode() {
+        return Ok(Json(serde_json::json!({"success": true, "mock": true})));
+    }
+
+    let wan = crate::wan::wan_interface(&state.db).await;
+    let applied = payload.clone();
+    let status = tokio::task::spawn_blocking(move || qos::apply(&wan, &applied).map(|()| qos::status(&wan)))
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Could not apply traffic shaping: {}", e)))?;
+    qos::save_settings(&state.db, &payload)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    tracing::info!(
+        "User {} updated traffic shaping (enabled: {}, down {} kbit/s, up {} kbit/s, {} priority rules)",
+        user.username, payload.enabled, payload.download_kbit, payload.upload_kbit, payload.rules.len()
+    );
+    Ok(Json(serde_json::json!({"success": true, "status": status})))
+}
@@ -1,11 +1,24 @@
-use axum::{http::StatusCode, Json};
-use serde::Serialize;
+use axum::{
+    extract::{Query, State},
+    http::{header, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use serde::{Deserialize, Serialize};
 use std::process::Command;
+use std::sync::Arc;
+use crate::system::privileges::sudo;
 use std::fs;
 use std::collections::HashMap;
 
+use crate::auth::bruteforce;
+use crate::certwatch::{self, MonitorSettings};
+use crate::connlog;
+use crate::incident;
+use crate::system::ipv6;
 use crate::mock;
-use super::AuthUser;
+use crate::AppState;
+use super::{require_permission, AuthUser};
 
 #[derive(Debug, Serialize)]
 pub struct SecurityOverview {
@@ -51,6 +64,7 @@ pub struct SshSession {
 }
 
 pub async fn overview(
+    State(state): State<Arc<AppState>>,
     AuthUser(_user): AuthUser,
 ) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
     if mock::is_mock_mode() {
@@ -69,8 +83,9 @@ pub async fn overview(
     // Get active connection count
     let active_connections = get_active_connections();
 
-    // Get recent security events
-    let recent_events = get_recent_events();
+    // Get recent security events, with RouterUI's own login blocks first
+    let mut recent_events = bruteforce_events(&state);
+    recent_events.extend(get_recent_events());
 
     // Get top blocked IPs (from iptables logs if available)
     let top_blocked = get_top_blocked_ips();
@@ -91,7 +106,7 @@ pub async fn overview(
 
 fn get_firewall_drops() -> u64 {
     // Get drop count from iptables INPUT chain
-    let output = Command::new("sudo")
+    let output = sudo()
         .args(["iptables", "-L", "INPUT", "-v", "-n"])
         .output()
         .ok();
@@ -123,7 +138,7 @@ fn get_blocklist_hits() -> BlocklistHits {
     };
 
     // Get iptables rule counters for blocklists
-    let output = Command::new("sudo")
+    let output = sudo()
         .args(["iptables", "-L", "INPUT", "-v", "-n"])
         .output()
         .ok();
@@ -168,7 +183,7 @@ fn parse_packet_count(line: &str) -> u64 {
 fn get_failed_ssh_attempts() -> u64 {
     // Parse auth.log for actual failed SSH attempts (not sudo failures)
     // Exclude: localhost (127.0.0.1), our own grep commands
-    let output = Command::new("sudo")
+    let output = sudo()
         .args(["grep", "-E", "sshd.*(Failed|Invalid user)", "/var/log/auth.log"])
         .output()
         .ok();
@@ -206,7 +221,7 @@ fn get_recent_events() -> Vec<SecurityEvent> {
     let mut events = Vec::new();
 
     // Get recent auth events
-    let output = Command::new("sudo")
+    let output = sudo()
         .args(["tail", "-100", "/var/log/auth.log"])
         .output()
         .ok();
@@ -223,6 +238,25 @@ fn get_recent_events() -> Vec<SecurityEvent> {
     events
 }
 
+fn bruteforce_events(state: &AppState) -> Vec<SecurityEvent> {
+    state
+        .bruteforce
+        .recent_blocks()
+        .into_iter()
+        .map(|block| SecurityEvent {
+            timestamp: block.blocked_at,
+            event_type: "RouterUI Brute Force".to_string(),
+            details: format!(
+                "Blocked after {} failed RouterUI logins (last user '{}') until {}",
+                block.failures, block.last_username, block.expires_at
+            ),
+            source_ip: block.ip,
+            severity: "high".to_string(),
+            is_external: true,
+        })
+        .collect()
+}
+
 fn is_internal_ip(ip: &str) -> bool {
     // LAN network is 10.22.22.x - this is trusted internal
     ip.starts_with("10.22.22.") || ip == "127.0.0.1" || ip == "N/A"
@@ -415,3 +449,314 @@ pub async fn connections(
 
     Ok(Json(serde_json::to_value(connections).unwrap()))
 }
+
+// ============ CONNECTION LOG ============
+
+pub async fn connection_log(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    require_permission(&user, "security:write").map_err(|(s, m)| (s, m.to_string()))?;
+
+    if mock::is_mock_mode() {
+        return Ok(Json(mock::security::connection_log()));
+    }
+
+    let stored = connlog::summary(&state.db)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    let settings = connlog::load_settings();
+    let effective = settings.clone().limited_by(&crate::privacy::policy());
+    Ok(Json(serde_json::json!({
+        "settings": settings,
+        "effective": effective,
+        "stored": stored,
+    })))
+}
+
+pub async fn update_connection_log(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    Json(payload): Json<connlog::ConnectionLogSettings>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    require_permission(&user, "security:write").map_err(|(s, m)| (s, m.to_string()))?;
+
+    if !(1..=3650).contains(&payload.retention_days) {
+        return Err((StatusCode::BAD_REQUEST, "Retention must be between 1 and 3650 days".to_string()));
+    }
+
+    if mock::is_mock_mode() {
+        return Ok(Json(serde_json::json!({"success": true, "mock": true})));
+    }
+
+    let policy = crate::privacy::policy();
+    if payload.enabled && !policy.connection_log {
+        return Err((StatusCode::CONFLICT, "The strict privacy profile does not allow connection logging".to_string()));
+    }
+
+    connlog::save_settings(&payload).map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    // A shorter retention applies right away
+    connlog::prune(&state.db, policy.cap_days(payload.retention_days))
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    Ok(Json(serde_json::json!({"success": true, "settings": payload})))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExportQuery {
+    // csv (default) or json
+    pub format: Option<String>,
+    #[serde(flatten)]
+    pub filter: connlog::ExportFilter,
+}
+
+pub async fn export_connection_log(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    Query(mut query): Query<ExportQuery>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    require_permission(&user, "security:write").map_err(|(s, m)| (s, m.to_string()))?;
+
+    // Stored timestamps are UTC RFC 3339, so bounds are compared in the same form
+    for bound in [&mut query.filter.from, &mut query.filter.to].into_iter().flatten() {
+        *bound = chrono::DateTime::parse_from_rfc3339(bound)
+            .map_err(|_| (StatusCode::BAD_REQUEST, format!("Invalid timestamp: {}", bound)))?
+            .with_timezone(&chrono::Utc)
+            .to_rfc3339();
+    }
+
+    let records = if mock::is_mock_mode() {
+        serde_json::from_value(mock::security::connection_log_entries()).unwrap_or_default()
+    } else {
+        connlog::query(&state.db, &query.filter)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?
+    };
+
+    let stamp = chrono::Utc::now().format("%Y%m%d_%H%M%S");
+    let (content_type, filename, body) = match query.format.as_deref().unwrap_or("csv") {
+        "csv" => ("text/csv; charset=utf-8", format!("connections_{}.csv", stamp), connlog::to_csv(&records)),
+        "json" => (
+            "application/json",
+            format!("connections_{}.json", stamp),
+            serde_json::to_string_pretty(&records).map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?,
+        ),
+        other => return Err((StatusCode::BAD_REQUEST, format!("Unknown export format: {}", other))),
+    };
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, content_type.to_string()),
+            (header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", filename)),
+        ],
+        body,
+    ))
+}
+
+// ============ ROUTERUI BRUTE FORCE BLOCKS ============
+
+pub async fn bruteforce_blocks(
+    State(state): State<Arc<AppState>>,
+    AuthUser(_user): AuthUser,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    if mock::is_mock_mode() {
+        return Ok(Json(mock::security::bruteforce()));
+    }
+
+    let active = tokio::task::spawn_blocking(bruteforce::active_blocks)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(serde_json::json!({
+        "set": bruteforce::SET_NAME,
+        "active": active,
+        "recent": state.bruteforce.recent_blocks(),
+    })))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UnblockRequest {
+    pub ip: String,
+}
+
+pub async fn bruteforce_unblock(
+    AuthUser(user): AuthUser,
+    Json(payload): Json<UnblockRequest>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    require_permission(&user, "security:write").map_err(|(s, m)| (s, m.to_string()))?;
+
+    if mock::is_mock_mode() {
+        return Ok(Json(serde_json::json!({"success": true, "mock": true})));
+    }
+
+    tokio::task::spawn_blocking(move || bruteforce::unblock(&payload.ip))
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+    Ok(Json(serde_json::json!({"success": true})))
+}
+
+// ============ INCIDENT REPORTS ============
+
+#[derive(Debug, Deserialize)]
+pub struct IncidentQuery {
+    pub ip: String,
+    #[serde(default = "default_incident_hours")]
+    pub hours: u32,
+    // json (default) or html
+    pub format: Option<String>,
+}
+
+fn default_incident_hours() -> u32 {
+    24
+}
+
+pub async fn incident_report(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    Query(query): Query<IncidentQuery>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    require_permission(&user, "security:write").map_err(|(s, m)| (s, m.to_string()))?;
+
+    let ip: std::net::IpAddr = query
+        .ip
+        .trim()
+        .parse()
+        .map_err(|_| (StatusCode::BAD_REQUEST, format!("Invalid IP address: {}", query.ip)))?;
+    if !(1..=incident::MAX_HOURS).contains(&query.hours) {
+        return Err((StatusCode::BAD_REQUEST, format!("hours must be between 1 and {}", incident::MAX_HOURS)));
+    }
+
+    let report: incident::IncidentReport = if mock::is_mock_mode() {
+        serde_json::from_value(mock::security::incident_report())
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    } else {
+        incident::build(&state, ip, query.hours).await
+    };
+
+    let stamp = chrono::Utc::now().format("%Y%m%d_%H%M%S");
+    let name = report.ip.replace([':', '.'], "-");
+    let (content_type, filename, body) = match query.format.as_deref().unwrap_or("json") {
+        "json" => (
+            "application/json",
+            format!("incident_{}_{}.json", name, stamp),
+            serde_json::to_string_pretty(&report).map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?,
+        ),
+        "html" => ("text/html; charset=utf-8", format!("incident_{}_{}.html", name, stamp), incident::to_html(&report)),
+        other => return Err((StatusCode::BAD_REQUEST, format!("Unknown report format: {}", other))),
+    };
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, content_type.to_string()),
+            (header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", filename)),
+        ],
+        body,
+    ))
+}
+
+// ============ CERTIFICATE INVENTORY ============
+
+pub async fn certificates(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    require_permission(&user, "security:write").map_err(|(s, m)| (s, m.to_string()))?;
+
+    if mock::is_mock_mode() {
+        return Ok(Json(mock::security::certificates()));
+    }
+
+    let settings = certwatch::load_settings();
+    let certificates = certwatch::inventory(&state.db, &settings).await;
+
+    Ok(Json(serde_json::json!({
+        "settings": settings,
+        "certificates": certificates,
+    })))
+}
+
+pub async fn update_certificate_monitor(
+    AuthUser(user): AuthUser,
+    Json(mut payload): Json<MonitorSettings>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    require_permission(&user, "security:write").map_err(|(s, m)| (s, m.to_string()))?;
+
+    payload.normalize().map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+    if mock::is_mock_mode() {
+        return Ok(Json(serde_json::json!({"success": true, "mock": true})));
+    }
+
+    certwatch::save_settings(&payload).map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    Ok(Json(serde_json::json!({"success": true, "settings": payload})))
+}
+
+// ============ IPV6 POSTURE ============
+
+async fn posture_interfaces(state: &AppState) -> (String, String) {
+    let wan = crate::wan::wan_interface(&state.db).await;
+    let lan = crate::interfaces::lan_interface(&state.db).await;
+    (wan, lan)
+}
+
+pub async fn ipv6_posture(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    require_permission(&user, "security:write").map_err(|(s, m)| (s, m.to_string()))?;
+
+    if mock::is_mock_mode() {
+        return Ok(Json(mock::security::ipv6_posture()));
+    }
+
+    let (wan, lan) = posture_interfaces(&state).await;
+    tokio::task::spawn_blocking(move || ipv6::audit(&wan, &lan))
+        .await
+        .map(|report| Json(serde_json::json!(report)))
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PostureFixRequest {
+    // Every failing check when omitted
+    pub id: Option<String>,
+}
+
+pub async fn fix_ipv6_posture(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    Json(payload): Json<PostureFixRequest>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    require_permission(&user, "security:write").map_err(|(s, m)| (s, m.to_string()))?;
+
+    if let Some(id) = &payload.id {
+        if !ipv6::CHECK_IDS.contains(&id.as_str()) {
+            return Err((StatusCode::BAD_REQUEST, format!("Unknown check: {}", id)));
+        }
+    }
+
+    if mock::is_mock_mode() {
+        return Ok(Json(mock::security::ipv6_posture()));
+    }
+
+    let (wan, lan) = posture_interfaces(&state).await;
+    tokio::task::spawn_blocking(move || {
+        let ids: Vec<String> = match payload.id {
+            Some(id) => vec![id],
+            None => ipv6::audit(&wan, &lan).checks.into_iter().filter(|c| c.fixable).map(|c| c.id).collect(),
+        };
+        for id in ids {
+            ipv6::fix(&id, &wan, &lan).map_err(|e| format!("{}: {}", id, e))?;
+        }
+        Ok(ipv6::audit(&wan, &lan))
+    })
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .map(|report| Json(serde_json::json!(report)))
+    .map_err(|e: String| (StatusCode::INTERNAL_SERVER_ERROR, e))
+}
@@ -1,6 +1,7 @@
 use axum::{extract::Json, http::StatusCode};
 use serde::{Deserialize, Serialize};
 use std::process::Command;
+use crate::system::privileges::sudo;
 
 use crate::mock;
 
@@ -288,7 +289,7 @@ pub async fn action(
         })));
     }
 
-    let output = Command::new("sudo")
+    let output = sudo()
         .args(["systemctl", action, &payload.name])
         .output()
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
@@ -323,7 +324,7 @@ pub async fn logs(
     let lines = payload.lines.unwrap_or(50);
     let lines_str = lines.to_string();
 
-    let output = Command::new("sudo")
+    let output = sudo()
         .args(["journalctl", "-u", &payload.name, "-n", &lines_str, "--no-pager", "-o", "short-iso"])
         .output()
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
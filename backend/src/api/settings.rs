@@ -0,0 +1,187 @@
+use axum::{extract::State, http::StatusCode, Json};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{Map, Value};
+use std::sync::Arc;
+
+use crate::auth::{password_policy, session_policy};
+use crate::metrics;
+use crate::public_status;
+use crate::AppState;
+use super::{firewall, media, require_permission, AuthUser};
+
+// Settings are addressed as "<section>.<field>", e.g. "session.idle_minutes". Each section is
+// one row of the settings table, guarded by the module that owns it.
+const SECTIONS: &[(&str, &str)] = &[
+    (session_policy::SETTINGS_KEY, "users"),
+    (password_policy::SETTINGS_KEY, "users"),
+    (media::SETTINGS_KEY, "media"),
+    (firewall::SETTINGS_KEY, "firewall"),
+    (metrics::SETTINGS_KEY, "system"),
+    (public_status::SETTINGS_KEY, "system"),
+];
+
+// Read back masked; sending the mask in an update keeps the stored value
+const SECRETS: &[&str] = &["media.radarr_api_key", "media.sonarr_api_key", "media.jellyfin_api_key"];
+const SECRET_MASK: &str = "********";
+
+fn flatten(section: &str, value: Value, into: &mut Map<String, Value>) {
+    if let Value::Object(fields) = value {
+        for (field, value) in fields {
+            let key = format!("{}.{}", section, field);
+            let value = match value {
+                Value::String(s) if !s.is_empty() && SECRETS.contains(&key.as_str()) => Value::from(SECRET_MASK),
+                value => value,
+            };
+            into.insert(key, value);
+        }
+    }
+}
+
+// `current` with `fields` replaced; fields it doesn't have are refused
+fn merge<T: Serialize + DeserializeOwned>(section: &str, current: T, fields: Map<String, Value>) -> Result<T, (StatusCode, String)> {
+    let mut current = serde_json::to_value(current).unwrap_or_default();
+    let current_fields = current
+        .as_object_mut()
+        .ok_or((StatusCode::INTERNAL_SERVER_ERROR, format!("Bad {} settings", section)))?;
+    for (field, value) in fields {
+        if !current_fields.contains_key(&field) {
+            return Err((StatusCode::BAD_REQUEST, format!("Unknown setting {}.{}", section, field)));
+        }
+        current_fields.insert(field, value);
+    }
+    serde_json::from_value(current).map_err(|e| (StatusCode::BAD_REQUEST, format!("Invalid {} setting: {}", section, e)))
+}
+
+// Every setting the user may read, defaults included
+pub async fn get_settings(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+) -> Json<Map<String, Value>> {
+    let mut settings = Map::new();
+    for (section, module) in SECTIONS {
+        if require_permission(&user, &format!("{}:read", module)).is_err() {
+            continue;
+        }
+        let value = match *section {
+            session_policy::SETTINGS_KEY => serde_json::to_value(session_policy::load(&state.db).await),
+            password_policy::SETTINGS_KEY => serde_json::to_value(password_policy::load(&state.db).await),
+            media::SETTINGS_KEY => serde_json::to_value(media::load_settings(&state.db).await),
+            firewall::SETTINGS_KEY => serde_json::to_value(firewall::load_settings(&state.db).await),
+            metrics::SETTINGS_KEY => serde_json::to_value(metrics::load_settings(&state.db).await),
+            public_status::SETTINGS_KEY => serde_json::to_value(public_status::load_settings(&state.db).await),
+            _ => continue,
+        };
+        flatten(section, value.unwrap_or_default(), &mut settings);
+    }
+    Json(settings)
+}
+
+// Change some settings; keys left out keep their value. Nothing is saved unless every key is
+// known, allowed and valid.
+pub async fn update_settings(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    Json(payload): Json<Map<String, Value>>,
+) -> Result<Json<Map<String, Value>>, (StatusCode, String)> {
+    let mut changes: Map<String, Value> = Map::new();
+    for (key, value) in payload {
+        let (section, field) = key
+            .split_once('.')
+            .filter(|(section, _)| SECTIONS.iter().any(|(s, _)| s == section))
+            .ok_or_else(|| (StatusCode::BAD_REQUEST, format!("Unknown setting {}", key)))?;
+        let module = SECTIONS.iter().find(|(s, _)| *s == section).map(|(_, m)| *m).unwrap_or_default();
+        require_permission(&user, &format!("{}:write", module)).map_err(|(s, m)| (s, m.to_string()))?;
+        if SECRETS.contains(&key.as_str()) && value == SECRET_MASK {
+            continue;
+        }
+        changes
+            .entry(section.to_string())
+            .or_insert_with(|| Value::Object(Map::new()))
+            .as_object_mut()
+            .map(|fields| fields.insert(field.to_string(), value));
+    }
+
+    // Everything is merged and validated before anything is saved
+    let session = match changes.remove(session_policy::SETTINGS_KEY) {
+        Some(Value::Object(fields)) => {
+            let policy: session_policy::SessionPolicy =
+                merge(session_policy::SETTINGS_KEY, session_policy::load(&state.db).await, fields)?;
+            policy.validate().map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+            Some(policy)
+        }
+        _ => None,
+    };
+    let password = match changes.remove(password_policy::SETTINGS_KEY) {
+        Some(Value::Object(fields)) => {
+            let policy: password_policy::PasswordPolicy =
+                merge(password_policy::SETTINGS_KEY, password_policy::load(&state.db).await, fields)?;
+            policy.validate().map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+            Some(policy)
+        }
+        _ => None,
+    };
+    let media_settings = match changes.remove(media::SETTINGS_KEY) {
+        Some(Value::Object(fields)) => {
+            let mut settings: media::MediaSettings =
+                merge(media::SETTINGS_KEY, media::load_settings(&state.db).await, fields)?;
+            settings.validate().map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+            Some(settings)
+        }
+        _ => None,
+    };
+    let firewall_settings = match changes.remove(firewall::SETTINGS_KEY) {
+        Some(Value::Object(fields)) => {
+            let settings: firewall::FirewallSettings =
+                merge(firewall::SETTINGS_KEY, firewall::load_settings(&state.db).await, fields)?;
+            settings.validate().map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+            Some(settings)
+        }
+        _ => None,
+    };
+    let metrics_settings = match changes.remove(metrics::SETTINGS_KEY) {
+        Some(Value::Object(fields)) => {
+            let settings: metrics::MetricsSettings =
+                merge(metrics::SETTINGS_KEY, metrics::load_settings(&state.db).await, fields)?;
+            settings.validate().map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+            Some(settings)
+        }
+        _ => None,
+    };
+    let public_status_settings = match changes.remove(public_status::SETTINGS_KEY) {
+        Some(Value::Object(fields)) => {
+            let mut settings: public_status::PublicStatusSettings =
+                merge(public_status::SETTINGS_KEY, public_status::load_settings(&state.db).await, fields)?;
+            settings.normalize();
+            settings.validate().map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+            Some(settings)
+        }
+        _ => None,
+    };
+
+    if let Some(policy) = session {
+        session_policy::save(&state.db, &policy).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+        tracing::info!("User {} changed session settings", user.username);
+    }
+    if let Some(policy) = password {
+        password_policy::save(&state.db, &policy).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+        tracing::info!("User {} changed password settings", user.username);
+    }
+    if let Some(settings) = media_settings {
+        media::save_settings(&state.db, &settings).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+        tracing::info!("User {} changed media settings", user.username);
+    }
+    if let Some(settings) = firewall_settings {
+        firewall::save_settings(&state.db, &settings).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+        tracing::info!("User {} changed firewall settings", user.username);
+    }
+    if let Some(settings) = metrics_settings {
+        metrics::save_settings(&state.db, &settings).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+        tracing::info!("User {} changed metrics settings", user.username);
+    }
+    if let Some(settings) = public_status_settings {
+        public_status::save_settings(&state.db, &settings).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+        tracing::info!("User {} changed public status settings", user.username);
+    }
+
+    Ok(get_settings(State(state), AuthUser(user)).await)
+}
@@ -1,8 +1,10 @@
-use axum::{extract::State, http::StatusCode, Json};
+use axum::{extract::{ConnectInfo, State}, http::{HeaderMap, StatusCode}, Json};
 use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
 use std::process::Command;
 use std::sync::Arc;
 
+use crate::system::privileges::{sudo, write_system_file};
 use crate::AppState;
 
 // ============ DATA STRUCTURES ============
@@ -50,33 +52,23 @@ pub struct ConfigureRouterResponse {
 
 // ============ API ENDPOINTS ============
 
-/// Check if setup is complete
-pub async fn status(
-    State(state): State<Arc<AppState>>,
-) -> Result<Json<SetupStatus>, (StatusCode, String)> {
-    let config_exists = sqlx::query_scalar::<_, i64>(
-        "SELECT COUNT(*) FROM sqlite_master WHERE type='table' AND name='setup_config'"
-    )
-        .fetch_one(&state.db)
-        .await
-        .unwrap_or(0) > 0;
-
-    if !config_exists {
-        return Ok(Json(SetupStatus {
-            is_complete: false,
-            current_step: 1,
-            total_steps: 4,
-        }));
-    }
-
-    let setup_complete = sqlx::query_scalar::<_, String>(
+pub async fn is_setup_complete(pool: &sqlx::SqlitePool) -> bool {
+    // setup_config only exists once the wizard has saved something, so a missing table reads as None
+    sqlx::query_scalar::<_, String>(
         "SELECT value FROM setup_config WHERE key = 'setup_complete'"
     )
-        .fetch_optional(&state.db)
+        .fetch_optional(pool)
         .await
         .unwrap_or(None)
         .map(|v| v == "true")
-        .unwrap_or(false);
+        .unwrap_or(false)
+}
+
+/// Check if setup is complete
+pub async fn status(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<SetupStatus>, (StatusCode, String)> {
+    let setup_complete = is_setup_complete(&state.db).await;
 
     Ok(Json(SetupStatus {
         is_complete: setup_complete,
@@ -85,6 +77,35 @@ pub async fn status(
     }))
 }
 
+/// Environment checks the wizard shows before letting setup proceed
+pub async fn preflight() -> Json<serde_json::Value> {
+    if crate::mock::is_mock_mode() {
+        return Json(crate::mock::setup::preflight());
+    }
+
+    let report = tokio::task::spawn_blocking(crate::system::preflight::run)
+        .await
+        .unwrap_or_else(|_| crate::system::preflight::PreflightReport { ready: false, checks: Vec::new() });
+    Json(serde_json::to_value(report).unwrap_or_default())
+}
+
+/// First-boot banner: where to reach this box and the setup wizard URL
+pub async fn banner(
+    State(state): State<Arc<AppState>>,
+) -> Json<crate::discovery::Banner> {
+    Json(crate::discovery::banner(&state, crate::config::get().port).await)
+}
+
+/// Lets the wizard check the token before asking for anything else
+pub async fn verify_token(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    state.setup_guard.verify(peer.ip(), &headers)?;
+    Ok(Json(serde_json::json!({ "success": true })))
+}
+
 /// Get available network interfaces
 pub async fn get_interfaces() -> Result<Json<Vec<NetworkInterface>>, (StatusCode, String)> {
     let output = Command::new("ip")
@@ -134,14 +155,19 @@ pub async fn get_interfaces() -> Result<Json<Vec<NetworkInterface>>, (StatusCode
 /// Create admin account during setup
 pub async fn create_admin(
     State(state): State<Arc<AppState>>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
     Json(payload): Json<CreateAdminRequest>,
 ) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    state.setup_guard.verify(peer.ip(), &headers)?;
+
     if payload.username.len() < 3 {
         return Err((StatusCode::BAD_REQUEST, "Username must be at least 3 characters".to_string()));
     }
-    if payload.password.len() < 6 {
-        return Err((StatusCode::BAD_REQUEST, "Password must be at least 6 characters".to_string()));
-    }
+    crate::auth::password_policy::load(&state.db)
+        .await
+        .check(&payload.password)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
 
     let password_hash = crate::auth::hash_password(&payload.password)
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
@@ -152,7 +178,35 @@ pub async fn create_admin(
         .unwrap_or(0);
 
     if existing > 0 {
-        return Err((StatusCode::CONFLICT, "Admin account already exists".to_string()));
+        // After a factory reset the kept admin is replaced by the one chosen here
+        let kept: Option<String> = sqlx::query_scalar("SELECT value FROM setup_config WHERE key = ?")
+            .bind(crate::system::factory_reset::KEPT_ADMIN_KEY)
+            .fetch_optional(&state.db)
+            .await
+            .ok()
+            .flatten();
+        let Some(kept) = kept else {
+            return Err((StatusCode::CONFLICT, "Admin account already exists".to_string()));
+        };
+
+        sqlx::query("UPDATE users SET username = ?, password_hash = ?, password_changed_at = datetime('now'), enabled = 1
+             WHERE username = ? AND role = 'admin'")
+            .bind(&payload.username)
+            .bind(&password_hash)
+            .bind(&kept)
+            .execute(&state.db)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        sqlx::query("DELETE FROM setup_config WHERE key = ?")
+            .bind(crate::system::factory_reset::KEPT_ADMIN_KEY)
+            .execute(&state.db)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+        return Ok(Json(serde_json::json!({
+            "success": true,
+            "message": "Admin account created"
+        })));
     }
 
     sqlx::query(
@@ -173,8 +227,12 @@ pub async fn create_admin(
 /// Configure the router - main configuration endpoint
 pub async fn configure_router(
     State(state): State<Arc<AppState>>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
     Json(payload): Json<ConfigureRouterRequest>,
 ) -> Result<Json<ConfigureRouterResponse>, (StatusCode, String)> {
+    state.setup_guard.verify(peer.ip(), &headers)?;
+
     let wan = &payload.wan_interface;
     let lan = &payload.lan_interface;
 
@@ -296,7 +354,11 @@ pub async fn configure_router(
 /// Complete setup
 pub async fn complete(
     State(state): State<Arc<AppState>>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
 ) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    state.setup_guard.verify(peer.ip(), &headers)?;
+
     sqlx::query(
         "CREATE TABLE IF NOT EXISTS setup_config (
             key TEXT PRIMARY KEY,
@@ -312,6 +374,8 @@ pub async fn complete(
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
+    state.setup_guard.consume();
+
     Ok(Json(serde_json::json!({
         "success": true,
         "message": "Setup complete! You can now log in."
@@ -322,14 +386,14 @@ pub async fn complete(
 
 fn configure_lan_ip(interface: &str) -> Result<(), String> {
     // First, flush existing IP addresses on the interface
-    Command::new("ip")
-        .args(["addr", "flush", "dev", interface])
+    sudo()
+        .args(["ip", "addr", "flush", "dev", interface])
         .output()
         .map_err(|e| e.to_string())?;
 
     // Set the static IP
-    let output = Command::new("ip")
-        .args(["addr", "add", "192.168.1.1/24", "dev", interface])
+    let output = sudo()
+        .args(["ip", "addr", "add", "192.168.1.1/24", "dev", interface])
         .output()
         .map_err(|e| e.to_string())?;
 
@@ -342,8 +406,8 @@ fn configure_lan_ip(interface: &str) -> Result<(), String> {
     }
 
     // Bring interface up
-    Command::new("ip")
-        .args(["link", "set", interface, "up"])
+    sudo()
+        .args(["ip", "link", "set", interface, "up"])
         .output()
         .map_err(|e| e.to_string())?;
 
@@ -361,12 +425,12 @@ fn configure_lan_ip(interface: &str) -> Result<(), String> {
 
     // Try netplan first (Ubuntu 18.04+)
     if std::path::Path::new("/etc/netplan").exists() {
-        std::fs::write(
+        write_system_file(
             "/etc/netplan/99-routerui-lan.yaml",
             &netplan_config
         ).ok();
-        Command::new("netplan")
-            .args(["apply"])
+        sudo()
+            .args(["netplan", "apply"])
             .output()
             .ok();
     } else {
@@ -379,8 +443,7 @@ iface {} inet static
 "#,
             interface, interface
         );
-        std::fs::create_dir_all("/etc/network/interfaces.d").ok();
-        std::fs::write(
+        write_system_file(
             format!("/etc/network/interfaces.d/{}", interface),
             &interfaces_config
         ).ok();
@@ -391,7 +454,7 @@ iface {} inet static
 
 fn enable_ip_forwarding() -> Result<(), String> {
     // Enable immediately
-    std::fs::write("/proc/sys/net/ipv4/ip_forward", "1")
+    write_system_file("/proc/sys/net/ipv4/ip_forward", "1")
         .map_err(|e| e.to_string())?;
 
     // Make it persistent
@@ -405,27 +468,26 @@ fn enable_ip_forwarding() -> Result<(), String> {
         } else {
             format!("{}\nnet.ipv4.ip_forward=1\n", sysctl_content)
         };
-        std::fs::write("/etc/sysctl.conf", new_content)
+        write_system_file("/etc/sysctl.conf", new_content)
             .map_err(|e| e.to_string())?;
     }
 
     // Also write to sysctl.d for systemd systems
-    std::fs::create_dir_all("/etc/sysctl.d").ok();
-    std::fs::write("/etc/sysctl.d/99-routerui.conf", "net.ipv4.ip_forward=1\n").ok();
+    write_system_file("/etc/sysctl.d/99-routerui.conf", "net.ipv4.ip_forward=1\n").ok();
 
     Ok(())
 }
 
 fn configure_nat(wan_interface: &str) -> Result<(), String> {
     // Clear existing NAT rules for our interface
-    Command::new("iptables")
-        .args(["-t", "nat", "-D", "POSTROUTING", "-o", wan_interface, "-j", "MASQUERADE"])
+    sudo()
+        .args(["iptables", "-t", "nat", "-D", "POSTROUTING", "-o", wan_interface, "-j", "MASQUERADE"])
         .output()
         .ok(); // Ignore error if rule doesn't exist
 
     // Add NAT masquerade rule
-    let output = Command::new("iptables")
-        .args(["-t", "nat", "-A", "POSTROUTING", "-o", wan_interface, "-j", "MASQUERADE"])
+    let output = sudo()
+        .args(["iptables", "-t", "nat", "-A", "POSTROUTING", "-o", wan_interface, "-j", "MASQUERADE"])
         .output()
         .map_err(|e| e.to_string())?;
 
@@ -434,13 +496,13 @@ fn configure_nat(wan_interface: &str) -> Result<(), String> {
     }
 
     // Allow forwarding
-    Command::new("iptables")
-        .args(["-A", "FORWARD", "-i", wan_interface, "-o", wan_interface, "-m", "state", "--state", "RELATED,ESTABLISHED", "-j", "ACCEPT"])
+    sudo()
+        .args(["iptables", "-A", "FORWARD", "-i", wan_interface, "-o", wan_interface, "-m", "state", "--state", "RELATED,ESTABLISHED", "-j", "ACCEPT"])
         .output()
         .ok();
 
-    Command::new("iptables")
-        .args(["-A", "FORWARD", "-j", "ACCEPT"])
+    sudo()
+        .args(["iptables", "-A", "FORWARD", "-j", "ACCEPT"])
         .output()
         .ok();
 
@@ -486,8 +548,7 @@ no-hosts
     );
 
     // Write configuration
-    std::fs::create_dir_all("/etc/dnsmasq.d").ok();
-    std::fs::write("/etc/dnsmasq.d/routerui.conf", &config)
+    write_system_file("/etc/dnsmasq.d/routerui.conf", &config)
         .map_err(|e| e.to_string())?;
 
     // Disable default dnsmasq config that might conflict
@@ -495,7 +556,7 @@ no-hosts
     if std::path::Path::new(default_conf).exists() {
         let content = std::fs::read_to_string(default_conf).unwrap_or_default();
         if !content.contains("conf-dir=/etc/dnsmasq.d") {
-            std::fs::write(default_conf, "conf-dir=/etc/dnsmasq.d/,*.conf\n")
+            write_system_file(default_conf, "conf-dir=/etc/dnsmasq.d/,*.conf\n")
                 .map_err(|e| e.to_string())?;
         }
     }
@@ -505,26 +566,26 @@ no-hosts
 
 fn start_dnsmasq() -> Result<(), String> {
     // Stop systemd-resolved if running (conflicts with dnsmasq on port 53)
-    Command::new("systemctl")
-        .args(["stop", "systemd-resolved"])
+    sudo()
+        .args(["systemctl", "stop", "systemd-resolved"])
         .output()
         .ok();
-    Command::new("systemctl")
-        .args(["disable", "systemd-resolved"])
+    sudo()
+        .args(["systemctl", "disable", "systemd-resolved"])
         .output()
         .ok();
 
     // Update /etc/resolv.conf to use our DNS
-    std::fs::write("/etc/resolv.conf", "nameserver 127.0.0.1\n").ok();
+    write_system_file("/etc/resolv.conf", "nameserver 127.0.0.1\n").ok();
 
     // Enable and start dnsmasq
-    Command::new("systemctl")
-        .args(["enable", "dnsmasq"])
+    sudo()
+        .args(["systemctl", "enable", "dnsmasq"])
         .output()
         .map_err(|e| e.to_string())?;
 
-    let output = Command::new("systemctl")
-        .args(["restart", "dnsmasq"])
+    let output = sudo()
+        .args(["systemctl", "restart", "dnsmasq"])
         .output()
         .map_err(|e| e.to_string())?;
 
@@ -547,29 +608,24 @@ fn start_dnsmasq() -> Result<(), String> {
 
 fn save_iptables() -> Result<(), String> {
     // Save iptables rules
-    let output = Command::new("bash")
-        .args(["-c", "iptables-save > /etc/iptables/rules.v4"])
-        .output();
-
-    match output {
-        Ok(o) if o.status.success() => Ok(()),
-        Ok(o) => {
-            // Try alternative location
-            Command::new("bash")
-                .args(["-c", "mkdir -p /etc/iptables && iptables-save > /etc/iptables/rules.v4"])
-                .output()
-                .ok();
-
-            // Also try netfilter-persistent
-            Command::new("netfilter-persistent")
-                .args(["save"])
-                .output()
-                .ok();
-
-            Ok(())
-        }
-        Err(e) => Err(e.to_string()),
+    let output = sudo()
+        .arg("iptables-save")
+        .output()
+        .map_err(|e| e.to_string())?;
+
+    if output.status.success()
+        && write_system_file("/etc/iptables/rules.v4", &output.stdout).is_ok()
+    {
+        return Ok(());
     }
+
+    // Fall back to netfilter-persistent
+    sudo()
+        .args(["netfilter-persistent", "save"])
+        .output()
+        .ok();
+
+    Ok(())
 }
 
 // ============ LEGACY ENDPOINTS (kept for compatibility) ============
@@ -584,8 +640,12 @@ pub struct NetworkConfigRequest {
 /// Save network configuration (legacy endpoint)
 pub async fn save_network_config(
     State(state): State<Arc<AppState>>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
     Json(payload): Json<NetworkConfigRequest>,
 ) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    state.setup_guard.verify(peer.ip(), &headers)?;
+
     sqlx::query(
         "CREATE TABLE IF NOT EXISTS setup_config (
             key TEXT PRIMARY KEY,
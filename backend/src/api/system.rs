@@ -1,19 +1,44 @@
-use axum::{http::StatusCode, Json};
+use axum::{
+    extract::{ConnectInfo, Query, State},
+    http::{header, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
 
+use crate::cache::{self, RefreshQuery};
 use crate::mock;
 use crate::system;
-use super::AuthUser;
+use crate::wan;
+use crate::AppState;
+use super::{require_permission, AuthUser};
 
 pub async fn status(
+    State(state): State<Arc<AppState>>,
     AuthUser(_user): AuthUser,
+    Query(query): Query<RefreshQuery>,
 ) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
     if mock::is_mock_mode() {
         return Ok(Json(mock::system::status()));
     }
 
-    system::get_system_status()
-        .map(|s| Json(serde_json::to_value(s).unwrap()))
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+    let mut status = state.cache
+        .get_or_fetch(cache::SYSTEM_STATUS, query.refresh, || async {
+            system::get_system_status()
+                .map(|s| serde_json::to_value(s).unwrap())
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+        })
+        .await?;
+
+    // Current/previous public IP comes from the tracker, not the cached snapshot
+    if let (Some(map), Ok(wan_ip)) = (status.as_object_mut(), wan::status(&state).await) {
+        map.insert("wan_ip".to_string(), serde_json::to_value(wan_ip).unwrap());
+    }
+
+    Ok(Json(status))
 }
 
 pub async fn interfaces(
@@ -34,6 +59,7 @@ pub async fn services(
 
 use serde::Serialize;
 use std::process::Command;
+use crate::system::privileges::sudo;
 
 #[derive(Serialize)]
 pub struct UpdateCheckResult {
@@ -51,7 +77,7 @@ pub async fn check_updates(
     AuthUser(_user): AuthUser,
 ) -> Result<Json<UpdateCheckResult>, (StatusCode, String)> {
     // Run apt update and list upgradable packages
-    let update_output = Command::new("sudo")
+    let update_output = sudo()
         .args(["apt", "update"])
         .output()
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
@@ -81,7 +107,7 @@ pub async fn install_updates(
     AuthUser(_user): AuthUser,
 ) -> Result<Json<UpdateInstallResult>, (StatusCode, String)> {
     // Run apt upgrade with -y flag
-    let output = Command::new("sudo")
+    let output = sudo()
         .args(["apt", "upgrade", "-y"])
         .output()
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
@@ -97,3 +123,655 @@ pub async fn install_updates(
         success: output.status.success() 
     }))
 }
+
+#[derive(Serialize)]
+pub struct AboutInfo {
+    pub name: String,
+    pub version: String,
+    pub build_hash: String,
+    pub started_at: String,
+    pub uptime_seconds: i64,
+}
+
+pub async fn about(
+    State(state): State<Arc<AppState>>,
+    AuthUser(_user): AuthUser,
+) -> Json<AboutInfo> {
+    let uptime = chrono::Utc::now() - state.started_at;
+
+    Json(AboutInfo {
+        name: env!("CARGO_PKG_NAME").to_string(),
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        // Set by the release build; local builds report "dev"
+        build_hash: option_env!("ROUTERUI_BUILD_HASH").unwrap_or("dev").to_string(),
+        started_at: state.started_at.to_rfc3339(),
+        uptime_seconds: uptime.num_seconds(),
+    })
+}
+
+pub async fn api_stats(
+    State(state): State<Arc<AppState>>,
+    AuthUser(_user): AuthUser,
+) -> Json<Vec<crate::stats::RouteSummary>> {
+    Json(state.api_stats.summary())
+}
+
+pub async fn reset_api_stats(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    require_permission(&user, "system:write").map_err(|(s, m)| (s, m.to_string()))?;
+    state.api_stats.reset();
+    Ok(Json(serde_json::json!({ "success": true })))
+}
+
+#[derive(Deserialize)]
+pub struct DbInfoQuery {
+    #[serde(default)]
+    pub check: bool,
+}
+
+pub async fn db_info(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    Query(query): Query<DbInfoQuery>,
+) -> Result<Json<crate::db::maintenance::DatabaseInfo>, (StatusCode, String)> {
+    require_permission(&user, "system:write").map_err(|(s, m)| (s, m.to_string()))?;
+    crate::db::maintenance::info(&state.db, query.check)
+        .await
+        .map(Json)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+}
+
+/// Checkpoint the WAL and VACUUM now instead of waiting for the daily run to find enough free pages
+pub async fn compact_db(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+) -> Result<Json<crate::db::maintenance::Compaction>, (StatusCode, String)> {
+    require_permission(&user, "system:write").map_err(|(s, m)| (s, m.to_string()))?;
+    crate::db::maintenance::compact(&state.db)
+        .await
+        .map(Json)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+}
+
+pub async fn wan_ip(
+    State(state): State<Arc<AppState>>,
+    AuthUser(_user): AuthUser,
+    Query(query): Query<RefreshQuery>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    if mock::is_mock_mode() {
+        return Ok(Json(mock::system::wan_ip()));
+    }
+
+    // ?refresh=true re-checks now instead of waiting for the background task
+    if query.refresh {
+        let _ = wan::check(&state).await;
+    }
+
+    wan::status(&state)
+        .await
+        .map(|s| Json(serde_json::to_value(s).unwrap()))
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+}
+
+pub async fn wan_ip_history(
+    State(state): State<Arc<AppState>>,
+    AuthUser(_user): AuthUser,
+) -> Result<Json<Vec<wan::WanIpRecord>>, (StatusCode, String)> {
+    wan::history(&state.db)
+        .await
+        .map(Json)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+}
+
+pub async fn listening(
+    State(state): State<Arc<AppState>>,
+    AuthUser(_user): AuthUser,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    if mock::is_mock_mode() {
+        return Ok(Json(mock::system::listening()));
+    }
+
+    let wan_iface = wan::wan_interface(&state.db).await;
+
+    // Addresses on the WAN interface count as exposed bind addresses
+    let wan_ips: Vec<std::net::IpAddr> = system::get_interfaces()
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|i| i.name == wan_iface)
+        .flat_map(|i| i.ipv4.into_iter().chain(i.ipv6))
+        .filter_map(|cidr| cidr.split('/').next().and_then(|ip| ip.parse().ok()))
+        .collect();
+
+    let sockets = system::listening::get_listening(&wan_iface, &wan_ips)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(serde_json::json!({
+        "wan_interface": wan_iface,
+        "exposed": sockets.iter().filter(|s| s.wan_reachable).count(),
+        "sockets": sockets,
+    })))
+}
+
+// ============ PRIVILEGES ============
+
+pub async fn privileges(
+    AuthUser(user): AuthUser,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    require_permission(&user, "system:write").map_err(|(s, m)| (s, m.to_string()))?;
+
+    if mock::is_mock_mode() {
+        return Ok(Json(mock::system::privileges()));
+    }
+
+    let report = tokio::task::spawn_blocking(system::privileges::test_all)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(Json(serde_json::to_value(report).unwrap_or_default()))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CommandsQuery {
+    // Only what one API request ran
+    pub request_id: Option<String>,
+    // Only commands starting with this binary, e.g. "iptables"
+    pub command: Option<String>,
+    pub limit: Option<usize>,
+}
+
+/// Privileged commands RouterUI ran recently, newest first
+pub async fn commands(
+    AuthUser(user): AuthUser,
+    Query(query): Query<CommandsQuery>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    require_permission(&user, "system:read").map_err(|(s, m)| (s, m.to_string()))?;
+
+    if mock::is_mock_mode() {
+        return Ok(Json(mock::system::commands()));
+    }
+
+    let limit = query.limit.unwrap_or(200).min(system::commands::MAX_ENTRIES);
+    let commands: Vec<_> = system::commands::history()
+        .into_iter()
+        .filter(|c| query.request_id.as_ref().is_none_or(|id| c.request_id.as_ref() == Some(id)))
+        .filter(|c| query.command.as_ref().is_none_or(|name| &c.command == name))
+        .take(limit)
+        .collect();
+    Ok(Json(serde_json::json!({
+        "commands": commands,
+        "retention": {
+            "max_entries": system::commands::MAX_ENTRIES,
+            "max_age_hours": system::commands::MAX_AGE.as_secs() / 3600,
+        },
+    })))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SudoersQuery {
+    pub user: Option<String>,
+}
+
+pub async fn sudoers_policy(
+    AuthUser(user): AuthUser,
+    Query(query): Query<SudoersQuery>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    require_permission(&user, "system:write").map_err(|(s, m)| (s, m.to_string()))?;
+
+    let target = query.user.unwrap_or_else(|| "routerui".to_string());
+    if target.is_empty() || !target.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+        return Err((StatusCode::BAD_REQUEST, "Invalid user name".to_string()));
+    }
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "text/plain; charset=utf-8".to_string()),
+            (header::CONTENT_DISPOSITION, "attachment; filename=\"routerui.sudoers\"".to_string()),
+        ],
+        system::privileges::generate_sudoers(&target),
+    ))
+}
+
+// ============ LOGGING CONTROL ============
+
+fn log_control() -> Result<&'static crate::logging::LogControl, (StatusCode, String)> {
+    crate::logging::control()
+        .ok_or((StatusCode::SERVICE_UNAVAILABLE, "Runtime log control not initialised".to_string()))
+}
+
+pub async fn logging_status(
+    AuthUser(user): AuthUser,
+) -> Result<Json<crate::logging::LoggingStatus>, (StatusCode, String)> {
+    require_permission(&user, "system:write").map_err(|(s, m)| (s, m.to_string()))?;
+    Ok(Json(log_control()?.status()))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetLoggingRequest {
+    // Full EnvFilter string, e.g. "routerui_api=info,tower_http=warn"
+    pub filter: Option<String>,
+    // Per-module overrides added on top of the current filter, e.g. {"routerui_api::wan": "trace"}
+    #[serde(default)]
+    pub modules: BTreeMap<String, String>,
+}
+
+pub async fn set_logging(
+    AuthUser(user): AuthUser,
+    Json(payload): Json<SetLoggingRequest>,
+) -> Result<Json<crate::logging::LoggingStatus>, (StatusCode, String)> {
+    require_permission(&user, "system:write").map_err(|(s, m)| (s, m.to_string()))?;
+    let control = log_control()?;
+
+    let mut directives: Vec<String> = vec![payload.filter.unwrap_or_else(|| control.status().filter)];
+    directives.extend(payload.modules.iter().map(|(module, level)| format!("{}={}", module, level)));
+    let filter = directives.into_iter().filter(|d| !d.is_empty()).collect::<Vec<_>>().join(",");
+
+    control.set_filter(&filter).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    Ok(Json(control.status()))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DebugLoggingRequest {
+    #[serde(default = "default_debug_minutes")]
+    pub minutes: u64,
+    pub filter: Option<String>,
+}
+
+fn default_debug_minutes() -> u64 {
+    15
+}
+
+/// Temporarily raise verbosity; reverts on its own after `minutes` (max 120)
+pub async fn debug_logging(
+    AuthUser(user): AuthUser,
+    Json(payload): Json<DebugLoggingRequest>,
+) -> Result<Json<crate::logging::DebugWindow>, (StatusCode, String)> {
+    require_permission(&user, "system:write").map_err(|(s, m)| (s, m.to_string()))?;
+    let filter = payload.filter.unwrap_or_else(|| "routerui_api=debug,tower_http=debug".to_string());
+
+    log_control()?
+        .start_debug_window(&filter, std::time::Duration::from_secs(payload.minutes.clamp(1, 120) * 60))
+        .map(Json)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))
+}
+
+pub async fn reset_logging(
+    AuthUser(user): AuthUser,
+) -> Result<Json<crate::logging::LoggingStatus>, (StatusCode, String)> {
+    require_permission(&user, "system:write").map_err(|(s, m)| (s, m.to_string()))?;
+    let control = log_control()?;
+    control.reset().map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    Ok(Json(control.status()))
+}
+
+pub async fn download_log(
+    AuthUser(user): AuthUser,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    require_permission(&user, "system:write").map_err(|(s, m)| (s, m.to_string()))?;
+    let filename = format!("routerui_{}.log", chrono::Utc::now().format("%Y%m%d_%H%M%S"));
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "text/plain; charset=utf-8".to_string()),
+            (header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", filename)),
+        ],
+        log_control()?.recent_log(),
+    ))
+}
+
+// ============ REMOTE SYSLOG ============
+
+pub async fn remote_syslog(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    require_permission(&user, "system:write").map_err(|(s, m)| (s, m.to_string()))?;
+    Ok(Json(serde_json::json!({
+        "settings": crate::syslog::load_settings(&state.db).await,
+        "status": crate::syslog::status(),
+    })))
+}
+
+pub async fn set_remote_syslog(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    Json(mut payload): Json<crate::syslog::RemoteSyslogSettings>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    require_permission(&user, "system:write").map_err(|(s, m)| (s, m.to_string()))?;
+    payload.normalize();
+    payload.validate().map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    crate::syslog::save_settings(&state.db, &payload)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    tracing::info!(
+        "User {} {} remote syslog{}",
+        user.username,
+        if payload.enabled { "enabled" } else { "disabled" },
+        if payload.enabled { format!(" to {}:{}", payload.host, payload.port) } else { String::new() }
+    );
+    remote_syslog(State(state), AuthUser(user)).await
+}
+
+/// Send one message with the settings as entered, saved or not
+pub async fn test_remote_syslog(
+    AuthUser(user): AuthUser,
+    Json(mut payload): Json<crate::syslog::RemoteSyslogSettings>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    require_permission(&user, "system:write").map_err(|(s, m)| (s, m.to_string()))?;
+    payload.normalize();
+    if payload.host.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "Enter a syslog server first".to_string()));
+    }
+    payload.validate().map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+    if mock::is_mock_mode() {
+        return Ok(Json(serde_json::json!({"success": true, "mock": true})));
+    }
+
+    crate::syslog::send_test(&payload, &user.username)
+        .await
+        .map_err(|e| (StatusCode::BAD_GATEWAY, e))?;
+    Ok(Json(serde_json::json!({"success": true})))
+}
+
+// ============ PRIVACY ============
+
+fn privacy_response(profile: crate::privacy::Profile) -> serde_json::Value {
+    use crate::privacy::Profile;
+    let profiles: Vec<_> = [Profile::Standard, Profile::Reduced, Profile::Strict]
+        .into_iter()
+        .map(|p| serde_json::json!({"profile": p, "policy": p.policy()}))
+        .collect();
+    serde_json::json!({
+        "profile": profile,
+        "policy": profile.policy(),
+        "profiles": profiles,
+    })
+}
+
+pub async fn privacy(AuthUser(_user): AuthUser) -> Json<serde_json::Value> {
+    Json(privacy_response(crate::privacy::load_settings().profile))
+}
+
+pub async fn set_privacy(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    Json(payload): Json<crate::privacy::PrivacySettings>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    require_permission(&user, "system:write").map_err(|(s, m)| (s, m.to_string()))?;
+
+    if mock::is_mock_mode() {
+        return Ok(Json(privacy_response(payload.profile)));
+    }
+
+    crate::privacy::save_settings(&payload).map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    tracing::info!("Privacy profile set to {:?} by {}", payload.profile, user.username);
+
+    // Saved either way; collectors that couldn't be reached are reported so they can be retried
+    let policy = payload.profile.policy();
+    let applied: serde_json::Map<String, serde_json::Value> = crate::privacy::apply(&state.db, &policy)
+        .await
+        .into_iter()
+        .map(|(collector, result)| {
+            let value = match result {
+                Ok(()) => serde_json::json!({"ok": true}),
+                Err(e) => serde_json::json!({"ok": false, "error": e}),
+            };
+            (collector.to_string(), value)
+        })
+        .collect();
+
+    let mut response = privacy_response(payload.profile);
+    response["applied"] = applied.into();
+    Ok(Json(response))
+}
+
+// ============ MANAGEMENT ACCESS ============
+
+pub async fn public_status(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    require_permission(&user, "system:read").map_err(|(s, m)| (s, m.to_string()))?;
+    let settings = crate::public_status::load_settings(&state.db).await;
+    // What the public currently gets, so the admin can see exactly what is exposed
+    let preview = crate::public_status::build(&settings, if mock::is_mock_mode() {
+        Some(mock::system::public_status_probe())
+    } else {
+        state.public_status.probe()
+    });
+    Ok(Json(serde_json::json!({
+        "settings": settings,
+        "path": crate::public_status::PATH,
+        "preview": if settings.enabled { serde_json::to_value(preview).unwrap_or_default() } else { serde_json::Value::Null },
+    })))
+}
+
+pub async fn set_public_status(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    Json(mut payload): Json<crate::public_status::PublicStatusSettings>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    require_permission(&user, "system:write").map_err(|(s, m)| (s, m.to_string()))?;
+    payload.normalize();
+    payload.validate().map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    crate::public_status::save_settings(&state.db, &payload)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    tracing::info!(
+        "User {} {} the public status endpoint",
+        user.username,
+        if payload.enabled { "enabled" } else { "disabled" }
+    );
+    public_status(State(state), AuthUser(user)).await
+}
+
+pub async fn management_access(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    require_permission(&user, "system:read").map_err(|(s, m)| (s, m.to_string()))?;
+    let access = system::management_access::load(&state.db).await;
+    Ok(Json(serde_json::json!({
+        "settings": access,
+        // Shown next to the settings so the admin can see what they are about to allow
+        "client_ip": peer.ip().to_canonical().to_string(),
+    })))
+}
+
+pub async fn set_management_access(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    Json(mut payload): Json<system::management_access::ManagementAccess>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    require_permission(&user, "system:write").map_err(|(s, m)| (s, m.to_string()))?;
+    payload.normalize().map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+    if mock::is_mock_mode() {
+        return Ok(Json(serde_json::json!({"success": true, "mock": true})));
+    }
+
+    if !system::management_access::would_permit(&state.db, &payload, peer.ip()).await {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("This would lock you out: {} is not on an allowed interface or in the allowed sources", peer.ip().to_canonical()),
+        ));
+    }
+    system::management_access::save(&state, &payload)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    tracing::info!(
+        "User {} {} LAN-only management (interfaces: {}; allowed sources: {})",
+        user.username,
+        if payload.enabled { "turned on" } else { "turned off" },
+        payload.interfaces.join(", "),
+        if payload.allowed_sources.is_empty() { "none".to_string() } else { payload.allowed_sources.join(", ") }
+    );
+    Ok(Json(serde_json::json!({"success": true})))
+}
+
+// ============ CONFIG DRIFT ============
+
+/// Managed files edited outside RouterUI since it last wrote them
+pub async fn drift() -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    if mock::is_mock_mode() {
+        return Ok(Json(mock::system::drift()));
+    }
+    let drifted = tokio::task::spawn_blocking(system::drift::all)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(Json(serde_json::json!(drifted)))
+}
+
+/// The latest configuration lint report; `?refresh=true` runs the checks again
+pub async fn lint(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<RefreshQuery>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    if mock::is_mock_mode() {
+        return Ok(Json(mock::system::lint()));
+    }
+    let report = match state.lint.last() {
+        Some(report) if !query.refresh => report,
+        _ => crate::lint::run(&state).await,
+    };
+    Ok(Json(serde_json::to_value(report).unwrap_or_default()))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ResolveDrift {
+    pub path: String,
+    // "adopt" keeps the file as edited, "overwrite" puts RouterUI's version back
+    pub action: String,
+}
+
+pub async fn resolve_drift(
+    AuthUser(user): AuthUser,
+    Json(payload): Json<ResolveDrift>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    require_permission(&user, "system:write").map_err(|(s, m)| (s, m.to_string()))?;
+
+    if mock::is_mock_mode() {
+        return Ok(Json(serde_json::json!({"success": true, "mock": true})));
+    }
+
+    let path = payload.path.clone();
+    let result = match payload.action.as_str() {
+        "adopt" => tokio::task::spawn_blocking(move || system::drift::adopt(&path)).await,
+        "overwrite" => tokio::task::spawn_blocking(move || system::drift::overwrite(&path)).await,
+        other => return Err((StatusCode::BAD_REQUEST, format!("Unknown action {}", other))),
+    };
+    result
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+    tracing::info!("User {} resolved the outside change to {} ({})", user.username, payload.path, payload.action);
+    Ok(Json(serde_json::json!({"success": true})))
+}
+
+// ============ MAINTENANCE MODE ============
+
+pub async fn maintenance_mode(AuthUser(_user): AuthUser) -> Json<system::maintenance_mode::MaintenanceMode> {
+    Json(system::maintenance_mode::load())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetMaintenanceMode {
+    pub enabled: bool,
+    pub reason: Option<String>,
+}
+
+pub async fn set_maintenance_mode(
+    AuthUser(user): AuthUser,
+    Json(payload): Json<SetMaintenanceMode>,
+) -> Result<Json<system::maintenance_mode::MaintenanceMode>, (StatusCode, String)> {
+    require_permission(&user, "system:write").map_err(|(s, m)| (s, m.to_string()))?;
+
+    let reason = payload.reason.map(|r| r.trim().to_string()).filter(|r| !r.is_empty());
+    if reason.as_ref().is_some_and(|r| r.len() > 200) {
+        return Err((StatusCode::BAD_REQUEST, "Reason must be at most 200 characters".to_string()));
+    }
+
+    let mode = if payload.enabled {
+        system::maintenance_mode::MaintenanceMode {
+            enabled: true,
+            reason,
+            enabled_by: Some(user.username.clone()),
+            enabled_at: Some(chrono::Utc::now().to_rfc3339()),
+        }
+    } else {
+        Default::default()
+    };
+
+    if mock::is_mock_mode() {
+        return Ok(Json(mode));
+    }
+
+    system::maintenance_mode::save(&mode).map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    tracing::info!("Maintenance mode {} by {}", if mode.enabled { "enabled" } else { "disabled" }, user.username);
+
+    Ok(Json(mode))
+}
+
+// ============ FACTORY RESET ============
+
+#[derive(Debug, Deserialize)]
+pub struct FactoryResetRequest {
+    // Must be factory_reset::CONFIRM_PHRASE
+    pub confirm: String,
+    // The admin's own password, checked again
+    pub password: String,
+}
+
+pub(crate) async fn run_factory_reset(state: &AppState, keep_user_id: i64) -> serde_json::Value {
+    let report = system::factory_reset::run(&state.db, keep_user_id).await;
+    // Setup is pending again, so a new setup token is printed
+    state.setup_guard.prepare(&state.db).await;
+    state.events.emit(crate::events::Event::FactoryReset {
+        removed: report.removed.len(),
+        errors: report.errors.clone(),
+    });
+    serde_json::json!({
+        "success": report.errors.is_empty(),
+        "removed": report.removed,
+        "errors": report.errors,
+    })
+}
+
+pub async fn factory_reset(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    Json(payload): Json<FactoryResetRequest>,
+) -> Result<(StatusCode, Json<serde_json::Value>), (StatusCode, String)> {
+    use system::factory_reset::CONFIRM_PHRASE;
+
+    require_permission(&user, "system:write").map_err(|(s, m)| (s, m.to_string()))?;
+    if payload.confirm.trim() != CONFIRM_PHRASE {
+        return Err((StatusCode::BAD_REQUEST, format!("Type {} to confirm", CONFIRM_PHRASE)));
+    }
+
+    if mock::is_mock_mode() {
+        return Ok((StatusCode::OK, Json(serde_json::json!({"success": true, "removed": [], "errors": [], "mock": true}))));
+    }
+
+    let hash: Option<String> = sqlx::query_scalar("SELECT password_hash FROM users WHERE id = ? AND role = 'admin'")
+        .bind(user.id)
+        .fetch_optional(&state.db)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    if !hash.is_some_and(|h| crate::auth::verify_password(&payload.password, &h)) {
+        return Err((StatusCode::FORBIDDEN, "Password is incorrect".to_string()));
+    }
+
+    if crate::approvals::required() {
+        let action = crate::approvals::Action::FactoryReset;
+        let summary = format!("Factory reset, keeping only the admin account {}", user.username);
+        return Ok(super::approvals::park(&state, &user, action, summary, serde_json::json!({"keep_user_id": user.id})));
+    }
+
+    tracing::warn!("Factory reset requested by {}", user.username);
+    Ok((StatusCode::OK, Json(run_factory_reset(&state, user.id).await)))
+}
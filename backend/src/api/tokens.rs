@@ -0,0 +1,76 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Extension, Json,
+};
+use serde::Deserialize;
+use std::sync::Arc;
+
+use crate::auth::api_tokens::{self, Limits, Scope, TokenAuth};
+use crate::AppState;
+
+use super::AuthUser;
+
+#[derive(Debug, Deserialize)]
+pub struct CreateToken {
+    pub name: String,
+    pub scope: Scope,
+    #[serde(flatten)]
+    pub limits: Limits,
+}
+
+pub async fn list(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+) -> Result<Json<Vec<api_tokens::ApiToken>>, (StatusCode, String)> {
+    let tokens = api_tokens::list(&state.db, user.id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(Json(tokens))
+}
+
+pub async fn create(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    via_key: Option<Extension<TokenAuth>>,
+    Json(payload): Json<CreateToken>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    // A leaked key must not be able to mint replacements for itself
+    if via_key.is_some() {
+        return Err((StatusCode::FORBIDDEN, "API keys can't create other API keys".to_string()));
+    }
+
+    let (token, secret) = api_tokens::create(&state.db, user.id, &payload.name, payload.scope, &payload.limits)
+        .await
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+    tracing::info!(
+        "User {} created API key '{}' ({}{})",
+        user.username,
+        token.name,
+        token.scope,
+        if token.allowed_paths.is_empty() { "" } else { ", limited to some paths" }
+    );
+
+    Ok(Json(serde_json::json!({
+        "token": token,
+        // Shown once; only its hash is stored
+        "secret": secret,
+    })))
+}
+
+pub async fn revoke(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    Path(id): Path<i64>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let revoked = api_tokens::revoke(&state.db, user.id, id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    if !revoked {
+        return Err((StatusCode::NOT_FOUND, "API key not found".to_string()));
+    }
+
+    tracing::info!("User {} revoked API key {}", user.username, id);
+    Ok(Json(serde_json::json!({"success": true})))
+}
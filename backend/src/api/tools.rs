@@ -1,9 +1,16 @@
-use axum::{extract::Json, http::StatusCode};
+use axum::{extract::{Json, State}, http::StatusCode};
 use serde::{Deserialize, Serialize};
 use std::process::Command;
 use std::fs;
 use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
 use chrono::Utc;
+use std::sync::Arc;
+
+use super::{require_permission, AuthUser, BulkItem};
+use crate::mock;
+use crate::system::privileges::{sudo, write_system_file};
+use crate::AppState;
 
 // ============ TRAFFIC MONITOR STRUCTURES ============
 
@@ -35,6 +42,10 @@ pub struct TrafficPoint {
 pub struct PingRequest {
     pub host: String,
     pub count: Option<u32>,
+    // Kernel name, role ("wan", "vpn") or "gluetun"; None follows the default route
+    pub interface: Option<String>,
+    // 4 or 6 to force the address family
+    pub family: Option<u8>,
 }
 
 #[derive(Debug, Serialize)]
@@ -46,11 +57,15 @@ pub struct PingResult {
     pub packets_received: u32,
     pub packet_loss: f32,
     pub avg_latency: Option<f32>,
+    // Where the probe went out, when not the default route
+    pub interface: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct TracerouteRequest {
     pub host: String,
+    pub interface: Option<String>,
+    pub family: Option<u8>,
 }
 
 #[derive(Debug, Serialize)]
@@ -58,6 +73,7 @@ pub struct TracerouteResult {
     pub host: String,
     pub output: String,
     pub hops: Vec<TracerouteHop>,
+    pub interface: Option<String>,
 }
 
 #[derive(Debug, Serialize)]
@@ -82,6 +98,19 @@ pub struct DnsLookupResult {
     pub output: String,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct IpInfoRequest {
+    pub ip: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct IpInfoResult {
+    pub ip: String,
+    pub reverse_dns: Vec<String>,
+    pub whitelisted: bool,
+    pub reputation: crate::reputation::Reputation,
+}
+
 #[derive(Debug, Serialize)]
 pub struct SpeedTestResult {
     pub running: bool,
@@ -93,6 +122,15 @@ pub struct SpeedTestResult {
     pub output: String,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct LanThroughputRequest {
+    // Sends to the router, which passes the traffic on to the receiver
+    pub sender: String,
+    pub receiver: String,
+    pub port: Option<u16>,
+    pub seconds: Option<u32>,
+}
+
 // ============ SYSTEM LOGS STRUCTURES ============
 
 #[derive(Debug, Deserialize)]
@@ -123,6 +161,26 @@ pub struct BackupInfo {
     pub filename: String,
     pub created: String,
     pub size: u64,
+    pub encrypted: bool,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct CreateBackup {
+    // Seal the backup with this passphrase; it is not stored anywhere
+    #[serde(default)]
+    pub passphrase: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DownloadBackup {
+    pub filename: String,
+    #[serde(default)]
+    pub passphrase: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeleteBackups {
+    pub filenames: Vec<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -139,8 +197,20 @@ pub struct BackupConfigs {
     pub hostapd: Option<String>,
     pub iptables: Option<String>,
     pub static_leases: Option<String>,
+    #[serde(default)]
+    pub dhcp_options: Option<String>,
+    #[serde(default)]
+    pub dns_rebind: Option<String>,
     pub wol_devices: Option<String>,
+    // Backups from before the whitelist moved into the database; newer ones carry it there
+    #[serde(default)]
     pub protection_whitelist: Option<String>,
+    // Additional hostapd instances (second radio), by name; hostapd above is the default one
+    #[serde(default)]
+    pub hostapd_radios: std::collections::BTreeMap<String, String>,
+    // Hex-encoded SQLite snapshot of routerui.db (users, settings)
+    pub database: Option<String>,
+    pub database_schema: Option<i64>,
 }
 
 // ============ TRAFFIC MONITOR ENDPOINTS ============
@@ -224,16 +294,95 @@ fn parse_traffic_array(arr: Option<&serde_json::Value>) -> Vec<TrafficPoint> {
 
 // ============ DIAGNOSTICS ENDPOINTS ============
 
-pub async fn ping(Json(payload): Json<PingRequest>) -> Result<Json<PingResult>, (StatusCode, String)> {
+// Where a ping or traceroute leaves from
+enum Source {
+    DefaultRoute,
+    Interface(String),
+    // Inside the Gluetun container's network namespace, so through its VPN tunnel
+    Gluetun,
+}
+
+impl Source {
+    fn label(&self) -> Option<String> {
+        match self {
+            Source::DefaultRoute => None,
+            Source::Interface(name) => Some(name.clone()),
+            Source::Gluetun => Some(GLUETUN_CONTAINER.to_string()),
+        }
+    }
+}
+
+const GLUETUN_CONTAINER: &str = "gluetun";
+
+async fn diagnostic_source(state: &AppState, interface: Option<&str>) -> Result<Source, (StatusCode, String)> {
+    let Some(interface) = interface.map(str::trim).filter(|i| !i.is_empty()) else {
+        return Ok(Source::DefaultRoute);
+    };
+    if interface == GLUETUN_CONTAINER {
+        let running = Command::new("docker")
+            .args(["ps", "-q", "--filter", &format!("name={}", GLUETUN_CONTAINER)])
+            .output()
+            .map(|o| !o.stdout.trim_ascii().is_empty())
+            .unwrap_or(false);
+        if !running {
+            return Err((StatusCode::BAD_REQUEST, "The Gluetun container is not running".to_string()));
+        }
+        return Ok(Source::Gluetun);
+    }
+    let name = crate::interfaces::roles(&state.db)
+        .await
+        .resolve(interface)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    if !crate::interfaces::valid_name(&name) || !std::path::Path::new("/sys/class/net").join(&name).exists() {
+        return Err((StatusCode::BAD_REQUEST, format!("No interface named {}", name)));
+    }
+    Ok(Source::Interface(name))
+}
+
+fn family_flag(family: Option<u8>) -> Result<Option<&'static str>, (StatusCode, String)> {
+    match family {
+        None => Ok(None),
+        Some(4) => Ok(Some("-4")),
+        Some(6) => Ok(Some("-6")),
+        Some(_) => Err((StatusCode::BAD_REQUEST, "Address family must be 4 or 6".to_string())),
+    }
+}
+
+// `program` run from `source`; interface_flag is how the program takes a source interface
+fn diagnostic_command(program: &str, source: &Source, interface_flag: &str) -> Command {
+    match source {
+        Source::DefaultRoute => Command::new(program),
+        Source::Interface(name) => {
+            let mut command = Command::new(program);
+            command.args([interface_flag, name]);
+            command
+        }
+        Source::Gluetun => {
+            // The container ships busybox ping and traceroute, which take the same flags
+            let mut command = Command::new("docker");
+            command.args(["exec", GLUETUN_CONTAINER, program]);
+            command
+        }
+    }
+}
+
+pub async fn ping(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<PingRequest>,
+) -> Result<Json<PingResult>, (StatusCode, String)> {
     // Validate host (prevent command injection)
     if !payload.host.chars().all(|c| c.is_alphanumeric() || c == '.' || c == '-' || c == ':') {
         return Err((StatusCode::BAD_REQUEST, "Invalid hostname".to_string()));
     }
+    let family = family_flag(payload.family)?;
+    let source = diagnostic_source(&state, payload.interface.as_deref()).await?;
 
     let count = payload.count.unwrap_or(4).min(20);
     let count_str = count.to_string();
 
-    let output = Command::new("ping")
+    let mut command = diagnostic_command("ping", &source, "-I");
+    command.args(family);
+    let output = command
         .args(["-c", &count_str, "-W", "2", &payload.host])
         .output()
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
@@ -278,16 +427,24 @@ pub async fn ping(Json(payload): Json<PingRequest>) -> Result<Json<PingResult>,
         packets_received,
         packet_loss,
         avg_latency,
+        interface: source.label(),
     }))
 }
 
-pub async fn traceroute(Json(payload): Json<TracerouteRequest>) -> Result<Json<TracerouteResult>, (StatusCode, String)> {
+pub async fn traceroute(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<TracerouteRequest>,
+) -> Result<Json<TracerouteResult>, (StatusCode, String)> {
     // Validate host
     if !payload.host.chars().all(|c| c.is_alphanumeric() || c == '.' || c == '-' || c == ':') {
         return Err((StatusCode::BAD_REQUEST, "Invalid hostname".to_string()));
     }
+    let family = family_flag(payload.family)?;
+    let source = diagnostic_source(&state, payload.interface.as_deref()).await?;
 
-    let output = Command::new("traceroute")
+    let mut command = diagnostic_command("traceroute", &source, "-i");
+    command.args(family);
+    let output = command
         .args(["-m", "20", "-w", "2", &payload.host])
         .output()
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
@@ -325,6 +482,7 @@ pub async fn traceroute(Json(payload): Json<TracerouteRequest>) -> Result<Json<T
         host: payload.host,
         output: stdout,
         hops,
+        interface: source.label(),
     }))
 }
 
@@ -358,6 +516,42 @@ pub async fn dns_lookup(Json(payload): Json<DnsLookupRequest>) -> Result<Json<Dn
     }))
 }
 
+// What RouterUI knows about an address: reverse DNS, whitelist and blocklist feed history
+pub async fn ip_info(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<IpInfoRequest>,
+) -> Result<Json<IpInfoResult>, (StatusCode, String)> {
+    let ip: std::net::IpAddr = payload
+        .ip
+        .trim()
+        .parse()
+        .map_err(|_| (StatusCode::BAD_REQUEST, "Invalid IP address".to_string()))?;
+
+    // Reverse DNS is best effort; the reputation is the useful part
+    let reverse_dns = Command::new("dig")
+        .args(["+short", "-x", &ip.to_string()])
+        .output()
+        .map(|output| {
+            String::from_utf8_lossy(&output.stdout)
+                .lines()
+                .map(|s| s.trim_end_matches('.').to_string())
+                .filter(|s| !s.is_empty())
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let reputation = crate::reputation::lookup(&state.db, ip)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(IpInfoResult {
+        ip: ip.to_string(),
+        reverse_dns,
+        whitelisted: crate::api::protection::is_whitelisted(&state.db, &ip.to_string()).await,
+        reputation,
+    }))
+}
+
 pub async fn speed_test() -> Result<Json<SpeedTestResult>, (StatusCode, String)> {
     // Run speedtest-cli
     let output = Command::new("speedtest-cli")
@@ -394,6 +588,51 @@ pub async fn speed_test() -> Result<Json<SpeedTestResult>, (StatusCode, String)>
     }))
 }
 
+// iperf3 between two LAN devices through the router (see lanperf/)
+pub async fn lan_throughput(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<LanThroughputRequest>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let port = payload.port.unwrap_or(crate::lanperf::DEFAULT_PORT);
+    let seconds = payload.seconds.unwrap_or(crate::lanperf::DEFAULT_SECONDS);
+    if port == 0 {
+        return Err((StatusCode::BAD_REQUEST, "Invalid port".to_string()));
+    }
+    if !(1..=crate::lanperf::MAX_SECONDS).contains(&seconds) {
+        return Err((StatusCode::BAD_REQUEST, format!("Tests run for 1 to {} seconds", crate::lanperf::MAX_SECONDS)));
+    }
+
+    let wan = crate::wan::wan_interface(&state.db).await;
+    let subnets = tokio::task::spawn_blocking(move || crate::system::lan_subnets(&wan))
+        .await
+        .unwrap_or_default();
+    let mut devices = Vec::new();
+    for value in [&payload.sender, &payload.receiver] {
+        let ip: std::net::IpAddr = value
+            .trim()
+            .parse()
+            .map_err(|_| (StatusCode::BAD_REQUEST, format!("Invalid IP address: {}", value)))?;
+        if ip.is_loopback() || !crate::system::is_lan_address(ip, &subnets) {
+            return Err((StatusCode::BAD_REQUEST, format!("{} is not a device on the LAN", ip)));
+        }
+        devices.push(ip.to_string());
+    }
+    if devices[0] == devices[1] {
+        return Err((StatusCode::BAD_REQUEST, "Pick two different devices".to_string()));
+    }
+
+    if mock::is_mock_mode() {
+        return Ok(Json(mock::tools::lan_throughput(&devices[0], &devices[1], seconds)));
+    }
+
+    let (sender, receiver) = (devices[0].clone(), devices[1].clone());
+    let report = tokio::task::spawn_blocking(move || crate::lanperf::run(&sender, &receiver, port, seconds))
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .map_err(|e| (StatusCode::BAD_GATEWAY, e))?;
+    Ok(Json(serde_json::to_value(report).unwrap()))
+}
+
 // ============ SYSTEM LOGS ENDPOINTS ============
 
 pub async fn logs(Json(payload): Json<LogsRequest>) -> Result<Json<LogsResult>, (StatusCode, String)> {
@@ -463,22 +702,28 @@ pub async fn log_units() -> Result<Json<Vec<LogUnit>>, (StatusCode, String)> {
 
 // ============ BACKUP/RESTORE ENDPOINTS ============
 
-const BACKUP_DIR: &str = "/opt/routerui/backups";
-
-pub async fn create_backup() -> Result<Json<BackupInfo>, (StatusCode, String)> {
-    // Ensure backup directory exists
-    fs::create_dir_all(BACKUP_DIR)
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+fn backup_dir() -> &'static std::path::Path {
+    &crate::config::get().backup_dir
+}
 
+/// Collect all config files and a database snapshot (shared with routerui-cli)
+pub async fn collect_backup(pool: &sqlx::SqlitePool) -> Result<BackupData, (StatusCode, String)> {
     // Read all config files
     let dnsmasq = fs::read_to_string("/etc/dnsmasq.d/router.conf").ok();
     let hostapd = fs::read_to_string("/etc/hostapd/hostapd.conf").ok();
+    let hostapd_radios = super::network::radios()
+        .into_iter()
+        .skip(1)
+        .filter_map(|radio| Some((radio.name, fs::read_to_string(&radio.config).ok()?)))
+        .collect();
     let static_leases = fs::read_to_string("/etc/dnsmasq.d/static-leases.conf").ok();
+    let dhcp_options = fs::read_to_string(super::network::DHCP_OPTIONS_FILE).ok();
+    let dns_rebind = fs::read_to_string(super::network::DNS_REBIND_FILE).ok();
     let wol_devices = fs::read_to_string("/opt/routerui/wol-devices.json").ok();
-    let protection_whitelist = fs::read_to_string("/opt/routerui/protection-whitelist.json").ok();
 
     // Get iptables rules
-    let iptables = Command::new("iptables-save")
+    let iptables = sudo()
+        .arg("iptables-save")
         .output()
         .ok()
         .map(|o| String::from_utf8_lossy(&o.stdout).to_string());
@@ -489,8 +734,13 @@ pub async fn create_backup() -> Result<Json<BackupInfo>, (StatusCode, String)> {
         .map(|o| String::from_utf8_lossy(&o.stdout).trim().to_string())
         .unwrap_or_else(|_| "router".to_string());
 
+    // Consistent snapshot of the app database
+    let database = crate::db::backup::snapshot(pool)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Database snapshot failed: {}", e)))?;
+
     let backup = BackupData {
-        version: "1.0".to_string(),
+        version: "1.1".to_string(),
         created: Utc::now().to_rfc3339(),
         hostname,
         configs: BackupConfigs {
@@ -498,35 +748,114 @@ pub async fn create_backup() -> Result<Json<BackupInfo>, (StatusCode, String)> {
             hostapd,
             iptables,
             static_leases,
+            dhcp_options,
+            dns_rebind,
             wol_devices,
-            protection_whitelist,
+            protection_whitelist: None,
+            hostapd_radios,
+            database: Some(hex::encode(database)),
+            database_schema: Some(crate::db::schema_version()),
         },
     };
 
-    // Create filename with timestamp
-    let filename = format!("backup_{}.json", Utc::now().format("%Y%m%d_%H%M%S"));
-    let filepath = format!("{}/{}", BACKUP_DIR, filename);
+    Ok(backup)
+}
 
-    // Write backup
-    let json = serde_json::to_string_pretty(&backup)
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+// Encrypted backups are named backup_<time>.enc.json
+const ENCRYPTED_SUFFIX: &str = ".enc.json";
+
+/// Serialize a backup for writing to a file, sealed with `passphrase` if one is given
+/// (shared with routerui-cli). Key derivation is deliberately slow; call off the async runtime.
+pub fn encode_backup(backup: &BackupData, passphrase: Option<&str>) -> Result<String, String> {
+    let json = serde_json::to_string_pretty(backup).map_err(|e| e.to_string())?;
+    match passphrase {
+        Some(passphrase) => {
+            let envelope = crate::backupcrypt::seal(json.as_bytes(), &backup.created, passphrase)?;
+            serde_json::to_string_pretty(&envelope).map_err(|e| e.to_string())
+        }
+        None => Ok(json),
+    }
+}
 
-    fs::write(&filepath, &json)
+/// Parse a backup file, opening it with `passphrase` if it is encrypted (shared with routerui-cli)
+pub fn decode_backup(content: &str, passphrase: Option<&str>) -> Result<BackupData, String> {
+    let value: serde_json::Value = serde_json::from_str(content).map_err(|e| format!("Invalid backup file: {}", e))?;
+    if !crate::backupcrypt::is_encrypted(&value) {
+        return serde_json::from_value(value).map_err(|e| format!("Invalid backup file: {}", e));
+    }
+    let passphrase = passphrase.ok_or("This backup is encrypted; its passphrase is needed to open it")?;
+    let envelope: crate::backupcrypt::Envelope =
+        serde_json::from_value(value).map_err(|e| format!("Invalid encrypted backup: {}", e))?;
+    let json = crate::backupcrypt::open(&envelope, passphrase)?;
+    serde_json::from_slice(&json).map_err(|e| format!("Invalid backup file: {}", e))
+}
+
+pub async fn create_backup(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    payload: Option<Json<CreateBackup>>,
+) -> Result<Json<BackupInfo>, (StatusCode, String)> {
+    require_permission(&user, "system:write").map_err(|(s, m)| (s, m.to_string()))?;
+
+    let passphrase = payload
+        .and_then(|Json(p)| p.passphrase)
+        .filter(|p| !p.is_empty());
+    if let Some(passphrase) = &passphrase {
+        crate::backupcrypt::validate_passphrase(passphrase).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    }
+
+    // Ensure backup directory exists
+    fs::create_dir_all(backup_dir())
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
+    let backup = collect_backup(&state.db).await?;
+
+    // Create filename with timestamp
+    let encrypted = passphrase.is_some();
+    let filename = format!(
+        "backup_{}{}",
+        Utc::now().format("%Y%m%d_%H%M%S"),
+        if encrypted { ENCRYPTED_SUFFIX } else { ".json" }
+    );
+    let filepath = backup_dir().join(&filename);
+
+    let created = backup.created.clone();
+    let json = tokio::task::spawn_blocking(move || encode_backup(&backup, passphrase.as_deref()))
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    // Write backup; it holds password hashes and settings secrets, so only we may read it
+    let written = fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(&filepath)
+        .and_then(|mut file| file.write_all(json.as_bytes()));
+    if let Err(e) = written {
+        let _ = fs::remove_file(&filepath);
+        return Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string()));
+    }
+
     let size = json.len() as u64;
 
     Ok(Json(BackupInfo {
         filename,
-        created: backup.created,
+        created,
         size,
+        encrypted,
     }))
 }
 
-pub async fn list_backups() -> Result<Json<Vec<BackupInfo>>, (StatusCode, String)> {
+pub async fn list_backups(
+    AuthUser(user): AuthUser,
+) -> Result<Json<Vec<BackupInfo>>, (StatusCode, String)> {
+    require_permission(&user, "system:write").map_err(|(s, m)| (s, m.to_string()))?;
+
     let mut backups = Vec::new();
 
-    if let Ok(entries) = fs::read_dir(BACKUP_DIR) {
+    if let Ok(entries) = fs::read_dir(backup_dir()) {
         for entry in entries.flatten() {
             let path = entry.path();
             if path.extension().map(|e| e == "json").unwrap_or(false) {
@@ -540,6 +869,7 @@ pub async fn list_backups() -> Result<Json<Vec<BackupInfo>>, (StatusCode, String
                         .unwrap_or_default();
 
                     backups.push(BackupInfo {
+                        encrypted: filename.ends_with(ENCRYPTED_SUFFIX),
                         filename,
                         created,
                         size: metadata.len(),
@@ -556,36 +886,92 @@ pub async fn list_backups() -> Result<Json<Vec<BackupInfo>>, (StatusCode, String
 }
 
 pub async fn download_backup(
-    Json(payload): Json<serde_json::Value>,
+    AuthUser(user): AuthUser,
+    Json(payload): Json<DownloadBackup>,
 ) -> Result<Json<BackupData>, (StatusCode, String)> {
-    let filename = payload.get("filename")
-        .and_then(|v| v.as_str())
-        .ok_or((StatusCode::BAD_REQUEST, "Missing filename".to_string()))?;
+    require_permission(&user, "system:write").map_err(|(s, m)| (s, m.to_string()))?;
+
+    let filename = payload.filename;
 
     // Validate filename (prevent path traversal)
     if filename.contains("..") || filename.contains('/') {
         return Err((StatusCode::BAD_REQUEST, "Invalid filename".to_string()));
     }
 
-    let filepath = format!("{}/{}", BACKUP_DIR, filename);
+    let filepath = backup_dir().join(&filename);
     let content = fs::read_to_string(&filepath)
         .map_err(|e| (StatusCode::NOT_FOUND, e.to_string()))?;
 
-    let backup: BackupData = serde_json::from_str(&content)
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let passphrase = payload.passphrase.filter(|p| !p.is_empty());
+    let backup = tokio::task::spawn_blocking(move || decode_backup(&content, passphrase.as_deref()))
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
 
     Ok(Json(backup))
 }
 
 pub async fn restore_backup(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
     Json(payload): Json<BackupConfigs>,
-) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+) -> Result<(StatusCode, Json<serde_json::Value>), (StatusCode, String)> {
+    // Restoring replaces the users table, so this must never be reachable without an admin
+    require_permission(&user, "system:write").map_err(|(s, m)| (s, m.to_string()))?;
+
+    if crate::approvals::required() {
+        let value = serde_json::to_value(&payload).map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        let parts: Vec<&str> = value
+            .as_object()
+            .map(|o| o.iter().filter(|(_, v)| !v.is_null()).map(|(k, _)| k.as_str()).collect())
+            .unwrap_or_default();
+        let summary = format!("Restore a backup containing: {}", parts.join(", "));
+        return Ok(super::approvals::park(&state, &user, crate::approvals::Action::RestoreBackup, summary, value));
+    }
+
+    let (restored, errors) = apply_backup(&state.db, &payload).await?;
+
+    Ok((StatusCode::OK, Json(serde_json::json!({
+        "success": errors.is_empty(),
+        "restored": restored,
+        "errors": errors
+    }))))
+}
+
+/// Write every config present in the backup; returns (restored, errors) (shared with routerui-cli)
+pub async fn apply_backup(
+    pool: &sqlx::SqlitePool,
+    payload: &BackupConfigs,
+) -> Result<(Vec<&'static str>, Vec<String>), (StatusCode, String)> {
     let mut restored = Vec::new();
     let mut errors = Vec::new();
 
+    // Check the database snapshot up front so an incompatible backup changes nothing
+    let database = match &payload.database {
+        Some(encoded) => {
+            let bytes = hex::decode(encoded)
+                .map_err(|_| (StatusCode::BAD_REQUEST, "Invalid database snapshot encoding".to_string()))?;
+            let version = crate::db::backup::snapshot_schema_version(&bytes)
+                .await
+                .map_err(|e| (StatusCode::BAD_REQUEST, format!("Invalid database snapshot: {}", e)))?;
+            if version > crate::db::schema_version() {
+                return Err((
+                    StatusCode::CONFLICT,
+                    format!(
+                        "Backup database schema v{} is newer than this RouterUI (v{})",
+                        version,
+                        crate::db::schema_version()
+                    ),
+                ));
+            }
+            Some(bytes)
+        }
+        None => None,
+    };
+
     // Restore dnsmasq config
     if let Some(config) = &payload.dnsmasq {
-        match fs::write("/etc/dnsmasq.d/router.conf", config) {
+        match write_system_file("/etc/dnsmasq.d/router.conf", config) {
             Ok(_) => restored.push("dnsmasq"),
             Err(e) => errors.push(format!("dnsmasq: {}", e)),
         }
@@ -593,20 +979,46 @@ pub async fn restore_backup(
 
     // Restore hostapd config
     if let Some(config) = &payload.hostapd {
-        match fs::write("/etc/hostapd/hostapd.conf", config) {
+        match write_system_file("/etc/hostapd/hostapd.conf", config) {
             Ok(_) => restored.push("hostapd"),
             Err(e) => errors.push(format!("hostapd: {}", e)),
         }
     }
+    for (name, config) in &payload.hostapd_radios {
+        let Some(path) = super::network::radio_config_path(name) else {
+            errors.push(format!("hostapd radio {}: invalid name", name));
+            continue;
+        };
+        match write_system_file(&path, config) {
+            Ok(_) => restored.push("hostapd radio"),
+            Err(e) => errors.push(format!("hostapd radio {}: {}", name, e)),
+        }
+    }
 
     // Restore static leases
     if let Some(config) = &payload.static_leases {
-        match fs::write("/etc/dnsmasq.d/static-leases.conf", config) {
+        match write_system_file("/etc/dnsmasq.d/static-leases.conf", config) {
             Ok(_) => restored.push("static_leases"),
             Err(e) => errors.push(format!("static_leases: {}", e)),
         }
     }
 
+    // Restore extra DHCP options
+    if let Some(config) = &payload.dhcp_options {
+        match write_system_file(super::network::DHCP_OPTIONS_FILE, config) {
+            Ok(_) => restored.push("dhcp_options"),
+            Err(e) => errors.push(format!("dhcp_options: {}", e)),
+        }
+    }
+
+    // Restore DNS rebinding protection
+    if let Some(config) = &payload.dns_rebind {
+        match write_system_file(super::network::DNS_REBIND_FILE, config) {
+            Ok(_) => restored.push("dns_rebind"),
+            Err(e) => errors.push(format!("dns_rebind: {}", e)),
+        }
+    }
+
     // Restore WOL devices
     if let Some(config) = &payload.wol_devices {
         match fs::write("/opt/routerui/wol-devices.json", config) {
@@ -615,17 +1027,24 @@ pub async fn restore_backup(
         }
     }
 
-    // Restore protection whitelist
+    // Restore the protection whitelist of an older backup
     if let Some(config) = &payload.protection_whitelist {
-        match fs::write("/opt/routerui/protection-whitelist.json", config) {
-            Ok(_) => restored.push("protection_whitelist"),
+        let legacy = serde_json::from_str(config)
+            .map(|whitelist| crate::db::protection::LegacyState { whitelist, ..Default::default() })
+            .map_err(|e| e.to_string());
+        match legacy {
+            Ok(legacy) => match crate::db::protection::import(pool, &legacy).await {
+                Ok(_) => restored.push("protection_whitelist"),
+                Err(e) => errors.push(format!("protection_whitelist: {}", e)),
+            },
             Err(e) => errors.push(format!("protection_whitelist: {}", e)),
         }
     }
 
     // Restore iptables (requires special handling)
     if let Some(rules) = &payload.iptables {
-        let mut child = Command::new("iptables-restore")
+        let mut child = sudo()
+            .arg("iptables-restore")
             .stdin(std::process::Stdio::piped())
             .spawn()
             .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
@@ -641,16 +1060,23 @@ pub async fn restore_backup(
         }
     }
 
-    Ok(Json(serde_json::json!({
-        "success": errors.is_empty(),
-        "restored": restored,
-        "errors": errors
-    })))
+    // Restore app database (users, settings); sessions are not carried over
+    if let Some(bytes) = &database {
+        match crate::db::backup::restore(pool, bytes).await {
+            Ok(_) => restored.push("database"),
+            Err(e) => errors.push(format!("database: {}", e)),
+        }
+    }
+
+    Ok((restored, errors))
 }
 
 pub async fn delete_backup(
+    AuthUser(user): AuthUser,
     Json(payload): Json<serde_json::Value>,
 ) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    require_permission(&user, "system:write").map_err(|(s, m)| (s, m.to_string()))?;
+
     let filename = payload.get("filename")
         .and_then(|v| v.as_str())
         .ok_or((StatusCode::BAD_REQUEST, "Missing filename".to_string()))?;
@@ -660,9 +1086,62 @@ pub async fn delete_backup(
         return Err((StatusCode::BAD_REQUEST, "Invalid filename".to_string()));
     }
 
-    let filepath = format!("{}/{}", BACKUP_DIR, filename);
+    let filepath = backup_dir().join(filename);
     fs::remove_file(&filepath)
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
     Ok(Json(serde_json::json!({ "success": true })))
 }
+
+// Delete several backups at once. Each file is first moved aside (hidden from the list); if any
+// of them can't be, the ones already moved are put back and nothing is deleted
+pub async fn delete_backups(
+    AuthUser(user): AuthUser,
+    Json(payload): Json<DeleteBackups>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    require_permission(&user, "system:write").map_err(|(s, m)| (s, m.to_string()))?;
+
+    if payload.filenames.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "No backups selected".to_string()));
+    }
+
+    let mut results = Vec::new();
+    let mut staged = Vec::new();
+    for filename in &payload.filenames {
+        if filename.contains("..") || filename.contains('/') || !filename.ends_with(".json") {
+            results.push(BulkItem::failed(filename, "Invalid filename"));
+            continue;
+        }
+        if staged.iter().any(|(name, _, _)| name == filename) {
+            results.push(BulkItem::failed(filename, "Listed more than once"));
+            continue;
+        }
+        let path = backup_dir().join(filename);
+        let aside = backup_dir().join(format!("{}.deleting", filename));
+        match fs::rename(&path, &aside) {
+            Ok(()) => {
+                results.push(BulkItem::ok(filename));
+                staged.push((filename.clone(), path, aside));
+            }
+            Err(e) => results.push(BulkItem::failed(filename, e.to_string())),
+        }
+    }
+
+    if results.iter().any(|r| !r.ok) {
+        for (_, path, aside) in &staged {
+            if let Err(e) = fs::rename(aside, path) {
+                tracing::error!("Failed to put back backup {}: {}", path.display(), e);
+            }
+        }
+        BulkItem::abandon(&mut results);
+        return Ok(Json(serde_json::json!({ "success": false, "results": results })));
+    }
+
+    for (filename, _, aside) in &staged {
+        if let Err(e) = fs::remove_file(aside) {
+            tracing::warn!("Failed to remove backup {}: {}", filename, e);
+        }
+    }
+
+    Ok(Json(serde_json::json!({ "success": true, "results": results })))
+}
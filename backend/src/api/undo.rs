@@ -0,0 +1,47 @@
+use axum::{extract::State, http::StatusCode, Json};
+use serde::Deserialize;
+use std::sync::Arc;
+
+use crate::undo::{Removal, UndoKind};
+use crate::AppState;
+use super::{network, protection, require_permission, AuthUser};
+
+#[derive(Debug, Deserialize)]
+pub struct UndoRequest {
+    pub token: String,
+}
+
+// Removals that can still be undone, limited to what the user may put back
+pub async fn pending(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+) -> Json<Vec<Removal>> {
+    let removals = state
+        .undo
+        .list()
+        .into_iter()
+        .filter(|r| require_permission(&user, r.kind.permission()).is_ok())
+        .collect();
+    Json(removals)
+}
+
+pub async fn undo(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    Json(payload): Json<UndoRequest>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let gone = || (StatusCode::GONE, "Too late to undo, or it was already undone".to_string());
+    let removal = state.undo.get(&payload.token).ok_or_else(gone)?;
+    require_permission(&user, removal.kind.permission()).map_err(|(s, m)| (s, m.to_string()))?;
+    let removal = state.undo.take(&payload.token).ok_or_else(gone)?;
+
+    match removal.kind {
+        UndoKind::StaticLease => network::restore_static_leases(removal.item)?,
+        UndoKind::LocalDns => network::restore_local_dns(removal.item)?,
+        UndoKind::WolDevice => network::restore_wol_devices(removal.item)?,
+        UndoKind::Whitelist => protection::restore_whitelist(&state.db, removal.item).await?,
+    }
+    tracing::info!("User {} undid the removal of {}", user.username, removal.summary);
+
+    Ok(Json(serde_json::json!({"success": true, "restored": removal.summary})))
+}
@@ -1,41 +1,40 @@
 use axum::{
-    extract::{Path, State},
+    extract::{Path, Query, State},
     http::StatusCode,
     Json,
 };
+use serde::Deserialize;
 use std::sync::Arc;
 
 use crate::{
-    auth,
+    approvals::Action,
+    auth::{self, login_history, permissions, session_cleanup},
     models::{User, UserCreate, UserPublic, UserUpdate, PasswordStrength},
     AppState,
 };
 
-use super::{require_role, AuthUser};
+use super::{require_permission, AuthUser};
 
 pub async fn list(
     State(state): State<Arc<AppState>>,
     AuthUser(user): AuthUser,
 ) -> Result<Json<Vec<UserPublic>>, (StatusCode, &'static str)> {
-    require_role(&user, &["admin"])?;
+    require_permission(&user, "users:write")?;
 
-    let users: Vec<User> = sqlx::query_as(
+    let mut users: Vec<User> = sqlx::query_as(
         "SELECT id, username, password_hash, role, enabled, created_at, last_login FROM users ORDER BY id"
     )
     .fetch_all(&state.db)
     .await
     .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Database error"))?;
 
-    Ok(Json(
-        users
-            .into_iter()
-            .map(|u| UserPublic {
-                id: u.id,
-                username: u.username,
-                role: u.role,
-            })
-            .collect(),
-    ))
+    for u in users.iter_mut().filter(|u| u.role == "custom") {
+        u.permissions = permissions::load(&state.db, u.id)
+            .await
+            .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Database error"))?;
+    }
+
+    Ok(Json(users.into_iter().map(UserPublic::from).collect()))
 }
 
 pub async fn get(
@@ -45,23 +44,43 @@ pub async fn get(
 ) -> Result<Json<UserPublic>, (StatusCode, &'static str)> {
     // Users can view themselves, admins can view anyone
     if user.id != id {
-        require_role(&user, &["admin"])?;
+        require_permission(&user, "users:write")?;
     }
 
-    let target: User = sqlx::query_as(
-        "SELECT id, username, password_hash, role, enabled, created_at, last_login FROM users WHERE id = ?"
-    )
-    .bind(id)
-    .fetch_optional(&state.db)
-    .await
-    .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Database error"))?
-    .ok_or((StatusCode::NOT_FOUND, "User not found"))?;
-
-    Ok(Json(UserPublic {
-        id: target.id,
-        username: target.username,
-        role: target.role,
-    }))
+    let target = crate::db::get_user_by_id(&state.db, id)
+        .await
+        .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Database error"))?
+        .ok_or((StatusCode::NOT_FOUND, "User not found"))?;
+
+    Ok(Json(UserPublic::from(target)))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LoginsQuery {
+    pub limit: Option<i64>,
+}
+
+// Sign-in attempts on an account, newest first
+pub async fn logins(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    Path(id): Path<i64>,
+    Query(query): Query<LoginsQuery>,
+) -> Result<Json<Vec<login_history::LoginAttempt>>, (StatusCode, &'static str)> {
+    // Users can review their own account, admins anyone's
+    if user.id != id {
+        require_permission(&user, "users:write")?;
+    }
+
+    crate::db::get_user_by_id(&state.db, id)
+        .await
+        .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Database error"))?
+        .ok_or((StatusCode::NOT_FOUND, "User not found"))?;
+
+    let attempts = login_history::for_user(&state.db, id, query.limit.unwrap_or(100))
+        .await
+        .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Database error"))?;
+    Ok(Json(attempts))
 }
 
 pub async fn create(
@@ -69,13 +88,21 @@ pub async fn create(
     AuthUser(user): AuthUser,
     Json(payload): Json<UserCreate>,
 ) -> Result<Json<UserPublic>, (StatusCode, String)> {
-    require_role(&user, &["admin"])
+    require_permission(&user, "users:write")
         .map_err(|(s, m)| (s, m.to_string()))?;
 
     // Validate role
-    if !["admin", "operator", "viewer"].contains(&payload.role.as_str()) {
+    if !permissions::ROLES.contains(&payload.role.as_str()) {
         return Err((StatusCode::BAD_REQUEST, "Invalid role".to_string()));
     }
+    for permission in &payload.permissions {
+        permissions::parse(permission).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    }
+
+    auth::password_policy::load(&state.db)
+        .await
+        .check(&payload.password)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
 
     // Hash password
     let password_hash = auth::hash_password(&payload.password)
@@ -97,11 +124,58 @@ pub async fn create(
         }
     })?;
 
-    Ok(Json(UserPublic {
-        id: result.last_insert_rowid(),
-        username: payload.username,
-        role: payload.role,
-    }))
+    let id = result.last_insert_rowid();
+    if payload.role == "custom" {
+        permissions::save(&state.db, id, &payload.permissions)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    }
+
+    let created = crate::db::get_user_by_id(&state.db, id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or((StatusCode::INTERNAL_SERVER_ERROR, "User vanished after creation".to_string()))?;
+    Ok(Json(UserPublic::from(created)))
+}
+
+// Admin rights taken away from an enabled admin need a second admin while two-person
+// confirmation is on; Some(summary) when this change is one of those
+async fn removes_admin(state: &AppState, id: i64, update: Option<&UserUpdate>) -> Result<Option<String>, (StatusCode, String)> {
+    if !crate::approvals::required() {
+        return Ok(None);
+    }
+    let target = crate::db::get_user_by_id(&state.db, id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .filter(|u| u.role == "admin" && u.enabled);
+    let Some(target) = target else { return Ok(None) };
+    let summary = match update {
+        None => format!("Delete admin {}", target.username),
+        Some(u) if u.enabled == Some(false) => format!("Disable admin {}", target.username),
+        Some(u) if u.role.as_deref().is_some_and(|r| r != "admin") => {
+            format!("Change admin {} to {}", target.username, u.role.as_deref().unwrap_or_default())
+        }
+        Some(_) => return Ok(None),
+    };
+    Ok(Some(summary))
+}
+
+fn validate_update(payload: &UserUpdate) -> Result<(), (StatusCode, String)> {
+    if payload.role.as_deref().is_some_and(|role| !permissions::ROLES.contains(&role)) {
+        return Err((StatusCode::BAD_REQUEST, "Invalid role".to_string()));
+    }
+    for permission in payload.permissions.iter().flatten() {
+        permissions::parse(permission).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    }
+    if payload.username.is_none()
+        && payload.password.is_none()
+        && payload.role.is_none()
+        && payload.enabled.is_none()
+        && payload.permissions.is_none()
+    {
+        return Err((StatusCode::BAD_REQUEST, "No fields to update".to_string()));
+    }
+    Ok(())
 }
 
 pub async fn update(
@@ -109,21 +183,39 @@ pub async fn update(
     AuthUser(user): AuthUser,
     Path(id): Path<i64>,
     Json(payload): Json<UserUpdate>,
-) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+) -> Result<(StatusCode, Json<serde_json::Value>), (StatusCode, String)> {
     // Users can update themselves (limited), admins can update anyone
     let is_self = user.id == id;
     if !is_self {
-        require_role(&user, &["admin"])
+        require_permission(&user, "users:write")
             .map_err(|(s, m)| (s, m.to_string()))?;
     }
 
-    // Non-admins can only change their password
-    if is_self && user.role != "admin" {
-        if payload.role.is_some() || payload.enabled.is_some() || payload.username.is_some() {
-            return Err((StatusCode::FORBIDDEN, "Can only change password".to_string()));
-        }
+    // Your own password is changed at /api/auth/password, which asks for the current one and
+    // signs out your other sessions; here it would skip both
+    if is_self && payload.password.is_some() {
+        return Err((StatusCode::BAD_REQUEST, "Change your own password at /api/auth/password".to_string()));
     }
+    // Without users:write, there is nothing else to change about yourself
+    if is_self
+        && !permissions::allows(&user, "users", permissions::Access::Write)
+        && (payload.role.is_some() || payload.enabled.is_some() || payload.username.is_some() || payload.permissions.is_some())
+    {
+        return Err((StatusCode::FORBIDDEN, "Requires the users:write permission".to_string()));
+    }
+    validate_update(&payload)?;
 
+    if let Some(summary) = removes_admin(&state, id, Some(&payload)).await? {
+        let request = serde_json::json!({"user_id": id, "update": payload});
+        return Ok(super::approvals::park(&state, &user, Action::RemoveAdmin, summary, request));
+    }
+
+    apply_update(&state, &user.username, id, &payload).await?;
+    Ok((StatusCode::OK, Json(serde_json::json!({ "success": true }))))
+}
+
+/// Write a checked update to user `id`; also run once a parked one is approved
+pub(crate) async fn apply_update(state: &AppState, actor: &str, id: i64, payload: &UserUpdate) -> Result<(), (StatusCode, String)> {
     // Build update query dynamically
     let mut updates = Vec::new();
     let mut values: Vec<String> = Vec::new();
@@ -134,16 +226,18 @@ pub async fn update(
     }
 
     if let Some(ref password) = payload.password {
+        auth::password_policy::load(&state.db)
+            .await
+            .check(password)
+            .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
         let hash = auth::hash_password(password)
             .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
         updates.push("password_hash = ?");
         values.push(hash);
+        updates.push("password_changed_at = datetime('now')");
     }
 
     if let Some(ref role) = payload.role {
-        if !["admin", "operator", "viewer"].contains(&role.as_str()) {
-            return Err((StatusCode::BAD_REQUEST, "Invalid role".to_string()));
-        }
         updates.push("role = ?");
         values.push(role.clone());
     }
@@ -153,54 +247,112 @@ pub async fn update(
         values.push(if enabled { "1" } else { "0" }.to_string());
     }
 
-    if updates.is_empty() {
-        return Err((StatusCode::BAD_REQUEST, "No fields to update".to_string()));
+    if !updates.is_empty() {
+        let query = format!("UPDATE users SET {} WHERE id = ?", updates.join(", "));
+
+        let mut q = sqlx::query(&query);
+        for v in &values {
+            q = q.bind(v);
+        }
+        q = q.bind(id);
+
+        q.execute(&state.db)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
     }
 
-    let query = format!("UPDATE users SET {} WHERE id = ?", updates.join(", "));
-    
-    let mut q = sqlx::query(&query);
-    for v in &values {
-        q = q.bind(v);
+    // A reset password or a disabled account signs the user out everywhere
+    if payload.password.is_some() || payload.enabled == Some(false) {
+        let revoked = auth::revoke_other_sessions(&state.db, id, None)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        tracing::info!("User {} updated user {}; {} session(s) signed out", actor, id, revoked);
     }
-    q = q.bind(id);
 
-    q.execute(&state.db)
+    // Grants only apply to the custom role; switching away from it drops them
+    let role: Option<String> = sqlx::query_scalar("SELECT role FROM users WHERE id = ?")
+        .bind(id)
+        .fetch_optional(&state.db)
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    match (role.as_deref(), &payload.permissions) {
+        (Some("custom"), Some(granted)) => permissions::save(&state.db, id, granted)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?,
+        (Some("custom"), None) => {}
+        _ => permissions::save(&state.db, id, &[])
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?,
+    }
 
-    Ok(Json(serde_json::json!({ "success": true })))
+    Ok(())
 }
 
 pub async fn delete(
     State(state): State<Arc<AppState>>,
     AuthUser(user): AuthUser,
     Path(id): Path<i64>,
-) -> Result<Json<serde_json::Value>, (StatusCode, &'static str)> {
-    require_role(&user, &["admin"])?;
+) -> Result<(StatusCode, Json<serde_json::Value>), (StatusCode, String)> {
+    require_permission(&user, "users:write").map_err(|(s, m)| (s, m.to_string()))?;
 
     // Can't delete yourself
     if user.id == id {
-        return Err((StatusCode::BAD_REQUEST, "Cannot delete yourself"));
+        return Err((StatusCode::BAD_REQUEST, "Cannot delete yourself".to_string()));
     }
 
+    if let Some(summary) = removes_admin(&state, id, None).await? {
+        let request = serde_json::json!({"user_id": id, "delete": true});
+        return Ok(super::approvals::park(&state, &user, Action::RemoveAdmin, summary, request));
+    }
+
+    delete_user(&state, &user.username, id).await?;
+    Ok((StatusCode::OK, Json(serde_json::json!({ "success": true }))))
+}
+
+pub(crate) async fn delete_user(state: &AppState, actor: &str, id: i64) -> Result<(), (StatusCode, String)> {
     sqlx::query("DELETE FROM users WHERE id = ?")
         .bind(id)
         .execute(&state.db)
         .await
-        .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Database error"))?;
+        .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Database error".to_string()))?;
+    tracing::info!("User {} deleted user {}", actor, id);
+    Ok(())
+}
+
+// Delete expired, revoked and idle sessions now rather than at the next scheduled run
+pub async fn cleanup_sessions(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+) -> Result<Json<session_cleanup::Cleanup>, (StatusCode, &'static str)> {
+    require_permission(&user, "users:write")?;
 
-    Ok(Json(serde_json::json!({ "success": true })))
+    let cleanup = session_cleanup::run(&state.db)
+        .await
+        .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Database error"))?;
+    Ok(Json(cleanup))
 }
 
-// Password strength check endpoint
+// Password strength check endpoint, judged against the password policy
 pub async fn check_password_strength(
+    State(state): State<Arc<AppState>>,
     Json(payload): Json<serde_json::Value>,
 ) -> Json<PasswordStrength> {
     let password = payload
         .get("password")
         .and_then(|v| v.as_str())
         .unwrap_or("");
-    
-    Json(auth::check_password_strength(password))
+
+    let policy = auth::password_policy::load(&state.db).await;
+    Json(auth::check_password_strength(password, &policy))
+}
+
+// Modules and roles for the permission editor
+pub async fn permission_catalog(
+    AuthUser(user): AuthUser,
+) -> Result<Json<serde_json::Value>, (StatusCode, &'static str)> {
+    require_permission(&user, "users:read")?;
+    Ok(Json(serde_json::json!({
+        "modules": permissions::MODULES,
+        "roles": permissions::ROLES,
+    })))
 }
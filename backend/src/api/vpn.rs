@@ -1,8 +1,15 @@
-use axum::{extract::Json, http::StatusCode};
+use axum::{
+    extract::{Json, Query, State},
+    http::StatusCode,
+};
 use serde::{Deserialize, Serialize};
 use std::process::Command;
+use crate::system::privileges::sudo;
+use std::sync::Arc;
 
+use crate::cache::{self, RefreshQuery};
 use crate::mock;
+use crate::AppState;
 
 // ============ TAILSCALE DATA STRUCTURES ============
 
@@ -327,15 +334,22 @@ fn get_gluetun_status() -> GluetunStatus {
 
 // ============ API ENDPOINTS ============
 
-pub async fn overview() -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+pub async fn overview(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<RefreshQuery>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
     if mock::is_mock_mode() {
         return Ok(Json(mock::vpn::overview()));
     }
 
-    let tailscale = parse_tailscale_status();
-    let gluetun = get_gluetun_status();
-
-    Ok(Json(serde_json::to_value(VpnOverview { tailscale, gluetun }).unwrap()))
+    state.cache
+        .get_or_fetch(cache::VPN_OVERVIEW, query.refresh, || async {
+            let tailscale = parse_tailscale_status();
+            let gluetun = get_gluetun_status();
+            Ok(serde_json::to_value(VpnOverview { tailscale, gluetun }).unwrap())
+        })
+        .await
+        .map(Json)
 }
 
 pub async fn tailscale_status() -> Result<Json<TailscaleStatus>, (StatusCode, String)> {
@@ -522,7 +536,7 @@ pub async fn tailscale_connect(
     }
 
     // Run tailscale up and capture login URL
-    let output = Command::new("sudo")
+    let output = sudo()
         .arg("tailscale")
         .args(&args)
         .output()
@@ -547,7 +561,7 @@ pub async fn tailscale_disconnect() -> Result<Json<serde_json::Value>, (StatusCo
         return Ok(Json(serde_json::json!({ "success": true, "mock": true })));
     }
 
-    let output = Command::new("sudo")
+    let output = sudo()
         .args(["tailscale", "down"])
         .output()
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
@@ -565,7 +579,7 @@ pub async fn tailscale_logout() -> Result<Json<serde_json::Value>, (StatusCode,
         return Ok(Json(serde_json::json!({ "success": true, "mock": true })));
     }
 
-    let output = Command::new("sudo")
+    let output = sudo()
         .args(["tailscale", "logout"])
         .output()
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
@@ -597,7 +611,7 @@ pub async fn tailscale_set_exit_node(
         args.push("--advertise-exit-node=false".to_string());
     }
 
-    let output = Command::new("sudo")
+    let output = sudo()
         .arg("tailscale")
         .args(&args)
         .output()
@@ -0,0 +1,57 @@
+use axum::{extract::State, http::StatusCode, Json};
+use std::sync::Arc;
+
+use super::{require_permission, AuthUser};
+use crate::mock;
+use crate::wan::{self, WanSettings};
+use crate::AppState;
+
+// ============ WAN IDENTITY ============
+
+pub async fn status(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    require_permission(&user, "network:write").map_err(|(s, m)| (s, m.to_string()))?;
+
+    if mock::is_mock_mode() {
+        return Ok(Json(mock::network::wan()));
+    }
+
+    let interface = wan::wan_interface(&state.db).await;
+    let address = wan::link_address(&interface);
+
+    Ok(Json(serde_json::json!({
+        "interface": interface,
+        "settings": wan::load_settings(),
+        "address": address,
+        "dhcp_options_supported": std::path::Path::new("/etc/netplan").exists(),
+    })))
+}
+
+pub async fn update_settings(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    Json(mut payload): Json<WanSettings>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    require_permission(&user, "network:write").map_err(|(s, m)| (s, m.to_string()))?;
+
+    payload.normalize();
+    payload.validate().map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+    if mock::is_mock_mode() {
+        return Ok(Json(serde_json::json!({"success": true, "mock": true})));
+    }
+
+    wan::save_settings(&payload).map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    // The WAN drops briefly while the address changes and DHCP renews
+    let interface = wan::wan_interface(&state.db).await;
+    let settings = payload.clone();
+    tokio::task::spawn_blocking(move || wan::apply_settings(&interface, &settings))
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Saved, but applying failed: {}", e)))?;
+
+    Ok(Json(serde_json::json!({"success": true, "settings": payload})))
+}
@@ -0,0 +1,146 @@
+// Two-person rule for destructive actions. When it is on, such an action requested by one
+// admin is parked here and only runs once a different admin approves it. Like an unconfirmed
+// firewall change, a request nobody approves within APPROVAL_WINDOW simply lapses.
+
+use axum::http::StatusCode;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+const SETTINGS_FILE: &str = "/opt/routerui/two-person.json";
+pub const APPROVAL_WINDOW: Duration = Duration::from_secs(10 * 60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    DisableFirewall,
+    RestoreBackup,
+    FactoryReset,
+    // Otherwise one admin could switch the rule off and go ahead alone
+    DisableTwoPerson,
+    // Demoting, disabling or deleting an admin; otherwise one admin could shut the others out
+    // and be left as the only one
+    RemoveAdmin,
+    // Zone settings that let the WAN reach the router, which turning the firewall off also does
+    OpenWanInput,
+}
+
+impl Action {
+    pub fn describe(self) -> &'static str {
+        match self {
+            Action::DisableFirewall => "Disable the firewall",
+            Action::RestoreBackup => "Restore a backup",
+            Action::FactoryReset => "Factory reset",
+            Action::DisableTwoPerson => "Turn off two-person confirmation",
+            Action::RemoveAdmin => "Remove an admin",
+            Action::OpenWanInput => "Open the router to the WAN",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ApprovalSettings {
+    #[serde(default)]
+    pub enabled: bool,
+}
+
+pub fn load_settings() -> ApprovalSettings {
+    std::fs::read_to_string(SETTINGS_FILE)
+        .ok()
+        .and_then(|c| serde_json::from_str(&c).ok())
+        .unwrap_or_default()
+}
+
+pub fn save_settings(settings: &ApprovalSettings) -> Result<(), String> {
+    let json = serde_json::to_string_pretty(settings).map_err(|e| e.to_string())?;
+    std::fs::write(SETTINGS_FILE, json).map_err(|e| e.to_string())
+}
+
+/// Whether destructive actions currently need a second admin
+pub fn required() -> bool {
+    load_settings().enabled
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PendingApproval {
+    pub id: String,
+    pub action: Action,
+    // What exactly will happen, e.g. which backup is restored
+    pub summary: String,
+    pub requested_by: String,
+    pub requested_at: String,
+    pub expires_at: String,
+    // API request that asked for it, to find it in the log once it is approved
+    pub request_id: Option<String>,
+    // Request body the action runs with once approved
+    #[serde(skip)]
+    pub payload: serde_json::Value,
+    #[serde(skip)]
+    deadline: Instant,
+}
+
+/// Requests waiting for a second admin, kept in memory: a restart drops them, which is the
+/// safe direction
+#[derive(Default)]
+pub struct ApprovalQueue {
+    pending: Mutex<Vec<PendingApproval>>,
+}
+
+impl ApprovalQueue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn live(&self) -> std::sync::MutexGuard<'_, Vec<PendingApproval>> {
+        let mut pending = self.pending.lock().unwrap();
+        let now = Instant::now();
+        pending.retain(|p| p.deadline > now);
+        pending
+    }
+
+    /// Park an action; a newer request for the same action replaces the older one
+    pub fn request(&self, action: Action, requested_by: &str, summary: String, payload: serde_json::Value) -> PendingApproval {
+        let now = Utc::now();
+        let request = PendingApproval {
+            id: uuid::Uuid::new_v4().to_string(),
+            action,
+            summary,
+            requested_by: requested_by.to_string(),
+            requested_at: now.to_rfc3339(),
+            expires_at: (now + chrono::Duration::from_std(APPROVAL_WINDOW).unwrap_or_default()).to_rfc3339(),
+            request_id: crate::logging::current_request_id(),
+            payload,
+            deadline: Instant::now() + APPROVAL_WINDOW,
+        };
+
+        let mut pending = self.live();
+        pending.retain(|p| p.action != action);
+        pending.push(request.clone());
+        request
+    }
+
+    pub fn list(&self) -> Vec<PendingApproval> {
+        self.live().clone()
+    }
+
+    /// Remove a request so it can run; the approver has to be someone other than the requester
+    pub fn approve(&self, id: &str, approver: &str) -> Result<PendingApproval, (StatusCode, String)> {
+        let mut pending = self.live();
+        let index = pending
+            .iter()
+            .position(|p| p.id == id)
+            .ok_or((StatusCode::NOT_FOUND, "No such request, or it has expired".to_string()))?;
+        if pending[index].requested_by == approver {
+            return Err((StatusCode::FORBIDDEN, "A different admin has to approve this".to_string()));
+        }
+        Ok(pending.remove(index))
+    }
+
+    /// Withdraw or turn down a request; any admin may do either
+    pub fn reject(&self, id: &str) -> Option<PendingApproval> {
+        let mut pending = self.live();
+        let index = pending.iter().position(|p| p.id == id)?;
+        Some(pending.remove(index))
+    }
+}
@@ -0,0 +1,350 @@
+use axum::{
+    extract::{Request, State},
+    http::{header::AUTHORIZATION, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use std::sync::Arc;
+
+use crate::models::User;
+use crate::AppState;
+
+/// Every API key starts with this, so a leaked one is easy to recognise
+pub const TOKEN_PREFIX: &str = "rk_";
+// Characters kept in clear to tell keys apart in the list
+const DISPLAY_PREFIX_LEN: usize = 8;
+const MAX_TOKENS_PER_USER: i64 = 50;
+const MAX_ALLOWED_PATHS: usize = 32;
+const MAX_EXPIRY_DAYS: u32 = 3650;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Scope {
+    // GET/HEAD only
+    Read,
+    // Everything the owner's role allows
+    Write,
+}
+
+impl Scope {
+    fn as_str(self) -> &'static str {
+        match self {
+            Scope::Read => "read",
+            Scope::Write => "write",
+        }
+    }
+
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "read" => Some(Scope::Read),
+            "write" => Some(Scope::Write),
+            _ => None,
+        }
+    }
+
+    pub fn allows(self, method: &Method) -> bool {
+        self == Scope::Write || matches!(*method, Method::GET | Method::HEAD)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ApiToken {
+    pub id: i64,
+    pub user_id: i64,
+    pub name: String,
+    // e.g. "rk_3f9a1c" - the rest is only shown once, at creation
+    pub prefix: String,
+    pub scope: String,
+    // Endpoints the key may call; empty for any its owner can
+    pub allowed_paths: Vec<String>,
+    pub created_at: String,
+    pub last_used_at: Option<String>,
+    pub expires_at: Option<String>,
+    pub expired: bool,
+}
+
+#[derive(sqlx::FromRow)]
+struct ApiTokenRow {
+    id: i64,
+    user_id: i64,
+    name: String,
+    prefix: String,
+    scope: String,
+    allowed_paths: Option<String>,
+    created_at: String,
+    last_used_at: Option<String>,
+    expires_at: Option<String>,
+    expired: bool,
+}
+
+impl From<ApiTokenRow> for ApiToken {
+    fn from(row: ApiTokenRow) -> Self {
+        ApiToken {
+            id: row.id,
+            user_id: row.user_id,
+            name: row.name,
+            prefix: row.prefix,
+            scope: row.scope,
+            allowed_paths: split_paths(row.allowed_paths.as_deref()),
+            created_at: row.created_at,
+            last_used_at: row.last_used_at,
+            expires_at: row.expires_at,
+            expired: row.expired,
+        }
+    }
+}
+
+const SELECT_TOKENS: &str = "SELECT id, user_id, name, prefix, scope, allowed_paths, created_at, last_used_at, expires_at,
+    COALESCE(expires_at <= datetime('now'), 0) AS expired FROM api_tokens";
+
+/// User and limits of the API key a request was made with, stored in the request extensions
+#[derive(Debug, Clone)]
+pub struct TokenAuth {
+    pub user: User,
+    pub scope: Scope,
+    pub allowed_paths: Vec<String>,
+}
+
+impl TokenAuth {
+    pub fn allows_path(&self, path: &str) -> bool {
+        self.allowed_paths.is_empty() || self.allowed_paths.iter().any(|p| path_matches(p, path))
+    }
+}
+
+/// What a new key may do beyond its scope, and for how long
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Limits {
+    // Exact paths ("/api/network/wol/wake") or prefixes ending in "*" ("/api/network/*")
+    pub allowed_paths: Vec<String>,
+    // None never expires
+    pub expires_in_days: Option<u32>,
+}
+
+// "/api/network/*" covers everything under /api/network/; anything else must match exactly
+fn path_matches(pattern: &str, path: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => path.starts_with(prefix),
+        None => path == pattern,
+    }
+}
+
+fn split_paths(stored: Option<&str>) -> Vec<String> {
+    stored.map(|s| s.lines().map(str::to_string).collect()).unwrap_or_default()
+}
+
+pub fn validate_name(name: &str) -> Result<String, String> {
+    let name = name.trim();
+    if name.is_empty() || name.len() > 64 {
+        return Err("Key names must be 1-64 characters".to_string());
+    }
+    Ok(name.to_string())
+}
+
+/// Trimmed, deduplicated path patterns; each must be under /api/ with at most a trailing "*"
+pub fn validate_paths(paths: &[String]) -> Result<Vec<String>, String> {
+    let mut paths: Vec<String> = paths.iter().map(|p| p.trim().to_string()).filter(|p| !p.is_empty()).collect();
+    paths.sort();
+    paths.dedup();
+    if paths.len() > MAX_ALLOWED_PATHS {
+        return Err(format!("At most {} allowed paths per key", MAX_ALLOWED_PATHS));
+    }
+    for path in &paths {
+        let valid = path.starts_with("/api/")
+            && path.len() <= 200
+            && !path.trim_end_matches('*').contains('*')
+            && path.chars().all(|c| c.is_ascii_graphic() && c != '?' && c != '#');
+        if !valid {
+            return Err(format!("'{}' is not an API path like /api/network/wol/wake or /api/network/*", path));
+        }
+    }
+    Ok(paths)
+}
+
+/// Returns the stored key and the secret, which can't be recovered later
+pub async fn create(pool: &SqlitePool, user_id: i64, name: &str, scope: Scope, limits: &Limits) -> Result<(ApiToken, String), String> {
+    let name = validate_name(name)?;
+    let allowed_paths = validate_paths(&limits.allowed_paths)?;
+    if limits.expires_in_days.is_some_and(|d| !(1..=MAX_EXPIRY_DAYS).contains(&d)) {
+        return Err(format!("Keys can expire after 1-{} days", MAX_EXPIRY_DAYS));
+    }
+
+    let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM api_tokens WHERE user_id = ?")
+        .bind(user_id)
+        .fetch_one(pool)
+        .await
+        .map_err(|e| e.to_string())?;
+    if count >= MAX_TOKENS_PER_USER {
+        return Err(format!("At most {} API keys per user", MAX_TOKENS_PER_USER));
+    }
+
+    let secret = format!("{}{}", TOKEN_PREFIX, super::generate_token());
+    let prefix = secret[..DISPLAY_PREFIX_LEN].to_string();
+
+    let id = sqlx::query(
+        "INSERT INTO api_tokens (user_id, name, token_hash, prefix, scope, allowed_paths, expires_at)
+         VALUES (?, ?, ?, ?, ?, ?, datetime('now', ?))",
+    )
+    .bind(user_id)
+    .bind(&name)
+    .bind(super::hash_token(&secret))
+    .bind(&prefix)
+    .bind(scope.as_str())
+    .bind((!allowed_paths.is_empty()).then(|| allowed_paths.join("\n")))
+    // datetime() of a NULL modifier is NULL, i.e. never
+    .bind(limits.expires_in_days.map(|d| format!("+{} days", d)))
+    .execute(pool)
+    .await
+    .map_err(|e| e.to_string())?
+    .last_insert_rowid();
+
+    let token = sqlx::query_as::<_, ApiTokenRow>(&format!("{} WHERE id = ?", SELECT_TOKENS))
+        .bind(id)
+        .fetch_one(pool)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok((token.into(), secret))
+}
+
+pub async fn list(pool: &SqlitePool, user_id: i64) -> Result<Vec<ApiToken>, sqlx::Error> {
+    let rows = sqlx::query_as::<_, ApiTokenRow>(&format!("{} WHERE user_id = ? ORDER BY id", SELECT_TOKENS))
+        .bind(user_id)
+        .fetch_all(pool)
+        .await?;
+    Ok(rows.into_iter().map(ApiToken::from).collect())
+}
+
+/// Only the owner can revoke a key; false when there was no such key
+pub async fn revoke(pool: &SqlitePool, user_id: i64, id: i64) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM api_tokens WHERE id = ? AND user_id = ?")
+        .bind(id)
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+// Expired keys are kept, so the owner sees why a script stopped working, but never authenticate
+const SELECT_LIVE_KEY: &str = "SELECT id, user_id, scope, allowed_paths FROM api_tokens
+    WHERE token_hash = ? AND (expires_at IS NULL OR expires_at > datetime('now'))";
+
+/// Owner (if still enabled), scope and allowed paths of an unexpired key
+pub async fn authenticate(pool: &SqlitePool, secret: &str) -> Result<Option<TokenAuth>, sqlx::Error> {
+    let row: Option<(i64, i64, String, Option<String>)> = sqlx::query_as(SELECT_LIVE_KEY)
+        .bind(super::hash_token(secret))
+        .fetch_optional(pool)
+        .await?;
+    let Some((id, user_id, scope, allowed_paths)) = row else { return Ok(None) };
+    let Some(scope) = Scope::parse(&scope) else { return Ok(None) };
+
+    let user = match crate::db::get_user_by_id(pool, user_id).await? {
+        Some(user) if user.enabled => user,
+        _ => return Ok(None),
+    };
+
+    sqlx::query("UPDATE api_tokens SET last_used_at = datetime('now') WHERE id = ?")
+        .bind(id)
+        .execute(pool)
+        .await?;
+
+    Ok(Some(TokenAuth { user, scope, allowed_paths: split_paths(allowed_paths.as_deref()) }))
+}
+
+fn bearer_key(request: &Request) -> Option<&str> {
+    request
+        .headers()
+        .get(AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+        .map(str::trim)
+        .filter(|t| t.starts_with(TOKEN_PREFIX))
+}
+
+// Route layer middleware: a request carrying an API key is checked here, whether or not its
+// handler asks for the user, so a revoked or read-only key can't slip through unchecked routes
+pub async fn enforce(State(state): State<Arc<AppState>>, mut request: Request, next: Next) -> Response {
+    let Some(secret) = bearer_key(&request) else {
+        return next.run(request).await;
+    };
+
+    let auth = match authenticate(&state.db, secret).await {
+        Ok(Some(auth)) => auth,
+        Ok(None) => return (StatusCode::UNAUTHORIZED, "Invalid API key").into_response(),
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    };
+    if !auth.scope.allows(request.method()) {
+        return (StatusCode::FORBIDDEN, "This API key is read-only").into_response();
+    }
+    if !auth.allows_path(request.uri().path()) {
+        return (StatusCode::FORBIDDEN, "This API key is not allowed to call this endpoint").into_response();
+    }
+
+    request.extensions_mut().insert(auth);
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paths(values: &[&str]) -> Vec<String> {
+        values.iter().map(|v| v.to_string()).collect()
+    }
+
+    #[test]
+    fn exact_paths_match_only_themselves() {
+        assert!(path_matches("/api/network/wol/wake", "/api/network/wol/wake"));
+        assert!(!path_matches("/api/network/wol/wake", "/api/network/wol/wake/all"));
+        assert!(!path_matches("/api/network/wol/wake", "/api/network/wol"));
+    }
+
+    #[test]
+    fn wildcards_match_their_prefix() {
+        assert!(path_matches("/api/network/*", "/api/network/wol/wake"));
+        assert!(path_matches("/api/network/*", "/api/network/"));
+        assert!(!path_matches("/api/network/*", "/api/network"));
+        assert!(!path_matches("/api/network/*", "/api/firewall/rules"));
+    }
+
+    #[test]
+    fn no_allowed_paths_means_every_path() {
+        let user = crate::models::User {
+            id: 1,
+            username: "script".to_string(),
+            password_hash: String::new(),
+            role: "admin".to_string(),
+            enabled: true,
+            created_at: String::new(),
+            last_login: None,
+            permissions: Vec::new(),
+        };
+        let mut auth = TokenAuth { user, scope: Scope::Write, allowed_paths: Vec::new() };
+        assert!(auth.allows_path("/api/firewall/rules"));
+        auth.allowed_paths = paths(&["/api/network/wol/*"]);
+        assert!(auth.allows_path("/api/network/wol/wake"));
+        assert!(!auth.allows_path("/api/firewall/rules"));
+    }
+
+    #[test]
+    fn validate_paths_trims_and_deduplicates() {
+        let valid = validate_paths(&paths(&[" /api/network/* ", "/api/network/*", "", "/api/dns/status"])).unwrap();
+        assert_eq!(valid, paths(&["/api/dns/status", "/api/network/*"]));
+    }
+
+    #[test]
+    fn validate_paths_refuses_non_api_paths() {
+        assert!(validate_paths(&paths(&["/etc/shadow"])).is_err());
+        assert!(validate_paths(&paths(&["api/network/*"])).is_err());
+        assert!(validate_paths(&paths(&["/api/*/wake"])).is_err());
+        assert!(validate_paths(&paths(&["/api/network/wol?x=1"])).is_err());
+        assert!(validate_paths(&paths(&["/api/network/wol#top"])).is_err());
+        assert!(validate_paths(&paths(&["/api/net work"])).is_err());
+        assert!(validate_paths(&paths(&[&format!("/api/{}", "a".repeat(200))])).is_err());
+        let too_many: Vec<String> = (0..=MAX_ALLOWED_PATHS).map(|i| format!("/api/path/{}", i)).collect();
+        assert!(validate_paths(&too_many).is_err());
+    }
+}
@@ -0,0 +1,238 @@
+use chrono::Utc;
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::events::Event;
+use crate::system::commands::RecordedCommand;
+use crate::system::privileges::sudo;
+use crate::AppState;
+
+/// ipset holding addresses blocked for guessing RouterUI passwords; entries expire on their own
+pub const SET_NAME: &str = "routerui-bruteforce";
+
+// A non-LAN address with MAX_FAILURES failed logins inside WINDOW is dropped for BLOCK_SECONDS
+const MAX_FAILURES: u32 = 5;
+const WINDOW: Duration = Duration::from_secs(10 * 60);
+const BLOCK_SECONDS: u32 = 60 * 60;
+// Forget stale counters once the table grows past this
+const MAX_TRACKED: usize = 4096;
+// Blocks kept in memory for the security page
+const RECENT_BLOCKS: usize = 50;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BruteForceBlock {
+    pub ip: String,
+    pub failures: u32,
+    pub last_username: String,
+    pub blocked_at: String,
+    pub expires_at: String,
+}
+
+/// Address currently in the ipset and how long until it is let back in
+#[derive(Debug, Serialize)]
+pub struct ActiveBlock {
+    pub ip: String,
+    pub expires_in: u32,
+}
+
+struct Failures {
+    count: u32,
+    first: Instant,
+}
+
+/// Counts failed logins per client address and hands repeat offenders to the firewall
+#[derive(Default)]
+pub struct BruteForceGuard {
+    failures: Mutex<HashMap<IpAddr, Failures>>,
+    recent: Mutex<VecDeque<BruteForceBlock>>,
+}
+
+impl BruteForceGuard {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Successful login: earlier failures from this address no longer count
+    pub fn record_success(&self, ip: IpAddr) {
+        self.failures.lock().unwrap().remove(&ip.to_canonical());
+    }
+
+    // Returns the failure count once it reaches the threshold, and starts counting afresh
+    fn count_failure(&self, ip: IpAddr) -> Option<u32> {
+        let mut failures = self.failures.lock().unwrap();
+        let now = Instant::now();
+
+        if failures.len() >= MAX_TRACKED {
+            failures.retain(|_, f| now.duration_since(f.first) < WINDOW);
+        }
+
+        let entry = failures.entry(ip).or_insert(Failures { count: 0, first: now });
+        if now.duration_since(entry.first) >= WINDOW {
+            *entry = Failures { count: 0, first: now };
+        }
+        entry.count += 1;
+
+        if entry.count < MAX_FAILURES {
+            return None;
+        }
+        let count = entry.count;
+        failures.remove(&ip);
+        Some(count)
+    }
+
+    fn remember(&self, block: BruteForceBlock) {
+        let mut recent = self.recent.lock().unwrap();
+        recent.push_front(block);
+        recent.truncate(RECENT_BLOCKS);
+    }
+
+    /// Blocks made since startup, newest first
+    pub fn recent_blocks(&self) -> Vec<BruteForceBlock> {
+        self.recent.lock().unwrap().iter().cloned().collect()
+    }
+}
+
+/// Called by the login handler for a wrong username or password. Once an address crosses the
+/// threshold it is added to the ipset in the background, unless it is on the LAN or whitelisted.
+pub fn record_failure(state: &Arc<AppState>, ip: IpAddr, username: &str) {
+    let ip = ip.to_canonical();
+    let Some(failures) = state.bruteforce.count_failure(ip) else { return };
+
+    if crate::mock::is_mock_mode() {
+        return;
+    }
+
+    let state = state.clone();
+    // Mistyped usernames are often passwords, so the privacy profile can leave them out
+    let username = if crate::privacy::policy().record_usernames { username.to_string() } else { String::new() };
+    tokio::spawn(async move {
+        let wan = crate::wan::wan_interface(&state.db).await;
+        let whitelisted = crate::api::protection::is_whitelisted(&state.db, &ip.to_string()).await;
+        let result = tokio::task::spawn_blocking(move || {
+            if crate::system::is_lan_address(ip, &crate::system::lan_subnets(&wan)) {
+                return Ok(false);
+            }
+            if whitelisted {
+                return Ok(false);
+            }
+            block(ip).map(|_| true)
+        })
+        .await
+        .unwrap_or_else(|e| Err(e.to_string()));
+
+        match result {
+            Ok(true) => {
+                let now = Utc::now();
+                let expires_at = (now + chrono::Duration::seconds(BLOCK_SECONDS as i64)).to_rfc3339();
+                if username.is_empty() {
+                    tracing::warn!("Blocked {} after {} failed logins", ip, failures);
+                } else {
+                    tracing::warn!("Blocked {} after {} failed logins (last user '{}')", ip, failures, username);
+                }
+
+                state.bruteforce.remember(BruteForceBlock {
+                    ip: ip.to_string(),
+                    failures,
+                    last_username: username.clone(),
+                    blocked_at: now.to_rfc3339(),
+                    expires_at: expires_at.clone(),
+                });
+                state.events.emit(Event::BruteForceBlocked {
+                    ip: ip.to_string(),
+                    failures,
+                    username,
+                    expires_at,
+                });
+            }
+            Ok(false) => {
+                tracing::warn!("{} failed {} logins but is on the LAN or whitelisted, not blocking", ip, failures);
+            }
+            Err(e) => tracing::error!("Could not block {} after {} failed logins: {}", ip, failures, e),
+        }
+    });
+}
+
+fn run(command: &mut RecordedCommand) -> Result<(), String> {
+    let output = command.output().map_err(|e| e.to_string())?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(String::from_utf8_lossy(&output.stderr).trim().to_string())
+    }
+}
+
+// Create the set and its DROP rule the first time anyone is blocked
+fn ensure_set() -> Result<(), String> {
+    let exists = sudo()
+        .args(["ipset", "list", SET_NAME, "-t"])
+        .output()
+        .map(|o| o.status.success())
+        .unwrap_or(false);
+    if !exists {
+        run(sudo().args(["ipset", "create", SET_NAME, "hash:net", "timeout", &BLOCK_SECONDS.to_string(), "maxelem", "1000000"]))?;
+    }
+
+    let rule = ["-m", "set", "--match-set", SET_NAME, "src", "-j", "DROP"];
+    let present = sudo()
+        .args(["iptables", "-C", "INPUT"])
+        .args(rule)
+        .output()
+        .map(|o| o.status.success())
+        .unwrap_or(false);
+    if !present {
+        run(sudo().args(["iptables", "-I", "INPUT", "1"]).args(rule))?;
+    }
+    Ok(())
+}
+
+fn block(ip: IpAddr) -> Result<(), String> {
+    // The set is IPv4 like the rest of the blocklists
+    if !ip.is_ipv4() {
+        return Err("only IPv4 addresses can be blocked".to_string());
+    }
+    ensure_set()?;
+    run(sudo().args(["ipset", "add", SET_NAME, &ip.to_string(), "timeout", &BLOCK_SECONDS.to_string(), "-exist"]))
+}
+
+/// Drop the set and its rule altogether (factory reset); recreated on the next block
+pub fn teardown() -> Result<(), String> {
+    let _ = sudo().args(["iptables", "-D", "INPUT", "-m", "set", "--match-set", SET_NAME, "src", "-j", "DROP"]).output();
+    let exists = sudo().args(["ipset", "list", SET_NAME, "-t"]).output().map(|o| o.status.success()).unwrap_or(false);
+    if exists {
+        run(sudo().args(["ipset", "destroy", SET_NAME]))?;
+    }
+    Ok(())
+}
+
+/// Let an address back in before its block expires
+pub fn unblock(ip: &str) -> Result<(), String> {
+    let ip: IpAddr = ip.parse().map_err(|_| format!("Invalid IP address: {}", ip))?;
+    run(sudo().args(["ipset", "del", SET_NAME, &ip.to_string()]))
+}
+
+/// Addresses currently blocked, read back from the ipset
+pub fn active_blocks() -> Vec<ActiveBlock> {
+    let output = match sudo().args(["ipset", "list", SET_NAME]).output() {
+        Ok(o) if o.status.success() => o,
+        _ => return Vec::new(),
+    };
+
+    // Members look like "198.51.100.7 timeout 3412"
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .skip_while(|line| !line.starts_with("Members:"))
+        .skip(1)
+        .filter_map(|line| {
+            let mut parts = line.split_whitespace();
+            let ip = parts.next()?.to_string();
+            let expires_in = match (parts.next(), parts.next()) {
+                (Some("timeout"), Some(secs)) => secs.parse().unwrap_or(0),
+                _ => 0,
+            };
+            Some(ActiveBlock { ip, expires_in })
+        })
+        .collect()
+}
@@ -0,0 +1,125 @@
+// CSRF tokens for cookie sessions. The session cookie is already SameSite=Strict; this covers
+// browsers that ignore that, and requests from a sibling subdomain, which count as same-site.
+// The token is derived from the session (HMAC under the token key), so there is nothing to store:
+// it is valid exactly as long as the session, and a new session means a new token. Requests
+// authenticated some other way (a bearer session token, an API key) can't be forged by a page
+// the user visits, so they don't need one.
+
+use axum::{
+    extract::Request,
+    http::{HeaderMap, HeaderName, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use ring::hmac;
+
+pub const HEADER: HeaderName = HeaderName::from_static("x-csrf-token");
+// Set on refusals so the frontend knows to fetch a fresh token and retry
+pub const REJECTED_HEADER: HeaderName = HeaderName::from_static("x-csrf-rejected");
+// Signing in and renewing a session happen before the page has the new session's token
+const EXEMPT_PATHS: &[&str] = &["/api/auth/login", "/api/auth/refresh"];
+// Keeps CSRF tokens apart from the stored token hashes, which use the same key
+const CONTEXT: &str = "csrf:";
+
+fn mac_input(session: &str) -> String {
+    format!("{}{}", CONTEXT, session)
+}
+
+/// The token for the session in the cookie, if the request has one
+pub fn token(headers: &HeaderMap) -> Option<String> {
+    let session = super::session_cookie(headers)?;
+    let tag = hmac::sign(super::token_key::key(), mac_input(&session).as_bytes());
+    Some(hex::encode(tag.as_ref()))
+}
+
+fn is_valid(headers: &HeaderMap, session: &str) -> bool {
+    let Some(token) = headers.get(HEADER).and_then(|v| v.to_str().ok()) else {
+        return false;
+    };
+    let Ok(tag) = hex::decode(token.trim()) else {
+        return false;
+    };
+    hmac::verify(super::token_key::key(), mac_input(session).as_bytes(), &tag).is_ok()
+}
+
+fn is_mutation(method: &Method) -> bool {
+    !matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS)
+}
+
+// Route layer middleware: a change made with a session cookie must carry the session's token
+pub async fn enforce(request: Request, next: Next) -> Response {
+    if !is_mutation(request.method()) || EXEMPT_PATHS.contains(&request.uri().path()) {
+        return next.run(request).await;
+    }
+    let Some(session) = super::session_cookie(request.headers()) else {
+        return next.run(request).await;
+    };
+    if is_valid(request.headers(), &session) {
+        return next.run(request).await;
+    }
+    tracing::debug!("Refused {} {} without a valid CSRF token", request.method(), request.uri().path());
+    (
+        StatusCode::FORBIDDEN,
+        [(REJECTED_HEADER, "1")],
+        "Missing or invalid CSRF token; reload the page and try again",
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::header;
+
+    fn headers(session: Option<&str>, csrf: Option<&str>) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        if let Some(session) = session {
+            headers.insert(header::COOKIE, format!("theme=dark; session={}", session).parse().unwrap());
+        }
+        if let Some(csrf) = csrf {
+            headers.insert(HEADER, csrf.parse().unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn token_needs_a_session_cookie() {
+        assert!(token(&headers(None, None)).is_none());
+        assert!(token(&headers(Some(""), None)).is_none());
+        assert!(token(&headers(Some("abc"), None)).is_some());
+    }
+
+    #[test]
+    fn token_is_valid_for_its_own_session_only() {
+        let csrf = token(&headers(Some("session-a"), None)).unwrap();
+        assert!(is_valid(&headers(Some("session-a"), Some(&csrf)), "session-a"));
+        assert!(!is_valid(&headers(Some("session-b"), Some(&csrf)), "session-b"));
+    }
+
+    #[test]
+    fn missing_or_malformed_tokens_are_refused() {
+        assert!(!is_valid(&headers(Some("session-a"), None), "session-a"));
+        assert!(!is_valid(&headers(Some("session-a"), Some("")), "session-a"));
+        assert!(!is_valid(&headers(Some("session-a"), Some("not hex")), "session-a"));
+        let mut csrf = token(&headers(Some("session-a"), None)).unwrap();
+        csrf.replace_range(..2, if csrf.starts_with("00") { "11" } else { "00" });
+        assert!(!is_valid(&headers(Some("session-a"), Some(&csrf)), "session-a"));
+    }
+
+    #[test]
+    fn the_session_token_itself_is_not_a_csrf_token() {
+        // Stored hashes use the same key; the context keeps the two apart
+        let hash = crate::auth::hash_token("session-a");
+        assert!(!is_valid(&headers(Some("session-a"), Some(&hash)), "session-a"));
+    }
+
+    #[test]
+    fn only_changes_need_a_token() {
+        assert!(!is_mutation(&Method::GET));
+        assert!(!is_mutation(&Method::HEAD));
+        assert!(!is_mutation(&Method::OPTIONS));
+        assert!(is_mutation(&Method::POST));
+        assert!(is_mutation(&Method::PUT));
+        assert!(is_mutation(&Method::DELETE));
+    }
+}
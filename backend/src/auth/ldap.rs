@@ -0,0 +1,318 @@
+// Sign-in against an LDAP directory or Active Directory. The router binds with a service
+// account, finds the user's entry with a search filter, then binds as that entry with the
+// password typed in; the role comes from the entry's groups. Accounts are created on first
+// sign-in and linked to the entry's DN, never to a local account by name. Local accounts are
+// always tried first, so a directory outage never locks the admin out.
+
+use ldap3::{Ldap, LdapConnAsync, LdapConnSettings, Scope, SearchEntry};
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use std::time::Duration;
+
+use super::oidc::RoleMapping;
+use crate::models::User;
+
+// Key in the settings table
+pub const SETTINGS_KEY: &str = "ldap";
+const TIMEOUT: Duration = Duration::from_secs(10);
+// LDAP result code for a wrong password
+const INVALID_CREDENTIALS: u32 = 49;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LdapSettings {
+    pub enabled: bool,
+    // ldap://dc1.corp.example.com or ldaps://ldap.example.com:636
+    pub url: String,
+    // Upgrade an ldap:// connection with StartTLS
+    pub starttls: bool,
+    // Accept any certificate; for directories with a self-signed one
+    pub skip_tls_verify: bool,
+    // Service account used to look users up; empty binds anonymously
+    pub bind_dn: String,
+    pub bind_password: String,
+    // Where to search for users, e.g. dc=corp,dc=example,dc=com
+    pub base_dn: String,
+    // {username} is replaced by the escaped name typed at the login page
+    pub user_filter: String,
+    // Attribute listing the user's groups, as DNs or plain names
+    pub group_attribute: String,
+    // First matching group wins; a group matches by full DN or by its CN
+    pub role_mapping: Vec<RoleMapping>,
+    // Role for users in none of the mapped groups; None refuses them
+    pub default_role: Option<String>,
+    // Create an account on first sign-in; otherwise only already linked users get in
+    pub auto_provision: bool,
+}
+
+impl Default for LdapSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            url: String::new(),
+            starttls: false,
+            skip_tls_verify: false,
+            bind_dn: String::new(),
+            bind_password: String::new(),
+            base_dn: String::new(),
+            // Matches OpenLDAP (uid) and Active Directory (sAMAccountName) users
+            user_filter: "(&(objectClass=person)(|(uid={username})(sAMAccountName={username})))".to_string(),
+            group_attribute: "memberOf".to_string(),
+            role_mapping: Vec::new(),
+            default_role: None,
+            auto_provision: true,
+        }
+    }
+}
+
+pub async fn load_settings(pool: &SqlitePool) -> LdapSettings {
+    match crate::db::settings::get(pool, SETTINGS_KEY).await {
+        Ok(settings) => settings.unwrap_or_default(),
+        Err(e) => {
+            tracing::warn!("Failed to read directory settings, using defaults: {}", e);
+            LdapSettings::default()
+        }
+    }
+}
+
+// Holds the service account password, which the API never sends back (see api::auth) and
+// /api/settings doesn't expose
+pub async fn save_settings(pool: &SqlitePool, settings: &LdapSettings) -> Result<(), String> {
+    crate::db::settings::set(pool, SETTINGS_KEY, settings).await.map_err(|e| e.to_string())
+}
+
+impl LdapSettings {
+    pub fn validate(&self) -> Result<(), String> {
+        for mapping in &self.role_mapping {
+            if mapping.group.trim().is_empty() {
+                return Err("Role mappings need a group name".to_string());
+            }
+            if !super::oidc::MAPPABLE_ROLES.contains(&mapping.role.as_str()) {
+                return Err(format!("Group '{}' maps to '{}', which must be admin, operator or viewer", mapping.group, mapping.role));
+            }
+        }
+        if let Some(role) = &self.default_role {
+            if !super::oidc::MAPPABLE_ROLES.contains(&role.as_str()) {
+                return Err("The default role must be admin, operator or viewer".to_string());
+            }
+        }
+        if !self.enabled {
+            return Ok(());
+        }
+
+        let url = reqwest::Url::parse(&self.url).map_err(|_| "The server must be an ldap:// or ldaps:// URL".to_string())?;
+        if !matches!(url.scheme(), "ldap" | "ldaps") || url.host().is_none() {
+            return Err("The server must be an ldap:// or ldaps:// URL".to_string());
+        }
+        if self.starttls && url.scheme() == "ldaps" {
+            return Err("StartTLS is only for ldap:// URLs; ldaps:// is encrypted already".to_string());
+        }
+        if self.base_dn.trim().is_empty() {
+            return Err("A search base is required".to_string());
+        }
+        if !self.user_filter.contains("{username}") {
+            return Err("The user filter must contain {username}".to_string());
+        }
+        if !self.user_filter.starts_with('(') || !self.user_filter.ends_with(')') {
+            return Err("The user filter must be enclosed in parentheses".to_string());
+        }
+        if self.group_attribute.trim().is_empty() {
+            return Err("A group attribute is required".to_string());
+        }
+        Ok(())
+    }
+
+    /// Role for someone in `groups`: the first mapping that matches, else the default
+    pub fn role_for(&self, groups: &[String]) -> Option<String> {
+        self.role_mapping
+            .iter()
+            .find(|m| {
+                let wanted = m.group.trim();
+                groups.iter().any(|g| g.eq_ignore_ascii_case(wanted) || common_name(g).is_some_and(|cn| cn.eq_ignore_ascii_case(wanted)))
+            })
+            .map(|m| m.role.clone())
+            .or_else(|| self.default_role.clone())
+    }
+}
+
+// "Admins" from "CN=Admins,OU=Groups,DC=corp,DC=example,DC=com"
+fn common_name(dn: &str) -> Option<&str> {
+    let (attr, value) = dn.split(',').next()?.split_once('=')?;
+    attr.trim().eq_ignore_ascii_case("cn").then(|| value.trim())
+}
+
+// ============ DIRECTORY ============
+
+/// Who the directory says signed in
+#[derive(Debug, Clone)]
+pub struct Identity {
+    pub dn: String,
+    pub username: String,
+    pub groups: Vec<String>,
+}
+
+/// Why a directory sign-in failed
+#[derive(Debug)]
+pub enum LdapError {
+    // No entry matches the username
+    UnknownUser,
+    // The DN is known once the user's entry was found
+    BadPassword { dn: Option<String> },
+    // Server unreachable, service bind refused, ...
+    Directory(String),
+}
+
+impl std::fmt::Display for LdapError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LdapError::UnknownUser => write!(f, "No such user in the directory"),
+            LdapError::BadPassword { .. } => write!(f, "Wrong password"),
+            LdapError::Directory(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+fn directory(context: &str) -> impl Fn(ldap3::LdapError) -> LdapError + '_ {
+    move |e| LdapError::Directory(format!("{}: {}", context, e))
+}
+
+// Connected and bound as the service account
+async fn connect(settings: &LdapSettings) -> Result<Ldap, LdapError> {
+    let conn_settings = LdapConnSettings::new()
+        .set_conn_timeout(TIMEOUT)
+        .set_starttls(settings.starttls)
+        .set_no_tls_verify(settings.skip_tls_verify);
+    let (conn, mut ldap) = LdapConnAsync::with_settings(conn_settings, &settings.url)
+        .await
+        .map_err(directory("LDAP connection"))?;
+    ldap3::drive!(conn);
+
+    if !settings.bind_dn.is_empty() {
+        ldap.with_timeout(TIMEOUT)
+            .simple_bind(&settings.bind_dn, &settings.bind_password)
+            .await
+            .and_then(|r| r.success())
+            .map_err(directory("Service account bind"))?;
+    }
+    Ok(ldap)
+}
+
+/// Connect and bind as the service account, to check the settings before saving them
+pub async fn check_server(settings: &LdapSettings) -> Result<(), String> {
+    let mut ldap = connect(settings).await.map_err(|e| e.to_string())?;
+    let _ = ldap.unbind().await;
+    Ok(())
+}
+
+/// Check `username` and `password` against the directory
+pub async fn authenticate(settings: &LdapSettings, username: &str, password: &str) -> Result<Identity, LdapError> {
+    // An empty password would be an unauthenticated bind, which many servers accept
+    if password.is_empty() || username.trim().is_empty() {
+        return Err(LdapError::BadPassword { dn: None });
+    }
+    let mut ldap = connect(settings).await?;
+    let result = find_and_bind(&mut ldap, settings, username, password).await;
+    let _ = ldap.unbind().await;
+    result
+}
+
+async fn find_and_bind(ldap: &mut Ldap, settings: &LdapSettings, username: &str, password: &str) -> Result<Identity, LdapError> {
+    let filter = settings.user_filter.replace("{username}", &ldap3::ldap_escape(username));
+    let (entries, _) = ldap
+        .with_timeout(TIMEOUT)
+        .search(&settings.base_dn, Scope::Subtree, &filter, vec![settings.group_attribute.as_str()])
+        .await
+        .and_then(|r| r.success())
+        .map_err(directory("User search"))?;
+
+    // Several matches means the filter is too loose; refuse rather than guess
+    let entry = match entries.len() {
+        0 => return Err(LdapError::UnknownUser),
+        1 => SearchEntry::construct(entries.into_iter().next().unwrap()),
+        n => return Err(LdapError::Directory(format!("{} directory entries match {}; tighten the user filter", n, username))),
+    };
+
+    let bind = ldap
+        .with_timeout(TIMEOUT)
+        .simple_bind(&entry.dn, password)
+        .await
+        .map_err(directory("User bind"))?;
+    match bind.rc {
+        0 => {}
+        INVALID_CREDENTIALS => return Err(LdapError::BadPassword { dn: Some(entry.dn) }),
+        _ => return Err(LdapError::Directory(format!("User bind: {}", bind))),
+    }
+
+    let groups = entry
+        .attrs
+        .iter()
+        .find(|(attr, _)| attr.eq_ignore_ascii_case(&settings.group_attribute))
+        .map(|(_, values)| values.clone())
+        .unwrap_or_default();
+    Ok(Identity { dn: entry.dn, username: username.trim().to_string(), groups })
+}
+
+// ============ ACCOUNTS ============
+
+/// The local account for `identity`, created on first sign-in. The directory owns the role: it
+/// is set again from the user's groups every time they sign in.
+pub async fn provision(pool: &SqlitePool, settings: &LdapSettings, identity: &Identity) -> Result<User, String> {
+    let role = settings
+        .role_for(&identity.groups)
+        .ok_or_else(|| format!("{} is not in any group that has access to RouterUI", identity.username))?;
+
+    let id = match linked_user(pool, &identity.dn).await.map_err(|e| e.to_string())? {
+        Some(id) => {
+            sqlx::query("UPDATE users SET role = ? WHERE id = ?")
+                .bind(&role)
+                .bind(id)
+                .execute(pool)
+                .await
+                .map_err(|e| e.to_string())?;
+            id
+        }
+        None if !settings.auto_provision => {
+            return Err(format!("No RouterUI account is linked to {}", identity.dn));
+        }
+        None => {
+            // Clashing names get a suffix, as for SSO accounts
+            let base = super::oidc::local_username(&identity.username);
+            let mut username = base.clone();
+            let mut n = 2;
+            while crate::db::get_user_by_username(pool, &username).await.map_err(|e| e.to_string())?.is_some() {
+                username = format!("{}-{}", base, n);
+                n += 1;
+            }
+            // Nobody knows this password; the account can only sign in through the directory
+            let password_hash = super::hash_password(&super::generate_token())?;
+            let id = sqlx::query("INSERT INTO users (username, password_hash, role, ldap_dn) VALUES (?, ?, ?, ?)")
+                .bind(&username)
+                .bind(&password_hash)
+                .bind(&role)
+                .bind(&identity.dn)
+                .execute(pool)
+                .await
+                .map_err(|e| e.to_string())?
+                .last_insert_rowid();
+            tracing::info!("Created user {} for directory sign-in of {}", username, identity.dn);
+            id
+        }
+    };
+
+    let user = crate::db::get_user_by_id(pool, id)
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or("The account disappeared during sign-in")?;
+    if !user.enabled {
+        return Err("Account disabled".to_string());
+    }
+    Ok(user)
+}
+
+/// The account linked to a directory entry, if any
+pub async fn linked_user(pool: &SqlitePool, dn: &str) -> Result<Option<i64>, sqlx::Error> {
+    sqlx::query_scalar("SELECT id FROM users WHERE ldap_dn = ? COLLATE NOCASE")
+        .bind(dn)
+        .fetch_optional(pool)
+        .await
+}
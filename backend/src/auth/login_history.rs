@@ -0,0 +1,125 @@
+// Every sign-in attempt, good or bad, so admins can see who got into the router and from where.
+// Attempts on unknown usernames are kept without a user; the name typed is only stored when the
+// privacy profile allows recording usernames.
+
+use chrono::Utc;
+use serde::Serialize;
+use sqlx::SqlitePool;
+
+// Most attempts returned for one user
+pub const MAX_ENTRIES: i64 = 500;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Method {
+    Password,
+    Ldap,
+    Sso,
+}
+
+impl Method {
+    fn as_str(self) -> &'static str {
+        match self {
+            Method::Password => "password",
+            Method::Ldap => "ldap",
+            Method::Sso => "sso",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Success,
+    BadPassword,
+    UnknownUser,
+    Disabled,
+    // SSO or directory identity turned down (domain, groups, provisioning off)
+    Refused,
+}
+
+impl Outcome {
+    fn as_str(self) -> &'static str {
+        match self {
+            Outcome::Success => "success",
+            Outcome::BadPassword => "bad_password",
+            Outcome::UnknownUser => "unknown_user",
+            Outcome::Disabled => "disabled",
+            Outcome::Refused => "refused",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct LoginAttempt {
+    pub id: i64,
+    pub created_at: String,
+    pub method: String,
+    pub result: String,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+/// Record an attempt. A failure to write is logged, never passed on: it must not stop a login.
+pub async fn record(
+    pool: &SqlitePool,
+    user_id: Option<i64>,
+    username: &str,
+    method: Method,
+    outcome: Outcome,
+    ip_address: Option<&str>,
+    user_agent: Option<&str>,
+) {
+    let username = if user_id.is_some() || crate::privacy::policy().record_usernames {
+        username
+    } else {
+        ""
+    };
+    let user_agent = user_agent.map(|ua| ua.chars().take(super::MAX_USER_AGENT_LEN).collect::<String>());
+
+    let result = sqlx::query(
+        "INSERT INTO login_history (user_id, username, method, result, ip_address, user_agent, created_at)
+         VALUES (?, ?, ?, ?, ?, ?, ?)"
+    )
+    .bind(user_id)
+    .bind(username)
+    .bind(method.as_str())
+    .bind(outcome.as_str())
+    .bind(ip_address)
+    .bind(user_agent)
+    .bind(Utc::now().to_rfc3339())
+    .execute(pool)
+    .await;
+    if let Err(e) = result {
+        tracing::warn!("Failed to record login attempt: {}", e);
+    }
+
+    use crate::syslog::{Message, Severity, Stream};
+    if crate::syslog::wants(Stream::Audit) {
+        let who = if username.is_empty() { "unknown user" } else { username };
+        let severity = if outcome == Outcome::Success { Severity::Info } else { Severity::Warning };
+        let mut message = Message::new(
+            Stream::Audit,
+            severity,
+            "login",
+            format!("Sign-in by {} ({}): {}", who, method.as_str(), outcome.as_str()),
+        )
+        .param("user", username)
+        .param("method", method.as_str())
+        .param("result", outcome.as_str());
+        if let Some(ip) = ip_address {
+            message = message.param("ip", ip);
+        }
+        crate::syslog::send(message);
+    }
+}
+
+/// A user's attempts, newest first
+pub async fn for_user(pool: &SqlitePool, user_id: i64, limit: i64) -> Result<Vec<LoginAttempt>, sqlx::Error> {
+    sqlx::query_as(
+        "SELECT id, created_at, method, result, ip_address, user_agent FROM login_history
+         WHERE user_id = ? ORDER BY created_at DESC, id DESC LIMIT ?"
+    )
+    .bind(user_id)
+    .bind(limit.clamp(1, MAX_ENTRIES))
+    .fetch_all(pool)
+    .await
+}
@@ -1,14 +1,57 @@
+pub mod api_tokens;
+pub mod bruteforce;
+pub mod csrf;
+pub mod ldap;
+pub mod login_history;
+pub mod oidc;
+pub mod password_policy;
+pub mod permissions;
+pub mod recovery;
+pub mod refresh;
+pub mod session_cleanup;
+pub mod session_policy;
+pub mod setup_token;
+pub mod token_key;
+
 use argon2::{
     password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
     Argon2,
 };
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{header, HeaderMap, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
 use chrono::{Duration, Utc};
 use rand::Rng;
+use serde::Serialize;
 use sqlx::SqlitePool;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
 
+use crate::events::{Event, EventBus};
 use crate::models::{PasswordStrength, Session, User};
+use crate::AppState;
 
-const SESSION_DURATION_HOURS: i64 = 4;
+// last_seen_at is written at most this often, not on every request
+const SEEN_UPDATE_INTERVAL_SECS: i64 = 60;
+const MAX_USER_AGENT_LEN: usize = 256;
+
+/// A login as shown to its owner; the token itself is never stored
+#[derive(Debug, Serialize)]
+pub struct SessionInfo {
+    pub id: i64,
+    pub created_at: String,
+    pub expires_at: String,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub last_seen_at: Option<String>,
+    // Kept alive by a remembered device's refresh token
+    pub remembered: bool,
+    // The session the request was made with
+    pub current: bool,
+}
 
 pub fn hash_password(password: &str) -> Result<String, String> {
     let salt = SaltString::generate(&mut OsRng);
@@ -34,31 +77,37 @@ pub fn generate_token() -> String {
     hex::encode(bytes)
 }
 
+/// What gets stored in place of a token: HMAC-SHA256 under the server's token key, in hex
 pub fn hash_token(token: &str) -> String {
-    use std::collections::hash_map::DefaultHasher;
-    use std::hash::{Hash, Hasher};
-    let mut hasher = DefaultHasher::new();
-    token.hash(&mut hasher);
-    format!("{:x}", hasher.finish())
+    hex::encode(ring::hmac::sign(token_key::key(), token.as_bytes()).as_ref())
 }
 
-pub fn check_password_strength(password: &str) -> PasswordStrength {
+/// How strong `password` is, and whether the policy accepts it. Unmet policy rules come first
+/// among the suggestions.
+pub fn check_password_strength(password: &str, policy: &password_policy::PasswordPolicy) -> PasswordStrength {
     let mut score = 0u8;
-    let mut suggestions = Vec::new();
+    let mut suggestions = policy.violations(password);
+    let meets_policy = suggestions.is_empty();
+    let mut suggest = |tip: &str| {
+        if !suggestions.iter().any(|s| s == tip) {
+            suggestions.push(tip.to_string());
+        }
+    };
 
-    if password.len() >= 8 { score += 1; } 
-    else { suggestions.push("Use at least 8 characters".to_string()); }
+    // A longer policy minimum has already been suggested
+    if password.len() >= 8 { score += 1; }
+    else if policy.min_length < 8 { suggest("Use at least 8 characters"); }
 
     if password.len() >= 12 { score += 1; }
 
-    if password.chars().any(|c| c.is_uppercase()) { score += 1; } 
-    else { suggestions.push("Add uppercase letters".to_string()); }
+    if password.chars().any(|c| c.is_uppercase()) { score += 1; }
+    else { suggest("Add uppercase letters"); }
 
-    if password.chars().any(|c| c.is_numeric()) { score += 1; } 
-    else { suggestions.push("Add numbers".to_string()); }
+    if password.chars().any(|c| c.is_numeric()) { score += 1; }
+    else { suggest("Add numbers"); }
 
-    if password.chars().any(|c| !c.is_alphanumeric()) { score += 1; } 
-    else { suggestions.push("Add special characters".to_string()); }
+    if password.chars().any(|c| !c.is_alphanumeric()) { score += 1; }
+    else { suggest("Add special characters"); }
 
     let label = match score {
         0..=1 => "Weak",
@@ -67,45 +116,267 @@ pub fn check_password_strength(password: &str) -> PasswordStrength {
         _ => "Strong",
     }.to_string();
 
-    PasswordStrength { score, label, suggestions }
+    PasswordStrength { score, label, suggestions, meets_policy }
 }
 
+/// New session lasting as long as the session policy allows. `remember` only marks it as
+/// belonging to a remembered device (see refresh.rs) and is dropped when those are turned off.
 pub async fn create_session(
     pool: &SqlitePool,
     user_id: i64,
     ip_address: Option<&str>,
+    user_agent: Option<&str>,
+    remember: bool,
 ) -> Result<String, sqlx::Error> {
+    let policy = session_policy::load(pool).await;
+    let remember = remember && policy.remember_days > 0;
     let token = generate_token();
     let token_hash = hash_token(&token);
-    let expires_at = (Utc::now() + Duration::hours(SESSION_DURATION_HOURS)).to_rfc3339();
+    let now = Utc::now();
+    let expires_at = (now + policy.session_lifetime()).to_rfc3339();
+    let user_agent = user_agent.map(|ua| ua.chars().take(MAX_USER_AGENT_LEN).collect::<String>());
+
+    sqlx::query(
+        "INSERT INTO sessions (user_id, token_hash, expires_at, ip_address, user_agent, last_seen_at, remember)
+         VALUES (?, ?, ?, ?, ?, ?, ?)"
+    )
+    .bind(user_id)
+    .bind(&token_hash)
+    .bind(&expires_at)
+    .bind(ip_address)
+    .bind(user_agent)
+    .bind(now.to_rfc3339())
+    .bind(remember)
+    .execute(pool)
+    .await?;
+    session_cleanup::enforce_limit(pool, user_id, policy.max_sessions_per_user).await?;
+
+    Ok(token)
+}
+
+// Sessions idle since before this are over
+fn idle_cutoff(policy: &session_policy::SessionPolicy) -> String {
+    match policy.idle_timeout() {
+        Some(idle) => (Utc::now() - idle).to_rfc3339(),
+        None => String::new(),
+    }
+}
 
-    sqlx::query("INSERT INTO sessions (user_id, token_hash, expires_at, ip_address) VALUES (?, ?, ?, ?)")
+/// Session token from the `session` cookie, or a bearer token that isn't an API key
+pub fn session_token(headers: &HeaderMap) -> Option<String> {
+    let from_bearer = || {
+        headers
+            .get(header::AUTHORIZATION)?
+            .to_str()
+            .ok()?
+            .strip_prefix("Bearer ")
+            .map(str::trim)
+            .filter(|t| !t.starts_with(api_tokens::TOKEN_PREFIX))
+            .map(str::to_string)
+    };
+    session_cookie(headers).or_else(from_bearer)
+}
+
+/// The session token from the cookie only, leaving out bearer tokens
+pub fn session_cookie(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(';'))
+        .find_map(|c| c.trim().strip_prefix("session=").map(str::to_string))
+        .filter(|t| !t.is_empty())
+}
+
+/// Unexpired, unrevoked sessions of a user, newest first
+pub async fn list_sessions(pool: &SqlitePool, user_id: i64, current_token: Option<&str>) -> Result<Vec<SessionInfo>, sqlx::Error> {
+    let current = current_token.map(hash_token);
+    let policy = session_policy::load(pool).await;
+    let sessions: Vec<Session> = sqlx::query_as(
+        "SELECT id, user_id, token_hash, created_at, expires_at, ip_address, user_agent, revoked_at, last_seen_at, remember
+         FROM sessions
+         WHERE user_id = ? AND expires_at > ? AND revoked_at IS NULL AND last_seen_at > ?
+         ORDER BY created_at DESC, id DESC"
+    )
+    .bind(user_id)
+    .bind(Utc::now().to_rfc3339())
+    .bind(idle_cutoff(&policy))
+    .fetch_all(pool)
+    .await?;
+
+    Ok(sessions
+        .into_iter()
+        .map(|s| SessionInfo {
+            current: current.as_deref() == Some(s.token_hash.as_str()),
+            id: s.id,
+            created_at: s.created_at,
+            expires_at: s.expires_at,
+            ip_address: s.ip_address,
+            user_agent: s.user_agent,
+            last_seen_at: s.last_seen_at,
+            remembered: s.remember,
+        })
+        .collect())
+}
+
+/// Only the owner can revoke a session; false when there was no such active session. A
+/// remembered device loses its refresh token too, or it would just sign itself back in.
+pub async fn revoke_session(pool: &SqlitePool, user_id: i64, id: i64) -> Result<bool, sqlx::Error> {
+    sqlx::query("UPDATE refresh_tokens SET revoked_at = datetime('now') WHERE session_id = ? AND user_id = ? AND revoked_at IS NULL")
+        .bind(id)
         .bind(user_id)
-        .bind(&token_hash)
-        .bind(&expires_at)
-        .bind(ip_address)
         .execute(pool)
         .await?;
+    let result = sqlx::query("UPDATE sessions SET revoked_at = datetime('now') WHERE id = ? AND user_id = ? AND revoked_at IS NULL")
+        .bind(id)
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected() > 0)
+}
 
-    Ok(token)
+/// Revoke every session of a user except the one with `keep_token`; returns how many were ended
+pub async fn revoke_other_sessions(pool: &SqlitePool, user_id: i64, keep_token: Option<&str>) -> Result<u64, sqlx::Error> {
+    sqlx::query(
+        "UPDATE refresh_tokens SET revoked_at = datetime('now')
+         WHERE user_id = ? AND revoked_at IS NULL
+           AND session_id IS NOT (SELECT id FROM sessions WHERE token_hash = ?)"
+    )
+    .bind(user_id)
+    .bind(keep_token.map(hash_token))
+    .execute(pool)
+    .await?;
+    let result = sqlx::query(
+        "UPDATE sessions SET revoked_at = datetime('now') WHERE user_id = ? AND revoked_at IS NULL AND token_hash IS NOT ?"
+    )
+    .bind(user_id)
+    .bind(keep_token.map(hash_token))
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected())
+}
+
+/// Sign out every remembered device of a user, refresh tokens included; returns how many were ended
+pub async fn forget_devices(pool: &SqlitePool, user_id: i64) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query(
+        "UPDATE refresh_tokens SET revoked_at = datetime('now') WHERE user_id = ? AND used_at IS NULL AND revoked_at IS NULL"
+    )
+    .bind(user_id)
+    .execute(pool)
+    .await?;
+    sqlx::query("UPDATE sessions SET revoked_at = datetime('now') WHERE user_id = ? AND remember = 1 AND revoked_at IS NULL")
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected())
+}
+
+pub async fn revoke_session_token(pool: &SqlitePool, token: &str) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE sessions SET revoked_at = datetime('now') WHERE token_hash = ? AND revoked_at IS NULL")
+        .bind(hash_token(token))
+        .execute(pool)
+        .await?;
+    Ok(())
 }
 
-pub async fn validate_session(pool: &SqlitePool, token: &str) -> Result<Option<User>, sqlx::Error> {
+/// User of a live session. With IP binding on, a session presented from outside the network it
+/// was created on is revoked on the spot and reported on the event bus.
+pub async fn validate_session(
+    pool: &SqlitePool,
+    events: &EventBus,
+    token: &str,
+    client_ip: Option<IpAddr>,
+) -> Result<Option<User>, sqlx::Error> {
     let token_hash = hash_token(token);
-    let now = Utc::now().to_rfc3339();
+    let now = Utc::now();
+    let policy = session_policy::load(pool).await;
 
     let session: Option<Session> = sqlx::query_as(
-        "SELECT id, user_id, token_hash, created_at, expires_at, ip_address FROM sessions WHERE token_hash = ? AND expires_at > ?"
+        "SELECT id, user_id, token_hash, created_at, expires_at, ip_address, user_agent, revoked_at, last_seen_at, remember
+         FROM sessions
+         WHERE token_hash = ? AND expires_at > ? AND revoked_at IS NULL AND last_seen_at > ?"
     )
     .bind(&token_hash)
-    .bind(&now)
+    .bind(now.to_rfc3339())
+    .bind(idle_cutoff(&policy))
     .fetch_optional(pool)
     .await?;
+    let Some(session) = session else { return Ok(None) };
+
+    let binding = policy.ip_binding;
+    let original_ip = session.ip_address.as_deref().and_then(|ip| ip.parse::<IpAddr>().ok());
+    if let (Some(original), Some(current)) = (original_ip, client_ip) {
+        if !binding.allows(original, current) {
+            sqlx::query("UPDATE sessions SET revoked_at = datetime('now') WHERE id = ?")
+                .bind(session.id)
+                .execute(pool)
+                .await?;
+            let username = crate::db::get_user_by_id(pool, session.user_id)
+                .await?
+                .map(|u| u.username)
+                .unwrap_or_default();
+            tracing::warn!(
+                "Session {} of {} created from {} was used from {}; revoked",
+                session.id, username, original, current
+            );
+            events.emit(Event::SessionIpMismatch {
+                username,
+                session_id: session.id,
+                original_ip: original.to_string(),
+                ip: current.to_string(),
+            });
+            return Ok(None);
+        }
+    }
+
+    // Activity slides the idle deadline along; the absolute expiry stays put
+    let seen_recently = session
+        .last_seen_at
+        .as_deref()
+        .and_then(|t| chrono::DateTime::parse_from_rfc3339(t).ok())
+        .is_some_and(|t| now.signed_duration_since(t) < Duration::seconds(SEEN_UPDATE_INTERVAL_SECS));
+    if !seen_recently {
+        sqlx::query("UPDATE sessions SET last_seen_at = ? WHERE id = ?")
+            .bind(now.to_rfc3339())
+            .bind(session.id)
+            .execute(pool)
+            .await?;
+    }
+
+    // A disabled account's sessions stop working straight away
+    Ok(crate::db::get_user_by_id(pool, session.user_id).await?.filter(|u| u.enabled))
+}
+
+/// The signed-in user of a request made with a live session, set by `authenticate`
+#[derive(Debug, Clone)]
+pub struct SessionAuth {
+    pub user: User,
+}
+
+// Route layer middleware: resolves the session cookie or bearer token through validate_session,
+// so expiry, idle timeout, revocation and IP binding apply to every request. A request without
+// a live session goes through unauthenticated; handlers that need a user answer 401.
+pub async fn authenticate(State(state): State<Arc<AppState>>, mut request: Request, next: Next) -> Response {
+    // API keys were checked by api_tokens::enforce
+    if request.extensions().get::<api_tokens::TokenAuth>().is_some() {
+        return next.run(request).await;
+    }
+    let Some(token) = session_token(request.headers()) else {
+        return next.run(request).await;
+    };
 
-    match session {
-        Some(s) => crate::db::get_user_by_id(pool, s.user_id).await,
-        None => Ok(None),
+    let client_ip = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(peer)| peer.ip().to_canonical());
+    match validate_session(&state.db, &state.events, &token, client_ip).await {
+        Ok(Some(user)) => {
+            request.extensions_mut().insert(SessionAuth { user });
+        }
+        Ok(None) => {}
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
     }
+    next.run(request).await
 }
 
 pub async fn create_default_admin(pool: &SqlitePool) -> Result<(), Box<dyn std::error::Error>> {
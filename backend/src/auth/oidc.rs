@@ -0,0 +1,517 @@
+// Sign-in through an external OpenID Connect provider (Authentik, Keycloak, Google, ...).
+// Authorization code flow with PKCE; the ID token is checked against the provider's published
+// keys, and a user signing in for the first time gets an account with a role mapped from their
+// groups. Local accounts keep working alongside, so a provider outage never locks anyone out.
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use ring::signature::{self, RsaPublicKeyComponents, UnparsedPublicKey};
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::models::User;
+
+const SETTINGS_FILE: &str = "/opt/routerui/oidc.json";
+pub const CALLBACK_PATH: &str = "/api/auth/oidc/callback";
+// Time allowed between leaving for the provider and coming back
+const LOGIN_WINDOW: Duration = Duration::from_secs(10 * 60);
+const MAX_PENDING: usize = 256;
+const CLOCK_SKEW_SECS: i64 = 60;
+// "custom" needs per-user grants, which a provider group can't carry
+pub(super) const MAPPABLE_ROLES: &[&str] = &["admin", "operator", "viewer"];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RoleMapping {
+    pub group: String,
+    pub role: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OidcSettings {
+    pub enabled: bool,
+    // e.g. https://auth.example.com/application/o/routerui/
+    pub issuer_url: String,
+    pub client_id: String,
+    pub client_secret: String,
+    // Callback URL exactly as registered with the provider
+    pub redirect_url: String,
+    pub scopes: String,
+    pub button_label: String,
+    // ID token claim holding the user's groups; dots reach into nested claims, e.g.
+    // Keycloak's "realm_access.roles"
+    pub groups_claim: String,
+    // First matching group wins, so list the most privileged first
+    pub role_mapping: Vec<RoleMapping>,
+    // Role for users in none of the mapped groups; None refuses them
+    pub default_role: Option<String>,
+    // Create an account on first sign-in; otherwise only already linked users get in
+    pub auto_provision: bool,
+    // Only users with a verified email address in one of these domains may sign in; empty
+    // allows anyone. For providers without groups, such as Google.
+    pub allowed_domains: Vec<String>,
+}
+
+impl Default for OidcSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            issuer_url: String::new(),
+            client_id: String::new(),
+            client_secret: String::new(),
+            redirect_url: String::new(),
+            scopes: "openid profile email".to_string(),
+            button_label: "Sign in with SSO".to_string(),
+            groups_claim: "groups".to_string(),
+            role_mapping: Vec::new(),
+            default_role: None,
+            auto_provision: true,
+            allowed_domains: Vec::new(),
+        }
+    }
+}
+
+pub fn load_settings() -> OidcSettings {
+    std::fs::read_to_string(SETTINGS_FILE)
+        .ok()
+        .and_then(|c| serde_json::from_str(&c).ok())
+        .unwrap_or_default()
+}
+
+// Holds the client secret, so only root may read it
+pub fn save_settings(settings: &OidcSettings) -> Result<(), String> {
+    use std::io::Write;
+    use std::os::unix::fs::OpenOptionsExt;
+
+    let json = serde_json::to_string_pretty(settings).map_err(|e| e.to_string())?;
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(SETTINGS_FILE)
+        .map_err(|e| e.to_string())?;
+    file.write_all(json.as_bytes()).map_err(|e| e.to_string())
+}
+
+fn is_http_url(url: &str) -> bool {
+    reqwest::Url::parse(url).is_ok_and(|u| matches!(u.scheme(), "http" | "https") && u.host().is_some())
+}
+
+impl OidcSettings {
+    pub fn validate(&self) -> Result<(), String> {
+        for mapping in &self.role_mapping {
+            if mapping.group.trim().is_empty() {
+                return Err("Role mappings need a group name".to_string());
+            }
+            if !MAPPABLE_ROLES.contains(&mapping.role.as_str()) {
+                return Err(format!("Group '{}' maps to '{}', which must be admin, operator or viewer", mapping.group, mapping.role));
+            }
+        }
+        for domain in &self.allowed_domains {
+            if domain.trim().is_empty() || domain.contains('@') || domain.contains(char::is_whitespace) {
+                return Err(format!("'{}' is not a domain (use e.g. example.com)", domain));
+            }
+        }
+        if let Some(role) = &self.default_role {
+            if !MAPPABLE_ROLES.contains(&role.as_str()) {
+                return Err("The default role must be admin, operator or viewer".to_string());
+            }
+        }
+        if !self.enabled {
+            return Ok(());
+        }
+
+        if !is_http_url(&self.issuer_url) {
+            return Err("The issuer URL must be an http(s) URL".to_string());
+        }
+        if self.client_id.trim().is_empty() {
+            return Err("A client ID is required".to_string());
+        }
+        if !is_http_url(&self.redirect_url) || !self.redirect_url.ends_with(CALLBACK_PATH) {
+            return Err(format!("The redirect URL must be RouterUI's address followed by {}", CALLBACK_PATH));
+        }
+        if !self.scopes.split_whitespace().any(|s| s == "openid") {
+            return Err("The scopes must include openid".to_string());
+        }
+        if self.groups_claim.trim().is_empty() {
+            return Err("A groups claim is required".to_string());
+        }
+        Ok(())
+    }
+
+    /// Whether a user with this email may sign in at all
+    pub fn allows_email(&self, email: Option<&str>, verified: bool) -> bool {
+        if self.allowed_domains.is_empty() {
+            return true;
+        }
+        let Some(domain) = email.filter(|_| verified).and_then(|e| e.rsplit_once('@')).map(|(_, d)| d) else {
+            return false;
+        };
+        self.allowed_domains.iter().any(|d| d.trim().eq_ignore_ascii_case(domain))
+    }
+
+    /// Role for someone in `groups`: the first mapping that matches, else the default
+    pub fn role_for(&self, groups: &[String]) -> Option<String> {
+        self.role_mapping
+            .iter()
+            .find(|m| groups.iter().any(|g| g == &m.group))
+            .map(|m| m.role.clone())
+            .or_else(|| self.default_role.clone())
+    }
+}
+
+// ============ PROVIDER ============
+
+#[derive(Debug, Clone, Deserialize)]
+struct Discovery {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    jwks_uri: String,
+}
+
+fn http_client() -> Result<reqwest::Client, String> {
+    reqwest::Client::builder()
+        .timeout(Duration::from_secs(15))
+        .user_agent(concat!("RouterUI/", env!("CARGO_PKG_VERSION")))
+        .build()
+        .map_err(|e| e.to_string())
+}
+
+async fn discover(http: &reqwest::Client, issuer_url: &str) -> Result<Discovery, String> {
+    let issuer_url = issuer_url.trim_end_matches('/');
+    let url = format!("{}/.well-known/openid-configuration", issuer_url);
+    let discovery = http
+        .get(&url)
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| format!("OIDC discovery: {}", e))?
+        .json::<Discovery>()
+        .await
+        .map_err(|e| format!("OIDC discovery: {}", e))?;
+    if discovery.issuer.trim_end_matches('/') != issuer_url {
+        return Err(format!("The provider calls itself '{}', not '{}'", discovery.issuer, issuer_url));
+    }
+    Ok(discovery)
+}
+
+/// Fetch the provider's discovery document, to check the settings before saving them
+pub async fn check_provider(settings: &OidcSettings) -> Result<(), String> {
+    discover(&http_client()?, &settings.issuer_url).await.map(|_| ())
+}
+
+// ============ LOGIN FLOW ============
+
+struct PendingLogin {
+    nonce: String,
+    verifier: String,
+    discovery: Discovery,
+    deadline: Instant,
+}
+
+/// Sign-ins that went off to the provider and haven't come back yet, keyed by `state`
+#[derive(Default)]
+pub struct OidcLogins {
+    pending: Mutex<HashMap<String, PendingLogin>>,
+}
+
+/// Who the provider says signed in
+#[derive(Debug, Clone)]
+pub struct Identity {
+    // "<issuer>#<sub>", which stays the same when the user is renamed at the provider
+    pub subject: String,
+    pub username: String,
+    pub email: Option<String>,
+    pub email_verified: bool,
+    pub groups: Vec<String>,
+}
+
+impl OidcLogins {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Provider URL to send the browser to
+    pub async fn start(&self, settings: &OidcSettings) -> Result<String, String> {
+        let discovery = discover(&http_client()?, &settings.issuer_url).await?;
+
+        let state = super::generate_token();
+        let nonce = super::generate_token();
+        let verifier = super::generate_token();
+        let challenge = URL_SAFE_NO_PAD.encode(ring::digest::digest(&ring::digest::SHA256, verifier.as_bytes()));
+
+        let url = reqwest::Url::parse_with_params(
+            &discovery.authorization_endpoint,
+            &[
+                ("response_type", "code"),
+                ("client_id", settings.client_id.as_str()),
+                ("redirect_uri", settings.redirect_url.as_str()),
+                ("scope", settings.scopes.as_str()),
+                ("state", state.as_str()),
+                ("nonce", nonce.as_str()),
+                ("code_challenge", challenge.as_str()),
+                ("code_challenge_method", "S256"),
+            ],
+        )
+        .map_err(|e| format!("Bad authorization endpoint: {}", e))?;
+
+        let mut pending = self.pending.lock().unwrap();
+        let now = Instant::now();
+        pending.retain(|_, p| p.deadline > now);
+        if pending.len() >= MAX_PENDING {
+            return Err("Too many sign-ins in progress, try again in a few minutes".to_string());
+        }
+        pending.insert(state, PendingLogin { nonce, verifier, discovery, deadline: now + LOGIN_WINDOW });
+        Ok(url.to_string())
+    }
+
+    /// Trade the code the provider sent back for a verified identity
+    pub async fn finish(&self, settings: &OidcSettings, state: &str, code: &str) -> Result<Identity, String> {
+        let login = self
+            .pending
+            .lock()
+            .unwrap()
+            .remove(state)
+            .filter(|p| p.deadline > Instant::now())
+            .ok_or("This sign-in has expired or was already used, please start again")?;
+
+        let mut request = http_client()?.post(&login.discovery.token_endpoint).form(&[
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", settings.redirect_url.as_str()),
+            ("client_id", settings.client_id.as_str()),
+            ("code_verifier", login.verifier.as_str()),
+        ]);
+        if !settings.client_secret.is_empty() {
+            request = request.basic_auth(&settings.client_id, Some(&settings.client_secret));
+        }
+        let response = request.send().await.map_err(|e| format!("Token request failed: {}", e))?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            return Err(format!("The provider refused the sign-in ({}): {}", status, body.trim()));
+        }
+
+        #[derive(Deserialize)]
+        struct TokenResponse {
+            id_token: String,
+        }
+        let tokens: TokenResponse = response.json().await.map_err(|e| format!("Token response: {}", e))?;
+
+        let claims = verify_id_token(settings, &login.discovery, &tokens.id_token).await?;
+        if claims.get("nonce").and_then(|n| n.as_str()) != Some(login.nonce.as_str()) {
+            return Err("The ID token is not for this sign-in".to_string());
+        }
+        identity(settings, &login.discovery, &claims)
+    }
+}
+
+// ============ ID TOKEN ============
+
+#[derive(Deserialize)]
+struct JwtHeader {
+    alg: String,
+    kid: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Jwk {
+    kty: String,
+    kid: Option<String>,
+    n: Option<String>,
+    e: Option<String>,
+    crv: Option<String>,
+    x: Option<String>,
+    y: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct JwkSet {
+    keys: Vec<Jwk>,
+}
+
+fn decode(part: &str) -> Result<Vec<u8>, String> {
+    URL_SAFE_NO_PAD.decode(part).map_err(|_| "The ID token is not valid base64url".to_string())
+}
+
+fn verify_with_key(alg: &str, key: &Jwk, message: &[u8], signature: &[u8]) -> Result<(), String> {
+    let field = |v: &Option<String>| v.as_deref().ok_or("Incomplete signing key").and_then(|v| decode(v).map_err(|_| "Bad signing key"));
+    let result = match (alg, key.kty.as_str()) {
+        ("RS256", "RSA") => RsaPublicKeyComponents { n: field(&key.n)?, e: field(&key.e)? }
+            .verify(&signature::RSA_PKCS1_2048_8192_SHA256, message, signature),
+        ("ES256", "EC") if key.crv.as_deref() == Some("P-256") => {
+            // Uncompressed point: 0x04 || x || y
+            let mut point = vec![0x04];
+            point.extend(field(&key.x)?);
+            point.extend(field(&key.y)?);
+            UnparsedPublicKey::new(&signature::ECDSA_P256_SHA256_FIXED, point).verify(message, signature)
+        }
+        _ => return Err(format!("Unsupported ID token algorithm {}", alg)),
+    };
+    result.map_err(|_| "The ID token signature is invalid".to_string())
+}
+
+async fn verify_id_token(settings: &OidcSettings, discovery: &Discovery, token: &str) -> Result<serde_json::Value, String> {
+    let parts: Vec<&str> = token.split('.').collect();
+    let [header, payload, sig] = parts[..] else {
+        return Err("The ID token is not a JWT".to_string());
+    };
+    let header: JwtHeader = serde_json::from_slice(&decode(header)?).map_err(|e| format!("ID token header: {}", e))?;
+    let message = format!("{}.{}", parts[0], parts[1]);
+    let sig = decode(sig)?;
+
+    if header.alg == "HS256" {
+        // Signed with the client secret (Authentik does this when no signing key is set)
+        if settings.client_secret.is_empty() {
+            return Err("The ID token is signed with the client secret, but none is configured".to_string());
+        }
+        let key = ring::hmac::Key::new(ring::hmac::HMAC_SHA256, settings.client_secret.as_bytes());
+        ring::hmac::verify(&key, message.as_bytes(), &sig).map_err(|_| "The ID token signature is invalid".to_string())?;
+    } else {
+        let jwks: JwkSet = http_client()?
+            .get(&discovery.jwks_uri)
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| format!("Provider keys: {}", e))?
+            .json()
+            .await
+            .map_err(|e| format!("Provider keys: {}", e))?;
+        let key = jwks
+            .keys
+            .iter()
+            .find(|k| header.kid.is_none() || k.kid == header.kid)
+            .ok_or("The ID token was signed with a key the provider doesn't publish")?;
+        verify_with_key(&header.alg, key, message.as_bytes(), &sig)?;
+    }
+
+    let claims: serde_json::Value = serde_json::from_slice(&decode(payload)?).map_err(|e| format!("ID token claims: {}", e))?;
+
+    if claims.get("iss").and_then(|v| v.as_str()) != Some(discovery.issuer.as_str()) {
+        return Err("The ID token was issued by someone else".to_string());
+    }
+    let audience_ok = match claims.get("aud") {
+        Some(serde_json::Value::String(aud)) => aud == &settings.client_id,
+        Some(serde_json::Value::Array(auds)) => auds.iter().any(|a| a.as_str() == Some(settings.client_id.as_str())),
+        _ => false,
+    };
+    if !audience_ok {
+        return Err("The ID token is meant for another application".to_string());
+    }
+    let expires = claims.get("exp").and_then(|v| v.as_i64()).unwrap_or(0);
+    if expires + CLOCK_SKEW_SECS < chrono::Utc::now().timestamp() {
+        return Err("The ID token has expired; check the router's clock".to_string());
+    }
+    Ok(claims)
+}
+
+fn identity(settings: &OidcSettings, discovery: &Discovery, claims: &serde_json::Value) -> Result<Identity, String> {
+    let claim = |name: &str| claims.get(name).and_then(|v| v.as_str()).map(str::to_string).filter(|v| !v.is_empty());
+    let sub = claim("sub").ok_or("The ID token has no subject")?;
+    let username = claim("preferred_username")
+        .or_else(|| claim("email"))
+        .unwrap_or_else(|| sub.clone());
+    // A claim literally named with dots wins over walking the path
+    let groups_claim = claims.get(&settings.groups_claim).or_else(|| {
+        settings.groups_claim.split('.').try_fold(claims, |value, key| value.get(key))
+    });
+    let groups = match groups_claim {
+        Some(serde_json::Value::Array(groups)) => groups.iter().filter_map(|g| g.as_str().map(str::to_string)).collect(),
+        Some(serde_json::Value::String(group)) => vec![group.clone()],
+        _ => Vec::new(),
+    };
+    // Some providers send the flag as a string
+    let email_verified = match claims.get("email_verified") {
+        Some(serde_json::Value::Bool(verified)) => *verified,
+        Some(serde_json::Value::String(verified)) => verified == "true",
+        _ => false,
+    };
+    Ok(Identity {
+        subject: format!("{}#{}", discovery.issuer, sub),
+        username,
+        email: claim("email"),
+        email_verified,
+        groups,
+    })
+}
+
+// ============ ACCOUNTS ============
+
+pub(super) fn local_username(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-' | '@'))
+        .take(48)
+        .collect();
+    if name.is_empty() { "sso-user".to_string() } else { name }
+}
+
+/// The local account for `identity`, created on first sign-in. The provider owns the role: it
+/// is set again from the user's groups every time they sign in.
+pub async fn provision(pool: &SqlitePool, settings: &OidcSettings, identity: &Identity) -> Result<User, String> {
+    if !settings.allows_email(identity.email.as_deref(), identity.email_verified) {
+        return Err(format!("{} does not have a verified email address in an allowed domain", identity.username));
+    }
+    let role = settings
+        .role_for(&identity.groups)
+        .ok_or_else(|| format!("{} is not in any group that has access to RouterUI", identity.username))?;
+
+    let linked: Option<i64> = sqlx::query_scalar("SELECT id FROM users WHERE oidc_subject = ?")
+        .bind(&identity.subject)
+        .fetch_optional(pool)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let id = match linked {
+        Some(id) => {
+            sqlx::query("UPDATE users SET role = ? WHERE id = ?")
+                .bind(&role)
+                .bind(id)
+                .execute(pool)
+                .await
+                .map_err(|e| e.to_string())?;
+            id
+        }
+        None if !settings.auto_provision => {
+            return Err(format!("No RouterUI account is linked to {}", identity.username));
+        }
+        None => {
+            // Never link to a local account by name: anyone able to pick that name at the
+            // provider would take it over. Clashing names get a suffix instead.
+            let base = local_username(&identity.username);
+            let mut username = base.clone();
+            let mut n = 2;
+            while crate::db::get_user_by_username(pool, &username).await.map_err(|e| e.to_string())?.is_some() {
+                username = format!("{}-{}", base, n);
+                n += 1;
+            }
+            // Nobody knows this password; the account can only sign in through the provider
+            let password_hash = super::hash_password(&super::generate_token())?;
+            let id = sqlx::query("INSERT INTO users (username, password_hash, role, oidc_subject) VALUES (?, ?, ?, ?)")
+                .bind(&username)
+                .bind(&password_hash)
+                .bind(&role)
+                .bind(&identity.subject)
+                .execute(pool)
+                .await
+                .map_err(|e| e.to_string())?
+                .last_insert_rowid();
+            tracing::info!("Created user {} for SSO sign-in of {}", username, identity.username);
+            id
+        }
+    };
+
+    let user = crate::db::get_user_by_id(pool, id)
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or("The account disappeared during sign-in")?;
+    if !user.enabled {
+        return Err("Account disabled".to_string());
+    }
+    Ok(user)
+}
@@ -0,0 +1,198 @@
+// What a password must look like, and how long it lasts. Applies wherever a password is chosen:
+// the setup wizard, the user editor and the change-password form. Accounts that sign in through
+// SSO or a directory have a password nobody knows, so they never expire.
+
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+
+const MIN_LENGTH: usize = 6;
+const MAX_LENGTH: usize = 128;
+const MAX_AGE_DAYS: u32 = 3650;
+
+// Refused outright when block_common is on; compared case-insensitively
+const COMMON_PASSWORDS: &[&str] = &[
+    "123456", "1234567", "12345678", "123456789", "1234567890", "12345678910", "111111", "000000",
+    "123123", "654321", "666666", "121212", "112233", "abc123", "abcd1234", "password", "password1",
+    "password123", "passw0rd", "p@ssw0rd", "p@ssword", "qwerty", "qwerty123", "qwertyuiop",
+    "1q2w3e4r", "1qaz2wsx", "zaq12wsx", "asdfghjkl", "iloveyou", "letmein", "welcome", "welcome1",
+    "monkey", "dragon", "football", "baseball", "sunshine", "princess", "superman", "trustno1",
+    "master", "shadow", "michael", "charlie", "starwars", "freedom", "whatever", "admin", "admin123",
+    "administrator", "root", "toor", "changeme", "default", "guest", "router", "routerui",
+    "internet", "wireless", "wifipassword", "secret", "hello123",
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PasswordPolicy {
+    pub min_length: usize,
+    pub require_lowercase: bool,
+    pub require_uppercase: bool,
+    pub require_digit: bool,
+    // Anything that isn't a letter or a digit
+    pub require_symbol: bool,
+    // Refuse passwords from the list of the most common ones
+    pub block_common: bool,
+    // Days until a password must be changed; 0 never
+    pub max_age_days: u32,
+}
+
+impl Default for PasswordPolicy {
+    fn default() -> Self {
+        Self {
+            min_length: 8,
+            require_lowercase: false,
+            require_uppercase: true,
+            require_digit: true,
+            require_symbol: false,
+            block_common: true,
+            max_age_days: 0,
+        }
+    }
+}
+
+impl PasswordPolicy {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(MIN_LENGTH..=MAX_LENGTH).contains(&self.min_length) {
+            return Err(format!("Minimum length must be {}-{} characters", MIN_LENGTH, MAX_LENGTH));
+        }
+        if self.max_age_days > MAX_AGE_DAYS {
+            return Err(format!("Maximum age must be at most {} days", MAX_AGE_DAYS));
+        }
+        Ok(())
+    }
+
+    /// What `password` is missing, one entry per rule it breaks
+    pub fn violations(&self, password: &str) -> Vec<String> {
+        let mut violations = Vec::new();
+        if password.chars().count() < self.min_length {
+            violations.push(format!("Use at least {} characters", self.min_length));
+        }
+        if self.require_lowercase && !password.chars().any(|c| c.is_lowercase()) {
+            violations.push("Add lowercase letters".to_string());
+        }
+        if self.require_uppercase && !password.chars().any(|c| c.is_uppercase()) {
+            violations.push("Add uppercase letters".to_string());
+        }
+        if self.require_digit && !password.chars().any(|c| c.is_numeric()) {
+            violations.push("Add numbers".to_string());
+        }
+        if self.require_symbol && password.chars().all(|c| c.is_alphanumeric()) {
+            violations.push("Add special characters".to_string());
+        }
+        if self.block_common && is_common(password) {
+            violations.push("Pick something less common; this password is on every attacker's list".to_string());
+        }
+        violations
+    }
+
+    /// Ok if `password` meets the policy, else why not, ready to show the user
+    pub fn check(&self, password: &str) -> Result<(), String> {
+        let violations = self.violations(password);
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(format!("Password does not meet the policy: {}", violations.join(", ")))
+        }
+    }
+}
+
+fn is_common(password: &str) -> bool {
+    let password = password.to_lowercase();
+    COMMON_PASSWORDS.contains(&password.as_str())
+}
+
+// Key in the settings table
+pub const SETTINGS_KEY: &str = "password";
+
+pub async fn load(pool: &SqlitePool) -> PasswordPolicy {
+    match crate::db::settings::get(pool, SETTINGS_KEY).await {
+        Ok(policy) => policy.unwrap_or_default(),
+        Err(e) => {
+            tracing::warn!("Failed to read password policy, using defaults: {}", e);
+            PasswordPolicy::default()
+        }
+    }
+}
+
+pub async fn save(pool: &SqlitePool, policy: &PasswordPolicy) -> Result<(), String> {
+    crate::db::settings::set(pool, SETTINGS_KEY, policy).await.map_err(|e| e.to_string())
+}
+
+/// Note that `user_id` just got a new password, restarting its max age
+pub async fn mark_changed(pool: &SqlitePool, user_id: i64) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE users SET password_changed_at = datetime('now') WHERE id = ?")
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Whether `user_id` has had the same password for longer than the policy allows. Accounts
+/// that never changed it count from their creation.
+pub async fn is_expired(pool: &SqlitePool, user_id: i64) -> bool {
+    let policy = load(pool).await;
+    if policy.max_age_days == 0 {
+        return false;
+    }
+    sqlx::query_scalar(
+        "SELECT COALESCE(password_changed_at, created_at) < datetime('now', ?) FROM users
+         WHERE id = ? AND oidc_subject IS NULL AND ldap_dn IS NULL"
+    )
+    .bind(format!("-{} days", policy.max_age_days))
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await
+    .ok()
+    .flatten()
+    .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_policy_accepts_a_reasonable_password() {
+        assert!(PasswordPolicy::default().violations("Corr3ct-Horse").is_empty());
+    }
+
+    #[test]
+    fn each_broken_rule_is_reported_once() {
+        let policy = PasswordPolicy {
+            min_length: 10,
+            require_lowercase: true,
+            require_uppercase: true,
+            require_digit: true,
+            require_symbol: true,
+            block_common: false,
+            max_age_days: 0,
+        };
+        assert_eq!(policy.violations("ABC").len(), 4);
+        assert_eq!(policy.violations("abc").len(), 4);
+        assert_eq!(policy.violations("abcdefghij").len(), 3);
+        assert!(policy.violations("Abcdefgh1!").is_empty());
+    }
+
+    #[test]
+    fn length_counts_characters_not_bytes() {
+        let policy = PasswordPolicy { min_length: 8, require_uppercase: false, require_digit: false, ..Default::default() };
+        assert!(!policy.violations("ééééééé").is_empty());
+        assert!(policy.violations("éééééééé").is_empty());
+    }
+
+    #[test]
+    fn common_passwords_are_refused_case_insensitively() {
+        let policy = PasswordPolicy { require_uppercase: false, require_digit: false, ..Default::default() };
+        assert!(!policy.violations("password").is_empty());
+        assert!(!policy.violations("PassWord123").is_empty());
+        let relaxed = PasswordPolicy { block_common: false, ..policy };
+        assert!(relaxed.violations("password").is_empty());
+    }
+
+    #[test]
+    fn check_passes_only_when_nothing_is_violated() {
+        let policy = PasswordPolicy::default();
+        assert!(policy.check("Corr3ct-Horse").is_ok());
+        assert!(policy.check("short").is_err());
+    }
+}
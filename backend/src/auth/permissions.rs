@@ -0,0 +1,225 @@
+// Per-module access control. A permission is "<module>:read" or "<module>:write" (write includes
+// read). The built-in roles map to fixed sets; users with the "custom" role get exactly the
+// permissions stored for them in `user_permissions`.
+
+use axum::{
+    extract::{FromRequestParts, Request},
+    http::{Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
+use sqlx::SqlitePool;
+
+use crate::api::AuthUser;
+use crate::models::User;
+
+pub const ROLES: &[&str] = &["admin", "operator", "viewer", "custom"];
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Module {
+    pub name: &'static str,
+    pub description: &'static str,
+}
+
+const fn module(name: &'static str, description: &'static str) -> Module {
+    Module { name, description }
+}
+
+pub const MODULES: &[Module] = &[
+    module("dashboard", "Dashboard and status overview"),
+    module("network", "Interfaces, routes, WAN, traffic shaping, modem, mesh and presence"),
+    module("wifi", "WiFi networks and access points"),
+    module("dhcp", "DHCP settings and static leases"),
+    module("dns", "Local DNS and AdGuard"),
+    module("wol", "Wake-on-LAN"),
+    module("firewall", "Firewall, port forwards and DMZ"),
+    module("protection", "Blocklists, country blocking, honeypot and antivirus"),
+    module("security", "Security overview, connection logs and certificates"),
+    module("vpn", "Tailscale and Gluetun"),
+    module("docker", "Docker containers"),
+    module("media", "Media center"),
+    module("services", "System services and add-ons"),
+    module("tools", "Network tools, traffic and logs"),
+    module("system", "System settings, backups, updates and profiles"),
+    module("users", "User accounts"),
+];
+
+// Most specific prefix first. Paths not listed here (login, setup, own sessions and API keys,
+// health probes) only need a signed-in user, if that
+const PATH_MODULES: &[(&str, &str)] = &[
+    ("/api/network/wol", "wol"),
+    ("/api/network/wifi", "wifi"),
+    ("/api/network/aps", "wifi"),
+    ("/api/network/dhcp", "dhcp"),
+    ("/api/network/dns", "dns"),
+    ("/api/network", "network"),
+    ("/api/qos", "network"),
+    ("/api/adguard", "dns"),
+    ("/api/dashboard", "dashboard"),
+    ("/api/metrics", "dashboard"),
+    ("/api/firewall", "firewall"),
+    ("/api/protection", "protection"),
+    ("/api/antivirus", "protection"),
+    ("/api/security", "security"),
+    ("/api/certificates", "security"),
+    ("/api/vpn", "vpn"),
+    ("/api/docker", "docker"),
+    ("/api/media", "media"),
+    ("/api/services", "services"),
+    ("/api/addons", "services"),
+    ("/api/tools/backup", "system"),
+    ("/api/tools", "tools"),
+    ("/api/system", "system"),
+    ("/api/users", "users"),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+impl Access {
+    fn for_method(method: &Method) -> Self {
+        if matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS) {
+            Access::Read
+        } else {
+            Access::Write
+        }
+    }
+}
+
+/// Check and normalise a permission string such as "docker:read"
+pub fn parse(permission: &str) -> Result<(String, Access), String> {
+    let (name, access) = permission
+        .trim()
+        .split_once(':')
+        .ok_or_else(|| format!("Permission '{}' must look like module:read or module:write", permission))?;
+    if !MODULES.iter().any(|m| m.name == name) {
+        return Err(format!("Unknown module '{}'", name));
+    }
+    let access = match access {
+        "read" => Access::Read,
+        "write" => Access::Write,
+        _ => return Err(format!("Access in '{}' must be read or write", permission)),
+    };
+    Ok((name.to_string(), access))
+}
+
+/// Permissions a built-in role has for a module
+fn role_access(role: &str, module: &str) -> Option<Access> {
+    match role {
+        "admin" => Some(Access::Write),
+        "operator" if module == "users" => None,
+        "operator" if module == "system" => Some(Access::Read),
+        "operator" => Some(Access::Write),
+        "viewer" if module == "users" => None,
+        "viewer" => Some(Access::Read),
+        _ => None,
+    }
+}
+
+pub fn allows(user: &User, module: &str, access: Access) -> bool {
+    let granted = if user.role == "custom" {
+        user.permissions
+            .iter()
+            .filter_map(|p| parse(p).ok())
+            .filter(|(name, _)| name == module)
+            .map(|(_, a)| a)
+            .max_by_key(|a| *a == Access::Write)
+    } else {
+        role_access(&user.role, module)
+    };
+    matches!((granted, access), (Some(Access::Write), _) | (Some(Access::Read), Access::Read))
+}
+
+/// Every permission a user effectively has, for display
+pub fn effective(user: &User) -> Vec<String> {
+    MODULES
+        .iter()
+        .filter_map(|m| {
+            if allows(user, m.name, Access::Write) {
+                Some(format!("{}:write", m.name))
+            } else if allows(user, m.name, Access::Read) {
+                Some(format!("{}:read", m.name))
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+/// Handler-level check, for actions stricter than the module's default for their method
+pub fn require(user: &User, permission: &str) -> Result<(), (StatusCode, &'static str)> {
+    match parse(permission) {
+        Ok((module, access)) if allows(user, &module, access) => Ok(()),
+        _ => Err((StatusCode::FORBIDDEN, "Insufficient permissions")),
+    }
+}
+
+pub fn module_for_path(path: &str) -> Option<&'static str> {
+    PATH_MODULES
+        .iter()
+        .find(|(prefix, _)| path == *prefix || path.strip_prefix(prefix).is_some_and(|rest| rest.starts_with('/')))
+        .map(|(_, module)| *module)
+}
+
+// ============ STORAGE ============
+
+pub async fn load(pool: &SqlitePool, user_id: i64) -> Result<Vec<String>, sqlx::Error> {
+    sqlx::query_scalar("SELECT permission FROM user_permissions WHERE user_id = ? ORDER BY permission")
+        .bind(user_id)
+        .fetch_all(pool)
+        .await
+}
+
+/// Validate and store the permissions of a custom-role user, replacing what was there
+pub async fn save(pool: &SqlitePool, user_id: i64, permissions: &[String]) -> Result<(), String> {
+    let mut normalized: Vec<String> = Vec::new();
+    for permission in permissions {
+        let (module, access) = parse(permission)?;
+        let permission = format!("{}:{}", module, if access == Access::Write { "write" } else { "read" });
+        if !normalized.contains(&permission) {
+            normalized.push(permission);
+        }
+    }
+
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+    sqlx::query("DELETE FROM user_permissions WHERE user_id = ?")
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+    for permission in &normalized {
+        sqlx::query("INSERT INTO user_permissions (user_id, permission) VALUES (?, ?)")
+            .bind(user_id)
+            .bind(permission)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+    tx.commit().await.map_err(|e| e.to_string())
+}
+
+// ============ MIDDLEWARE ============
+
+// Route layer middleware: reads need <module>:read, anything else <module>:write. The user is
+// the API key's owner or the session's, resolved by the layers outside this one.
+pub async fn enforce(request: Request, next: Next) -> Response {
+    let Some(module) = module_for_path(request.uri().path()) else {
+        return next.run(request).await;
+    };
+    let access = Access::for_method(request.method());
+
+    let (mut parts, body) = request.into_parts();
+    let user = match AuthUser::from_request_parts(&mut parts, &()).await {
+        Ok(AuthUser(user)) => user,
+        Err(rejection) => return rejection.into_response(),
+    };
+    if !allows(&user, module, access) {
+        let needed = if access == Access::Write { "write" } else { "read" };
+        return (StatusCode::FORBIDDEN, format!("Requires the {}:{} permission", module, needed)).into_response();
+    }
+    next.run(Request::from_parts(parts, body)).await
+}
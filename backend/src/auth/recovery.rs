@@ -0,0 +1,140 @@
+// Getting back in when every admin password is lost, without wiping the database. Someone with a
+// shell on the router asks for a recovery code (routerui-cli recovery-code, or a POST to
+// /api/auth/recovery-code from the router itself, which prints it to the journal) and sends it
+// to /api/auth/recover with a new password. Only a hash is kept, the code dies after one use,
+// VALID_MINUTES or MAX_FAILURES wrong guesses, whichever comes first.
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+pub const VALID_MINUTES: i64 = 15;
+const MAX_FAILURES: u32 = 5;
+
+// Serialises read-modify-write of the code file, which the CLI may also write
+static LOCK: Mutex<()> = Mutex::new(());
+
+#[derive(Debug, Serialize, Deserialize)]
+struct PendingCode {
+    user_id: i64,
+    username: String,
+    code_hash: String,
+    expires_at: DateTime<Utc>,
+    failures: u32,
+}
+
+fn code_file() -> PathBuf {
+    crate::db::data_dir().join("recovery-code")
+}
+
+fn read_pending() -> Option<PendingCode> {
+    std::fs::read_to_string(code_file()).ok().and_then(|c| serde_json::from_str(&c).ok())
+}
+
+fn write_pending(pending: &PendingCode) -> std::io::Result<()> {
+    use std::io::Write;
+    use std::os::unix::fs::OpenOptionsExt;
+
+    let path = code_file();
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(&path)?;
+    file.write_all(serde_json::to_string(pending)?.as_bytes())?;
+    // Written by root from the CLI, the file must still be readable (and removable) by the service
+    if let Some(owner) = path.parent().and_then(|dir| std::fs::metadata(dir).ok()) {
+        use std::os::unix::fs::MetadataExt;
+        let _ = std::os::unix::fs::chown(&path, Some(owner.uid()), Some(owner.gid()));
+    }
+    Ok(())
+}
+
+fn discard() {
+    let _ = std::fs::remove_file(code_file());
+}
+
+// Plain SHA-256: the CLI writes the file without the server's token key loaded
+fn hash_code(code: &str) -> String {
+    hex::encode(ring::digest::digest(&ring::digest::SHA256, normalize(code).as_bytes()))
+}
+
+// Codes are shown grouped ("ABCD-EFGH-JKLM") but accepted however they are typed
+fn normalize(code: &str) -> String {
+    code.chars().filter(|c| c.is_ascii_alphanumeric()).collect::<String>().to_uppercase()
+}
+
+/// A new code for `username`, or the first admin when None, replacing any earlier one.
+/// Returns the code and whose password it resets.
+pub async fn issue(pool: &SqlitePool, username: Option<&str>) -> Result<(String, String), String> {
+    let user = match username {
+        Some(name) => crate::db::get_user_by_username(pool, name)
+            .await
+            .map_err(|e| e.to_string())?
+            .ok_or_else(|| format!("No such user: {}", name))?,
+        None => {
+            let id: i64 = sqlx::query_scalar("SELECT id FROM users WHERE role = 'admin' ORDER BY id LIMIT 1")
+                .fetch_optional(pool)
+                .await
+                .map_err(|e| e.to_string())?
+                .ok_or("There is no admin account; finish the setup wizard instead")?;
+            crate::db::get_user_by_id(pool, id)
+                .await
+                .map_err(|e| e.to_string())?
+                .ok_or("There is no admin account; finish the setup wizard instead")?
+        }
+    };
+
+    let raw = super::generate_token()[..12].to_uppercase();
+    let code = format!("{}-{}-{}", &raw[..4], &raw[4..8], &raw[8..]);
+
+    let _guard = LOCK.lock().unwrap();
+    write_pending(&PendingCode {
+        user_id: user.id,
+        username: user.username.clone(),
+        code_hash: hash_code(&code),
+        expires_at: Utc::now() + Duration::minutes(VALID_MINUTES),
+        failures: 0,
+    })
+    .map_err(|e| format!("Could not save the recovery code to {}: {}", code_file().display(), e))?;
+
+    Ok((code, user.username))
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Use `code` up; returns the account it was issued for. Wrong guesses count towards
+/// MAX_FAILURES, after which the code is thrown away.
+pub fn redeem(code: &str) -> Result<(i64, String), String> {
+    const INVALID: &str = "Invalid or expired recovery code";
+
+    let _guard = LOCK.lock().unwrap();
+    let mut pending = read_pending().ok_or(INVALID)?;
+    if pending.expires_at < Utc::now() {
+        discard();
+        return Err(INVALID.to_string());
+    }
+    if constant_time_eq(hash_code(code).as_bytes(), pending.code_hash.as_bytes()) {
+        discard();
+        return Ok((pending.user_id, pending.username));
+    }
+
+    pending.failures += 1;
+    tracing::warn!("Wrong recovery code entered ({}/{})", pending.failures, MAX_FAILURES);
+    if pending.failures >= MAX_FAILURES {
+        tracing::warn!("Recovery code discarded after {} wrong guesses", MAX_FAILURES);
+        discard();
+    } else if let Err(e) = write_pending(&pending) {
+        tracing::warn!("Could not count the failed recovery attempt, discarding the code: {}", e);
+        discard();
+    }
+    Err(INVALID.to_string())
+}
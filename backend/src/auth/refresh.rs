@@ -0,0 +1,192 @@
+// Refresh tokens for remembered devices. Signing in with "remember this device" gives an
+// ordinary short session plus a long-lived refresh token; POST /api/auth/refresh trades the
+// refresh token for a new session and a new refresh token. Each token works once. All tokens
+// handed out to one device form a family that shares the original expiry, and presenting a
+// token that was already used revokes the whole family: either the device or a thief is
+// replaying it, and we can't tell which.
+
+use chrono::Utc;
+use sqlx::SqlitePool;
+use std::net::IpAddr;
+
+use crate::events::{Event, EventBus};
+use crate::models::User;
+
+pub const COOKIE: &str = "refresh";
+// Only the refresh and logout endpoints ever see the cookie
+pub const COOKIE_PATH: &str = "/api/auth";
+
+#[derive(Debug, sqlx::FromRow)]
+struct RefreshToken {
+    id: i64,
+    family_id: String,
+    user_id: i64,
+    session_id: Option<i64>,
+    expires_at: String,
+    used_at: Option<String>,
+    revoked_at: Option<String>,
+}
+
+/// A new session and the refresh token that replaces the one presented
+pub struct Rotation {
+    pub user: User,
+    pub session_token: String,
+    pub refresh_token: String,
+    pub expires_at: String,
+}
+
+pub fn cookie(token: &str, max_age_secs: i64) -> String {
+    format!("{}={}; Path={}; HttpOnly; SameSite=Strict; Max-Age={}", COOKIE, token, COOKIE_PATH, max_age_secs)
+}
+
+pub fn clear_cookie() -> String {
+    format!("{}=; Path={}; HttpOnly; SameSite=Strict; Max-Age=0", COOKIE, COOKIE_PATH)
+}
+
+/// Refresh token from the `refresh` cookie
+pub fn from_cookie(headers: &axum::http::HeaderMap) -> Option<String> {
+    headers
+        .get_all(axum::http::header::COOKIE)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(';'))
+        .find_map(|c| c.trim().strip_prefix("refresh=").map(str::to_string))
+        .filter(|t| !t.is_empty())
+}
+
+/// Start a new family for a session that was just created with "remember this device"
+pub async fn issue(pool: &SqlitePool, user_id: i64, session_token: &str) -> Result<(String, String), sqlx::Error> {
+    let lifetime = super::session_policy::load(pool).await.remember_lifetime();
+    let expires_at = (Utc::now() + lifetime).to_rfc3339();
+    let family_id = uuid::Uuid::new_v4().to_string();
+    let token = insert(pool, &family_id, user_id, session_token, &expires_at).await?;
+    Ok((token, expires_at))
+}
+
+async fn insert(pool: &SqlitePool, family_id: &str, user_id: i64, session_token: &str, expires_at: &str) -> Result<String, sqlx::Error> {
+    let token = super::generate_token();
+    sqlx::query(
+        "INSERT INTO refresh_tokens (family_id, user_id, token_hash, session_id, expires_at)
+         VALUES (?, ?, ?, (SELECT id FROM sessions WHERE token_hash = ?), ?)"
+    )
+    .bind(family_id)
+    .bind(user_id)
+    .bind(super::hash_token(&token))
+    .bind(super::hash_token(session_token))
+    .bind(expires_at)
+    .execute(pool)
+    .await?;
+    Ok(token)
+}
+
+/// End every session and unused token of a family
+async fn revoke_family(pool: &SqlitePool, family_id: &str) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "UPDATE sessions SET revoked_at = datetime('now')
+         WHERE revoked_at IS NULL AND id IN (SELECT session_id FROM refresh_tokens WHERE family_id = ?)"
+    )
+    .bind(family_id)
+    .execute(pool)
+    .await?;
+    sqlx::query("UPDATE refresh_tokens SET revoked_at = datetime('now') WHERE family_id = ? AND revoked_at IS NULL")
+        .bind(family_id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Use a refresh token: its session is replaced by a new one and it by a new token.
+/// Err carries the message for the client.
+pub async fn rotate(
+    pool: &SqlitePool,
+    events: &EventBus,
+    token: &str,
+    ip_address: Option<&str>,
+    user_agent: Option<&str>,
+) -> Result<Rotation, String> {
+    let db = |e: sqlx::Error| e.to_string();
+    let invalid = || "Invalid or expired refresh token".to_string();
+
+    let row: Option<RefreshToken> = sqlx::query_as(
+        "SELECT id, family_id, user_id, session_id, expires_at, used_at, revoked_at FROM refresh_tokens WHERE token_hash = ?"
+    )
+    .bind(super::hash_token(token))
+    .fetch_optional(pool)
+    .await
+    .map_err(db)?;
+    let row = row.ok_or_else(invalid)?;
+
+    if row.used_at.is_some() && row.revoked_at.is_none() {
+        revoke_family(pool, &row.family_id).await.map_err(db)?;
+        tracing::warn!("Refresh token {} of user {} was reused; signed out that device", row.id, row.user_id);
+        return Err("This refresh token was already used, so the device has been signed out. Please sign in again.".to_string());
+    }
+    if row.revoked_at.is_some() || row.expires_at <= Utc::now().to_rfc3339() {
+        return Err(invalid());
+    }
+
+    let user = match crate::db::get_user_by_id(pool, row.user_id).await.map_err(db)? {
+        Some(user) if user.enabled => user,
+        _ => return Err(invalid()),
+    };
+
+    // With IP binding on, a refresh from another network would carry a stolen cookie straight
+    // past it, so it ends the device like a mismatched session does
+    let current_ip = ip_address.and_then(|ip| ip.parse::<IpAddr>().ok());
+    if let (Some(session_id), Some(current)) = (row.session_id, current_ip) {
+        let original: Option<Option<String>> = sqlx::query_scalar("SELECT ip_address FROM sessions WHERE id = ?")
+            .bind(session_id)
+            .fetch_optional(pool)
+            .await
+            .map_err(db)?;
+        let original = original.flatten().and_then(|ip| ip.parse::<IpAddr>().ok());
+        let binding = super::session_policy::load(pool).await.ip_binding;
+        if let Some(original) = original.filter(|&original| !binding.allows(original, current)) {
+            revoke_family(pool, &row.family_id).await.map_err(db)?;
+            tracing::warn!(
+                "Remembered device of {} signed in from {} was refreshed from {}; signed out",
+                user.username, original, current
+            );
+            events.emit(Event::SessionIpMismatch {
+                username: user.username,
+                session_id,
+                original_ip: original.to_string(),
+                ip: current.to_string(),
+            });
+            return Err("This device was signed in from another network. Please sign in again.".to_string());
+        }
+    }
+
+    // Claim the token before anything else, so two concurrent refreshes can't both succeed
+    let claimed = sqlx::query("UPDATE refresh_tokens SET used_at = datetime('now') WHERE id = ? AND used_at IS NULL")
+        .bind(row.id)
+        .execute(pool)
+        .await
+        .map_err(db)?;
+    if claimed.rows_affected() == 0 {
+        return Err(invalid());
+    }
+    if let Some(session_id) = row.session_id {
+        sqlx::query("UPDATE sessions SET revoked_at = datetime('now') WHERE id = ? AND revoked_at IS NULL")
+            .bind(session_id)
+            .execute(pool)
+            .await
+            .map_err(db)?;
+    }
+
+    let session_token = super::create_session(pool, user.id, ip_address, user_agent, true).await.map_err(db)?;
+    let refresh_token = insert(pool, &row.family_id, user.id, &session_token, &row.expires_at).await.map_err(db)?;
+    Ok(Rotation { user, session_token, refresh_token, expires_at: row.expires_at })
+}
+
+/// Revoke the family a refresh token belongs to, e.g. on logout
+pub async fn revoke(pool: &SqlitePool, token: &str) -> Result<(), sqlx::Error> {
+    let family: Option<String> = sqlx::query_scalar("SELECT family_id FROM refresh_tokens WHERE token_hash = ?")
+        .bind(super::hash_token(token))
+        .fetch_optional(pool)
+        .await?;
+    match family {
+        Some(family) => revoke_family(pool, &family).await,
+        None => Ok(()),
+    }
+}
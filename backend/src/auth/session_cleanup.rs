@@ -0,0 +1,119 @@
+// Sessions that can no longer be used: past their absolute expiry, signed out or revoked, or
+// idle longer than the policy allows. Nothing reads them again, so they are deleted rather than
+// left to grow the table. Refresh tokens past their expiry go with them.
+
+use chrono::Utc;
+use serde::Serialize;
+use sqlx::SqlitePool;
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::session_policy;
+use crate::AppState;
+
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(15 * 60);
+
+#[derive(Debug, Default, Serialize)]
+pub struct Cleanup {
+    pub expired: u64,
+    pub revoked: u64,
+    pub idle: u64,
+    // Ended because their user went over the per-user limit
+    pub over_limit: u64,
+    pub refresh_tokens: u64,
+}
+
+/// End the oldest live sessions of `user_id` beyond the policy's per-user limit, refresh tokens
+/// included so a remembered device doesn't sign itself back in. Returns how many were ended.
+pub async fn enforce_limit(pool: &SqlitePool, user_id: i64, limit: u32) -> Result<u64, sqlx::Error> {
+    if limit == 0 {
+        return Ok(0);
+    }
+    let over: Vec<i64> = sqlx::query_scalar(
+        "SELECT id FROM sessions
+         WHERE user_id = ? AND revoked_at IS NULL AND expires_at > ?
+         ORDER BY created_at DESC, id DESC
+         LIMIT -1 OFFSET ?"
+    )
+    .bind(user_id)
+    .bind(Utc::now().to_rfc3339())
+    .bind(limit as i64)
+    .fetch_all(pool)
+    .await?;
+
+    for id in &over {
+        sqlx::query("UPDATE refresh_tokens SET revoked_at = datetime('now') WHERE session_id = ? AND revoked_at IS NULL")
+            .bind(id)
+            .execute(pool)
+            .await?;
+        sqlx::query("UPDATE sessions SET revoked_at = datetime('now') WHERE id = ?")
+            .bind(id)
+            .execute(pool)
+            .await?;
+    }
+    Ok(over.len() as u64)
+}
+
+/// Delete every session that can't be used any more and apply the per-user limit
+pub async fn run(pool: &SqlitePool) -> Result<Cleanup, sqlx::Error> {
+    let policy = session_policy::load(pool).await;
+    let now = Utc::now().to_rfc3339();
+    let mut cleanup = Cleanup::default();
+
+    if policy.max_sessions_per_user > 0 {
+        let users: Vec<i64> = sqlx::query_scalar(
+            "SELECT user_id FROM sessions WHERE revoked_at IS NULL AND expires_at > ?
+             GROUP BY user_id HAVING COUNT(*) > ?"
+        )
+        .bind(&now)
+        .bind(policy.max_sessions_per_user as i64)
+        .fetch_all(pool)
+        .await?;
+        for user_id in users {
+            cleanup.over_limit += enforce_limit(pool, user_id, policy.max_sessions_per_user).await?;
+        }
+    }
+
+    cleanup.expired = sqlx::query("DELETE FROM sessions WHERE expires_at <= ?")
+        .bind(&now)
+        .execute(pool)
+        .await?
+        .rows_affected();
+    cleanup.revoked = sqlx::query("DELETE FROM sessions WHERE revoked_at IS NOT NULL")
+        .execute(pool)
+        .await?
+        .rows_affected();
+    // With the idle check off the cutoff is empty, which still catches rows never seen at all
+    cleanup.idle = sqlx::query("DELETE FROM sessions WHERE last_seen_at IS NULL OR last_seen_at < ?")
+        .bind(super::idle_cutoff(&policy))
+        .execute(pool)
+        .await?
+        .rows_affected();
+    cleanup.refresh_tokens = sqlx::query("DELETE FROM refresh_tokens WHERE expires_at <= ?")
+        .bind(&now)
+        .execute(pool)
+        .await?
+        .rows_affected();
+
+    // over_limit rows were revoked first, so they are counted again in revoked
+    let removed = cleanup.expired + cleanup.revoked + cleanup.idle;
+    if removed > 0 || cleanup.refresh_tokens > 0 {
+        tracing::info!(
+            "Session cleanup: {} sessions removed ({} over the per-user limit), {} refresh tokens",
+            removed, cleanup.over_limit, cleanup.refresh_tokens
+        );
+    }
+    Ok(cleanup)
+}
+
+pub fn spawn(state: Arc<AppState>) {
+    tokio::spawn(async move {
+        loop {
+            state.tasks.beat("session_cleanup", CLEANUP_INTERVAL);
+            if let Err(e) = run(&state.db).await {
+                tracing::error!("Session cleanup failed: {}", e);
+            }
+            tokio::time::sleep(CLEANUP_INTERVAL).await;
+        }
+    });
+}
@@ -0,0 +1,151 @@
+// How long a login lasts, and where from. Every session has an absolute limit and ends after a
+// stretch of inactivity; "remember this device" adds a refresh token that runs for days and
+// buys new sessions as old ones end (see refresh.rs). Optionally a session only works from the
+// network it was created on, so a cookie copied off a laptop is useless elsewhere.
+
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use std::net::IpAddr;
+
+const MAX_ABSOLUTE_HOURS: u32 = 7 * 24;
+const MAX_IDLE_MINUTES: u32 = 24 * 60;
+const MAX_REMEMBER_DAYS: u32 = 365;
+const MAX_SESSIONS_PER_USER: u32 = 100;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IpBinding {
+    #[default]
+    Off,
+    // Same /24 (IPv4) or /64 (IPv6): survives DHCP renumbering and IPv6 privacy addresses
+    Subnet,
+    Address,
+}
+
+impl IpBinding {
+    /// Whether a session created from `original` may be used from `current`
+    pub fn allows(self, original: IpAddr, current: IpAddr) -> bool {
+        let (original, current) = (original.to_canonical(), current.to_canonical());
+        match (self, original, current) {
+            (IpBinding::Off, _, _) => true,
+            (IpBinding::Address, a, b) => a == b,
+            (IpBinding::Subnet, IpAddr::V4(a), IpAddr::V4(b)) => a.octets()[..3] == b.octets()[..3],
+            (IpBinding::Subnet, IpAddr::V6(a), IpAddr::V6(b)) => a.segments()[..4] == b.segments()[..4],
+            // Switching address families means switching networks
+            (IpBinding::Subnet, _, _) => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SessionPolicy {
+    // Hard limit for a session, however active
+    pub absolute_hours: u32,
+    // Sessions end after this long without a request; 0 turns the idle check off
+    pub idle_minutes: u32,
+    // Lifetime of a remembered device's refresh tokens; 0 turns the option off
+    pub remember_days: u32,
+    // A session used from outside its network is ended and reported
+    pub ip_binding: IpBinding,
+    // Signed-in sessions one user can hold; the oldest are ended past this. 0 for no limit
+    pub max_sessions_per_user: u32,
+}
+
+impl Default for SessionPolicy {
+    fn default() -> Self {
+        Self {
+            absolute_hours: 12,
+            idle_minutes: 60,
+            remember_days: 30,
+            ip_binding: IpBinding::Off,
+            max_sessions_per_user: 0,
+        }
+    }
+}
+
+impl SessionPolicy {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(1..=MAX_ABSOLUTE_HOURS).contains(&self.absolute_hours) {
+            return Err(format!("Session lifetime must be 1-{} hours", MAX_ABSOLUTE_HOURS));
+        }
+        if self.idle_minutes > MAX_IDLE_MINUTES {
+            return Err(format!("Idle timeout must be at most {} minutes", MAX_IDLE_MINUTES));
+        }
+        if self.idle_minutes > 0 && self.idle_minutes < 5 {
+            return Err("Idle timeout must be at least 5 minutes (or 0 for none)".to_string());
+        }
+        if self.remember_days > MAX_REMEMBER_DAYS {
+            return Err(format!("Remembered devices can last at most {} days", MAX_REMEMBER_DAYS));
+        }
+        if self.max_sessions_per_user > MAX_SESSIONS_PER_USER {
+            return Err(format!("Sessions per user can be limited to at most {} (or 0 for no limit)", MAX_SESSIONS_PER_USER));
+        }
+        Ok(())
+    }
+
+    /// Absolute lifetime of a new session
+    pub fn session_lifetime(&self) -> chrono::Duration {
+        chrono::Duration::hours(self.absolute_hours as i64)
+    }
+
+    /// How long a remembered device can keep refreshing its session
+    pub fn remember_lifetime(&self) -> chrono::Duration {
+        chrono::Duration::days(self.remember_days as i64)
+    }
+
+    pub fn idle_timeout(&self) -> Option<chrono::Duration> {
+        (self.idle_minutes > 0).then(|| chrono::Duration::minutes(self.idle_minutes as i64))
+    }
+}
+
+// Key in the settings table
+pub const SETTINGS_KEY: &str = "session";
+
+/// Current policy, or the defaults until one is saved
+pub async fn load(pool: &SqlitePool) -> SessionPolicy {
+    match crate::db::settings::get(pool, SETTINGS_KEY).await {
+        Ok(policy) => policy.unwrap_or_default(),
+        Err(e) => {
+            tracing::warn!("Failed to read session policy, using defaults: {}", e);
+            SessionPolicy::default()
+        }
+    }
+}
+
+pub async fn save(pool: &SqlitePool, policy: &SessionPolicy) -> Result<(), String> {
+    crate::db::settings::set(pool, SETTINGS_KEY, policy).await.map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(value: &str) -> IpAddr {
+        value.parse().unwrap()
+    }
+
+    #[test]
+    fn off_allows_any_network() {
+        assert!(IpBinding::Off.allows(ip("192.168.1.10"), ip("203.0.113.7")));
+        assert!(IpBinding::Off.allows(ip("192.168.1.10"), ip("2001:db8::1")));
+    }
+
+    #[test]
+    fn address_binding_needs_the_same_address() {
+        assert!(IpBinding::Address.allows(ip("192.168.1.10"), ip("192.168.1.10")));
+        assert!(!IpBinding::Address.allows(ip("192.168.1.10"), ip("192.168.1.11")));
+        // The same client seen over a dual-stack socket
+        assert!(IpBinding::Address.allows(ip("192.168.1.10"), ip("::ffff:192.168.1.10")));
+    }
+
+    #[test]
+    fn subnet_binding_compares_the_network() {
+        assert!(IpBinding::Subnet.allows(ip("192.168.1.10"), ip("192.168.1.200")));
+        assert!(!IpBinding::Subnet.allows(ip("192.168.1.10"), ip("192.168.2.10")));
+        assert!(IpBinding::Subnet.allows(ip("::ffff:10.0.0.5"), ip("10.0.0.9")));
+        assert!(IpBinding::Subnet.allows(ip("2001:db8:1:2::10"), ip("2001:db8:1:2:abcd::1")));
+        assert!(!IpBinding::Subnet.allows(ip("2001:db8:1:2::10"), ip("2001:db8:1:3::10")));
+        assert!(!IpBinding::Subnet.allows(ip("192.168.1.10"), ip("2001:db8::1")));
+    }
+}
@@ -0,0 +1,160 @@
+use axum::http::{HeaderMap, StatusCode};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+pub const HEADER: &str = "x-setup-token";
+
+// After this many wrong tokens a client is refused for LOCKOUT
+const MAX_FAILURES: u32 = 5;
+const LOCKOUT: Duration = Duration::from_secs(5 * 60);
+// Forget clients that stopped guessing once the table grows past this
+const MAX_TRACKED: usize = 1024;
+
+fn token_file() -> PathBuf {
+    crate::db::data_dir().join("setup-token")
+}
+
+fn read_token() -> Option<String> {
+    std::fs::read_to_string(token_file())
+        .ok()
+        .map(|t| t.trim().to_string())
+        .filter(|t| !t.is_empty())
+}
+
+fn write_token(token: &str) -> std::io::Result<()> {
+    use std::os::unix::fs::OpenOptionsExt;
+    use std::io::Write;
+
+    let path = token_file();
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)?;
+    writeln!(file, "{}", token)
+}
+
+/// Token still waiting to be used, if setup hasn't completed
+pub fn pending_token() -> Option<String> {
+    read_token()
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[derive(Default)]
+struct Attempts {
+    failures: u32,
+    locked_until: Option<Instant>,
+}
+
+/// One-time token that must accompany every setup wizard mutation until setup completes,
+/// so nobody else on the LAN can claim the admin account first
+#[derive(Default)]
+pub struct SetupGuard {
+    // Failures are counted per client so one device can't lock everyone else out
+    attempts: Mutex<HashMap<IpAddr, Attempts>>,
+    // Set when setup is pending but the token file couldn't be written
+    token_error: Mutex<Option<String>>,
+}
+
+impl SetupGuard {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Called at startup: create (or reuse) the token while setup is pending and print it
+    pub async fn prepare(&self, pool: &sqlx::SqlitePool) {
+        if crate::api::setup::is_setup_complete(pool).await {
+            let _ = std::fs::remove_file(token_file());
+            return;
+        }
+
+        let token = match read_token() {
+            Some(t) => t,
+            None => {
+                let t = super::generate_token()[..12].to_uppercase();
+                if let Err(e) = write_token(&t) {
+                    tracing::error!("Could not save setup token to {}: {}", token_file().display(), e);
+                    *self.token_error.lock().unwrap() = Some(format!(
+                        "Setup token could not be saved to {}: {}. Fix the permissions and restart RouterUI.",
+                        token_file().display(),
+                        e
+                    ));
+                    return;
+                }
+                t
+            }
+        };
+
+        tracing::warn!("==================================================");
+        tracing::warn!("  RouterUI setup token: {}", token);
+        tracing::warn!("  Enter it in the setup wizard to continue.");
+        tracing::warn!("  Also stored in {}", token_file().display());
+        tracing::warn!("==================================================");
+    }
+
+    /// Check the X-Setup-Token header; wrong tokens count towards a temporary lockout for `peer`
+    pub fn verify(&self, peer: IpAddr, headers: &HeaderMap) -> Result<(), (StatusCode, String)> {
+        if let Some(error) = self.token_error.lock().unwrap().clone() {
+            return Err((StatusCode::INTERNAL_SERVER_ERROR, error));
+        }
+
+        let mut clients = self.attempts.lock().unwrap();
+        let now = Instant::now();
+
+        if clients.len() >= MAX_TRACKED {
+            clients.retain(|_, a| a.locked_until.is_some_and(|until| now < until));
+        }
+        let attempts = clients.entry(peer).or_default();
+
+        if let Some(until) = attempts.locked_until {
+            if now < until {
+                let secs = until.saturating_duration_since(now).as_secs();
+                return Err((
+                    StatusCode::TOO_MANY_REQUESTS,
+                    format!("Too many invalid setup tokens, try again in {}s", secs),
+                ));
+            }
+            attempts.locked_until = None;
+            attempts.failures = 0;
+        }
+
+        let expected = read_token().ok_or((
+            StatusCode::FORBIDDEN,
+            "Setup is already complete".to_string(),
+        ))?;
+
+        let provided = headers
+            .get(HEADER)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.trim().to_uppercase())
+            .unwrap_or_default();
+
+        if constant_time_eq(provided.as_bytes(), expected.as_bytes()) {
+            clients.remove(&peer);
+            return Ok(());
+        }
+
+        attempts.failures += 1;
+        tracing::warn!("Invalid setup token attempt from {} ({}/{})", peer, attempts.failures, MAX_FAILURES);
+        if attempts.failures >= MAX_FAILURES {
+            attempts.locked_until = Some(now + LOCKOUT);
+        }
+        Err((StatusCode::UNAUTHORIZED, "Invalid setup token".to_string()))
+    }
+
+    /// Setup finished - the token is no longer valid for anything
+    pub fn consume(&self) {
+        let _ = std::fs::remove_file(token_file());
+        self.attempts.lock().unwrap().clear();
+    }
+}
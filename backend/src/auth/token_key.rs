@@ -0,0 +1,83 @@
+// Key for hash_token. Session, refresh and API tokens are stored as HMAC-SHA256(key, token), so
+// a copy of the database alone is no use for checking guesses. The key is made on first boot
+// and kept next to the database; losing it signs everyone out and invalidates every API key.
+
+use ring::hmac;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+static KEY: OnceLock<hmac::Key> = OnceLock::new();
+
+fn key_file() -> PathBuf {
+    crate::db::data_dir().join("token-key")
+}
+
+fn load_or_create() -> std::io::Result<hmac::Key> {
+    use std::io::Write;
+    use std::os::unix::fs::OpenOptionsExt;
+
+    let path = key_file();
+    if let Ok(existing) = std::fs::read_to_string(&path) {
+        if let Ok(bytes) = hex::decode(existing.trim()) {
+            if bytes.len() >= 32 {
+                return Ok(hmac::Key::new(hmac::HMAC_SHA256, &bytes));
+            }
+        }
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("{} is not a valid token key", path.display()),
+        ));
+    }
+
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let secret = super::generate_token();
+    // create_new: two processes starting at once must not each write their own key
+    let mut file = match std::fs::OpenOptions::new().write(true).create_new(true).mode(0o600).open(&path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => return load_or_create(),
+        Err(e) => return Err(e),
+    };
+    writeln!(file, "{}", secret)?;
+    tracing::info!("Created token key {}", path.display());
+    Ok(hmac::Key::new(hmac::HMAC_SHA256, &hex::decode(secret).unwrap_or_default()))
+}
+
+/// Load the key, creating it on first boot; called at startup so a broken key file stops the
+/// server instead of silently signing everyone out
+pub fn init() -> std::io::Result<()> {
+    if KEY.get().is_none() {
+        let key = load_or_create()?;
+        let _ = KEY.set(key);
+    }
+    Ok(())
+}
+
+#[cfg(not(test))]
+pub(super) fn key() -> &'static hmac::Key {
+    KEY.get_or_init(|| {
+        load_or_create().unwrap_or_else(|e| {
+            tracing::error!("Token key unavailable ({}); tokens issued now won't survive a restart", e);
+            hmac::Key::new(hmac::HMAC_SHA256, super::generate_token().as_bytes())
+        })
+    })
+}
+
+// Tests get a fixed key rather than one written next to the database
+#[cfg(test)]
+pub(super) fn key() -> &'static hmac::Key {
+    KEY.get_or_init(|| hmac::Key::new(hmac::HMAC_SHA256, &[0x5a; 32]))
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn token_hashes_are_keyed_hex_digests() {
+        let hash = crate::auth::hash_token("session-a");
+        assert_eq!(hash.len(), 64);
+        assert!(hash.chars().all(|c| c.is_ascii_hexdigit()));
+        assert_eq!(hash, crate::auth::hash_token("session-a"));
+        assert_ne!(hash, crate::auth::hash_token("session-b"));
+    }
+}
@@ -0,0 +1,138 @@
+// Passphrase encryption for backups, which hold WiFi passwords, VPN keys and API tokens. The key
+// is derived from the passphrase with Argon2id and a random salt; the backup JSON is sealed with
+// AES-256-GCM. The envelope is itself JSON, so encrypted backups list, download and copy like
+// plain ones. The KDF parameters travel in the envelope and are authenticated with the data,
+// so they can be raised later without breaking old files.
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use base64::engine::general_purpose::STANDARD as B64;
+use base64::Engine;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+
+pub const FORMAT: &str = "routerui-encrypted-backup";
+const VERSION: u32 = 1;
+pub const MIN_PASSPHRASE_LEN: usize = 12;
+const SALT_LEN: usize = 16;
+// Argon2id: 64 MiB, 3 passes. Takes about a second on a small router, once per backup.
+const MEMORY_KIB: u32 = 64 * 1024;
+const ITERATIONS: u32 = 3;
+const PARALLELISM: u32 = 1;
+// Refuse to spend more than this deriving a key for a file someone handed us
+const MAX_MEMORY_KIB: u32 = 512 * 1024;
+const MAX_ITERATIONS: u32 = 16;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Kdf {
+    pub algorithm: String,
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+    pub salt: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Envelope {
+    pub format: String,
+    pub version: u32,
+    // When the backup was taken; left readable for listings
+    pub created: String,
+    pub kdf: Kdf,
+    pub cipher: String,
+    pub nonce: String,
+    pub ciphertext: String,
+}
+
+impl Envelope {
+    // Everything outside the ciphertext, bound to it so none of it can be swapped
+    fn aad(&self) -> String {
+        format!(
+            "{}:{}:{}:{}:{}:{}:{}:{}",
+            self.format, self.version, self.created, self.kdf.algorithm, self.kdf.memory_kib,
+            self.kdf.iterations, self.kdf.parallelism, self.kdf.salt
+        )
+    }
+}
+
+/// Whether `value` (a parsed backup file) is an encrypted envelope rather than a plain backup
+pub fn is_encrypted(value: &serde_json::Value) -> bool {
+    value.get("format").and_then(|f| f.as_str()) == Some(FORMAT)
+}
+
+pub fn validate_passphrase(passphrase: &str) -> Result<(), String> {
+    if passphrase.chars().count() < MIN_PASSPHRASE_LEN {
+        return Err(format!("Backup passphrases must be at least {} characters", MIN_PASSPHRASE_LEN));
+    }
+    Ok(())
+}
+
+fn derive_key(passphrase: &str, kdf: &Kdf) -> Result<LessSafeKey, String> {
+    if kdf.algorithm != "argon2id" {
+        return Err(format!("Unsupported key derivation {}", kdf.algorithm));
+    }
+    if kdf.memory_kib > MAX_MEMORY_KIB || kdf.iterations > MAX_ITERATIONS || kdf.parallelism > 16 {
+        return Err("Key derivation parameters are out of range".to_string());
+    }
+    let salt = B64.decode(&kdf.salt).map_err(|_| "Invalid salt".to_string())?;
+    let params = Params::new(kdf.memory_kib, kdf.iterations, kdf.parallelism, Some(32)).map_err(|e| e.to_string())?;
+    let mut key = [0u8; 32];
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(passphrase.as_bytes(), &salt, &mut key)
+        .map_err(|e| e.to_string())?;
+    let key = UnboundKey::new(&AES_256_GCM, &key).map_err(|_| "Invalid key".to_string())?;
+    Ok(LessSafeKey::new(key))
+}
+
+/// Encrypt a serialized backup
+pub fn seal(plaintext: &[u8], created: &str, passphrase: &str) -> Result<Envelope, String> {
+    validate_passphrase(passphrase)?;
+    let rng = SystemRandom::new();
+    let mut salt = [0u8; SALT_LEN];
+    let mut nonce = [0u8; NONCE_LEN];
+    rng.fill(&mut salt).map_err(|_| "No randomness available".to_string())?;
+    rng.fill(&mut nonce).map_err(|_| "No randomness available".to_string())?;
+
+    let mut envelope = Envelope {
+        format: FORMAT.to_string(),
+        version: VERSION,
+        created: created.to_string(),
+        kdf: Kdf {
+            algorithm: "argon2id".to_string(),
+            memory_kib: MEMORY_KIB,
+            iterations: ITERATIONS,
+            parallelism: PARALLELISM,
+            salt: B64.encode(salt),
+        },
+        cipher: "aes-256-gcm".to_string(),
+        nonce: B64.encode(nonce),
+        ciphertext: String::new(),
+    };
+
+    let key = derive_key(passphrase, &envelope.kdf)?;
+    let mut data = plaintext.to_vec();
+    key.seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), Aad::from(envelope.aad()), &mut data)
+        .map_err(|_| "Encryption failed".to_string())?;
+    envelope.ciphertext = B64.encode(data);
+    Ok(envelope)
+}
+
+/// Decrypt an envelope back to the backup JSON. A wrong passphrase and a tampered file look the
+/// same from here.
+pub fn open(envelope: &Envelope, passphrase: &str) -> Result<Vec<u8>, String> {
+    if envelope.format != FORMAT || envelope.version != VERSION || envelope.cipher != "aes-256-gcm" {
+        return Err("Unsupported backup encryption".to_string());
+    }
+    let nonce: [u8; NONCE_LEN] = B64
+        .decode(&envelope.nonce)
+        .ok()
+        .and_then(|n| n.try_into().ok())
+        .ok_or("Invalid nonce")?;
+    let mut data = B64.decode(&envelope.ciphertext).map_err(|_| "Invalid ciphertext".to_string())?;
+
+    let key = derive_key(passphrase, &envelope.kdf)?;
+    let plaintext = key
+        .open_in_place(Nonce::assume_unique_for_key(nonce), Aad::from(envelope.aad()), &mut data)
+        .map_err(|_| "Wrong passphrase, or the backup is damaged".to_string())?;
+    Ok(plaintext.to_vec())
+}
@@ -0,0 +1,212 @@
+// Offline companion to routerui-api for when the web UI is unreachable.
+// Talks to the database and config files directly - run it on the router itself (as root).
+
+use sqlx::sqlite::SqlitePoolOptions;
+use sqlx::SqlitePool;
+use std::path::Path;
+
+use routerui_api::{api, auth, db, system};
+
+const MOCK_DROPIN_DIR: &str = "/etc/systemd/system/routerui.service.d";
+const MOCK_DROPIN_FILE: &str = "/etc/systemd/system/routerui.service.d/mock.conf";
+
+const USAGE: &str = "routerui-cli - offline RouterUI administration
+
+USAGE:
+    routerui-cli <COMMAND> [ARGS]
+
+COMMANDS:
+    status                          Print system and database status
+    migrate                         Run database migrations
+    reset-password <user> [pass]    Set a user's password (random if omitted) and end their sessions
+    recovery-code [user]            Print a one-time code for resetting a password over the API
+                                    (default: the first admin)
+    backup export [file]            Write a backup to file (default: stdout)
+    backup import <file>            Restore a backup file
+    mock <on|off>                   Toggle mock mode for the routerui service
+    sudoers [user]                  Print a minimal sudoers.d policy (default user: routerui)
+    help                            Show this message
+
+Reads /etc/routerui/config.toml and DATABASE_URL like routerui-api. Backups are encrypted on
+export and opened on import with the passphrase in ROUTERUI_BACKUP_PASSPHRASE, when it is set.";
+
+type CliResult = Result<(), String>;
+
+async fn connect() -> Result<SqlitePool, String> {
+    SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect(&db::database_url())
+        .await
+        .map_err(|e| format!("Cannot open database {}: {}", db::database_url(), e))
+}
+
+async fn status() -> CliResult {
+    let pool = connect().await?;
+
+    let system = system::get_system_status().map_err(|e| e.to_string())?;
+    let database = db::maintenance::info(&pool, false).await.map_err(|e| e.to_string())?;
+    let setup_complete = api::setup::is_setup_complete(&pool).await;
+    let users = db::count_users(&pool).await.map_err(|e| e.to_string())?;
+
+    let report = serde_json::json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "mock_mode": Path::new(MOCK_DROPIN_FILE).exists(),
+        "setup_complete": setup_complete,
+        "setup_token": auth::setup_token::pending_token(),
+        "users": users,
+        "system": system,
+        "database": database,
+    });
+    println!("{}", serde_json::to_string_pretty(&report).unwrap());
+    Ok(())
+}
+
+async fn migrate() -> CliResult {
+    let pool = connect().await?;
+    db::migrate(&pool).await.map_err(|e| e.to_string())?;
+    for (version, description) in db::applied_migrations(&pool).await.map_err(|e| e.to_string())? {
+        println!("  {:04} {}", version, description);
+    }
+    println!("Migrations complete (schema v{})", db::schema_version());
+    Ok(())
+}
+
+async fn reset_password(username: &str, password: Option<&str>) -> CliResult {
+    let pool = connect().await?;
+
+    let user = db::get_user_by_username(&pool, username)
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("No such user: {}", username))?;
+
+    let generated = password.is_none();
+    let password = match password {
+        Some(p) => p.to_string(),
+        None => auth::generate_token()[..16].to_string(),
+    };
+    let hash = auth::hash_password(&password)?;
+
+    sqlx::query("UPDATE users SET password_hash = ?, enabled = 1 WHERE id = ?")
+        .bind(&hash)
+        .bind(user.id)
+        .execute(&pool)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    // Anyone holding the old credentials gets logged out
+    sqlx::query("DELETE FROM sessions WHERE user_id = ?")
+        .bind(user.id)
+        .execute(&pool)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    if generated {
+        println!("Password for {} reset to: {}", username, password);
+    } else {
+        println!("Password for {} updated", username);
+    }
+    Ok(())
+}
+
+async fn recovery_code(username: Option<&str>) -> CliResult {
+    let pool = connect().await?;
+    let (code, username) = auth::recovery::issue(&pool, username).await?;
+    println!("Recovery code for {}: {}", username, code);
+    println!(
+        "POST it with a new password to /api/auth/recover within {} minutes. It works once.",
+        auth::recovery::VALID_MINUTES
+    );
+    Ok(())
+}
+
+fn backup_passphrase() -> Option<String> {
+    std::env::var("ROUTERUI_BACKUP_PASSPHRASE").ok().filter(|p| !p.is_empty())
+}
+
+async fn backup_export(file: Option<&str>) -> CliResult {
+    let pool = connect().await?;
+    let backup = api::tools::collect_backup(&pool).await.map_err(|(_, e)| e)?;
+    let json = api::tools::encode_backup(&backup, backup_passphrase().as_deref())?;
+
+    match file {
+        Some(path) => {
+            std::fs::write(path, json).map_err(|e| e.to_string())?;
+            eprintln!("Backup written to {}", path);
+        }
+        None => println!("{}", json),
+    }
+    Ok(())
+}
+
+async fn backup_import(file: &str) -> CliResult {
+    let pool = connect().await?;
+    let content = std::fs::read_to_string(file).map_err(|e| format!("{}: {}", file, e))?;
+    let backup = api::tools::decode_backup(&content, backup_passphrase().as_deref())?;
+
+    let (restored, errors) = api::tools::apply_backup(&pool, &backup.configs)
+        .await
+        .map_err(|(_, e)| e)?;
+
+    println!("Restored: {}", restored.join(", "));
+    if !errors.is_empty() {
+        return Err(format!("Restore completed with errors:\n  {}", errors.join("\n  ")));
+    }
+    println!("Restart affected services (or reboot) to apply.");
+    Ok(())
+}
+
+fn mock(mode: &str) -> CliResult {
+    match mode {
+        "on" => {
+            std::fs::create_dir_all(MOCK_DROPIN_DIR).map_err(|e| e.to_string())?;
+            std::fs::write(MOCK_DROPIN_FILE, "[Service]\nEnvironment=ROUTERUI_MOCK=1\n")
+                .map_err(|e| e.to_string())?;
+        }
+        "off" => {
+            if Path::new(MOCK_DROPIN_FILE).exists() {
+                std::fs::remove_file(MOCK_DROPIN_FILE).map_err(|e| e.to_string())?;
+            }
+        }
+        _ => return Err("Expected 'on' or 'off'".to_string()),
+    }
+
+    let _ = std::process::Command::new("systemctl").arg("daemon-reload").status();
+    println!("Mock mode {} - run 'systemctl restart routerui' to apply", mode);
+    Ok(())
+}
+
+fn sudoers(user: &str) -> CliResult {
+    print!("{}", system::privileges::generate_sudoers(user));
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
+
+    let result = match args.as_slice() {
+        ["status"] => status().await,
+        ["migrate"] => migrate().await,
+        ["reset-password", user] => reset_password(user, None).await,
+        ["reset-password", user, pass] => reset_password(user, Some(pass)).await,
+        ["recovery-code"] => recovery_code(None).await,
+        ["recovery-code", user] => recovery_code(Some(user)).await,
+        ["backup", "export"] => backup_export(None).await,
+        ["backup", "export", file] => backup_export(Some(file)).await,
+        ["backup", "import", file] => backup_import(file).await,
+        ["mock", mode] => mock(mode),
+        ["sudoers"] => sudoers("routerui"),
+        ["sudoers", user] => sudoers(user),
+        [] | ["help"] | ["--help"] | ["-h"] => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+        _ => Err(format!("Unknown command\n\n{}", USAGE)),
+    };
+
+    if let Err(e) = result {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,262 @@
+// Root helper for routerui-api. The web service runs unprivileged and asks this process, over
+// a Unix socket, to run the specific commands and write the specific files it needs. Anything
+// outside the policy in routerui_api::helper is refused, so a bug in the web layer can't be
+// turned into arbitrary root access.
+
+use std::io::{BufReader, IsTerminal, Read, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use routerui_api::helper::{self, Privileged, Request, Response};
+use routerui_api::{config, logging, system};
+
+const USAGE: &str = "routerui-helper - privileged helper for RouterUI
+
+USAGE:
+    routerui-helper [serve]                 Listen on the helper socket (run as root)
+    routerui-helper exec <program> [args]   Run a command through the helper (used by routerui-api)
+    routerui-helper ping                    Check that the helper is reachable";
+
+// ============ SERVER ============
+
+fn group_id(name: &str) -> Option<u32> {
+    let groups = std::fs::read_to_string("/etc/group").ok()?;
+    groups.lines().find_map(|line| {
+        let mut fields = line.split(':');
+        if fields.next()? != name {
+            return None;
+        }
+        fields.nth(1)?.parse().ok()
+    })
+}
+
+fn run_command(command: &Privileged, stdin: Option<&str>) -> Response {
+    let (program, args) = match command.argv() {
+        Ok(argv) => argv,
+        Err(e) => return Response::denied(e),
+    };
+    let Some(path) = system::preflight::find_binary(program) else {
+        return Response::denied(format!("{} is not installed", program));
+    };
+    let stdin = stdin.filter(|_| command.accepts_stdin());
+
+    let mut process = Command::new(path);
+    process
+        .args(args)
+        .env_clear()
+        .env("PATH", "/usr/local/sbin:/usr/local/bin:/usr/sbin:/usr/bin:/sbin:/bin")
+        .env("LANG", "C.UTF-8")
+        .stdin(if stdin.is_some() { Stdio::piped() } else { Stdio::null() })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+
+    let mut child = match process.spawn() {
+        Ok(c) => c,
+        Err(e) => return Response::denied(e.to_string()),
+    };
+    if let (Some(input), Some(mut pipe)) = (stdin, child.stdin.take()) {
+        let _ = pipe.write_all(input.as_bytes());
+    }
+
+    match child.wait_with_output() {
+        Ok(output) => Response {
+            ok: true,
+            status: output.status.code(),
+            stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+            error: None,
+        },
+        Err(e) => Response::denied(e.to_string()),
+    }
+}
+
+fn write_file(path: &Path, contents: &str) -> Result<(), String> {
+    // procfs entries can't be replaced by rename
+    if path.starts_with("/proc") {
+        return std::fs::write(path, contents).map_err(|e| e.to_string());
+    }
+
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    }
+    let tmp = path.with_extension("routerui-tmp");
+    std::fs::write(&tmp, contents).map_err(|e| e.to_string())?;
+    std::fs::set_permissions(&tmp, std::fs::Permissions::from_mode(0o644)).map_err(|e| e.to_string())?;
+    std::fs::rename(&tmp, path).map_err(|e| {
+        let _ = std::fs::remove_file(&tmp);
+        e.to_string()
+    })
+}
+
+fn handle(request: Request, peer: u32) -> Response {
+    match request {
+        Request::Ping => Response { ok: true, stdout: env!("CARGO_PKG_VERSION").to_string(), ..Default::default() },
+        Request::Check { command } => match helper::command_allowed(&command) {
+            Ok(()) => Response { ok: true, ..Default::default() },
+            Err(e) => Response::denied(e),
+        },
+        Request::Run { command, stdin, request_id } => {
+            let request_id = request_id.as_deref().unwrap_or("-");
+            if let Err(e) = helper::command_allowed(&command) {
+                tracing::warn!(peer, request_id, "Refused: {}", e);
+                return Response::denied(e);
+            }
+            let (program, args) = command.argv().unwrap_or_default();
+            let args: Vec<String> = args.iter().map(|a| system::commands::redact(a)).collect();
+            let line = format!("{} {}", program, args.join(" "));
+            tracing::info!(peer, request_id, "Run: {}", line);
+            let response = run_command(&command, stdin.as_deref());
+            if response.status != Some(0) {
+                let reason = response.error.as_deref().or(response.stderr.lines().next()).unwrap_or_default();
+                tracing::warn!(peer, request_id, status = response.status, "Failed: {}: {}", line, reason);
+            }
+            response
+        }
+        Request::WriteFile { path, contents, dry_run, request_id } => {
+            let request_id = request_id.as_deref().unwrap_or("-");
+            if let Err(e) = helper::write_allowed(&path) {
+                tracing::warn!(peer, request_id, "Refused: {}", e);
+                return Response::denied(e);
+            }
+            if dry_run {
+                return Response { ok: true, ..Default::default() };
+            }
+            tracing::info!(peer, request_id, "Write: {} ({} bytes)", path.display(), contents.len());
+            match write_file(&path, &contents) {
+                Ok(()) => Response { ok: true, ..Default::default() },
+                Err(e) => {
+                    tracing::warn!(peer, request_id, "Failed: write {}: {}", path.display(), e);
+                    Response::denied(e)
+                }
+            }
+        }
+    }
+}
+
+async fn serve() -> Result<(), String> {
+    let config = config::get();
+    logging::init(&config.log_level, config.log_format);
+
+    let socket = &config.helper_socket;
+    let gid = match &config.helper_group {
+        Some(name) => Some(group_id(name).ok_or_else(|| format!("Unknown group '{}'", name))?),
+        None => None,
+    };
+
+    if let Some(dir) = socket.parent() {
+        std::fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+    }
+    let _ = std::fs::remove_file(socket);
+    let listener = tokio::net::UnixListener::bind(socket).map_err(|e| format!("{}: {}", socket.display(), e))?;
+
+    // Only root and the web service's group may connect
+    std::os::unix::fs::chown(socket, Some(0), gid).map_err(|e| e.to_string())?;
+    std::fs::set_permissions(socket, std::fs::Permissions::from_mode(if gid.is_some() { 0o660 } else { 0o600 }))
+        .map_err(|e| e.to_string())?;
+
+    tracing::info!("routerui-helper listening on {}", socket.display());
+
+    loop {
+        let (stream, _) = match listener.accept().await {
+            Ok(s) => s,
+            Err(e) => {
+                tracing::error!("accept failed: {}", e);
+                continue;
+            }
+        };
+
+        let cred = match stream.peer_cred() {
+            Ok(c) => c,
+            Err(_) => continue,
+        };
+        if cred.uid() != 0 && Some(cred.gid()) != gid {
+            tracing::warn!("Rejected connection from uid {} gid {}", cred.uid(), cred.gid());
+            continue;
+        }
+
+        let Ok(stream) = stream.into_std() else { continue };
+        tokio::task::spawn_blocking(move || {
+            let _ = stream.set_nonblocking(false);
+            let response = match helper::read_request(BufReader::new(&stream)) {
+                Ok(request) => handle(request, cred.uid()),
+                Err(e) => Response::denied(e),
+            };
+            let mut line = serde_json::to_vec(&response).unwrap_or_default();
+            line.push(b'\n');
+            let _ = (&stream).write_all(&line);
+        });
+    }
+}
+
+// ============ CLIENT ============
+
+// Behaves like the command itself: same stdout/stderr and exit status
+fn exec(program: &str, args: &[String]) -> i32 {
+    let stdin = if std::io::stdin().is_terminal() {
+        None
+    } else {
+        let mut input = String::new();
+        let _ = std::io::stdin().read_to_string(&mut input);
+        Some(input).filter(|i| !i.is_empty())
+    };
+
+    // The shim is unprivileged; the helper validates the typed operation again on its side
+    let command = match Privileged::parse(program, args) {
+        Ok(c) => c,
+        Err(e) => {
+            eprintln!("routerui-helper: {} - not allowed by helper policy", e);
+            return 126;
+        }
+    };
+    let request_id = std::env::var(helper::REQUEST_ID_ENV).ok().filter(|id| !id.is_empty());
+    let request = Request::Run { command, stdin, request_id };
+    match helper::call(&request) {
+        Ok(response) => {
+            print!("{}", response.stdout);
+            eprint!("{}", response.stderr);
+            match response.error {
+                Some(e) => {
+                    eprintln!("routerui-helper: {}", e);
+                    126
+                }
+                None => response.status.unwrap_or(1),
+            }
+        }
+        Err(e) => {
+            eprintln!("routerui-helper: cannot reach routerui-helper at {}: {}", helper::socket_path().display(), e);
+            125
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    let code = match args.first().map(|s| s.as_str()) {
+        None | Some("serve") => match serve().await {
+            Ok(()) => 0,
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                1
+            }
+        },
+        Some("exec") if args.len() >= 2 => exec(&args[1], &args[2..]),
+        Some("ping") => match helper::call(&Request::Ping) {
+            Ok(r) => {
+                println!("routerui-helper {} at {}", r.stdout, helper::socket_path().display());
+                0
+            }
+            Err(e) => {
+                eprintln!("Cannot reach {}: {}", helper::socket_path().display(), e);
+                1
+            }
+        },
+        _ => {
+            println!("{}", USAGE);
+            2
+        }
+    };
+    std::process::exit(code);
+}
@@ -0,0 +1,187 @@
+use axum::{
+    body::Body,
+    extract::Request,
+    http::{header, HeaderValue, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// Cache keys and TTLs for the status endpoints the frontend polls
+pub const DOCKER_STATUS: (&str, Duration) = ("docker_status", Duration::from_secs(10));
+pub const VPN_OVERVIEW: (&str, Duration) = ("vpn_overview", Duration::from_secs(10));
+pub const ADGUARD_OVERVIEW: (&str, Duration) = ("adguard_overview", Duration::from_secs(5));
+pub const SYSTEM_STATUS: (&str, Duration) = ("system_status", Duration::from_secs(5));
+
+struct Entry {
+    value: serde_json::Value,
+    fetched: Instant,
+    fetched_at: DateTime<Utc>,
+}
+
+/// Staleness info attached to cached responses under the `_cache` key
+#[derive(Debug, Serialize)]
+pub struct CacheMeta {
+    pub cached: bool,
+    pub age_secs: u64,
+    pub ttl_secs: u64,
+    pub fetched_at: String,
+}
+
+/// `?refresh=true` bypasses the cache and re-fetches
+#[derive(Debug, Default, Deserialize)]
+pub struct RefreshQuery {
+    #[serde(default)]
+    pub refresh: bool,
+}
+
+/// TTL cache for JSON responses of expensive status endpoints
+#[derive(Default)]
+pub struct ResponseCache {
+    entries: Mutex<HashMap<&'static str, Entry>>,
+}
+
+impl ResponseCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Return the cached value for `key` if younger than `ttl`, otherwise run `fetch` and store
+    /// its result. Errors are never cached.
+    pub async fn get_or_fetch<F, Fut, E>(
+        &self,
+        (key, ttl): (&'static str, Duration),
+        force_refresh: bool,
+        fetch: F,
+    ) -> Result<serde_json::Value, E>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<serde_json::Value, E>>,
+    {
+        if !force_refresh {
+            let entries = self.entries.lock().unwrap();
+            if let Some(entry) = entries.get(key) {
+                let age = entry.fetched.elapsed();
+                if age < ttl {
+                    return Ok(with_meta(entry.value.clone(), CacheMeta {
+                        cached: true,
+                        age_secs: age.as_secs(),
+                        ttl_secs: ttl.as_secs(),
+                        fetched_at: entry.fetched_at.to_rfc3339(),
+                    }));
+                }
+            }
+        }
+
+        let value = fetch().await?;
+        let fetched_at = Utc::now();
+
+        self.entries.lock().unwrap().insert(key, Entry {
+            value: value.clone(),
+            fetched: Instant::now(),
+            fetched_at,
+        });
+
+        Ok(with_meta(value, CacheMeta {
+            cached: false,
+            age_secs: 0,
+            ttl_secs: ttl.as_secs(),
+            fetched_at: fetched_at.to_rfc3339(),
+        }))
+    }
+
+    pub fn invalidate(&self, key: &str) {
+        self.entries.lock().unwrap().remove(key);
+    }
+}
+
+fn with_meta(mut value: serde_json::Value, meta: CacheMeta) -> serde_json::Value {
+    if let serde_json::Value::Object(ref mut map) = value {
+        map.insert("_cache".to_string(), serde_json::to_value(meta).unwrap());
+    }
+    value
+}
+
+// SvelteKit puts content-hashed build output here; a file's name changes whenever its content does
+const IMMUTABLE_ASSETS: &str = "/_app/immutable/";
+
+/// Layer for the frontend files: hashed build output is cached for a year, everything else
+/// (index.html, also when served as the SPA fallback) is revalidated so a new build shows up
+/// on the next load
+pub async fn static_asset_headers(request: Request, next: Next) -> Response {
+    let immutable = request.uri().path().starts_with(IMMUTABLE_ASSETS);
+    let mut response = next.run(request).await;
+    let value = if immutable && response.status().is_success() {
+        "public, max-age=31536000, immutable"
+    } else {
+        "no-cache"
+    };
+    response.headers_mut().insert(header::CACHE_CONTROL, HeaderValue::from_static(value));
+    response
+}
+
+// Weak because `_cache` is left out of the hash: two responses that differ only in cache age
+// count as the same
+fn json_etag(body: &[u8]) -> Option<String> {
+    use std::hash::{Hash, Hasher};
+
+    let mut value: serde_json::Value = serde_json::from_slice(body).ok()?;
+    if let serde_json::Value::Object(ref mut map) = value {
+        map.remove("_cache");
+    }
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    value.to_string().hash(&mut hasher);
+    Some(format!("W/\"{:016x}\"", hasher.finish()))
+}
+
+fn etag_matches(if_none_match: &str, etag: &str) -> bool {
+    let opaque = |tag: &str| tag.trim().trim_start_matches("W/").to_string();
+    if_none_match.split(',').any(|tag| tag.trim() == "*" || opaque(tag) == opaque(etag))
+}
+
+/// Route layer for polled GET endpoints: tags JSON responses with an ETag and answers
+/// 304 Not Modified when the client already has that version
+pub async fn etag(request: Request, next: Next) -> Response {
+    if request.method() != Method::GET {
+        return next.run(request).await;
+    }
+    let if_none_match = request
+        .headers()
+        .get(header::IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string);
+
+    let response = next.run(request).await;
+    if response.status() != StatusCode::OK {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let bytes = match axum::body::to_bytes(body, usize::MAX).await {
+        Ok(bytes) => bytes,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    };
+    let Some(tag) = json_etag(&bytes).and_then(|t| HeaderValue::from_str(&t).ok()) else {
+        return Response::from_parts(parts, Body::from(bytes));
+    };
+
+    // no-cache: the browser may keep the response but has to revalidate before every use
+    parts.headers.insert(header::ETAG, tag.clone());
+    parts.headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+
+    if if_none_match.is_some_and(|v| etag_matches(&v, tag.to_str().unwrap_or_default())) {
+        let mut not_modified = StatusCode::NOT_MODIFIED.into_response();
+        for name in [header::ETAG, header::CACHE_CONTROL] {
+            if let Some(value) = parts.headers.remove(&name) {
+                not_modified.headers_mut().insert(name, value);
+            }
+        }
+        return not_modified;
+    }
+    Response::from_parts(parts, Body::from(bytes))
+}
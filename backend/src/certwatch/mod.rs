@@ -0,0 +1,358 @@
+// Inventory of the TLS certificates served on the LAN: RouterUI's own, the ones issued through
+// ACME, and whatever local services (Jellyfin, AdGuard, reverse-proxy vhosts) present when
+// probed. A certificate close to expiry raises one event per certificate and expiry date.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use std::collections::HashSet;
+use std::net::{IpAddr, SocketAddr};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::events::Event;
+use crate::AppState;
+
+const SETTINGS_FILE: &str = "/opt/routerui/certificate-monitor.json";
+const CHECK_INTERVAL: Duration = Duration::from_secs(12 * 60 * 60);
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+const MAX_ENDPOINTS: usize = 50;
+
+// ============ SETTINGS ============
+
+/// A TLS service to connect to and read the certificate from
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Endpoint {
+    pub name: String,
+    pub host: String,
+    pub port: u16,
+    // SNI name to ask for, for reverse-proxy vhosts sharing one address
+    #[serde(default)]
+    pub server_name: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MonitorSettings {
+    #[serde(default = "default_warn_days")]
+    pub warn_days: i64,
+    #[serde(default = "default_endpoints")]
+    pub endpoints: Vec<Endpoint>,
+}
+
+fn default_warn_days() -> i64 {
+    14
+}
+
+fn default_endpoints() -> Vec<Endpoint> {
+    let endpoint = |name: &str, port| Endpoint {
+        name: name.to_string(),
+        host: "127.0.0.1".to_string(),
+        port,
+        server_name: None,
+    };
+    vec![endpoint("Jellyfin", 8920), endpoint("AdGuard Home", 443)]
+}
+
+impl Default for MonitorSettings {
+    fn default() -> Self {
+        Self {
+            warn_days: default_warn_days(),
+            endpoints: default_endpoints(),
+        }
+    }
+}
+
+pub fn load_settings() -> MonitorSettings {
+    std::fs::read_to_string(SETTINGS_FILE)
+        .ok()
+        .and_then(|c| serde_json::from_str(&c).ok())
+        .unwrap_or_default()
+}
+
+pub fn save_settings(settings: &MonitorSettings) -> Result<(), String> {
+    let json = serde_json::to_string_pretty(settings).map_err(|e| e.to_string())?;
+    std::fs::write(SETTINGS_FILE, json).map_err(|e| e.to_string())
+}
+
+fn valid_hostname(value: &str) -> bool {
+    !value.is_empty()
+        && value.len() <= 253
+        && value.split('.').all(|label| {
+            !label.is_empty() && !label.starts_with('-') && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        })
+}
+
+impl MonitorSettings {
+    pub fn normalize(&mut self) -> Result<(), String> {
+        if !(1..=90).contains(&self.warn_days) {
+            return Err("Warning period must be between 1 and 90 days".to_string());
+        }
+        if self.endpoints.len() > MAX_ENDPOINTS {
+            return Err(format!("At most {} endpoints can be monitored", MAX_ENDPOINTS));
+        }
+        for endpoint in &mut self.endpoints {
+            endpoint.name = endpoint.name.trim().to_string();
+            endpoint.host = endpoint.host.trim().to_lowercase();
+            endpoint.server_name = endpoint
+                .server_name
+                .as_deref()
+                .map(|s| s.trim().to_lowercase())
+                .filter(|s| !s.is_empty());
+
+            if endpoint.name.is_empty() || endpoint.name.len() > 64 {
+                return Err("Endpoint names must be 1-64 characters".to_string());
+            }
+            if endpoint.host.parse::<IpAddr>().is_err() && !valid_hostname(&endpoint.host) {
+                return Err(format!("Invalid host: {}", endpoint.host));
+            }
+            if endpoint.port == 0 {
+                return Err(format!("{}: port is required", endpoint.name));
+            }
+            if let Some(server_name) = &endpoint.server_name {
+                if !valid_hostname(server_name) {
+                    return Err(format!("Invalid server name: {}", server_name));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// ============ INVENTORY ============
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CertSource {
+    Routerui,
+    Acme,
+    Endpoint,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CertStatus {
+    Valid,
+    Expiring,
+    Expired,
+    // The file couldn't be read or the service didn't answer with TLS
+    Error,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct InventoryEntry {
+    pub name: String,
+    pub source: CertSource,
+    // File path or host:port
+    pub location: String,
+    pub subject: Option<String>,
+    pub issuer: Option<String>,
+    pub dns_names: Vec<String>,
+    pub expires_at: Option<String>,
+    pub days_left: Option<i64>,
+    pub self_signed: bool,
+    pub status: CertStatus,
+    pub error: Option<String>,
+}
+
+struct CertInfo {
+    subject: String,
+    issuer: String,
+    dns_names: Vec<String>,
+    not_after: DateTime<Utc>,
+}
+
+fn parse_der(der: &[u8]) -> Result<CertInfo, String> {
+    let (_, cert) = x509_parser::parse_x509_certificate(der).map_err(|e| e.to_string())?;
+    let dns_names = cert
+        .subject_alternative_name()
+        .ok()
+        .flatten()
+        .map(|san| {
+            san.value
+                .general_names
+                .iter()
+                .filter_map(|name| match name {
+                    x509_parser::extensions::GeneralName::DNSName(dns) => Some(dns.to_string()),
+                    _ => None,
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    Ok(CertInfo {
+        subject: cert.subject().to_string(),
+        issuer: cert.issuer().to_string(),
+        dns_names,
+        not_after: DateTime::from_timestamp(cert.validity().not_after.timestamp(), 0)
+            .ok_or_else(|| "Invalid expiry".to_string())?,
+    })
+}
+
+// First certificate of a PEM file, i.e. the leaf of a fullchain
+fn read_pem(path: &Path) -> Result<CertInfo, String> {
+    let pem = std::fs::read(path).map_err(|e| e.to_string())?;
+    let (_, pem) = x509_parser::pem::parse_x509_pem(&pem).map_err(|e| e.to_string())?;
+    parse_der(&pem.contents)
+}
+
+// Self-signed and private-CA certificates are the norm on a LAN, so nothing is verified
+async fn probe(endpoint: &Endpoint) -> Result<CertInfo, String> {
+    let mut builder = reqwest::Client::builder()
+        .timeout(PROBE_TIMEOUT)
+        .tls_danger_accept_invalid_certs(true)
+        .tls_info(true)
+        .redirect(reqwest::redirect::Policy::none());
+
+    let host = match &endpoint.server_name {
+        Some(server_name) => {
+            let ip: IpAddr = match endpoint.host.parse() {
+                Ok(ip) => ip,
+                Err(_) => tokio::net::lookup_host((endpoint.host.as_str(), endpoint.port))
+                    .await
+                    .map_err(|e| e.to_string())?
+                    .next()
+                    .ok_or_else(|| format!("{} did not resolve", endpoint.host))?
+                    .ip(),
+            };
+            builder = builder.resolve(server_name, SocketAddr::new(ip, endpoint.port));
+            server_name.clone()
+        }
+        None if endpoint.host.contains(':') => format!("[{}]", endpoint.host),
+        None => endpoint.host.clone(),
+    };
+    let client = builder.build().map_err(|e| e.to_string())?;
+
+    let response = client
+        .get(format!("https://{}:{}/", host, endpoint.port))
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    let der = response
+        .extensions()
+        .get::<reqwest::tls::TlsInfo>()
+        .and_then(|info| info.peer_certificate())
+        .ok_or_else(|| "No certificate presented".to_string())?;
+    parse_der(der)
+}
+
+fn entry(name: &str, source: CertSource, location: String, result: Result<CertInfo, String>, warn_days: i64) -> InventoryEntry {
+    let mut entry = InventoryEntry {
+        name: name.to_string(),
+        source,
+        location,
+        subject: None,
+        issuer: None,
+        dns_names: Vec::new(),
+        expires_at: None,
+        days_left: None,
+        self_signed: false,
+        status: CertStatus::Error,
+        error: None,
+    };
+
+    match result {
+        Ok(info) => {
+            let days_left = (info.not_after - Utc::now()).num_days();
+            entry.status = if info.not_after <= Utc::now() {
+                CertStatus::Expired
+            } else if days_left < warn_days {
+                CertStatus::Expiring
+            } else {
+                CertStatus::Valid
+            };
+            entry.self_signed = info.subject == info.issuer;
+            entry.subject = Some(info.subject);
+            entry.issuer = Some(info.issuer);
+            entry.dns_names = info.dns_names;
+            entry.expires_at = Some(info.not_after.to_rfc3339());
+            entry.days_left = Some(days_left);
+        }
+        Err(e) => entry.error = Some(e),
+    }
+    entry
+}
+
+/// Every certificate RouterUI knows about, soonest expiry first
+pub async fn inventory(pool: &SqlitePool, settings: &MonitorSettings) -> Vec<InventoryEntry> {
+    let mut entries = Vec::new();
+
+    let ui_cert = crate::config::get().tls.as_ref().map(|t| t.cert.clone());
+    if let Some(path) = &ui_cert {
+        let location = path.display().to_string();
+        entries.push(entry("RouterUI", CertSource::Routerui, location, read_pem(path), settings.warn_days));
+    }
+
+    for cert in crate::acme::list(pool).await.unwrap_or_default() {
+        if cert.status != "valid" || cert.used_by_ui {
+            continue;
+        }
+        let path = Path::new(&cert.cert_path);
+        entries.push(entry(&cert.name, CertSource::Acme, cert.cert_path.clone(), read_pem(path), settings.warn_days));
+    }
+
+    let mut probes = tokio::task::JoinSet::new();
+    for (index, endpoint) in settings.endpoints.iter().cloned().enumerate() {
+        probes.spawn(async move {
+            let result = probe(&endpoint).await;
+            (index, endpoint, result)
+        });
+    }
+    let mut probed: Vec<_> = probes.join_all().await;
+    probed.sort_by_key(|(index, _, _)| *index);
+    for (_, endpoint, result) in probed {
+        let location = match &endpoint.server_name {
+            Some(server_name) => format!("{} ({}:{})", server_name, endpoint.host, endpoint.port),
+            None => format!("{}:{}", endpoint.host, endpoint.port),
+        };
+        entries.push(entry(&endpoint.name, CertSource::Endpoint, location, result, settings.warn_days));
+    }
+
+    entries.sort_by_key(|e| e.days_left.unwrap_or(i64::MAX));
+    entries
+}
+
+// ============ ALERTS ============
+
+/// Remembers which expiring certificates were already reported
+#[derive(Default)]
+pub struct CertTracker {
+    notified: Mutex<HashSet<(String, String)>>,
+}
+
+impl CertTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+pub async fn check(state: &AppState) {
+    let settings = load_settings();
+    for cert in inventory(&state.db, &settings).await {
+        if !matches!(cert.status, CertStatus::Expiring | CertStatus::Expired) {
+            continue;
+        }
+        let (Some(expires_at), Some(days_left)) = (cert.expires_at, cert.days_left) else { continue };
+        // A renewed certificate has a new expiry date and is reported again when it gets close
+        let key = (cert.location.clone(), expires_at.clone());
+        if !state.certs.notified.lock().unwrap().insert(key) {
+            continue;
+        }
+        state.events.emit(Event::CertificateExpiring {
+            name: cert.name,
+            location: cert.location,
+            expires_at,
+            days_left,
+        });
+    }
+}
+
+pub fn spawn(state: Arc<AppState>) {
+    tokio::spawn(async move {
+        loop {
+            state.tasks.beat("certificates", CHECK_INTERVAL);
+            check(&state).await;
+            tokio::time::sleep(CHECK_INTERVAL).await;
+        }
+    });
+}
@@ -0,0 +1,500 @@
+// Optional NAT/connection logging for users who have to keep such records. conntrack is polled
+// and each connection is written once it closes, with the address it was translated to.
+// Off by default.
+
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::system::privileges::sudo;
+use crate::AppState;
+
+const SETTINGS_FILE: &str = "/opt/routerui/connection-log.json";
+const POLL_INTERVAL: Duration = Duration::from_secs(30);
+const PRUNE_INTERVAL: ChronoDuration = ChronoDuration::hours(1);
+// conntrack's own default limit; anything beyond is dropped rather than growing without bound
+const MAX_OPEN: usize = 65536;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Storage {
+    Database,
+    // One JSON-lines file per day
+    Files,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConnectionLogSettings {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_storage")]
+    pub storage: Storage,
+    #[serde(default = "default_retention_days")]
+    pub retention_days: u32,
+    // Skip connections that were not translated, such as LAN clients talking to the router
+    #[serde(default = "default_nat_only")]
+    pub nat_only: bool,
+}
+
+fn default_storage() -> Storage {
+    Storage::Database
+}
+
+fn default_retention_days() -> u32 {
+    30
+}
+
+fn default_nat_only() -> bool {
+    true
+}
+
+impl Default for ConnectionLogSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            storage: default_storage(),
+            retention_days: default_retention_days(),
+            nat_only: default_nat_only(),
+        }
+    }
+}
+
+impl ConnectionLogSettings {
+    /// Settings as they apply under the privacy profile
+    pub fn limited_by(mut self, policy: &crate::privacy::Policy) -> Self {
+        self.enabled &= policy.connection_log;
+        self.retention_days = policy.cap_days(self.retention_days);
+        self
+    }
+}
+
+pub fn load_settings() -> ConnectionLogSettings {
+    std::fs::read_to_string(SETTINGS_FILE)
+        .ok()
+        .and_then(|c| serde_json::from_str(&c).ok())
+        .unwrap_or_default()
+}
+
+pub fn save_settings(settings: &ConnectionLogSettings) -> Result<(), String> {
+    let json = serde_json::to_string_pretty(settings).map_err(|e| e.to_string())?;
+    std::fs::write(SETTINGS_FILE, json).map_err(|e| e.to_string())
+}
+
+/// One connection as seen by conntrack
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, sqlx::FromRow)]
+pub struct ConnectionRecord {
+    pub protocol: String,
+    pub src_ip: String,
+    pub src_port: Option<i64>,
+    pub dst_ip: String,
+    pub dst_port: Option<i64>,
+    // Source NAT: the public address and port the connection left with
+    pub nat_src_ip: Option<String>,
+    pub nat_src_port: Option<i64>,
+    // Destination NAT (port forwards): the LAN host that received it
+    pub nat_dst_ip: Option<String>,
+    pub nat_dst_port: Option<i64>,
+    pub first_seen: String,
+    pub last_seen: String,
+}
+
+impl ConnectionRecord {
+    fn is_nat(&self) -> bool {
+        self.nat_src_ip.is_some() || self.nat_dst_ip.is_some()
+    }
+}
+
+/// Parse `conntrack -L -o extended,id` output into (conntrack id, connection)
+fn parse_conntrack(output: &str, now: &str) -> Vec<(u64, ConnectionRecord)> {
+    let mut connections = Vec::new();
+
+    for line in output.lines() {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        // ipv4 2 tcp 6 431999 ESTABLISHED src=... dst=... sport=... dport=... src=... ...
+        let Some(protocol) = tokens.get(2) else { continue };
+        let mut original: HashMap<&str, &str> = HashMap::new();
+        let mut reply: HashMap<&str, &str> = HashMap::new();
+        let mut id = None;
+        for (key, value) in tokens.iter().filter_map(|t| t.split_once('=')) {
+            if key == "id" {
+                id = value.parse::<u64>().ok();
+            } else if !original.contains_key(key) {
+                original.insert(key, value);
+            } else {
+                reply.entry(key).or_insert(value);
+            }
+        }
+
+        let (Some(id), Some(src), Some(dst)) = (id, original.get("src"), original.get("dst")) else {
+            continue;
+        };
+        if [src, dst].iter().any(|ip| ip.parse::<std::net::IpAddr>().is_ok_and(|ip| ip.is_loopback())) {
+            continue;
+        }
+
+        let port = |map: &HashMap<&str, &str>, key: &str| map.get(key).and_then(|p| p.parse::<i64>().ok());
+        let (src_port, dst_port) = (port(&original, "sport"), port(&original, "dport"));
+        let (reply_src, reply_dst) = (reply.get("src").copied(), reply.get("dst").copied());
+        let (reply_sport, reply_dport) = (port(&reply, "sport"), port(&reply, "dport"));
+
+        // Replies go to the translated source and come from the translated destination
+        let snat = reply_dst.is_some_and(|ip| ip != *src) || reply_dport.is_some_and(|p| Some(p) != src_port);
+        let dnat = reply_src.is_some_and(|ip| ip != *dst) || reply_sport.is_some_and(|p| Some(p) != dst_port);
+
+        connections.push((
+            id,
+            ConnectionRecord {
+                protocol: protocol.to_string(),
+                src_ip: src.to_string(),
+                src_port,
+                dst_ip: dst.to_string(),
+                dst_port,
+                nat_src_ip: snat.then(|| reply_dst.unwrap_or_default().to_string()),
+                nat_src_port: if snat { reply_dport } else { None },
+                nat_dst_ip: dnat.then(|| reply_src.unwrap_or_default().to_string()),
+                nat_dst_port: if dnat { reply_sport } else { None },
+                first_seen: now.to_string(),
+                last_seen: now.to_string(),
+            },
+        ));
+    }
+
+    connections
+}
+
+fn files_dir() -> PathBuf {
+    crate::db::data_dir().join("connection-log")
+}
+
+fn day_file(day: &str) -> PathBuf {
+    files_dir().join(format!("connections-{}.jsonl", day))
+}
+
+async fn write_records(pool: &SqlitePool, storage: Storage, records: &[ConnectionRecord]) -> Result<(), String> {
+    match storage {
+        Storage::Database => {
+            let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+            for r in records {
+                sqlx::query(
+                    "INSERT INTO connection_log (protocol, src_ip, src_port, dst_ip, dst_port, nat_src_ip, nat_src_port,
+                                                 nat_dst_ip, nat_dst_port, first_seen, last_seen)
+                     VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+                )
+                .bind(&r.protocol)
+                .bind(&r.src_ip)
+                .bind(r.src_port)
+                .bind(&r.dst_ip)
+                .bind(r.dst_port)
+                .bind(&r.nat_src_ip)
+                .bind(r.nat_src_port)
+                .bind(&r.nat_dst_ip)
+                .bind(r.nat_dst_port)
+                .bind(&r.first_seen)
+                .bind(&r.last_seen)
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| e.to_string())?;
+            }
+            tx.commit().await.map_err(|e| e.to_string())
+        }
+        Storage::Files => {
+            std::fs::create_dir_all(files_dir()).map_err(|e| e.to_string())?;
+            let mut by_day: HashMap<&str, Vec<&ConnectionRecord>> = HashMap::new();
+            for r in records {
+                by_day.entry(r.last_seen.get(..10).unwrap_or("unknown")).or_default().push(r);
+            }
+            for (day, records) in by_day {
+                let mut file = std::fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(day_file(day))
+                    .map_err(|e| e.to_string())?;
+                for r in records {
+                    let line = serde_json::to_string(r).map_err(|e| e.to_string())?;
+                    writeln!(file, "{}", line).map_err(|e| e.to_string())?;
+                }
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Drop everything older than the retention period, from both storages
+pub async fn prune(pool: &SqlitePool, retention_days: u32) -> Result<u64, String> {
+    let cutoff = Utc::now() - ChronoDuration::days(retention_days as i64);
+    let mut removed = sqlx::query("DELETE FROM connection_log WHERE last_seen < ?")
+        .bind(cutoff.to_rfc3339())
+        .execute(pool)
+        .await
+        .map_err(|e| e.to_string())?
+        .rows_affected();
+
+    let oldest_kept = day_file(&cutoff.format("%Y-%m-%d").to_string());
+    for entry in std::fs::read_dir(files_dir()).into_iter().flatten().flatten() {
+        let path = entry.path();
+        let is_log = path.extension().is_some_and(|e| e == "jsonl");
+        if is_log && path < oldest_kept && std::fs::remove_file(&path).is_ok() {
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
+/// What is stored right now, across both storages
+#[derive(Debug, Serialize)]
+pub struct StoredSummary {
+    pub database_entries: i64,
+    pub files: usize,
+    pub file_bytes: u64,
+    pub oldest: Option<String>,
+}
+
+pub async fn summary(pool: &SqlitePool) -> Result<StoredSummary, String> {
+    let (database_entries, oldest): (i64, Option<String>) =
+        sqlx::query_as("SELECT COUNT(*), MIN(first_seen) FROM connection_log")
+            .fetch_one(pool)
+            .await
+            .map_err(|e| e.to_string())?;
+
+    let mut files: Vec<(String, u64)> = std::fs::read_dir(files_dir())
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter(|e| e.path().extension().is_some_and(|e| e == "jsonl"))
+        .map(|e| (e.file_name().to_string_lossy().to_string(), e.metadata().map(|m| m.len()).unwrap_or(0)))
+        .collect();
+    files.sort();
+
+    // connections-YYYY-MM-DD.jsonl
+    let oldest_file = files.first().and_then(|(name, _)| name.get(12..22)).map(|d| d.to_string());
+    let oldest = match (oldest, oldest_file) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    };
+
+    Ok(StoredSummary {
+        database_entries,
+        files: files.len(),
+        file_bytes: files.iter().map(|(_, size)| size).sum(),
+        oldest,
+    })
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ExportFilter {
+    // RFC 3339 bounds on when the connection was seen
+    pub from: Option<String>,
+    pub to: Option<String>,
+    // Matches any of the connection's addresses
+    pub ip: Option<String>,
+    pub limit: Option<u32>,
+}
+
+fn matches(record: &ConnectionRecord, filter: &ExportFilter) -> bool {
+    let in_range = filter.from.as_ref().is_none_or(|from| record.last_seen >= *from)
+        && filter.to.as_ref().is_none_or(|to| record.first_seen <= *to);
+    let has_ip = filter.ip.as_ref().is_none_or(|ip| {
+        [Some(&record.src_ip), Some(&record.dst_ip), record.nat_src_ip.as_ref(), record.nat_dst_ip.as_ref()]
+            .into_iter()
+            .flatten()
+            .any(|a| a == ip)
+    });
+    in_range && has_ip
+}
+
+/// Stored connections from both storages, oldest first
+pub async fn query(pool: &SqlitePool, filter: &ExportFilter) -> Result<Vec<ConnectionRecord>, String> {
+    let limit = filter.limit.unwrap_or(100_000).min(1_000_000) as usize;
+
+    let mut records: Vec<ConnectionRecord> = sqlx::query_as(
+        "SELECT protocol, src_ip, src_port, dst_ip, dst_port, nat_src_ip, nat_src_port, nat_dst_ip, nat_dst_port,
+                first_seen, last_seen
+         FROM connection_log
+         WHERE (?1 IS NULL OR last_seen >= ?1) AND (?2 IS NULL OR first_seen <= ?2)
+           AND (?3 IS NULL OR ?3 IN (src_ip, dst_ip, nat_src_ip, nat_dst_ip))
+         ORDER BY first_seen LIMIT ?4",
+    )
+    .bind(&filter.from)
+    .bind(&filter.to)
+    .bind(&filter.ip)
+    .bind(limit as i64)
+    .fetch_all(pool)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    let mut files: Vec<PathBuf> = std::fs::read_dir(files_dir())
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|e| e.path())
+        .filter(|p| p.extension().is_some_and(|e| e == "jsonl"))
+        .collect();
+    files.sort();
+    for path in files {
+        let Ok(file) = std::fs::File::open(&path) else { continue };
+        for line in std::io::BufReader::new(file).lines().map_while(Result::ok) {
+            if records.len() >= limit {
+                break;
+            }
+            if let Ok(record) = serde_json::from_str::<ConnectionRecord>(&line) {
+                if matches(&record, filter) {
+                    records.push(record);
+                }
+            }
+        }
+    }
+
+    records.sort_by(|a, b| a.first_seen.cmp(&b.first_seen));
+    records.truncate(limit);
+    Ok(records)
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+pub fn to_csv(records: &[ConnectionRecord]) -> String {
+    let mut out = String::from(
+        "first_seen,last_seen,protocol,src_ip,src_port,dst_ip,dst_port,nat_src_ip,nat_src_port,nat_dst_ip,nat_dst_port\n",
+    );
+    let port = |p: Option<i64>| p.map(|p| p.to_string()).unwrap_or_default();
+    for r in records {
+        let fields = [
+            r.first_seen.clone(),
+            r.last_seen.clone(),
+            r.protocol.clone(),
+            r.src_ip.clone(),
+            port(r.src_port),
+            r.dst_ip.clone(),
+            port(r.dst_port),
+            r.nat_src_ip.clone().unwrap_or_default(),
+            port(r.nat_src_port),
+            r.nat_dst_ip.clone().unwrap_or_default(),
+            port(r.nat_dst_port),
+        ];
+        out.push_str(&fields.iter().map(|f| csv_field(f)).collect::<Vec<_>>().join(","));
+        out.push('\n');
+    }
+    out
+}
+
+/// Connections currently open, keyed by conntrack id
+#[derive(Default)]
+struct Tracker {
+    open: HashMap<u64, ConnectionRecord>,
+}
+
+impl Tracker {
+    /// Fold in a conntrack listing and return the connections that have since closed
+    fn update(&mut self, current: Vec<(u64, ConnectionRecord)>, now: &str) -> Vec<ConnectionRecord> {
+        let mut seen = HashMap::with_capacity(current.len());
+        for (id, record) in current {
+            seen.insert(id, record);
+        }
+
+        let mut closed = Vec::new();
+        self.open.retain(|id, record| match seen.remove(id) {
+            // conntrack reuses ids; a different tuple is a new connection
+            Some(latest) if latest.src_ip == record.src_ip && latest.src_port == record.src_port => {
+                record.last_seen = now.to_string();
+                true
+            }
+            Some(latest) => {
+                closed.push(std::mem::replace(record, latest));
+                true
+            }
+            None => {
+                closed.push(record.clone());
+                false
+            }
+        });
+
+        for (id, record) in seen {
+            if self.open.len() >= MAX_OPEN {
+                break;
+            }
+            self.open.insert(id, record);
+        }
+        closed
+    }
+
+    fn drain(&mut self) -> Vec<ConnectionRecord> {
+        self.open.drain().map(|(_, r)| r).collect()
+    }
+}
+
+fn list_connections() -> Result<String, String> {
+    let output = sudo()
+        .args(["conntrack", "-L", "-o", "extended,id"])
+        .output()
+        .map_err(|e| e.to_string())?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+async fn poll(state: &AppState, tracker: &mut Tracker, last_prune: &mut Option<DateTime<Utc>>) {
+    let policy = crate::privacy::policy();
+    let settings = load_settings().limited_by(&policy);
+
+    if last_prune.is_none_or(|at| Utc::now() - at >= PRUNE_INTERVAL) {
+        match prune(&state.db, settings.retention_days).await {
+            Ok(0) => {}
+            Ok(n) => tracing::info!("Connection log: pruned {} entries past retention", n),
+            Err(e) => tracing::warn!("Connection log: prune failed: {}", e),
+        }
+        *last_prune = Some(Utc::now());
+    }
+
+    let now = Utc::now().to_rfc3339();
+    let closed = if settings.enabled {
+        let output = match tokio::task::spawn_blocking(list_connections).await {
+            Ok(Ok(output)) => output,
+            Ok(Err(e)) => {
+                tracing::warn!("Connection log: conntrack failed: {}", e);
+                return;
+            }
+            Err(_) => return,
+        };
+        let current = parse_conntrack(&output, &now)
+            .into_iter()
+            .filter(|(_, r)| !settings.nat_only || r.is_nat())
+            .collect();
+        tracker.update(current, &now)
+    } else if policy.connection_log {
+        // Switched off: write out what was open and stop tracking
+        tracker.drain()
+    } else {
+        // Forbidden by the privacy profile: forget what was open as well
+        tracker.drain();
+        Vec::new()
+    };
+
+    if !closed.is_empty() {
+        if let Err(e) = write_records(&state.db, settings.storage, &closed).await {
+            tracing::warn!("Connection log: could not write {} entries: {}", closed.len(), e);
+        }
+    }
+}
+
+pub fn spawn(state: Arc<AppState>) {
+    tokio::spawn(async move {
+        let mut tracker = Tracker::default();
+        let mut last_prune = None;
+        loop {
+            state.tasks.beat("connection_log", POLL_INTERVAL);
+            poll(&state, &mut tracker, &mut last_prune).await;
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    });
+}
@@ -0,0 +1,210 @@
+use sqlx::{Connection, SqliteConnection, SqlitePool};
+use std::path::{Path, PathBuf};
+
+// Runtime-only state that shouldn't be carried across a restore, credentials (an old backup
+// would bring back refresh tokens revoked since by a logout or password change, and API keys
+// deleted since), and the migration history, which describes the live schema rather than the
+// snapshot's
+const SKIP_TABLES: &[&str] = &["sessions", "refresh_tokens", "api_tokens", "maintenance_log", "sqlite_sequence", "_sqlx_migrations"];
+
+fn temp_path() -> PathBuf {
+    std::env::temp_dir().join(format!("routerui-db-{}.sqlite", uuid::Uuid::new_v4()))
+}
+
+// Copies of the database hold password and token hashes, so only we may read them
+fn create_private(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    use std::io::Write;
+    use std::os::unix::fs::OpenOptionsExt;
+
+    let mut file = std::fs::OpenOptions::new().write(true).create_new(true).mode(0o600).open(path)?;
+    file.write_all(contents)
+}
+
+/// Take a consistent copy of the live database with VACUUM INTO
+pub async fn snapshot(pool: &SqlitePool) -> Result<Vec<u8>, String> {
+    let path = temp_path();
+
+    let result = async {
+        // VACUUM INTO fills an empty file, which keeps the permissions it was created with
+        create_private(&path, &[]).map_err(|e| e.to_string())?;
+        sqlx::query("VACUUM INTO ?")
+            .bind(path.to_string_lossy().to_string())
+            .execute(pool)
+            .await
+            .map_err(|e| e.to_string())?;
+        std::fs::read(&path).map_err(|e| e.to_string())
+    }
+    .await;
+
+    let _ = std::fs::remove_file(&path);
+    result
+}
+
+/// Schema version stamped into a snapshot (PRAGMA user_version)
+pub async fn snapshot_schema_version(bytes: &[u8]) -> Result<i64, String> {
+    let path = temp_path();
+
+    let result = async {
+        create_private(&path, bytes).map_err(|e| e.to_string())?;
+        let mut conn = SqliteConnection::connect(&format!("sqlite:{}?mode=ro", path.display()))
+            .await
+            .map_err(|e| e.to_string())?;
+        let version: i64 = sqlx::query_scalar("PRAGMA user_version")
+            .fetch_one(&mut conn)
+            .await
+            .map_err(|e| e.to_string())?;
+        let _ = conn.close().await;
+        Ok(version)
+    }
+    .await;
+
+    let _ = std::fs::remove_file(&path);
+    result
+}
+
+async fn table_columns(conn: &mut SqliteConnection, schema: &str, table: &str) -> Result<Vec<String>, sqlx::Error> {
+    sqlx::query_scalar(&format!("SELECT name FROM pragma_table_info('{}', '{}')", table, schema))
+        .fetch_all(conn)
+        .await
+}
+
+/// Replace the contents of every table present in both the snapshot and the live database.
+/// Snapshots from an older schema are fine (only shared columns are copied); newer ones are refused.
+pub async fn restore(pool: &SqlitePool, bytes: &[u8]) -> Result<Vec<String>, String> {
+    let version = snapshot_schema_version(bytes).await?;
+    if version > super::schema_version() {
+        return Err(format!(
+            "Backup database schema v{} is newer than this RouterUI (v{})",
+            version,
+            super::schema_version()
+        ));
+    }
+
+    let path = temp_path();
+
+    let result = async {
+        create_private(&path, bytes).map_err(|e| e.to_string())?;
+        let mut conn = pool.acquire().await.map_err(|e| e.to_string())?;
+
+        sqlx::query("ATTACH DATABASE ? AS restore_src")
+            .bind(path.to_string_lossy().to_string())
+            .execute(&mut *conn)
+            .await
+            .map_err(|e| e.to_string())?;
+
+        let copied = copy_tables(&mut conn).await;
+
+        let _ = sqlx::query("DETACH DATABASE restore_src").execute(&mut *conn).await;
+        copied.map_err(|e| e.to_string())
+    }
+    .await;
+
+    let _ = std::fs::remove_file(&path);
+    result
+}
+
+async fn copy_tables(conn: &mut SqliteConnection) -> Result<Vec<String>, sqlx::Error> {
+    let tables: Vec<String> = sqlx::query_scalar(
+        "SELECT name FROM restore_src.sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%'
+         AND name IN (SELECT name FROM main.sqlite_master WHERE type = 'table')"
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let mut tx = conn.begin().await?;
+    // Tables are copied in arbitrary order, so check references at commit
+    sqlx::query("PRAGMA defer_foreign_keys = ON").execute(&mut *tx).await?;
+
+    let mut restored = Vec::new();
+    for table in tables {
+        if SKIP_TABLES.contains(&table.as_str()) {
+            continue;
+        }
+
+        let live = table_columns(&mut tx, "main", &table).await?;
+        let backup = table_columns(&mut tx, "restore_src", &table).await?;
+        let shared: Vec<String> = live
+            .into_iter()
+            .filter(|c| backup.contains(c))
+            .map(|c| format!("\"{}\"", c))
+            .collect();
+        if shared.is_empty() {
+            continue;
+        }
+        let cols = shared.join(", ");
+
+        sqlx::query(&format!("DELETE FROM main.\"{}\"", table))
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query(&format!(
+            "INSERT INTO main.\"{t}\" ({c}) SELECT {c} FROM restore_src.\"{t}\"",
+            t = table,
+            c = cols
+        ))
+        .execute(&mut *tx)
+        .await?;
+
+        restored.push(table);
+    }
+
+    tx.commit().await?;
+    Ok(restored)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn live_database() -> (SqlitePool, PathBuf) {
+        let path = temp_path();
+        let pool = SqlitePool::connect(&format!("sqlite:{}?mode=rwc", path.display())).await.unwrap();
+        crate::db::migrate(&pool).await.unwrap();
+        sqlx::query("INSERT INTO users (id, username, password_hash, role) VALUES (1, 'admin', 'x', 'admin')")
+            .execute(&pool)
+            .await
+            .unwrap();
+        (pool, path)
+    }
+
+    #[tokio::test]
+    async fn restore_keeps_revoked_refresh_tokens_revoked() {
+        let (pool, path) = live_database().await;
+        sqlx::query("INSERT INTO refresh_tokens (family_id, user_id, token_hash, expires_at) VALUES ('f', 1, 'hash', '2999-01-01')")
+            .execute(&pool)
+            .await
+            .unwrap();
+        let backup = snapshot(&pool).await.unwrap();
+
+        sqlx::query("UPDATE refresh_tokens SET revoked_at = datetime('now')").execute(&pool).await.unwrap();
+        let restored = restore(&pool, &backup).await.unwrap();
+        assert!(!restored.contains(&"refresh_tokens".to_string()));
+
+        let usable: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM refresh_tokens WHERE token_hash = 'hash' AND revoked_at IS NULL")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(usable, 0);
+
+        pool.close().await;
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[tokio::test]
+    async fn restore_keeps_deleted_api_keys_deleted() {
+        let (pool, path) = live_database().await;
+        sqlx::query("INSERT INTO api_tokens (user_id, name, token_hash, prefix, scope) VALUES (1, 'ci', 'hash', 'rui_', 'read')")
+            .execute(&pool)
+            .await
+            .unwrap();
+        let backup = snapshot(&pool).await.unwrap();
+
+        sqlx::query("DELETE FROM api_tokens").execute(&pool).await.unwrap();
+        restore(&pool, &backup).await.unwrap();
+
+        let keys: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM api_tokens").fetch_one(&pool).await.unwrap();
+        assert_eq!(keys, 0);
+
+        pool.close().await;
+        let _ = std::fs::remove_file(path);
+    }
+}
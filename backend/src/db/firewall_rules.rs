@@ -0,0 +1,155 @@
+// Metadata for port forwards, blocked IPs and IPv6 pinholes, which iptables lists without a description or any
+// on/off state. Rows are keyed by the rule itself. A disabled rule has no iptables rules at all
+// and lives only here; the listings put it back next to the live ones. Whether a rule is in
+// iptables wins over `enabled`, so a rolled-back change never shows the wrong state.
+// References tie a rule to the device it was made for, so deleting the device can offer to take
+// its rules along, and to the named group a template created it in, so the set can be removed
+// as one. A rule with a schedule is switched on and off by crate::api::firewall::FirewallScheduler.
+
+use chrono::Utc;
+use serde::Serialize;
+use sqlx::SqlitePool;
+use std::collections::HashMap;
+
+use crate::scheduler::Schedule;
+
+pub const PORT_FORWARD: &str = "port_forward";
+pub const BLOCKED_IP: &str = "blocked_ip";
+pub const BLOCKED_IP6: &str = "blocked_ip6";
+pub const PINHOLE6: &str = "pinhole6";
+// Object kind of a reference: a device by lowercase MAC address
+pub const DEVICE: &str = "device";
+// Object kind of a reference: a named group of rules made from a template
+pub const GROUP: &str = "group";
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct RuleMeta {
+    pub rule_key: String,
+    pub description: String,
+    pub enabled: bool,
+    pub created_by: Option<String>,
+    pub created_at: String,
+    // JSON Schedule, NULL when the rule is always in force
+    #[serde(skip)]
+    pub schedule: Option<String>,
+}
+
+impl RuleMeta {
+    pub fn schedule(&self) -> Option<Schedule> {
+        self.schedule.as_deref().and_then(|s| serde_json::from_str(s).ok())
+    }
+}
+
+/// Every rule of `kind`, by key
+pub async fn list(pool: &SqlitePool, kind: &str) -> Result<HashMap<String, RuleMeta>, sqlx::Error> {
+    let rows: Vec<RuleMeta> = sqlx::query_as(
+        "SELECT rule_key, description, enabled, created_by, created_at, schedule FROM firewall_rules WHERE kind = ?",
+    )
+    .bind(kind)
+    .fetch_all(pool)
+    .await?;
+    Ok(rows.into_iter().map(|r| (r.rule_key.clone(), r)).collect())
+}
+
+/// A newly added rule; replaces whatever was recorded for the same key before
+pub async fn record(pool: &SqlitePool, kind: &str, key: &str, description: &str, created_by: &str) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT OR REPLACE INTO firewall_rules (kind, rule_key, description, enabled, created_by, created_at)
+         VALUES (?, ?, ?, 1, ?, ?)",
+    )
+    .bind(kind)
+    .bind(key)
+    .bind(description)
+    .bind(created_by)
+    .bind(Utc::now().to_rfc3339())
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Rules added before metadata was kept get a row without a creator
+pub async fn set_enabled(pool: &SqlitePool, kind: &str, key: &str, enabled: bool) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO firewall_rules (kind, rule_key, enabled, created_at) VALUES (?, ?, ?, ?)
+         ON CONFLICT(kind, rule_key) DO UPDATE SET enabled = excluded.enabled",
+    )
+    .bind(kind)
+    .bind(key)
+    .bind(enabled)
+    .bind(Utc::now().to_rfc3339())
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// None takes the schedule off; rules added before metadata was kept get a row without a creator
+pub async fn set_schedule(pool: &SqlitePool, kind: &str, key: &str, schedule: Option<&Schedule>) -> Result<(), sqlx::Error> {
+    let schedule = schedule.map(|s| serde_json::to_string(s).unwrap_or_default());
+    sqlx::query(
+        "INSERT INTO firewall_rules (kind, rule_key, enabled, created_at, schedule) VALUES (?, ?, 1, ?, ?)
+         ON CONFLICT(kind, rule_key) DO UPDATE SET schedule = excluded.schedule",
+    )
+    .bind(kind)
+    .bind(key)
+    .bind(Utc::now().to_rfc3339())
+    .bind(schedule)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Forget a rule and its references
+pub async fn remove(pool: &SqlitePool, kind: &str, key: &str) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    sqlx::query("DELETE FROM firewall_rules WHERE kind = ? AND rule_key = ?")
+        .bind(kind)
+        .bind(key)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query("DELETE FROM firewall_refs WHERE rule_kind = ? AND rule_key = ?")
+        .bind(kind)
+        .bind(key)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await
+}
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct RuleRef {
+    pub rule_kind: String,
+    pub rule_key: String,
+    pub object_kind: String,
+    pub object_id: String,
+}
+
+/// Note that the rule was made for the object; a rule can belong to several
+pub async fn add_ref(pool: &SqlitePool, kind: &str, key: &str, object_kind: &str, object_id: &str) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT OR IGNORE INTO firewall_refs (rule_kind, rule_key, object_kind, object_id, created_at) VALUES (?, ?, ?, ?, ?)",
+    )
+    .bind(kind)
+    .bind(key)
+    .bind(object_kind)
+    .bind(object_id)
+    .bind(Utc::now().to_rfc3339())
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Rules made for the object
+pub async fn refs_to(pool: &SqlitePool, object_kind: &str, object_id: &str) -> Result<Vec<RuleRef>, sqlx::Error> {
+    sqlx::query_as(
+        "SELECT rule_kind, rule_key, object_kind, object_id FROM firewall_refs WHERE object_kind = ? AND object_id = ?",
+    )
+    .bind(object_kind)
+    .bind(object_id)
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn all_refs(pool: &SqlitePool) -> Result<Vec<RuleRef>, sqlx::Error> {
+    sqlx::query_as("SELECT rule_kind, rule_key, object_kind, object_id FROM firewall_refs ORDER BY rule_kind, rule_key")
+        .fetch_all(pool)
+        .await
+}
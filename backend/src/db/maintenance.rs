@@ -0,0 +1,327 @@
+use chrono::{Duration as ChronoDuration, Utc};
+use serde::Serialize;
+use sqlx::SqlitePool;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::AppState;
+
+const MAINTENANCE_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+// VACUUM rewrites the whole file, so only do it once enough pages are free
+const VACUUM_FREE_RATIO: f64 = 0.2;
+// integrity_check stops after this many problems; past that the file needs restoring anyway
+const INTEGRITY_MAX_ERRORS: i64 = 50;
+
+/// Rows in `table` whose `column` is older than `days` are deleted on each run.
+/// Tables that don't exist (feature not in use yet) are skipped.
+pub struct RetentionPolicy {
+    pub table: &'static str,
+    pub column: &'static str,
+    pub days: i64,
+    // Shortened further by the privacy profile
+    pub privacy_capped: bool,
+}
+
+impl RetentionPolicy {
+    /// Retention in effect under the current privacy profile
+    pub fn effective_days(&self) -> i64 {
+        if !self.privacy_capped {
+            return self.days;
+        }
+        crate::privacy::policy().retention_days.map_or(self.days, |cap| self.days.min(cap as i64))
+    }
+}
+
+pub const RETENTION_POLICIES: &[RetentionPolicy] = &[
+    // Expired sessions are useless once past expiry
+    RetentionPolicy { table: "sessions", column: "expires_at", days: 0, privacy_capped: false },
+    RetentionPolicy { table: "maintenance_log", column: "ran_at", days: 90, privacy_capped: false },
+    RetentionPolicy { table: "login_history", column: "created_at", days: 180, privacy_capped: true },
+    // Listings no feed has reported for half a year
+    RetentionPolicy { table: "ip_reputation", column: "last_seen", days: 180, privacy_capped: false },
+    RetentionPolicy { table: "dhcp_pool_samples", column: "sampled_at", days: 30, privacy_capped: true },
+    // A year of daily energy totals
+    RetentionPolicy { table: "power_samples", column: "sampled_at", days: 365, privacy_capped: false },
+    // Has to outlive a billing cycle
+    RetentionPolicy { table: "modem_usage", column: "sampled_at", days: 400, privacy_capped: false },
+    RetentionPolicy { table: "monitor_checks", column: "checked_at", days: 30, privacy_capped: false },
+    RetentionPolicy { table: "wol_wakes", column: "woken_at", days: 180, privacy_capped: true },
+];
+
+#[derive(Debug, Serialize)]
+pub struct TableSize {
+    pub name: String,
+    pub rows: i64,
+    pub bytes: i64,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct MaintenanceRun {
+    pub ran_at: String,
+    pub rows_deleted: i64,
+    pub vacuumed: bool,
+    pub duration_ms: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PageStats {
+    pub page_size: i64,
+    pub page_count: i64,
+    pub freelist_count: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct IntegrityCheck {
+    pub ok: bool,
+    // Empty when ok
+    pub errors: Vec<String>,
+    pub duration_ms: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Compaction {
+    // Main file plus WAL, as found on disk
+    pub bytes_before: u64,
+    pub bytes_after: u64,
+    // Frames copied back into the main file by the checkpoint; None outside WAL mode
+    pub checkpointed_frames: Option<i64>,
+    pub duration_ms: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DatabaseInfo {
+    pub path: Option<String>,
+    pub file_bytes: i64,
+    pub free_bytes: i64,
+    // Sizes of the files themselves, which can differ from the page count (WAL not yet checkpointed)
+    pub disk_bytes: Option<u64>,
+    pub wal_bytes: Option<u64>,
+    pub journal_mode: String,
+    pub pages: PageStats,
+    // Only run when asked for, it reads every page
+    pub integrity: Option<IntegrityCheck>,
+    pub tables: Vec<TableSize>,
+    pub retention: Vec<RetentionInfo>,
+    pub last_maintenance: Option<MaintenanceRun>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RetentionInfo {
+    pub table: String,
+    pub days: i64,
+}
+
+async fn table_exists(pool: &SqlitePool, table: &str) -> Result<bool, sqlx::Error> {
+    let count: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = ?"
+    )
+    .bind(table)
+    .fetch_one(pool)
+    .await?;
+    Ok(count > 0)
+}
+
+async fn page_stats(pool: &SqlitePool) -> Result<(i64, i64, i64), sqlx::Error> {
+    let page_size: i64 = sqlx::query_scalar("PRAGMA page_size").fetch_one(pool).await?;
+    let page_count: i64 = sqlx::query_scalar("PRAGMA page_count").fetch_one(pool).await?;
+    let freelist: i64 = sqlx::query_scalar("PRAGMA freelist_count").fetch_one(pool).await?;
+    Ok((page_size, page_count, freelist))
+}
+
+fn file_size(suffix: &str) -> Option<u64> {
+    let path = super::database_file()?;
+    let mut name = path.into_os_string();
+    name.push(suffix);
+    std::fs::metadata(name).ok().map(|m| m.len())
+}
+
+/// Bytes used on disk by the main file and its WAL
+fn disk_usage() -> u64 {
+    file_size("").unwrap_or(0) + file_size("-wal").unwrap_or(0)
+}
+
+pub async fn integrity_check(pool: &SqlitePool) -> Result<IntegrityCheck, sqlx::Error> {
+    let started = std::time::Instant::now();
+    let rows: Vec<String> = sqlx::query_scalar(&format!("PRAGMA integrity_check({})", INTEGRITY_MAX_ERRORS))
+        .fetch_all(pool)
+        .await?;
+    let ok = rows.len() == 1 && rows[0] == "ok";
+    Ok(IntegrityCheck {
+        ok,
+        errors: if ok { Vec::new() } else { rows },
+        duration_ms: started.elapsed().as_millis() as i64,
+    })
+}
+
+/// Checkpoint the WAL into the main file and truncate it, then VACUUM unconditionally.
+/// Recorded in the maintenance log like a scheduled run that vacuumed.
+pub async fn compact(pool: &SqlitePool) -> Result<Compaction, sqlx::Error> {
+    let started = std::time::Instant::now();
+    let bytes_before = disk_usage();
+
+    let checkpointed_frames = wal_checkpoint(pool).await?;
+    sqlx::query("VACUUM").execute(pool).await?;
+    // VACUUM in WAL mode writes the new pages to the WAL, so fold those back in too
+    if checkpointed_frames.is_some() {
+        wal_checkpoint(pool).await?;
+    }
+
+    let compaction = Compaction {
+        bytes_before,
+        bytes_after: disk_usage(),
+        checkpointed_frames,
+        duration_ms: started.elapsed().as_millis() as i64,
+    };
+
+    sqlx::query(
+        "INSERT INTO maintenance_log (ran_at, rows_deleted, vacuumed, duration_ms) VALUES (?, 0, 1, ?)"
+    )
+    .bind(Utc::now().to_rfc3339())
+    .bind(compaction.duration_ms)
+    .execute(pool)
+    .await?;
+
+    tracing::info!(
+        "Database compacted: {} -> {} bytes, {}ms",
+        compaction.bytes_before, compaction.bytes_after, compaction.duration_ms
+    );
+    Ok(compaction)
+}
+
+// Returns the frames checkpointed, or None when the database isn't in WAL mode
+async fn wal_checkpoint(pool: &SqlitePool) -> Result<Option<i64>, sqlx::Error> {
+    let (busy, log, checkpointed): (i64, i64, i64) = sqlx::query_as("PRAGMA wal_checkpoint(TRUNCATE)")
+        .fetch_one(pool)
+        .await?;
+    if log < 0 {
+        return Ok(None);
+    }
+    if busy != 0 {
+        tracing::warn!("WAL checkpoint could not finish, a reader was holding it back");
+    }
+    Ok(Some(checkpointed))
+}
+
+/// Apply retention policies, returning the number of rows deleted
+pub async fn prune(pool: &SqlitePool) -> Result<i64, sqlx::Error> {
+    let mut rows_deleted = 0;
+
+    for policy in RETENTION_POLICIES {
+        if !table_exists(pool, policy.table).await? {
+            continue;
+        }
+        let cutoff = (Utc::now() - ChronoDuration::days(policy.effective_days())).to_rfc3339();
+        let result = sqlx::query(&format!(
+            "DELETE FROM {} WHERE {} < ?",
+            policy.table, policy.column
+        ))
+        .bind(&cutoff)
+        .execute(pool)
+        .await?;
+        rows_deleted += result.rows_affected() as i64;
+    }
+    Ok(rows_deleted)
+}
+
+/// Apply retention policies, refresh planner statistics and VACUUM if worthwhile
+pub async fn run(pool: &SqlitePool) -> Result<MaintenanceRun, sqlx::Error> {
+    let started = std::time::Instant::now();
+    let rows_deleted = prune(pool).await?;
+
+    sqlx::query("ANALYZE").execute(pool).await?;
+
+    let (_, page_count, freelist) = page_stats(pool).await?;
+    let vacuumed = page_count > 0 && (freelist as f64 / page_count as f64) >= VACUUM_FREE_RATIO;
+    if vacuumed {
+        sqlx::query("VACUUM").execute(pool).await?;
+    }
+
+    let run = MaintenanceRun {
+        ran_at: Utc::now().to_rfc3339(),
+        rows_deleted,
+        vacuumed,
+        duration_ms: started.elapsed().as_millis() as i64,
+    };
+
+    sqlx::query(
+        "INSERT INTO maintenance_log (ran_at, rows_deleted, vacuumed, duration_ms) VALUES (?, ?, ?, ?)"
+    )
+    .bind(&run.ran_at)
+    .bind(run.rows_deleted)
+    .bind(run.vacuumed)
+    .bind(run.duration_ms)
+    .execute(pool)
+    .await?;
+
+    tracing::info!(
+        "Database maintenance: {} rows pruned, vacuumed: {}, {}ms",
+        run.rows_deleted, run.vacuumed, run.duration_ms
+    );
+
+    Ok(run)
+}
+
+pub async fn info(pool: &SqlitePool, check_integrity: bool) -> Result<DatabaseInfo, sqlx::Error> {
+    let (page_size, page_count, freelist) = page_stats(pool).await?;
+    let journal_mode: String = sqlx::query_scalar("PRAGMA journal_mode").fetch_one(pool).await?;
+    let integrity = if check_integrity { Some(integrity_check(pool).await?) } else { None };
+
+    let names: Vec<String> = sqlx::query_scalar(
+        "SELECT name FROM sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%' ORDER BY name"
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut tables = Vec::new();
+    for name in names {
+        let rows: i64 = sqlx::query_scalar(&format!("SELECT COUNT(*) FROM \"{}\"", name))
+            .fetch_one(pool)
+            .await?;
+        // dbstat counts table pages plus the pages of its indexes
+        let bytes: i64 = sqlx::query_scalar(
+            "SELECT COALESCE(SUM(pgsize), 0) FROM dbstat WHERE name = ?1
+             OR name IN (SELECT name FROM sqlite_master WHERE type = 'index' AND tbl_name = ?1)"
+        )
+        .bind(&name)
+        .fetch_one(pool)
+        .await
+        .unwrap_or(0);
+        tables.push(TableSize { name, rows, bytes });
+    }
+    tables.sort_by_key(|t| std::cmp::Reverse(t.bytes));
+
+    let last_maintenance = sqlx::query_as::<_, MaintenanceRun>(
+        "SELECT ran_at, rows_deleted, vacuumed, duration_ms FROM maintenance_log ORDER BY id DESC LIMIT 1"
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(DatabaseInfo {
+        path: super::database_file().map(|p| p.display().to_string()),
+        file_bytes: page_size * page_count,
+        free_bytes: page_size * freelist,
+        disk_bytes: file_size(""),
+        wal_bytes: file_size("-wal"),
+        journal_mode,
+        pages: PageStats { page_size, page_count, freelist_count: freelist },
+        integrity,
+        tables,
+        retention: RETENTION_POLICIES
+            .iter()
+            .map(|p| RetentionInfo { table: p.table.to_string(), days: p.effective_days() })
+            .collect(),
+        last_maintenance,
+    })
+}
+
+pub fn spawn(state: Arc<AppState>) {
+    tokio::spawn(async move {
+        loop {
+            state.tasks.beat("db_maintenance", MAINTENANCE_INTERVAL);
+            if let Err(e) = run(&state.db).await {
+                tracing::error!("Database maintenance failed: {}", e);
+            }
+            tokio::time::sleep(MAINTENANCE_INTERVAL).await;
+        }
+    });
+}
@@ -1,76 +1,148 @@
+pub mod backup;
+pub mod firewall_rules;
+pub mod maintenance;
+pub mod protection;
+pub mod settings;
+
+use sqlx::migrate::Migrator;
 use sqlx::SqlitePool;
+use std::path::PathBuf;
+
+const DEFAULT_DATA_DIR: &str = "/opt/routerui/config";
+
+pub fn database_url() -> String {
+    crate::config::get().database_url.clone()
+}
+
+/// Path of the SQLite file behind the database URL, if it points at one
+pub fn database_file() -> Option<PathBuf> {
+    let url = database_url();
+    let path = url
+        .strip_prefix("sqlite://")
+        .or_else(|| url.strip_prefix("sqlite:"))?
+        .split('?')
+        .next()?;
+
+    if path.is_empty() || path == ":memory:" {
+        return None;
+    }
+    Some(PathBuf::from(path))
+}
+
+/// Directory holding the database; used for other persistent state and health probes
+pub fn data_dir() -> PathBuf {
+    if let Some(dir) = &crate::config::get().data_dir {
+        return dir.clone();
+    }
+    database_file()
+        .and_then(|f| f.parent().map(|p| p.to_path_buf()))
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or_else(|| PathBuf::from(DEFAULT_DATA_DIR))
+}
+
+/// Versioned schema changes, embedded from backend/migrations at build time. Applied migrations
+/// are recorded in _sqlx_migrations; upgrades only go forward.
+pub static MIGRATOR: Migrator = sqlx::migrate!("./migrations");
+
+/// Version of the newest migration this build knows about; also stamped into PRAGMA user_version
+pub fn schema_version() -> i64 {
+    MIGRATOR.iter().map(|m| m.version).max().unwrap_or(0)
+}
 
 pub async fn migrate(pool: &SqlitePool) -> Result<(), sqlx::Error> {
-    sqlx::query(
-        r#"
-        CREATE TABLE IF NOT EXISTS users (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            username TEXT UNIQUE NOT NULL,
-            password_hash TEXT NOT NULL,
-            role TEXT NOT NULL DEFAULT 'viewer',
-            enabled INTEGER NOT NULL DEFAULT 1,
-            created_at TEXT NOT NULL DEFAULT (datetime('now')),
-            last_login TEXT
-        )
-        "#,
-    )
-    .execute(pool)
-    .await?;
+    if !table_exists(pool, "_sqlx_migrations").await? {
+        upgrade_legacy(pool).await?;
+    }
 
-    sqlx::query(
-        r#"
-        CREATE TABLE IF NOT EXISTS sessions (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            user_id INTEGER NOT NULL,
-            token_hash TEXT UNIQUE NOT NULL,
-            created_at TEXT NOT NULL DEFAULT (datetime('now')),
-            expires_at TEXT NOT NULL,
-            ip_address TEXT,
-            FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
-        )
-        "#,
-    )
-    .execute(pool)
-    .await?;
+    MIGRATOR.run(pool).await?;
 
-    // Index for session lookups
-    sqlx::query(
-        r#"
-        CREATE INDEX IF NOT EXISTS idx_sessions_token ON sessions(token_hash)
-        "#,
-    )
-    .execute(pool)
-    .await?;
+    sqlx::query(&format!("PRAGMA user_version = {}", schema_version()))
+        .execute(pool)
+        .await?;
 
-    // Index for expired session cleanup
-    sqlx::query(
-        r#"
-        CREATE INDEX IF NOT EXISTS idx_sessions_expires ON sessions(expires_at)
-        "#,
-    )
-    .execute(pool)
-    .await?;
+    tracing::info!("Database migrations complete (schema v{})", schema_version());
+    Ok(())
+}
+
+/// Migrations applied to this database, oldest first
+pub async fn applied_migrations(pool: &SqlitePool) -> Result<Vec<(i64, String)>, sqlx::Error> {
+    sqlx::query_as("SELECT version, description FROM _sqlx_migrations WHERE success = 1 ORDER BY version")
+        .fetch_all(pool)
+        .await
+}
 
-    tracing::info!("Database migrations complete");
+// Databases from before versioned migrations had columns added in place as features arrived, so
+// an old install may lack some. Bring them up to the baseline's columns so 0018_baseline (all
+// IF NOT EXISTS) can be recorded as applied. Frozen: new schema changes go in a migration file.
+async fn upgrade_legacy(pool: &SqlitePool) -> Result<(), sqlx::Error> {
+    add_column(pool, "users", "oidc_subject", "TEXT").await?;
+    add_column(pool, "users", "ldap_dn", "TEXT").await?;
+    add_column(pool, "users", "password_changed_at", "TEXT").await?;
+
+    add_column(pool, "sessions", "user_agent", "TEXT").await?;
+    add_column(pool, "sessions", "revoked_at", "TEXT").await?;
+    add_column(pool, "sessions", "last_seen_at", "TEXT").await?;
+    add_column(pool, "sessions", "remember", "INTEGER NOT NULL DEFAULT 0").await?;
+    // Sessions from before the idle timeout count as active since they started
+    if table_exists(pool, "sessions").await? {
+        sqlx::query("UPDATE sessions SET last_seen_at = strftime('%Y-%m-%dT%H:%M:%S+00:00', created_at) WHERE last_seen_at IS NULL")
+            .execute(pool)
+            .await?;
+    }
+    Ok(())
+}
+
+async fn table_exists(pool: &SqlitePool, table: &str) -> Result<bool, sqlx::Error> {
+    let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = ?")
+        .bind(table)
+        .fetch_one(pool)
+        .await?;
+    Ok(count > 0)
+}
+
+// Adds a column an older table was created without; missing tables are left to the baseline
+async fn add_column(pool: &SqlitePool, table: &str, column: &str, definition: &str) -> Result<(), sqlx::Error> {
+    if !table_exists(pool, table).await? {
+        return Ok(());
+    }
+    let exists: i64 = sqlx::query_scalar(&format!("SELECT COUNT(*) FROM pragma_table_info('{}') WHERE name = ?", table))
+        .bind(column)
+        .fetch_one(pool)
+        .await?;
+    if exists == 0 {
+        sqlx::query(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition))
+            .execute(pool)
+            .await?;
+    }
     Ok(())
 }
 
 pub async fn get_user_by_username(pool: &SqlitePool, username: &str) -> Result<Option<crate::models::User>, sqlx::Error> {
-    sqlx::query_as::<_, crate::models::User>(
+    let user = sqlx::query_as::<_, crate::models::User>(
         "SELECT id, username, password_hash, role, enabled, created_at, last_login FROM users WHERE username = ?"
     )
     .bind(username)
     .fetch_optional(pool)
-    .await
+    .await?;
+    with_permissions(pool, user).await
 }
 
 pub async fn get_user_by_id(pool: &SqlitePool, id: i64) -> Result<Option<crate::models::User>, sqlx::Error> {
-    sqlx::query_as::<_, crate::models::User>(
+    let user = sqlx::query_as::<_, crate::models::User>(
         "SELECT id, username, password_hash, role, enabled, created_at, last_login FROM users WHERE id = ?"
     )
     .bind(id)
     .fetch_optional(pool)
-    .await
+    .await?;
+    with_permissions(pool, user).await
+}
+
+async fn with_permissions(pool: &SqlitePool, user: Option<crate::models::User>) -> Result<Option<crate::models::User>, sqlx::Error> {
+    let Some(mut user) = user else { return Ok(None) };
+    if user.role == "custom" {
+        user.permissions = crate::auth::permissions::load(pool, user.id).await?;
+    }
+    Ok(Some(user))
 }
 
 pub async fn count_users(pool: &SqlitePool) -> Result<i64, sqlx::Error> {
@@ -0,0 +1,148 @@
+// Protection state: which blocklists and country blocks are on, how many entries each held when
+// it was last downloaded and when that was, and the whitelist. The ipsets and iptables rules are
+// rebuilt from this; it used to live in JSON files that concurrent requests could overwrite.
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use std::collections::HashMap;
+
+pub const BLOCKLIST: &str = "blocklist";
+pub const COUNTRY: &str = "country";
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct ListState {
+    pub id: String,
+    pub enabled: bool,
+    pub entry_count: i64,
+    pub updated_at: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct WhitelistEntry {
+    pub ip: String,
+    pub description: String,
+    pub added_at: String,
+}
+
+/// Every list of `kind` there is state for, by id
+pub async fn lists(pool: &SqlitePool, kind: &str) -> Result<HashMap<String, ListState>, sqlx::Error> {
+    let rows: Vec<ListState> = sqlx::query_as(
+        "SELECT id, enabled, entry_count, updated_at FROM protection_lists WHERE kind = ?",
+    )
+    .bind(kind)
+    .fetch_all(pool)
+    .await?;
+    Ok(rows.into_iter().map(|r| (r.id.clone(), r)).collect())
+}
+
+/// Turn a list on or off; its count and update time are kept for when it comes back
+pub async fn set_enabled(pool: &SqlitePool, kind: &str, id: &str, enabled: bool) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO protection_lists (kind, id, enabled) VALUES (?, ?, ?)
+         ON CONFLICT(kind, id) DO UPDATE SET enabled = excluded.enabled",
+    )
+    .bind(kind)
+    .bind(id)
+    .bind(enabled)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// A list was just downloaded and loaded with `entry_count` entries
+pub async fn record_update(pool: &SqlitePool, kind: &str, id: &str, entry_count: usize) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO protection_lists (kind, id, enabled, entry_count, updated_at) VALUES (?, ?, 1, ?, ?)
+         ON CONFLICT(kind, id) DO UPDATE SET entry_count = excluded.entry_count, updated_at = excluded.updated_at",
+    )
+    .bind(kind)
+    .bind(id)
+    .bind(entry_count as i64)
+    .bind(Utc::now().to_rfc3339())
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+pub async fn whitelist(pool: &SqlitePool) -> Result<Vec<WhitelistEntry>, sqlx::Error> {
+    sqlx::query_as("SELECT ip, description, added_at FROM protection_whitelist ORDER BY added_at, ip")
+        .fetch_all(pool)
+        .await
+}
+
+pub async fn is_whitelisted(pool: &SqlitePool, ip: &str) -> Result<bool, sqlx::Error> {
+    let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM protection_whitelist WHERE ip = ?")
+        .bind(ip)
+        .fetch_one(pool)
+        .await?;
+    Ok(count > 0)
+}
+
+/// Add entries all together, or none of them if any address is already on the whitelist.
+/// Returns the address that was, if one was.
+pub async fn add_to_whitelist(pool: &SqlitePool, entries: &[WhitelistEntry]) -> Result<Option<String>, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    for entry in entries {
+        let inserted = sqlx::query(
+            "INSERT INTO protection_whitelist (ip, description, added_at) VALUES (?, ?, ?) ON CONFLICT(ip) DO NOTHING",
+        )
+        .bind(&entry.ip)
+        .bind(&entry.description)
+        .bind(&entry.added_at)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+        if inserted == 0 {
+            tx.rollback().await?;
+            return Ok(Some(entry.ip.clone()));
+        }
+    }
+    tx.commit().await?;
+    Ok(None)
+}
+
+/// The removed entry, if `ip` was on the whitelist
+pub async fn remove_from_whitelist(pool: &SqlitePool, ip: &str) -> Result<Option<WhitelistEntry>, sqlx::Error> {
+    sqlx::query_as("DELETE FROM protection_whitelist WHERE ip = ? RETURNING ip, description, added_at")
+        .bind(ip)
+        .fetch_optional(pool)
+        .await
+}
+
+/// State carried over from the JSON files of older versions
+#[derive(Debug, Default)]
+pub struct LegacyState {
+    pub blocklists: Vec<ListState>,
+    pub countries: Vec<ListState>,
+    pub whitelist: Vec<WhitelistEntry>,
+}
+
+/// Store `legacy` in one transaction. Rows already in the database win, so importing the same
+/// files twice changes nothing.
+pub async fn import(pool: &SqlitePool, legacy: &LegacyState) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    for (kind, lists) in [(BLOCKLIST, &legacy.blocklists), (COUNTRY, &legacy.countries)] {
+        for list in lists {
+            sqlx::query(
+                "INSERT OR IGNORE INTO protection_lists (kind, id, enabled, entry_count, updated_at) VALUES (?, ?, ?, ?, ?)",
+            )
+            .bind(kind)
+            .bind(&list.id)
+            .bind(list.enabled)
+            .bind(list.entry_count)
+            .bind(&list.updated_at)
+            .execute(&mut *tx)
+            .await?;
+        }
+    }
+    for entry in &legacy.whitelist {
+        sqlx::query("INSERT OR IGNORE INTO protection_whitelist (ip, description, added_at) VALUES (?, ?, ?)")
+            .bind(&entry.ip)
+            .bind(&entry.description)
+            .bind(&entry.added_at)
+            .execute(&mut *tx)
+            .await?;
+    }
+    tx.commit().await
+}
@@ -0,0 +1,26 @@
+// Runtime settings as key/value rows. Values are stored as JSON so each owner reads back the
+// type it wrote; a key that was never set (or no longer parses) means "use the default".
+
+use serde::{de::DeserializeOwned, Serialize};
+use sqlx::SqlitePool;
+
+pub async fn get<T: DeserializeOwned>(pool: &SqlitePool, key: &str) -> Result<Option<T>, sqlx::Error> {
+    let value: Option<String> = sqlx::query_scalar("SELECT value FROM settings WHERE key = ?")
+        .bind(key)
+        .fetch_optional(pool)
+        .await?;
+    Ok(value.and_then(|v| serde_json::from_str(&v).ok()))
+}
+
+pub async fn set<T: Serialize>(pool: &SqlitePool, key: &str, value: &T) -> Result<(), sqlx::Error> {
+    let json = serde_json::to_string(value).map_err(|e| sqlx::Error::Encode(Box::new(e)))?;
+    sqlx::query(
+        "INSERT INTO settings (key, value, updated_at) VALUES (?, ?, datetime('now'))
+         ON CONFLICT(key) DO UPDATE SET value = excluded.value, updated_at = excluded.updated_at"
+    )
+    .bind(key)
+    .bind(json)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
@@ -0,0 +1,133 @@
+// IP conflict detection for static assignments. Before an address is reserved for a device or
+// given a local DNS name, ask the LAN who has it: arping reports every MAC that answers for it,
+// and dnsmasq's lease table knows who was handed it dynamically. Two devices on one address both
+// work, intermittently, which is why these are so hard to track down afterwards.
+
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::net::Ipv4Addr;
+use std::process::Command;
+
+use crate::api::network::DNSMASQ_LEASES;
+use crate::system::privileges::sudo;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Conflict {
+    pub ip: String,
+    // Devices other than the expected one seen with the address; empty for the router's own
+    pub macs: Vec<String>,
+    pub message: String,
+}
+
+enum Route {
+    // One of the router's own addresses
+    Local,
+    // On a subnet the router is attached to, through this interface
+    Direct(String),
+}
+
+fn route(ip: Ipv4Addr) -> Result<Route, String> {
+    let output = Command::new("ip")
+        .args(["-j", "route", "get", &ip.to_string()])
+        .output()
+        .map_err(|e| format!("Could not look up the route to {}: {}", ip, e))?;
+    let routes: Vec<serde_json::Value> = serde_json::from_slice(&output.stdout).unwrap_or_default();
+    let route = routes.first().ok_or_else(|| format!("No route to {}", ip))?;
+    if route.get("type").and_then(|t| t.as_str()) == Some("local") {
+        return Ok(Route::Local);
+    }
+    if route.get("gateway").is_some() {
+        return Err(format!("{} is not on a LAN subnet, so it could not be checked for conflicts", ip));
+    }
+    let dev = route.get("dev").and_then(|d| d.as_str()).ok_or_else(|| format!("No route to {}", ip))?;
+    Ok(Route::Direct(dev.to_string()))
+}
+
+// "Unicast reply from 192.168.1.20 [AA:BB:CC:DD:EE:FF]  0.61ms"
+fn parse_arping(output: &str) -> BTreeSet<String> {
+    output
+        .lines()
+        .filter(|l| l.contains("reply from"))
+        .filter_map(|l| Some(l.split_once('[')?.1.split_once(']')?.0.to_lowercase()))
+        .collect()
+}
+
+// Every MAC that answers ARP for `ip` on `interface`
+fn arp_probe(interface: &str, ip: Ipv4Addr) -> Result<BTreeSet<String>, String> {
+    let output = sudo()
+        .args(["arping", "-c", "2", "-w", "2", "-I", interface, &ip.to_string()])
+        .output()
+        .map_err(|e| format!("Could not run arping: {}", e))?;
+    let macs = parse_arping(&String::from_utf8_lossy(&output.stdout));
+    // arping exits 1 when nobody answered, anything else is a failure to probe
+    if macs.is_empty() && !output.status.success() && output.status.code() != Some(1) {
+        return Err(format!("arping failed: {}", String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    Ok(macs)
+}
+
+// MACs holding an unexpired dynamic lease on `ip`
+fn lease_holders(ip: Ipv4Addr) -> BTreeSet<String> {
+    let now = chrono::Utc::now().timestamp();
+    std::fs::read_to_string(DNSMASQ_LEASES)
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|line| {
+            let parts: Vec<&str> = line.split_whitespace().collect();
+            let expires: i64 = parts.first()?.parse().ok()?;
+            (parts.get(2)?.parse::<Ipv4Addr>().ok()? == ip && (expires == 0 || expires > now))
+                .then(|| parts[1].to_lowercase())
+        })
+        .collect()
+}
+
+/// Whether a device answers ARP for `ip`, or it is the router's own address. Err when the LAN
+/// could not be probed.
+pub fn answers(ip: Ipv4Addr) -> Result<bool, String> {
+    match route(ip)? {
+        Route::Local => Ok(true),
+        Route::Direct(interface) => Ok(!arp_probe(&interface, ip)?.is_empty()),
+    }
+}
+
+/// Whether `ip` is taken by a device other than `expected_mac`. With no expected device one
+/// holder is fine, only several different ones are a conflict. Err when the LAN could not be
+/// probed, in which case the caller should go ahead with a warning rather than block.
+pub fn check(ip: Ipv4Addr, expected_mac: Option<&str>) -> Result<Option<Conflict>, String> {
+    let expected = expected_mac.map(str::to_lowercase);
+    let conflict = |macs: BTreeSet<String>| {
+        let macs: Vec<String> = macs.into_iter().filter(|m| Some(m) != expected.as_ref()).collect();
+        let taken = if expected.is_some() { !macs.is_empty() } else { macs.len() > 1 };
+        taken.then(|| Conflict {
+            ip: ip.to_string(),
+            message: if expected.is_some() {
+                format!("{} is in use by {}", ip, macs.join(", "))
+            } else {
+                format!("{} is used by several devices: {}", ip, macs.join(", "))
+            },
+            macs,
+        })
+    };
+
+    let interface = match route(ip)? {
+        // Pointing a name at the router is fine, reserving its address for a device is not
+        Route::Local if expected.is_some() => {
+            return Ok(Some(Conflict {
+                ip: ip.to_string(),
+                macs: Vec::new(),
+                message: format!("{} is the router's own address", ip),
+            }))
+        }
+        Route::Local => return Ok(None),
+        Route::Direct(interface) => interface,
+    };
+
+    let mut holders = lease_holders(ip);
+    match arp_probe(&interface, ip) {
+        Ok(answered) => holders.extend(answered),
+        // The lease table alone may still be enough to tell
+        Err(e) => return conflict(holders).map(Some).ok_or(e),
+    }
+    Ok(conflict(holders))
+}
+
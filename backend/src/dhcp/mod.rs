@@ -0,0 +1,106 @@
+pub mod conflict;
+
+use chrono::{Duration as ChronoDuration, Utc};
+use serde::Serialize;
+use sqlx::SqlitePool;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::api::network::{pool_status, PoolLevel, PoolStatus};
+use crate::events::Event;
+use crate::AppState;
+
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(5 * 60);
+// Once warned, stay quiet until usage has dropped this far, so a pool hovering
+// around the threshold doesn't alert on every sample
+const RECOVERED_PERCENT: f64 = 70.0;
+
+/// DHCP pool usage at one point in time
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct PoolSample {
+    pub sampled_at: String,
+    pub size: i64,
+    pub used: i64,
+}
+
+pub async fn record(pool: &SqlitePool, status: &PoolStatus) -> Result<(), sqlx::Error> {
+    sqlx::query("INSERT INTO dhcp_pool_samples (sampled_at, size, used) VALUES (?, ?, ?)")
+        .bind(Utc::now().to_rfc3339())
+        .bind(status.size as i64)
+        .bind(status.used as i64)
+        .execute(pool)
+        .await
+        .map(|_| ())
+}
+
+fn since(hours: u32) -> String {
+    (Utc::now() - ChronoDuration::hours(hours as i64)).to_rfc3339()
+}
+
+pub async fn history(pool: &SqlitePool, hours: u32) -> Result<Vec<PoolSample>, sqlx::Error> {
+    sqlx::query_as("SELECT sampled_at, size, used FROM dhcp_pool_samples WHERE sampled_at >= ? ORDER BY sampled_at")
+        .bind(since(hours))
+        .fetch_all(pool)
+        .await
+}
+
+/// Busiest sample in the last `hours`
+pub async fn peak(pool: &SqlitePool, hours: u32) -> Result<Option<PoolSample>, sqlx::Error> {
+    sqlx::query_as(
+        "SELECT sampled_at, size, used FROM dhcp_pool_samples WHERE sampled_at >= ?
+         ORDER BY CAST(used AS REAL) / size DESC, sampled_at DESC LIMIT 1",
+    )
+    .bind(since(hours))
+    .fetch_optional(pool)
+    .await
+}
+
+/// Tracks the last level alerted on
+#[derive(Default)]
+pub struct PoolAlert {
+    alerted: Option<PoolLevel>,
+}
+
+impl PoolAlert {
+    /// Returns true when `status` deserves an alert: the pool just became busy, or got worse
+    pub fn check(&mut self, status: &PoolStatus) -> bool {
+        if status.utilization < RECOVERED_PERCENT {
+            self.alerted = None;
+            return false;
+        }
+        if status.level == PoolLevel::Ok || self.alerted.is_some_and(|level| level >= status.level) {
+            return false;
+        }
+        self.alerted = Some(status.level);
+        true
+    }
+}
+
+async fn sample(state: &AppState, alert: &mut PoolAlert) {
+    let Some(status) = tokio::task::spawn_blocking(pool_status).await.ok().flatten() else {
+        return;
+    };
+    if let Err(e) = record(&state.db, &status).await {
+        tracing::warn!("Could not record DHCP pool sample: {}", e);
+    }
+    if alert.check(&status) {
+        state.events.emit(Event::DhcpPoolLow {
+            used: status.used,
+            size: status.size,
+            utilization: status.utilization,
+            level: status.level,
+            suggestions: status.suggestions,
+        });
+    }
+}
+
+pub fn spawn(state: Arc<AppState>) {
+    tokio::spawn(async move {
+        let mut alert = PoolAlert::default();
+        loop {
+            state.tasks.beat("dhcp_pool", SAMPLE_INTERVAL);
+            sample(&state, &mut alert).await;
+            tokio::time::sleep(SAMPLE_INTERVAL).await;
+        }
+    });
+}
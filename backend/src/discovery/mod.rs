@@ -0,0 +1,135 @@
+use mdns_sd::{ServiceDaemon, ServiceInfo};
+use serde::Serialize;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::net::UdpSocket;
+
+use crate::AppState;
+
+const MDNS_SERVICE_TYPE: &str = "_http._tcp.local.";
+const MDNS_HOSTNAME: &str = "routerui.local.";
+const MDNS_INSTANCE: &str = "RouterUI";
+
+// Clients broadcast DISCOVERY_PROBE to this UDP port; every RouterUI on the LAN answers with its banner
+pub const DISCOVERY_PORT: u16 = 3081;
+const DISCOVERY_PROBE: &str = "ROUTERUI_DISCOVER";
+// How long the list of LAN subnets allowed to probe is reused before re-reading interfaces
+const SUBNET_REFRESH: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Serialize)]
+pub struct Banner {
+    pub name: String,
+    pub version: String,
+    pub hostname: String,
+    pub mdns_name: String,
+    pub urls: Vec<String>,
+    pub setup_complete: bool,
+    pub setup_url: Option<String>,
+}
+
+/// ROUTERUI_DISCOVERY=0 turns off mDNS and the broadcast responder
+pub fn is_enabled() -> bool {
+    std::env::var("ROUTERUI_DISCOVERY").map(|v| v != "0" && v != "false").unwrap_or(true)
+}
+
+fn lan_ipv4_addresses() -> Vec<String> {
+    crate::system::get_interfaces()
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|i| i.ipv4)
+        .filter_map(|cidr| cidr.split('/').next().map(|ip| ip.to_string()))
+        .collect()
+}
+
+fn read_hostname() -> String {
+    std::fs::read_to_string("/etc/hostname")
+        .map(|h| h.trim().to_string())
+        .unwrap_or_else(|_| "router".to_string())
+}
+
+/// How to reach this RouterUI, plus where to go next if the setup wizard hasn't been run
+pub async fn banner(state: &AppState, port: u16) -> Banner {
+    // Listing interfaces shells out to `ip`, keep it off the async workers
+    let (hostname, addresses) = tokio::task::spawn_blocking(|| (read_hostname(), lan_ipv4_addresses()))
+        .await
+        .unwrap_or_else(|_| ("router".to_string(), Vec::new()));
+    let mdns_name = MDNS_HOSTNAME.trim_end_matches('.').to_string();
+
+    let scheme = if crate::config::get().tls.is_some() { "https" } else { "http" };
+    let mut urls = vec![format!("{}://{}:{}", scheme, mdns_name, port)];
+    urls.extend(addresses.into_iter().map(|ip| format!("{}://{}:{}", scheme, ip, port)));
+
+    let setup_complete = crate::api::setup::is_setup_complete(&state.db).await;
+    let setup_url = (!setup_complete).then(|| format!("{}/setup", urls[0]));
+
+    Banner {
+        name: MDNS_INSTANCE.to_string(),
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        hostname,
+        mdns_name,
+        urls,
+        setup_complete,
+        setup_url,
+    }
+}
+
+fn register_mdns(port: u16) -> Result<ServiceDaemon, mdns_sd::Error> {
+    let daemon = ServiceDaemon::new()?;
+    let properties = [("path", "/"), ("version", env!("CARGO_PKG_VERSION"))];
+    let service = ServiceInfo::new(
+        MDNS_SERVICE_TYPE,
+        MDNS_INSTANCE,
+        MDNS_HOSTNAME,
+        "",
+        port,
+        &properties[..],
+    )?
+    .enable_addr_auto();
+    daemon.register(service)?;
+    Ok(daemon)
+}
+
+async fn respond_to_probes(state: Arc<AppState>, port: u16) -> std::io::Result<()> {
+    let socket = UdpSocket::bind(("0.0.0.0", DISCOVERY_PORT)).await?;
+    let mut buf = [0u8; 512];
+    let mut subnets = Vec::new();
+    let mut refreshed: Option<Instant> = None;
+
+    loop {
+        let (len, peer) = socket.recv_from(&mut buf).await?;
+        if String::from_utf8_lossy(&buf[..len]).trim() != DISCOVERY_PROBE {
+            continue;
+        }
+
+        if refreshed.is_none_or(|at| at.elapsed() >= SUBNET_REFRESH) {
+            let wan = crate::wan::wan_interface(&state.db).await;
+            subnets = tokio::task::spawn_blocking(move || crate::system::lan_subnets(&wan)).await.unwrap_or_default();
+            refreshed = Some(Instant::now());
+        }
+        if !crate::system::is_lan_address(peer.ip(), &subnets) {
+            tracing::debug!("Ignoring discovery probe from non-LAN address {}", peer);
+            continue;
+        }
+
+        let reply = serde_json::to_vec(&banner(&state, port).await).unwrap_or_default();
+        let _ = socket.send_to(&reply, peer).await;
+    }
+}
+
+pub fn spawn(state: Arc<AppState>, port: u16) {
+    // The daemon runs on its own thread; keep the handle alive for the life of the process
+    let daemon = match register_mdns(port) {
+        Ok(d) => Some(d),
+        Err(e) => {
+            tracing::warn!("mDNS advertisement unavailable: {}", e);
+            None
+        }
+    };
+
+    tokio::spawn(async move {
+        let _daemon = daemon;
+        if let Err(e) = respond_to_probes(state, port).await {
+            tracing::warn!("Discovery responder stopped: {}", e);
+        }
+    });
+}
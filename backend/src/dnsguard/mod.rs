@@ -0,0 +1,315 @@
+// Keeps devices on the router's DNS, so AdGuard filtering (and safe search) can't be sidestepped
+// by an app with its own resolver. Plain DNS to outside servers is redirected to the router or
+// refused, DNS over TLS/QUIC (port 853) is refused, and DNS over HTTPS is cut off by refusing
+// HTTPS to well-known public resolvers and, through AdGuard, by not resolving their hostnames.
+// NTP can be pulled to the router as well. Everything lives in one chain per table, so turning
+// it off removes every trace. IPv4 only, like the rest of the firewall.
+
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use std::net::Ipv4Addr;
+use std::sync::Arc;
+
+use crate::system::commands::RecordedCommand;
+use crate::system::privileges::sudo;
+use crate::AppState;
+
+// Key in the settings table
+pub const SETTINGS_KEY: &str = "dns_guard";
+const CHAIN: &str = "ROUTERUI-DNSGUARD";
+/// ipset of public DNS-over-HTTPS resolvers
+pub const DOH_SET: &str = "routerui-doh";
+// Marks RouterUI's block among the AdGuard custom rules
+pub const ADGUARD_MARKER: &str = "routerui-dns-guard";
+const MAX_EXCEPTIONS: usize = 64;
+
+// Anycast addresses of the big public resolvers; their HTTPS endpoints serve nothing but DNS
+const DOH_RESOLVERS: &[&str] = &[
+    // Cloudflare
+    "1.1.1.1", "1.0.0.1", "1.1.1.2", "1.0.0.2", "1.1.1.3", "1.0.0.3", "104.16.248.249", "104.16.249.249",
+    // Google
+    "8.8.8.8", "8.8.4.4",
+    // Quad9
+    "9.9.9.9", "149.112.112.112", "9.9.9.10", "149.112.112.10", "9.9.9.11", "149.112.112.11",
+    // OpenDNS / Cisco Umbrella
+    "208.67.222.222", "208.67.220.220", "146.112.41.2",
+    // AdGuard DNS
+    "94.140.14.14", "94.140.15.15", "94.140.14.15", "94.140.15.16", "94.140.14.140", "94.140.14.141",
+    // NextDNS
+    "45.90.28.0/24", "45.90.30.0/24",
+    // CleanBrowsing
+    "185.228.168.0/24", "185.228.169.0/24",
+    // Control D
+    "76.76.2.0/24", "76.76.10.0/24",
+    // Mullvad
+    "194.242.2.2", "194.242.2.3", "194.242.2.4", "194.242.2.5", "194.242.2.9",
+];
+
+// Resolver hostnames apps look up to reach DoH when the addresses above aren't hardcoded
+const DOH_HOSTS: &[&str] = &[
+    "cloudflare-dns.com", "mozilla.cloudflare-dns.com", "one.one.one.one", "dns.google", "dns.google.com",
+    "dns.quad9.net", "dns10.quad9.net", "dns11.quad9.net", "doh.opendns.com", "dns.adguard-dns.com",
+    "dns.adguard.com", "dns.nextdns.io", "doh.cleanbrowsing.org", "freedns.controld.com", "dns.mullvad.net",
+    "doh.mullvad.net", "chrome.cloudflare-dns.com", "doh.dns.sb", "dns.twnic.tw",
+];
+
+// Answering NXDOMAIN for these tells Firefox to leave DoH off and Apple devices to leave
+// iCloud Private Relay off
+const CANARY_HOSTS: &[&str] = &["use-application-dns.net", "mask.icloud.com", "mask-h2.icloud.com"];
+
+// ============ SETTINGS ============
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DnsMode {
+    // Devices may use any DNS server
+    #[default]
+    Off,
+    // Queries to other servers are answered by the router; devices notice nothing
+    Redirect,
+    // Queries to other servers are refused
+    Block,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DnsGuardSettings {
+    // Plain DNS (port 53) to servers other than the router
+    pub dns: DnsMode,
+    // DNS over TLS and DNS over QUIC (port 853)
+    pub block_dot: bool,
+    // DNS over HTTPS to well-known public resolvers
+    pub block_doh: bool,
+    // Answer NTP (port 123) from the router whichever server a device asks
+    pub redirect_ntp: bool,
+    // Devices left alone, as IPv4 addresses or CIDR ranges
+    pub exceptions: Vec<String>,
+}
+
+pub async fn load_settings(pool: &SqlitePool) -> DnsGuardSettings {
+    match crate::db::settings::get(pool, SETTINGS_KEY).await {
+        Ok(settings) => settings.unwrap_or_default(),
+        Err(e) => {
+            tracing::warn!("Failed to read DNS bypass settings, using defaults: {}", e);
+            DnsGuardSettings::default()
+        }
+    }
+}
+
+pub async fn save_settings(pool: &SqlitePool, settings: &DnsGuardSettings) -> Result<(), String> {
+    crate::db::settings::set(pool, SETTINGS_KEY, settings).await.map_err(|e| e.to_string())
+}
+
+fn valid_exception(value: &str) -> bool {
+    match value.split_once('/') {
+        Some((ip, prefix)) => ip.parse::<Ipv4Addr>().is_ok() && prefix.parse::<u8>().is_ok_and(|p| p <= 32),
+        None => value.parse::<Ipv4Addr>().is_ok(),
+    }
+}
+
+impl DnsGuardSettings {
+    pub fn normalize(&mut self) -> Result<(), String> {
+        self.exceptions = self.exceptions.iter().map(|e| e.trim().to_string()).filter(|e| !e.is_empty()).collect();
+        self.exceptions.sort();
+        self.exceptions.dedup();
+        if self.exceptions.len() > MAX_EXCEPTIONS {
+            return Err(format!("At most {} exceptions are supported", MAX_EXCEPTIONS));
+        }
+        if let Some(bad) = self.exceptions.iter().find(|e| !valid_exception(e)) {
+            return Err(format!("'{}' is not an IPv4 address or CIDR range", bad));
+        }
+        Ok(())
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.dns != DnsMode::Off || self.block_dot || self.block_doh || self.redirect_ntp
+    }
+
+    // Rules of the filter chain, for traffic leaving through the WAN
+    fn filter_rules(&self) -> Vec<Vec<String>> {
+        let mut rules: Vec<Vec<&str>> = Vec::new();
+        if self.dns == DnsMode::Block {
+            rules.push(vec!["-p", "udp", "--dport", "53", "-j", "REJECT"]);
+            rules.push(vec!["-p", "tcp", "--dport", "53", "-j", "REJECT", "--reject-with", "tcp-reset"]);
+        }
+        if self.block_dot {
+            rules.push(vec!["-p", "tcp", "--dport", "853", "-j", "REJECT", "--reject-with", "tcp-reset"]);
+            rules.push(vec!["-p", "udp", "--dport", "853", "-j", "REJECT"]);
+        }
+        if self.block_doh {
+            // HTTP/3 runs over UDP 443
+            rules.push(vec!["-p", "tcp", "--dport", "443", "-m", "set", "--match-set", DOH_SET, "dst", "-j", "REJECT", "--reject-with", "tcp-reset"]);
+            rules.push(vec!["-p", "udp", "--dport", "443", "-m", "set", "--match-set", DOH_SET, "dst", "-j", "REJECT"]);
+        }
+        self.with_exceptions(rules)
+    }
+
+    // Rules of the nat chain, for traffic arriving from the LAN
+    fn nat_rules(&self) -> Vec<Vec<String>> {
+        let mut rules: Vec<Vec<&str>> = Vec::new();
+        if self.dns == DnsMode::Redirect {
+            rules.push(vec!["-p", "udp", "--dport", "53", "-j", "REDIRECT", "--to-ports", "53"]);
+            rules.push(vec!["-p", "tcp", "--dport", "53", "-j", "REDIRECT", "--to-ports", "53"]);
+        }
+        if self.redirect_ntp {
+            rules.push(vec!["-p", "udp", "--dport", "123", "-j", "REDIRECT", "--to-ports", "123"]);
+        }
+        self.with_exceptions(rules)
+    }
+
+    // Exceptions RETURN before any other rule; an empty chain needs none
+    fn with_exceptions(&self, rules: Vec<Vec<&str>>) -> Vec<Vec<String>> {
+        if rules.is_empty() {
+            return Vec::new();
+        }
+        let exceptions = self.exceptions.iter().map(|e| vec!["-s".to_string(), e.clone(), "-j".to_string(), "RETURN".to_string()]);
+        exceptions
+            .chain(rules.into_iter().map(|r| r.into_iter().map(str::to_string).collect()))
+            .collect()
+    }
+
+    /// AdGuard custom rules: resolver hostnames blocked, canaries answered with NXDOMAIN, for
+    /// every client but the exceptions
+    pub fn adguard_rules(&self) -> Vec<String> {
+        if !self.block_doh {
+            return Vec::new();
+        }
+        // "~" leaves a client out
+        let clients = (!self.exceptions.is_empty()).then(|| {
+            let excluded: Vec<String> = self.exceptions.iter().map(|e| format!("~{}", e)).collect();
+            format!("client={}", excluded.join("|"))
+        });
+        let modifiers = |first: Option<&str>| {
+            let list: Vec<&str> = first.into_iter().chain(clients.as_deref()).collect();
+            if list.is_empty() { String::new() } else { format!("${}", list.join(",")) }
+        };
+        let mut rules: Vec<String> = DOH_HOSTS.iter().map(|h| format!("||{}^{}", h, modifiers(None))).collect();
+        rules.extend(CANARY_HOSTS.iter().map(|h| format!("||{}^{}", h, modifiers(Some("dnsrewrite=NXDOMAIN")))));
+        rules
+    }
+}
+
+// ============ FIREWALL ============
+
+fn run(command: &mut RecordedCommand) -> Result<(), String> {
+    let output = command.output().map_err(|e| e.to_string())?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(String::from_utf8_lossy(&output.stderr).trim().to_string())
+    }
+}
+
+fn succeeds(command: &mut RecordedCommand) -> bool {
+    command.output().map(|o| o.status.success()).unwrap_or(false)
+}
+
+fn set_exists() -> bool {
+    succeeds(sudo().args(["ipset", "list", DOH_SET, "-t"]))
+}
+
+fn chain_exists(table: &str) -> bool {
+    succeeds(sudo().args(["iptables", "-t", table, "-S", CHAIN]))
+}
+
+// Jumps are removed by reading them back, since the WAN interface may have changed
+fn remove_jumps(table: &str, parent: &str) {
+    let Ok(output) = sudo().args(["iptables", "-t", table, "-S", parent]).output() else { return };
+    let prefix = format!("-A {} ", parent);
+    let suffix = format!("-j {}", CHAIN);
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        let Some(rule) = line.strip_prefix(&prefix) else { continue };
+        if rule.ends_with(&suffix) {
+            let _ = sudo().args(["iptables", "-t", table, "-D", parent]).args(rule.split_whitespace()).output();
+        }
+    }
+}
+
+fn remove_rules() {
+    for (table, parent) in [("filter", "FORWARD"), ("nat", "PREROUTING")] {
+        remove_jumps(table, parent);
+        if chain_exists(table) {
+            let _ = sudo().args(["iptables", "-t", table, "-F", CHAIN]).output();
+            let _ = sudo().args(["iptables", "-t", table, "-X", CHAIN]).output();
+        }
+    }
+}
+
+fn fill_doh_set() -> Result<(), String> {
+    if !set_exists() {
+        run(sudo().args(["ipset", "create", DOH_SET, "hash:net", "maxelem", "1024"]))?;
+    }
+    run(sudo().args(["ipset", "flush", DOH_SET]))?;
+    for resolver in DOH_RESOLVERS {
+        run(sudo().args(["ipset", "add", DOH_SET, resolver, "-exist"]))?;
+    }
+    Ok(())
+}
+
+/// Put the firewall in line with the settings; nothing of ours is left when all is off.
+/// Saved so it survives a reboot.
+pub fn apply(wan: &str, settings: &DnsGuardSettings) -> Result<(), String> {
+    remove_rules();
+
+    if settings.block_doh {
+        fill_doh_set()?;
+    } else if set_exists() {
+        run(sudo().args(["ipset", "destroy", DOH_SET]))?;
+    }
+
+    let filter = settings.filter_rules();
+    if !filter.is_empty() {
+        run(sudo().args(["iptables", "-N", CHAIN]))?;
+        for rule in &filter {
+            run(sudo().args(["iptables", "-A", CHAIN]).args(rule))?;
+        }
+        run(sudo().args(["iptables", "-I", "FORWARD", "1", "-o", wan, "-j", CHAIN]))?;
+    }
+
+    let nat = settings.nat_rules();
+    if !nat.is_empty() {
+        run(sudo().args(["iptables", "-t", "nat", "-N", CHAIN]))?;
+        for rule in &nat {
+            run(sudo().args(["iptables", "-t", "nat", "-A", CHAIN]).args(rule))?;
+        }
+        run(sudo().args(["iptables", "-t", "nat", "-I", "PREROUTING", "1", "!", "-i", wan, "-j", CHAIN]))?;
+    }
+
+    run(sudo().args(["netfilter-persistent", "save"]))
+}
+
+/// Push the resolver hostnames to AdGuard. False when AdGuard isn't running, in which case only
+/// the resolver addresses are blocked.
+pub async fn apply_adguard(settings: &DnsGuardSettings) -> Result<bool, String> {
+    if !crate::api::adguard::reachable().await {
+        return Ok(false);
+    }
+    crate::api::adguard::set_managed_rules(ADGUARD_MARKER, settings.adguard_rules()).await?;
+    Ok(true)
+}
+
+/// Drop the chains and the resolver set (factory reset)
+pub fn teardown() -> Result<(), String> {
+    remove_rules();
+    if set_exists() {
+        run(sudo().args(["ipset", "destroy", DOH_SET]))?;
+    }
+    Ok(())
+}
+
+/// Rebuild the rules at startup: the resolver set doesn't survive a reboot, and the saved rules
+/// can't be restored without it
+pub fn spawn(state: Arc<AppState>) {
+    tokio::spawn(async move {
+        let settings = load_settings(&state.db).await;
+        if !settings.enabled() {
+            return;
+        }
+        let wan = crate::wan::wan_interface(&state.db).await;
+        match tokio::task::spawn_blocking(move || apply(&wan, &settings)).await {
+            Ok(Err(e)) => tracing::warn!("Could not set up DNS bypass blocking: {}", e),
+            Err(e) => tracing::warn!("Could not set up DNS bypass blocking: {}", e),
+            Ok(Ok(())) => {}
+        }
+    });
+}
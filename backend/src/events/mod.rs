@@ -0,0 +1,126 @@
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+// Slow subscribers lag (and miss events) rather than blocking emitters
+const CHANNEL_CAPACITY: usize = 256;
+
+/// Things that happened on the router that other subsystems (DDNS, notifications) react to
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    WanIpChanged {
+        previous: Option<String>,
+        current: String,
+        changed_at: String,
+    },
+    BruteForceBlocked {
+        ip: String,
+        failures: u32,
+        username: String,
+        expires_at: String,
+    },
+    DhcpPoolLow {
+        used: u32,
+        size: u32,
+        utilization: f64,
+        level: crate::api::network::PoolLevel,
+        suggestions: Vec<String>,
+    },
+    // Modem data use crossed the warning threshold or the SIM's cap for this billing cycle
+    ModemDataCap {
+        used_mb: f64,
+        cap_mb: u64,
+        percent: f64,
+        level: crate::modem::CapLevel,
+    },
+    // Traffic moved to the modem because the wired WAN is unreachable, or back again
+    WanFailover {
+        active: bool,
+        interface: String,
+    },
+    // A tracked person or device arrived home or left (after the away delay)
+    PresenceChanged {
+        device_id: String,
+        name: String,
+        home: bool,
+    },
+    // The first tracked device came home, or the last one left
+    HouseholdPresence {
+        anyone_home: bool,
+    },
+    // A destructive action is waiting for a second admin
+    ApprovalRequested {
+        id: String,
+        action: crate::approvals::Action,
+        summary: String,
+        requested_by: String,
+        expires_at: String,
+    },
+    // A TLS certificate on the LAN expires within the warning period, or already has
+    CertificateExpiring {
+        name: String,
+        location: String,
+        expires_at: String,
+        days_left: i64,
+    },
+    // An uptime monitor failed enough checks in a row to count as down, or passed one again
+    MonitorStatusChanged {
+        id: String,
+        name: String,
+        url: String,
+        up: bool,
+        error: Option<String>,
+    },
+    // A homelab host was woken but its SSH port didn't come up in time
+    HostWakeFailed {
+        id: String,
+        name: String,
+        waited_secs: u64,
+    },
+    // A session token turned up from another network than it was created on, and was ended
+    SessionIpMismatch {
+        username: String,
+        session_id: i64,
+        original_ip: String,
+        ip: String,
+    },
+    // A scanner connected to a decoy port and was banned
+    HoneypotBanned {
+        ip: String,
+        country: Option<String>,
+        expires_at: String,
+    },
+    // RouterUI was reset to its installed state; `errors` lists parts that could not be removed
+    FactoryReset {
+        removed: usize,
+        errors: Vec<String>,
+    },
+}
+
+/// In-process broadcast bus for [`Event`]s
+pub struct EventBus {
+    tx: broadcast::Sender<Event>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        let (tx, _) = broadcast::channel(CHANNEL_CAPACITY);
+        Self { tx }
+    }
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn emit(&self, event: Event) {
+        tracing::info!("Event: {:?}", event);
+        // No subscribers is fine
+        let _ = self.tx.send(event);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Event> {
+        self.tx.subscribe()
+    }
+}
@@ -0,0 +1,129 @@
+// Offline IPv4 country lookup from ipdeny's per-country zone files. The whole set is one
+// archive, refreshed weekly and loaded into a sorted range table on first use.
+
+use std::net::Ipv4Addr;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use crate::AppState;
+
+const ZONES_URL: &str = "https://www.ipdeny.com/ipblocks/data/countries/all-zones.tar.gz";
+const GEOIP_DIR: &str = "/opt/routerui/blocklists/geoip";
+const MAX_AGE: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+const CHECK_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+// (first, last, country) with ranges sorted by `first` and never overlapping
+type Table = Vec<(u32, u32, [u8; 2])>;
+
+static TABLE: Mutex<Option<Arc<Table>>> = Mutex::new(None);
+
+fn zones_dir() -> &'static Path {
+    Path::new(GEOIP_DIR)
+}
+
+fn archive_path() -> PathBuf {
+    zones_dir().join("all-zones.tar.gz")
+}
+
+/// When the zone files were last downloaded
+pub fn updated_at() -> Option<SystemTime> {
+    std::fs::metadata(archive_path()).and_then(|m| m.modified()).ok()
+}
+
+fn parse_cidr(line: &str) -> Option<(u32, u32)> {
+    let (addr, prefix) = line.split_once('/')?;
+    let start = u32::from(addr.parse::<Ipv4Addr>().ok()?);
+    let prefix: u32 = prefix.parse().ok().filter(|p| *p <= 32)?;
+    let size = if prefix == 0 { u32::MAX } else { (1u32 << (32 - prefix)) - 1 };
+    Some((start, start | size))
+}
+
+fn load() -> Table {
+    let mut table = Table::new();
+    let Ok(entries) = std::fs::read_dir(zones_dir()) else { return table };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.extension().and_then(|e| e.to_str()) != Some("zone") {
+            continue;
+        }
+        let Some(code) = path.file_stem().and_then(|s| s.to_str()).map(|s| s.to_uppercase()) else { continue };
+        let Ok(code) = <[u8; 2]>::try_from(code.as_bytes()) else { continue };
+        let Ok(content) = std::fs::read_to_string(&path) else { continue };
+        table.extend(content.lines().filter_map(|l| parse_cidr(l.trim())).map(|(a, b)| (a, b, code)));
+    }
+    table.sort_unstable_by_key(|(first, _, _)| *first);
+    table
+}
+
+fn table() -> Arc<Table> {
+    let mut cached = TABLE.lock().unwrap();
+    cached.get_or_insert_with(|| Arc::new(load())).clone()
+}
+
+/// ISO country code of an IPv4 address; None for IPv6, private ranges or before the first download
+pub fn country(ip: &str) -> Option<String> {
+    let ip = u32::from(ip.parse::<Ipv4Addr>().ok()?);
+    let table = table();
+    let index = table.partition_point(|(first, _, _)| *first <= ip).checked_sub(1)?;
+    let (_, last, code) = table[index];
+    (ip <= last).then(|| String::from_utf8_lossy(&code).into_owned())
+}
+
+/// Download and unpack the zone files, then drop the loaded table so the next lookup reloads
+pub fn refresh() -> Result<(), String> {
+    std::fs::create_dir_all(zones_dir()).map_err(|e| e.to_string())?;
+    let archive = archive_path();
+    let partial = archive.with_extension("part");
+
+    let download = Command::new("curl")
+        .args(["-s", "-f", "-o"])
+        .arg(&partial)
+        .arg(ZONES_URL)
+        .output()
+        .map_err(|e| e.to_string())?;
+    if !download.status.success() {
+        let _ = std::fs::remove_file(&partial);
+        return Err("Failed to download the country zone files".to_string());
+    }
+
+    let unpack = Command::new("tar")
+        .arg("-xzf")
+        .arg(&partial)
+        .arg("-C")
+        .arg(zones_dir())
+        .output()
+        .map_err(|e| e.to_string())?;
+    if !unpack.status.success() {
+        let _ = std::fs::remove_file(&partial);
+        return Err(String::from_utf8_lossy(&unpack.stderr).trim().to_string());
+    }
+    std::fs::rename(&partial, &archive).map_err(|e| e.to_string())?;
+
+    *TABLE.lock().unwrap() = None;
+    Ok(())
+}
+
+fn is_stale() -> bool {
+    updated_at()
+        .and_then(|t| t.elapsed().ok())
+        .is_none_or(|age| age > MAX_AGE)
+}
+
+pub fn spawn(state: Arc<AppState>) {
+    tokio::spawn(async move {
+        loop {
+            state.tasks.beat("geoip", CHECK_INTERVAL);
+            if is_stale() {
+                match tokio::task::spawn_blocking(refresh).await {
+                    Ok(Err(e)) => tracing::warn!("GeoIP refresh failed: {}", e),
+                    Err(e) => tracing::warn!("GeoIP refresh failed: {}", e),
+                    Ok(Ok(())) => tracing::info!("GeoIP zone files updated"),
+                }
+            }
+            tokio::time::sleep(CHECK_INTERVAL).await;
+        }
+    });
+}
@@ -0,0 +1,256 @@
+// Temporary guest WiFi passwords. Each code is valid for a number of hours and comes with the
+// WIFI: string phones read from a QR code. There are two ways to make codes work, picked in the
+// settings. Rotating the guest SSID's passphrase works with any hostapd, but only the newest code
+// is valid and every rotation drops connected guests. Per-station PSKs list every active code in a
+// wpa_psk_file, which hostapd (2.10 or later) re-reads without dropping anyone; the guest SSID's
+// own passphrase keeps working alongside. Adopted mesh APs only ever get the guest passphrase.
+
+use chrono::{Duration as ChronoDuration, SecondsFormat, Utc};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::api::network::{guest_network, reload_guest_psks, update_guest_options};
+use crate::system::privileges::write_system_file;
+use crate::AppState;
+
+// Key in the settings table
+pub const SETTINGS_KEY: &str = "guest_codes";
+pub const PSK_FILE: &str = "/etc/hostapd/guest-psk.conf";
+pub const MAX_HOURS: u32 = 30 * 24;
+const MAX_LABEL_LEN: usize = 100;
+const SYNC_INTERVAL: Duration = Duration::from_secs(60);
+// Expired and revoked codes stay listed this long
+const KEEP_DAYS: i64 = 30;
+// No 0/o, 1/l/i: codes get read out loud and typed on phones
+const CODE_ALPHABET: &[u8] = b"abcdefghjkmnpqrstuvwxyz23456789";
+// Any station may use a PSK on this line
+const ANY_STATION: &str = "00:00:00:00:00:00";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Mode {
+    #[default]
+    Rotate,
+    PerStation,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GuestCodeSettings {
+    pub mode: Mode,
+}
+
+pub async fn load_settings(pool: &SqlitePool) -> GuestCodeSettings {
+    match crate::db::settings::get(pool, SETTINGS_KEY).await {
+        Ok(settings) => settings.unwrap_or_default(),
+        Err(e) => {
+            tracing::warn!("Failed to read guest code settings, using defaults: {}", e);
+            GuestCodeSettings::default()
+        }
+    }
+}
+
+pub async fn save_settings(pool: &SqlitePool, settings: &GuestCodeSettings) -> Result<(), String> {
+    crate::db::settings::set(pool, SETTINGS_KEY, settings).await.map_err(|e| e.to_string())
+}
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct GuestCode {
+    pub id: i64,
+    pub code: String,
+    pub label: String,
+    pub created_by: String,
+    pub created_at: String,
+    pub expires_at: String,
+    pub revoked_at: Option<String>,
+}
+
+// Stored times are all UTC to the second, so they compare as strings
+fn timestamp(at: chrono::DateTime<Utc>) -> String {
+    at.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+impl GuestCode {
+    pub fn is_active(&self) -> bool {
+        self.revoked_at.is_none() && self.expires_at > timestamp(Utc::now())
+    }
+}
+
+/// Twelve characters in groups of four, e.g. "k7pq-2m9x-c4rt"
+pub fn generate_code() -> String {
+    let mut rng = rand::thread_rng();
+    let chars: Vec<char> = (0..12)
+        .map(|_| CODE_ALPHABET[rng.gen_range(0..CODE_ALPHABET.len())] as char)
+        .collect();
+    chars.chunks(4).map(|c| c.iter().collect::<String>()).collect::<Vec<_>>().join("-")
+}
+
+/// The string WiFi QR codes carry: WIFI:T:WPA;S:<ssid>;P:<password>;;
+pub fn qr_payload(ssid: &str, password: &str) -> String {
+    let escape = |value: &str| {
+        value.chars().fold(String::new(), |mut out, c| {
+            if matches!(c, '\\' | ';' | ',' | ':' | '"') {
+                out.push('\\');
+            }
+            out.push(c);
+            out
+        })
+    };
+    format!("WIFI:T:WPA;S:{};P:{};;", escape(ssid), escape(password))
+}
+
+pub fn validate_label(label: &str) -> Result<(), String> {
+    if label.chars().count() > MAX_LABEL_LEN {
+        return Err(format!("Labels can be at most {} characters", MAX_LABEL_LEN));
+    }
+    Ok(())
+}
+
+/// Newest first
+pub async fn list(pool: &SqlitePool) -> Result<Vec<GuestCode>, sqlx::Error> {
+    sqlx::query_as(
+        "SELECT id, code, label, created_by, created_at, expires_at, revoked_at FROM guest_codes ORDER BY id DESC",
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Issue a code valid for `hours`. Rotating the passphrase leaves room for one valid code, so in
+/// that mode the ones before it are revoked.
+pub async fn create(pool: &SqlitePool, hours: u32, label: &str, created_by: &str) -> Result<GuestCode, sqlx::Error> {
+    let now = Utc::now();
+    let rotate = load_settings(pool).await.mode == Mode::Rotate;
+    let mut tx = pool.begin().await?;
+    if rotate {
+        sqlx::query("UPDATE guest_codes SET revoked_at = ? WHERE revoked_at IS NULL AND expires_at > ?")
+            .bind(timestamp(now))
+            .bind(timestamp(now))
+            .execute(&mut *tx)
+            .await?;
+    }
+    let code = sqlx::query_as(
+        "INSERT INTO guest_codes (code, label, created_by, created_at, expires_at) VALUES (?, ?, ?, ?, ?)
+         RETURNING id, code, label, created_by, created_at, expires_at, revoked_at",
+    )
+    .bind(generate_code())
+    .bind(label)
+    .bind(created_by)
+    .bind(timestamp(now))
+    .bind(timestamp(now + ChronoDuration::hours(hours as i64)))
+    .fetch_one(&mut *tx)
+    .await?;
+    tx.commit().await?;
+    Ok(code)
+}
+
+/// The revoked code, if `id` was still active
+pub async fn revoke(pool: &SqlitePool, id: i64) -> Result<Option<GuestCode>, sqlx::Error> {
+    let now = timestamp(Utc::now());
+    sqlx::query_as(
+        "UPDATE guest_codes SET revoked_at = ? WHERE id = ? AND revoked_at IS NULL AND expires_at > ?
+         RETURNING id, code, label, created_by, created_at, expires_at, revoked_at",
+    )
+    .bind(&now)
+    .bind(id)
+    .bind(&now)
+    .fetch_optional(pool)
+    .await
+}
+
+async fn purge(pool: &SqlitePool) -> Result<(), sqlx::Error> {
+    let cutoff = timestamp(Utc::now() - ChronoDuration::days(KEEP_DAYS));
+    sqlx::query("DELETE FROM guest_codes WHERE expires_at < ? OR revoked_at < ?")
+        .bind(&cutoff)
+        .bind(&cutoff)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+fn psk_file(active: &[String]) -> String {
+    let mut content = String::from("# Guest access codes - managed by RouterUI\n");
+    for code in active {
+        content.push_str(&format!("{} {}\n", ANY_STATION, code));
+    }
+    content
+}
+
+// Make hostapd accept exactly the active codes; returns whether the guest passphrase changed
+fn apply(mode: Mode, active: &[String], issued: &[String]) -> Result<bool, String> {
+    let Some(guest) = guest_network() else {
+        return Ok(false);
+    };
+    match mode {
+        Mode::PerStation => {
+            let content = psk_file(active);
+            let changed = std::fs::read_to_string(PSK_FILE).ok().as_deref() != Some(content.as_str());
+            if changed {
+                write_system_file(PSK_FILE, &content).map_err(|e| e.to_string())?;
+            }
+            // A restart reads the file anyway
+            let restarted = update_guest_options(&[("wpa_psk_file", Some(PSK_FILE.to_string()))])?;
+            if changed && !restarted {
+                reload_guest_psks(&guest.interface)?;
+            }
+            Ok(false)
+        }
+        Mode::Rotate => {
+            let mut options = Vec::new();
+            if guest.psk_file.as_deref() == Some(PSK_FILE) {
+                options.push(("wpa_psk_file", None));
+            }
+            let passphrase = match active.first() {
+                Some(code) if guest.passphrase.as_deref() != Some(code.as_str()) => Some(code.clone()),
+                // The last code ran out: move to a passphrase nobody was given
+                None if guest.passphrase.as_ref().is_some_and(|p| issued.contains(p)) => {
+                    Some(format!("{}-{}", generate_code(), generate_code()))
+                }
+                _ => None,
+            };
+            let passphrase_changed = passphrase.is_some();
+            if let Some(passphrase) = passphrase {
+                options.push(("wpa_passphrase", Some(passphrase)));
+            }
+            if options.is_empty() {
+                return Ok(false);
+            }
+            update_guest_options(&options)?;
+            Ok(passphrase_changed)
+        }
+    }
+}
+
+/// Bring hostapd in line with the codes; after every change to them, and every minute for expiry
+pub async fn sync(pool: &SqlitePool) -> Result<(), String> {
+    let mode = load_settings(pool).await.mode;
+    let codes = list(pool).await.map_err(|e| e.to_string())?;
+    let active: Vec<String> = codes.iter().filter(|c| c.is_active()).map(|c| c.code.clone()).collect();
+    let issued: Vec<String> = codes.into_iter().map(|c| c.code).collect();
+
+    let passphrase_changed = tokio::task::spawn_blocking(move || apply(mode, &active, &issued))
+        .await
+        .map_err(|e| e.to_string())??;
+    if passphrase_changed {
+        tracing::info!("Guest WiFi passphrase rotated for guest access codes");
+        crate::mesh::spawn_sync_all(pool.clone());
+    }
+    Ok(())
+}
+
+pub fn spawn(state: Arc<AppState>) {
+    tokio::spawn(async move {
+        loop {
+            state.tasks.beat("guest_codes", SYNC_INTERVAL);
+            if let Err(e) = purge(&state.db).await {
+                tracing::warn!("Failed to purge old guest codes: {}", e);
+            }
+            if let Err(e) = sync(&state.db).await {
+                tracing::warn!("Failed to apply guest access codes: {}", e);
+            }
+            tokio::time::sleep(SYNC_INTERVAL).await;
+        }
+    });
+}
@@ -0,0 +1,169 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::AppState;
+
+// A task counts as dead once it misses this many of its own intervals
+const MISSED_BEATS_ALLOWED: u32 = 3;
+const DEFAULT_WATCHDOG_INTERVAL: Duration = Duration::from_secs(30);
+
+// ============ BACKGROUND TASK HEARTBEATS ============
+
+struct Heartbeat {
+    last_beat: Instant,
+    interval: Duration,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TaskHealth {
+    pub name: String,
+    pub alive: bool,
+    pub seconds_since_beat: u64,
+    pub interval_secs: u64,
+}
+
+/// Tracks the last heartbeat of each long-running background task
+#[derive(Default)]
+pub struct TaskRegistry {
+    tasks: Mutex<HashMap<&'static str, Heartbeat>>,
+}
+
+impl TaskRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record that a task is still running. `interval` is how often it is expected to beat.
+    pub fn beat(&self, name: &'static str, interval: Duration) {
+        let mut tasks = self.tasks.lock().unwrap();
+        tasks.insert(name, Heartbeat { last_beat: Instant::now(), interval });
+    }
+
+    pub fn snapshot(&self) -> Vec<TaskHealth> {
+        let tasks = self.tasks.lock().unwrap();
+        let mut result: Vec<TaskHealth> = tasks
+            .iter()
+            .map(|(name, hb)| {
+                let elapsed = hb.last_beat.elapsed();
+                TaskHealth {
+                    name: name.to_string(),
+                    alive: elapsed <= hb.interval * MISSED_BEATS_ALLOWED,
+                    seconds_since_beat: elapsed.as_secs(),
+                    interval_secs: hb.interval.as_secs(),
+                }
+            })
+            .collect();
+        result.sort_by(|a, b| a.name.cmp(&b.name));
+        result
+    }
+}
+
+// ============ HEALTH CHECKS ============
+
+#[derive(Debug, Serialize)]
+pub struct CheckResult {
+    pub ok: bool,
+    pub error: Option<String>,
+}
+
+impl CheckResult {
+    fn from_result<E: ToString>(result: Result<(), E>) -> Self {
+        match result {
+            Ok(()) => CheckResult { ok: true, error: None },
+            Err(e) => CheckResult { ok: false, error: Some(e.to_string()) },
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct HealthReport {
+    pub healthy: bool,
+    pub database: CheckResult,
+    pub disk: CheckResult,
+    pub tasks: Vec<TaskHealth>,
+}
+
+pub async fn check(state: &AppState) -> HealthReport {
+    let database = CheckResult::from_result(
+        sqlx::query_scalar::<_, i64>("SELECT 1")
+            .fetch_one(&state.db)
+            .await
+            .map(|_| ()),
+    );
+
+    let disk = CheckResult::from_result(check_disk_writable());
+
+    let tasks = state.tasks.snapshot();
+
+    let healthy = database.ok && disk.ok && tasks.iter().all(|t| t.alive);
+
+    HealthReport { healthy, database, disk, tasks }
+}
+
+fn check_disk_writable() -> Result<(), std::io::Error> {
+    // Unique per call so concurrent checks don't delete each other's probe
+    let probe = crate::db::data_dir().join(format!(".health-probe-{}", uuid::Uuid::new_v4()));
+    std::fs::write(&probe, b"ok")?;
+    std::fs::remove_file(&probe)
+}
+
+// ============ SYSTEMD INTEGRATION ============
+
+/// Send a state notification to systemd (see sd_notify(3)).
+/// Returns false when not running under a notify-aware unit.
+pub fn sd_notify(message: &str) -> bool {
+    use std::os::unix::net::UnixDatagram;
+
+    let socket_path = match std::env::var("NOTIFY_SOCKET") {
+        Ok(p) if !p.is_empty() => p,
+        _ => return false,
+    };
+
+    let socket = match UnixDatagram::unbound() {
+        Ok(s) => s,
+        Err(_) => return false,
+    };
+
+    let sent = if let Some(name) = socket_path.strip_prefix('@') {
+        // Abstract namespace socket
+        use std::os::linux::net::SocketAddrExt;
+        std::os::unix::net::SocketAddr::from_abstract_name(name.as_bytes())
+            .and_then(|addr| socket.send_to_addr(message.as_bytes(), &addr))
+    } else {
+        socket.send_to(message.as_bytes(), &socket_path)
+    };
+
+    sent.is_ok()
+}
+
+// systemd sets WATCHDOG_USEC when WatchdogSec= is configured; ping at half that
+fn watchdog_interval() -> Duration {
+    std::env::var("WATCHDOG_USEC")
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .map(|usec| Duration::from_micros(usec / 2))
+        .unwrap_or(DEFAULT_WATCHDOG_INTERVAL)
+}
+
+/// Periodically run the health checks and only feed the systemd watchdog while they pass,
+/// so a wedged database or full disk gets the service restarted.
+pub fn spawn_watchdog(state: Arc<AppState>) {
+    let interval = watchdog_interval();
+
+    tokio::spawn(async move {
+        loop {
+            state.tasks.beat("watchdog", interval);
+
+            let report = check(&state).await;
+            if report.healthy {
+                sd_notify("WATCHDOG=1");
+            } else {
+                tracing::warn!("Health check failed, withholding watchdog ping: {:?}", report);
+            }
+
+            tokio::time::sleep(interval).await;
+        }
+    });
+}
@@ -0,0 +1,1117 @@
+// Typed privileged operations. The web service never hands the helper a command line: it sends
+// one of these, and the helper validates every field and builds the argv itself, so there is
+// nothing to smuggle extra arguments, options or paths through. `parse` lets the
+// `routerui-helper exec` shim accept the same command lines the sudo fallback runs.
+
+use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, Ipv4Addr};
+
+pub const QUARANTINE_DIR: &str = "/opt/routerui/quarantine";
+pub const RESTORED_DIR: &str = "/opt/routerui/restored";
+const AUTH_LOG: &str = "/var/log/auth.log";
+const MAX_LINES: u32 = 10_000;
+// tc rejects rates it can't represent; 100 Gbit/s is far beyond any WAN
+const MAX_SHAPER_KBIT: u32 = 100_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Table {
+    Filter,
+    Nat,
+    // Only for the DSCP marks of traffic shaping
+    Mangle,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IptablesAction {
+    List,
+    ListRules,
+    Check,
+    Insert,
+    Append,
+    Delete,
+    Policy,
+    // Chain management, limited to RouterUI's own ROUTERUI-* chains
+    NewChain,
+    FlushChain,
+    DeleteChain,
+}
+
+impl IptablesAction {
+    const ALL: [IptablesAction; 10] = [
+        IptablesAction::List,
+        IptablesAction::ListRules,
+        IptablesAction::Check,
+        IptablesAction::Insert,
+        IptablesAction::Append,
+        IptablesAction::Delete,
+        IptablesAction::Policy,
+        IptablesAction::NewChain,
+        IptablesAction::FlushChain,
+        IptablesAction::DeleteChain,
+    ];
+
+    fn flag(self) -> &'static str {
+        match self {
+            IptablesAction::List => "-L",
+            IptablesAction::ListRules => "-S",
+            IptablesAction::Check => "-C",
+            IptablesAction::Insert => "-I",
+            IptablesAction::Append => "-A",
+            IptablesAction::Delete => "-D",
+            IptablesAction::Policy => "-P",
+            IptablesAction::NewChain => "-N",
+            IptablesAction::FlushChain => "-F",
+            IptablesAction::DeleteChain => "-X",
+        }
+    }
+}
+
+// Which way a cake shaper faces: egress on the WAN itself, or ingress on the IFB that WAN
+// downloads are redirected through
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ShaperDirection {
+    Egress,
+    Ingress,
+}
+
+impl ShaperDirection {
+    // Per-host fairness by the LAN address, which cake finds through conntrack ("nat")
+    fn options(self) -> &'static [&'static str] {
+        match self {
+            ShaperDirection::Egress => &["diffserv4", "nat", "dual-srchost"],
+            ShaperDirection::Ingress => &["diffserv4", "nat", "dual-dsthost", "ingress"],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SystemctlAction {
+    Start,
+    Stop,
+    Restart,
+    Reload,
+    Enable,
+    Disable,
+}
+
+impl SystemctlAction {
+    const ALL: [SystemctlAction; 6] = [
+        SystemctlAction::Start,
+        SystemctlAction::Stop,
+        SystemctlAction::Restart,
+        SystemctlAction::Reload,
+        SystemctlAction::Enable,
+        SystemctlAction::Disable,
+    ];
+
+    fn as_str(self) -> &'static str {
+        match self {
+            SystemctlAction::Start => "start",
+            SystemctlAction::Stop => "stop",
+            SystemctlAction::Restart => "restart",
+            SystemctlAction::Reload => "reload",
+            SystemctlAction::Enable => "enable",
+            SystemctlAction::Disable => "disable",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TailscaleAction {
+    Up,
+    Down,
+    Logout,
+    Set,
+}
+
+impl TailscaleAction {
+    const ALL: [TailscaleAction; 4] =
+        [TailscaleAction::Up, TailscaleAction::Down, TailscaleAction::Logout, TailscaleAction::Set];
+
+    fn as_str(self) -> &'static str {
+        match self {
+            TailscaleAction::Up => "up",
+            TailscaleAction::Down => "down",
+            TailscaleAction::Logout => "logout",
+            TailscaleAction::Set => "set",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HostapdAction {
+    WpsPbc,
+    WpsCancel,
+    WpsGetStatus,
+    // Re-read wpa_psk_file (guest access codes)
+    ReloadWpaPsk,
+}
+
+impl HostapdAction {
+    const ALL: [HostapdAction; 4] = [
+        HostapdAction::WpsPbc,
+        HostapdAction::WpsCancel,
+        HostapdAction::WpsGetStatus,
+        HostapdAction::ReloadWpaPsk,
+    ];
+
+    fn as_str(self) -> &'static str {
+        match self {
+            HostapdAction::WpsPbc => "wps_pbc",
+            HostapdAction::WpsCancel => "wps_cancel",
+            HostapdAction::WpsGetStatus => "wps_get_status",
+            HostapdAction::ReloadWpaPsk => "reload_wpa_psk",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum Privileged {
+    Iptables { ipv6: bool, table: Table, action: IptablesAction, args: Vec<String> },
+    IptablesSave { table: Option<Table> },
+    // Rules arrive on stdin
+    IptablesRestore,
+    Ip6tablesSave,
+    // Rules arrive on stdin; only ever a save taken before a change, put back on rollback
+    Ip6tablesRestore,
+    PersistFirewall,
+    IpsetList { set: String, terse: bool },
+    // Sets created with a timeout expire their entries on their own
+    IpsetCreate { set: String, timeout: Option<u32> },
+    IpsetAdd { set: String, entry: String, timeout: Option<u32> },
+    IpsetDel { set: String, entry: String },
+    IpsetFlush { set: String },
+    IpsetDestroy { set: String },
+    Systemctl { action: SystemctlAction, unit: String },
+    ServiceLog { unit: String, lines: u32 },
+    KernelLog { since_hours: u32 },
+    AuthLogTail { lines: u32 },
+    AuthLogFailures,
+    RouteAdd { destination: String, gateway: String, interface: Option<String> },
+    RouteDel { destination: String },
+    AddrFlush { interface: String },
+    AddrAdd { address: String, interface: String },
+    LinkUp { interface: String },
+    LinkDown { interface: String },
+    // WAN MAC override
+    LinkAddress { interface: String, mac: String },
+    // A VLAN link for an extra LAN subnet, always named <parent>.<id>
+    VlanAdd { parent: String, vlan_id: u16 },
+    // Only VLAN links can be deleted, never a physical interface
+    VlanDel { interface: String },
+    AddrDel { address: String, interface: String },
+    NetplanApply,
+    WakeOnLan { interface: String, mac: String },
+    // Who answers for an address, before it is assigned statically
+    ArpProbe { interface: String, address: String },
+    // Push-button WPS only; PIN methods are never offered
+    HostapdCli { interface: String, action: HostapdAction },
+    // Connection tracking table, for the optional connection log
+    ConntrackList,
+    // Traffic shaping (SQM): a cake qdisc on the interface's root
+    Shaper { interface: String, direction: ShaperDirection, bandwidth_kbit: u32 },
+    ShaperDel { interface: String },
+    // Downloads are shaped by redirecting the WAN's ingress to an IFB device
+    IngressAdd { interface: String },
+    IngressDel { interface: String },
+    IngressRedirect { interface: String, ifb: String },
+    // Only links named ifb* can be created or deleted this way
+    IfbAdd { interface: String },
+    IfbDel { interface: String },
+    Tailscale { action: TailscaleAction, flags: Vec<String> },
+    // ModemManager; reading modem state needs no privileges, these do
+    ModemSignalSetup { modem: u32 },
+    ModemConnect { modem: u32, settings: Vec<(String, String)> },
+    ModemDisconnect { modem: u32 },
+    SmsList { modem: u32 },
+    SmsRead { sms: u32 },
+    SmsCreate { modem: u32, number: String, text: String },
+    SmsSend { sms: u32 },
+    SmsDelete { modem: u32, sms: u32 },
+    // Default route over the modem's data interface, for WAN failover
+    ModemRoute { add: bool, interface: String, metric: u32 },
+    Clamscan { path: String, quarantine: bool },
+    Freshclam,
+    QuarantineDelete { file_name: String },
+    QuarantineRestore { file_name: String },
+    AptUpdate,
+    AptUpgrade,
+}
+
+// ============ FIELD VALIDATION ============
+
+fn require(ok: bool, what: &str, value: &str) -> Result<(), String> {
+    if ok {
+        Ok(())
+    } else {
+        Err(format!("invalid {}: {:?}", what, value))
+    }
+}
+
+// Systemd unit, ipset name: never an option, never a path
+fn valid_name(value: &str, max: usize) -> bool {
+    !value.is_empty()
+        && value.len() <= max
+        && !value.starts_with('-')
+        && !value.starts_with('.')
+        && value.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.' | ':' | '@'))
+}
+
+fn unit(value: &str) -> Result<(), String> {
+    require(valid_name(value, 256), "unit name", value)
+}
+
+fn set_name(value: &str) -> Result<(), String> {
+    require(valid_name(value, 31), "ipset name", value)
+}
+
+fn interface(value: &str) -> Result<(), String> {
+    let ok = !value.is_empty()
+        && value.len() <= 15
+        && !value.starts_with('-')
+        && value.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'));
+    require(ok, "interface", value)
+}
+
+fn ifb(value: &str) -> Result<(), String> {
+    interface(value)?;
+    require(value.starts_with("ifb"), "IFB interface", value)
+}
+
+fn is_cidr(value: &str) -> bool {
+    let (addr, prefix) = match value.split_once('/') {
+        Some((addr, prefix)) => (addr, Some(prefix)),
+        None => (value, None),
+    };
+    let Ok(addr) = addr.parse::<IpAddr>() else { return false };
+    match prefix {
+        None => true,
+        Some(p) => p.parse::<u8>().is_ok_and(|p| p <= if addr.is_ipv4() { 32 } else { 128 }),
+    }
+}
+
+fn cidr(value: &str) -> Result<(), String> {
+    require(is_cidr(value), "address", value)
+}
+
+// ipset hash:net accepts an address, a CIDR or a from-to range
+fn ipset_entry(value: &str) -> Result<(), String> {
+    let ok = match value.split_once('-') {
+        Some((from, to)) => from.parse::<IpAddr>().is_ok() && to.parse::<IpAddr>().is_ok(),
+        None => is_cidr(value),
+    };
+    require(ok, "ipset entry", value)
+}
+
+// ipset caps timeouts at 2147483 seconds
+fn ipset_timeout(value: u32) -> Result<(), String> {
+    require((1..=2_147_483).contains(&value), "ipset timeout", &value.to_string())
+}
+
+fn mac(value: &str) -> Result<(), String> {
+    let parts: Vec<&str> = value.split(':').collect();
+    let ok = parts.len() == 6 && parts.iter().all(|p| p.len() == 2 && p.chars().all(|c| c.is_ascii_hexdigit()));
+    require(ok, "MAC address", value)
+}
+
+// One path component inside a fixed directory
+fn file_name(value: &str) -> Result<(), String> {
+    let ok = !value.is_empty()
+        && value != "."
+        && value != ".."
+        && !value.starts_with('-')
+        && !value.contains('/')
+        && !value.chars().any(|c| c.is_control());
+    require(ok, "file name", value)
+}
+
+fn scan_path(value: &str) -> Result<(), String> {
+    let ok = value.starts_with('/') && !value.chars().any(|c| c.is_control());
+    require(ok, "scan path", value)
+}
+
+// Rule arguments are free-form, but iptables' --modprobe (-M) runs an arbitrary program as root.
+// getopt_long accepts any unambiguous prefix, so `--mod=...` must be refused too.
+fn iptables_arg(value: &str) -> Result<(), String> {
+    let option = value.split('=').next().unwrap_or_default();
+    let modprobe = (option.len() >= 3 && "--modprobe".starts_with(option))
+        || (value.starts_with('-') && !value.starts_with("--") && value.contains('M'));
+    require(!modprobe && !value.chars().any(|c| c.is_control()), "iptables argument", value)
+}
+
+fn tailscale_flag(action: TailscaleAction, flag: &str) -> Result<(), String> {
+    let ok = match action {
+        TailscaleAction::Up => {
+            flag == "--advertise-exit-node"
+                || flag == "--accept-routes"
+                || flag.strip_prefix("--advertise-routes=").is_some_and(|routes| {
+                    !routes.is_empty() && routes.split(',').all(is_cidr)
+                })
+                || flag.strip_prefix("--hostname=").is_some_and(|name| {
+                    !name.is_empty() && !name.starts_with('-') && name.chars().all(|c| c.is_alphanumeric() || c == '-')
+                })
+        }
+        TailscaleAction::Set => {
+            flag == "--advertise-exit-node"
+                || flag == "--advertise-exit-node=false"
+                || flag == "--exit-node-allow-lan-access"
+                // Empty stops routing through an exit node
+                || flag.strip_prefix("--exit-node=").is_some_and(|node| node.is_empty() || node.parse::<IpAddr>().is_ok())
+        }
+        TailscaleAction::Down | TailscaleAction::Logout => false,
+    };
+    require(ok, "tailscale flag", flag)
+}
+
+// --simple-connect takes key=value pairs joined by commas; values are never quoted
+const CONNECT_KEYS: &[&str] = &["apn", "ip-type", "user", "password", "allowed-auth"];
+
+fn connect_setting(key: &str, value: &str) -> Result<(), String> {
+    let ok = CONNECT_KEYS.contains(&key)
+        && !value.is_empty()
+        && value.len() <= 100
+        && !value.chars().any(|c| c.is_control() || matches!(c, ',' | '=' | '\'' | '"'));
+    require(ok, "connect setting", &format!("{}={}", key, value))
+}
+
+fn sms_number(value: &str) -> Result<(), String> {
+    let digits = value.strip_prefix('+').unwrap_or(value);
+    let ok = !digits.is_empty() && digits.len() <= 20 && digits.chars().all(|c| c.is_ascii_digit());
+    require(ok, "phone number", value)
+}
+
+// Single-quoted inside the --messaging-create-sms argument, so no quotes of its own
+fn sms_text(value: &str) -> Result<(), String> {
+    let ok = !value.is_empty()
+        && value.chars().count() <= 640
+        && !value.chars().any(|c| c == '\'' || (c.is_control() && c != '\n'));
+    require(ok, "message text", value)
+}
+
+// IPv6 rules only ever go into ROUTERUI-* chains or jump to one; the policy stays untouched
+fn ip6tables_allowed(action: IptablesAction, table: Table, args: &[String]) -> bool {
+    let ours = |v: &String| v.starts_with("ROUTERUI-");
+    table == Table::Filter
+        && match action {
+            IptablesAction::List | IptablesAction::ListRules => true,
+            IptablesAction::NewChain | IptablesAction::FlushChain | IptablesAction::DeleteChain => true,
+            IptablesAction::Policy => false,
+            IptablesAction::Check | IptablesAction::Insert | IptablesAction::Append | IptablesAction::Delete => {
+                args.first().is_some_and(ours)
+                    || args.windows(2).last().is_some_and(|w| w[0] == "-j" && ours(&w[1]))
+            }
+        }
+}
+
+fn lines(value: u32) -> Result<(), String> {
+    require((1..=MAX_LINES).contains(&value), "line count", &value.to_string())
+}
+
+impl Privileged {
+    /// Validate every field and build the exact command line to run
+    pub fn argv(&self) -> Result<(&'static str, Vec<String>), String> {
+        let s = |v: &[&str]| v.iter().map(|a| a.to_string()).collect::<Vec<_>>();
+
+        Ok(match self {
+            Privileged::Iptables { ipv6, table, action, args } => {
+                for arg in args {
+                    iptables_arg(arg)?;
+                }
+                if *ipv6 && !ip6tables_allowed(*action, *table, args) {
+                    return Err("ip6tables is only used for reading rules and RouterUI's own chains".to_string());
+                }
+                if *action == IptablesAction::Policy {
+                    let ok = *table == Table::Filter
+                        && args.len() == 2
+                        && args[0] == "INPUT"
+                        && (args[1] == "DROP" || args[1] == "ACCEPT");
+                    require(ok, "policy", &args.join(" "))?;
+                }
+                if matches!(action, IptablesAction::NewChain | IptablesAction::FlushChain | IptablesAction::DeleteChain) {
+                    let ok = args.len() == 1 && args[0].starts_with("ROUTERUI-") && valid_name(&args[0], 28);
+                    require(ok, "chain", &args.join(" "))?;
+                }
+
+                let mut argv = Vec::new();
+                match table {
+                    Table::Nat => argv.extend(s(&["-t", "nat"])),
+                    Table::Mangle => argv.extend(s(&["-t", "mangle"])),
+                    Table::Filter => {}
+                }
+                argv.push(action.flag().to_string());
+                argv.extend(args.iter().cloned());
+                (if *ipv6 { "ip6tables" } else { "iptables" }, argv)
+            }
+            Privileged::IptablesSave { table } => match table {
+                Some(Table::Nat) => ("iptables-save", s(&["-t", "nat"])),
+                Some(Table::Filter) => ("iptables-save", s(&["-t", "filter"])),
+                Some(Table::Mangle) => ("iptables-save", s(&["-t", "mangle"])),
+                None => ("iptables-save", vec![]),
+            },
+            Privileged::IptablesRestore => ("iptables-restore", vec![]),
+            Privileged::Ip6tablesSave => ("ip6tables-save", vec![]),
+            Privileged::Ip6tablesRestore => ("ip6tables-restore", vec![]),
+            Privileged::PersistFirewall => ("netfilter-persistent", s(&["save"])),
+            Privileged::IpsetList { set, terse } => {
+                set_name(set)?;
+                let mut argv = s(&["list", set]);
+                if *terse {
+                    argv.push("-t".to_string());
+                }
+                ("ipset", argv)
+            }
+            Privileged::IpsetCreate { set, timeout } => {
+                set_name(set)?;
+                match timeout {
+                    Some(t) => {
+                        ipset_timeout(*t)?;
+                        ("ipset", s(&["create", set, "hash:net", "timeout", &t.to_string(), "maxelem", "1000000"]))
+                    }
+                    None => ("ipset", s(&["create", set, "hash:net", "maxelem", "1000000"])),
+                }
+            }
+            Privileged::IpsetAdd { set, entry, timeout } => {
+                set_name(set)?;
+                ipset_entry(entry)?;
+                match timeout {
+                    Some(t) => {
+                        ipset_timeout(*t)?;
+                        ("ipset", s(&["add", set, entry, "timeout", &t.to_string(), "-exist"]))
+                    }
+                    None => ("ipset", s(&["add", set, entry, "-exist"])),
+                }
+            }
+            Privileged::IpsetDel { set, entry } => {
+                set_name(set)?;
+                ipset_entry(entry)?;
+                ("ipset", s(&["del", set, entry]))
+            }
+            Privileged::IpsetFlush { set } => {
+                set_name(set)?;
+                ("ipset", s(&["flush", set]))
+            }
+            Privileged::IpsetDestroy { set } => {
+                set_name(set)?;
+                ("ipset", s(&["destroy", set]))
+            }
+            Privileged::Systemctl { action, unit: name } => {
+                unit(name)?;
+                ("systemctl", s(&[action.as_str(), name]))
+            }
+            Privileged::ServiceLog { unit: name, lines: count } => {
+                unit(name)?;
+                lines(*count)?;
+                ("journalctl", s(&["-u", name, "-n", &count.to_string(), "--no-pager", "-o", "short-iso"]))
+            }
+            Privileged::KernelLog { since_hours } => {
+                require((1..=24 * 31).contains(since_hours), "hours", &since_hours.to_string())?;
+                let since = format!("{} hours ago", since_hours);
+                ("journalctl", s(&["-k", "--since", &since, "--no-pager", "-o", "short-iso"]))
+            }
+            Privileged::AuthLogTail { lines: count } => {
+                lines(*count)?;
+                ("tail", vec![format!("-{}", count), AUTH_LOG.to_string()])
+            }
+            Privileged::AuthLogFailures => ("grep", s(&["-E", "sshd.*(Failed|Invalid user)", AUTH_LOG])),
+            Privileged::RouteAdd { destination, gateway, interface: dev } => {
+                cidr(destination)?;
+                require(gateway.parse::<IpAddr>().is_ok(), "gateway", gateway)?;
+                let mut argv = s(&["route", "add", destination, "via", gateway]);
+                if let Some(dev) = dev {
+                    interface(dev)?;
+                    argv.extend(s(&["dev", dev]));
+                }
+                ("ip", argv)
+            }
+            Privileged::RouteDel { destination } => {
+                cidr(destination)?;
+                ("ip", s(&["route", "del", destination]))
+            }
+            Privileged::AddrFlush { interface: dev } => {
+                interface(dev)?;
+                ("ip", s(&["addr", "flush", "dev", dev]))
+            }
+            Privileged::AddrAdd { address, interface: dev } => {
+                require(address.contains('/') && is_cidr(address), "address", address)?;
+                interface(dev)?;
+                ("ip", s(&["addr", "add", address, "dev", dev]))
+            }
+            Privileged::LinkUp { interface: dev } => {
+                interface(dev)?;
+                ("ip", s(&["link", "set", dev, "up"]))
+            }
+            Privileged::LinkDown { interface: dev } => {
+                interface(dev)?;
+                ("ip", s(&["link", "set", dev, "down"]))
+            }
+            Privileged::LinkAddress { interface: dev, mac: address } => {
+                interface(dev)?;
+                mac(address)?;
+                ("ip", s(&["link", "set", dev, "address", address]))
+            }
+            Privileged::VlanAdd { parent, vlan_id } => {
+                interface(parent)?;
+                require((1..=4094).contains(vlan_id), "VLAN ID", &vlan_id.to_string())?;
+                let (name, id) = (format!("{}.{}", parent, vlan_id), vlan_id.to_string());
+                interface(&name)?;
+                ("ip", s(&["link", "add", "link", parent, "name", &name, "type", "vlan", "id", &id]))
+            }
+            Privileged::VlanDel { interface: dev } => {
+                interface(dev)?;
+                let vlan = dev.rsplit_once('.').is_some_and(|(parent, id)| {
+                    !parent.is_empty() && id.parse::<u16>().is_ok_and(|id| (1..=4094).contains(&id))
+                });
+                require(vlan, "VLAN interface", dev)?;
+                ("ip", s(&["link", "del", dev]))
+            }
+            Privileged::AddrDel { address, interface: dev } => {
+                require(address.contains('/') && is_cidr(address), "address", address)?;
+                interface(dev)?;
+                ("ip", s(&["addr", "del", address, "dev", dev]))
+            }
+            Privileged::NetplanApply => ("netplan", s(&["apply"])),
+            Privileged::WakeOnLan { interface: dev, mac: address } => {
+                interface(dev)?;
+                mac(address)?;
+                ("etherwake", s(&["-i", dev, address]))
+            }
+            Privileged::ArpProbe { interface: dev, address } => {
+                interface(dev)?;
+                require(address.parse::<Ipv4Addr>().is_ok(), "IPv4 address", address)?;
+                ("arping", s(&["-c", "2", "-w", "2", "-I", dev, address]))
+            }
+            Privileged::HostapdCli { interface: dev, action } => {
+                interface(dev)?;
+                ("hostapd_cli", s(&["-i", dev, action.as_str()]))
+            }
+            Privileged::ConntrackList => ("conntrack", s(&["-L", "-o", "extended,id"])),
+            Privileged::Shaper { interface: dev, direction, bandwidth_kbit } => {
+                interface(dev)?;
+                require((1..=MAX_SHAPER_KBIT).contains(bandwidth_kbit), "bandwidth", &bandwidth_kbit.to_string())?;
+                let mut argv = s(&["qdisc", "replace", "dev", dev, "root", "cake", "bandwidth", &format!("{}kbit", bandwidth_kbit)]);
+                argv.extend(s(direction.options()));
+                ("tc", argv)
+            }
+            Privileged::ShaperDel { interface: dev } => {
+                interface(dev)?;
+                ("tc", s(&["qdisc", "del", "dev", dev, "root"]))
+            }
+            Privileged::IngressAdd { interface: dev } => {
+                interface(dev)?;
+                ("tc", s(&["qdisc", "add", "dev", dev, "handle", "ffff:", "ingress"]))
+            }
+            Privileged::IngressDel { interface: dev } => {
+                interface(dev)?;
+                ("tc", s(&["qdisc", "del", "dev", dev, "ingress"]))
+            }
+            Privileged::IngressRedirect { interface: dev, ifb: target } => {
+                interface(dev)?;
+                ifb(target)?;
+                ("tc", s(&["filter", "add", "dev", dev, "parent", "ffff:", "matchall", "action", "mirred", "egress", "redirect", "dev", target]))
+            }
+            Privileged::IfbAdd { interface: dev } => {
+                ifb(dev)?;
+                ("ip", s(&["link", "add", "name", dev, "type", "ifb"]))
+            }
+            Privileged::IfbDel { interface: dev } => {
+                ifb(dev)?;
+                ("ip", s(&["link", "del", dev]))
+            }
+            Privileged::Tailscale { action, flags } => {
+                for flag in flags {
+                    tailscale_flag(*action, flag)?;
+                }
+                let mut argv = vec![action.as_str().to_string()];
+                argv.extend(flags.iter().cloned());
+                ("tailscale", argv)
+            }
+            Privileged::ModemSignalSetup { modem } => ("mmcli", vec!["-m".to_string(), modem.to_string(), "--signal-setup=30".to_string()]),
+            Privileged::ModemConnect { modem, settings } => {
+                require(settings.iter().any(|(k, _)| k == "apn"), "connect settings", "missing apn")?;
+                for (key, value) in settings {
+                    connect_setting(key, value)?;
+                }
+                let joined: Vec<String> = settings.iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+                ("mmcli", vec!["-m".to_string(), modem.to_string(), format!("--simple-connect={}", joined.join(","))])
+            }
+            Privileged::ModemDisconnect { modem } => ("mmcli", vec!["-m".to_string(), modem.to_string(), "--simple-disconnect".to_string()]),
+            Privileged::SmsList { modem } => ("mmcli", vec!["-m".to_string(), modem.to_string(), "--messaging-list-sms".to_string(), "-J".to_string()]),
+            Privileged::SmsRead { sms } => ("mmcli", vec!["-s".to_string(), sms.to_string(), "-J".to_string()]),
+            Privileged::SmsCreate { modem, number, text } => {
+                sms_number(number)?;
+                sms_text(text)?;
+                (
+                    "mmcli",
+                    vec![
+                        "-m".to_string(),
+                        modem.to_string(),
+                        format!("--messaging-create-sms=number='{}',text='{}'", number, text),
+                    ],
+                )
+            }
+            Privileged::SmsSend { sms } => ("mmcli", vec!["-s".to_string(), sms.to_string(), "--send".to_string()]),
+            Privileged::SmsDelete { modem, sms } => {
+                ("mmcli", vec!["-m".to_string(), modem.to_string(), format!("--messaging-delete-sms={}", sms)])
+            }
+            Privileged::ModemRoute { add, interface: dev, metric } => {
+                interface(dev)?;
+                let action = if *add { "add" } else { "del" };
+                ("ip", s(&["route", action, "default", "dev", dev, "metric", &metric.to_string()]))
+            }
+            Privileged::Clamscan { path, quarantine } => {
+                scan_path(path)?;
+                let mut argv = s(&["-r", "--infected", "--no-summary"]);
+                if *quarantine {
+                    argv.extend(s(&["--move", QUARANTINE_DIR]));
+                }
+                argv.push(path.clone());
+                ("clamscan", argv)
+            }
+            Privileged::Freshclam => ("freshclam", vec![]),
+            Privileged::QuarantineDelete { file_name: name } => {
+                file_name(name)?;
+                ("rm", vec!["-f".to_string(), format!("{}/{}", QUARANTINE_DIR, name)])
+            }
+            Privileged::QuarantineRestore { file_name: name } => {
+                file_name(name)?;
+                ("mv", vec![format!("{}/{}", QUARANTINE_DIR, name), format!("{}/{}", RESTORED_DIR, name)])
+            }
+            Privileged::AptUpdate => ("apt", s(&["update"])),
+            Privileged::AptUpgrade => ("apt", s(&["upgrade", "-y"])),
+        })
+    }
+
+    /// Only iptables-restore and ip6tables-restore read their input from the caller
+    pub fn accepts_stdin(&self) -> bool {
+        matches!(self, Privileged::IptablesRestore | Privileged::Ip6tablesRestore)
+    }
+
+    /// Map a sudo-style command line onto a typed operation. The result must rebuild exactly
+    /// the same command line, so nothing the caller asked for is silently dropped.
+    pub fn parse(program: &str, args: &[String]) -> Result<Self, String> {
+        let a: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
+        let owned = |v: &[&str]| v.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        let n = |v: &str| v.to_string();
+        let unsupported = || format!("'{} {}' is not a supported privileged operation", program, args.join(" "));
+        let number = |v: &str| v.parse::<u32>().map_err(|_| unsupported());
+
+        let command = match (program, a.as_slice()) {
+            ("iptables" | "ip6tables", rest) => {
+                let (table, rest) = match rest {
+                    ["-t", "nat", rest @ ..] => (Table::Nat, rest),
+                    ["-t", "mangle", rest @ ..] => (Table::Mangle, rest),
+                    rest => (Table::Filter, rest),
+                };
+                let (flag, rest) = rest.split_first().ok_or_else(unsupported)?;
+                let action = IptablesAction::ALL
+                    .into_iter()
+                    .find(|a| a.flag() == *flag)
+                    .ok_or_else(unsupported)?;
+                Privileged::Iptables { ipv6: program == "ip6tables", table, action, args: owned(rest) }
+            }
+            ("iptables-save", []) => Privileged::IptablesSave { table: None },
+            ("iptables-save", ["-t", "nat"]) => Privileged::IptablesSave { table: Some(Table::Nat) },
+            ("iptables-save", ["-t", "filter"]) => Privileged::IptablesSave { table: Some(Table::Filter) },
+            ("iptables-save", ["-t", "mangle"]) => Privileged::IptablesSave { table: Some(Table::Mangle) },
+            ("iptables-restore", []) => Privileged::IptablesRestore,
+            ("ip6tables-save", []) => Privileged::Ip6tablesSave,
+            ("ip6tables-restore", []) => Privileged::Ip6tablesRestore,
+            ("netfilter-persistent", ["save"]) => Privileged::PersistFirewall,
+            ("ipset", ["list", set]) => Privileged::IpsetList { set: n(set), terse: false },
+            ("ipset", ["list", set, "-t"]) => Privileged::IpsetList { set: n(set), terse: true },
+            ("ipset", ["create", set, "hash:net", "maxelem", "1000000"]) => Privileged::IpsetCreate { set: n(set), timeout: None },
+            ("ipset", ["create", set, "hash:net", "timeout", t, "maxelem", "1000000"]) => Privileged::IpsetCreate {
+                set: n(set),
+                timeout: Some(t.parse().map_err(|_| unsupported())?),
+            },
+            ("ipset", ["add", set, entry, "-exist"]) => Privileged::IpsetAdd { set: n(set), entry: n(entry), timeout: None },
+            ("ipset", ["add", set, entry, "timeout", t, "-exist"]) => Privileged::IpsetAdd {
+                set: n(set),
+                entry: n(entry),
+                timeout: Some(t.parse().map_err(|_| unsupported())?),
+            },
+            ("ipset", ["del", set, entry]) => Privileged::IpsetDel { set: n(set), entry: n(entry) },
+            ("ipset", ["flush", set]) => Privileged::IpsetFlush { set: n(set) },
+            ("ipset", ["destroy", set]) => Privileged::IpsetDestroy { set: n(set) },
+            ("systemctl", [action, unit]) => Privileged::Systemctl {
+                action: SystemctlAction::ALL.into_iter().find(|a| a.as_str() == *action).ok_or_else(unsupported)?,
+                unit: n(unit),
+            },
+            ("journalctl", ["-u", unit, "-n", count, "--no-pager", "-o", "short-iso"]) => Privileged::ServiceLog {
+                unit: n(unit),
+                lines: count.parse().map_err(|_| unsupported())?,
+            },
+            ("journalctl", ["-k", "--since", since, "--no-pager", "-o", "short-iso"]) => Privileged::KernelLog {
+                since_hours: since
+                    .strip_suffix(" hours ago")
+                    .and_then(|h| h.parse().ok())
+                    .ok_or_else(unsupported)?,
+            },
+            ("tail", [count, AUTH_LOG]) => Privileged::AuthLogTail {
+                lines: count.strip_prefix('-').and_then(|c| c.parse().ok()).ok_or_else(unsupported)?,
+            },
+            ("grep", ["-E", "sshd.*(Failed|Invalid user)", AUTH_LOG]) => Privileged::AuthLogFailures,
+            ("ip", ["route", "add", destination, "via", gateway]) => {
+                Privileged::RouteAdd { destination: n(destination), gateway: n(gateway), interface: None }
+            }
+            ("ip", ["route", "add", destination, "via", gateway, "dev", dev]) => {
+                Privileged::RouteAdd { destination: n(destination), gateway: n(gateway), interface: Some(n(dev)) }
+            }
+            ("ip", ["route", "del", destination]) => Privileged::RouteDel { destination: n(destination) },
+            ("ip", ["addr", "flush", "dev", dev]) => Privileged::AddrFlush { interface: n(dev) },
+            ("ip", ["addr", "add", address, "dev", dev]) => {
+                Privileged::AddrAdd { address: n(address), interface: n(dev) }
+            }
+            ("ip", ["link", "set", dev, "up"]) => Privileged::LinkUp { interface: n(dev) },
+            ("ip", ["link", "set", dev, "down"]) => Privileged::LinkDown { interface: n(dev) },
+            ("ip", ["link", "set", dev, "address", address]) => {
+                Privileged::LinkAddress { interface: n(dev), mac: n(address) }
+            }
+            ("ip", ["link", "add", "link", parent, "name", _, "type", "vlan", "id", id]) => {
+                Privileged::VlanAdd { parent: n(parent), vlan_id: id.parse().map_err(|_| unsupported())? }
+            }
+            ("ip", ["link", "add", "name", dev, "type", "ifb"]) => Privileged::IfbAdd { interface: n(dev) },
+            ("ip", ["link", "del", dev]) if dev.starts_with("ifb") => Privileged::IfbDel { interface: n(dev) },
+            ("ip", ["link", "del", dev]) => Privileged::VlanDel { interface: n(dev) },
+            ("ip", ["addr", "del", address, "dev", dev]) => {
+                Privileged::AddrDel { address: n(address), interface: n(dev) }
+            }
+            ("netplan", ["apply"]) => Privileged::NetplanApply,
+            ("etherwake", ["-i", dev, address]) => Privileged::WakeOnLan { interface: n(dev), mac: n(address) },
+            ("arping", ["-c", "2", "-w", "2", "-I", dev, address]) => {
+                Privileged::ArpProbe { interface: n(dev), address: n(address) }
+            }
+            ("hostapd_cli", ["-i", dev, action]) => Privileged::HostapdCli {
+                interface: n(dev),
+                action: HostapdAction::ALL.into_iter().find(|a| a.as_str() == *action).ok_or_else(unsupported)?,
+            },
+            ("conntrack", ["-L", "-o", "extended,id"]) => Privileged::ConntrackList,
+            ("tc", ["qdisc", "replace", "dev", dev, "root", "cake", "bandwidth", rate, options @ ..]) => Privileged::Shaper {
+                interface: n(dev),
+                direction: [ShaperDirection::Egress, ShaperDirection::Ingress]
+                    .into_iter()
+                    .find(|d| d.options() == options)
+                    .ok_or_else(unsupported)?,
+                bandwidth_kbit: rate.strip_suffix("kbit").and_then(|r| r.parse().ok()).ok_or_else(unsupported)?,
+            },
+            ("tc", ["qdisc", "del", "dev", dev, "root"]) => Privileged::ShaperDel { interface: n(dev) },
+            ("tc", ["qdisc", "add", "dev", dev, "handle", "ffff:", "ingress"]) => Privileged::IngressAdd { interface: n(dev) },
+            ("tc", ["qdisc", "del", "dev", dev, "ingress"]) => Privileged::IngressDel { interface: n(dev) },
+            ("tc", ["filter", "add", "dev", dev, "parent", "ffff:", "matchall", "action", "mirred", "egress", "redirect", "dev", target]) => {
+                Privileged::IngressRedirect { interface: n(dev), ifb: n(target) }
+            }
+            ("tailscale", [action, flags @ ..]) => Privileged::Tailscale {
+                action: TailscaleAction::ALL.into_iter().find(|a| a.as_str() == *action).ok_or_else(unsupported)?,
+                flags: owned(flags),
+            },
+            ("mmcli", ["-m", modem, "--signal-setup=30"]) => Privileged::ModemSignalSetup { modem: number(modem)? },
+            ("mmcli", ["-m", modem, "--simple-disconnect"]) => Privileged::ModemDisconnect { modem: number(modem)? },
+            ("mmcli", ["-m", modem, "--messaging-list-sms", "-J"]) => Privileged::SmsList { modem: number(modem)? },
+            ("mmcli", ["-s", sms, "-J"]) => Privileged::SmsRead { sms: number(sms)? },
+            ("mmcli", ["-s", sms, "--send"]) => Privileged::SmsSend { sms: number(sms)? },
+            ("mmcli", ["-m", modem, option]) if option.starts_with("--simple-connect=") => Privileged::ModemConnect {
+                modem: number(modem)?,
+                settings: option["--simple-connect=".len()..]
+                    .split(',')
+                    .map(|pair| pair.split_once('=').map(|(k, v)| (n(k), n(v))).ok_or_else(unsupported))
+                    .collect::<Result<_, _>>()?,
+            },
+            ("mmcli", ["-m", modem, option]) if option.starts_with("--messaging-delete-sms=") => Privileged::SmsDelete {
+                modem: number(modem)?,
+                sms: number(&option["--messaging-delete-sms=".len()..])?,
+            },
+            ("mmcli", ["-m", modem, option]) if option.starts_with("--messaging-create-sms=number='") => {
+                let rest = &option["--messaging-create-sms=number='".len()..];
+                let (phone, text) = rest.split_once("',text='").ok_or_else(unsupported)?;
+                Privileged::SmsCreate {
+                    modem: number(modem)?,
+                    number: n(phone),
+                    text: n(text.strip_suffix('\'').ok_or_else(unsupported)?),
+                }
+            }
+            ("ip", ["route", action @ ("add" | "del"), "default", "dev", dev, "metric", metric]) => Privileged::ModemRoute {
+                add: *action == "add",
+                interface: n(dev),
+                metric: number(metric)?,
+            },
+            ("clamscan", ["-r", "--infected", "--no-summary", "--move", QUARANTINE_DIR, path]) => {
+                Privileged::Clamscan { path: n(path), quarantine: true }
+            }
+            ("clamscan", ["-r", "--infected", "--no-summary", path]) => {
+                Privileged::Clamscan { path: n(path), quarantine: false }
+            }
+            ("freshclam", []) => Privileged::Freshclam,
+            ("rm", ["-f", path]) => Privileged::QuarantineDelete { file_name: in_dir(path, QUARANTINE_DIR).ok_or_else(unsupported)? },
+            ("mv", [from, to]) => {
+                let name = in_dir(from, QUARANTINE_DIR).ok_or_else(unsupported)?;
+                if in_dir(to, RESTORED_DIR).as_deref() != Some(name.as_str()) {
+                    return Err(unsupported());
+                }
+                Privileged::QuarantineRestore { file_name: name }
+            }
+            ("apt", ["update"]) => Privileged::AptUpdate,
+            ("apt", ["upgrade", "-y"]) => Privileged::AptUpgrade,
+            _ => return Err(unsupported()),
+        };
+
+        let (built_program, built_args) = command.argv()?;
+        if built_program != program || built_args != args {
+            return Err(unsupported());
+        }
+        Ok(command)
+    }
+}
+
+// `dir/<name>` -> name, for a single path component
+fn in_dir(path: &str, dir: &str) -> Option<String> {
+    let name = path.strip_prefix(dir)?.strip_prefix('/')?;
+    file_name(name).ok()?;
+    Some(name.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(command: &str) -> Result<Privileged, String> {
+        let mut parts = command.split(' ');
+        let program = parts.next().unwrap();
+        let args: Vec<String> = parts.map(|s| s.to_string()).collect();
+        Privileged::parse(program, &args)
+    }
+
+    #[test]
+    fn policy_examples_are_supported() {
+        for command in crate::system::privileges::COMMANDS {
+            let args: Vec<String> = command.example.iter().map(|a| a.to_string()).collect();
+            if let Err(e) = Privileged::parse(command.binary, &args) {
+                panic!("{} {:?}: {}", command.binary, command.example, e);
+            }
+        }
+    }
+
+    #[test]
+    fn quarantine_delete_takes_exactly_one_file() {
+        assert!(parse("rm -f /opt/routerui/quarantine/x").is_ok());
+        assert!(parse("rm -f /opt/routerui/quarantine/x /etc/shadow").is_err());
+        assert!(parse("rm -f -r /").is_err());
+        assert!(parse("rm -f /opt/routerui/quarantine/x -r /").is_err());
+        assert!(parse("rm -f /opt/routerui/quarantine/../../../etc/shadow").is_err());
+        assert!(parse("rm -f /opt/routerui/quarantine/").is_err());
+        assert!(parse("rm -rf /opt/routerui/quarantine/x").is_err());
+    }
+
+    #[test]
+    fn quarantine_restore_stays_in_its_directories() {
+        assert!(parse("mv /opt/routerui/quarantine/x /opt/routerui/restored/x").is_ok());
+        assert!(parse("mv /opt/routerui/quarantine/x /etc/cron.d/x").is_err());
+        assert!(parse("mv /opt/routerui/quarantine/x /opt/routerui/restored/x /etc").is_err());
+        assert!(parse("mv /opt/routerui/quarantine/x /opt/routerui/restored/y").is_err());
+        assert!(parse("mv /etc/shadow /opt/routerui/restored/shadow").is_err());
+
+        let typed = Privileged::QuarantineRestore { file_name: "../../etc/shadow".to_string() };
+        assert!(typed.argv().is_err());
+    }
+
+    #[test]
+    fn systemctl_units_are_names_not_paths() {
+        assert!(parse("systemctl enable dnsmasq").is_ok());
+        assert!(parse("systemctl restart wg-quick@wg0.service").is_ok());
+        assert!(parse("systemctl enable /opt/routerui/config/evil.service").is_err());
+        assert!(parse("systemctl enable --now evil").is_err());
+        assert!(parse("systemctl start a b").is_err());
+        assert!(parse("systemctl link /opt/routerui/evil.service").is_err());
+
+        let typed = Privileged::Systemctl { action: SystemctlAction::Enable, unit: "../evil.service".to_string() };
+        assert!(typed.argv().is_err());
+    }
+
+    #[test]
+    fn clamscan_cannot_move_or_log_elsewhere() {
+        assert!(parse("clamscan -r --infected --no-summary --move /opt/routerui/quarantine /home").is_ok());
+        assert!(parse("clamscan -r --infected --no-summary /home").is_ok());
+        assert!(parse("clamscan --move=/etc /home").is_err());
+        assert!(parse("clamscan -r --infected --no-summary --copy=/etc /home").is_err());
+        assert!(parse("clamscan -r --infected --no-summary --log=/etc/passwd /home").is_err());
+        assert!(parse("clamscan -r --infected --no-summary --move /etc /home").is_err());
+        assert!(parse("clamscan -r --infected --no-summary /home --move=/etc").is_err());
+    }
+
+    #[test]
+    fn tailscale_is_limited_to_known_flags() {
+        assert!(parse("tailscale up --accept-routes --hostname=router").is_ok());
+        assert!(parse("tailscale up --advertise-routes=192.168.1.0/24,10.0.0.0/8").is_ok());
+        assert!(parse("tailscale set --advertise-exit-node=false").is_ok());
+        assert!(parse("tailscale down").is_ok());
+        assert!(parse("tailscale ssh root@host").is_err());
+        assert!(parse("tailscale up --exit-node=100.64.0.1").is_err());
+        assert!(parse("tailscale set --exit-node=100.64.0.1 --exit-node-allow-lan-access").is_ok());
+        assert!(parse("tailscale set --exit-node=").is_ok());
+        assert!(parse("tailscale set --exit-node=evil.example.com").is_err());
+        assert!(parse("tailscale down --accept-routes").is_err());
+        assert!(parse("tailscale serve --bg 22").is_err());
+    }
+
+    #[test]
+    fn mmcli_is_limited_to_modem_operations() {
+        assert!(parse("mmcli -m 0 --simple-connect=apn=internet,ip-type=ipv4v6").is_ok());
+        assert!(parse("mmcli -m 0 --simple-connect=ip-type=ipv4").is_err());
+        assert!(parse("mmcli -m 0 --simple-connect=apn=internet,number=*99#").is_err());
+        assert!(parse("mmcli -m 0 --simple-connect=apn=internet --command=AT+CFUN=0").is_err());
+        assert!(parse("mmcli -m 0 --command=AT+CFUN=0").is_err());
+        assert!(parse("mmcli -m 0 --factory-reset=000000").is_err());
+        assert!(parse("mmcli -m 0 --messaging-list-sms -J").is_ok());
+        assert!(parse("mmcli -m 0 --messaging-delete-sms=3").is_ok());
+        assert!(parse("mmcli -m 0 --messaging-delete-sms=3,4").is_err());
+        assert!(parse("mmcli -s 3 --send").is_ok());
+        assert!(parse("mmcli -m x --simple-disconnect").is_err());
+        assert!(parse("ip route add default dev wwan0 metric 50").is_ok());
+        assert!(parse("ip route add default dev wwan0 metric 50 table 5").is_err());
+
+        let parse_args = |args: &[&str]| Privileged::parse("mmcli", &args.iter().map(|a| a.to_string()).collect::<Vec<_>>());
+        assert!(parse_args(&["-m", "0", "--messaging-create-sms=number='+15551234',text='Balance?'"]).is_ok());
+        assert!(parse_args(&["-m", "0", "--messaging-create-sms=number='+15551234',text='it's'"]).is_err());
+        assert!(parse_args(&["-m", "0", "--messaging-create-sms=number='+1555 --x',text='hi'"]).is_err());
+    }
+
+    #[test]
+    fn iptables_refuses_modprobe() {
+        assert!(parse("iptables -I INPUT 1 -s 192.0.2.1 -j DROP").is_ok());
+        assert!(parse("iptables -L INPUT -n --modprobe=/opt/routerui/evil").is_err());
+        assert!(parse("iptables -L INPUT -n --modp=/opt/routerui/evil").is_err());
+        assert!(parse("iptables -L INPUT -M /opt/routerui/evil").is_err());
+        assert!(parse("iptables -L INPUT -nM /opt/routerui/evil").is_err());
+        assert!(parse("iptables -I INPUT 1 -m set --match-set x src -j DROP").is_ok());
+        assert!(parse("iptables -A ROUTERUI-HONEYPOT -j SET --add-set routerui-honeypot src --exist --timeout 86400").is_ok());
+        assert!(parse("iptables -P INPUT DROP").is_ok());
+        assert!(parse("iptables -P FORWARD ACCEPT").is_err());
+        assert!(parse("iptables -F").is_err());
+        assert!(parse("ip6tables -I INPUT 1 -j ACCEPT").is_err());
+        assert!(parse("ip6tables -I INPUT 1 -i enp1s0 -j ROUTERUI-V6-WAN").is_ok());
+        assert!(parse("ip6tables -A ROUTERUI-V6-WAN -j DROP").is_ok());
+        assert!(parse("ip6tables -I INPUT 1 -j ROUTERUI-V6-WAN -j ACCEPT").is_err());
+        assert!(parse("ip6tables -P INPUT ACCEPT").is_err());
+        assert!(parse("ip6tables -t nat -A ROUTERUI-V6-WAN -j DROP").is_err());
+        assert!(parse("ip6tables -A ROUTERUI-V6-PINHOLE -d 2001:db8::10 -p tcp -m tcp --dport 443 -j ACCEPT").is_ok());
+        assert!(parse("ip6tables -D INPUT -j ROUTERUI-V6-BLOCK").is_ok());
+        assert!(parse("ip6tables -D INPUT -s 2001:db8::1 -j DROP").is_err());
+        assert!(parse("ip6tables-save -t nat").is_err());
+    }
+
+    #[test]
+    fn ipset_fields_are_validated() {
+        assert!(parse("ipset add protection-whitelist 192.0.2.0/24 -exist").is_ok());
+        assert!(parse("ipset add set 192.0.2.1-192.0.2.9 -exist").is_ok());
+        assert!(parse("ipset add set not-an-ip -exist").is_err());
+        assert!(parse("ipset add -file /etc/shadow -exist").is_err());
+        assert!(parse("ipset add set 192.0.2.1").is_err());
+        assert!(parse("ipset restore").is_err());
+    }
+
+    #[test]
+    fn network_fields_are_validated() {
+        assert!(parse("ip route add 10.0.0.0/8 via 192.168.1.254 dev eth0").is_ok());
+        assert!(parse("ip route add 10.0.0.0/8 via 192.168.1.254 dev eth0 table 5").is_err());
+        assert!(parse("ip route add 10.0.0.0/33 via 192.168.1.254").is_err());
+        assert!(parse("ip link set eth0 down").is_ok());
+        assert!(parse("ip link set eth0 address 02:11:22:33:44:55").is_ok());
+        assert!(parse("ip link set eth0 address 02:11:22:33:44").is_err());
+        assert!(parse("ip link set eth0 netns 1").is_err());
+        assert!(parse("ip netns exec x sh").is_err());
+        assert!(parse("etherwake -i enp2s0 00:11:22:33:44:55").is_ok());
+        assert!(parse("etherwake -i enp2s0 -b").is_err());
+        assert!(parse("arping -c 2 -w 2 -I eth0 192.168.1.20").is_ok());
+        assert!(parse("arping -c 2 -w 2 -I eth0 -U 192.168.1.20").is_err());
+        assert!(parse("arping -c 2 -w 2 -I eth0 192.168.1.0/24").is_err());
+        assert!(parse("hostapd_cli -i wlan0 wps_pbc").is_ok());
+        assert!(parse("hostapd_cli -i wlan0_1 reload_wpa_psk").is_ok());
+        assert!(parse("hostapd_cli -i wlan0 wps_pin any 12345670").is_err());
+        assert!(parse("hostapd_cli -i wlan0 wps_ap_pin random").is_err());
+        assert!(parse("hostapd_cli -p /tmp -i wlan0 wps_pbc").is_err());
+        assert!(parse("conntrack -L -o extended,id").is_ok());
+        assert!(parse("conntrack -F").is_err());
+        assert!(parse("conntrack -D -s 192.0.2.1").is_err());
+    }
+
+    #[test]
+    fn only_vlan_links_are_created_or_deleted() {
+        assert!(parse("ip link add link enp2s0 name enp2s0.20 type vlan id 20").is_ok());
+        assert!(parse("ip link add link enp2s0 name evil type vlan id 20").is_err());
+        assert!(parse("ip link add link enp2s0 name enp2s0.0 type vlan id 0").is_err());
+        assert!(parse("ip link add link enp2s0 name enp2s0.5000 type vlan id 5000").is_err());
+        assert!(parse("ip link add link enp2s0 name enp2s0.20 type macvlan id 20").is_err());
+        assert!(parse("ip link del enp2s0.20").is_ok());
+        assert!(parse("ip link del enp2s0").is_err());
+        assert!(parse("ip link del .20").is_err());
+        assert!(parse("ip addr del 10.22.30.1/24 dev enp2s0.20").is_ok());
+        assert!(parse("ip addr del 10.22.30.1 dev enp2s0.20").is_err());
+    }
+
+    #[test]
+    fn tc_is_limited_to_cake_shaping() {
+        assert!(parse("tc qdisc replace dev enp1s0 root cake bandwidth 20000kbit diffserv4 nat dual-srchost").is_ok());
+        assert!(parse("tc qdisc replace dev ifb-routerui root cake bandwidth 100000kbit diffserv4 nat dual-dsthost ingress").is_ok());
+        assert!(parse("tc qdisc replace dev enp1s0 root cake bandwidth 0kbit diffserv4 nat dual-srchost").is_err());
+        assert!(parse("tc qdisc replace dev enp1s0 root cake bandwidth 20mbit diffserv4 nat dual-srchost").is_err());
+        assert!(parse("tc qdisc replace dev enp1s0 root netem delay 500ms").is_err());
+        assert!(parse("tc filter add dev enp1s0 parent ffff: matchall action mirred egress redirect dev ifb-routerui").is_ok());
+        assert!(parse("tc filter add dev enp1s0 parent ffff: matchall action mirred egress redirect dev enp2s0").is_err());
+        assert!(parse("tc -batch /tmp/x").is_err());
+        assert!(parse("ip link add name ifb-routerui type ifb").is_ok());
+        assert!(parse("ip link add name enp2s0 type ifb").is_err());
+        assert!(parse("ip link del ifb-routerui").is_ok());
+        assert!(parse("iptables -t mangle -A ROUTERUI-QOS -s 192.168.1.20 -j DSCP --set-dscp-class EF").is_ok());
+        assert!(parse("iptables -t mangle -P INPUT DROP").is_err());
+    }
+
+    #[test]
+    fn a_field_cannot_smuggle_extra_arguments() {
+        let parse_args = |program: &str, args: &[&str]| {
+            Privileged::parse(program, &args.iter().map(|a| a.to_string()).collect::<Vec<_>>())
+        };
+        assert!(parse_args("rm", &["-f", "/opt/routerui/quarantine/x /etc/shadow"]).is_err());
+        assert!(parse_args("systemctl", &["start", "dnsmasq --now"]).is_err());
+        assert!(parse_args("ipset", &["add", "set", "192.0.2.1 -file", "-exist"]).is_err());
+        assert!(parse_args("ip", &["route", "del", "10.0.0.0/8 table 5"]).is_err());
+
+        // A quarantined file name with a space is still one argument
+        let command = parse_args("rm", &["-f", "/opt/routerui/quarantine/a b"]).unwrap();
+        assert_eq!(command.argv().unwrap().1, vec!["-f", "/opt/routerui/quarantine/a b"]);
+    }
+
+    #[test]
+    fn programs_outside_the_protocol_are_refused() {
+        assert!(parse("bash -c id").is_err());
+        assert!(parse("/usr/sbin/iptables -L").is_err());
+        assert!(parse("apt install evil").is_err());
+        assert!(parse("tee /etc/hostapd/hostapd.conf").is_err());
+    }
+
+    #[test]
+    fn ipset_timeouts_are_numeric_and_bounded() {
+        assert!(parse("ipset create routerui-bruteforce hash:net timeout 3600 maxelem 1000000").is_ok());
+        assert!(parse("ipset add routerui-bruteforce 198.51.100.7 timeout 3600 -exist").is_ok());
+        assert!(parse("ipset add routerui-bruteforce 198.51.100.7 timeout 0 -exist").is_err());
+        assert!(parse("ipset add routerui-bruteforce 198.51.100.7 timeout 99999999 -exist").is_err());
+        assert!(parse("ipset add routerui-bruteforce 198.51.100.7 timeout 0600 -exist").is_err());
+        assert!(parse("ipset add routerui-bruteforce 198.51.100.7 timeout -1 -exist").is_err());
+    }
+
+    #[test]
+    fn only_routerui_chains_can_be_managed() {
+        assert!(parse("iptables -N ROUTERUI-GEO-ALLOW").is_ok());
+        assert!(parse("iptables -F ROUTERUI-GEO-ALLOW").is_ok());
+        assert!(parse("iptables -X ROUTERUI-GEO-ALLOW").is_ok());
+        assert!(parse("iptables -F").is_err());
+        assert!(parse("iptables -F INPUT").is_err());
+        assert!(parse("iptables -X ROUTERUI-A ROUTERUI-B").is_err());
+        assert!(parse("iptables -t nat -F ROUTERUI-GEO-ALLOW").is_ok());
+    }
+}
@@ -0,0 +1,173 @@
+// Protocol and policy for routerui-helper, the small root process that performs privileged
+// operations for the unprivileged web service. One JSON request per connection, one JSON
+// response back; the helper only runs the typed operations in `commands` and only writes
+// files matching WRITABLE_PATHS.
+
+pub mod commands;
+
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::UnixStream;
+use std::path::{Component, Path, PathBuf};
+use std::time::Duration;
+
+pub use commands::Privileged;
+
+// Long-running commands (apt upgrade, clamscan) still have to finish
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+const MAX_REQUEST_BYTES: usize = 16 * 1024 * 1024;
+// Set by sudo() on the `routerui-helper exec` shim so the request ID reaches the helper's log
+pub const REQUEST_ID_ENV: &str = "ROUTERUI_REQUEST_ID";
+
+/// Files the helper may replace. `*` matches within a single path segment.
+pub const WRITABLE_PATHS: &[&str] = &[
+    "/etc/dnsmasq.conf",
+    "/etc/dnsmasq.d/*.conf",
+    "/etc/hostapd/*.conf",
+    "/etc/sysctl.conf",
+    "/etc/sysctl.d/99-routerui.conf",
+    "/etc/sysctl.d/98-routerui-ipv6.conf",
+    "/etc/resolv.conf",
+    "/etc/netplan/99-routerui-lan.yaml",
+    "/etc/netplan/98-routerui-subnets.yaml",
+    "/etc/network/interfaces.d/*",
+    "/etc/systemd/network/10-netplan-*.network.d/routerui-wan.conf",
+    "/etc/iptables/rules.v4",
+    "/proc/sys/net/ipv4/ip_forward",
+    "/proc/sys/net/ipv6/conf/*/accept_ra",
+    "/proc/sys/net/ipv6/conf/*/use_tempaddr",
+];
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum Request {
+    Ping,
+    // Would this operation be accepted? Used for the privileges report
+    Check { command: Privileged },
+    Run {
+        command: Privileged,
+        #[serde(default)]
+        stdin: Option<String>,
+        // API request that caused this, for the helper's log
+        #[serde(default)]
+        request_id: Option<String>,
+    },
+    // `dry_run` only checks the path against the policy
+    WriteFile {
+        path: PathBuf,
+        contents: String,
+        #[serde(default)]
+        dry_run: bool,
+        #[serde(default)]
+        request_id: Option<String>,
+    },
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Response {
+    pub ok: bool,
+    pub status: Option<i32>,
+    #[serde(default)]
+    pub stdout: String,
+    #[serde(default)]
+    pub stderr: String,
+    pub error: Option<String>,
+}
+
+impl Response {
+    pub fn denied(reason: String) -> Self {
+        Response { ok: false, error: Some(reason), ..Default::default() }
+    }
+}
+
+// ============ POLICY ============
+
+// Glob match where `*` never crosses a '/'; `\*` is a literal star
+fn path_match(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern.first() {
+        None => text.is_empty(),
+        Some(b'\\') if pattern.len() > 1 => {
+            text.first() == Some(&pattern[1]) && path_match(&pattern[2..], &text[1..])
+        }
+        Some(b'*') => {
+            (0..=text.len())
+                .take_while(|&i| i == 0 || text[i - 1] != b'/')
+                .any(|i| path_match(&pattern[1..], &text[i..]))
+        }
+        Some(c) => text.first() == Some(c) && path_match(&pattern[1..], &text[1..]),
+    }
+}
+
+/// Validate a typed operation; the error says why it was refused
+pub fn command_allowed(command: &Privileged) -> Result<(), String> {
+    command
+        .argv()
+        .map(|_| ())
+        .map_err(|e| format!("{} - not allowed by helper policy", e))
+}
+
+pub fn write_allowed(path: &Path) -> Result<(), String> {
+    let clean = path.is_absolute()
+        && path.components().all(|c| matches!(c, Component::RootDir | Component::Normal(_)));
+    let text = path.to_string_lossy();
+
+    if clean && WRITABLE_PATHS.iter().any(|p| path_match(p.as_bytes(), text.as_bytes())) {
+        Ok(())
+    } else {
+        Err(format!("writing {} is not allowed by helper policy", path.display()))
+    }
+}
+
+// ============ CLIENT ============
+
+pub fn socket_path() -> &'static Path {
+    &crate::config::get().helper_socket
+}
+
+/// The helper is used whenever its socket is present; otherwise we fall back to sudo
+pub fn is_available() -> bool {
+    socket_path().exists()
+}
+
+pub fn call(request: &Request) -> std::io::Result<Response> {
+    let mut stream = UnixStream::connect(socket_path())?;
+    stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+
+    let mut line = serde_json::to_vec(request)?;
+    line.push(b'\n');
+    stream.write_all(&line)?;
+
+    let mut reader = BufReader::new(stream);
+    let mut response = String::new();
+    reader.read_line(&mut response)?;
+    serde_json::from_str(&response).map_err(std::io::Error::other)
+}
+
+/// Read one request line from a connection, refusing oversized payloads
+pub fn read_request(reader: impl BufRead) -> Result<Request, String> {
+    let mut buf = Vec::new();
+    let mut limited = reader.take(MAX_REQUEST_BYTES as u64);
+    limited.read_until(b'\n', &mut buf).map_err(|e| e.to_string())?;
+    if buf.len() >= MAX_REQUEST_BYTES {
+        return Err("Request too large".to_string());
+    }
+    serde_json::from_slice(&buf).map_err(|e| format!("Invalid request: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_are_limited_to_listed_files() {
+        assert!(write_allowed(Path::new("/etc/dnsmasq.d/router.conf")).is_ok());
+        assert!(write_allowed(Path::new("/etc/hostapd/hostapd.conf")).is_ok());
+        assert!(write_allowed(Path::new("/etc/systemd/network/10-netplan-enp1s0.network.d/routerui-wan.conf")).is_ok());
+        assert!(write_allowed(Path::new("/etc/systemd/network/10-netplan-enp1s0.network.d/other.conf")).is_err());
+        assert!(write_allowed(Path::new("/etc/dnsmasq.d/../sudoers.d/x.conf")).is_err());
+        assert!(write_allowed(Path::new("/etc/dnsmasq.d/sub/x.conf")).is_err());
+        assert!(write_allowed(Path::new("/etc/dnsmasq.d/x.conf/../../shadow")).is_err());
+        assert!(write_allowed(Path::new("etc/dnsmasq.conf")).is_err());
+        assert!(write_allowed(Path::new("/etc/sudoers")).is_err());
+    }
+}
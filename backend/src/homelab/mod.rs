@@ -0,0 +1,326 @@
+// Other Linux machines on the LAN (NAS, media server, ...) shown and controlled from RouterUI.
+// RouterUI logs in over SSH with the key it uses for the mesh APs and only ever runs a fixed set
+// of commands: power off, reboot and a disk usage query. Hosts with a MAC address can be woken,
+// after which RouterUI watches for SSH to come up so a failed wake doesn't go unnoticed.
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::events::Event;
+use crate::AppState;
+
+const SSH_TIMEOUT: Duration = Duration::from_secs(30);
+// How often every host's SSH port is probed for the online indicator
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+const PROBE_TIMEOUT: Duration = Duration::from_secs(3);
+// A woken host that doesn't answer on SSH within this long is reported as not having come up
+const WAKE_TIMEOUT: Duration = Duration::from_secs(180);
+const WAKE_POLL: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct Host {
+    pub id: String,
+    pub name: String,
+    pub host: String,
+    pub port: i64,
+    pub username: String,
+    pub mac_address: Option<String>,
+    pub os: Option<String>,
+    pub created_at: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    Shutdown,
+    Reboot,
+    DiskUsage,
+}
+
+impl Action {
+    fn command(self) -> String {
+        match self {
+            Action::Shutdown => privileged("systemctl poweroff"),
+            Action::Reboot => privileged("systemctl reboot"),
+            Action::DiskUsage => "df -P -k -x tmpfs -x devtmpfs -x overlay -x squashfs".to_string(),
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Action::Shutdown => "shutdown",
+            Action::Reboot => "reboot",
+            Action::DiskUsage => "disk_usage",
+        }
+    }
+}
+
+// Directly as root, otherwise through passwordless sudo for that one command
+fn privileged(command: &str) -> String {
+    format!("if [ \"$(id -u)\" = 0 ]; then {0}; else sudo -n {0}; fi", command)
+}
+
+/// Run one of our fixed commands on the host, returning its stdout
+async fn run(host: &Host, command: &str, power: bool) -> Result<String, String> {
+    crate::mesh::public_key().await?;
+
+    let output = crate::mesh::ssh(&host.host, host.port, &host.username)
+        .arg(command)
+        .kill_on_drop(true)
+        .output();
+    let output = tokio::time::timeout(SSH_TIMEOUT, output)
+        .await
+        .map_err(|_| format!("{} did not respond within {}s", host.host, SSH_TIMEOUT.as_secs()))?
+        .map_err(|e| format!("ssh: {}", e))?;
+
+    let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+    // Powering off or rebooting can take the connection down before ssh sees the exit status
+    let dropped = power && output.status.code() == Some(255) && stderr.contains("closed by remote host");
+    if !output.status.success() && !dropped {
+        return Err(if stderr.is_empty() { format!("exit status {}", output.status) } else { stderr });
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Disk {
+    pub filesystem: String,
+    pub mount: String,
+    pub size_kb: u64,
+    pub used_kb: u64,
+    pub available_kb: u64,
+    pub percent: u8,
+}
+
+// `df -P -k` output; mount points may contain spaces
+fn parse_df(output: &str) -> Vec<Disk> {
+    output
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let parts: Vec<&str> = line.split_whitespace().collect();
+            if parts.len() < 6 {
+                return None;
+            }
+            Some(Disk {
+                filesystem: parts[0].to_string(),
+                mount: parts[5..].join(" "),
+                size_kb: parts[1].parse().ok()?,
+                used_kb: parts[2].parse().ok()?,
+                available_kb: parts[3].parse().ok()?,
+                percent: parts[4].trim_end_matches('%').parse().ok()?,
+            })
+        })
+        .collect()
+}
+
+/// Whether the host's SSH port accepts connections
+async fn probe(host: &Host) -> bool {
+    let Ok(port) = u16::try_from(host.port) else { return false };
+    let connect = tokio::net::TcpStream::connect((host.host.as_str(), port));
+    matches!(tokio::time::timeout(PROBE_TIMEOUT, connect).await, Ok(Ok(_)))
+}
+
+// ============ STORAGE ============
+
+pub async fn list(pool: &SqlitePool) -> Result<Vec<Host>, sqlx::Error> {
+    sqlx::query_as("SELECT * FROM homelab_hosts ORDER BY name").fetch_all(pool).await
+}
+
+pub async fn get(pool: &SqlitePool, id: &str) -> Result<Option<Host>, sqlx::Error> {
+    sqlx::query_as("SELECT * FROM homelab_hosts WHERE id = ?").bind(id).fetch_optional(pool).await
+}
+
+/// Check that RouterUI can log in, then store the host
+pub async fn add(pool: &SqlitePool, mut host: Host) -> Result<Host, String> {
+    let os = run(&host, "uname -sr", false)
+        .await
+        .map_err(|e| format!("Could not log in to {}: {}", host.host, e))?;
+    host.os = Some(os.trim().to_string()).filter(|os| !os.is_empty());
+
+    sqlx::query(
+        "INSERT INTO homelab_hosts (id, name, host, port, username, mac_address, os, created_at)
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+    )
+    .bind(&host.id)
+    .bind(&host.name)
+    .bind(&host.host)
+    .bind(host.port)
+    .bind(&host.username)
+    .bind(&host.mac_address)
+    .bind(&host.os)
+    .bind(&host.created_at)
+    .execute(pool)
+    .await
+    .map_err(|e| match e {
+        sqlx::Error::Database(db) if db.is_unique_violation() => format!("{}:{} is already added", host.host, host.port),
+        e => e.to_string(),
+    })?;
+    Ok(host)
+}
+
+pub async fn remove(pool: &SqlitePool, id: &str) -> Result<bool, sqlx::Error> {
+    let Some(host) = get(pool, id).await? else { return Ok(false) };
+    sqlx::query("DELETE FROM homelab_hosts WHERE id = ?").bind(id).execute(pool).await?;
+    crate::mesh::forget_host_key(&host.host, host.port).await;
+    Ok(true)
+}
+
+// ============ STATUS ============
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WakeResult {
+    Waiting,
+    Up,
+    TimedOut,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WakeCheck {
+    pub sent_at: String,
+    pub result: WakeResult,
+    // How long SSH took to come up
+    pub after_secs: Option<u64>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct HostState {
+    // None until the first probe
+    pub online: Option<bool>,
+    pub last_checked: Option<String>,
+    // From the last disk usage query
+    pub disks: Option<Vec<Disk>>,
+    pub disks_checked: Option<String>,
+    pub wake: Option<WakeCheck>,
+}
+
+/// What is known about each host between requests
+#[derive(Default)]
+pub struct HostTracker {
+    hosts: Mutex<HashMap<String, HostState>>,
+}
+
+impl HostTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, id: &str) -> HostState {
+        self.hosts.lock().unwrap().get(id).cloned().unwrap_or_default()
+    }
+
+    fn update(&self, id: &str, change: impl FnOnce(&mut HostState)) {
+        change(self.hosts.lock().unwrap().entry(id.to_string()).or_default());
+    }
+
+    fn set_online(&self, id: &str, online: bool) {
+        self.update(id, |s| {
+            s.online = Some(online);
+            s.last_checked = Some(Utc::now().to_rfc3339());
+        });
+    }
+}
+
+/// Run `action` on the host; a disk usage query is also kept for the host list
+pub async fn act(state: &Arc<AppState>, host: &Host, action: Action) -> Result<Option<Vec<Disk>>, String> {
+    let power = matches!(action, Action::Shutdown | Action::Reboot);
+    let output = run(host, &action.command(), power).await?;
+    if action != Action::DiskUsage {
+        return Ok(None);
+    }
+
+    let disks = parse_df(&output);
+    let cached = disks.clone();
+    state.homelab.update(&host.id, |s| {
+        s.disks = Some(cached);
+        s.disks_checked = Some(Utc::now().to_rfc3339());
+    });
+    state.homelab.set_online(&host.id, true);
+    Ok(Some(disks))
+}
+
+/// Send a magic packet for the host and watch for it to come up. The wake is recorded in the
+/// Wake-on-LAN history like any other. False when the host is already up and nothing was sent.
+pub async fn wake(state: &Arc<AppState>, host: &Host, username: &str, source_ip: IpAddr) -> Result<bool, String> {
+    let mac = host.mac_address.clone().ok_or("This host has no MAC address to wake it with")?;
+    if probe(host).await {
+        state.homelab.set_online(&host.id, true);
+        return Ok(false);
+    }
+
+    let origin = crate::wol::origin(&state.db, source_ip).await;
+    let mut record = crate::wol::Wake::new(&mac, Some(&host.name), username, source_ip, origin);
+    if origin == crate::wol::Origin::Tailscale {
+        record.tailscale_identity = crate::wol::tailscale_identity(source_ip).await;
+    }
+    record.interface = crate::interfaces::lan_interface(&state.db).await;
+
+    let (interface, target) = (record.interface.clone(), mac.clone());
+    let sent = tokio::task::spawn_blocking(move || crate::wol::send(&interface, &target))
+        .await
+        .map_err(|e| e.to_string())
+        .and_then(|r| r);
+    record.success = sent.is_ok();
+    record.error = sent.as_ref().err().cloned();
+    if let Err(e) = crate::wol::record(&state.db, &record).await {
+        tracing::warn!("Could not record the wake of {}: {}", host.name, e);
+    }
+    sent?;
+
+    let sent_at = Instant::now();
+    state.homelab.update(&host.id, |s| {
+        s.wake = Some(WakeCheck { sent_at: record.woken_at.clone(), result: WakeResult::Waiting, after_secs: None });
+    });
+
+    let (state, host) = (state.clone(), host.clone());
+    tokio::spawn(async move {
+        while sent_at.elapsed() < WAKE_TIMEOUT {
+            tokio::time::sleep(WAKE_POLL).await;
+            if probe(&host).await {
+                let after = sent_at.elapsed().as_secs();
+                tracing::info!("{} came up {}s after being woken", host.name, after);
+                state.homelab.set_online(&host.id, true);
+                state.homelab.update(&host.id, |s| {
+                    if let Some(wake) = &mut s.wake {
+                        wake.result = WakeResult::Up;
+                        wake.after_secs = Some(after);
+                    }
+                });
+                return;
+            }
+        }
+        state.homelab.update(&host.id, |s| {
+            if let Some(wake) = &mut s.wake {
+                wake.result = WakeResult::TimedOut;
+            }
+        });
+        state.events.emit(Event::HostWakeFailed {
+            id: host.id.clone(),
+            name: host.name.clone(),
+            waited_secs: WAKE_TIMEOUT.as_secs(),
+        });
+    });
+    Ok(true)
+}
+
+/// Probe every host's SSH port for the online indicator
+pub fn spawn(state: Arc<AppState>) {
+    tokio::spawn(async move {
+        loop {
+            state.tasks.beat("homelab", CHECK_INTERVAL);
+            let hosts = list(&state.db).await.unwrap_or_default();
+            state.homelab.hosts.lock().unwrap().retain(|id, _| hosts.iter().any(|h| &h.id == id));
+            for host in &hosts {
+                let online = probe(host).await;
+                state.homelab.set_online(&host.id, online);
+            }
+            tokio::time::sleep(CHECK_INTERVAL).await;
+        }
+    });
+}
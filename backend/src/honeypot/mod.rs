@@ -0,0 +1,345 @@
+// Decoy ports: nothing on the router listens on them, so anyone connecting from the WAN is
+// scanning. The kernel bans the source itself (iptables SET target) on the first SYN; a watcher
+// picks the new members of the ban set up for the protection page and notifications.
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashSet, VecDeque};
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::events::Event;
+use crate::system::commands::RecordedCommand;
+use crate::system::privileges::sudo;
+use crate::AppState;
+
+const SETTINGS_FILE: &str = "/opt/routerui/honeypot.json";
+/// ipset holding banned scanners; entries expire on their own
+pub const SET_NAME: &str = "routerui-honeypot";
+const CHAIN: &str = "ROUTERUI-HONEYPOT";
+const WHITELIST_SET: &str = "protection-whitelist";
+// Shows up in the protection blocked log with "honeypot" as the reason
+const LOG_PREFIX: &str = "BLOCKED:honeypot: ";
+const POLL_INTERVAL: Duration = Duration::from_secs(30);
+// iptables multiport takes at most 15 ports
+const MAX_PORTS: usize = 15;
+// ipset caps timeouts at 2147483 seconds
+const MAX_BAN_HOURS: u32 = 596;
+const RECENT_BANS: usize = 50;
+
+// ============ SETTINGS ============
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HoneypotSettings {
+    #[serde(default)]
+    pub enabled: bool,
+    // TCP ports to open as decoys on the WAN interface
+    #[serde(default = "default_ports")]
+    pub ports: Vec<u16>,
+    #[serde(default = "default_ban_hours")]
+    pub ban_hours: u32,
+}
+
+fn default_ports() -> Vec<u16> {
+    vec![23, 2323]
+}
+
+fn default_ban_hours() -> u32 {
+    24
+}
+
+impl Default for HoneypotSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            ports: default_ports(),
+            ban_hours: default_ban_hours(),
+        }
+    }
+}
+
+pub fn load_settings() -> HoneypotSettings {
+    std::fs::read_to_string(SETTINGS_FILE)
+        .ok()
+        .and_then(|c| serde_json::from_str(&c).ok())
+        .unwrap_or_default()
+}
+
+pub fn save_settings(settings: &HoneypotSettings) -> Result<(), String> {
+    let json = serde_json::to_string_pretty(settings).map_err(|e| e.to_string())?;
+    std::fs::write(SETTINGS_FILE, json).map_err(|e| e.to_string())
+}
+
+impl HoneypotSettings {
+    pub fn normalize(&mut self) -> Result<(), String> {
+        self.ports.sort_unstable();
+        self.ports.dedup();
+        if self.ports.contains(&0) {
+            return Err("Port 0 can't be used".to_string());
+        }
+        if self.enabled && self.ports.is_empty() {
+            return Err("Pick at least one decoy port".to_string());
+        }
+        if self.ports.len() > MAX_PORTS {
+            return Err(format!("At most {} decoy ports are supported", MAX_PORTS));
+        }
+        if !(1..=MAX_BAN_HOURS).contains(&self.ban_hours) {
+            return Err(format!("Ban duration must be between 1 and {} hours", MAX_BAN_HOURS));
+        }
+        Ok(())
+    }
+
+    fn ban_seconds(&self) -> u32 {
+        self.ban_hours * 60 * 60
+    }
+}
+
+// ============ FIREWALL ============
+
+fn run(command: &mut RecordedCommand) -> Result<(), String> {
+    let output = command.output().map_err(|e| e.to_string())?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(String::from_utf8_lossy(&output.stderr).trim().to_string())
+    }
+}
+
+fn succeeds(command: &mut RecordedCommand) -> bool {
+    command.output().map(|o| o.status.success()).unwrap_or(false)
+}
+
+fn set_exists() -> bool {
+    succeeds(sudo().args(["ipset", "list", SET_NAME, "-t"]))
+}
+
+fn chain_exists() -> bool {
+    succeeds(sudo().args(["iptables", "-S", CHAIN]))
+}
+
+fn ban_rule() -> [&'static str; 7] {
+    ["-m", "set", "--match-set", SET_NAME, "src", "-j", "DROP"]
+}
+
+fn log_rule() -> [&'static str; 10] {
+    ["-m", "limit", "--limit", "30/min", "-j", "LOG", "--log-prefix", LOG_PREFIX, "--log-level", "4"]
+}
+
+// New TCP connections to a decoy port from the WAN go to our chain
+fn jump_rule(wan: &str, ports: &[u16]) -> Vec<String> {
+    let ports = ports.iter().map(|p| p.to_string()).collect::<Vec<_>>().join(",");
+    ["-i", wan, "-p", "tcp", "-m", "multiport", "--dports", &ports, "-m", "conntrack", "--ctstate", "NEW", "-j", CHAIN]
+        .iter()
+        .map(|a| a.to_string())
+        .collect()
+}
+
+// Jumps are removed by reading them back, since the ports may have changed since they were added
+fn remove_jumps() {
+    let Ok(output) = sudo().args(["iptables", "-S", "INPUT"]).output() else { return };
+    let suffix = format!("-j {}", CHAIN);
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        let Some(rule) = line.strip_prefix("-A INPUT ") else { continue };
+        if rule.ends_with(&suffix) {
+            let _ = sudo().args(["iptables", "-D", "INPUT"]).args(rule.split_whitespace()).output();
+        }
+    }
+}
+
+fn remove_rules() {
+    remove_jumps();
+    for chain in ["INPUT", "FORWARD"] {
+        let _ = sudo().args(["iptables", "-D", chain]).args(ban_rule()).output();
+    }
+    if chain_exists() {
+        let _ = sudo().args(["iptables", "-F", CHAIN]).output();
+        let _ = sudo().args(["iptables", "-X", CHAIN]).output();
+    }
+}
+
+/// Put the firewall in line with the settings: decoy ports on the WAN when enabled, nothing
+/// (and no bans) when not. Saved so it survives a reboot.
+pub fn apply(wan: &str, settings: &HoneypotSettings) -> Result<(), String> {
+    remove_rules();
+
+    if !settings.enabled {
+        if set_exists() {
+            run(sudo().args(["ipset", "destroy", SET_NAME]))?;
+        }
+        return run(sudo().args(["netfilter-persistent", "save"]));
+    }
+
+    let ban_seconds = settings.ban_seconds().to_string();
+    if !set_exists() {
+        run(sudo().args(["ipset", "create", SET_NAME, "hash:net", "timeout", &ban_seconds, "maxelem", "1000000"]))?;
+    }
+    crate::api::protection::create_ipset(WHITELIST_SET).map_err(|(_, e)| e)?;
+
+    run(sudo().args(["iptables", "-N", CHAIN]))?;
+    run(sudo().args(["iptables", "-A", CHAIN, "-m", "set", "--match-set", WHITELIST_SET, "src", "-j", "RETURN"]))?;
+    if crate::privacy::policy().blocked_traffic_log {
+        run(sudo().args(["iptables", "-A", CHAIN]).args(log_rule()))?;
+    }
+    run(sudo().args(["iptables", "-A", CHAIN, "-j", "SET", "--add-set", SET_NAME, "src", "--exist", "--timeout", &ban_seconds]))?;
+    run(sudo().args(["iptables", "-A", CHAIN, "-j", "DROP"]))?;
+
+    run(sudo().args(["iptables", "-I", "INPUT", "1"]).args(jump_rule(wan, &settings.ports)))?;
+    // Banned scanners lose access to port forwards too, not just the router
+    for chain in ["INPUT", "FORWARD"] {
+        run(sudo().args(["iptables", "-I", chain, "1"]).args(ban_rule()))?;
+    }
+
+    run(sudo().args(["netfilter-persistent", "save"]))
+}
+
+/// Add or remove the LOG rule along with the privacy profile, keeping the bans
+pub fn set_logging(enabled: bool) -> Result<(), String> {
+    if !chain_exists() {
+        return Ok(());
+    }
+    let logged = succeeds(sudo().args(["iptables", "-C", CHAIN]).args(log_rule()));
+    match (enabled, logged) {
+        // After the whitelist RETURN
+        (true, false) => run(sudo().args(["iptables", "-I", CHAIN, "2"]).args(log_rule())),
+        (false, true) => run(sudo().args(["iptables", "-D", CHAIN]).args(log_rule())),
+        _ => Ok(()),
+    }
+}
+
+/// Drop the chain, its rules and the ban set (factory reset)
+pub fn teardown() -> Result<(), String> {
+    remove_rules();
+    if set_exists() {
+        run(sudo().args(["ipset", "destroy", SET_NAME]))?;
+    }
+    Ok(())
+}
+
+// ============ BANS ============
+
+/// Address in the ban set and how long until it is let back in
+#[derive(Debug, Serialize)]
+pub struct ActiveBan {
+    pub ip: String,
+    pub country: Option<String>,
+    pub expires_in: u32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HoneypotBan {
+    pub ip: String,
+    pub country: Option<String>,
+    pub banned_at: String,
+    pub expires_at: String,
+}
+
+/// Addresses currently banned, read back from the ipset
+pub fn active_bans() -> Vec<ActiveBan> {
+    let output = match sudo().args(["ipset", "list", SET_NAME]).output() {
+        Ok(o) if o.status.success() => o,
+        _ => return Vec::new(),
+    };
+
+    // Members look like "198.51.100.7 timeout 3412"
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .skip_while(|line| !line.starts_with("Members:"))
+        .skip(1)
+        .filter_map(|line| {
+            let mut parts = line.split_whitespace();
+            let ip = parts.next()?.to_string();
+            let expires_in = match (parts.next(), parts.next()) {
+                (Some("timeout"), Some(secs)) => secs.parse().unwrap_or(0),
+                _ => 0,
+            };
+            let country = crate::geoip::country(&ip);
+            Some(ActiveBan { ip, country, expires_in })
+        })
+        .collect()
+}
+
+/// Let an address back in before its ban expires
+pub fn unban(ip: &str) -> Result<(), String> {
+    let ip: IpAddr = ip.parse().map_err(|_| format!("Invalid IP address: {}", ip))?;
+    run(sudo().args(["ipset", "del", SET_NAME, &ip.to_string()]))
+}
+
+/// Remembers which bans were already reported
+#[derive(Default)]
+pub struct HoneypotTracker {
+    // None until the first poll, whose bans predate this run and aren't reported again
+    known: Mutex<Option<HashSet<String>>>,
+    recent: Mutex<VecDeque<HoneypotBan>>,
+}
+
+impl HoneypotTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Bans made since startup, newest first
+    pub fn recent_bans(&self) -> Vec<HoneypotBan> {
+        self.recent.lock().unwrap().iter().cloned().collect()
+    }
+}
+
+async fn poll(state: &AppState) {
+    let active = tokio::task::spawn_blocking(active_bans).await.unwrap_or_default();
+    let current: HashSet<String> = active.iter().map(|b| b.ip.clone()).collect();
+
+    let new: Vec<ActiveBan> = {
+        let mut known = state.honeypot.known.lock().unwrap();
+        let new = match known.as_ref() {
+            Some(known) => active.into_iter().filter(|b| !known.contains(&b.ip)).collect(),
+            None => Vec::new(),
+        };
+        *known = Some(current);
+        new
+    };
+
+    let now = Utc::now();
+    for ban in new {
+        let expires_at = (now + chrono::Duration::seconds(ban.expires_in as i64)).to_rfc3339();
+        tracing::warn!("Honeypot banned {}", ban.ip);
+        {
+            let mut recent = state.honeypot.recent.lock().unwrap();
+            recent.push_front(HoneypotBan {
+                ip: ban.ip.clone(),
+                country: ban.country.clone(),
+                banned_at: now.to_rfc3339(),
+                expires_at: expires_at.clone(),
+            });
+            recent.truncate(RECENT_BANS);
+        }
+        state.events.emit(Event::HoneypotBanned {
+            ip: ban.ip,
+            country: ban.country,
+            expires_at,
+        });
+    }
+}
+
+pub fn spawn(state: Arc<AppState>) {
+    tokio::spawn(async move {
+        // The ban set doesn't survive a reboot, and the saved rules can't be restored without it
+        let settings = load_settings();
+        if settings.enabled {
+            let wan = crate::wan::wan_interface(&state.db).await;
+            let result = tokio::task::spawn_blocking(move || apply(&wan, &settings)).await;
+            match result {
+                Ok(Err(e)) => tracing::warn!("Could not set up the honeypot ports: {}", e),
+                Err(e) => tracing::warn!("Could not set up the honeypot ports: {}", e),
+                Ok(Ok(())) => {}
+            }
+        }
+
+        loop {
+            state.tasks.beat("honeypot", POLL_INTERVAL);
+            if load_settings().enabled {
+                poll(&state).await;
+            }
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    });
+}
@@ -0,0 +1,429 @@
+// Incident reports: everything the router knows about one remote address over a time window,
+// in a form that can be handed to an ISP's abuse desk or an employer. The timeline is rebuilt
+// from the kernel log (firewall, honeypot and port-forward hits), failed SSH logins and
+// RouterUI's own login blocks; GeoIP, blocklist reputation and RDAP whois data are attached.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use std::net::IpAddr;
+use std::time::Duration;
+
+use crate::system::privileges::sudo;
+use crate::AppState;
+
+const AUTH_LOG: &str = "/var/log/auth.log";
+const RDAP_URL: &str = "https://rdap.org/ip";
+const RDAP_TIMEOUT: Duration = Duration::from_secs(10);
+// Oldest entries beyond this are left out, and counted in `omitted`
+const MAX_TIMELINE: usize = 500;
+pub const MAX_HOURS: u32 = 24 * 31;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TimelineEntry {
+    pub timestamp: String,
+    // firewall, honeypot, port_forward, ssh or routerui
+    pub source: String,
+    pub summary: String,
+    // Raw log line the entry was read from, if any
+    pub log_line: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Whois {
+    pub network: Option<String>,
+    pub handle: Option<String>,
+    pub range: Option<String>,
+    pub country: Option<String>,
+    pub abuse_email: Option<String>,
+    pub source: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReputationSummary {
+    pub score: u32,
+    pub summary: String,
+    pub feeds: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct IncidentSummary {
+    pub firewall_hits: usize,
+    pub ports: Vec<u16>,
+    pub ssh_failures: usize,
+    pub first_seen: Option<String>,
+    pub last_seen: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IncidentReport {
+    pub ip: String,
+    pub generated_at: String,
+    pub hostname: String,
+    pub hours: u32,
+    pub since: String,
+    pub country: Option<String>,
+    pub reputation: Option<ReputationSummary>,
+    pub whois: Option<Whois>,
+    pub whois_error: Option<String>,
+    pub summary: IncidentSummary,
+    pub timeline: Vec<TimelineEntry>,
+    pub omitted: usize,
+}
+
+// ============ LOG SOURCES ============
+
+fn field<'a>(line: &'a str, name: &str) -> Option<&'a str> {
+    line.split_whitespace().find_map(|p| p.strip_prefix(name))
+}
+
+// journalctl short-iso ("2026-01-18T10:30:00+0000") and rsyslog's RFC 3339 timestamps
+fn parse_timestamp(value: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(value)
+        .or_else(|_| DateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S%z"))
+        .ok()
+        .map(|t| t.with_timezone(&Utc))
+}
+
+fn kernel_entry(line: &str) -> Option<(TimelineEntry, Option<u16>)> {
+    let timestamp = parse_timestamp(line.split_whitespace().next()?)?;
+    let proto = field(line, "PROTO=").unwrap_or("?");
+    let port: Option<u16> = field(line, "DPT=").and_then(|p| p.parse().ok());
+    let target = port.map(|p| format!("{} port {}", proto, p)).unwrap_or_else(|| proto.to_string());
+
+    let (source, summary) = if line.contains("BLOCKED:honeypot:") {
+        ("honeypot", format!("Connected to decoy {} and was banned", target))
+    } else if let Some(start) = line.find("BLOCKED:") {
+        let reason = line[start + 8..].split(':').next().unwrap_or("firewall");
+        ("firewall", format!("Dropped {} ({})", target, reason))
+    } else if line.contains("RUI-PF-") {
+        ("port_forward", format!("New connection to forwarded {}", target))
+    } else {
+        return None;
+    };
+
+    Some((
+        TimelineEntry {
+            timestamp: timestamp.to_rfc3339(),
+            source: source.to_string(),
+            summary,
+            log_line: Some(line.to_string()),
+        },
+        port,
+    ))
+}
+
+fn kernel_entries(ip: &str, hours: u32) -> Vec<(TimelineEntry, Option<u16>)> {
+    let output = sudo()
+        .args(["journalctl", "-k", "--since", &format!("{} hours ago", hours), "--no-pager", "-o", "short-iso"])
+        .output();
+    let Ok(output) = output else { return Vec::new() };
+    let needle = format!(" SRC={} ", ip);
+
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter(|line| line.contains(&needle))
+        .filter_map(kernel_entry)
+        .collect()
+}
+
+fn ssh_entries(ip: &str, since: DateTime<Utc>) -> Vec<TimelineEntry> {
+    let output = sudo().args(["grep", "-E", "sshd.*(Failed|Invalid user)", AUTH_LOG]).output();
+    let Ok(output) = output else { return Vec::new() };
+    let needle = format!(" from {} ", ip);
+
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter(|line| line.contains(&needle))
+        .filter_map(|line| {
+            // Old-style syslog timestamps carry no year, so only RFC 3339 logs can be placed on the timeline
+            let timestamp = parse_timestamp(line.split_whitespace().next()?)?;
+            if timestamp < since {
+                return None;
+            }
+            let message = line.split_once("]: ").map(|(_, m)| m).unwrap_or(line);
+            let summary = message.split(" from ").next().unwrap_or(message).trim().to_string();
+            Some(TimelineEntry {
+                timestamp: timestamp.to_rfc3339(),
+                source: "ssh".to_string(),
+                summary: format!("SSH: {}", summary),
+                log_line: Some(line.to_string()),
+            })
+        })
+        .collect()
+}
+
+fn routerui_entries(state: &AppState, ip: &str, since: DateTime<Utc>) -> Vec<TimelineEntry> {
+    let bruteforce = state.bruteforce.recent_blocks().into_iter().filter(|b| b.ip == ip).map(|block| TimelineEntry {
+        timestamp: block.blocked_at,
+        source: "routerui".to_string(),
+        summary: format!("Blocked after {} failed RouterUI logins, until {}", block.failures, block.expires_at),
+        log_line: None,
+    });
+    let honeypot = state.honeypot.recent_bans().into_iter().filter(|b| b.ip == ip).map(|ban| TimelineEntry {
+        timestamp: ban.banned_at,
+        source: "honeypot".to_string(),
+        summary: format!("Added to the honeypot ban set until {}", ban.expires_at),
+        log_line: None,
+    });
+
+    bruteforce
+        .chain(honeypot)
+        .filter(|e| parse_timestamp(&e.timestamp).is_some_and(|t| t >= since))
+        .collect()
+}
+
+// ============ ENRICHMENT ============
+
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let shared = ip.octets()[0] == 100 && (ip.octets()[1] & 0xc0) == 64;
+            !(ip.is_private() || ip.is_loopback() || ip.is_link_local() || ip.is_unspecified() || shared)
+        }
+        IpAddr::V6(ip) => {
+            let unique_local = (ip.segments()[0] & 0xfe00) == 0xfc00;
+            let link_local = (ip.segments()[0] & 0xffc0) == 0xfe80;
+            !(ip.is_loopback() || ip.is_unspecified() || unique_local || link_local)
+        }
+    }
+}
+
+// The abuse contact sits on an entity (possibly nested) with the "abuse" role
+fn abuse_email(entities: &[serde_json::Value]) -> Option<String> {
+    for entity in entities {
+        let is_abuse = entity["roles"].as_array().is_some_and(|r| r.iter().any(|r| r == "abuse"));
+        if is_abuse {
+            let email = entity["vcardArray"][1]
+                .as_array()
+                .and_then(|items| items.iter().find(|i| i[0] == "email"))
+                .and_then(|i| i[3].as_str());
+            if let Some(email) = email {
+                return Some(email.to_string());
+            }
+        }
+        if let Some(nested) = entity["entities"].as_array() {
+            if let Some(email) = abuse_email(nested) {
+                return Some(email);
+            }
+        }
+    }
+    None
+}
+
+async fn whois(ip: IpAddr) -> Result<Whois, String> {
+    let client = reqwest::Client::builder().timeout(RDAP_TIMEOUT).build().map_err(|e| e.to_string())?;
+    let url = format!("{}/{}", RDAP_URL, ip);
+    let response = client
+        .get(&url)
+        .header("Accept", "application/rdap+json")
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    if !response.status().is_success() {
+        return Err(format!("RDAP lookup failed ({})", response.status()));
+    }
+    let data: serde_json::Value = response.json().await.map_err(|e| e.to_string())?;
+
+    let text = |key: &str| data[key].as_str().map(str::to_string);
+    let range = match (data["startAddress"].as_str(), data["endAddress"].as_str()) {
+        (Some(start), Some(end)) => Some(format!("{} - {}", start, end)),
+        _ => None,
+    };
+    Ok(Whois {
+        network: text("name"),
+        handle: text("handle"),
+        range,
+        country: text("country"),
+        abuse_email: data["entities"].as_array().and_then(|e| abuse_email(e)),
+        source: url,
+    })
+}
+
+async fn reputation(pool: &SqlitePool, ip: IpAddr) -> Option<ReputationSummary> {
+    let reputation = crate::reputation::lookup(pool, ip).await.ok()?;
+    if reputation.listings.is_empty() {
+        return None;
+    }
+    let mut feeds: Vec<String> = reputation.listings.iter().map(|l| l.source.clone()).collect();
+    feeds.sort();
+    feeds.dedup();
+    Some(ReputationSummary { score: reputation.score, summary: reputation.summary, feeds })
+}
+
+// ============ REPORT ============
+
+fn hostname() -> String {
+    std::fs::read_to_string("/etc/hostname")
+        .map(|h| h.trim().to_string())
+        .unwrap_or_else(|_| "router".to_string())
+}
+
+pub async fn build(state: &AppState, ip: IpAddr, hours: u32) -> IncidentReport {
+    let now = Utc::now();
+    let since = now - chrono::Duration::hours(hours as i64);
+    let ip_text = ip.to_string();
+
+    let logs = {
+        let ip = ip_text.clone();
+        tokio::task::spawn_blocking(move || (kernel_entries(&ip, hours), ssh_entries(&ip, since)))
+            .await
+            .unwrap_or_default()
+    };
+    let (kernel, ssh) = logs;
+
+    let mut ports: Vec<u16> = kernel.iter().filter_map(|(_, port)| *port).collect();
+    ports.sort_unstable();
+    ports.dedup();
+    let firewall_hits = kernel.len();
+    let ssh_failures = ssh.len();
+
+    let mut timeline: Vec<TimelineEntry> = kernel
+        .into_iter()
+        .map(|(entry, _)| entry)
+        .chain(ssh)
+        .chain(routerui_entries(state, &ip_text, since))
+        .collect();
+    timeline.sort_by_key(|e| parse_timestamp(&e.timestamp));
+    let omitted = timeline.len().saturating_sub(MAX_TIMELINE);
+    timeline.drain(..omitted);
+
+    let (whois, whois_error) = if is_public(ip) {
+        match whois(ip).await {
+            Ok(whois) => (Some(whois), None),
+            Err(e) => (None, Some(e)),
+        }
+    } else {
+        (None, Some("Private address, no public registration".to_string()))
+    };
+
+    let country = {
+        let ip = ip_text.clone();
+        tokio::task::spawn_blocking(move || crate::geoip::country(&ip)).await.ok().flatten()
+    };
+
+    IncidentReport {
+        summary: IncidentSummary {
+            firewall_hits,
+            ports,
+            ssh_failures,
+            first_seen: timeline.first().map(|e| e.timestamp.clone()),
+            last_seen: timeline.last().map(|e| e.timestamp.clone()),
+        },
+        ip: ip_text,
+        generated_at: now.to_rfc3339(),
+        hostname: hostname(),
+        hours,
+        since: since.to_rfc3339(),
+        country,
+        reputation: reputation(&state.db, ip).await,
+        whois,
+        whois_error,
+        timeline,
+        omitted,
+    }
+}
+
+// ============ HTML ============
+
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn row(label: &str, value: Option<&str>) -> String {
+    format!("<tr><th>{}</th><td>{}</td></tr>\n", label, escape(value.unwrap_or("-")))
+}
+
+/// Self-contained page with print styles, so "Print to PDF" gives a shareable document
+pub fn to_html(report: &IncidentReport) -> String {
+    let ports = report.summary.ports.iter().map(|p| p.to_string()).collect::<Vec<_>>().join(", ");
+    let mut html = String::new();
+
+    html.push_str("<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n");
+    html.push_str(&format!("<title>Incident report: {}</title>\n", escape(&report.ip)));
+    html.push_str(
+        "<style>\n\
+         body { font-family: -apple-system, 'Segoe UI', Helvetica, Arial, sans-serif; color: #111; margin: 2rem; font-size: 14px; }\n\
+         h1 { font-size: 1.5rem; margin-bottom: 0.25rem; }\n\
+         h2 { font-size: 1.1rem; margin-top: 2rem; border-bottom: 1px solid #ccc; padding-bottom: 0.25rem; }\n\
+         table { border-collapse: collapse; width: 100%; }\n\
+         th, td { text-align: left; vertical-align: top; padding: 0.3rem 0.5rem; border-bottom: 1px solid #eee; }\n\
+         th { width: 12rem; color: #555; font-weight: 600; }\n\
+         .timeline th { width: auto; }\n\
+         .mono, .log { font-family: ui-monospace, Menlo, Consolas, monospace; }\n\
+         .log { font-size: 11px; color: #555; word-break: break-all; }\n\
+         .muted { color: #777; }\n\
+         @media print { body { margin: 0; } tr { page-break-inside: avoid; } }\n\
+         </style>\n</head>\n<body>\n",
+    );
+
+    html.push_str(&format!("<h1>Incident report: <span class=\"mono\">{}</span></h1>\n", escape(&report.ip)));
+    html.push_str(&format!(
+        "<p class=\"muted\">Generated {} by {} covering the last {} hours (since {})</p>\n",
+        escape(&report.generated_at),
+        escape(&report.hostname),
+        report.hours,
+        escape(&report.since)
+    ));
+
+    html.push_str("<h2>Summary</h2>\n<table>\n");
+    html.push_str(&row("Firewall log entries", Some(&report.summary.firewall_hits.to_string())));
+    html.push_str(&row("Ports targeted", (!ports.is_empty()).then_some(ports.as_str())));
+    html.push_str(&row("Failed SSH logins", Some(&report.summary.ssh_failures.to_string())));
+    html.push_str(&row("First seen", report.summary.first_seen.as_deref()));
+    html.push_str(&row("Last seen", report.summary.last_seen.as_deref()));
+    html.push_str(&row("Country (GeoIP)", report.country.as_deref()));
+    if let Some(reputation) = &report.reputation {
+        html.push_str(&row("Blocklist reputation", Some(&reputation.summary)));
+        html.push_str(&row("Listed by", Some(&reputation.feeds.join(", "))));
+    }
+    html.push_str("</table>\n");
+
+    html.push_str("<h2>Registration (RDAP)</h2>\n");
+    match &report.whois {
+        Some(whois) => {
+            html.push_str("<table>\n");
+            html.push_str(&row("Network", whois.network.as_deref()));
+            html.push_str(&row("Handle", whois.handle.as_deref()));
+            html.push_str(&row("Range", whois.range.as_deref()));
+            html.push_str(&row("Country", whois.country.as_deref()));
+            html.push_str(&row("Abuse contact", whois.abuse_email.as_deref()));
+            html.push_str(&row("Source", Some(&whois.source)));
+            html.push_str("</table>\n");
+        }
+        None => html.push_str(&format!(
+            "<p class=\"muted\">{}</p>\n",
+            escape(report.whois_error.as_deref().unwrap_or("Not available"))
+        )),
+    }
+
+    html.push_str("<h2>Timeline</h2>\n");
+    if report.timeline.is_empty() {
+        html.push_str("<p class=\"muted\">Nothing logged for this address in the period.</p>\n");
+    } else {
+        if report.omitted > 0 {
+            html.push_str(&format!("<p class=\"muted\">{} earlier entries omitted.</p>\n", report.omitted));
+        }
+        html.push_str("<table class=\"timeline\">\n<tr><th>Time (UTC)</th><th>Source</th><th>Event</th></tr>\n");
+        for entry in &report.timeline {
+            html.push_str(&format!(
+                "<tr><td class=\"mono\">{}</td><td>{}</td><td>{}",
+                escape(&entry.timestamp),
+                escape(&entry.source),
+                escape(&entry.summary)
+            ));
+            if let Some(line) = &entry.log_line {
+                html.push_str(&format!("<div class=\"log\">{}</div>", escape(line)));
+            }
+            html.push_str("</td></tr>\n");
+        }
+        html.push_str("</table>\n");
+    }
+
+    html.push_str("</body>\n</html>\n");
+    html
+}
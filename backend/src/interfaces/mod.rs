@@ -0,0 +1,171 @@
+// Interfaces by what they do rather than what the kernel calls them. A new NIC or a kernel update
+// can rename enp1s0 to enp2s0; API clients and automations that ask for "wan" keep working once
+// the role points at the new name. Roles come from the settings table when set there, otherwise
+// from the setup wizard (WAN, LAN), the guest BSS in hostapd.conf and whichever VPN interface exists.
+
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+
+const DEFAULT_LAN_INTERFACE: &str = "enp2s0";
+// Tried in order for the vpn role when none is configured
+const VPN_INTERFACES: &[&str] = &["tailscale0", "wg0"];
+// Linux limit (IFNAMSIZ less the terminating NUL)
+const MAX_NAME_LEN: usize = 15;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    Wan,
+    Lan,
+    Guest,
+    Vpn,
+}
+
+impl Role {
+    pub const ALL: [Role; 4] = [Role::Wan, Role::Lan, Role::Guest, Role::Vpn];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Role::Wan => "wan",
+            Role::Lan => "lan",
+            Role::Guest => "guest",
+            Role::Vpn => "vpn",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Role> {
+        Role::ALL.into_iter().find(|r| r.as_str() == name)
+    }
+}
+
+/// Interfaces pinned to a role; None leaves the role to be detected
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RoleSettings {
+    pub wan: Option<String>,
+    pub lan: Option<String>,
+    pub guest: Option<String>,
+    pub vpn: Option<String>,
+}
+
+/// Whether the kernel would take `name` as an interface name, VLAN links (eth0.10) included
+pub fn valid_name(name: &str) -> bool {
+    !name.is_empty() && name.len() <= MAX_NAME_LEN && name.chars().all(|c| c.is_ascii_alphanumeric() || "._-@".contains(c))
+}
+
+impl RoleSettings {
+    /// Trim and drop empty names
+    pub fn normalize(&mut self) {
+        for name in [&mut self.wan, &mut self.lan, &mut self.guest, &mut self.vpn] {
+            *name = name.as_deref().map(str::trim).filter(|n| !n.is_empty()).map(str::to_string);
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        let assigned: Vec<(Role, &str)> = Role::ALL.into_iter().filter_map(|role| Some((role, self.get(role)?))).collect();
+        for &(role, name) in &assigned {
+            if !valid_name(name) {
+                return Err(format!("'{}' is not a valid interface name for {}", name, role.as_str()));
+            }
+            // "lan" as an interface name would make role lookups ambiguous
+            if Role::parse(name).is_some_and(|r| r != role) {
+                return Err(format!("An interface named {} can't be given the {} role", name, role.as_str()));
+            }
+            if let Some((other, _)) = assigned.iter().find(|(other, n)| *other != role && *n == name) {
+                return Err(format!("{} can't be both {} and {}", name, role.as_str(), other.as_str()));
+            }
+        }
+        Ok(())
+    }
+
+    fn get(&self, role: Role) -> Option<&str> {
+        match role {
+            Role::Wan => self.wan.as_deref(),
+            Role::Lan => self.lan.as_deref(),
+            Role::Guest => self.guest.as_deref(),
+            Role::Vpn => self.vpn.as_deref(),
+        }
+    }
+}
+
+// Key in the settings table
+pub const SETTINGS_KEY: &str = "interfaces";
+
+pub async fn load_settings(pool: &SqlitePool) -> RoleSettings {
+    match crate::db::settings::get(pool, SETTINGS_KEY).await {
+        Ok(settings) => settings.unwrap_or_default(),
+        Err(e) => {
+            tracing::warn!("Failed to read interface roles, detecting them: {}", e);
+            RoleSettings::default()
+        }
+    }
+}
+
+pub async fn save_settings(pool: &SqlitePool, settings: &RoleSettings) -> Result<(), String> {
+    crate::db::settings::set(pool, SETTINGS_KEY, settings).await.map_err(|e| e.to_string())
+}
+
+/// The kernel name behind each role
+#[derive(Debug, Clone, Serialize)]
+pub struct Roles {
+    pub wan: String,
+    pub lan: String,
+    pub guest: Option<String>,
+    pub vpn: Option<String>,
+}
+
+impl Roles {
+    pub fn name_of(&self, role: Role) -> Option<&str> {
+        match role {
+            Role::Wan => Some(&self.wan),
+            Role::Lan => Some(&self.lan),
+            Role::Guest => self.guest.as_deref(),
+            Role::Vpn => self.vpn.as_deref(),
+        }
+    }
+
+    pub fn role_of(&self, name: &str) -> Option<Role> {
+        Role::ALL.into_iter().find(|r| self.name_of(*r) == Some(name))
+    }
+
+    /// The kernel name for `name_or_role`: "wan" becomes e.g. enp1s0, a kernel name is kept.
+    /// Errors for a role with no interface behind it.
+    pub fn resolve(&self, name_or_role: &str) -> Result<String, String> {
+        match Role::parse(name_or_role) {
+            Some(role) => self
+                .name_of(role)
+                .map(str::to_string)
+                .ok_or_else(|| format!("No interface has the {} role", role.as_str())),
+            None => Ok(name_or_role.to_string()),
+        }
+    }
+}
+
+/// Current roles, settings first, then detection
+pub async fn roles(pool: &SqlitePool) -> Roles {
+    let settings = load_settings(pool).await;
+    Roles {
+        wan: crate::wan::wan_interface(pool).await,
+        lan: lan_interface(pool).await,
+        guest: settings.guest.or_else(crate::api::network::guest_interface),
+        vpn: settings.vpn.or_else(|| {
+            VPN_INTERFACES
+                .iter()
+                .find(|i| std::path::Path::new("/sys/class/net").join(i).exists())
+                .map(|i| i.to_string())
+        }),
+    }
+}
+
+/// LAN interface: the one set in settings, else the one chosen in the setup wizard
+pub async fn lan_interface(pool: &SqlitePool) -> String {
+    if let Some(lan) = load_settings(pool).await.lan {
+        return lan;
+    }
+    sqlx::query_scalar::<_, String>("SELECT value FROM setup_config WHERE key = 'lan_interface'")
+        .fetch_optional(pool)
+        .await
+        .ok()
+        .flatten()
+        .unwrap_or_else(|| DEFAULT_LAN_INTERFACE.to_string())
+}
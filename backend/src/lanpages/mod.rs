@@ -0,0 +1,326 @@
+// "LAN pages": short names on the LAN that go somewhere useful. printer.lan redirects to the
+// printer's web UI, start.lan is a page of household links, and so on. Each hostname gets a
+// local DNS entry pointing at the router, and a small web server on the LAN address (port 80 by
+// default, separate from the management UI) answers by Host header.
+
+use axum::{
+    extract::State,
+    http::{header, HeaderMap, StatusCode},
+    response::{Html, IntoResponse, Redirect, Response},
+    Router,
+};
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+use crate::AppState;
+
+// Key in the settings table
+pub const SETTINGS_KEY: &str = "lan_pages";
+// Picks up a changed LAN address, or a LAN interface that came up after we started
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+const MAX_PAGES: usize = 50;
+const MAX_LINKS: usize = 50;
+const MAX_HTML_BYTES: usize = 64 * 1024;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Link {
+    pub name: String,
+    pub url: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Content {
+    Redirect { target: String },
+    // A generated page of links, e.g. a start page
+    Links { title: String, links: Vec<Link> },
+    Html { html: String },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LanPage {
+    // e.g. "printer.lan"
+    pub hostname: String,
+    #[serde(flatten)]
+    pub content: Content,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LanPagesSettings {
+    pub enabled: bool,
+    pub port: u16,
+    pub pages: Vec<LanPage>,
+}
+
+impl Default for LanPagesSettings {
+    fn default() -> Self {
+        Self { enabled: false, port: 80, pages: Vec::new() }
+    }
+}
+
+fn is_http_url(url: &str) -> bool {
+    reqwest::Url::parse(url).is_ok_and(|u| matches!(u.scheme(), "http" | "https"))
+}
+
+fn is_hostname(name: &str) -> bool {
+    name.len() <= 253
+        && name.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+        })
+}
+
+impl LanPagesSettings {
+    pub fn normalize(&mut self) -> Result<(), String> {
+        if self.port == 0 {
+            return Err("Pick a port for the LAN pages".to_string());
+        }
+        if self.port == crate::config::get().port {
+            return Err(format!("Port {} is taken by RouterUI itself", self.port));
+        }
+        if self.pages.len() > MAX_PAGES {
+            return Err(format!("At most {} LAN pages", MAX_PAGES));
+        }
+        let mut seen = Vec::new();
+        for page in &mut self.pages {
+            page.hostname = page.hostname.trim().trim_end_matches('.').to_lowercase();
+            if !is_hostname(&page.hostname) {
+                return Err(format!("'{}' is not a valid hostname", page.hostname));
+            }
+            if seen.contains(&page.hostname) {
+                return Err(format!("{} is listed twice", page.hostname));
+            }
+            seen.push(page.hostname.clone());
+
+            match &mut page.content {
+                Content::Redirect { target } => {
+                    *target = target.trim().to_string();
+                    if !is_http_url(target) {
+                        return Err(format!("{}: the redirect must go to an http:// or https:// address", page.hostname));
+                    }
+                }
+                Content::Links { links, .. } => {
+                    if links.len() > MAX_LINKS {
+                        return Err(format!("{}: at most {} links", page.hostname, MAX_LINKS));
+                    }
+                    for link in links.iter_mut() {
+                        link.url = link.url.trim().to_string();
+                        if !is_http_url(&link.url) {
+                            return Err(format!("{}: '{}' is not an http:// or https:// address", page.hostname, link.url));
+                        }
+                    }
+                }
+                Content::Html { html } => {
+                    if html.len() > MAX_HTML_BYTES {
+                        return Err(format!("{}: pages can be at most {} KiB", page.hostname, MAX_HTML_BYTES / 1024));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+pub async fn load_settings(pool: &SqlitePool) -> LanPagesSettings {
+    match crate::db::settings::get(pool, SETTINGS_KEY).await {
+        Ok(settings) => settings.unwrap_or_default(),
+        Err(e) => {
+            tracing::warn!("Failed to read LAN pages, serving none: {}", e);
+            LanPagesSettings::default()
+        }
+    }
+}
+
+/// Save, point DNS at the router for the served hostnames (dropping the rest) and rebind
+pub async fn save(state: &Arc<AppState>, settings: &LanPagesSettings) -> Result<(), String> {
+    let previous = load_settings(&state.db).await;
+    crate::db::settings::set(&state.db, SETTINGS_KEY, settings).await.map_err(|e| e.to_string())?;
+
+    let lan_ip = lan_address(&state.db).await;
+    let current = settings.clone();
+    tokio::task::spawn_blocking(move || {
+        let Some(ip) = lan_ip.map(|ip| ip.to_string()) else {
+            return Ok(());
+        };
+        let served = |hostname: &str| current.enabled && current.pages.iter().any(|p| p.hostname == hostname);
+        for page in previous.pages.iter().chain(&current.pages).filter(|p| !served(&p.hostname)) {
+            crate::api::network::remove_local_dns_entry(&page.hostname, &ip).map_err(|(_, e)| e)?;
+        }
+        if current.enabled {
+            sync_dns(&current, &ip)?;
+        }
+        Ok::<(), String>(())
+    })
+    .await
+    .map_err(|e| e.to_string())??;
+
+    restart(state).await;
+    Ok(())
+}
+
+fn sync_dns(settings: &LanPagesSettings, ip: &str) -> Result<(), String> {
+    for page in &settings.pages {
+        crate::api::network::ensure_local_dns(&page.hostname, ip).map_err(|(_, e)| e)?;
+    }
+    Ok(())
+}
+
+async fn lan_address(pool: &SqlitePool) -> Option<Ipv4Addr> {
+    let lan = crate::interfaces::lan_interface(pool).await;
+    tokio::task::spawn_blocking(move || crate::wan::interface_ipv4(&lan)).await.ok().flatten()
+}
+
+// ============ SERVER ============
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ServerStatus {
+    pub listening: Option<SocketAddr>,
+    pub error: Option<String>,
+}
+
+/// The LAN pages listener, if it is running
+#[derive(Default)]
+pub struct LanPagesServer {
+    task: Mutex<Option<(SocketAddr, JoinHandle<()>)>>,
+    error: Mutex<Option<String>>,
+}
+
+impl LanPagesServer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn status(&self) -> ServerStatus {
+        ServerStatus {
+            listening: self.task.lock().unwrap().as_ref().map(|(addr, _)| *addr),
+            error: self.error.lock().unwrap().clone(),
+        }
+    }
+
+    fn stop(&self) {
+        if let Some((_, task)) = self.task.lock().unwrap().take() {
+            task.abort();
+        }
+    }
+}
+
+// Where the server should be listening under the current settings, if anywhere
+async fn wanted_address(pool: &SqlitePool, settings: &LanPagesSettings) -> Result<Option<SocketAddr>, String> {
+    if !settings.enabled || settings.pages.is_empty() {
+        return Ok(None);
+    }
+    let ip = lan_address(pool).await.ok_or("The LAN interface has no IPv4 address")?;
+    Ok(Some(SocketAddr::new(ip.into(), settings.port)))
+}
+
+/// Stop the listener and start it again for the current settings
+pub async fn restart(state: &Arc<AppState>) {
+    let server = &state.lan_pages;
+    server.stop();
+    *server.error.lock().unwrap() = None;
+
+    let settings = load_settings(&state.db).await;
+    let addr = match wanted_address(&state.db, &settings).await {
+        Ok(Some(addr)) => addr,
+        Ok(None) => return,
+        Err(e) => {
+            *server.error.lock().unwrap() = Some(e);
+            return;
+        }
+    };
+    let listener = match tokio::net::TcpListener::bind(addr).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            tracing::warn!("LAN pages could not listen on {}: {}", addr, e);
+            *server.error.lock().unwrap() = Some(format!("Could not listen on {}: {}", addr, e));
+            return;
+        }
+    };
+
+    let app = Router::new().fallback(serve_page).with_state(state.clone());
+    let task = tokio::spawn(async move {
+        if let Err(e) = axum::serve(listener, app).await {
+            tracing::warn!("LAN pages server stopped: {}", e);
+        }
+    });
+    tracing::info!("Serving {} LAN page(s) on {}", settings.pages.len(), addr);
+    *server.task.lock().unwrap() = Some((addr, task));
+}
+
+pub fn spawn(state: Arc<AppState>) {
+    tokio::spawn(async move {
+        restart(&state).await;
+        loop {
+            state.tasks.beat("lan_pages", CHECK_INTERVAL);
+            tokio::time::sleep(CHECK_INTERVAL).await;
+
+            let settings = load_settings(&state.db).await;
+            let wanted = wanted_address(&state.db, &settings).await.ok().flatten();
+            let status = state.lan_pages.status();
+            if wanted != status.listening || (wanted.is_some() && status.error.is_some()) {
+                restart(&state).await;
+                if let Some(addr) = wanted {
+                    let ip = addr.ip().to_string();
+                    if let Ok(Err(e)) = tokio::task::spawn_blocking(move || sync_dns(&settings, &ip)).await {
+                        tracing::warn!("Could not update DNS for the LAN pages: {}", e);
+                    }
+                }
+            }
+        }
+    });
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;").replace('\'', "&#39;")
+}
+
+fn render(title: &str, links: &[Link]) -> String {
+    let items: String = links
+        .iter()
+        .map(|l| format!("<li><a href=\"{}\">{}</a></li>\n", escape(&l.url), escape(&l.name)))
+        .collect();
+    format!(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\
+         <title>{0}</title><style>body{{font-family:sans-serif;max-width:40rem;margin:2rem auto;padding:0 1rem}}\
+         li{{margin:.5rem 0}}</style></head>\n<body><h1>{0}</h1>\n<ul>\n{1}</ul></body></html>\n",
+        escape(title),
+        items
+    )
+}
+
+async fn serve_page(State(state): State<Arc<AppState>>, headers: HeaderMap) -> Response {
+    let host = headers
+        .get(header::HOST)
+        .and_then(|h| h.to_str().ok())
+        .map(|h| h.rsplit_once(':').filter(|(_, port)| port.parse::<u16>().is_ok()).map_or(h, |(name, _)| name))
+        .unwrap_or_default()
+        .trim_end_matches('.')
+        .to_lowercase();
+
+    let settings = load_settings(&state.db).await;
+    let Some(page) = settings.pages.iter().find(|p| p.hostname == host) else {
+        // Anything else, the bare IP included, gets the list of pages
+        let port = if settings.port == 80 { String::new() } else { format!(":{}", settings.port) };
+        let links: Vec<Link> = settings
+            .pages
+            .iter()
+            .map(|p| Link { name: p.hostname.clone(), url: format!("http://{}{}/", p.hostname, port) })
+            .collect();
+        return (StatusCode::NOT_FOUND, Html(render("LAN pages", &links))).into_response();
+    };
+
+    match &page.content {
+        // Temporary, so browsers don't hold on to it after the target changes
+        Content::Redirect { target } => Redirect::temporary(target).into_response(),
+        Content::Links { title, links } => Html(render(title, links)).into_response(),
+        Content::Html { html } => Html(html.clone()).into_response(),
+    }
+}
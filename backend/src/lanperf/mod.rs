@@ -0,0 +1,285 @@
+// Throughput between two LAN devices, measured through the router. Each device runs an iperf3
+// server (`iperf3 -s`, natively or in a container). The router pulls from the sender and pushes
+// to the receiver: first one leg at a time, then both at once, which is the path a transfer
+// between them takes through the router. Comparing the legs with their link rates, and with the
+// CPU figures iperf3 reports, points at what holds a slow transfer back.
+
+use serde::Serialize;
+use std::process::Command;
+use std::sync::Mutex;
+
+pub const DEFAULT_PORT: u16 = 5201;
+pub const DEFAULT_SECONDS: u32 = 5;
+pub const MAX_SECONDS: u32 = 30;
+// Give up on a device that doesn't answer instead of waiting for the TCP timeout
+const CONNECT_TIMEOUT_MS: &str = "3000";
+// iperf3 reports the CPU as the limit above this
+const CPU_BUSY_PERCENT: f64 = 80.0;
+// Share of the negotiated rate a healthy link carries: WiFi rarely gets past 60% of its PHY
+// rate, Ethernet loses about 6% to framing
+const WIFI_EFFICIENCY: f64 = 0.6;
+const WIRED_EFFICIENCY: f64 = 0.94;
+// Below this share of what it should carry, a leg (or the relay) is underperforming
+const SHORTFALL: f64 = 0.7;
+
+// One test at a time: two would only measure each other
+static RUNNING: Mutex<()> = Mutex::new(());
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Medium {
+    Wifi,
+    Wired,
+}
+
+/// How a device reaches the router
+#[derive(Debug, Clone, Serialize)]
+pub struct Link {
+    pub mac: Option<String>,
+    pub hostname: Option<String>,
+    // Router side: the WiFi interface, or the port the route goes out of
+    pub interface: Option<String>,
+    pub medium: Medium,
+    // The station's bitrate in the leg's direction for WiFi, the port's speed for wired
+    pub rate_mbps: Option<f64>,
+    pub signal_dbm: Option<i32>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Measurement {
+    pub mbps: f64,
+    pub retransmits: Option<u64>,
+    pub router_cpu: f64,
+    pub device_cpu: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Leg {
+    pub ip: String,
+    pub link: Link,
+    // The leg on its own
+    pub alone: Measurement,
+    // While the other leg runs too
+    pub relayed: Measurement,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Bottleneck {
+    Wifi,
+    Switch,
+    Devices,
+    Router,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Report {
+    pub seconds: u32,
+    // sender -> router
+    pub sender: Leg,
+    // router -> receiver
+    pub receiver: Leg,
+    // What a transfer from sender to receiver got through the router
+    pub relay_mbps: f64,
+    pub bottleneck: Bottleneck,
+    pub explanation: String,
+}
+
+// First number of an `iw` bitrate, e.g. "866.7 MBit/s VHT-MCS 9 80MHz short GI"
+fn bitrate_mbps(value: &str) -> Option<f64> {
+    value.split_whitespace().next()?.parse().ok()
+}
+
+// `ip route get <ip>`: "10.22.22.50 dev enp2s0 src 10.22.22.1 uid 0"
+fn route_interface(ip: &str) -> Option<String> {
+    let output = Command::new("ip").args(["route", "get", ip]).output().ok()?;
+    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+    let parts: Vec<&str> = stdout.split_whitespace().collect();
+    parts.iter().position(|p| *p == "dev").and_then(|i| parts.get(i + 1)).map(|d| d.to_string())
+}
+
+// Negotiated speed of a wired port; bridges and virtual links have none
+fn port_speed(interface: &str) -> Option<f64> {
+    let speed: i64 = std::fs::read_to_string(format!("/sys/class/net/{}/speed", interface)).ok()?.trim().parse().ok()?;
+    (speed > 0).then_some(speed as f64)
+}
+
+// `to_router` picks the station's rx bitrate (what the router receives from it), else tx
+fn link_for(ip: &str, to_router: bool) -> Link {
+    let mac = crate::api::network::mac_for_ip(ip);
+    let station = crate::api::network::wifi_clients()
+        .into_iter()
+        .find(|c| c.ip_address.as_deref() == Some(ip) || Some(&c.mac_address) == mac.as_ref());
+
+    match station {
+        Some(station) => {
+            let bitrate = if to_router { &station.rx_bitrate } else { &station.tx_bitrate };
+            Link {
+                mac: Some(station.mac_address.clone()),
+                hostname: station.hostname.clone(),
+                interface: Some(station.interface.clone()),
+                medium: Medium::Wifi,
+                rate_mbps: bitrate.as_deref().and_then(bitrate_mbps),
+                signal_dbm: station.signal_dbm,
+            }
+        }
+        None => {
+            let interface = route_interface(ip);
+            Link {
+                mac,
+                hostname: None,
+                rate_mbps: interface.as_deref().and_then(port_speed),
+                interface,
+                medium: Medium::Wired,
+                signal_dbm: None,
+            }
+        }
+    }
+}
+
+// `reverse` has the device send and the router receive (iperf3 -R)
+fn run_iperf(ip: &str, port: u16, seconds: u32, reverse: bool) -> Result<Measurement, String> {
+    let port = port.to_string();
+    let seconds = seconds.to_string();
+    let mut args = vec!["-c", ip, "-p", &port, "-t", &seconds, "-J", "--connect-timeout", CONNECT_TIMEOUT_MS];
+    if reverse {
+        args.push("-R");
+    }
+    let output = Command::new("iperf3").args(&args).output().map_err(|e| match e.kind() {
+        std::io::ErrorKind::NotFound => "iperf3 is not installed on the router".to_string(),
+        _ => e.to_string(),
+    })?;
+
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout)
+        .map_err(|_| format!("{}: {}", ip, String::from_utf8_lossy(&output.stderr).trim()))?;
+    if let Some(error) = json.get("error").and_then(|e| e.as_str()) {
+        return Err(format!("{}: {} (is `iperf3 -s -p {}` running there?)", ip, error, port));
+    }
+
+    let end = &json["end"];
+    let bits = end.pointer("/sum_received/bits_per_second").and_then(|v| v.as_f64()).unwrap_or(0.0);
+    let cpu = |key: &str| end.pointer(&format!("/cpu_utilization_percent/{}", key)).and_then(|v| v.as_f64()).unwrap_or(0.0);
+    Ok(Measurement {
+        mbps: bits / 1_000_000.0,
+        retransmits: end.pointer("/sum_sent/retransmits").and_then(|v| v.as_u64()),
+        router_cpu: cpu("host_total"),
+        device_cpu: cpu("remote_total"),
+    })
+}
+
+// What a leg should carry given its link, when the rate is known
+fn expected_mbps(link: &Link) -> Option<f64> {
+    let efficiency = match link.medium {
+        Medium::Wifi => WIFI_EFFICIENCY,
+        Medium::Wired => WIRED_EFFICIENCY,
+    };
+    link.rate_mbps.map(|rate| rate * efficiency)
+}
+
+fn describe(leg: &Leg) -> String {
+    match &leg.link.hostname {
+        Some(name) => format!("{} ({})", name, leg.ip),
+        None => leg.ip.clone(),
+    }
+}
+
+/// Name the most likely limit, checked from the most to the least certain evidence
+pub fn diagnose(sender: &Leg, receiver: &Leg, relay_mbps: f64) -> (Bottleneck, String) {
+    let legs = [sender, receiver];
+
+    for leg in legs {
+        let busy = leg.alone.device_cpu.max(leg.relayed.device_cpu);
+        if busy >= CPU_BUSY_PERCENT {
+            let explanation = format!(
+                "{} used {:.0}% CPU to move {:.0} Mb/s: the device itself can't go faster",
+                describe(leg), busy, leg.alone.mbps
+            );
+            return (Bottleneck::Devices, explanation);
+        }
+    }
+    let router_cpu = legs.iter().map(|l| l.alone.router_cpu.max(l.relayed.router_cpu)).fold(0.0, f64::max);
+    if router_cpu >= CPU_BUSY_PERCENT {
+        let explanation = format!("The router's CPU reached {:.0}% relaying the traffic", router_cpu);
+        return (Bottleneck::Router, explanation);
+    }
+
+    let slowest = if sender.alone.mbps <= receiver.alone.mbps { sender } else { receiver };
+    if slowest.link.medium == Medium::Wifi {
+        let rate = slowest.link.rate_mbps.map(|r| format!(" over a {:.0} Mb/s link", r)).unwrap_or_default();
+        let signal = slowest.link.signal_dbm.map(|s| format!(" at {} dBm", s)).unwrap_or_default();
+        let explanation = format!(
+            "WiFi: {} gets {:.0} Mb/s{}{}, the slowest leg of the path",
+            describe(slowest), slowest.alone.mbps, rate, signal
+        );
+        return (Bottleneck::Wifi, explanation);
+    }
+    if let (Some(rate), Some(expected)) = (slowest.link.rate_mbps, expected_mbps(&slowest.link)) {
+        if slowest.alone.mbps < expected * SHORTFALL {
+            let explanation = format!(
+                "{} gets {:.0} Mb/s although the router's port runs at {:.0} Mb/s: a slower switch port, \
+                 a 100 Mb/s link or a bad cable sits between them",
+                describe(slowest), slowest.alone.mbps, rate
+            );
+            return (Bottleneck::Switch, explanation);
+        }
+        if rate <= 100.0 {
+            let explanation = format!("The router's port towards {} negotiated only {:.0} Mb/s", describe(slowest), rate);
+            return (Bottleneck::Switch, explanation);
+        }
+    }
+
+    if relay_mbps < slowest.alone.mbps * SHORTFALL {
+        let same_radio = legs.iter().all(|l| l.link.medium == Medium::Wifi) && sender.link.interface == receiver.link.interface;
+        if same_radio {
+            let explanation = format!(
+                "Both devices share one WiFi radio, so relayed traffic crosses the air twice: {:.0} Mb/s together \
+                 against {:.0} Mb/s apart",
+                relay_mbps, slowest.alone.mbps
+            );
+            return (Bottleneck::Wifi, explanation);
+        }
+        let explanation = format!(
+            "Each leg is fast on its own but only {:.0} Mb/s get through with both running (against {:.0} Mb/s): \
+             they share a congested link, usually the one between the switch and the router",
+            relay_mbps, slowest.alone.mbps
+        );
+        return (Bottleneck::Switch, explanation);
+    }
+
+    let explanation = format!(
+        "The network carries {:.0} Mb/s between these devices, about what their links allow. A slower transfer \
+         is limited by the devices: their disks or the software copying the data",
+        relay_mbps
+    );
+    (Bottleneck::Devices, explanation)
+}
+
+/// Run the test; takes about three times `seconds`
+pub fn run(sender: &str, receiver: &str, port: u16, seconds: u32) -> Result<Report, String> {
+    let _running = RUNNING.try_lock().map_err(|_| "A throughput test is already running".to_string())?;
+
+    let sender_alone = run_iperf(sender, port, seconds, true)?;
+    let receiver_alone = run_iperf(receiver, port, seconds, false)?;
+    let (sender_relayed, receiver_relayed) = std::thread::scope(|scope| {
+        let pull = scope.spawn(|| run_iperf(sender, port, seconds, true));
+        let push = run_iperf(receiver, port, seconds, false);
+        (pull.join().unwrap_or_else(|_| Err("iperf3 failed".to_string())), push)
+    });
+
+    let sender = Leg {
+        ip: sender.to_string(),
+        link: link_for(sender, true),
+        alone: sender_alone,
+        relayed: sender_relayed?,
+    };
+    let receiver = Leg {
+        ip: receiver.to_string(),
+        link: link_for(receiver, false),
+        alone: receiver_alone,
+        relayed: receiver_relayed?,
+    };
+    let relay_mbps = sender.relayed.mbps.min(receiver.relayed.mbps);
+    let (bottleneck, explanation) = diagnose(&sender, &receiver, relay_mbps);
+
+    Ok(Report { seconds, sender, receiver, relay_mbps, bottleneck, explanation })
+}
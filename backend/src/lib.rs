@@ -0,0 +1,74 @@
+pub mod acme;
+pub mod api;
+pub mod approvals;
+pub mod auth;
+pub mod backupcrypt;
+pub mod cache;
+pub mod certwatch;
+pub mod config;
+pub mod connlog;
+pub mod db;
+pub mod dhcp;
+pub mod discovery;
+pub mod dnsguard;
+pub mod events;
+pub mod geoip;
+pub mod guestcodes;
+pub mod health;
+pub mod homelab;
+pub mod helper;
+pub mod honeypot;
+pub mod incident;
+pub mod interfaces;
+pub mod lanpages;
+pub mod lanperf;
+pub mod lint;
+pub mod logging;
+pub mod metrics;
+pub mod mesh;
+pub mod mock;
+pub mod modem;
+pub mod monitors;
+pub mod models;
+pub mod power;
+pub mod presence;
+pub mod privacy;
+pub mod profiles;
+pub mod public_status;
+pub mod qos;
+pub mod reputation;
+pub mod safesearch;
+pub mod scheduler;
+pub mod stats;
+pub mod subnets;
+pub mod syslog;
+pub mod system;
+pub mod undo;
+pub mod wan;
+pub mod wol;
+pub mod zones;
+
+pub struct AppState {
+    pub db: sqlx::SqlitePool,
+    pub started_at: chrono::DateTime<chrono::Utc>,
+    pub tasks: health::TaskRegistry,
+    pub api_stats: stats::ApiStats,
+    pub cache: cache::ResponseCache,
+    pub events: events::EventBus,
+    pub wan: wan::WanTracker,
+    pub setup_guard: auth::setup_token::SetupGuard,
+    pub bruteforce: auth::bruteforce::BruteForceGuard,
+    pub oidc: auth::oidc::OidcLogins,
+    pub approvals: approvals::ApprovalQueue,
+    pub undo: undo::UndoStore,
+    pub modem: modem::ModemTracker,
+    pub presence: presence::PresenceTracker,
+    pub certs: certwatch::CertTracker,
+    pub honeypot: honeypot::HoneypotTracker,
+    pub management_access: system::management_access::AccessCache,
+    pub monitors: monitors::MonitorTracker,
+    pub lan_pages: lanpages::LanPagesServer,
+    pub homelab: homelab::HostTracker,
+    pub public_status: public_status::PublicStatusTracker,
+    pub lint: lint::LintTracker,
+}
@@ -0,0 +1,325 @@
+// Configuration lint: a pass over the state RouterUI manages looking for things that are set up
+// but can't work - rules naming interfaces that don't exist, static leases no LAN subnet covers,
+// port forwards to hosts that don't answer, blocklists whose ipset is gone, certificates past
+// their expiry. Nothing here changes anything; it runs once at startup and on demand, and the
+// latest report is kept for /api/system/lint.
+
+use serde::Serialize;
+use sqlx::SqlitePool;
+use std::collections::BTreeMap;
+use std::net::Ipv4Addr;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use crate::certwatch::{self, CertSource, CertStatus};
+use crate::interfaces::Role;
+use crate::system::privileges::sudo;
+use crate::AppState;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Finding {
+    pub check: &'static str,
+    pub severity: Severity,
+    // The interface, lease, forward, set or certificate the finding is about
+    pub subject: String,
+    pub message: String,
+    // What to change when it isn't intended
+    pub fix: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LintReport {
+    pub checked_at: String,
+    pub duration_ms: u64,
+    pub errors: usize,
+    pub warnings: usize,
+    // Most severe first
+    pub findings: Vec<Finding>,
+}
+
+/// The latest report
+#[derive(Default)]
+pub struct LintTracker {
+    last: Mutex<Option<LintReport>>,
+}
+
+impl LintTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn last(&self) -> Option<LintReport> {
+        self.last.lock().unwrap().clone()
+    }
+}
+
+fn finding(check: &'static str, severity: Severity, subject: impl Into<String>, message: impl Into<String>, fix: Option<&str>) -> Finding {
+    Finding {
+        check,
+        severity,
+        subject: subject.into(),
+        message: message.into(),
+        fix: fix.map(str::to_string),
+    }
+}
+
+fn interface_exists(name: &str) -> bool {
+    Path::new("/sys/class/net").join(name).exists()
+}
+
+// ============ CHECKS ============
+
+// Interfaces named by the zones, subnets and the live iptables rules
+fn check_interfaces(zones: &crate::zones::Zones, subnets: &crate::subnets::SubnetSettings) -> Vec<Finding> {
+    let mut findings = Vec::new();
+
+    for role in Role::ALL {
+        let Some(name) = zones.roles.name_of(role) else { continue };
+        if !interface_exists(name) {
+            // The firewall and DHCP are built around the WAN and LAN, the others are optional
+            let severity = if matches!(role, Role::Wan | Role::Lan) { Severity::Error } else { Severity::Warning };
+            findings.push(finding(
+                "interfaces",
+                severity,
+                name,
+                format!("{} has the {} role but doesn't exist", name, role.as_str()),
+                Some("Assign the role to an existing interface on the Network page"),
+            ));
+        }
+    }
+
+    for member in &zones.settings.members {
+        if !interface_exists(&member.interface) {
+            findings.push(finding(
+                "interfaces",
+                Severity::Info,
+                &member.interface,
+                format!("{} is in the {} zone but doesn't exist, so its zone rules match nothing", member.interface, member.zone.as_str()),
+                Some("Remove it from the zone on the Firewall page if it is gone for good"),
+            ));
+        }
+    }
+
+    for subnet in &subnets.subnets {
+        if !interface_exists(&subnet.interface) {
+            findings.push(finding(
+                "interfaces",
+                Severity::Error,
+                &subnet.interface,
+                format!("Subnet {} is on {}, which doesn't exist", subnet.name, subnet.interface),
+                Some("Move the subnet to an existing interface on the Network page"),
+            ));
+        }
+    }
+
+    // Rules added by hand or left behind by a removed NIC; "eth+" style wildcards match any
+    let saved = sudo().arg("iptables-save").output();
+    let mut missing: BTreeMap<String, usize> = BTreeMap::new();
+    if let Ok(output) = saved {
+        for line in String::from_utf8_lossy(&output.stdout).lines().filter(|l| l.starts_with("-A ")) {
+            let words: Vec<&str> = line.split_whitespace().filter(|w| *w != "!").collect();
+            for pair in words.windows(2) {
+                let (flag, name) = (pair[0], pair[1]);
+                if matches!(flag, "-i" | "-o") && !name.ends_with('+') && !interface_exists(name) {
+                    *missing.entry(name.to_string()).or_default() += 1;
+                }
+            }
+        }
+    }
+    for (name, rules) in missing {
+        findings.push(finding(
+            "interfaces",
+            Severity::Warning,
+            &name,
+            format!("{} iptables rule(s) match on {}, which doesn't exist", rules, name),
+            Some("Remove the rules, or check whether the interface was renamed"),
+        ));
+    }
+
+    findings
+}
+
+fn check_static_leases(wan: &str) -> Vec<Finding> {
+    let subnets = crate::system::lan_subnets(wan);
+    // Without addresses on the LAN side there is nothing to compare against
+    if subnets.is_empty() {
+        return Vec::new();
+    }
+    crate::api::network::load_static_leases()
+        .into_iter()
+        .filter_map(|lease| {
+            let subject = format!("{} ({})", lease.ip_address, lease.mac_address);
+            match lease.ip_address.parse::<Ipv4Addr>() {
+                Ok(ip) if crate::system::is_lan_address(ip.into(), &subnets) => None,
+                Ok(_) => Some(finding(
+                    "static_leases",
+                    Severity::Warning,
+                    subject,
+                    format!("{} is outside every LAN subnet, so dnsmasq never hands it out", lease.ip_address),
+                    Some("Change the reservation to an address on the LAN"),
+                )),
+                Err(_) => Some(finding(
+                    "static_leases",
+                    Severity::Error,
+                    subject,
+                    format!("\"{}\" is not an IPv4 address", lease.ip_address),
+                    Some("Fix or remove the reservation"),
+                )),
+            }
+        })
+        .collect()
+}
+
+// arping takes a couple of seconds per host, so the hosts are probed in parallel
+fn check_port_forwards() -> Vec<Finding> {
+    let forwards = crate::api::firewall::live_port_forwards().unwrap_or_default();
+    let mut hosts: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for forward in forwards.iter().filter(|f| f.enabled) {
+        hosts
+            .entry(forward.internal_ip.clone())
+            .or_default()
+            .push(format!("{}/{}", forward.external_port, forward.protocol));
+    }
+
+    let probed: Vec<(String, Vec<String>, Result<bool, String>)> = std::thread::scope(|scope| {
+        let handles: Vec<_> = hosts
+            .into_iter()
+            .map(|(ip, ports)| {
+                scope.spawn(move || {
+                    let answers = ip.parse::<Ipv4Addr>().map_err(|e| e.to_string()).and_then(crate::dhcp::conflict::answers);
+                    (ip, ports, answers)
+                })
+            })
+            .collect();
+        handles.into_iter().filter_map(|h| h.join().ok()).collect()
+    });
+
+    probed
+        .into_iter()
+        .filter_map(|(ip, ports, answers)| match answers {
+            Ok(true) => None,
+            Ok(false) => Some(finding(
+                "port_forwards",
+                Severity::Warning,
+                &ip,
+                format!("Nothing answers at {}, which port(s) {} forward to", ip, ports.join(", ")),
+                Some("Check the device is on, or disable the forward"),
+            )),
+            Err(e) => Some(finding(
+                "port_forwards",
+                Severity::Info,
+                &ip,
+                format!("Could not check whether {} is online: {}", ip, e),
+                None,
+            )),
+        })
+        .collect()
+}
+
+fn check_blocklists(sets: Vec<String>) -> Vec<Finding> {
+    sets.into_iter()
+        .filter(|set| {
+            let listed = sudo().args(["ipset", "list", set, "-t"]).output();
+            !listed.map(|o| o.status.success()).unwrap_or(false)
+        })
+        .map(|set| {
+            finding(
+                "blocklists",
+                Severity::Error,
+                &set,
+                format!("{} is enabled but its ipset is missing, so nothing on it is blocked", set),
+                Some("Update the list on the Protection page to load it again"),
+            )
+        })
+        .collect()
+}
+
+async fn check_certificates(pool: &SqlitePool) -> Vec<Finding> {
+    let settings = certwatch::load_settings();
+    certwatch::inventory(pool, &settings)
+        .await
+        .into_iter()
+        .filter_map(|cert| {
+            let subject = format!("{} ({})", cert.name, cert.location);
+            match cert.status {
+                CertStatus::Valid => None,
+                CertStatus::Expired => Some(finding(
+                    "certificates",
+                    Severity::Error,
+                    subject,
+                    format!("{} expired on {}", cert.name, cert.expires_at.unwrap_or_default()),
+                    Some("Renew it on the Certificates page"),
+                )),
+                CertStatus::Expiring => Some(finding(
+                    "certificates",
+                    Severity::Warning,
+                    subject,
+                    format!("{} expires in {} day(s)", cert.name, cert.days_left.unwrap_or_default()),
+                    Some("Renew it on the Certificates page"),
+                )),
+                // A monitored service being down is the monitors' business, not a config problem
+                CertStatus::Error if matches!(cert.source, CertSource::Endpoint) => None,
+                CertStatus::Error => Some(finding(
+                    "certificates",
+                    Severity::Error,
+                    subject,
+                    format!("{} could not be read: {}", cert.name, cert.error.unwrap_or_default()),
+                    Some("Reissue it, or point RouterUI at the right file"),
+                )),
+            }
+        })
+        .collect()
+}
+
+// ============ RUN ============
+
+/// Run every check and keep the report as the latest
+pub async fn run(state: &AppState) -> LintReport {
+    let started = Instant::now();
+    let zones = crate::zones::load(&state.db).await;
+    let subnets = crate::subnets::load_settings(&state.db).await;
+    let sets = crate::api::protection::list_sets(&state.db, true).await.unwrap_or_default();
+
+    let blocking = tokio::task::spawn_blocking(move || {
+        let mut findings = check_interfaces(&zones, &subnets);
+        findings.extend(check_static_leases(zones.wan()));
+        findings.extend(check_port_forwards());
+        findings.extend(check_blocklists(sets));
+        findings
+    });
+    let mut findings = check_certificates(&state.db).await;
+    findings.extend(blocking.await.unwrap_or_default());
+
+    // Stable order: most severe first, then by check and subject
+    findings.sort_by(|a, b| b.severity.cmp(&a.severity).then(a.check.cmp(b.check)).then(a.subject.cmp(&b.subject)));
+
+    let report = LintReport {
+        checked_at: chrono::Utc::now().to_rfc3339(),
+        duration_ms: started.elapsed().as_millis() as u64,
+        errors: findings.iter().filter(|f| f.severity == Severity::Error).count(),
+        warnings: findings.iter().filter(|f| f.severity == Severity::Warning).count(),
+        findings,
+    };
+    *state.lint.last.lock().unwrap() = Some(report.clone());
+    report
+}
+
+/// One pass at startup, logged so problems show up in the journal even if nobody opens the UI
+pub fn spawn(state: Arc<AppState>) {
+    tokio::spawn(async move {
+        let report = run(&state).await;
+        for f in report.findings.iter().filter(|f| f.severity > Severity::Info) {
+            tracing::warn!("Config lint ({}): {}", f.check, f.message);
+        }
+        tracing::info!("Config lint: {} error(s), {} warning(s)", report.errors, report.warnings);
+    });
+}
@@ -0,0 +1,348 @@
+use axum::{
+    body::Body,
+    extract::Request,
+    http::{header, HeaderName, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::io::Write;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+use tracing_subscriber::{
+    field::RecordFields,
+    fmt::{format::{DefaultFields, Writer}, FormatFields, MakeWriter},
+    layer::SubscriberExt,
+    reload,
+    util::SubscriberInitExt,
+    EnvFilter, Registry,
+};
+
+use crate::config::LogFormat;
+
+// Recent application log kept in memory for download from the UI
+const BUFFER_LINES: usize = 5000;
+const MAX_DEBUG_WINDOW: Duration = Duration::from_secs(2 * 60 * 60);
+
+// ============ IN-MEMORY LOG BUFFER ============
+
+#[derive(Clone, Default)]
+pub struct LogBuffer {
+    lines: Arc<Mutex<VecDeque<String>>>,
+}
+
+impl LogBuffer {
+    pub fn contents(&self) -> String {
+        let lines = self.lines.lock().unwrap();
+        lines.iter().map(|l| l.as_str()).collect()
+    }
+}
+
+pub struct BufferWriter {
+    buffer: LogBuffer,
+    pending: Vec<u8>,
+}
+
+impl Write for BufferWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.pending.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+// The fmt layer writes one event per writer, so push the whole line on drop
+impl Drop for BufferWriter {
+    fn drop(&mut self) {
+        if self.pending.is_empty() {
+            return;
+        }
+        let line = String::from_utf8_lossy(&self.pending).into_owned();
+        let mut lines = self.buffer.lines.lock().unwrap();
+        if lines.len() == BUFFER_LINES {
+            lines.pop_front();
+        }
+        lines.push_back(line);
+    }
+}
+
+impl<'a> MakeWriter<'a> for LogBuffer {
+    type Writer = BufferWriter;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        BufferWriter { buffer: self.clone(), pending: Vec::new() }
+    }
+}
+
+// Span fields are formatted once per formatter type and shared between layers, so the buffer
+// needs its own type or it inherits the stdout layer's ANSI colours
+#[derive(Default)]
+struct PlainFields(DefaultFields);
+
+impl<'writer> FormatFields<'writer> for PlainFields {
+    fn format_fields<R: RecordFields>(&self, writer: Writer<'writer>, fields: R) -> std::fmt::Result {
+        self.0.format_fields(writer, fields)
+    }
+}
+
+// ============ REQUEST IDS ============
+
+/// ID of an API request, in its extensions; logged with everything the request causes,
+/// including the commands routerui-helper runs for it
+#[derive(Debug, Clone)]
+pub struct RequestId(pub String);
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+    // Method and path, without the query string
+    static REQUEST_LINE: String;
+}
+
+/// ID of the request being handled on this task, if any
+pub fn current_request_id() -> Option<String> {
+    REQUEST_ID.try_with(|id| id.clone()).ok()
+}
+
+/// "POST /api/firewall/port-forwards" for the request being handled on this task, if any
+pub fn current_request_line() -> Option<String> {
+    REQUEST_LINE.try_with(|line| line.clone()).ok()
+}
+
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+// Error bodies are short messages; anything bigger is passed through untouched
+const MAX_TAGGED_BODY: usize = 64 * 1024;
+
+// A reverse proxy may already have named the request; keep its ID if it's something sane
+fn incoming_request_id(request: &Request) -> Option<String> {
+    let id = request.headers().get(&REQUEST_ID_HEADER)?.to_str().ok()?;
+    let valid = !id.is_empty() && id.len() <= 64 && id.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    valid.then(|| id.to_string())
+}
+
+/// Outermost layer: gives every request an ID before the trace span is created, and hands it
+/// back in the X-Request-Id header and in error bodies, so "I got an error at 14:32" can be
+/// matched to the log
+pub async fn assign_request_id(mut request: Request, next: Next) -> Response {
+    let id = incoming_request_id(&request).unwrap_or_else(|| uuid::Uuid::new_v4().simple().to_string()[..16].to_string());
+    request.extensions_mut().insert(RequestId(id.clone()));
+    let line = format!("{} {}", request.method(), request.uri().path());
+    let mut response = REQUEST_ID.scope(id.clone(), REQUEST_LINE.scope(line, next.run(request))).await;
+
+    if response.status().is_client_error() || response.status().is_server_error() {
+        response = tag_error(response, &id).await;
+    }
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    response
+}
+
+// Plain-text errors get the ID appended, JSON objects a request_id field. 5xx messages are
+// logged as well: the handler returned them, so nothing else has
+async fn tag_error(response: Response, id: &str) -> Response {
+    let (mut parts, body) = response.into_parts();
+    let content_type = parts
+        .headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("")
+        .to_string();
+    let taggable = (content_type.starts_with("text/plain") || content_type.starts_with("application/json"))
+        && !parts.headers.contains_key(header::CONTENT_ENCODING);
+    if !taggable {
+        return Response::from_parts(parts, body);
+    }
+
+    let bytes = match axum::body::to_bytes(body, MAX_TAGGED_BODY).await {
+        Ok(bytes) => bytes,
+        Err(_) => return Response::from_parts(parts, Body::empty()),
+    };
+    let message = String::from_utf8_lossy(&bytes).into_owned();
+    if parts.status.is_server_error() {
+        tracing::warn!(status = parts.status.as_u16(), "{}", message);
+    }
+
+    let tagged = if content_type.starts_with("application/json") {
+        match serde_json::from_slice::<serde_json::Value>(&bytes) {
+            Ok(serde_json::Value::Object(mut object)) => {
+                object.entry("request_id").or_insert_with(|| id.into());
+                serde_json::to_vec(&object).unwrap_or_else(|_| bytes.to_vec())
+            }
+            _ => bytes.to_vec(),
+        }
+    } else if message.trim().is_empty() {
+        format!("Request ID {}", id).into_bytes()
+    } else {
+        format!("{} (request ID {})", message.trim_end(), id).into_bytes()
+    };
+
+    parts.headers.remove(header::CONTENT_LENGTH);
+    Response::from_parts(parts, Body::from(tagged))
+}
+
+/// TraceLayer span carrying the request ID alongside the usual method and URI
+pub fn request_span(request: &Request) -> tracing::Span {
+    let id = request.extensions().get::<RequestId>().map(|r| r.0.as_str()).unwrap_or("-");
+    tracing::info_span!("request", request_id = %id, method = %request.method(), uri = %request.uri())
+}
+
+// ============ RUNTIME FILTER CONTROL ============
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DebugWindow {
+    pub filter: String,
+    pub until: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LoggingStatus {
+    pub filter: String,
+    pub default_filter: String,
+    pub debug_window: Option<DebugWindow>,
+}
+
+struct FilterState {
+    // Filter to return to when a debug window ends
+    base: String,
+    active: String,
+    debug_until: Option<DateTime<Utc>>,
+    // Bumped on every change so a stale auto-revert timer doesn't undo a newer setting
+    generation: u64,
+}
+
+pub struct LogControl {
+    handle: reload::Handle<EnvFilter, Registry>,
+    default_filter: String,
+    state: Mutex<FilterState>,
+    buffer: LogBuffer,
+}
+
+static CONTROL: OnceLock<LogControl> = OnceLock::new();
+
+/// Install the global subscriber: stdout (text or JSON) plus the in-memory buffer, behind a
+/// reloadable filter
+pub fn init(filter: &str, format: LogFormat) {
+    // An unparseable filter falls back to "info"; remember that one so reset() can reapply it
+    let (env_filter, filter) = match EnvFilter::try_new(filter) {
+        Ok(f) => (f, filter),
+        Err(_) => (EnvFilter::new("info"), "info"),
+    };
+    let (filter_layer, handle) = reload::Layer::new(env_filter);
+    let buffer = LogBuffer::default();
+
+    let json = format == LogFormat::Json;
+    tracing_subscriber::registry()
+        .with(filter_layer)
+        .with((!json).then(tracing_subscriber::fmt::layer))
+        // The enclosing request span (and its request_id) is attached to every event
+        .with(json.then(|| tracing_subscriber::fmt::layer().json().with_current_span(true).with_span_list(false)))
+        .with(
+            tracing_subscriber::fmt::layer()
+                .with_ansi(false)
+                .fmt_fields(PlainFields::default())
+                .with_writer(buffer.clone()),
+        )
+        .init();
+
+    let _ = CONTROL.set(LogControl {
+        handle,
+        default_filter: filter.to_string(),
+        state: Mutex::new(FilterState {
+            base: filter.to_string(),
+            active: filter.to_string(),
+            debug_until: None,
+            generation: 0,
+        }),
+        buffer,
+    });
+}
+
+/// None when running without init() (e.g. routerui-cli)
+pub fn control() -> Option<&'static LogControl> {
+    CONTROL.get()
+}
+
+impl LogControl {
+    pub fn status(&self) -> LoggingStatus {
+        let state = self.state.lock().unwrap();
+        LoggingStatus {
+            filter: state.active.clone(),
+            default_filter: self.default_filter.clone(),
+            debug_window: state.debug_until.map(|until| DebugWindow {
+                filter: state.active.clone(),
+                until: until.to_rfc3339(),
+            }),
+        }
+    }
+
+    fn apply(&self, filter: &str) -> Result<(), String> {
+        let env_filter = EnvFilter::try_new(filter).map_err(|e| e.to_string())?;
+        self.handle.reload(env_filter).map_err(|e| e.to_string())
+    }
+
+    /// Replace the filter until the next change or restart (cancels any debug window)
+    pub fn set_filter(&self, filter: &str) -> Result<(), String> {
+        self.apply(filter)?;
+        let mut state = self.state.lock().unwrap();
+        state.base = filter.to_string();
+        state.active = filter.to_string();
+        state.debug_until = None;
+        state.generation += 1;
+        tracing::info!("Log filter set to '{}'", filter);
+        Ok(())
+    }
+
+    pub fn reset(&self) -> Result<(), String> {
+        self.set_filter(&self.default_filter.clone())
+    }
+
+    /// Switch to `filter` for `duration`, then revert to whatever was set before
+    pub fn start_debug_window(&'static self, filter: &str, duration: Duration) -> Result<DebugWindow, String> {
+        let duration = duration.min(MAX_DEBUG_WINDOW);
+        self.apply(filter)?;
+
+        let until = Utc::now() + chrono::Duration::from_std(duration).unwrap_or_default();
+        let generation = {
+            let mut state = self.state.lock().unwrap();
+            state.active = filter.to_string();
+            state.debug_until = Some(until);
+            state.generation += 1;
+            state.generation
+        };
+        tracing::info!("Debug logging '{}' enabled until {}", filter, until.to_rfc3339());
+
+        tokio::spawn(async move {
+            tokio::time::sleep(duration).await;
+            self.end_debug_window(generation);
+        });
+
+        Ok(DebugWindow { filter: filter.to_string(), until: until.to_rfc3339() })
+    }
+
+    fn end_debug_window(&self, generation: u64) {
+        let base = {
+            let mut state = self.state.lock().unwrap();
+            if state.generation != generation {
+                return;
+            }
+            state.active = state.base.clone();
+            state.debug_until = None;
+            state.generation += 1;
+            state.base.clone()
+        };
+        if let Err(e) = self.apply(&base) {
+            tracing::error!("Failed to revert log filter: {}", e);
+        } else {
+            tracing::info!("Debug logging window ended, filter back to '{}'", base);
+        }
+    }
+
+    pub fn recent_log(&self) -> String {
+        self.buffer.contents()
+    }
+}
@@ -1,36 +1,38 @@
-mod api;
-mod auth;
-mod db;
-mod mock;
-mod models;
-mod system;
-
 use axum::{
-    routing::{get, post},
+    middleware,
+    routing::{delete, get, post},
     Router,
 };
+use axum_server::tls_rustls::RustlsConfig;
 use sqlx::sqlite::SqlitePoolOptions;
 use std::sync::Arc;
+use tower::ServiceBuilder;
+use tower_http::compression::CompressionLayer;
 use tower_http::cors::{Any, CorsLayer};
 use tower_http::trace::TraceLayer;
 use tower_http::services::{ServeDir, ServeFile};
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
-pub struct AppState {
-    pub db: sqlx::SqlitePool,
-}
+use routerui_api::{acme, api, approvals, auth, cache, certwatch, config, connlog, db, dhcp, discovery, dnsguard, events, geoip, guestcodes, health, homelab, honeypot, lanpages, lint, logging, metrics, mock, modem, monitors, power, presence, public_status, qos, scheduler, stats, syslog, system, undo, wan, AppState};
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    tracing_subscriber::registry()
-        .with(tracing_subscriber::EnvFilter::new(
-            std::env::var("RUST_LOG").unwrap_or_else(|_| "routerui_api=debug,tower_http=debug".into()),
-        ))
-        .with(tracing_subscriber::fmt::layer())
-        .init();
+    let config = config::get();
+
+    logging::init(&config.log_level, config.log_format);
+
+    // Refuse to start half-configured; list every problem at once
+    if !config.errors.is_empty() {
+        for error in &config.errors {
+            tracing::error!("Config error: {}", error);
+        }
+        return Err(format!("{} configuration error(s), not starting", config.errors.len()).into());
+    }
+    match &config.source {
+        Some(path) => tracing::info!("Loaded config from {}", path.display()),
+        None => tracing::info!("No config file found, using defaults and environment"),
+    }
 
-    let db_path = std::env::var("DATABASE_URL")
-        .unwrap_or_else(|_| "sqlite:/opt/routerui/config/routerui.db?mode=rwc".to_string());
+    let db_path = db::database_url();
 
     let pool = SqlitePoolOptions::new()
         .max_connections(5)
@@ -38,21 +40,85 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .await?;
 
     db::migrate(&pool).await?;
+    system::drift::init(&pool).await?;
+    auth::token_key::init()?;
+    if let Err(e) = api::protection::import_legacy_state(&pool).await {
+        tracing::error!("Importing protection state from JSON files failed: {}", e);
+    }
     auth::create_default_admin(&pool).await?;
 
-    let state = Arc::new(AppState { db: pool });
+    let state = Arc::new(AppState {
+        db: pool,
+        started_at: chrono::Utc::now(),
+        tasks: health::TaskRegistry::new(),
+        api_stats: stats::ApiStats::new(),
+        cache: cache::ResponseCache::new(),
+        events: events::EventBus::new(),
+        wan: wan::WanTracker::new(),
+        setup_guard: auth::setup_token::SetupGuard::new(),
+        bruteforce: auth::bruteforce::BruteForceGuard::new(),
+        oidc: auth::oidc::OidcLogins::new(),
+        approvals: approvals::ApprovalQueue::new(),
+        undo: undo::UndoStore::new(),
+        modem: modem::ModemTracker::new(),
+        presence: presence::PresenceTracker::new(),
+        certs: certwatch::CertTracker::new(),
+        honeypot: honeypot::HoneypotTracker::new(),
+        management_access: system::management_access::AccessCache::new(),
+        monitors: monitors::MonitorTracker::new(),
+        lan_pages: lanpages::LanPagesServer::new(),
+        homelab: homelab::HostTracker::new(),
+        public_status: public_status::PublicStatusTracker::new(),
+        lint: lint::LintTracker::new(),
+    });
+
+    state.setup_guard.prepare(&state.db).await;
+
+    health::spawn_watchdog(state.clone());
+    db::maintenance::spawn(state.clone());
+    auth::session_cleanup::spawn(state.clone());
+    if !mock::is_mock_mode() {
+        wan::spawn(state.clone());
+        acme::spawn(state.clone());
+        scheduler::spawn(state.clone());
+        dhcp::spawn(state.clone());
+        connlog::spawn(state.clone());
+        power::spawn(state.clone());
+        metrics::spawn(state.clone());
+        modem::spawn(state.clone());
+        presence::spawn(state.clone());
+        certwatch::spawn(state.clone());
+        monitors::spawn(state.clone());
+        lanpages::spawn(state.clone());
+        homelab::spawn(state.clone());
+        geoip::spawn(state.clone());
+        honeypot::spawn(state.clone());
+        dnsguard::spawn(state.clone());
+        qos::spawn(state.clone());
+        public_status::spawn(state.clone());
+        guestcodes::spawn(state.clone());
+        syslog::spawn(state.clone());
+        lint::spawn(state.clone());
+    }
 
     let cors = CorsLayer::new()
         .allow_origin(Any)
         .allow_methods(Any)
-        .allow_headers(Any);
+        .allow_headers(Any)
+        .expose_headers([logging::REQUEST_ID_HEADER, auth::csrf::REJECTED_HEADER]);
 
-    let frontend_dir = std::env::var("FRONTEND_DIR")
-        .unwrap_or_else(|_| "/opt/routerui/frontend/build".to_string());
+    let frontend_dir = config.frontend_dir.display().to_string();
 
     let app = Router::new()
+        // Health (no auth required, for systemd and uptime monitors)
+        .route("/api/health", get(api::health::health))
+        // Opt-in status summary for external status pages (no auth, rate-limited)
+        .route(public_status::PATH, get(api::public::status))
         // Setup wizard routes (no auth required)
         .route("/api/setup/status", get(api::setup::status))
+        .route("/api/setup/banner", get(api::setup::banner))
+        .route("/api/setup/preflight", get(api::setup::preflight))
+        .route("/api/setup/verify-token", post(api::setup::verify_token))
         .route("/api/setup/interfaces", get(api::setup::get_interfaces))
         .route("/api/setup/admin", post(api::setup::create_admin))
         .route("/api/setup/configure-router", post(api::setup::configure_router))
@@ -64,21 +130,84 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .route("/api/addons/install", post(api::addons::install))
         // Auth routes
         .route("/api/auth/login", post(api::auth::login))
+        .route("/api/auth/refresh", post(api::auth::refresh))
         .route("/api/auth/logout", post(api::auth::logout))
         .route("/api/auth/me", get(api::auth::me))
+        .route("/api/auth/csrf", get(api::auth::csrf))
+        .route("/api/auth/password", post(api::auth::change_password))
+        .route("/api/auth/recovery-code", post(api::auth::recovery_code))
+        .route("/api/auth/recover", post(api::auth::recover))
+        .route("/api/auth/sessions", get(api::auth::sessions))
+        .route("/api/auth/sessions/revoke-others", post(api::auth::revoke_other_sessions))
+        .route("/api/auth/sessions/forget-devices", post(api::auth::forget_devices))
+        .route("/api/auth/session-policy", get(api::auth::session_policy).post(api::auth::update_session_policy))
+        .route("/api/auth/password-policy", get(api::auth::password_policy).post(api::auth::update_password_policy))
+        .route("/api/auth/sessions/{id}", delete(api::auth::revoke_session))
+        .route("/api/auth/oidc/status", get(api::auth::oidc_status))
+        .route("/api/auth/oidc/login", get(api::auth::oidc_login))
+        .route("/api/auth/oidc/callback", get(api::auth::oidc_callback))
+        .route("/api/auth/oidc/settings", get(api::auth::oidc_settings).post(api::auth::update_oidc_settings))
+        .route("/api/auth/ldap/settings", get(api::auth::ldap_settings).post(api::auth::update_ldap_settings))
         // User management
         .route("/api/users", get(api::users::list).post(api::users::create))
+        .route("/api/users/permissions", get(api::users::permission_catalog))
+        .route("/api/users/password-strength", post(api::users::check_password_strength))
+        .route("/api/users/sessions/cleanup", post(api::users::cleanup_sessions))
         .route("/api/users/{id}", get(api::users::get)
             .put(api::users::update)
             .delete(api::users::delete))
+        .route("/api/users/{id}/logins", get(api::users::logins))
+        // API keys for scripts
+        .route("/api/tokens", get(api::tokens::list).post(api::tokens::create))
+        .route("/api/tokens/{id}", delete(api::tokens::revoke))
         // System status
-        .route("/api/system/status", get(api::system::status))
+        .route("/api/system/status", get(api::system::status).layer(middleware::from_fn(cache::etag)))
         .route("/api/system/interfaces", get(api::system::interfaces))
         .route("/api/system/services", get(api::system::services))
+        .route("/api/system/about", get(api::system::about))
+        .route("/api/system/api-stats", get(api::system::api_stats))
+        .route("/api/system/api-stats/reset", post(api::system::reset_api_stats))
+        .route("/api/system/db", get(api::system::db_info).post(api::system::compact_db))
+        .route("/api/system/wan-ip", get(api::system::wan_ip))
+        .route("/api/system/wan-ip/history", get(api::system::wan_ip_history))
+        .route("/api/system/listening", get(api::system::listening))
+        .route("/api/system/logging", get(api::system::logging_status).post(api::system::set_logging))
+        .route("/api/system/logging/debug", post(api::system::debug_logging))
+        .route("/api/system/logging/reset", post(api::system::reset_logging))
+        .route("/api/system/logging/download", get(api::system::download_log))
+        .route("/api/system/logging/remote", get(api::system::remote_syslog).post(api::system::set_remote_syslog))
+        .route("/api/system/logging/remote/test", post(api::system::test_remote_syslog))
+        .route("/api/system/approvals", get(api::approvals::status))
+        .route("/api/system/approvals/settings", post(api::approvals::update_settings))
+        .route("/api/system/approvals/approve", post(api::approvals::approve))
+        .route("/api/system/approvals/reject", post(api::approvals::reject))
+        .route("/api/system/factory-reset", post(api::system::factory_reset))
+        .route("/api/system/profiles", get(api::profiles::list).post(api::profiles::save))
+        .route("/api/system/profiles/remove", post(api::profiles::remove))
+        .route("/api/system/profiles/activate", post(api::profiles::activate))
+        .route("/api/system/profiles/default", post(api::profiles::set_default))
+        .route("/api/system/privacy", get(api::system::privacy).post(api::system::set_privacy))
+        .route("/api/system/maintenance-mode", get(api::system::maintenance_mode).post(api::system::set_maintenance_mode))
+        .route("/api/system/drift", get(api::system::drift).post(api::system::resolve_drift))
+        .route("/api/system/lint", get(api::system::lint))
+        .route("/api/system/public-status", get(api::system::public_status).post(api::system::set_public_status))
+        .route("/api/system/management-access", get(api::system::management_access).post(api::system::set_management_access))
+        .route("/api/system/privileges", get(api::system::privileges))
+        .route("/api/system/privileges/sudoers", get(api::system::sudoers_policy))
+        .route("/api/system/commands", get(api::system::commands))
+        // Certificates (ACME DNS-01)
+        .route("/api/certificates", get(api::certificates::list))
+        .route("/api/certificates/settings", post(api::certificates::save_settings))
+        .route("/api/certificates/issue", post(api::certificates::issue))
+        .route("/api/certificates/renew", post(api::certificates::renew))
+        .route("/api/certificates/delete", post(api::certificates::delete))
         .route("/api/system/updates/check", post(api::system::check_updates))
         .route("/api/system/updates/install", post(api::system::install_updates))
         // Dashboard
         .route("/api/dashboard", get(api::dashboard::overview))
+        .route("/api/dashboard/power", get(api::dashboard::power))
+        .route("/api/metrics", get(api::metrics::list))
+        .route("/api/metrics/history", get(api::metrics::history))
         // AdGuard Home
         .route("/api/adguard/overview", get(api::adguard::overview))
         .route("/api/adguard/protection", post(api::adguard::toggle_protection))
@@ -93,10 +222,29 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .route("/api/firewall/port-forwards", get(api::firewall::port_forwards))
         .route("/api/firewall/port-forwards/add", post(api::firewall::add_port_forward))
         .route("/api/firewall/port-forwards/remove", post(api::firewall::remove_port_forward))
+        .route("/api/firewall/port-forwards/enabled", post(api::firewall::set_port_forward_enabled))
+        .route("/api/firewall/port-forwards/schedule", post(api::firewall::set_port_forward_schedule))
+        .route("/api/firewall/port-forwards/logging", post(api::firewall::set_port_forward_logging))
+        .route("/api/firewall/port-forwards/access", get(api::firewall::port_forward_access))
         .route("/api/firewall/blocked-ips", get(api::firewall::blocked_ips))
         .route("/api/firewall/blocked-ips/add", post(api::firewall::add_blocked_ip))
         .route("/api/firewall/blocked-ips/remove", post(api::firewall::remove_blocked_ip))
+        .route("/api/firewall/blocked-ips/enabled", post(api::firewall::set_blocked_ip_enabled))
+        .route("/api/firewall/blocked-ips/schedule", post(api::firewall::set_blocked_ip_schedule))
+        .route("/api/firewall/blocked-ips/bulk", post(api::firewall::bulk_blocked_ips))
         .route("/api/firewall/rules", get(api::firewall::raw_rules))
+        .route("/api/firewall/ipv6", get(api::firewall::ipv6))
+        .route("/api/firewall/ipv6/blocked/add", post(api::firewall::add_ipv6_block))
+        .route("/api/firewall/ipv6/blocked/remove", post(api::firewall::remove_ipv6_block))
+        .route("/api/firewall/ipv6/pinholes/add", post(api::firewall::add_pinhole))
+        .route("/api/firewall/ipv6/pinholes/remove", post(api::firewall::remove_pinhole))
+        .route("/api/firewall/zones", get(api::firewall::zones).post(api::firewall::set_zones))
+        .route("/api/firewall/templates", get(api::firewall::templates))
+        .route("/api/firewall/templates/apply", post(api::firewall::apply_template))
+        .route("/api/firewall/groups", get(api::firewall::groups))
+        .route("/api/firewall/groups/remove", post(api::firewall::remove_group))
+        .route("/api/firewall/orphans", get(api::firewall::orphans))
+        .route("/api/firewall/orphans/remove", post(api::firewall::remove_orphans))
         .route("/api/firewall/dmz", get(api::firewall::dmz_status))
         .route("/api/firewall/dmz/set", post(api::firewall::set_dmz))
         .route("/api/firewall/pending", get(api::firewall::pending))
@@ -114,7 +262,10 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .route("/api/protection/quick-allow", post(api::protection::quick_allow))
         .route("/api/protection/countries", get(api::protection::countries))
         .route("/api/protection/countries/toggle", post(api::protection::toggle_country))
+        .route("/api/protection/countries/policy", get(api::protection::country_policy).post(api::protection::set_country_policy))
         .route("/api/protection/enable-logging", post(api::protection::enable_logging))
+        .route("/api/protection/honeypot", get(api::protection::honeypot).post(api::protection::update_honeypot))
+        .route("/api/protection/honeypot/unban", post(api::protection::honeypot_unban))
         // Antivirus
         .route("/api/antivirus/status", get(api::antivirus::status))
         .route("/api/antivirus/update", post(api::antivirus::update_signatures))
@@ -125,17 +276,50 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .route("/api/antivirus/quarantine/action", post(api::antivirus::quarantine_action))
         .route("/api/antivirus/daemon", post(api::antivirus::toggle_daemon))
         // Network
-        .route("/api/network/interfaces", get(api::network::interfaces))
-        .route("/api/network/dhcp", get(api::network::dhcp_status))
+        .route("/api/network/interfaces", get(api::network::interfaces).layer(middleware::from_fn(cache::etag)))
+        .route("/api/network/interfaces/roles", get(api::network::interface_roles).post(api::network::update_interface_roles))
+        .route("/api/network/subnets", get(api::network::subnets).post(api::network::update_subnets))
+        // Traffic shaping (SQM)
+        .route("/api/qos", get(api::qos::status).post(api::qos::update))
+        .route("/api/network/dhcp", get(api::network::dhcp_status).layer(middleware::from_fn(cache::etag)))
         .route("/api/network/dhcp/config", post(api::network::update_dhcp_config))
         .route("/api/network/dhcp/static/add", post(api::network::add_static_lease))
         .route("/api/network/dhcp/static/remove", post(api::network::remove_static_lease))
+        .route("/api/network/dhcp/static/import", post(api::network::import_static_leases))
+        .route("/api/network/dhcp/pool", get(api::network::dhcp_pool))
+        .route("/api/network/dhcp/options", get(api::network::dhcp_options).post(api::network::update_dhcp_options))
         .route("/api/network/wifi", get(api::network::wifi_status))
         .route("/api/network/wifi/update", post(api::network::update_wifi))
         .route("/api/network/wifi/toggle", post(api::network::toggle_wifi))
+        .route("/api/network/aps", get(api::mesh::list).post(api::mesh::adopt))
+        .route("/api/network/aps/remove", post(api::mesh::remove))
+        .route("/api/network/aps/sync", post(api::mesh::sync))
+        .route("/api/network/modem", get(api::modem::status))
+        .route("/api/network/modem/settings", post(api::modem::update_settings))
+        .route("/api/network/modem/connect", post(api::modem::connect))
+        .route("/api/network/modem/disconnect", post(api::modem::disconnect))
+        .route("/api/network/modem/sms", get(api::modem::sms_list))
+        .route("/api/network/modem/sms/send", post(api::modem::sms_send))
+        .route("/api/network/modem/sms/delete", post(api::modem::sms_delete))
+        .route("/api/network/wan", get(api::wan::status))
+        .route("/api/network/wan/settings", post(api::wan::update_settings))
+        .route("/api/network/presence", get(api::presence::status))
+        .route("/api/network/presence/settings", post(api::presence::update_settings))
+        .route("/api/network/wifi/radios", get(api::network::wifi_radios))
+        .route("/api/network/wifi/clients", get(api::network::wifi_clients_list))
+        .route("/api/network/wifi/advanced", get(api::network::wifi_advanced).post(api::network::update_wifi_advanced))
+        .route("/api/network/wifi/wps", get(api::network::wps_status).post(api::network::wps_action))
+        .route("/api/network/wifi/schedule", get(api::network::wifi_schedule).post(api::network::update_wifi_schedule))
+        .route("/api/network/wifi/guest-codes", get(api::network::guest_codes).post(api::network::create_guest_code))
+        .route("/api/network/wifi/guest-codes/revoke", post(api::network::revoke_guest_code))
+        .route("/api/network/wifi/guest-codes/settings", post(api::network::update_guest_code_settings))
         .route("/api/network/dns", get(api::network::dns_status))
         .route("/api/network/dns/local/add", post(api::network::add_local_dns))
         .route("/api/network/dns/local/remove", post(api::network::remove_local_dns))
+        .route("/api/network/dns/rebind", get(api::network::dns_rebind).post(api::network::update_dns_rebind))
+        .route("/api/network/dns/safe-search", get(api::network::safe_search).post(api::network::update_safe_search))
+        .route("/api/network/dns/bypass", get(api::network::dns_bypass).post(api::network::update_dns_bypass))
+        .route("/api/network/dns/pages", get(api::network::lan_pages).post(api::network::update_lan_pages))
         .route("/api/network/routes", get(api::network::routes))
         .route("/api/network/routes/add", post(api::network::add_route))
         .route("/api/network/routes/remove", post(api::network::remove_route))
@@ -143,15 +327,25 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .route("/api/network/wol/add", post(api::network::add_wol_device))
         .route("/api/network/wol/remove", post(api::network::remove_wol_device))
         .route("/api/network/wol/wake", post(api::network::wake_device))
+        .route("/api/network/wol/history", get(api::network::wol_history))
         // Services Management
         .route("/api/services", get(api::services::list))
         .route("/api/services/all", get(api::services::list_all))
         .route("/api/services/action", post(api::services::action))
         .route("/api/services/logs", post(api::services::logs))
         .route("/api/services/status", post(api::services::status))
+        .route("/api/services/monitors", get(api::monitors::list))
+        .route("/api/services/monitors/settings", post(api::monitors::update_settings))
+        .route("/api/services/monitors/{id}/history", get(api::monitors::history))
+        .route("/api/services/monitors/{id}/check", post(api::monitors::check_now))
+        .route("/api/services/hosts", get(api::homelab::list))
+        .route("/api/services/hosts/add", post(api::homelab::add))
+        .route("/api/services/hosts/remove", post(api::homelab::remove))
+        .route("/api/services/hosts/{id}/action", post(api::homelab::action))
+        .route("/api/services/hosts/{id}/wake", post(api::homelab::wake))
         // Docker
         .route("/api/docker/status", get(api::docker::status))
-        .route("/api/docker/containers", get(api::docker::containers))
+        .route("/api/docker/containers", get(api::docker::containers).layer(middleware::from_fn(cache::etag)))
         .route("/api/docker/containers/action", post(api::docker::container_action))
         .route("/api/docker/containers/logs", post(api::docker::container_logs))
         .route("/api/docker/images", get(api::docker::images))
@@ -176,7 +370,9 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .route("/api/tools/ping", post(api::tools::ping))
         .route("/api/tools/traceroute", post(api::tools::traceroute))
         .route("/api/tools/dns-lookup", post(api::tools::dns_lookup))
+        .route("/api/tools/ip-info", post(api::tools::ip_info))
         .route("/api/tools/speed-test", post(api::tools::speed_test))
+        .route("/api/tools/lan-throughput", post(api::tools::lan_throughput))
         // Tools - System Logs
         .route("/api/tools/logs", post(api::tools::logs))
         .route("/api/tools/logs/units", get(api::tools::log_units))
@@ -186,27 +382,83 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .route("/api/tools/backup/download", post(api::tools::download_backup))
         .route("/api/tools/backup/restore", post(api::tools::restore_backup))
         .route("/api/tools/backup/delete", post(api::tools::delete_backup))
+        .route("/api/tools/backup/delete-bulk", post(api::tools::delete_backups))
         // Security Monitor
         .route("/api/security/overview", get(api::security::overview))
         .route("/api/security/feed", get(api::security::live_feed))
         .route("/api/security/connections", get(api::security::connections))
+        .route("/api/security/connection-log", get(api::security::connection_log).post(api::security::update_connection_log))
+        .route("/api/security/connection-log/export", get(api::security::export_connection_log))
+        .route("/api/security/bruteforce", get(api::security::bruteforce_blocks))
+        .route("/api/security/bruteforce/unblock", post(api::security::bruteforce_unblock))
+        .route("/api/security/incident-report", get(api::security::incident_report))
+        .route("/api/security/certificates", get(api::security::certificates))
+        .route("/api/security/certificates/settings", post(api::security::update_certificate_monitor))
+        .route("/api/security/ipv6", get(api::security::ipv6_posture))
+        .route("/api/security/ipv6/fix", post(api::security::fix_ipv6_posture))
+        // Runtime settings, addressed as "<section>.<field>"
+        .route("/api/settings", get(api::settings::get_settings).put(api::settings::update_settings))
+        // Undo recent removals (static leases, local DNS, WoL devices, whitelist)
+        .route("/api/undo", get(api::undo::pending).post(api::undo::undo))
         // Media Center
         .route("/api/media/overview", get(api::media::overview))
         // Middleware
+        .route_layer(middleware::from_fn(system::privileges::explain_denials))
+        .route_layer(middleware::from_fn(system::maintenance_mode::enforce))
+        .route_layer(middleware::from_fn(auth::permissions::enforce))
+        .route_layer(middleware::from_fn(auth::csrf::enforce))
+        .route_layer(middleware::from_fn(syslog::audit_requests))
+        .route_layer(middleware::from_fn_with_state(state.clone(), auth::authenticate))
+        .route_layer(middleware::from_fn_with_state(state.clone(), auth::api_tokens::enforce))
+        .route_layer(middleware::from_fn_with_state(state.clone(), stats::track))
         .layer(cors)
-        .layer(TraceLayer::new_for_http())
-        .with_state(state)
+        .layer(TraceLayer::new_for_http().make_span_with(logging::request_span))
+        .layer(middleware::from_fn(logging::assign_request_id))
+        .with_state(state.clone())
         .fallback_service(
-            ServeDir::new(&frontend_dir)
-                .not_found_service(ServeFile::new(format!("{}/index.html", frontend_dir)))
-        );
+            ServiceBuilder::new()
+                .layer(middleware::from_fn(cache::static_asset_headers))
+                .service(
+                    ServeDir::new(&frontend_dir)
+                        .not_found_service(ServeFile::new(format!("{}/index.html", frontend_dir)))
+                )
+        )
+        // Outside the routes so the frontend is refused too
+        .layer(middleware::from_fn_with_state(state.clone(), system::management_access::enforce))
+        // gzip or brotli, whichever the client prefers; covers the frontend files and the API,
+        // where raw firewall rules and logs run to hundreds of KB
+        .layer(CompressionLayer::new());
+
+    let port = config.port;
+    let addr = std::net::SocketAddr::new(config.bind_address, port);
+    let tls = match &config.tls {
+        Some(tls) => Some(RustlsConfig::from_pem_file(&tls.cert, &tls.key).await?),
+        None => None,
+    };
+    if let Some(tls) = &tls {
+        acme::register_ui_tls(tls.clone());
+    }
+    tracing::info!("Starting RouterUI on {}{}", addr, if tls.is_some() { " (TLS)" } else { "" });
+
+    let listener = std::net::TcpListener::bind(addr)?;
+    listener.set_nonblocking(true)?;
+    health::sd_notify("READY=1");
 
-    let port = std::env::var("ROUTERUI_PORT").unwrap_or_else(|_| "3080".to_string());
-    let addr = format!("0.0.0.0:{}", port);
-    tracing::info!("Starting RouterUI on {}", addr);
+    if discovery::is_enabled() {
+        discovery::spawn(state.clone(), port);
+    }
+    let banner = discovery::banner(&state, port).await;
+    match &banner.setup_url {
+        Some(url) => tracing::info!("RouterUI is not set up yet - open {} to begin", url),
+        None => tracing::info!("RouterUI available at {}", banner.urls.join(", ")),
+    }
 
-    let listener = tokio::net::TcpListener::bind(&addr).await?;
-    axum::serve(listener, app).await?;
+    // Peer addresses are needed for per-client setup token lockouts
+    let service = app.into_make_service_with_connect_info::<std::net::SocketAddr>();
+    match tls {
+        Some(tls) => axum_server::from_tcp_rustls(listener, tls).serve(service).await?,
+        None => axum::serve(tokio::net::TcpListener::from_std(listener)?, service).await?,
+    }
 
     Ok(())
 }
@@ -0,0 +1,298 @@
+// Additional OpenWrt access points kept in sync with RouterUI's WiFi settings. RouterUI logs in
+// over SSH with its own key and applies the settings with uci, into wifi-iface sections it owns
+// (routerui_<radio> and routerui_guest_<radio>), so the AP's own interfaces are left alone.
+
+use chrono::Utc;
+use serde::Serialize;
+use sqlx::SqlitePool;
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+
+const SSH_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// What gets pushed to every AP, read from the default radio's hostapd.conf
+#[derive(Debug, Clone)]
+pub struct WifiSettings {
+    pub ssid: String,
+    pub passphrase: String,
+    // OpenWrt encryption: psk2, sae or sae-mixed
+    pub encryption: String,
+    pub hidden: bool,
+    // 802.11r mobility domain, when fast roaming is on
+    pub mobility_domain: Option<String>,
+    // Guest SSID and passphrase, when the guest network is switched on
+    pub guest: Option<(String, String)>,
+}
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct ManagedAp {
+    pub id: String,
+    pub name: String,
+    pub host: String,
+    pub port: i64,
+    pub username: String,
+    // OpenWrt network (and so VLAN) the main and guest SSIDs are attached to
+    pub lan_network: String,
+    pub guest_network: Option<String>,
+    pub model: Option<String>,
+    pub firmware: Option<String>,
+    pub last_sync_at: Option<String>,
+    pub last_error: Option<String>,
+    pub created_at: String,
+}
+
+fn ssh_dir() -> PathBuf {
+    crate::db::data_dir().join("ssh")
+}
+
+fn key_path() -> PathBuf {
+    ssh_dir().join("id_ed25519")
+}
+
+fn known_hosts() -> PathBuf {
+    ssh_dir().join("known_hosts")
+}
+
+/// RouterUI's public key, created on first use; it goes in the AP's
+/// /etc/dropbear/authorized_keys (and in authorized_keys on homelab hosts)
+pub async fn public_key() -> Result<String, String> {
+    let key = key_path();
+    if !key.exists() {
+        std::fs::create_dir_all(ssh_dir()).map_err(|e| e.to_string())?;
+        let output = tokio::process::Command::new("ssh-keygen")
+            .args(["-q", "-t", "ed25519", "-N", "", "-C", "routerui"])
+            .arg("-f")
+            .arg(&key)
+            .output()
+            .await
+            .map_err(|e| format!("ssh-keygen: {}", e))?;
+        if !output.status.success() {
+            return Err(format!("ssh-keygen: {}", String::from_utf8_lossy(&output.stderr).trim()));
+        }
+    }
+    std::fs::read_to_string(key.with_extension("pub"))
+        .map(|k| k.trim().to_string())
+        .map_err(|e| e.to_string())
+}
+
+// Single-quoted for the AP's shell
+fn quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+pub fn valid_username(username: &str) -> bool {
+    !username.is_empty()
+        && !username.starts_with('-')
+        && username.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'))
+}
+
+pub fn valid_host(host: &str) -> bool {
+    host.parse::<std::net::IpAddr>().is_ok()
+        || (!host.is_empty()
+            && host.len() <= 253
+            && !host.starts_with('-')
+            && host.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.'))
+}
+
+// uci section and network names
+pub fn valid_network(name: &str) -> bool {
+    !name.is_empty() && name.len() <= 32 && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// ssh to `username@host` with RouterUI's key; the caller adds the remote command
+pub(crate) fn ssh(host: &str, port: i64, username: &str) -> tokio::process::Command {
+    let mut command = tokio::process::Command::new("ssh");
+    command
+        .arg("-i")
+        .arg(key_path())
+        .arg("-o")
+        .arg(format!("UserKnownHostsFile={}", known_hosts().display()))
+        // Pin the host key on first login, refuse a changed one afterwards
+        .args(["-o", "StrictHostKeyChecking=accept-new", "-o", "BatchMode=yes", "-o", "ConnectTimeout=10"])
+        .arg("-p")
+        .arg(port.to_string())
+        .arg(format!("{}@{}", username, host));
+    command
+}
+
+/// Drop the pinned host key, e.g. for a machine that was re-installed at the same address
+pub(crate) async fn forget_host_key(host: &str, port: i64) {
+    let _ = tokio::process::Command::new("ssh-keygen")
+        .arg("-R")
+        .arg(if port == 22 { host.to_string() } else { format!("[{}]:{}", host, port) })
+        .arg("-f")
+        .arg(known_hosts())
+        .output()
+        .await;
+}
+
+/// Run a script on the AP with `sh -s`, returning its stdout
+async fn run(ap: &ManagedAp, script: &str) -> Result<String, String> {
+    public_key().await?;
+
+    let mut child = ssh(&ap.host, ap.port, &ap.username)
+        .args(["sh", "-s"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| format!("ssh: {}", e))?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(script.as_bytes()).await.map_err(|e| e.to_string())?;
+    }
+
+    let output = tokio::time::timeout(SSH_TIMEOUT, child.wait_with_output())
+        .await
+        .map_err(|_| format!("{} did not respond within {}s", ap.host, SSH_TIMEOUT.as_secs()))?
+        .map_err(|e| e.to_string())?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        return Err(if stderr.is_empty() { format!("exit status {}", output.status) } else { stderr });
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// Model and OpenWrt release, which also proves the key is installed
+async fn board(ap: &ManagedAp) -> Result<(String, String), String> {
+    let output = run(ap, "ubus call system board\n").await?;
+    let board: serde_json::Value = serde_json::from_str(&output).map_err(|_| "Not an OpenWrt device".to_string())?;
+    let model = board["model"].as_str().unwrap_or("unknown").to_string();
+    let firmware = board["release"]["description"].as_str().unwrap_or("OpenWrt").to_string();
+    Ok((model, firmware))
+}
+
+// One wifi-iface per radio on the AP; the guest one is removed when there is no guest network
+fn sync_script(ap: &ManagedAp, wifi: &WifiSettings) -> String {
+    // Roaming and the hidden flag only apply to the main network
+    let iface = |section: &str, network: &str, ssid: &str, key: &str, main: bool| {
+        let mut lines = vec![
+            format!("uci set wireless.{s}=wifi-iface", s = section),
+            format!("uci set wireless.{s}.device=\"$dev\"", s = section),
+            format!("uci set wireless.{s}.mode=ap", s = section),
+            format!("uci set wireless.{s}.network={}", quote(network), s = section),
+            format!("uci set wireless.{s}.ssid={}", quote(ssid), s = section),
+            format!("uci set wireless.{s}.encryption={}", quote(&wifi.encryption), s = section),
+            format!("uci set wireless.{s}.key={}", quote(key), s = section),
+            format!("uci set wireless.{s}.hidden={}", u8::from(wifi.hidden && main), s = section),
+            format!("uci set wireless.{s}.disabled=0", s = section),
+        ];
+        match (&wifi.mobility_domain, main) {
+            (Some(domain), true) => lines.extend([
+                format!("uci set wireless.{s}.ieee80211r=1", s = section),
+                format!("uci set wireless.{s}.mobility_domain={}", quote(domain), s = section),
+                format!("uci set wireless.{s}.ft_over_ds=0", s = section),
+                format!("uci set wireless.{s}.ft_psk_generate_local=1", s = section),
+            ]),
+            _ => lines.push(format!("uci -q delete wireless.{s}.ieee80211r || true", s = section)),
+        }
+        lines.join("\n    ")
+    };
+
+    let main = iface("routerui_$dev", &ap.lan_network, &wifi.ssid, &wifi.passphrase, true);
+    let guest = match (&wifi.guest, &ap.guest_network) {
+        (Some((ssid, key)), Some(network)) => iface("routerui_guest_$dev", network, ssid, key, false),
+        _ => "uci -q delete wireless.routerui_guest_$dev || true".to_string(),
+    };
+
+    format!(
+        "set -e\n\
+         radios=$(uci -q show wireless | sed -n 's/^wireless\\.\\([^.=]*\\)=wifi-device$/\\1/p')\n\
+         [ -n \"$radios\" ] || {{ echo 'No radios configured' >&2; exit 1; }}\n\
+         for dev in $radios; do\n    \
+         {main}\n    \
+         {guest}\n\
+         done\n\
+         uci commit wireless\n\
+         wifi reload\n"
+    )
+}
+
+async fn record_sync(pool: &SqlitePool, id: &str, error: Option<&str>) {
+    let query = match error {
+        None => sqlx::query("UPDATE managed_aps SET last_sync_at = ?, last_error = NULL WHERE id = ?")
+            .bind(Utc::now().to_rfc3339()),
+        Some(e) => sqlx::query("UPDATE managed_aps SET last_error = ? WHERE id = ?").bind(e.to_string()),
+    };
+    let _ = query.bind(id).execute(pool).await;
+}
+
+pub async fn list(pool: &SqlitePool) -> Result<Vec<ManagedAp>, sqlx::Error> {
+    sqlx::query_as("SELECT * FROM managed_aps ORDER BY name").fetch_all(pool).await
+}
+
+/// Check that RouterUI can log in, then store the AP
+pub async fn adopt(pool: &SqlitePool, mut ap: ManagedAp) -> Result<ManagedAp, String> {
+    let (model, firmware) = board(&ap)
+        .await
+        .map_err(|e| format!("Could not log in to {}: {}", ap.host, e))?;
+    ap.model = Some(model);
+    ap.firmware = Some(firmware);
+
+    sqlx::query(
+        "INSERT INTO managed_aps (id, name, host, port, username, lan_network, guest_network, model, firmware, created_at)
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+    )
+    .bind(&ap.id)
+    .bind(&ap.name)
+    .bind(&ap.host)
+    .bind(ap.port)
+    .bind(&ap.username)
+    .bind(&ap.lan_network)
+    .bind(&ap.guest_network)
+    .bind(&ap.model)
+    .bind(&ap.firmware)
+    .bind(&ap.created_at)
+    .execute(pool)
+    .await
+    .map_err(|e| e.to_string())?;
+    Ok(ap)
+}
+
+pub async fn remove(pool: &SqlitePool, id: &str) -> Result<bool, sqlx::Error> {
+    let ap: Option<ManagedAp> = sqlx::query_as("SELECT * FROM managed_aps WHERE id = ?")
+        .bind(id)
+        .fetch_optional(pool)
+        .await?;
+    let Some(ap) = ap else { return Ok(false) };
+
+    sqlx::query("DELETE FROM managed_aps WHERE id = ?").bind(id).execute(pool).await?;
+
+    // A re-flashed AP at the same address gets a new host key
+    forget_host_key(&ap.host, ap.port).await;
+    Ok(true)
+}
+
+/// Push the current settings to one AP, recording the outcome
+pub async fn sync(pool: &SqlitePool, ap: &ManagedAp) -> Result<(), String> {
+    let wifi = crate::api::network::mesh_wifi_settings().ok_or("No WiFi network configured on RouterUI")?;
+    let result = run(ap, &sync_script(ap, &wifi)).await.map(|_| ());
+
+    match &result {
+        Ok(()) => tracing::info!("Synced WiFi settings to AP {} ({})", ap.name, ap.host),
+        Err(e) => tracing::warn!("Could not sync WiFi settings to AP {} ({}): {}", ap.name, ap.host, e),
+    }
+    record_sync(pool, &ap.id, result.as_ref().err().map(|e| e.as_str())).await;
+    result
+}
+
+/// Push to every adopted AP; failures are recorded per AP
+pub async fn sync_all(pool: &SqlitePool) -> Vec<(String, Result<(), String>)> {
+    let mut results = Vec::new();
+    for ap in list(pool).await.unwrap_or_default() {
+        let result = sync(pool, &ap).await;
+        results.push((ap.id, result));
+    }
+    results
+}
+
+/// After a WiFi change on RouterUI
+pub fn spawn_sync_all(pool: SqlitePool) {
+    tokio::spawn(async move {
+        sync_all(&pool).await;
+    });
+}
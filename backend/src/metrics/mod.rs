@@ -0,0 +1,304 @@
+// History of CPU, memory, per-interface throughput and tracked connections, kept in SQLite so
+// the dashboard can chart it without vnstat. Samples are taken every few seconds and rolled up
+// as they age: as sampled for a day, 5-minute buckets for a week, hourly buckets for a year.
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::AppState;
+
+// Key in the settings table
+pub const SETTINGS_KEY: &str = "metrics";
+const MIN_INTERVAL_SECS: u64 = 5;
+const MAX_INTERVAL_SECS: u64 = 300;
+const ROLLUP_INTERVAL: Duration = Duration::from_secs(60);
+const CONNTRACK_COUNT: &str = "/proc/sys/net/netfilter/nf_conntrack_count";
+const NET_DIR: &str = "/sys/class/net";
+// Charts get about this many points at most, whatever the range
+const MAX_POINTS: i64 = 300;
+const MAX_RANGE_SECS: i64 = 365 * 24 * 60 * 60;
+
+/// Bucket width in seconds (0 for samples as taken) and how long buckets are kept, finest first.
+/// Each tier after the first is rolled up from the one before it.
+pub struct Tier {
+    pub resolution: i64,
+    pub keep_secs: i64,
+}
+
+pub const TIERS: &[Tier] = &[
+    Tier { resolution: 0, keep_secs: 24 * 60 * 60 },
+    Tier { resolution: 5 * 60, keep_secs: 7 * 24 * 60 * 60 },
+    Tier { resolution: 60 * 60, keep_secs: MAX_RANGE_SECS },
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MetricsSettings {
+    pub enabled: bool,
+    pub interval_secs: u64,
+}
+
+impl Default for MetricsSettings {
+    fn default() -> Self {
+        Self { enabled: true, interval_secs: 10 }
+    }
+}
+
+impl MetricsSettings {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(MIN_INTERVAL_SECS..=MAX_INTERVAL_SECS).contains(&self.interval_secs) {
+            return Err(format!("Metrics interval must be {}-{} seconds", MIN_INTERVAL_SECS, MAX_INTERVAL_SECS));
+        }
+        Ok(())
+    }
+}
+
+pub async fn load_settings(pool: &SqlitePool) -> MetricsSettings {
+    match crate::db::settings::get(pool, SETTINGS_KEY).await {
+        Ok(settings) => settings.unwrap_or_default(),
+        Err(e) => {
+            tracing::warn!("Failed to read metrics settings, using defaults: {}", e);
+            MetricsSettings::default()
+        }
+    }
+}
+
+pub async fn save_settings(pool: &SqlitePool, settings: &MetricsSettings) -> Result<(), String> {
+    crate::db::settings::set(pool, SETTINGS_KEY, settings).await.map_err(|e| e.to_string())
+}
+
+/// "cpu", "memory", "connections" or "rx:<interface>" / "tx:<interface>"
+pub fn valid_metric(name: &str) -> bool {
+    match name.split_once(':') {
+        Some(("rx" | "tx", interface)) => {
+            !interface.is_empty() && interface.len() <= 15 && !interface.contains('/')
+        }
+        Some(_) => false,
+        None => matches!(name, "cpu" | "memory" | "connections"),
+    }
+}
+
+/// "30m", "24h", "7d" in seconds
+pub fn parse_range(range: &str) -> Option<i64> {
+    let unit = match range.chars().last()? {
+        'm' => 60,
+        'h' => 60 * 60,
+        'd' => 24 * 60 * 60,
+        _ => return None,
+    };
+    let count: i64 = range[..range.len() - 1].parse().ok()?;
+    let secs = count.checked_mul(unit)?;
+    (60..=MAX_RANGE_SECS).contains(&secs).then_some(secs)
+}
+
+// ============ SAMPLING ============
+
+// Cumulative counters, turned into rates between two samples
+#[derive(Default)]
+struct Counters {
+    // Busy and total jiffies over all CPUs
+    cpu: Option<(u64, u64)>,
+    // rx and tx bytes per interface
+    interfaces: HashMap<String, (u64, u64)>,
+    taken: Option<Instant>,
+}
+
+fn read_cpu() -> Option<(u64, u64)> {
+    let stat = std::fs::read_to_string("/proc/stat").ok()?;
+    let fields: Vec<u64> = stat.lines().next()?.split_whitespace().skip(1).filter_map(|v| v.parse().ok()).collect();
+    // user nice system idle iowait irq softirq steal; guest time is already in user
+    let total: u64 = fields.iter().take(8).sum();
+    let idle = fields.get(3)? + fields.get(4).unwrap_or(&0);
+    Some((total - idle, total))
+}
+
+fn read_interfaces() -> HashMap<String, (u64, u64)> {
+    let read = |name: &str, counter: &str| {
+        std::fs::read_to_string(format!("{}/{}/statistics/{}", NET_DIR, name, counter))
+            .ok()
+            .and_then(|v| v.trim().parse::<u64>().ok())
+    };
+    std::fs::read_dir(NET_DIR)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        // Same as the traffic view: container plumbing and loopback are noise
+        .filter(|name| name != "lo" && !name.starts_with("veth") && !name.starts_with("docker"))
+        .filter_map(|name| Some((read(&name, "rx_bytes")?, read(&name, "tx_bytes")?, name)))
+        .map(|(rx, tx, name)| (name, (rx, tx)))
+        .collect()
+}
+
+// Current values, and rates since the previous call
+fn sample(previous: &mut Counters) -> Vec<(String, f64)> {
+    let mut values = Vec::new();
+    let now = Instant::now();
+    let elapsed = previous.taken.map(|t| now.duration_since(t).as_secs_f64()).filter(|s| *s > 0.0);
+
+    let cpu = read_cpu();
+    if let (Some((busy, total)), Some((prev_busy, prev_total))) = (cpu, previous.cpu) {
+        if total > prev_total {
+            let percent = busy.saturating_sub(prev_busy) as f64 / (total - prev_total) as f64 * 100.0;
+            values.push(("cpu".to_string(), percent.min(100.0)));
+        }
+    }
+
+    let meminfo = std::fs::read_to_string("/proc/meminfo").unwrap_or_default();
+    if !meminfo.is_empty() {
+        values.push(("memory".to_string(), crate::system::parse_meminfo(&meminfo).percent_used));
+    }
+
+    // Only there once conntrack is loaded, i.e. with NAT or stateful rules in place
+    if let Some(count) = std::fs::read_to_string(CONNTRACK_COUNT).ok().and_then(|v| v.trim().parse::<f64>().ok()) {
+        values.push(("connections".to_string(), count));
+    }
+
+    let interfaces = read_interfaces();
+    if let Some(secs) = elapsed {
+        for (name, (rx, tx)) in &interfaces {
+            // A counter that went backwards was reset (interface recreated); skip this round
+            if let Some((prev_rx, prev_tx)) = previous.interfaces.get(name).filter(|(r, t)| rx >= r && tx >= t) {
+                values.push((format!("rx:{}", name), (rx - prev_rx) as f64 / secs));
+                values.push((format!("tx:{}", name), (tx - prev_tx) as f64 / secs));
+            }
+        }
+    }
+
+    *previous = Counters { cpu, interfaces, taken: Some(now) };
+    values
+}
+
+async fn record(pool: &SqlitePool, at: i64, values: &[(String, f64)]) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    for (metric, value) in values {
+        sqlx::query(
+            "INSERT OR REPLACE INTO metrics (metric, resolution, bucket, avg, min, max, samples) VALUES (?1, 0, ?2, ?3, ?3, ?3, 1)",
+        )
+        .bind(metric)
+        .bind(at)
+        .bind(value)
+        .execute(&mut *tx)
+        .await?;
+    }
+    tx.commit().await
+}
+
+/// Fold finished buckets into the next tier up and drop what each tier no longer keeps
+pub async fn roll_up(pool: &SqlitePool, now: i64) -> Result<(), sqlx::Error> {
+    for pair in TIERS.windows(2) {
+        let (from, to) = (pair[0].resolution, pair[1].resolution);
+        // The newest rolled-up bucket is redone, the rest are final
+        let since: i64 = sqlx::query_scalar("SELECT COALESCE(MAX(bucket), 0) FROM metrics WHERE resolution = ?")
+            .bind(to)
+            .fetch_one(pool)
+            .await?;
+        sqlx::query(
+            "INSERT OR REPLACE INTO metrics (metric, resolution, bucket, avg, min, max, samples)
+             SELECT metric, ?1, bucket - bucket % ?1, SUM(avg * samples) / SUM(samples), MIN(min), MAX(max), SUM(samples)
+             FROM metrics WHERE resolution = ?2 AND bucket >= ?3 AND bucket < ?4
+             GROUP BY metric, bucket - bucket % ?1",
+        )
+        .bind(to)
+        .bind(from)
+        .bind(since)
+        .bind(now - now % to)
+        .execute(pool)
+        .await?;
+    }
+    for tier in TIERS {
+        sqlx::query("DELETE FROM metrics WHERE resolution = ? AND bucket < ?")
+            .bind(tier.resolution)
+            .bind(now - tier.keep_secs)
+            .execute(pool)
+            .await?;
+    }
+    Ok(())
+}
+
+pub fn spawn(state: Arc<AppState>) {
+    tokio::spawn(async move {
+        let mut counters = Counters::default();
+        let mut last_rollup = Instant::now();
+        loop {
+            let settings = load_settings(&state.db).await;
+            let interval = Duration::from_secs(settings.interval_secs.clamp(MIN_INTERVAL_SECS, MAX_INTERVAL_SECS));
+            state.tasks.beat("metrics", interval);
+
+            if settings.enabled {
+                let mut previous = std::mem::take(&mut counters);
+                let sampled = tokio::task::spawn_blocking(move || (sample(&mut previous), previous)).await;
+                if let Ok((values, taken)) = sampled {
+                    counters = taken;
+                    if let Err(e) = record(&state.db, Utc::now().timestamp(), &values).await {
+                        tracing::warn!("Could not record metrics: {}", e);
+                    }
+                }
+            } else {
+                // Rates across a pause would be averages over the whole pause
+                counters = Counters::default();
+            }
+
+            if last_rollup.elapsed() >= ROLLUP_INTERVAL {
+                last_rollup = Instant::now();
+                if let Err(e) = roll_up(&state.db, Utc::now().timestamp()).await {
+                    tracing::warn!("Could not roll up metrics: {}", e);
+                }
+            }
+            tokio::time::sleep(interval).await;
+        }
+    });
+}
+
+// ============ HISTORY ============
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct Point {
+    // Unix time the point starts at
+    pub time: i64,
+    pub avg: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct History {
+    pub metric: String,
+    pub range_secs: i64,
+    // Width of each point
+    pub step_secs: i64,
+    pub points: Vec<Point>,
+}
+
+/// `metric` over the last `range_secs`, from the finest tier that still covers it
+pub async fn history(pool: &SqlitePool, metric: &str, range_secs: i64, interval_secs: u64) -> Result<History, sqlx::Error> {
+    let tier = TIERS.iter().find(|t| t.keep_secs >= range_secs).unwrap_or(&TIERS[TIERS.len() - 1]);
+    let width = if tier.resolution == 0 { interval_secs as i64 } else { tier.resolution };
+    // Whole buckets of the tier, enough of them per point to stay under MAX_POINTS
+    let per_point = ((range_secs / width + MAX_POINTS - 1) / MAX_POINTS).max(1);
+    let step = width * per_point;
+
+    let since = Utc::now().timestamp() - range_secs;
+    let points = sqlx::query_as(
+        "SELECT bucket - bucket % ?1 AS time, SUM(avg * samples) / SUM(samples) AS avg, MIN(min) AS min, MAX(max) AS max
+         FROM metrics WHERE metric = ?2 AND resolution = ?3 AND bucket >= ?4
+         GROUP BY bucket - bucket % ?1 ORDER BY time",
+    )
+    .bind(step)
+    .bind(metric)
+    .bind(tier.resolution)
+    .bind(since)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(History { metric: metric.to_string(), range_secs, step_secs: step, points })
+}
+
+/// Every metric with data, e.g. to list the interfaces there is throughput for
+pub async fn names(pool: &SqlitePool) -> Result<Vec<String>, sqlx::Error> {
+    sqlx::query_scalar("SELECT DISTINCT metric FROM metrics ORDER BY metric").fetch_all(pool).await
+}
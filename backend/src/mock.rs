@@ -68,6 +68,69 @@ pub mod dashboard {
             ]
         })
     }
+
+    pub fn power() -> serde_json::Value {
+        json!({
+            "current": {
+                "sources": [
+                    { "name": "package-0", "kind": "rapl", "watts": 4.8, "detail": null },
+                    { "name": "ina219 in", "kind": "sensor", "watts": 9.6, "detail": null }
+                ],
+                "watts": 9.6,
+                "method": "sensor",
+                "unreadable": [],
+                "read_at": "2026-10-16T12:00:00+00:00"
+            },
+            "history": [
+                { "sampled_at": "2026-10-16T10:00:00+00:00", "watts": 9.1, "method": "sensor" },
+                { "sampled_at": "2026-10-16T11:00:00+00:00", "watts": 10.4, "method": "sensor" },
+                { "sampled_at": "2026-10-16T12:00:00+00:00", "watts": 9.6, "method": "sensor" }
+            ],
+            "daily": [
+                { "day": "2026-10-14", "kwh": 0.231 },
+                { "day": "2026-10-15", "kwh": 0.228 },
+                { "day": "2026-10-16", "kwh": 0.118 }
+            ],
+            "today_kwh": 0.118,
+            "last_30d_kwh": 0.577
+        })
+    }
+
+    pub fn metrics() -> serde_json::Value {
+        json!({
+            "metrics": ["connections", "cpu", "memory", "rx:enp1s0", "rx:enp2s0", "tx:enp1s0", "tx:enp2s0"],
+            "settings": { "enabled": true, "interval_secs": 10 },
+            "retention": [
+                { "resolution_secs": 0, "keep_secs": 86400 },
+                { "resolution_secs": 300, "keep_secs": 604800 },
+                { "resolution_secs": 3600, "keep_secs": 31536000 }
+            ]
+        })
+    }
+
+    // A daily rhythm with some noise, scaled to look right for the metric
+    pub fn metrics_history(metric: &str, range_secs: i64) -> crate::metrics::History {
+        let (base, swing) = match metric {
+            "cpu" => (12.0, 8.0),
+            "memory" => (41.0, 3.0),
+            "connections" => (380.0, 220.0),
+            m if m.starts_with("rx:") => (2_400_000.0, 1_800_000.0),
+            _ => (420_000.0, 300_000.0),
+        };
+        let count = 120;
+        let step = (range_secs / count).max(10);
+        let now = chrono::Utc::now().timestamp();
+        let points = (0..count)
+            .map(|i| {
+                let time = now - range_secs + i * step;
+                let phase = (time % 86_400) as f64 / 86_400.0 * std::f64::consts::TAU;
+                let noise = ((time / step) % 7) as f64 / 7.0 - 0.5;
+                let avg = (base + swing * phase.sin() + swing * 0.3 * noise).max(0.0);
+                crate::metrics::Point { time: time - time % step, avg, min: avg * 0.8, max: avg * 1.3 }
+            })
+            .collect();
+        crate::metrics::History { metric: metric.to_string(), range_secs, step_secs: step, points }
+    }
 }
 
 // Mock data for network
@@ -85,7 +148,8 @@ pub mod network {
                 "ipv6": null,
                 "rx_bytes": 1073741824,
                 "tx_bytes": 536870912,
-                "speed": "1000Mb/s"
+                "speed": "1000Mb/s",
+                "role": "wan"
             },
             {
                 "name": "enp2s0",
@@ -96,7 +160,8 @@ pub mod network {
                 "ipv6": null,
                 "rx_bytes": 2147483648_i64,
                 "tx_bytes": 1073741824_i64,
-                "speed": "1000Mb/s"
+                "speed": "1000Mb/s",
+                "role": "lan"
             },
             {
                 "name": "wlo1",
@@ -107,7 +172,8 @@ pub mod network {
                 "ipv6": null,
                 "rx_bytes": 536870912,
                 "tx_bytes": 268435456,
-                "speed": null
+                "speed": null,
+                "role": null
             },
             {
                 "name": "tailscale0",
@@ -118,11 +184,62 @@ pub mod network {
                 "ipv6": null,
                 "rx_bytes": 104857600,
                 "tx_bytes": 52428800,
-                "speed": null
+                "speed": null,
+                "role": "vpn"
             }
         ])
     }
 
+    pub fn interface_roles() -> serde_json::Value {
+        json!({
+            "roles": { "wan": "enp1s0", "lan": "enp2s0", "guest": "wlo1_1", "vpn": "tailscale0" },
+            "settings": { "wan": null, "lan": null, "guest": null, "vpn": null }
+        })
+    }
+
+    pub fn subnets() -> serde_json::Value {
+        json!({
+            "primary": { "interface": "enp2s0", "addresses": ["10.22.22.1/24"] },
+            "subnets": [
+                {
+                    "name": "iot",
+                    "interface": "enp2s0",
+                    "vlan_id": 20,
+                    "address": "10.22.30.1/24",
+                    "dhcp": { "range_start": "10.22.30.100", "range_end": "10.22.30.200", "lease_time": "12h", "dns_server": null },
+                    "domain": "iot.lan",
+                    "isolated": true,
+                    "link": "enp2s0.20",
+                    "up": true,
+                    "pool": {
+                        "range_start": "10.22.30.100",
+                        "range_end": "10.22.30.200",
+                        "size": 101,
+                        "used": 14,
+                        "free": 87,
+                        "utilization": 13.9,
+                        "level": "ok",
+                        "lease_time": "12h",
+                        "expiring_soon": 1,
+                        "suggestions": []
+                    }
+                },
+                {
+                    "name": "lab",
+                    "interface": "enp3s0",
+                    "vlan_id": null,
+                    "address": "10.22.40.1/24",
+                    "dhcp": null,
+                    "domain": null,
+                    "isolated": false,
+                    "link": "enp3s0",
+                    "up": false,
+                    "pool": null
+                }
+            ]
+        })
+    }
+
     pub fn dhcp_status() -> serde_json::Value {
         json!({
             "enabled": true,
@@ -133,10 +250,137 @@ pub mod network {
             "gateway": "10.22.22.1",
             "dns": ["10.22.22.1"],
             "leases": [
-                { "mac": "aa:bb:cc:dd:ee:01", "ip": "10.22.22.131", "hostname": "Pixel-7-Pro", "expires": "2026-01-19 10:00:00" },
-                { "mac": "aa:bb:cc:dd:ee:02", "ip": "10.22.22.185", "hostname": "desktop-pc", "expires": "2026-01-19 12:00:00" }
+                { "mac": "aa:bb:cc:dd:ee:01", "ip": "10.22.22.131", "hostname": "Pixel-7-Pro", "expires": "2026-01-19 10:00:00", "subnet": "lan" },
+                { "mac": "aa:bb:cc:dd:ee:02", "ip": "10.22.22.185", "hostname": "desktop-pc", "expires": "2026-01-19 12:00:00", "subnet": "lan" }
+            ],
+            "static_leases": [],
+            "pool": dhcp_pool()["current"]
+        })
+    }
+
+    pub fn dhcp_pool() -> serde_json::Value {
+        let history: Vec<serde_json::Value> = (0..24)
+            .map(|h| {
+                let used = [42, 40, 38, 37, 36, 36, 38, 45, 58, 66, 71, 74, 78, 83, 88, 90, 86, 80, 72, 65, 58, 52, 48, 44][h];
+                json!({"sampled_at": format!("2026-10-15T{:02}:00:00+00:00", h), "size": 101, "used": used})
+            })
+            .collect();
+        json!({
+            "current": {
+                "range_start": "10.22.22.100",
+                "range_end": "10.22.22.200",
+                "size": 101,
+                "used": 86,
+                "free": 15,
+                "utilization": 85.1,
+                "level": "warning",
+                "lease_time": "24h",
+                "expiring_soon": 4,
+                "suggestions": [
+                    "Extend the range to 10.22.22.100 - 10.22.22.254 (53 more addresses)",
+                    "Shorten the lease time from 24h to 2h so addresses of departed devices free up sooner"
+                ]
+            },
+            "peak": history[15],
+            "peak_7d": history[15],
+            "history": history
+        })
+    }
+
+    pub fn dhcp_options() -> serde_json::Value {
+        json!({
+            "options": [
+                {"tag": null, "vendor": null, "option": "42", "value": "10.22.22.1", "force": false},
+                {"tag": "voip", "vendor": null, "option": "66", "value": "http://10.22.22.5/provision", "force": true},
+                {"tag": "voip", "vendor": null, "option": "120", "value": "10.22.22.5", "force": false}
             ],
-            "static_leases": []
+            "tag_rules": [
+                {"tag": "voip", "match_type": "mac", "value": "00:04:f2:*:*:*"},
+                {"tag": "voip", "match_type": "vendor_class", "value": "Polycom"}
+            ]
+        })
+    }
+
+    pub fn dns_rebind() -> serde_json::Value {
+        json!({
+            "enabled": true,
+            "allow_localhost": false,
+            "exceptions": ["plex.direct"]
+        })
+    }
+
+    pub fn safe_search() -> serde_json::Value {
+        json!({
+            "groups": [
+                {"id": "kids", "name": "Kids", "enabled": true, "clients": ["10.22.22.131", "10.22.22.140"], "google": true, "bing": true, "youtube": "strict"},
+                {"id": "everyone", "name": "Everyone", "enabled": false, "clients": [], "google": true, "bing": false, "youtube": "moderate"}
+            ],
+            "backend": "adguard"
+        })
+    }
+
+    pub fn dns_bypass() -> serde_json::Value {
+        json!({
+            "settings": {
+                "dns": "redirect",
+                "block_dot": true,
+                "block_doh": true,
+                "redirect_ntp": false,
+                "exceptions": ["10.22.22.50"]
+            },
+            "adguard": true
+        })
+    }
+
+    pub fn qos() -> serde_json::Value {
+        let tins = |bulk: u64, best: u64, video: u64, voice: u64| json!([
+            { "name": "Bulk", "sent_bytes": bulk, "drops": 210, "avg_delay_us": 2400, "peak_delay_us": 9800 },
+            { "name": "Best Effort", "sent_bytes": best, "drops": 1320, "avg_delay_us": 1100, "peak_delay_us": 6200 },
+            { "name": "Video", "sent_bytes": video, "drops": 4, "avg_delay_us": 310, "peak_delay_us": 1900 },
+            { "name": "Voice", "sent_bytes": voice, "drops": 0, "avg_delay_us": 45, "peak_delay_us": 420 }
+        ]);
+        json!({
+            "settings": {
+                "enabled": true,
+                "download_kbit": 90000,
+                "upload_kbit": 18000,
+                "rules": [
+                    { "device": "10.22.22.30", "protocol": null, "port": null, "priority": "voice", "description": "Desk phone" },
+                    { "device": null, "protocol": "udp", "port": 3478, "priority": "video", "description": "Video calls (STUN/TURN)" },
+                    { "device": "10.22.22.5", "protocol": null, "port": null, "priority": "bulk", "description": "NAS offsite backup" }
+                ]
+            },
+            "wan": "enp1s0",
+            "status": {
+                "upload": {
+                    "interface": "enp1s0", "bandwidth_kbit": 18000, "sent_bytes": 4_812_330_112u64, "drops": 1534, "backlog_bytes": 0,
+                    "tins": tins(1_904_220_400, 2_611_004_220, 210_118_300, 86_987_192)
+                },
+                "download": {
+                    "interface": "ifb-routerui", "bandwidth_kbit": 90000, "sent_bytes": 38_220_410_553u64, "drops": 8112, "backlog_bytes": 1514,
+                    "tins": tins(0, 38_220_410_553, 0, 0)
+                }
+            }
+        })
+    }
+
+    pub fn lan_pages() -> serde_json::Value {
+        json!({
+            "settings": {
+                "enabled": true,
+                "port": 80,
+                "pages": [
+                    {"hostname": "printer.lan", "type": "redirect", "target": "http://192.168.1.40:631/"},
+                    {
+                        "hostname": "start.lan", "type": "links", "title": "Home",
+                        "links": [
+                            {"name": "Router", "url": "http://192.168.1.1:8080/"},
+                            {"name": "Jellyfin", "url": "http://192.168.1.10:8096/"}
+                        ]
+                    }
+                ]
+            },
+            "status": {"listening": "192.168.1.1:80", "error": null}
         })
     }
 
@@ -151,6 +395,214 @@ pub mod network {
             "connected_clients": 3
         })
     }
+
+    pub fn wifi_radios() -> serde_json::Value {
+        json!([
+            {"name": "hostapd", "interface": "wlo1", "ssid": "MockNetwork", "channel": 6, "band": "2.4GHz", "enabled": true, "clients": 2},
+            {"name": "wlan5g", "interface": "wlx5g", "ssid": "MockNetwork", "channel": 36, "band": "5GHz", "enabled": true, "clients": 1}
+        ])
+    }
+
+    pub fn wifi_clients() -> serde_json::Value {
+        json!([
+            {"mac_address": "aa:bb:cc:dd:ee:01", "radio": "hostapd", "interface": "wlo1", "ssid": "MockNetwork", "band": "2.4GHz", "ip_address": "192.168.1.100", "hostname": "laptop", "signal_dbm": -48, "tx_bitrate": "144.4 MBit/s", "rx_bitrate": "130.0 MBit/s", "connected_secs": 5400, "rx_bytes": 104857600, "tx_bytes": 524288000},
+            {"mac_address": "aa:bb:cc:dd:ee:02", "radio": "hostapd", "interface": "wlo1", "ssid": "MockNetwork", "band": "2.4GHz", "ip_address": "192.168.1.101", "hostname": "thermostat", "signal_dbm": -71, "tx_bitrate": "65.0 MBit/s", "rx_bitrate": "24.0 MBit/s", "connected_secs": 86400, "rx_bytes": 1048576, "tx_bytes": 2097152},
+            {"mac_address": "aa:bb:cc:dd:ee:03", "radio": "wlan5g", "interface": "wlx5g", "ssid": "MockNetwork", "band": "5GHz", "ip_address": "192.168.1.102", "hostname": "phone", "signal_dbm": -55, "tx_bitrate": "866.7 MBit/s", "rx_bitrate": "780.0 MBit/s", "connected_secs": 1200, "rx_bytes": 52428800, "tx_bytes": 209715200}
+        ])
+    }
+
+    pub fn managed_aps() -> serde_json::Value {
+        json!({
+            "access_points": [
+                {
+                    "id": "3f1c2a9e-5d7b-4e21-9c0a-1b2d3e4f5a6b",
+                    "name": "Upstairs",
+                    "host": "192.168.1.3",
+                    "port": 22,
+                    "username": "root",
+                    "lan_network": "lan",
+                    "guest_network": "guest",
+                    "model": "Ubiquiti UniFi 6 Lite",
+                    "firmware": "OpenWrt 23.05.3 r23809-234f1a2efa",
+                    "last_sync_at": "2026-10-16T08:12:44+00:00",
+                    "last_error": null,
+                    "created_at": "2026-09-02T19:40:10+00:00"
+                }
+            ],
+            "public_key": "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIMockMockMockMockMockMockMockMockMockMockMo routerui"
+        })
+    }
+
+    pub fn wifi_advanced() -> serde_json::Value {
+        json!({
+            "interface": "wlo1",
+            "wps": true,
+            "fast_transition": false,
+            "mobility_domain": null,
+            "band_steering": null
+        })
+    }
+
+    pub fn wps_status() -> serde_json::Value {
+        json!({
+            "enabled": true,
+            "pbc_status": "Disabled",
+            "last_result": "None",
+            "peer_address": null
+        })
+    }
+
+    pub fn modem() -> serde_json::Value {
+        json!({
+            "settings": {
+                "role": "failover",
+                "apn": "internet",
+                "username": null,
+                "password": null,
+                "ip_type": "ipv4v6",
+                "data_cap_mb": 20000,
+                "billing_day": 1,
+                "warn_percent": 80,
+                "disconnect_at_cap": false
+            },
+            "password_set": false,
+            "modem": {
+                "index": 0,
+                "manufacturer": "Quectel",
+                "model": "EM12-G",
+                "state": "registered",
+                "operator": "MockTel",
+                "registration": "home",
+                "access_technologies": ["lte"],
+                "own_numbers": ["+15550100"],
+                "signal": { "quality": 72.0, "technology": "lte", "rssi": -67.0, "rsrp": -96.0, "rsrq": -11.0, "snr": 9.4 },
+                "data_interface": "wwan0",
+                "bearers": [0]
+            },
+            "error": null,
+            "usage": {
+                "cycle_start": "2026-10-01",
+                "cycle_end": "2026-11-01",
+                "rx_bytes": 1843200000_i64,
+                "tx_bytes": 201600000_i64,
+                "used_mb": 2044.8,
+                "cap_mb": 20000,
+                "percent": 10.224
+            },
+            "daily": [
+                { "day": "2026-10-14", "rx_bytes": 0, "tx_bytes": 0 },
+                { "day": "2026-10-15", "rx_bytes": 1843200000_i64, "tx_bytes": 201600000_i64 }
+            ],
+            "monitor": {
+                "last_checked": "2026-10-16T12:00:00+00:00",
+                "last_error": null,
+                "failover_active": false,
+                "failover_since": null,
+                "capped_cycle": null
+            }
+        })
+    }
+
+    pub fn modem_sms() -> serde_json::Value {
+        json!({
+            "messages": [
+                { "id": 1, "number": "MockTel", "text": "You have used 80% of your monthly data.", "state": "received", "timestamp": "2026-10-15T09:12:00+00:00" },
+                { "id": 0, "number": "+15550199", "text": "Welcome to MockTel!", "state": "received", "timestamp": "2026-09-30T18:00:00+00:00" }
+            ]
+        })
+    }
+
+    pub fn wan() -> serde_json::Value {
+        json!({
+            "interface": "enp1s0",
+            "settings": {
+                "mac_address": "02:1a:2b:3c:4d:5e",
+                "dhcp_hostname": "routerui",
+                "dhcp_vendor_class": null
+            },
+            "address": { "current": "02:1a:2b:3c:4d:5e", "permanent": "52:54:00:12:34:56" },
+            "dhcp_options_supported": true
+        })
+    }
+
+    pub fn presence() -> serde_json::Value {
+        json!({
+            "settings": {
+                "enabled": true,
+                "ble_enabled": true,
+                "away_after_secs": 600,
+                "devices": [
+                    { "id": "p1", "name": "Alex's phone", "wifi_mac": "aa:bb:cc:dd:ee:01", "ble_address": null },
+                    { "id": "p2", "name": "Sam's keys", "wifi_mac": null, "ble_address": "c4:7c:8d:11:22:33" }
+                ],
+                "away_profile": null,
+                "home_profile": null
+            },
+            "devices": [
+                {
+                    "device": { "id": "p1", "name": "Alex's phone", "wifi_mac": "aa:bb:cc:dd:ee:01", "ble_address": null },
+                    "state": { "home": true, "last_seen": "2026-10-16T08:59:30+00:00", "seen_via": "wifi", "since": "2026-10-16T07:12:00+00:00" }
+                },
+                {
+                    "device": { "id": "p2", "name": "Sam's keys", "wifi_mac": null, "ble_address": "c4:7c:8d:11:22:33" },
+                    "state": { "home": false, "last_seen": "2026-10-16T06:40:00+00:00", "seen_via": "ble", "since": "2026-10-16T06:50:00+00:00" }
+                }
+            ],
+            "anyone_home": true,
+            "error": null
+        })
+    }
+
+    pub fn wifi_schedule() -> serde_json::Value {
+        json!({
+            "schedule": {
+                "radio": {
+                    "enabled": true,
+                    "windows": [{"days": [], "start": "07:00", "end": "23:00"}]
+                },
+                "guest": {
+                    "enabled": true,
+                    "windows": [{"days": ["sat", "sun"], "start": "00:00", "end": "00:00"}]
+                }
+            },
+            "radio": {"active": true, "next_change": "2026-10-16T23:00:00+00:00"},
+            "guest": {"active": false, "next_change": "2026-10-17T00:00:00+00:00"},
+            "guest_network": {"interface": "wlo1_1", "ssid": "MockNetwork-Guest", "enabled": false}
+        })
+    }
+
+    pub(crate) fn guest_network() -> crate::api::network::GuestNetwork {
+        crate::api::network::GuestNetwork {
+            interface: "wlo1_1".to_string(),
+            ssid: "MockNetwork-Guest".to_string(),
+            enabled: false,
+            passphrase: None,
+            psk_file: None,
+        }
+    }
+
+    pub fn wol_history() -> serde_json::Value {
+        json!({
+            "wakes": [
+                {
+                    "woken_at": "2026-10-16T21:42:10+00:00", "mac_address": "aa:bb:cc:dd:ee:01", "device_name": "Gaming PC",
+                    "username": "admin", "source_ip": "100.101.102.103", "origin": "tailscale",
+                    "tailscale_identity": "admin@example.com (pixel-8)", "interface": "enp2s0", "success": true, "error": null
+                },
+                {
+                    "woken_at": "2026-10-16T08:15:37+00:00", "mac_address": "aa:bb:cc:dd:ee:02", "device_name": "NAS",
+                    "username": "admin", "source_ip": "192.168.1.50", "origin": "lan",
+                    "tailscale_identity": null, "interface": "enp2s0", "success": true, "error": null
+                },
+                {
+                    "woken_at": "2026-10-15T19:03:55+00:00", "mac_address": "aa:bb:cc:dd:ee:02", "device_name": "NAS",
+                    "username": "phone", "source_ip": "100.87.12.4", "origin": "tailscale",
+                    "tailscale_identity": "kid@example.com (iphone)", "interface": "", "success": false,
+                    "error": "NAS is not set up to be woken from off the LAN"
+                }
+            ]
+        })
+    }
 }
 
 // Mock data for firewall
@@ -174,7 +626,95 @@ pub mod firewall {
     }
 
     pub fn port_forwards() -> serde_json::Value {
-        json!([])
+        json!([
+            {
+                "id": 1, "enabled": true, "protocol": "tcp", "external_port": 8443,
+                "internal_ip": "192.168.1.10", "internal_port": 443, "description": "Home Assistant",
+                "created_by": "admin", "created_at": "2024-01-10T18:22:04+00:00",
+                "countries": ["US", "CA"], "log_access": true
+            },
+            {
+                "id": 0, "enabled": false, "protocol": "udp", "external_port": 25565,
+                "internal_ip": "192.168.1.30", "internal_port": 25565, "description": "Minecraft (off for the school week)",
+                "created_by": "admin", "created_at": "2024-01-12T20:05:41+00:00",
+                "countries": [], "log_access": false
+            }
+        ])
+    }
+
+    pub fn blocked_ips() -> serde_json::Value {
+        json!([
+            {
+                "ip": "45.155.205.100", "enabled": true, "description": "Known scanner",
+                "created_by": "admin", "created_at": "2024-01-14T09:30:00+00:00"
+            },
+            {
+                "ip": "192.168.1.100", "enabled": false, "description": "Test block",
+                "created_by": null, "created_at": "2024-01-15T11:02:17+00:00"
+            }
+        ])
+    }
+
+    pub fn ipv6() -> serde_json::Value {
+        json!({
+            "status": {
+                "available": true, "wan_interface": "enp1s0", "input_policy": "ACCEPT",
+                "forward_policy": "ACCEPT", "output_policy": "ACCEPT", "inbound_protected": true
+            },
+            "blocked": [
+                {
+                    "ip": "2001:db8:bad::/48", "enabled": true, "description": "Scanner network",
+                    "created_by": "admin", "created_at": "2024-01-14T09:31:00+00:00"
+                }
+            ],
+            "pinholes": [
+                {
+                    "protocol": "tcp", "address": "2001:db8:1234:1::10", "port": 443,
+                    "description": "Home Assistant", "created_by": "admin", "created_at": "2024-01-10T18:25:11+00:00"
+                }
+            ]
+        })
+    }
+
+    pub fn port_forward_access() -> serde_json::Value {
+        json!({
+            "hours": 24,
+            "logged_ports": [8443],
+            "geoip_updated": "2024-01-14T03:00:00+00:00",
+            "forwards": [
+                {
+                    "external_port": 8443,
+                    "protocol": "tcp",
+                    "connections": 57,
+                    "unique_sources": 4,
+                    "first_seen": "2024-01-14T09:12:40+0000",
+                    "last_seen": "2024-01-15T08:47:03+0000",
+                    "countries": [
+                        {"country": "US", "connections": 41},
+                        {"country": "DE", "connections": 12},
+                        {"country": "CN", "connections": 4}
+                    ],
+                    "top_sources": [
+                        {"ip": "73.42.118.9", "country": "US", "connections": 38},
+                        {"ip": "91.64.201.17", "country": "DE", "connections": 12},
+                        {"ip": "222.186.30.112", "country": "CN", "connections": 4},
+                        {"ip": "104.28.55.6", "country": "US", "connections": 3}
+                    ]
+                }
+            ]
+        })
+    }
+
+    pub fn zones() -> serde_json::Value {
+        json!({
+            "zones": [
+                {"zone": "wan", "interfaces": ["enp1s0"], "input": "drop"},
+                {"zone": "lan", "interfaces": ["enp2s0", "wlo1", "br0"], "input": "accept"},
+                {"zone": "guest", "interfaces": ["wlo1_1"], "input": "drop"},
+                {"zone": "vpn", "interfaces": ["tailscale0"], "input": "accept"}
+            ],
+            "settings": crate::zones::ZoneSettings::default()
+        })
     }
 }
 
@@ -182,6 +722,70 @@ pub mod firewall {
 pub mod security {
     use serde_json::json;
 
+    pub fn incident_report() -> serde_json::Value {
+        json!({
+            "ip": "45.155.205.100",
+            "generated_at": "2026-01-18T11:02:44+00:00",
+            "hostname": "router",
+            "hours": 24,
+            "since": "2026-01-17T11:02:44+00:00",
+            "country": "RU",
+            "reputation": {"score": 3, "summary": "Listed in 3 feeds since 2025-11", "feeds": ["emerging-threats", "firehol-level1", "spamhaus-drop"]},
+            "whois": {
+                "network": "EXAMPLE-HOSTING-NET",
+                "handle": "EXAMPLE-45-155-204",
+                "range": "45.155.204.0 - 45.155.205.255",
+                "country": "RU",
+                "abuse_email": "abuse@example-hosting.net",
+                "source": "https://rdap.org/ip/45.155.205.100"
+            },
+            "whois_error": null,
+            "summary": {
+                "firewall_hits": 3,
+                "ports": [22, 23, 3389],
+                "ssh_failures": 1,
+                "first_seen": "2026-01-18T10:14:01+00:00",
+                "last_seen": "2026-01-18T10:30:00+00:00"
+            },
+            "timeline": [
+                {"timestamp": "2026-01-18T10:14:01+00:00", "source": "ssh", "summary": "SSH: Invalid user admin", "log_line": "2026-01-18T10:14:01.402311+00:00 router sshd[4122]: Invalid user admin from 45.155.205.100 port 51234"},
+                {"timestamp": "2026-01-18T10:14:02+00:00", "source": "honeypot", "summary": "Connected to decoy TCP port 23 and was banned", "log_line": "2026-01-18T10:14:02+0000 router kernel: BLOCKED:honeypot: IN=enp1s0 OUT= SRC=45.155.205.100 DST=203.0.113.7 PROTO=TCP SPT=40112 DPT=23"},
+                {"timestamp": "2026-01-18T10:14:30+00:00", "source": "honeypot", "summary": "Added to the honeypot ban set until 2026-01-19T10:14:02+00:00", "log_line": null},
+                {"timestamp": "2026-01-18T10:29:00+00:00", "source": "firewall", "summary": "Dropped TCP port 3389 (spamhaus-drop)", "log_line": "2026-01-18T10:29:00+0000 router kernel: BLOCKED:spamhaus-drop: IN=enp1s0 OUT= SRC=45.155.205.100 DST=203.0.113.7 PROTO=TCP SPT=40544 DPT=3389"},
+                {"timestamp": "2026-01-18T10:30:00+00:00", "source": "firewall", "summary": "Dropped TCP port 22 (spamhaus-drop)", "log_line": "2026-01-18T10:30:00+0000 router kernel: BLOCKED:spamhaus-drop: IN=enp1s0 OUT= SRC=45.155.205.100 DST=203.0.113.7 PROTO=TCP SPT=45678 DPT=22"}
+            ],
+            "omitted": 0
+        })
+    }
+
+    pub fn connection_log() -> serde_json::Value {
+        json!({
+            "settings": {"enabled": true, "storage": "database", "retention_days": 30, "nat_only": true},
+            "effective": {"enabled": true, "storage": "database", "retention_days": 30, "nat_only": true},
+            "stored": {"database_entries": 48213, "files": 0, "file_bytes": 0, "oldest": "2026-09-16T00:02:11+00:00"}
+        })
+    }
+
+    pub fn connection_log_entries() -> serde_json::Value {
+        json!([
+            {
+                "protocol": "tcp", "src_ip": "10.22.22.131", "src_port": 51544, "dst_ip": "93.184.216.34", "dst_port": 443,
+                "nat_src_ip": "203.0.113.5", "nat_src_port": 51544, "nat_dst_ip": null, "nat_dst_port": null,
+                "first_seen": "2026-10-16T08:00:12+00:00", "last_seen": "2026-10-16T08:04:42+00:00"
+            },
+            {
+                "protocol": "udp", "src_ip": "10.22.22.185", "src_port": 40123, "dst_ip": "1.1.1.1", "dst_port": 53,
+                "nat_src_ip": "203.0.113.5", "nat_src_port": 40123, "nat_dst_ip": null, "nat_dst_port": null,
+                "first_seen": "2026-10-16T08:01:02+00:00", "last_seen": "2026-10-16T08:01:32+00:00"
+            },
+            {
+                "protocol": "tcp", "src_ip": "198.51.100.20", "src_port": 60211, "dst_ip": "203.0.113.5", "dst_port": 8443,
+                "nat_src_ip": null, "nat_src_port": null, "nat_dst_ip": "10.22.22.50", "nat_dst_port": 443,
+                "first_seen": "2026-10-16T08:02:40+00:00", "last_seen": "2026-10-16T08:03:10+00:00"
+            }
+        ])
+    }
+
     pub fn overview() -> serde_json::Value {
         json!({
             "firewall_drops_24h": 156,
@@ -192,6 +796,14 @@ pub mod security {
             "failed_ssh_attempts_24h": 3,
             "active_connections": 24,
             "recent_events": [
+                {
+                    "timestamp": "2026-01-18T10:41:00+00:00",
+                    "event_type": "RouterUI Brute Force",
+                    "source_ip": "203.0.113.45",
+                    "details": "Blocked after 5 failed RouterUI logins (last user 'admin') until 2026-01-18T11:41:00+00:00",
+                    "severity": "high",
+                    "is_external": true
+                },
                 {
                     "timestamp": "2026-01-18T10:30:00",
                     "event_type": "Failed Login",
@@ -218,6 +830,75 @@ pub mod security {
         })
     }
 
+    pub fn bruteforce() -> serde_json::Value {
+        json!({
+            "set": "routerui-bruteforce",
+            "active": [
+                { "ip": "203.0.113.45", "expires_in": 3120 }
+            ],
+            "recent": [
+                {
+                    "ip": "203.0.113.45",
+                    "failures": 5,
+                    "last_username": "admin",
+                    "blocked_at": "2026-01-18T10:41:00+00:00",
+                    "expires_at": "2026-01-18T11:41:00+00:00"
+                }
+            ]
+        })
+    }
+
+    pub fn certificates() -> serde_json::Value {
+        json!({
+            "settings": {
+                "warn_days": 14,
+                "endpoints": [
+                    { "name": "Jellyfin", "host": "127.0.0.1", "port": 8920, "server_name": null },
+                    { "name": "AdGuard Home", "host": "127.0.0.1", "port": 443, "server_name": null },
+                    { "name": "Nextcloud", "host": "10.22.22.5", "port": 443, "server_name": "cloud.home.example.com" }
+                ]
+            },
+            "certificates": [
+                {
+                    "name": "AdGuard Home", "source": "endpoint", "location": "127.0.0.1:443",
+                    "subject": "CN=adguard.home", "issuer": "CN=adguard.home", "dns_names": ["adguard.home"],
+                    "expires_at": "2026-10-25T00:00:00+00:00", "days_left": 8, "self_signed": true,
+                    "status": "expiring", "error": null
+                },
+                {
+                    "name": "RouterUI", "source": "routerui", "location": "/opt/routerui/config/certs/router.home.example.com/fullchain.pem",
+                    "subject": "CN=router.home.example.com", "issuer": "C=US, O=Let's Encrypt, CN=R11", "dns_names": ["router.home.example.com"],
+                    "expires_at": "2026-12-20T10:00:00+00:00", "days_left": 64, "self_signed": false,
+                    "status": "valid", "error": null
+                },
+                {
+                    "name": "Nextcloud", "source": "endpoint", "location": "cloud.home.example.com (10.22.22.5:443)",
+                    "subject": "CN=cloud.home.example.com", "issuer": "C=US, O=Let's Encrypt, CN=R10", "dns_names": ["cloud.home.example.com"],
+                    "expires_at": "2027-01-02T08:00:00+00:00", "days_left": 76, "self_signed": false,
+                    "status": "valid", "error": null
+                },
+                {
+                    "name": "Jellyfin", "source": "endpoint", "location": "127.0.0.1:8920",
+                    "subject": null, "issuer": null, "dns_names": [], "expires_at": null, "days_left": null,
+                    "self_signed": false, "status": "error", "error": "error sending request for url (https://127.0.0.1:8920/)"
+                }
+            ]
+        })
+    }
+
+    pub fn ipv6_posture() -> serde_json::Value {
+        json!({
+            "wan_interface": "enp1s0",
+            "lan_interface": "enp2s0",
+            "ipv6_enabled": true,
+            "checks": [
+                { "id": "inbound", "name": "Default-deny inbound", "status": "fail", "detail": "The IPv6 FORWARD chain accepts new connections from enp1s0: LAN devices are reachable from the internet", "fixable": true },
+                { "id": "ra_guard", "name": "RA guard on LAN", "status": "pass", "detail": "The router ignores router advertisements from enp2s0", "fixable": false },
+                { "id": "privacy", "name": "Privacy extensions", "status": "warn", "detail": "Traffic from the router uses the stable address of enp1s0, which can be tracked", "fixable": true }
+            ]
+        })
+    }
+
     pub fn connections() -> serde_json::Value {
         json!([
             { "local_addr": "10.22.22.1:22", "remote_addr": "10.22.22.185:54321", "state": "ESTABLISHED", "process": "sshd" },
@@ -227,6 +908,26 @@ pub mod security {
 }
 
 // Mock data for media
+pub mod protection {
+    use serde_json::json;
+
+    pub fn honeypot() -> serde_json::Value {
+        json!({
+            "settings": {"enabled": true, "ports": [23, 2323], "ban_hours": 24},
+            "set": "routerui-honeypot",
+            "active": [
+                {"ip": "45.155.205.100", "country": "RU", "expires_in": 81240},
+                {"ip": "222.186.30.112", "country": "CN", "expires_in": 62115},
+                {"ip": "185.220.101.34", "country": "DE", "expires_in": 3050}
+            ],
+            "recent": [
+                {"ip": "45.155.205.100", "country": "RU", "banned_at": "2026-01-18T10:14:02+00:00", "expires_at": "2026-01-19T10:14:02+00:00"},
+                {"ip": "222.186.30.112", "country": "CN", "banned_at": "2026-01-18T04:58:37+00:00", "expires_at": "2026-01-19T04:58:37+00:00"}
+            ]
+        })
+    }
+}
+
 pub mod media {
     use serde_json::json;
 
@@ -354,12 +1055,165 @@ pub mod services {
             { "name": "tailscaled", "display_name": "Tailscale", "status": "active", "enabled": true }
         ])
     }
+
+    pub fn monitors() -> serde_json::Value {
+        let jellyfin = json!({
+            "id": "m1", "name": "Jellyfin", "url": "http://192.168.1.20:8096/health", "interval_secs": 60,
+            "timeout_secs": 10, "expected_status": 200, "keyword": "Healthy", "ignore_tls_errors": false, "enabled": true
+        });
+        let nextcloud = json!({
+            "id": "m2", "name": "Nextcloud", "url": "https://cloud.home.lan/status.php", "interval_secs": 300,
+            "timeout_secs": 10, "expected_status": null, "keyword": null, "ignore_tls_errors": true, "enabled": true
+        });
+        json!({
+            "settings": { "failures_before_alert": 2, "monitors": [jellyfin, nextcloud] },
+            "monitors": [
+                {
+                    "monitor": jellyfin,
+                    "state": {
+                        "up": true, "since": "2026-10-15T22:10:00+00:00", "last_checked": "2026-10-16T09:00:00+00:00",
+                        "last_result": { "up": true, "status_code": 200, "latency_ms": 14, "error": null },
+                        "consecutive_failures": 0
+                    },
+                    "uptime": { "uptime_24h": 100.0, "uptime_30d": 99.93, "avg_latency_ms_24h": 15.2 }
+                },
+                {
+                    "monitor": nextcloud,
+                    "state": {
+                        "up": false, "since": "2026-10-16T08:45:00+00:00", "last_checked": "2026-10-16T08:58:00+00:00",
+                        "last_result": { "up": false, "status_code": 502, "latency_ms": 31, "error": "Status 502" },
+                        "consecutive_failures": 4
+                    },
+                    "uptime": { "uptime_24h": 95.1, "uptime_30d": 99.2, "avg_latency_ms_24h": 88.4 }
+                }
+            ]
+        })
+    }
+
+    pub fn monitor_history() -> serde_json::Value {
+        let checks: Vec<serde_json::Value> = (0..24)
+            .map(|h| {
+                let up = h != 20;
+                json!({
+                    "checked_at": format!("2026-10-16T{:02}:00:00+00:00", h % 24),
+                    "up": up,
+                    "status_code": if up { 200 } else { 502 },
+                    "latency_ms": 12 + (h * 7) % 20,
+                    "error": if up { None } else { Some("Status 502") }
+                })
+            })
+            .collect();
+        json!({ "id": "m1", "hours": 24, "checks": checks })
+    }
+
+    pub fn homelab_hosts() -> serde_json::Value {
+        json!({
+            "public_key": "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIMockMockMockMockMockMockMockMockMockMockMo routerui",
+            "hosts": [
+                {
+                    "host": {
+                        "id": "h1", "name": "NAS", "host": "192.168.1.20", "port": 22, "username": "admin",
+                        "mac_address": "aa:bb:cc:dd:ee:02", "os": "Linux 6.1.0-18-amd64", "created_at": "2026-10-01T12:00:00+00:00"
+                    },
+                    "state": {
+                        "online": true, "last_checked": "2026-10-16T21:59:00+00:00",
+                        "disks": [
+                            {"filesystem": "/dev/nvme0n1p2", "mount": "/", "size_kb": 245_000_000, "used_kb": 61_000_000, "available_kb": 171_000_000, "percent": 27},
+                            {"filesystem": "tank/media", "mount": "/srv/media", "size_kb": 7_800_000_000u64, "used_kb": 6_900_000_000u64, "available_kb": 900_000_000, "percent": 89}
+                        ],
+                        "disks_checked": "2026-10-16T21:40:12+00:00",
+                        "wake": {"sent_at": "2026-10-16T08:15:37+00:00", "result": "up", "after_secs": 42}
+                    }
+                },
+                {
+                    "host": {
+                        "id": "h2", "name": "Media server", "host": "192.168.1.10", "port": 22, "username": "root",
+                        "mac_address": null, "os": "Linux 6.8.0-45-generic", "created_at": "2026-10-02T18:30:00+00:00"
+                    },
+                    "state": {"online": false, "last_checked": "2026-10-16T21:59:00+00:00", "disks": null, "disks_checked": null, "wake": null}
+                }
+            ]
+        })
+    }
 }
 
 // Mock data for system
 pub mod system {
     use serde_json::json;
 
+    pub fn public_status_probe() -> crate::public_status::Probe {
+        crate::public_status::Probe {
+            internet_up: true,
+            latency_ms: Some(11.4),
+            checked_at: chrono::Utc::now().to_rfc3339(),
+        }
+    }
+
+    pub fn drift() -> serde_json::Value {
+        json!([
+            {
+                "path": "/etc/dnsmasq.d/static-leases.conf",
+                "written_at": "2025-01-12T09:30:00+00:00",
+                "deleted": false,
+                "diff": "--- written by RouterUI\n+++ on disk\n@@ -1,2 +1,3 @@\n dhcp-host=aa:bb:cc:dd:ee:01,10.22.22.20,nas\n dhcp-host=aa:bb:cc:dd:ee:02,10.22.22.21,printer\n+dhcp-host=aa:bb:cc:dd:ee:03,10.22.22.22,camera\n"
+            }
+        ])
+    }
+
+    pub fn commands() -> serde_json::Value {
+        json!({
+            "commands": [
+                {
+                    "id": 3, "started_at": "2026-10-17T08:02:11+00:00", "command": "netfilter-persistent", "args": ["save"],
+                    "exit_code": 0, "error": null, "duration_ms": 412,
+                    "request_id": "5f2c9a1e7b3d4c60", "request": "POST /api/firewall/port-forwards"
+                },
+                {
+                    "id": 2, "started_at": "2026-10-17T08:02:11+00:00", "command": "iptables",
+                    "args": ["-t", "nat", "-A", "PREROUTING", "-i", "enp1s0", "-p", "tcp", "--dport", "25565", "-j", "DNAT", "--to-destination", "10.22.22.40:25565"],
+                    "exit_code": 0, "error": null, "duration_ms": 9,
+                    "request_id": "5f2c9a1e7b3d4c60", "request": "POST /api/firewall/port-forwards"
+                },
+                {
+                    "id": 1, "started_at": "2026-10-17T08:00:00+00:00", "command": "ipset", "args": ["list", "spamhaus-drop", "-t"],
+                    "exit_code": 1, "error": null, "duration_ms": 4,
+                    "request_id": null, "request": null
+                }
+            ],
+            "retention": { "max_entries": 1000, "max_age_hours": 24 }
+        })
+    }
+
+    pub fn lint() -> serde_json::Value {
+        json!({
+            "checked_at": "2026-10-17T08:00:00+00:00",
+            "duration_ms": 2140,
+            "errors": 1,
+            "warnings": 2,
+            "findings": [
+                {
+                    "check": "blocklists", "severity": "error", "subject": "spamhaus-drop",
+                    "message": "spamhaus-drop is enabled but its ipset is missing, so nothing on it is blocked",
+                    "fix": "Update the list on the Protection page to load it again"
+                },
+                {
+                    "check": "certificates", "severity": "warning", "subject": "AdGuard Home (127.0.0.1:443)",
+                    "message": "AdGuard Home expires in 8 day(s)", "fix": "Renew it on the Certificates page"
+                },
+                {
+                    "check": "port_forwards", "severity": "warning", "subject": "10.22.22.40",
+                    "message": "Nothing answers at 10.22.22.40, which port(s) 25565/tcp forward to",
+                    "fix": "Check the device is on, or disable the forward"
+                },
+                {
+                    "check": "interfaces", "severity": "info", "subject": "wlo1",
+                    "message": "wlo1 is in the lan zone but doesn't exist, so its zone rules match nothing",
+                    "fix": "Remove it from the zone on the Firewall page if it is gone for good"
+                }
+            ]
+        })
+    }
+
     pub fn status() -> serde_json::Value {
         json!({
             "hostname": "mock-router",
@@ -369,7 +1223,171 @@ pub mod system {
             "cpu_model": "Intel N150",
             "cpu_cores": 4,
             "memory_total_mb": 16000,
-            "memory_used_mb": 4000
+            "memory_used_mb": 4000,
+            "wan_ip": wan_ip()
+        })
+    }
+
+    pub fn listening() -> serde_json::Value {
+        json!({
+            "wan_interface": "enp1s0",
+            "exposed": 1,
+            "sockets": [
+                { "protocol": "udp", "bind_address": "0.0.0.0", "port": 41641, "interface": null, "process": "tailscaled", "pid": 901, "wan_reachable": true, "reason": "Allowed by firewall rule" },
+                { "protocol": "tcp", "bind_address": "0.0.0.0", "port": 22, "interface": null, "process": "sshd", "pid": 812, "wan_reachable": false, "reason": "Dropped by INPUT policy" },
+                { "protocol": "udp", "bind_address": "192.168.1.1", "port": 53, "interface": null, "process": "dnsmasq", "pid": 655, "wan_reachable": false, "reason": "Bound to a non-WAN address" },
+                { "protocol": "tcp", "bind_address": "0.0.0.0", "port": 3080, "interface": null, "process": "routerui-api", "pid": 1020, "wan_reachable": false, "reason": "Dropped by INPUT policy" },
+                { "protocol": "tcp", "bind_address": "127.0.0.1", "port": 3000, "interface": null, "process": "AdGuardHome", "pid": 733, "wan_reachable": false, "reason": "Bound to loopback" }
+            ]
+        })
+    }
+
+    pub fn privileges() -> serde_json::Value {
+        json!({
+            "user": "routerui",
+            "running_as_root": false,
+            "mode": "helper",
+            "allowed": 3,
+            "denied": 1,
+            "tests": [
+                { "binary": "iptables", "command": "iptables -L INPUT -n", "rule": "iptables -L *", "purpose": "Read firewall rules", "allowed": true, "detail": "Allowed" },
+                { "binary": "ipset", "command": "ipset list protection-whitelist", "rule": "ipset list *", "purpose": "Read blocklists", "allowed": true, "detail": "Allowed" },
+                { "binary": "systemctl", "command": "systemctl restart dnsmasq", "rule": "systemctl restart *", "purpose": "Restart services", "allowed": true, "detail": "Allowed" },
+                { "binary": "apt", "command": "apt upgrade -y", "rule": "apt upgrade -y", "purpose": "System updates", "allowed": false, "detail": "Not permitted by sudoers" }
+            ]
+        })
+    }
+
+    pub fn profiles() -> serde_json::Value {
+        json!({
+            "profiles": [
+                {
+                    "profile": { "id": "home", "name": "Home", "firewall": true, "wifi": true, "guest_wifi": true, "exit_node": "", "parental_control": false, "safe_search": false, "schedule": { "enabled": false, "windows": [] } },
+                    "changes": ["Firewall on", "WiFi on", "Guest WiFi on", "No VPN exit node", "Parental control off", "Safe search off"],
+                    "scheduled": null,
+                    "next_change": null
+                },
+                {
+                    "profile": { "id": "vacation", "name": "Vacation", "firewall": true, "wifi": false, "guest_wifi": false, "exit_node": null, "parental_control": null, "safe_search": null, "schedule": { "enabled": true, "windows": [{ "days": ["sat", "sun"], "start": "00:00", "end": "00:00" }] } },
+                    "changes": ["Firewall on", "WiFi off", "Guest WiFi off"],
+                    "scheduled": false,
+                    "next_change": "2026-10-17T00:00:00+00:00"
+                }
+            ],
+            "active": "home",
+            "active_since": "2026-10-12T18:00:00+00:00",
+            "default_profile": "home"
+        })
+    }
+
+    pub fn wan_ip() -> serde_json::Value {
+        json!({
+            "interface": "enp1s0",
+            "current": { "ip": "203.0.113.42", "source": "interface:enp1s0", "changed_at": "2024-01-15T08:12:00+00:00" },
+            "previous": { "ip": "203.0.113.17", "source": "interface:enp1s0", "changed_at": "2024-01-02T03:40:00+00:00" },
+            "last_checked": "2024-01-16T10:00:00+00:00",
+            "last_error": null
+        })
+    }
+}
+
+// Mock data for setup
+pub mod tools {
+    use serde_json::json;
+
+    pub fn lan_throughput(sender: &str, receiver: &str, seconds: u32) -> serde_json::Value {
+        json!({
+            "seconds": seconds,
+            "sender": {
+                "ip": sender,
+                "link": {
+                    "mac": "aa:bb:cc:dd:ee:01", "hostname": "laptop", "interface": "wlo1",
+                    "medium": "wifi", "rate_mbps": 400.0, "signal_dbm": -67
+                },
+                "alone": {"mbps": 188.4, "retransmits": 42, "router_cpu": 12.5, "device_cpu": 21.0},
+                "relayed": {"mbps": 176.9, "retransmits": 57, "router_cpu": 19.8, "device_cpu": 20.4}
+            },
+            "receiver": {
+                "ip": receiver,
+                "link": {
+                    "mac": "aa:bb:cc:dd:ee:02", "hostname": null, "interface": "enp2s0",
+                    "medium": "wired", "rate_mbps": 1000.0, "signal_dbm": null
+                },
+                "alone": {"mbps": 936.2, "retransmits": 0, "router_cpu": 18.1, "device_cpu": 9.7},
+                "relayed": {"mbps": 181.3, "retransmits": 0, "router_cpu": 19.8, "device_cpu": 4.2}
+            },
+            "relay_mbps": 176.9,
+            "bottleneck": "wifi",
+            "explanation": format!("WiFi: laptop ({}) gets 188 Mb/s over a 400 Mb/s link at -67 dBm, the slowest leg of the path", sender)
+        })
+    }
+}
+
+pub mod setup {
+    use serde_json::json;
+
+    pub fn preflight() -> serde_json::Value {
+        json!({
+            "ready": true,
+            "checks": [
+                { "id": "binary:ip", "name": "ip", "status": "pass", "detail": "/usr/sbin/ip", "fix": null },
+                { "id": "binary:iptables", "name": "iptables", "status": "pass", "detail": "/usr/sbin/iptables", "fix": null },
+                { "id": "binary:systemctl", "name": "systemctl", "status": "pass", "detail": "/usr/bin/systemctl", "fix": null },
+                { "id": "binary:netfilter-persistent", "name": "netfilter-persistent", "status": "warn", "detail": "'netfilter-persistent' was not found; related features will be unavailable", "fix": "apt install iptables-persistent" },
+                { "id": "sudo", "name": "sudo", "status": "pass", "detail": "Running as root", "fix": null },
+                { "id": "module:ipset", "name": "ipset", "status": "pass", "detail": "Loaded", "fix": null },
+                { "id": "module:nf_nat", "name": "nf_nat", "status": "pass", "detail": "Loaded", "fix": null },
+                { "id": "sysctl:ip_forward", "name": "IP forwarding", "status": "warn", "detail": "Disabled; the setup wizard will enable it", "fix": null },
+                { "id": "write:/opt/routerui", "name": "Write access to /opt/routerui", "status": "pass", "detail": "Writable", "fix": null }
+            ]
+        })
+    }
+}
+
+// Mock data for certificates
+pub mod certificates {
+    use serde_json::json;
+
+    pub fn list() -> serde_json::Value {
+        json!({
+            "settings": {
+                "directory_url": "https://acme-v02.api.letsencrypt.org/directory",
+                "email": "admin@example.com",
+                "provider": "cloudflare",
+                "api_token_set": true,
+                "zone_id": null,
+                "propagation_timeout": 300
+            },
+            "certificates": [
+                {
+                    "name": "router.home.example.com",
+                    "domains": ["router.home.example.com"],
+                    "status": "valid",
+                    "local_ip": "192.168.1.1",
+                    "issued_at": "2024-01-10T04:00:00+00:00",
+                    "expires_at": "2024-04-09T04:00:00+00:00",
+                    "last_error": null,
+                    "cert_path": "/opt/routerui/config/certs/router.home.example.com/fullchain.pem",
+                    "key_path": "/opt/routerui/config/certs/router.home.example.com/privkey.pem",
+                    "used_by_ui": true
+                },
+                {
+                    "name": "wildcard.lan.example.com",
+                    "domains": ["*.lan.example.com", "lan.example.com"],
+                    "status": "error",
+                    "local_ip": null,
+                    "issued_at": null,
+                    "expires_at": null,
+                    "last_error": "_acme-challenge.lan.example.com did not appear in public DNS within 300s",
+                    "cert_path": "/opt/routerui/config/certs/wildcard.lan.example.com/fullchain.pem",
+                    "key_path": "/opt/routerui/config/certs/wildcard.lan.example.com/privkey.pem",
+                    "used_by_ui": false
+                }
+            ],
+            "directories": {
+                "production": "https://acme-v02.api.letsencrypt.org/directory",
+                "staging": "https://acme-staging-v02.api.letsencrypt.org/directory"
+            }
         })
     }
 }
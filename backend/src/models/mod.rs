@@ -10,6 +10,10 @@ pub struct User {
     pub enabled: bool,
     pub created_at: String,
     pub last_login: Option<String>,
+    // Only used by the "custom" role; filled in by db::get_user_by_*
+    #[sqlx(skip)]
+    #[serde(default)]
+    pub permissions: Vec<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -17,6 +21,8 @@ pub struct UserCreate {
     pub username: String,
     pub password: String,
     pub role: String,
+    #[serde(default)]
+    pub permissions: Vec<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -25,18 +31,33 @@ pub struct UserUpdate {
     pub password: Option<String>,
     pub role: Option<String>,
     pub enabled: Option<bool>,
+    pub permissions: Option<Vec<String>>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LoginRequest {
     pub username: String,
     pub password: String,
+    // "Remember this device": a long-lived session that doesn't time out when idle
+    #[serde(default)]
+    pub remember: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ChangePasswordRequest {
+    pub current_password: String,
+    pub new_password: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LoginResponse {
     pub token: String,
+    // Only with "remember this device"; also set as an HttpOnly cookie for the browser
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub refresh_token: Option<String>,
     pub user: UserPublic,
+    // The password is older than the policy allows and should be changed
+    pub password_expired: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -44,6 +65,20 @@ pub struct UserPublic {
     pub id: i64,
     pub username: String,
     pub role: String,
+    // Effective "module:access" grants, whatever the role
+    #[serde(default)]
+    pub permissions: Vec<String>,
+}
+
+impl From<User> for UserPublic {
+    fn from(user: User) -> Self {
+        UserPublic {
+            permissions: crate::auth::permissions::effective(&user),
+            id: user.id,
+            username: user.username,
+            role: user.role,
+        }
+    }
 }
 
 #[derive(Debug, Clone, sqlx::FromRow)]
@@ -54,6 +89,10 @@ pub struct Session {
     pub created_at: String,
     pub expires_at: String,
     pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub revoked_at: Option<String>,
+    pub last_seen_at: Option<String>,
+    pub remember: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -61,4 +100,6 @@ pub struct PasswordStrength {
     pub score: u8,
     pub label: String,
     pub suggestions: Vec<String>,
+    // The password policy accepts it
+    pub meets_policy: bool,
 }
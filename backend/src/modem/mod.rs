@@ -0,0 +1,713 @@
+// USB LTE/5G modem through ModemManager (mmcli): status and signal, connecting with the
+// configured APN, data usage against the SIM's monthly cap, SMS, and using the modem either as
+// the WAN or as a failover that only comes up while the wired WAN is unreachable.
+
+use chrono::{Datelike, Duration as ChronoDuration, Local, NaiveDate, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::SqlitePool;
+use std::process::Command;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::events::Event;
+use crate::system::commands::RecordedCommand;
+use crate::system::privileges::sudo;
+use crate::AppState;
+
+const SETTINGS_FILE: &str = "/opt/routerui/modem.json";
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+// Consecutive failed/successful checks of the wired WAN before switching over or back
+const FAILOVER_AFTER: u32 = 3;
+const RECOVER_AFTER: u32 = 3;
+// Probed through the wired WAN interface only
+const PROBE_TARGETS: &[&str] = &["1.1.1.1", "8.8.8.8"];
+// Metric of the default route over the modem: below a DHCP WAN's usual 100, so traffic moves
+// over while failover is active. A wired WAN with a static default route (metric 0) keeps
+// winning; give that route a metric to use failover.
+const FAILOVER_METRIC: u32 = 50;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ModemRole {
+    #[default]
+    Disabled,
+    // Always connected and used as the uplink
+    Wan,
+    // Connected only while the wired WAN is down
+    Failover,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModemSettings {
+    #[serde(default)]
+    pub role: ModemRole,
+    #[serde(default)]
+    pub apn: String,
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub password: Option<String>,
+    // ipv4, ipv6 or ipv4v6; left to the modem when unset
+    #[serde(default)]
+    pub ip_type: Option<String>,
+    // Monthly allowance of the SIM, in MB
+    #[serde(default)]
+    pub data_cap_mb: Option<u64>,
+    // Day of the month the allowance resets
+    #[serde(default = "default_billing_day")]
+    pub billing_day: u32,
+    #[serde(default = "default_warn_percent")]
+    pub warn_percent: u32,
+    // Hang up once the cap is reached, until the next billing cycle
+    #[serde(default)]
+    pub disconnect_at_cap: bool,
+}
+
+fn default_billing_day() -> u32 {
+    1
+}
+
+fn default_warn_percent() -> u32 {
+    80
+}
+
+impl Default for ModemSettings {
+    fn default() -> Self {
+        Self {
+            role: ModemRole::Disabled,
+            apn: String::new(),
+            username: None,
+            password: None,
+            ip_type: None,
+            data_cap_mb: None,
+            billing_day: default_billing_day(),
+            warn_percent: default_warn_percent(),
+            disconnect_at_cap: false,
+        }
+    }
+}
+
+pub fn load_settings() -> ModemSettings {
+    std::fs::read_to_string(SETTINGS_FILE)
+        .ok()
+        .and_then(|c| serde_json::from_str(&c).ok())
+        .unwrap_or_default()
+}
+
+// Holds the APN password, so the file is 0600: owned by the service user, which writes it, and
+// unreadable to anyone else
+pub fn save_settings(settings: &ModemSettings) -> Result<(), String> {
+    use std::io::Write;
+    use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+
+    let json = serde_json::to_string_pretty(settings).map_err(|e| e.to_string())?;
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(SETTINGS_FILE)
+        .map_err(|e| e.to_string())?;
+    // mode() only applies to a new file; one saved by an earlier version is readable by anyone
+    file.set_permissions(std::fs::Permissions::from_mode(0o600)).map_err(|e| e.to_string())?;
+    file.write_all(json.as_bytes()).map_err(|e| e.to_string())
+}
+
+impl ModemSettings {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.role != ModemRole::Disabled && self.apn.trim().is_empty() {
+            return Err("An APN is required to use the modem".to_string());
+        }
+        if let Some(ip_type) = &self.ip_type {
+            if !["ipv4", "ipv6", "ipv4v6"].contains(&ip_type.as_str()) {
+                return Err(format!("Unknown IP type: {}", ip_type));
+            }
+        }
+        if !(1..=28).contains(&self.billing_day) {
+            return Err("Billing day must be between 1 and 28".to_string());
+        }
+        if !(1..=100).contains(&self.warn_percent) {
+            return Err("Warning threshold must be between 1 and 100%".to_string());
+        }
+        // mmcli takes the settings as one comma-separated argument
+        let fields = [Some(&self.apn), self.username.as_ref(), self.password.as_ref()];
+        if fields.into_iter().flatten().any(|v| v.chars().any(|c| c.is_control() || matches!(c, ',' | '=' | '\'' | '"'))) {
+            return Err("APN, username and password cannot contain , = or quotes".to_string());
+        }
+        Ok(())
+    }
+
+    fn connect_settings(&self) -> Vec<(String, String)> {
+        let mut settings = vec![("apn".to_string(), self.apn.trim().to_string())];
+        let optional = [("ip-type", &self.ip_type), ("user", &self.username), ("password", &self.password)];
+        for (key, value) in optional {
+            if let Some(value) = value.as_deref().filter(|v| !v.is_empty()) {
+                settings.push((key.to_string(), value.to_string()));
+            }
+        }
+        settings
+    }
+}
+
+// ============ MMCLI ============
+
+fn mmcli_json(args: &[&str]) -> Result<Value, String> {
+    let output = Command::new("mmcli").args(args).output().map_err(|e| format!("mmcli: {}", e))?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+    }
+    serde_json::from_slice(&output.stdout).map_err(|e| e.to_string())
+}
+
+fn mmcli_privileged(args: &[String]) -> Result<Vec<u8>, String> {
+    let output = sudo().arg("mmcli").args(args).output().map_err(|e| e.to_string())?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+    }
+    Ok(output.stdout)
+}
+
+// mmcli reports every value as a string, "--" when unknown
+fn text<'a>(value: &'a Value, path: &[&str]) -> Option<&'a str> {
+    path.iter()
+        .try_fold(value, |v, key| v.get(key))?
+        .as_str()
+        .filter(|s| *s != "--" && !s.is_empty())
+}
+
+fn number(value: &Value, path: &[&str]) -> Option<f64> {
+    text(value, path)?.parse().ok()
+}
+
+fn strings(value: &Value, path: &[&str]) -> Vec<String> {
+    path.iter()
+        .try_fold(value, |v, key| v.get(key))
+        .and_then(|v| v.as_array())
+        .map(|a| a.iter().filter_map(|s| s.as_str()).filter(|s| *s != "--").map(String::from).collect())
+        .unwrap_or_default()
+}
+
+// "/org/freedesktop/ModemManager1/Modem/0" -> 0
+fn object_index(path: &str) -> Option<u32> {
+    path.rsplit('/').next()?.parse().ok()
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Signal {
+    // 0-100, as reported by the modem
+    pub quality: Option<f64>,
+    // lte, 5gnr, umts... whichever the modem reports
+    pub technology: Option<String>,
+    pub rssi: Option<f64>,
+    pub rsrp: Option<f64>,
+    pub rsrq: Option<f64>,
+    pub snr: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ModemStatus {
+    pub index: u32,
+    pub manufacturer: Option<String>,
+    pub model: Option<String>,
+    pub state: Option<String>,
+    pub operator: Option<String>,
+    pub registration: Option<String>,
+    pub access_technologies: Vec<String>,
+    pub own_numbers: Vec<String>,
+    pub signal: Signal,
+    // Kernel network interface carrying data, e.g. wwan0
+    pub data_interface: Option<String>,
+    pub bearers: Vec<u32>,
+}
+
+impl ModemStatus {
+    pub fn connected(&self) -> bool {
+        self.state.as_deref() == Some("connected")
+    }
+}
+
+/// First modem ModemManager knows about
+pub fn find_modem() -> Result<Option<u32>, String> {
+    let list = mmcli_json(&["-L", "-J"])?;
+    Ok(strings(&list, &["modem-list"]).iter().find_map(|p| object_index(p)))
+}
+
+fn signal(index: u32, info: &Value) -> Signal {
+    let quality = number(info, &["modem", "generic", "signal-quality", "value"]);
+    let detail = mmcli_json(&["-m", &index.to_string(), "--signal-get", "-J"]).unwrap_or(Value::Null);
+    let signal = &detail["modem"]["signal"];
+
+    // Newest technology with values first
+    let technology = ["5g", "nr5g", "lte", "umts", "gsm", "cdma1x", "evdo"]
+        .into_iter()
+        .find(|t| ["rssi", "rsrp", "rsrq", "snr", "ecio"].iter().any(|k| text(signal, &[t, k]).is_some()));
+    let field = |key: &str| technology.and_then(|t| number(signal, &[t, key]));
+
+    Signal {
+        quality,
+        technology: technology.map(String::from),
+        rssi: field("rssi"),
+        rsrp: field("rsrp"),
+        rsrq: field("rsrq"),
+        snr: field("snr"),
+    }
+}
+
+pub fn status(index: u32) -> Result<ModemStatus, String> {
+    let info = mmcli_json(&["-m", &index.to_string(), "-J"])?;
+    let generic = |key: &str| text(&info, &["modem", "generic", key]).map(String::from);
+
+    // "wwan0 (net)"
+    let data_interface = strings(&info, &["modem", "generic", "ports"])
+        .iter()
+        .find_map(|p| p.strip_suffix(" (net)").map(String::from));
+
+    Ok(ModemStatus {
+        index,
+        manufacturer: generic("manufacturer"),
+        model: generic("model"),
+        state: generic("state"),
+        operator: text(&info, &["modem", "3gpp", "operator-name"]).map(String::from),
+        registration: text(&info, &["modem", "3gpp", "registration-state"]).map(String::from),
+        access_technologies: strings(&info, &["modem", "generic", "access-technologies"]),
+        own_numbers: strings(&info, &["modem", "generic", "own-numbers"]),
+        signal: signal(index, &info),
+        data_interface,
+        bearers: strings(&info, &["modem", "generic", "bearers"]).iter().filter_map(|p| object_index(p)).collect(),
+    })
+}
+
+/// Turn on the extended signal readings (RSRP, SNR...), which modems don't report by default
+pub fn enable_signal_details(index: u32) -> Result<(), String> {
+    mmcli_privileged(&["-m".to_string(), index.to_string(), "--signal-setup=30".to_string()]).map(|_| ())
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BearerConfig {
+    pub interface: String,
+    // static or dhcp
+    pub method: String,
+    pub address: Option<String>,
+    pub prefix: Option<u8>,
+    pub gateway: Option<String>,
+    pub dns: Vec<String>,
+}
+
+fn connected_bearer(status: &ModemStatus) -> Option<BearerConfig> {
+    status.bearers.iter().find_map(|bearer| {
+        let info = mmcli_json(&["-b", &bearer.to_string(), "-J"]).ok()?;
+        if text(&info, &["bearer", "status", "connected"]) != Some("yes") {
+            return None;
+        }
+        Some(BearerConfig {
+            interface: text(&info, &["bearer", "status", "interface"]).map(String::from).or_else(|| status.data_interface.clone())?,
+            method: text(&info, &["bearer", "ipv4-config", "method"]).unwrap_or("dhcp").to_string(),
+            address: text(&info, &["bearer", "ipv4-config", "address"]).map(String::from),
+            prefix: text(&info, &["bearer", "ipv4-config", "prefix"]).and_then(|p| p.parse().ok()),
+            gateway: text(&info, &["bearer", "ipv4-config", "gateway"]).map(String::from),
+            dns: strings(&info, &["bearer", "ipv4-config", "dns"]),
+        })
+    })
+}
+
+fn run_checked(command: &mut RecordedCommand) -> Result<(), String> {
+    let output = command.output().map_err(|e| e.to_string())?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+    }
+    Ok(())
+}
+
+// ModemManager only brings up the bearer; addressing the interface and NAT are left to us.
+// With the dhcp method (ECM/NCM modems) the system's DHCP client addresses the interface.
+fn configure_interface(bearer: &BearerConfig) -> Result<(), String> {
+    let dev = bearer.interface.as_str();
+    if bearer.method == "static" {
+        let (Some(address), Some(prefix)) = (&bearer.address, bearer.prefix) else {
+            return Err("Bearer reported no IPv4 address".to_string());
+        };
+        run_checked(sudo().args(["ip", "addr", "flush", "dev", dev]))?;
+        run_checked(sudo().args(["ip", "addr", "add", &format!("{}/{}", address, prefix), "dev", dev]))?;
+    }
+    run_checked(sudo().args(["ip", "link", "set", dev, "up"]))?;
+
+    let masquerade = ["POSTROUTING", "-o", dev, "-j", "MASQUERADE"];
+    let exists = sudo().args(["iptables", "-t", "nat", "-C"]).args(masquerade).output().is_ok_and(|o| o.status.success());
+    if !exists {
+        run_checked(sudo().args(["iptables", "-t", "nat", "-A"]).args(masquerade))?;
+    }
+    Ok(())
+}
+
+/// Connect with the configured APN and set up the data interface
+pub fn connect(index: u32, settings: &ModemSettings) -> Result<BearerConfig, String> {
+    let args: Vec<String> = crate::helper::commands::Privileged::ModemConnect { modem: index, settings: settings.connect_settings() }
+        .argv()?
+        .1;
+    mmcli_privileged(&args)?;
+
+    let bearer = connected_bearer(&status(index)?).ok_or("Connected, but no active bearer was reported")?;
+    configure_interface(&bearer)?;
+    tracing::info!("Modem {} connected on {} ({})", index, bearer.interface, bearer.address.as_deref().unwrap_or("dhcp"));
+    Ok(bearer)
+}
+
+pub fn disconnect(index: u32) -> Result<(), String> {
+    mmcli_privileged(&["-m".to_string(), index.to_string(), "--simple-disconnect".to_string()]).map(|_| ())
+}
+
+fn set_default_route(interface: &str, add: bool) -> Result<(), String> {
+    let metric = FAILOVER_METRIC.to_string();
+    match run_checked(sudo().args(["ip", "route", if add { "add" } else { "del" }, "default", "dev", interface, "metric", &metric])) {
+        Err(e) if add && e.contains("File exists") => Ok(()),
+        result => result,
+    }
+}
+
+// ============ SMS ============
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Sms {
+    pub id: u32,
+    pub number: Option<String>,
+    pub text: Option<String>,
+    // received, sent, stored...
+    pub state: Option<String>,
+    pub timestamp: Option<String>,
+}
+
+pub fn list_sms(index: u32) -> Result<Vec<Sms>, String> {
+    let list: Value = serde_json::from_slice(&mmcli_privileged(&[
+        "-m".to_string(),
+        index.to_string(),
+        "--messaging-list-sms".to_string(),
+        "-J".to_string(),
+    ])?)
+    .map_err(|e| e.to_string())?;
+
+    let mut messages: Vec<Sms> = strings(&list, &["modem.messaging.sms"])
+        .iter()
+        .filter_map(|path| object_index(path))
+        .filter_map(|id| {
+            let sms: Value = serde_json::from_slice(&mmcli_privileged(&["-s".to_string(), id.to_string(), "-J".to_string()]).ok()?).ok()?;
+            Some(Sms {
+                id,
+                number: text(&sms, &["sms", "content", "number"]).map(String::from),
+                text: text(&sms, &["sms", "content", "text"]).map(String::from),
+                state: text(&sms, &["sms", "properties", "state"]).map(String::from),
+                timestamp: text(&sms, &["sms", "properties", "timestamp"]).map(String::from),
+            })
+        })
+        .collect();
+    messages.sort_by(|a, b| b.timestamp.cmp(&a.timestamp));
+    Ok(messages)
+}
+
+pub fn send_sms(index: u32, number: &str, message: &str) -> Result<(), String> {
+    let args = crate::helper::commands::Privileged::SmsCreate { modem: index, number: number.to_string(), text: message.to_string() }
+        .argv()?
+        .1;
+    // "Successfully created new SMS: /org/freedesktop/ModemManager1/SMS/4"
+    let created = String::from_utf8_lossy(&mmcli_privileged(&args)?).to_string();
+    let sms = created
+        .split_whitespace()
+        .last()
+        .and_then(object_index)
+        .ok_or_else(|| format!("Unexpected mmcli output: {}", created.trim()))?;
+    mmcli_privileged(&["-s".to_string(), sms.to_string(), "--send".to_string()]).map(|_| ())
+}
+
+pub fn delete_sms(index: u32, sms: u32) -> Result<(), String> {
+    mmcli_privileged(&["-m".to_string(), index.to_string(), format!("--messaging-delete-sms={}", sms)]).map(|_| ())
+}
+
+// ============ DATA USAGE ============
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DataUsage {
+    pub cycle_start: String,
+    pub cycle_end: String,
+    pub rx_bytes: i64,
+    pub tx_bytes: i64,
+    pub used_mb: f64,
+    pub cap_mb: Option<u64>,
+    pub percent: Option<f64>,
+}
+
+/// Start of the billing cycle containing `today`
+fn cycle_start(today: NaiveDate, billing_day: u32) -> NaiveDate {
+    let this_month = today.with_day(billing_day).unwrap_or(today);
+    if today >= this_month {
+        this_month
+    } else {
+        this_month.checked_sub_months(chrono::Months::new(1)).unwrap_or(this_month)
+    }
+}
+
+fn local_midnight(date: NaiveDate) -> chrono::DateTime<Utc> {
+    Local
+        .from_local_datetime(&date.and_hms_opt(0, 0, 0).unwrap_or_default())
+        .earliest()
+        .map(|t| t.with_timezone(&Utc))
+        .unwrap_or_else(Utc::now)
+}
+
+pub async fn record_usage(pool: &SqlitePool, rx_bytes: u64, tx_bytes: u64) -> Result<(), sqlx::Error> {
+    sqlx::query("INSERT INTO modem_usage (sampled_at, rx_bytes, tx_bytes) VALUES (?, ?, ?)")
+        .bind(Utc::now().to_rfc3339())
+        .bind(rx_bytes as i64)
+        .bind(tx_bytes as i64)
+        .execute(pool)
+        .await
+        .map(|_| ())
+}
+
+/// Traffic in the current billing cycle
+pub async fn usage(pool: &SqlitePool, settings: &ModemSettings) -> Result<DataUsage, sqlx::Error> {
+    let start = cycle_start(Local::now().date_naive(), settings.billing_day);
+    let end = start.checked_add_months(chrono::Months::new(1)).unwrap_or(start);
+    let (rx, tx): (i64, i64) = sqlx::query_as(
+        "SELECT COALESCE(SUM(rx_bytes), 0), COALESCE(SUM(tx_bytes), 0) FROM modem_usage WHERE sampled_at >= ?",
+    )
+    .bind(local_midnight(start).to_rfc3339())
+    .fetch_one(pool)
+    .await?;
+
+    let used_mb = (rx + tx) as f64 / 1_000_000.0;
+    Ok(DataUsage {
+        cycle_start: start.to_string(),
+        cycle_end: end.to_string(),
+        rx_bytes: rx,
+        tx_bytes: tx,
+        used_mb,
+        cap_mb: settings.data_cap_mb,
+        percent: settings.data_cap_mb.filter(|c| *c > 0).map(|cap| used_mb / cap as f64 * 100.0),
+    })
+}
+
+/// Daily traffic for the last `days` days, oldest first
+pub async fn daily_usage(pool: &SqlitePool, days: u32) -> Result<Vec<(String, i64, i64)>, sqlx::Error> {
+    sqlx::query_as(
+        "SELECT substr(sampled_at, 1, 10) AS day, SUM(rx_bytes), SUM(tx_bytes) FROM modem_usage
+         WHERE sampled_at >= ? GROUP BY day ORDER BY day",
+    )
+    .bind((Utc::now() - ChronoDuration::days(days as i64)).to_rfc3339())
+    .fetch_all(pool)
+    .await
+}
+
+fn interface_counters(interface: &str) -> Option<(u64, u64)> {
+    let read = |name: &str| {
+        std::fs::read_to_string(format!("/sys/class/net/{}/statistics/{}", interface, name)).ok()?.trim().parse().ok()
+    };
+    Some((read("rx_bytes")?, read("tx_bytes")?))
+}
+
+// ============ MONITOR ============
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CapLevel {
+    Warning,
+    Exceeded,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct MonitorState {
+    pub last_checked: Option<String>,
+    pub last_error: Option<String>,
+    // Failover currently routing traffic over the modem
+    pub failover_active: bool,
+    pub failover_since: Option<String>,
+    // Disconnected because the cap was reached; cleared when the cycle rolls over
+    pub capped_cycle: Option<String>,
+    #[serde(skip)]
+    alerted: Option<(String, CapLevel)>,
+    #[serde(skip)]
+    wan_failures: u32,
+    #[serde(skip)]
+    wan_successes: u32,
+    #[serde(skip)]
+    counters: Option<(String, u64, u64)>,
+}
+
+/// State of the background modem check, shown alongside the modem status
+#[derive(Default)]
+pub struct ModemTracker {
+    state: Mutex<MonitorState>,
+}
+
+impl ModemTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn snapshot(&self) -> MonitorState {
+        self.state.lock().unwrap().clone()
+    }
+}
+
+fn wired_wan_up(interface: &str) -> bool {
+    PROBE_TARGETS.iter().any(|target| {
+        Command::new("ping")
+            .args(["-c", "1", "-W", "2", "-I", interface, target])
+            .output()
+            .is_ok_and(|o| o.status.success())
+    })
+}
+
+// Bytes moved since the previous sample; counters restart when the interface is recreated
+fn counter_delta(previous: &Option<(String, u64, u64)>, interface: &str, rx: u64, tx: u64) -> (u64, u64) {
+    match previous {
+        Some((dev, prev_rx, prev_tx)) if dev == interface => (
+            if rx >= *prev_rx { rx - prev_rx } else { rx },
+            if tx >= *prev_tx { tx - prev_tx } else { tx },
+        ),
+        _ => (0, 0),
+    }
+}
+
+async fn check(state: &AppState, settings: &ModemSettings) -> Result<(), String> {
+    let Some(index) = tokio::task::spawn_blocking(find_modem).await.map_err(|e| e.to_string())?? else {
+        return Err("No modem found by ModemManager".to_string());
+    };
+    let modem = tokio::task::spawn_blocking(move || status(index)).await.map_err(|e| e.to_string())??;
+
+    // Data usage
+    if let Some((rx, tx)) = modem.data_interface.as_deref().and_then(interface_counters) {
+        let interface = modem.data_interface.clone().unwrap_or_default();
+        let previous = state.modem.state.lock().unwrap().counters.replace((interface.clone(), rx, tx));
+        let (rx_delta, tx_delta) = counter_delta(&previous, &interface, rx, tx);
+        if rx_delta + tx_delta > 0 {
+            record_usage(&state.db, rx_delta, tx_delta).await.map_err(|e| e.to_string())?;
+        }
+    }
+    let usage = usage(&state.db, settings).await.map_err(|e| e.to_string())?;
+    let level = usage.percent.and_then(|p| {
+        if p >= 100.0 {
+            Some(CapLevel::Exceeded)
+        } else if p >= settings.warn_percent as f64 {
+            Some(CapLevel::Warning)
+        } else {
+            None
+        }
+    });
+    let capped = {
+        let mut monitor = state.modem.state.lock().unwrap();
+        if let Some(level) = level {
+            if monitor.alerted.as_ref().is_none_or(|(cycle, alerted)| *cycle != usage.cycle_start || *alerted < level) {
+                monitor.alerted = Some((usage.cycle_start.clone(), level));
+                state.events.emit(Event::ModemDataCap {
+                    used_mb: usage.used_mb,
+                    cap_mb: usage.cap_mb.unwrap_or_default(),
+                    percent: usage.percent.unwrap_or_default(),
+                    level,
+                });
+            }
+        }
+        if monitor.capped_cycle.as_ref().is_some_and(|c| *c != usage.cycle_start) {
+            monitor.capped_cycle = None;
+        }
+        if level == Some(CapLevel::Exceeded) && settings.disconnect_at_cap {
+            monitor.capped_cycle = Some(usage.cycle_start.clone());
+        }
+        monitor.capped_cycle.is_some()
+    };
+
+    // Connection
+    let want_connected = match settings.role {
+        ModemRole::Disabled => return Ok(()),
+        ModemRole::Wan => true,
+        ModemRole::Failover => {
+            let wired = crate::wan::wan_interface(&state.db).await;
+            let up = tokio::task::spawn_blocking(move || wired_wan_up(&wired)).await.unwrap_or(true);
+            let mut monitor = state.modem.state.lock().unwrap();
+            if up {
+                monitor.wan_successes += 1;
+                monitor.wan_failures = 0;
+            } else {
+                monitor.wan_failures += 1;
+                monitor.wan_successes = 0;
+            }
+            if monitor.failover_active {
+                monitor.wan_successes < RECOVER_AFTER
+            } else {
+                monitor.wan_failures >= FAILOVER_AFTER
+            }
+        }
+    } && !capped;
+
+    let failover = settings.role == ModemRole::Failover;
+    let was_active = state.modem.state.lock().unwrap().failover_active;
+    let settings = settings.clone();
+    let interface = modem.data_interface.clone();
+    let connected = modem.connected();
+
+    if want_connected && (!connected || (failover && !was_active)) {
+        let bearer = tokio::task::spawn_blocking(move || {
+            let bearer = if connected {
+                connected_bearer(&status(index)?).ok_or("No active bearer".to_string())?
+            } else {
+                connect(index, &settings)?
+            };
+            set_default_route(&bearer.interface, true)?;
+            Ok::<_, String>(bearer)
+        })
+        .await
+        .map_err(|e| e.to_string())??;
+
+        if failover {
+            {
+                let mut monitor = state.modem.state.lock().unwrap();
+                monitor.failover_active = true;
+                monitor.failover_since = Some(Utc::now().to_rfc3339());
+            }
+            tracing::warn!("Wired WAN unreachable, failing over to the modem on {}", bearer.interface);
+            state.events.emit(Event::WanFailover { active: true, interface: bearer.interface });
+        }
+    } else if !want_connected && (connected || was_active) {
+        tokio::task::spawn_blocking(move || {
+            if let Some(dev) = interface.as_deref() {
+                // Already gone if the modem dropped the connection
+                let _ = set_default_route(dev, false);
+            }
+            disconnect(index)
+        })
+        .await
+        .map_err(|e| e.to_string())??;
+
+        if was_active {
+            {
+                let mut monitor = state.modem.state.lock().unwrap();
+                monitor.failover_active = false;
+                monitor.failover_since = None;
+            }
+            let interface = modem.data_interface.clone().unwrap_or_default();
+            tracing::info!("Wired WAN is back, modem failover ended");
+            state.events.emit(Event::WanFailover { active: false, interface });
+        }
+    }
+    Ok(())
+}
+
+pub fn spawn(state: Arc<AppState>) {
+    tokio::spawn(async move {
+        loop {
+            state.tasks.beat("modem", CHECK_INTERVAL);
+            let settings = load_settings();
+            if settings.role != ModemRole::Disabled || settings.data_cap_mb.is_some() {
+                let result = check(&state, &settings).await;
+                let mut monitor = state.modem.state.lock().unwrap();
+                monitor.last_checked = Some(Utc::now().to_rfc3339());
+                if let Err(e) = &result {
+                    if monitor.last_error.as_ref() != Some(e) {
+                        tracing::warn!("Modem check failed: {}", e);
+                    }
+                }
+                monitor.last_error = result.err();
+            }
+            tokio::time::sleep(CHECK_INTERVAL).await;
+        }
+    });
+}
@@ -0,0 +1,418 @@
+// Uptime checks for self-hosted services: an HTTP(S) request to each URL on its own interval,
+// judged by status code and optionally a keyword in the body. Every check is kept in
+// monitor_checks for the history graphs; a service going down (after a few failures in a row,
+// to ride out blips) or coming back raises an event.
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::events::Event;
+use crate::AppState;
+
+// Key in the settings table
+pub const SETTINGS_KEY: &str = "monitors";
+// How often due monitors are looked for; the shortest interval is a multiple of it
+const TICK: Duration = Duration::from_secs(5);
+const MAX_MONITORS: usize = 50;
+const MIN_INTERVAL_SECS: u64 = 30;
+const MAX_INTERVAL_SECS: u64 = 24 * 60 * 60;
+const MAX_TIMEOUT_SECS: u64 = 60;
+// Only this much of a body is searched for the keyword
+const MAX_BODY_BYTES: usize = 1024 * 1024;
+const MAX_REDIRECTS: usize = 5;
+
+// ============ SETTINGS ============
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Monitor {
+    #[serde(default)]
+    pub id: String,
+    pub name: String,
+    pub url: String,
+    #[serde(default = "default_interval")]
+    pub interval_secs: u64,
+    #[serde(default = "default_timeout")]
+    pub timeout_secs: u64,
+    // None accepts any 2xx or 3xx
+    #[serde(default)]
+    pub expected_status: Option<u16>,
+    // Must appear in the body, e.g. a page title, to catch a proxy's error page served with 200
+    #[serde(default)]
+    pub keyword: Option<String>,
+    // Self-signed certificates are common on a LAN
+    #[serde(default)]
+    pub ignore_tls_errors: bool,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+}
+
+fn default_interval() -> u64 {
+    60
+}
+
+fn default_timeout() -> u64 {
+    10
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MonitorSettings {
+    // Failed checks in a row before a monitor counts as down
+    #[serde(default = "default_failures_before_alert")]
+    pub failures_before_alert: u32,
+    #[serde(default)]
+    pub monitors: Vec<Monitor>,
+}
+
+fn default_failures_before_alert() -> u32 {
+    2
+}
+
+impl Default for MonitorSettings {
+    fn default() -> Self {
+        Self {
+            failures_before_alert: default_failures_before_alert(),
+            monitors: Vec::new(),
+        }
+    }
+}
+
+pub async fn load_settings(pool: &SqlitePool) -> MonitorSettings {
+    match crate::db::settings::get(pool, SETTINGS_KEY).await {
+        Ok(settings) => settings.unwrap_or_default(),
+        Err(e) => {
+            tracing::warn!("Failed to read uptime monitor settings, using defaults: {}", e);
+            MonitorSettings::default()
+        }
+    }
+}
+
+pub async fn save_settings(pool: &SqlitePool, settings: &MonitorSettings) -> Result<(), String> {
+    crate::db::settings::set(pool, SETTINGS_KEY, settings).await.map_err(|e| e.to_string())
+}
+
+impl MonitorSettings {
+    /// Check and normalise: trimmed names and keywords, ids for new monitors
+    pub fn normalize(&mut self) -> Result<(), String> {
+        if !(1..=10).contains(&self.failures_before_alert) {
+            return Err("Failures before alerting must be between 1 and 10".to_string());
+        }
+        if self.monitors.len() > MAX_MONITORS {
+            return Err(format!("At most {} monitors", MAX_MONITORS));
+        }
+        for monitor in &mut self.monitors {
+            monitor.name = monitor.name.trim().to_string();
+            monitor.url = monitor.url.trim().to_string();
+            monitor.keyword = monitor.keyword.as_deref().map(str::trim).filter(|k| !k.is_empty()).map(str::to_string);
+
+            if monitor.name.is_empty() || monitor.name.len() > 64 {
+                return Err("Monitor names must be 1-64 characters".to_string());
+            }
+            let url = reqwest::Url::parse(&monitor.url).map_err(|e| format!("{}: invalid URL: {}", monitor.name, e))?;
+            if !matches!(url.scheme(), "http" | "https") || url.host_str().is_none() {
+                return Err(format!("{}: only http:// and https:// URLs can be monitored", monitor.name));
+            }
+            if !(MIN_INTERVAL_SECS..=MAX_INTERVAL_SECS).contains(&monitor.interval_secs) {
+                return Err(format!("{}: interval must be between 30 seconds and 24 hours", monitor.name));
+            }
+            if monitor.timeout_secs == 0 || monitor.timeout_secs > MAX_TIMEOUT_SECS || monitor.timeout_secs >= monitor.interval_secs {
+                return Err(format!(
+                    "{}: timeout must be 1-{} seconds and shorter than the interval",
+                    monitor.name, MAX_TIMEOUT_SECS
+                ));
+            }
+            if monitor.expected_status.is_some_and(|s| !(100..=599).contains(&s)) {
+                return Err(format!("{}: expected status must be an HTTP status code", monitor.name));
+            }
+            if monitor.keyword.as_ref().is_some_and(|k| k.len() > 200) {
+                return Err(format!("{}: keyword must be at most 200 characters", monitor.name));
+            }
+            if monitor.id.is_empty() {
+                monitor.id = uuid::Uuid::new_v4().to_string();
+            }
+        }
+        Ok(())
+    }
+}
+
+// ============ CHECKS ============
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CheckResult {
+    pub up: bool,
+    pub status_code: Option<u16>,
+    pub latency_ms: Option<i64>,
+    pub error: Option<String>,
+}
+
+// The body up to MAX_BODY_BYTES, as text
+async fn read_body(mut response: reqwest::Response) -> Result<String, String> {
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk().await.map_err(|e| describe(&e))? {
+        body.extend_from_slice(&chunk);
+        if body.len() >= MAX_BODY_BYTES {
+            body.truncate(MAX_BODY_BYTES);
+            break;
+        }
+    }
+    Ok(String::from_utf8_lossy(&body).into_owned())
+}
+
+// reqwest's own message is just "error sending request"; the root cause says what went wrong
+fn describe(error: &reqwest::Error) -> String {
+    let mut cause: &dyn std::error::Error = error;
+    while let Some(source) = cause.source() {
+        cause = source;
+    }
+    cause.to_string()
+}
+
+pub async fn check(monitor: &Monitor) -> CheckResult {
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(monitor.timeout_secs))
+        .tls_danger_accept_invalid_certs(monitor.ignore_tls_errors)
+        .redirect(reqwest::redirect::Policy::limited(MAX_REDIRECTS))
+        .user_agent(concat!("RouterUI-Monitor/", env!("CARGO_PKG_VERSION")))
+        .build();
+    let client = match client {
+        Ok(client) => client,
+        Err(e) => return CheckResult { up: false, status_code: None, latency_ms: None, error: Some(e.to_string()) },
+    };
+
+    let started = Instant::now();
+    let response = match client.get(&monitor.url).send().await {
+        Ok(response) => response,
+        Err(e) => {
+            let error = if e.is_timeout() { format!("No answer within {}s", monitor.timeout_secs) } else { describe(&e) };
+            return CheckResult { up: false, status_code: None, latency_ms: None, error: Some(error) };
+        }
+    };
+    let latency_ms = Some(started.elapsed().as_millis() as i64);
+    let status = response.status();
+    let status_code = Some(status.as_u16());
+
+    let status_ok = match monitor.expected_status {
+        Some(expected) => status.as_u16() == expected,
+        None => status.is_success() || status.is_redirection(),
+    };
+    if !status_ok {
+        let error = match monitor.expected_status {
+            Some(expected) => format!("Status {} instead of {}", status.as_u16(), expected),
+            None => format!("Status {}", status.as_u16()),
+        };
+        return CheckResult { up: false, status_code, latency_ms, error: Some(error) };
+    }
+
+    if let Some(keyword) = &monitor.keyword {
+        match read_body(response).await {
+            Ok(body) if body.contains(keyword.as_str()) => {}
+            Ok(_) => {
+                let error = format!("\"{}\" not found in the response", keyword);
+                return CheckResult { up: false, status_code, latency_ms, error: Some(error) };
+            }
+            Err(e) => return CheckResult { up: false, status_code, latency_ms, error: Some(e) },
+        }
+    }
+    CheckResult { up: true, status_code, latency_ms, error: None }
+}
+
+// ============ HISTORY ============
+
+async fn record(pool: &SqlitePool, monitor_id: &str, result: &CheckResult) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO monitor_checks (monitor_id, checked_at, up, status_code, latency_ms, error) VALUES (?, ?, ?, ?, ?, ?)",
+    )
+    .bind(monitor_id)
+    .bind(Utc::now().to_rfc3339())
+    .bind(result.up)
+    .bind(result.status_code)
+    .bind(result.latency_ms)
+    .bind(&result.error)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct CheckRecord {
+    pub checked_at: String,
+    pub up: bool,
+    pub status_code: Option<i64>,
+    pub latency_ms: Option<i64>,
+    pub error: Option<String>,
+}
+
+/// Checks of `monitor_id` in the last `hours`, oldest first
+pub async fn history(pool: &SqlitePool, monitor_id: &str, hours: i64) -> Result<Vec<CheckRecord>, sqlx::Error> {
+    let since = (Utc::now() - chrono::Duration::hours(hours)).to_rfc3339();
+    sqlx::query_as(
+        "SELECT checked_at, up, status_code, latency_ms, error FROM monitor_checks
+         WHERE monitor_id = ? AND checked_at >= ? ORDER BY checked_at",
+    )
+    .bind(monitor_id)
+    .bind(since)
+    .fetch_all(pool)
+    .await
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct Uptime {
+    // Percent of checks that passed; None without any checks in the period
+    pub uptime_24h: Option<f64>,
+    pub uptime_30d: Option<f64>,
+    pub avg_latency_ms_24h: Option<f64>,
+}
+
+pub async fn uptime(pool: &SqlitePool, monitor_id: &str) -> Result<Uptime, sqlx::Error> {
+    let percent = |hours: i64| {
+        let since = (Utc::now() - chrono::Duration::hours(hours)).to_rfc3339();
+        sqlx::query_scalar::<_, Option<f64>>(
+            "SELECT AVG(up) * 100.0 FROM monitor_checks WHERE monitor_id = ? AND checked_at >= ?",
+        )
+        .bind(monitor_id)
+        .bind(since)
+        .fetch_one(pool)
+    };
+    let since = (Utc::now() - chrono::Duration::hours(24)).to_rfc3339();
+    let avg_latency_ms_24h = sqlx::query_scalar::<_, Option<f64>>(
+        "SELECT AVG(latency_ms) FROM monitor_checks WHERE monitor_id = ? AND checked_at >= ? AND up = 1",
+    )
+    .bind(monitor_id)
+    .bind(since)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(Uptime {
+        uptime_24h: percent(24).await?,
+        uptime_30d: percent(30 * 24).await?,
+        avg_latency_ms_24h,
+    })
+}
+
+// ============ STATUS AND ALERTS ============
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct MonitorState {
+    // None until the first check, or while failures are below the alert threshold after a restart
+    pub up: Option<bool>,
+    // When `up` last changed
+    pub since: Option<String>,
+    pub last_checked: Option<String>,
+    pub last_result: Option<CheckResult>,
+    pub consecutive_failures: u32,
+    #[serde(skip)]
+    last_run: Option<Instant>,
+}
+
+/// Latest status of every monitor
+#[derive(Default)]
+pub struct MonitorTracker {
+    monitors: Mutex<HashMap<String, MonitorState>>,
+}
+
+impl MonitorTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, id: &str) -> Option<MonitorState> {
+        self.monitors.lock().unwrap().get(id).cloned()
+    }
+
+    /// Enabled monitors whose interval has passed, marked as started so the next tick skips them
+    fn take_due(&self, settings: &MonitorSettings) -> Vec<Monitor> {
+        let mut monitors = self.monitors.lock().unwrap();
+        monitors.retain(|id, _| settings.monitors.iter().any(|m| &m.id == id));
+        let now = Instant::now();
+        settings
+            .monitors
+            .iter()
+            .filter(|m| m.enabled)
+            .filter(|m| {
+                let state = monitors.entry(m.id.clone()).or_default();
+                let due = state.last_run.is_none_or(|at| now.duration_since(at) >= Duration::from_secs(m.interval_secs));
+                if due {
+                    state.last_run = Some(now);
+                }
+                due
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// Fold in a check; returns the new status when the monitor went down or came back
+    fn update(&self, monitor: &Monitor, result: &CheckResult, failures_before_alert: u32) -> Option<bool> {
+        let mut monitors = self.monitors.lock().unwrap();
+        let state = monitors.entry(monitor.id.clone()).or_default();
+        let now = Utc::now().to_rfc3339();
+        state.last_checked = Some(now.clone());
+        state.last_result = Some(result.clone());
+        state.consecutive_failures = if result.up { 0 } else { state.consecutive_failures + 1 };
+
+        let up = if result.up {
+            Some(true)
+        } else if state.consecutive_failures >= failures_before_alert {
+            Some(false)
+        } else {
+            state.up
+        };
+        if up == state.up {
+            return None;
+        }
+        let previous = state.up;
+        state.up = up;
+        state.since = Some(now);
+        // Coming up for the first time after a start isn't news; being down is
+        match (previous, up) {
+            (None, Some(true)) => None,
+            _ => up,
+        }
+    }
+}
+
+/// Check `monitor` now, store the result and alert on a change
+pub async fn run(state: &AppState, monitor: &Monitor, failures_before_alert: u32) -> CheckResult {
+    let result = check(monitor).await;
+    if let Err(e) = record(&state.db, &monitor.id, &result).await {
+        tracing::warn!("Could not store the check of {}: {}", monitor.name, e);
+    }
+    if let Some(up) = state.monitors.update(monitor, &result, failures_before_alert) {
+        if up {
+            tracing::info!("Monitor {} is back up", monitor.name);
+        } else {
+            tracing::warn!("Monitor {} is down: {}", monitor.name, result.error.as_deref().unwrap_or("check failed"));
+        }
+        state.events.emit(Event::MonitorStatusChanged {
+            id: monitor.id.clone(),
+            name: monitor.name.clone(),
+            url: monitor.url.clone(),
+            up,
+            error: result.error.clone(),
+        });
+    }
+    result
+}
+
+pub fn spawn(state: Arc<AppState>) {
+    tokio::spawn(async move {
+        loop {
+            state.tasks.beat("monitors", TICK);
+            let settings = load_settings(&state.db).await;
+            for monitor in state.monitors.take_due(&settings) {
+                let state = state.clone();
+                let failures_before_alert = settings.failures_before_alert;
+                tokio::spawn(async move {
+                    run(&state, &monitor, failures_before_alert).await;
+                });
+            }
+            tokio::time::sleep(TICK).await;
+        }
+    });
+}
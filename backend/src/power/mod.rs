@@ -0,0 +1,290 @@
+// Power draw of the router host, from whatever the hardware exposes: Intel/AMD RAPL energy
+// counters, batteries (laptops, UPS HATs) and USB or hwmon power sensors common on SBCs.
+// Sampled once a minute so energy use can be totalled per day.
+
+use chrono::{Duration as ChronoDuration, Utc};
+use serde::Serialize;
+use sqlx::SqlitePool;
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::AppState;
+
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(60);
+// RAPL only exposes a cumulative counter, so power is the difference over this window
+const RAPL_WINDOW: Duration = Duration::from_secs(1);
+const POWERCAP_DIR: &str = "/sys/class/powercap";
+const POWER_SUPPLY_DIR: &str = "/sys/class/power_supply";
+const HWMON_DIR: &str = "/sys/class/hwmon";
+
+// Reading taken by the last sample, served to the dashboard without waiting on RAPL
+static LATEST: Mutex<Option<PowerReading>> = Mutex::new(None);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SourceKind {
+    Rapl,
+    Battery,
+    Usb,
+    Sensor,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PowerSource {
+    pub name: String,
+    pub kind: SourceKind,
+    pub watts: f64,
+    // e.g. battery state and charge
+    pub detail: Option<String>,
+}
+
+/// Which sources the system-wide estimate is based on, best first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Method {
+    // Discharging battery: everything the host draws
+    Battery,
+    // Board input sensors
+    Sensor,
+    // CPU package only, so a lower bound
+    Rapl,
+}
+
+impl Method {
+    fn as_str(self) -> &'static str {
+        match self {
+            Method::Battery => "battery",
+            Method::Sensor => "sensor",
+            Method::Rapl => "rapl",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PowerReading {
+    pub sources: Vec<PowerSource>,
+    pub watts: Option<f64>,
+    pub method: Option<Method>,
+    // Sources present but not readable, e.g. RAPL counters restricted to root
+    pub unreadable: Vec<String>,
+    pub read_at: String,
+}
+
+fn read_trimmed(path: &Path) -> Option<String> {
+    fs::read_to_string(path).ok().map(|s| s.trim().to_string())
+}
+
+fn read_number(path: &Path) -> Option<f64> {
+    read_trimmed(path)?.parse().ok()
+}
+
+// Top-level RAPL domains (intel-rapl:0, not the intel-rapl:0:0 subzones they contain)
+fn rapl_domains(unreadable: &mut Vec<String>) -> Vec<(String, f64, f64, std::path::PathBuf)> {
+    let Ok(entries) = fs::read_dir(POWERCAP_DIR) else {
+        return Vec::new();
+    };
+    let mut domains = Vec::new();
+    for path in entries.flatten().map(|e| e.path()) {
+        let Some(file_name) = path.file_name().and_then(|n| n.to_str()) else { continue };
+        if !file_name.contains("-rapl:") || file_name.matches(':').count() != 1 {
+            continue;
+        }
+        let name = read_trimmed(&path.join("name")).unwrap_or_else(|| file_name.to_string());
+        match fs::read_to_string(path.join("energy_uj")) {
+            Ok(energy) => {
+                let Ok(energy) = energy.trim().parse::<f64>() else { continue };
+                let max = read_number(&path.join("max_energy_range_uj")).unwrap_or(f64::MAX);
+                domains.push((name, energy, max, path));
+            }
+            Err(e) => unreadable.push(format!("RAPL {}: {}", name, e)),
+        }
+    }
+    domains
+}
+
+fn rapl(unreadable: &mut Vec<String>) -> Vec<PowerSource> {
+    let before = rapl_domains(unreadable);
+    if before.is_empty() {
+        return Vec::new();
+    }
+    let started = Instant::now();
+    std::thread::sleep(RAPL_WINDOW);
+    let elapsed = started.elapsed().as_secs_f64();
+
+    before
+        .into_iter()
+        .filter_map(|(name, start, max, path)| {
+            let end = read_number(&path.join("energy_uj"))?;
+            // The counter wraps at max_energy_range_uj
+            let used = if end >= start { end - start } else { max - start + end };
+            Some(PowerSource { name, kind: SourceKind::Rapl, watts: used / 1e6 / elapsed, detail: None })
+        })
+        .collect()
+}
+
+fn power_supplies() -> Vec<PowerSource> {
+    let Ok(entries) = fs::read_dir(POWER_SUPPLY_DIR) else {
+        return Vec::new();
+    };
+    let mut sources = Vec::new();
+    for path in entries.flatten().map(|e| e.path()) {
+        let kind = match read_trimmed(&path.join("type")).as_deref() {
+            Some("Battery") => SourceKind::Battery,
+            Some(t) if t.starts_with("USB") => SourceKind::Usb,
+            _ => continue,
+        };
+        // power_now in µW, or current (µA) times voltage (µV)
+        let watts = read_number(&path.join("power_now")).map(|p| p / 1e6).or_else(|| {
+            Some(read_number(&path.join("current_now"))? * read_number(&path.join("voltage_now"))? / 1e12)
+        });
+        let Some(watts) = watts else { continue };
+
+        let status = read_trimmed(&path.join("status"));
+        let capacity = read_number(&path.join("capacity"));
+        let detail = match (status, capacity) {
+            (Some(status), Some(capacity)) => Some(format!("{}, {}%", status, capacity)),
+            (status, _) => status,
+        };
+        sources.push(PowerSource {
+            name: path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default(),
+            kind,
+            watts: watts.abs(),
+            detail,
+        });
+    }
+    sources
+}
+
+// hwmon power*_input, in µW (INA2xx and similar board monitors)
+fn hwmon_sensors() -> Vec<PowerSource> {
+    let Ok(entries) = fs::read_dir(HWMON_DIR) else {
+        return Vec::new();
+    };
+    let mut sources = Vec::new();
+    for path in entries.flatten().map(|e| e.path()) {
+        let chip = read_trimmed(&path.join("name")).unwrap_or_default();
+        let Ok(files) = fs::read_dir(&path) else { continue };
+        for file in files.flatten().map(|f| f.path()) {
+            let Some(input) = file.file_name().and_then(|n| n.to_str()).filter(|n| n.starts_with("power") && n.ends_with("_input")) else {
+                continue;
+            };
+            let Some(microwatts) = read_number(&file) else { continue };
+            let label = read_trimmed(&path.join(input.replace("_input", "_label")))
+                .unwrap_or_else(|| input.trim_end_matches("_input").to_string());
+            sources.push(PowerSource {
+                name: format!("{} {}", chip, label).trim().to_string(),
+                kind: SourceKind::Sensor,
+                watts: microwatts / 1e6,
+                detail: None,
+            });
+        }
+    }
+    sources
+}
+
+fn estimate(sources: &[PowerSource]) -> Option<(f64, Method)> {
+    let total = |pick: &dyn Fn(&PowerSource) -> bool| -> Option<f64> {
+        let picked: Vec<f64> = sources.iter().filter(|s| pick(s)).map(|s| s.watts).collect();
+        (!picked.is_empty()).then(|| picked.iter().sum())
+    };
+    let discharging = |s: &PowerSource| s.kind == SourceKind::Battery && s.detail.as_deref().is_some_and(|d| d.starts_with("Discharging"));
+
+    total(&discharging)
+        .map(|w| (w, Method::Battery))
+        .or_else(|| total(&|s| matches!(s.kind, SourceKind::Sensor | SourceKind::Usb) && s.watts > 0.0).map(|w| (w, Method::Sensor)))
+        .or_else(|| total(&|s| s.kind == SourceKind::Rapl).map(|w| (w, Method::Rapl)))
+}
+
+/// Read every source; blocks for RAPL_WINDOW when RAPL is present
+pub fn read() -> PowerReading {
+    let mut unreadable = Vec::new();
+    let mut sources = rapl(&mut unreadable);
+    sources.extend(power_supplies());
+    sources.extend(hwmon_sensors());
+    let estimate = estimate(&sources);
+
+    PowerReading {
+        sources,
+        watts: estimate.map(|(w, _)| w),
+        method: estimate.map(|(_, m)| m),
+        unreadable,
+        read_at: Utc::now().to_rfc3339(),
+    }
+}
+
+/// Reading from the most recent sample
+pub fn latest() -> Option<PowerReading> {
+    LATEST.lock().unwrap().clone()
+}
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct PowerSample {
+    pub sampled_at: String,
+    pub watts: f64,
+    pub method: String,
+}
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct DailyEnergy {
+    // UTC date
+    pub day: String,
+    pub kwh: f64,
+}
+
+// Each sample stands for the interval that follows it
+pub async fn record(pool: &SqlitePool, watts: f64, method: Method) -> Result<(), sqlx::Error> {
+    sqlx::query("INSERT INTO power_samples (sampled_at, watts, energy_wh, method) VALUES (?, ?, ?, ?)")
+        .bind(Utc::now().to_rfc3339())
+        .bind(watts)
+        .bind(watts * SAMPLE_INTERVAL.as_secs_f64() / 3600.0)
+        .bind(method.as_str())
+        .execute(pool)
+        .await
+        .map(|_| ())
+}
+
+fn since(hours: u32) -> String {
+    (Utc::now() - ChronoDuration::hours(hours as i64)).to_rfc3339()
+}
+
+pub async fn history(pool: &SqlitePool, hours: u32) -> Result<Vec<PowerSample>, sqlx::Error> {
+    sqlx::query_as("SELECT sampled_at, watts, method FROM power_samples WHERE sampled_at >= ? ORDER BY sampled_at")
+        .bind(since(hours))
+        .fetch_all(pool)
+        .await
+}
+
+/// Energy per day over the last `days` days, oldest first
+pub async fn daily(pool: &SqlitePool, days: u32) -> Result<Vec<DailyEnergy>, sqlx::Error> {
+    sqlx::query_as(
+        "SELECT substr(sampled_at, 1, 10) AS day, SUM(energy_wh) / 1000.0 AS kwh FROM power_samples
+         WHERE sampled_at >= ? GROUP BY day ORDER BY day",
+    )
+    .bind(since(days * 24))
+    .fetch_all(pool)
+    .await
+}
+
+async fn sample(state: &AppState) {
+    let Ok(reading) = tokio::task::spawn_blocking(read).await else {
+        return;
+    };
+    if let (Some(watts), Some(method)) = (reading.watts, reading.method) {
+        if let Err(e) = record(&state.db, watts, method).await {
+            tracing::warn!("Could not record power sample: {}", e);
+        }
+    }
+    *LATEST.lock().unwrap() = Some(reading);
+}
+
+pub fn spawn(state: Arc<AppState>) {
+    tokio::spawn(async move {
+        loop {
+            state.tasks.beat("power", SAMPLE_INTERVAL);
+            sample(&state).await;
+            tokio::time::sleep(SAMPLE_INTERVAL).await;
+        }
+    });
+}
@@ -0,0 +1,309 @@
+// Home/away status for tracked people and devices, from WiFi associations on this router's
+// radios and, optionally, Bluetooth LE advertisements. Changes are emitted as events, and the
+// household going empty or occupied can switch config profiles (e.g. a lockdown profile).
+// Clients of adopted access points are not visible here.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::process::Command;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::events::Event;
+use crate::AppState;
+
+const SETTINGS_FILE: &str = "/opt/routerui/presence.json";
+const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+// Length of each BLE scan; advertisers send every few seconds at most
+const BLE_SCAN_SECS: u32 = 8;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrackedDevice {
+    #[serde(default)]
+    pub id: String,
+    // Person or device, e.g. "Alice's phone"
+    pub name: String,
+    #[serde(default)]
+    pub wifi_mac: Option<String>,
+    // Needs a fixed address: a BLE tag, or a phone with address randomisation off
+    #[serde(default)]
+    pub ble_address: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PresenceSettings {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default)]
+    pub ble_enabled: bool,
+    // Phones drop off WiFi to save power, so only unseen for this long counts as away
+    #[serde(default = "default_away_after")]
+    pub away_after_secs: u64,
+    #[serde(default)]
+    pub devices: Vec<TrackedDevice>,
+    // Config profiles switched to when the last tracked device leaves / the first comes back
+    #[serde(default)]
+    pub away_profile: Option<String>,
+    #[serde(default)]
+    pub home_profile: Option<String>,
+}
+
+fn default_away_after() -> u64 {
+    10 * 60
+}
+
+impl Default for PresenceSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            ble_enabled: false,
+            away_after_secs: default_away_after(),
+            devices: Vec::new(),
+            away_profile: None,
+            home_profile: None,
+        }
+    }
+}
+
+pub fn load_settings() -> PresenceSettings {
+    std::fs::read_to_string(SETTINGS_FILE)
+        .ok()
+        .and_then(|c| serde_json::from_str(&c).ok())
+        .unwrap_or_default()
+}
+
+pub fn save_settings(settings: &PresenceSettings) -> Result<(), String> {
+    let json = serde_json::to_string_pretty(settings).map_err(|e| e.to_string())?;
+    std::fs::write(SETTINGS_FILE, json).map_err(|e| e.to_string())
+}
+
+fn valid_mac(value: &str) -> bool {
+    let parts: Vec<&str> = value.split(':').collect();
+    parts.len() == 6 && parts.iter().all(|p| p.len() == 2 && p.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+impl PresenceSettings {
+    /// Check and normalise: trimmed names, lowercase addresses, ids for new devices
+    pub fn normalize(&mut self) -> Result<(), String> {
+        if !(60..=24 * 3600).contains(&self.away_after_secs) {
+            return Err("Away delay must be between 1 minute and 24 hours".to_string());
+        }
+        let profiles = crate::profiles::load().profiles;
+        for profile in [&self.away_profile, &self.home_profile].into_iter().flatten() {
+            if !profiles.iter().any(|p| &p.id == profile) {
+                return Err(format!("No profile {}", profile));
+            }
+        }
+        for device in &mut self.devices {
+            device.name = device.name.trim().to_string();
+            if device.name.is_empty() {
+                return Err("Every tracked device needs a name".to_string());
+            }
+            for address in [&mut device.wifi_mac, &mut device.ble_address] {
+                *address = address.as_deref().map(|a| a.trim().to_lowercase()).filter(|a| !a.is_empty());
+                if let Some(a) = address.as_deref().filter(|a| !valid_mac(a)) {
+                    return Err(format!("{}: invalid address {}", device.name, a));
+                }
+            }
+            if device.wifi_mac.is_none() && device.ble_address.is_none() {
+                return Err(format!("{}: give a WiFi MAC or a Bluetooth address", device.name));
+            }
+            if device.id.is_empty() {
+                device.id = uuid::Uuid::new_v4().to_string();
+            }
+        }
+        Ok(())
+    }
+}
+
+// ============ DETECTION ============
+
+/// Addresses of BLE devices heard during a short scan
+fn ble_scan() -> Result<HashSet<String>, String> {
+    let output = Command::new("bluetoothctl")
+        .args(["--timeout", &BLE_SCAN_SECS.to_string(), "scan", "on"])
+        .output()
+        .map_err(|e| format!("bluetoothctl: {}", e))?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+    }
+    // "[NEW] Device AA:BB:CC:DD:EE:FF Name", "[CHG] Device AA:BB:CC:DD:EE:FF RSSI: -60"
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| line.split_once("Device ")?.1.split_whitespace().next())
+        .filter(|a| valid_mac(a))
+        .map(|a| a.to_lowercase())
+        .collect())
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DeviceState {
+    // None until known: right after start, before the device was seen or the delay passed
+    pub home: Option<bool>,
+    pub last_seen: Option<String>,
+    // wifi or ble
+    pub seen_via: Option<String>,
+    pub since: Option<String>,
+    #[serde(skip)]
+    last_seen_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Default)]
+struct TrackerState {
+    devices: HashMap<String, DeviceState>,
+    anyone_home: Option<bool>,
+    last_error: Option<String>,
+}
+
+/// Current presence of every tracked device
+pub struct PresenceTracker {
+    started_at: DateTime<Utc>,
+    state: Mutex<TrackerState>,
+}
+
+impl Default for PresenceTracker {
+    fn default() -> Self {
+        Self { started_at: Utc::now(), state: Mutex::new(TrackerState::default()) }
+    }
+}
+
+impl PresenceTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn device(&self, id: &str) -> Option<DeviceState> {
+        self.state.lock().unwrap().devices.get(id).cloned()
+    }
+
+    pub fn anyone_home(&self) -> Option<bool> {
+        self.state.lock().unwrap().anyone_home
+    }
+
+    pub fn last_error(&self) -> Option<String> {
+        self.state.lock().unwrap().last_error.clone()
+    }
+
+    /// Fold in one round of sightings, returning the devices whose status changed and the
+    /// new household status if that changed. Transitions out of "unknown" are not changes.
+    fn update(
+        &self,
+        settings: &PresenceSettings,
+        wifi: &HashSet<String>,
+        ble: &HashSet<String>,
+        now: DateTime<Utc>,
+    ) -> (Vec<(TrackedDevice, bool)>, Option<bool>) {
+        let mut state = self.state.lock().unwrap();
+        state.devices.retain(|id, _| settings.devices.iter().any(|d| &d.id == id));
+        let away_after = chrono::Duration::seconds(settings.away_after_secs as i64);
+
+        let mut changed = Vec::new();
+        for device in &settings.devices {
+            let entry = state.devices.entry(device.id.clone()).or_insert(DeviceState {
+                home: None,
+                last_seen: None,
+                seen_via: None,
+                since: None,
+                last_seen_at: None,
+            });
+            let via = if device.wifi_mac.as_ref().is_some_and(|m| wifi.contains(m)) {
+                Some("wifi")
+            } else if device.ble_address.as_ref().is_some_and(|a| ble.contains(a)) {
+                Some("ble")
+            } else {
+                None
+            };
+            if let Some(via) = via {
+                entry.last_seen_at = Some(now);
+                entry.last_seen = Some(now.to_rfc3339());
+                entry.seen_via = Some(via.to_string());
+            }
+
+            let home = match entry.last_seen_at {
+                Some(seen) if now - seen < away_after => Some(true),
+                _ if now - self.started_at >= away_after => Some(false),
+                _ => None,
+            };
+            if home.is_some() && home != entry.home {
+                if entry.home.is_some() {
+                    changed.push((device.clone(), home == Some(true)));
+                }
+                entry.home = home;
+                entry.since = Some(now.to_rfc3339());
+            }
+        }
+
+        let known: Vec<bool> = state.devices.values().filter_map(|d| d.home).collect();
+        let anyone_home = if known.contains(&true) {
+            Some(true)
+        } else if !known.is_empty() && known.len() == state.devices.len() {
+            Some(false)
+        } else {
+            None
+        };
+        let household = match (state.anyone_home, anyone_home) {
+            (Some(before), Some(now)) if before != now => Some(now),
+            _ => None,
+        };
+        if anyone_home.is_some() {
+            state.anyone_home = anyone_home;
+        }
+        (changed, household)
+    }
+}
+
+async fn check(state: &AppState, settings: &PresenceSettings) {
+    let ble_enabled = settings.ble_enabled && settings.devices.iter().any(|d| d.ble_address.is_some());
+    let Ok((wifi, ble)) = tokio::task::spawn_blocking(move || {
+        let wifi: HashSet<String> = crate::api::network::wifi_clients().into_iter().map(|c| c.mac_address).collect();
+        let ble = if ble_enabled { ble_scan() } else { Ok(HashSet::new()) };
+        (wifi, ble)
+    })
+    .await
+    else {
+        return;
+    };
+
+    let ble = match ble {
+        Ok(ble) => {
+            state.presence.state.lock().unwrap().last_error = None;
+            ble
+        }
+        Err(e) => {
+            let mut tracker = state.presence.state.lock().unwrap();
+            if tracker.last_error.as_ref() != Some(&e) {
+                tracing::warn!("Bluetooth presence scan failed: {}", e);
+            }
+            tracker.last_error = Some(e);
+            HashSet::new()
+        }
+    };
+
+    let (changed, household) = state.presence.update(settings, &wifi, &ble, Utc::now());
+    for (device, home) in changed {
+        state.events.emit(Event::PresenceChanged { device_id: device.id, name: device.name, home });
+    }
+    if let Some(anyone_home) = household {
+        state.events.emit(Event::HouseholdPresence { anyone_home });
+        let profile = if anyone_home { &settings.home_profile } else { &settings.away_profile };
+        if let Some(profile) = profile {
+            if let Err(e) = crate::profiles::activate(&state.db, profile).await {
+                tracing::error!("Presence could not switch to profile {}: {}", profile, e);
+            }
+        }
+    }
+}
+
+pub fn spawn(state: Arc<AppState>) {
+    tokio::spawn(async move {
+        loop {
+            state.tasks.beat("presence", CHECK_INTERVAL);
+            let settings = load_settings();
+            if settings.enabled && !settings.devices.is_empty() {
+                check(&state, &settings).await;
+            }
+            tokio::time::sleep(CHECK_INTERVAL).await;
+        }
+    });
+}
@@ -0,0 +1,140 @@
+// One global switch for how much RouterUI and the services it drives keep about clients.
+// The profile caps the individual settings rather than rewriting them, so going back to
+// Standard restores whatever was configured before.
+
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+
+const SETTINGS_FILE: &str = "/opt/routerui/privacy.json";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Profile {
+    #[default]
+    Standard,
+    // Anonymized DNS logs, short retention
+    Reduced,
+    // No per-client logs at all
+    Strict,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DnsLogging {
+    Full,
+    // Client addresses are truncated by AdGuard
+    Anonymized,
+    Off,
+}
+
+/// What each collector is allowed to keep under a profile
+#[derive(Debug, Clone, Serialize)]
+pub struct Policy {
+    pub dns_query_log: DnsLogging,
+    // AdGuard query log and statistics retention
+    pub dns_retention_hours: u32,
+    pub dns_statistics: bool,
+    pub connection_log: bool,
+    // LOG rules in front of the protection blocklists
+    pub blocked_traffic_log: bool,
+    // Attempted usernames in the brute-force history and events
+    pub record_usernames: bool,
+    // Upper bound on retention for logs and metrics history; None leaves each setting alone
+    pub retention_days: Option<u32>,
+}
+
+impl Profile {
+    pub fn policy(self) -> Policy {
+        match self {
+            Profile::Standard => Policy {
+                dns_query_log: DnsLogging::Full,
+                dns_retention_hours: 90 * 24,
+                dns_statistics: true,
+                connection_log: true,
+                blocked_traffic_log: true,
+                record_usernames: true,
+                retention_days: None,
+            },
+            Profile::Reduced => Policy {
+                dns_query_log: DnsLogging::Anonymized,
+                dns_retention_hours: 24,
+                dns_statistics: true,
+                connection_log: true,
+                blocked_traffic_log: true,
+                record_usernames: false,
+                retention_days: Some(7),
+            },
+            Profile::Strict => Policy {
+                dns_query_log: DnsLogging::Off,
+                dns_retention_hours: 24,
+                dns_statistics: false,
+                connection_log: false,
+                blocked_traffic_log: false,
+                record_usernames: false,
+                retention_days: Some(1),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PrivacySettings {
+    #[serde(default)]
+    pub profile: Profile,
+}
+
+pub fn load_settings() -> PrivacySettings {
+    std::fs::read_to_string(SETTINGS_FILE)
+        .ok()
+        .and_then(|c| serde_json::from_str(&c).ok())
+        .unwrap_or_default()
+}
+
+pub fn save_settings(settings: &PrivacySettings) -> Result<(), String> {
+    let json = serde_json::to_string_pretty(settings).map_err(|e| e.to_string())?;
+    std::fs::write(SETTINGS_FILE, json).map_err(|e| e.to_string())
+}
+
+/// Policy of the profile in effect
+pub fn policy() -> Policy {
+    load_settings().profile.policy()
+}
+
+impl Policy {
+    /// Retention after the profile's cap
+    pub fn cap_days(&self, days: u32) -> u32 {
+        self.retention_days.map_or(days, |cap| days.min(cap))
+    }
+}
+
+/// Push the policy to everything that doesn't consult it on its own, returning one
+/// result per collector
+pub async fn apply(pool: &SqlitePool, policy: &Policy) -> Vec<(&'static str, Result<(), String>)> {
+    let mut results = vec![("adguard", crate::api::adguard::apply_privacy(policy).await)];
+
+    let logging = policy.blocked_traffic_log;
+    let protection = match crate::api::protection::list_sets(pool, true).await {
+        Ok(sets) => tokio::task::spawn_blocking(move || crate::api::protection::set_blocklist_logging(sets, logging))
+            .await
+            .unwrap_or_else(|e| Err(e.to_string())),
+        Err(e) => Err(e.to_string()),
+    };
+    results.push(("protection", protection));
+    let honeypot = tokio::task::spawn_blocking(move || crate::honeypot::set_logging(logging))
+        .await
+        .unwrap_or_else(|e| Err(e.to_string()));
+    results.push(("honeypot", honeypot));
+
+    // Don't wait for the next scheduled prune to honour a shorter retention
+    let connlog = crate::connlog::load_settings().limited_by(policy);
+    results.push(("connection_log", crate::connlog::prune(pool, connlog.retention_days).await.map(|_| ())));
+    let maintenance = crate::db::maintenance::prune(pool).await.map(|_| ()).map_err(|e| e.to_string());
+    results.push(("maintenance", maintenance));
+
+    for (collector, result) in &results {
+        if let Err(e) = result {
+            tracing::warn!("Privacy profile: could not apply to {}: {}", collector, e);
+        }
+    }
+    results
+}
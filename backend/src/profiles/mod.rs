@@ -0,0 +1,244 @@
+// Named sets of settings (home, away, travel...) switched in one go, by hand or on a weekly
+// schedule. A profile only lists what it changes; everything it leaves out stays as it is.
+// There is no notification channel yet, so profiles cannot switch alerts such as camera
+// notifications; they cover firewall, WiFi, VPN routing and parental settings.
+
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::scheduler::{Edge, Schedule};
+
+const PROFILES_FILE: &str = "/opt/routerui/profiles.json";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConfigProfile {
+    // Empty for a profile that hasn't been saved yet
+    #[serde(default)]
+    pub id: String,
+    pub name: String,
+    // INPUT policy DROP (on) or ACCEPT (off)
+    #[serde(default)]
+    pub firewall: Option<bool>,
+    // Every hostapd radio
+    #[serde(default)]
+    pub wifi: Option<bool>,
+    #[serde(default)]
+    pub guest_wifi: Option<bool>,
+    // Tailscale exit node to send internet traffic through; empty goes direct
+    #[serde(default)]
+    pub exit_node: Option<String>,
+    // AdGuard parental control and enforced safe search
+    #[serde(default)]
+    pub parental_control: Option<bool>,
+    #[serde(default)]
+    pub safe_search: Option<bool>,
+    // Safe search groups enforced while the profile is active; all others are switched off
+    #[serde(default)]
+    pub safe_search_groups: Option<Vec<String>>,
+    // Switched to when a window starts; the default profile takes over when it ends
+    #[serde(default)]
+    pub schedule: Schedule,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Profiles {
+    #[serde(default)]
+    pub profiles: Vec<ConfigProfile>,
+    // Profile applied last, by hand or by schedule
+    #[serde(default)]
+    pub active: Option<String>,
+    #[serde(default)]
+    pub active_since: Option<String>,
+    // Fallback when a scheduled profile's window ends
+    #[serde(default)]
+    pub default_profile: Option<String>,
+}
+
+pub fn load() -> Profiles {
+    std::fs::read_to_string(PROFILES_FILE)
+        .ok()
+        .and_then(|c| serde_json::from_str(&c).ok())
+        .unwrap_or_default()
+}
+
+pub fn save(profiles: &Profiles) -> Result<(), String> {
+    let json = serde_json::to_string_pretty(profiles).map_err(|e| e.to_string())?;
+    std::fs::write(PROFILES_FILE, json).map_err(|e| e.to_string())
+}
+
+impl ConfigProfile {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.name.trim().is_empty() {
+            return Err("Profile name is required".to_string());
+        }
+        if let Some(node) = self.exit_node.as_deref().filter(|n| !n.is_empty()) {
+            node.parse::<std::net::IpAddr>()
+                .map_err(|_| format!("Exit node must be a Tailscale IP address: {}", node))?;
+        }
+        self.schedule.validate()
+    }
+
+    /// Settings the profile touches, for display; `safe_search` names the groups it enables
+    pub fn changes(&self, safe_search: &[crate::safesearch::SafeSearchGroup]) -> Vec<String> {
+        let on_off = |on: bool| if on { "on" } else { "off" };
+        let mut changes = Vec::new();
+        if let Some(on) = self.firewall {
+            changes.push(format!("Firewall {}", on_off(on)));
+        }
+        if let Some(on) = self.wifi {
+            changes.push(format!("WiFi {}", on_off(on)));
+        }
+        if let Some(on) = self.guest_wifi {
+            changes.push(format!("Guest WiFi {}", on_off(on)));
+        }
+        match self.exit_node.as_deref() {
+            Some("") => changes.push("No VPN exit node".to_string()),
+            Some(node) => changes.push(format!("Internet via exit node {}", node)),
+            None => {}
+        }
+        if let Some(on) = self.parental_control {
+            changes.push(format!("Parental control {}", on_off(on)));
+        }
+        if let Some(on) = self.safe_search {
+            changes.push(format!("Safe search {}", on_off(on)));
+        }
+        if let Some(ids) = &self.safe_search_groups {
+            let names: Vec<&str> = safe_search.iter().filter(|g| ids.contains(&g.id)).map(|g| g.name.as_str()).collect();
+            if names.is_empty() {
+                changes.push("No safe search groups".to_string());
+            } else {
+                changes.push(format!("Safe search for {}", names.join(", ")));
+            }
+        }
+        changes
+    }
+}
+
+fn set_exit_node(node: &str) -> Result<(), String> {
+    let mut command = crate::system::privileges::sudo();
+    command.args(["tailscale", "set", &format!("--exit-node={}", node)]);
+    if !node.is_empty() {
+        // Keep the LAN reachable from the router itself
+        command.arg("--exit-node-allow-lan-access");
+    }
+    let output = command.output().map_err(|e| e.to_string())?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+    }
+    Ok(())
+}
+
+/// Apply every setting in the profile, returning one result per setting. A firewall switch-off
+/// is refused while two-person confirmation is on, since a profile must not get around it.
+pub async fn apply(pool: &sqlx::SqlitePool, profile: &ConfigProfile) -> Vec<(&'static str, Result<(), String>)> {
+    let mut results = Vec::new();
+
+    if let Some(on) = profile.firewall {
+        let result = if !on && crate::approvals::required() {
+            Err("Turning the firewall off needs a second admin's approval".to_string())
+        } else {
+            let zones = crate::zones::load(pool).await;
+            tokio::task::spawn_blocking(move || crate::api::firewall::set_enabled_now(on, &zones).map_err(|(_, e)| e))
+                .await
+                .unwrap_or_else(|e| Err(e.to_string()))
+        };
+        results.push(("firewall", result));
+    }
+
+    let (wifi, guest) = (profile.wifi, profile.guest_wifi);
+    if wifi.is_some() || guest.is_some() {
+        // Guest block first, so a radio coming up starts with the right SSIDs
+        let (guest_result, wifi_result) = tokio::task::spawn_blocking(move || {
+            (
+                guest.map(crate::api::network::set_guest_ssid),
+                wifi.map(crate::api::network::set_radio),
+            )
+        })
+        .await
+        .unwrap_or_else(|e| (guest.map(|_| Err(e.to_string())), wifi.map(|_| Err(e.to_string()))));
+        if let Some(result) = guest_result {
+            if result.is_ok() {
+                crate::mesh::spawn_sync_all(pool.clone());
+            }
+            results.push(("guest_wifi", result));
+        }
+        if let Some(result) = wifi_result {
+            results.push(("wifi", result));
+        }
+    }
+
+    if let Some(node) = profile.exit_node.clone() {
+        let result = tokio::task::spawn_blocking(move || set_exit_node(&node))
+            .await
+            .unwrap_or_else(|e| Err(e.to_string()));
+        results.push(("exit_node", result));
+    }
+
+    if let Some(on) = profile.parental_control {
+        results.push(("parental_control", crate::api::adguard::set_parental(on).await));
+    }
+    if let Some(on) = profile.safe_search {
+        results.push(("safe_search", crate::api::adguard::set_safe_search(on).await));
+    }
+    if let Some(ids) = &profile.safe_search_groups {
+        results.push(("safe_search_groups", crate::safesearch::enable_only(pool, ids).await));
+    }
+
+    for (setting, result) in &results {
+        if let Err(e) = result {
+            tracing::warn!("Profile {}: could not apply {}: {}", profile.name, setting, e);
+        }
+    }
+    results
+}
+
+/// Apply profile `id` and record it as active
+pub async fn activate(pool: &sqlx::SqlitePool, id: &str) -> Result<Vec<(&'static str, Result<(), String>)>, String> {
+    let mut profiles = load();
+    let profile = profiles
+        .profiles
+        .iter()
+        .find(|p| p.id == id)
+        .cloned()
+        .ok_or_else(|| format!("No profile {}", id))?;
+
+    tracing::info!("Switching to profile {}", profile.name);
+    let results = apply(pool, &profile).await;
+
+    profiles.active = Some(profile.id);
+    profiles.active_since = Some(chrono::Utc::now().to_rfc3339());
+    save(&profiles)?;
+    Ok(results)
+}
+
+/// Driven by the scheduler every minute; like the WiFi schedule it acts on window boundaries
+/// only, so a manual switch in between holds until the next boundary
+#[derive(Default)]
+pub struct ProfileScheduler {
+    edges: HashMap<String, Edge>,
+}
+
+impl ProfileScheduler {
+    pub async fn tick(&mut self, pool: &sqlx::SqlitePool, now: DateTime<Local>) {
+        let profiles = load();
+        self.edges.retain(|id, _| profiles.profiles.iter().any(|p| &p.id == id));
+
+        for profile in &profiles.profiles {
+            let edge = self.edges.entry(profile.id.clone()).or_default();
+            let target = match edge.changed(profile.schedule.active_at(now)) {
+                Some(true) => Some(profile.id.clone()),
+                // Only hand back if nothing else was switched to meanwhile
+                Some(false) if profiles.active.as_deref() == Some(profile.id.as_str()) => {
+                    profiles.default_profile.clone().filter(|d| d != &profile.id)
+                }
+                _ => None,
+            };
+            if let Some(target) = target {
+                if let Err(e) = activate(pool, &target).await {
+                    tracing::error!("Profile schedule could not switch to {}: {}", target, e);
+                }
+            }
+        }
+    }
+}
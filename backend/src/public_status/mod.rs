@@ -0,0 +1,268 @@
+// A status summary anyone can fetch without signing in, for an external status page or a
+// dashboard widget. Off until turned on, and then limited to the fields picked in the settings:
+// whether the internet is reachable, the latency to a probe address and the router's uptime.
+// Nothing about the LAN, the WAN address or the devices behind the router is ever included.
+// Each client gets a few requests a minute.
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::process::Command;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::AppState;
+
+// Key in the settings table
+pub const SETTINGS_KEY: &str = "public_status";
+pub const PATH: &str = "/api/public/status";
+const PROBE_INTERVAL: Duration = Duration::from_secs(30);
+const PROBE_COUNT: &str = "3";
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+const MAX_REQUESTS_PER_MINUTE: u32 = 600;
+// Clients remembered for rate limiting before idle ones are dropped
+const MAX_TRACKED: usize = 4096;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PublicStatusSettings {
+    pub enabled: bool,
+    pub show_internet: bool,
+    pub show_latency: bool,
+    pub show_uptime: bool,
+    // Pinged over the WAN for the internet and latency fields
+    pub probe_host: String,
+    pub requests_per_minute: u32,
+    // Also answer clients that management access would otherwise turn away
+    pub allow_any_network: bool,
+}
+
+impl Default for PublicStatusSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            show_internet: true,
+            show_latency: true,
+            show_uptime: true,
+            probe_host: "1.1.1.1".to_string(),
+            requests_per_minute: 30,
+            allow_any_network: false,
+        }
+    }
+}
+
+impl PublicStatusSettings {
+    pub fn normalize(&mut self) {
+        self.probe_host = self.probe_host.trim().to_string();
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.probe_host.parse::<IpAddr>().is_err() {
+            return Err("Probe address must be an IP address".to_string());
+        }
+        if !(1..=MAX_REQUESTS_PER_MINUTE).contains(&self.requests_per_minute) {
+            return Err(format!("Requests per minute must be 1-{}", MAX_REQUESTS_PER_MINUTE));
+        }
+        Ok(())
+    }
+
+    // The probe only runs when something would show its result
+    fn probes(&self) -> bool {
+        self.enabled && (self.show_internet || self.show_latency)
+    }
+}
+
+pub async fn load_settings(pool: &SqlitePool) -> PublicStatusSettings {
+    match crate::db::settings::get(pool, SETTINGS_KEY).await {
+        Ok(settings) => settings.unwrap_or_default(),
+        Err(e) => {
+            tracing::warn!("Failed to read public status settings, using defaults: {}", e);
+            PublicStatusSettings::default()
+        }
+    }
+}
+
+pub async fn save_settings(pool: &SqlitePool, settings: &PublicStatusSettings) -> Result<(), String> {
+    crate::db::settings::set(pool, SETTINGS_KEY, settings).await.map_err(|e| e.to_string())
+}
+
+// ============ PROBE ============
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Probe {
+    pub internet_up: bool,
+    pub latency_ms: Option<f64>,
+    pub checked_at: String,
+}
+
+// Average round trip from ping's summary line, e.g. "rtt min/avg/max/mdev = 9.1/10.2/11.0/0.7 ms"
+fn parse_ping(output: &str) -> (u32, Option<f64>) {
+    let mut received = 0;
+    let mut average = None;
+    for line in output.lines() {
+        if line.contains("packets transmitted") {
+            received = line
+                .split(',')
+                .nth(1)
+                .and_then(|part| part.split_whitespace().next())
+                .and_then(|n| n.parse().ok())
+                .unwrap_or(0);
+        }
+        if line.contains("min/avg/max") {
+            average = line
+                .split('=')
+                .nth(1)
+                .and_then(|stats| stats.split('/').nth(1))
+                .and_then(|avg| avg.trim().parse().ok());
+        }
+    }
+    (received, average)
+}
+
+fn ping(interface: &str, host: &str) -> Probe {
+    let output = Command::new("ping")
+        .args(["-c", PROBE_COUNT, "-W", "2", "-I", interface, host])
+        .output();
+    let (received, latency_ms) = match output {
+        Ok(output) => parse_ping(&String::from_utf8_lossy(&output.stdout)),
+        Err(e) => {
+            tracing::warn!("Public status probe could not run ping: {}", e);
+            (0, None)
+        }
+    };
+    Probe {
+        internet_up: received > 0,
+        latency_ms: latency_ms.map(|ms| (ms * 10.0).round() / 10.0),
+        checked_at: Utc::now().to_rfc3339(),
+    }
+}
+
+// ============ STATE ============
+
+struct Window {
+    started: Instant,
+    requests: u32,
+}
+
+/// The latest probe and the request counts for rate limiting
+#[derive(Default)]
+pub struct PublicStatusTracker {
+    probe: Mutex<Option<Probe>>,
+    clients: Mutex<HashMap<IpAddr, Window>>,
+}
+
+impl PublicStatusTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn probe(&self) -> Option<Probe> {
+        self.probe.lock().unwrap().clone()
+    }
+
+    /// Count a request from `client`; Err carries the seconds until it may ask again
+    pub fn admit(&self, client: IpAddr, per_minute: u32) -> Result<(), u64> {
+        let mut clients = self.clients.lock().unwrap();
+        let now = Instant::now();
+        if clients.len() >= MAX_TRACKED && !clients.contains_key(&client) {
+            clients.retain(|_, w| now.duration_since(w.started) < RATE_WINDOW);
+            // Every window is still open; make room by forgetting the oldest
+            if clients.len() >= MAX_TRACKED {
+                if let Some(oldest) = clients.iter().min_by_key(|(_, w)| w.started).map(|(ip, _)| *ip) {
+                    clients.remove(&oldest);
+                }
+            }
+        }
+        let window = clients.entry(client).or_insert(Window { started: now, requests: 0 });
+        if now.duration_since(window.started) >= RATE_WINDOW {
+            *window = Window { started: now, requests: 0 };
+        }
+        if window.requests >= per_minute {
+            let wait = RATE_WINDOW.saturating_sub(now.duration_since(window.started));
+            return Err(wait.as_secs().max(1));
+        }
+        window.requests += 1;
+        Ok(())
+    }
+}
+
+// ============ RESPONSE ============
+
+#[derive(Debug, Serialize)]
+pub struct PublicStatus {
+    // "up" or "down"; absent until the first probe has run
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub internet: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latency_ms: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub uptime_secs: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub checked_at: Option<String>,
+}
+
+fn uptime_secs() -> Option<u64> {
+    let uptime = std::fs::read_to_string("/proc/uptime").ok()?;
+    let secs: f64 = uptime.split_whitespace().next()?.parse().ok()?;
+    Some(secs as u64)
+}
+
+/// The fields `settings` allows, and nothing else
+pub fn build(settings: &PublicStatusSettings, probe: Option<Probe>) -> PublicStatus {
+    let probe = probe.filter(|_| settings.show_internet || settings.show_latency);
+    PublicStatus {
+        internet: probe
+            .as_ref()
+            .filter(|_| settings.show_internet)
+            .map(|p| if p.internet_up { "up" } else { "down" }),
+        latency_ms: probe.as_ref().filter(|_| settings.show_latency).and_then(|p| p.latency_ms),
+        uptime_secs: if settings.show_uptime { uptime_secs() } else { None },
+        checked_at: probe.map(|p| p.checked_at),
+    }
+}
+
+/// Whether management access should let `path` through for anyone
+pub async fn open_to_all(pool: &SqlitePool, path: &str) -> bool {
+    if path != PATH {
+        return false;
+    }
+    let settings = load_settings(pool).await;
+    settings.enabled && settings.allow_any_network
+}
+
+pub fn spawn(state: Arc<AppState>) {
+    tokio::spawn(async move {
+        loop {
+            state.tasks.beat("public_status", PROBE_INTERVAL);
+            let settings = load_settings(&state.db).await;
+            if settings.probes() {
+                let interface = crate::wan::wan_interface(&state.db).await;
+                let host = settings.probe_host.clone();
+                if let Ok(probe) = tokio::task::spawn_blocking(move || ping(&interface, &host)).await {
+                    *state.public_status.probe.lock().unwrap() = Some(probe);
+                }
+            } else {
+                *state.public_status.probe.lock().unwrap() = None;
+            }
+            tokio::time::sleep(PROBE_INTERVAL).await;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tracked_clients_stay_bounded() {
+        let tracker = PublicStatusTracker::new();
+        for i in 0..=MAX_TRACKED as u32 {
+            assert!(tracker.admit(IpAddr::from(i.to_be_bytes()), 10).is_ok());
+        }
+        assert_eq!(tracker.clients.lock().unwrap().len(), MAX_TRACKED);
+        // The first client was the oldest, so it made room for the last
+        assert!(!tracker.clients.lock().unwrap().contains_key(&IpAddr::from(0u32.to_be_bytes())));
+    }
+}
@@ -0,0 +1,331 @@
+// Traffic shaping (SQM). Queues build up in the modem when the WAN runs at full speed, which is
+// what makes calls stutter while someone uploads; cake shaping just below the line rate keeps
+// the queue on the router instead, where it is short and fair. Uploads are shaped on the WAN,
+// downloads on an IFB device the WAN's ingress is redirected through. Priorities are DSCP marks
+// on traffic leaving through the WAN, which cake's diffserv4 tins honour; downloads can't be
+// marked before they are shaped, so there cake's per-host fairness does the work. IPv4 only,
+// like the rest of the firewall. Nothing here survives a reboot, so it is rebuilt at startup.
+
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use std::net::Ipv4Addr;
+use std::process::Command;
+use std::sync::Arc;
+
+use crate::system::commands::RecordedCommand;
+use crate::system::privileges::sudo;
+use crate::AppState;
+
+// Key in the settings table
+pub const SETTINGS_KEY: &str = "qos";
+const CHAIN: &str = "ROUTERUI-QOS";
+pub const IFB: &str = "ifb-routerui";
+const MAX_RULES: usize = 64;
+const MAX_KBIT: u32 = 10_000_000;
+
+// ============ SETTINGS ============
+
+/// cake's diffserv4 tins, most urgent first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Priority {
+    Voice,
+    Video,
+    BestEffort,
+    Bulk,
+}
+
+impl Priority {
+    fn dscp_class(self) -> &'static str {
+        match self {
+            Priority::Voice => "EF",
+            Priority::Video => "AF41",
+            Priority::BestEffort => "CS0",
+            Priority::Bulk => "CS1",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PriorityRule {
+    // IPv4 address or CIDR range of the device(s)
+    #[serde(default)]
+    pub device: Option<String>,
+    // "tcp" or "udp"; None with a port means both
+    #[serde(default)]
+    pub protocol: Option<String>,
+    // Matched on either end, so it covers both a service on the LAN and one on the internet
+    #[serde(default)]
+    pub port: Option<u16>,
+    pub priority: Priority,
+    #[serde(default)]
+    pub description: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct QosSettings {
+    pub enabled: bool,
+    // A little below the measured line rate; 0 leaves that direction unshaped
+    pub download_kbit: u32,
+    pub upload_kbit: u32,
+    // The first matching rule decides
+    pub rules: Vec<PriorityRule>,
+}
+
+fn valid_device(value: &str) -> bool {
+    match value.split_once('/') {
+        Some((ip, prefix)) => ip.parse::<Ipv4Addr>().is_ok() && prefix.parse::<u8>().is_ok_and(|p| p <= 32),
+        None => value.parse::<Ipv4Addr>().is_ok(),
+    }
+}
+
+impl QosSettings {
+    pub fn normalize(&mut self) {
+        for rule in &mut self.rules {
+            rule.device = rule.device.as_deref().map(str::trim).filter(|d| !d.is_empty()).map(str::to_string);
+            rule.protocol = rule.protocol.as_deref().map(|p| p.trim().to_lowercase()).filter(|p| !p.is_empty());
+            rule.description = rule.description.trim().to_string();
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        for (name, kbit) in [("Download", self.download_kbit), ("Upload", self.upload_kbit)] {
+            if kbit > MAX_KBIT {
+                return Err(format!("{} bandwidth can be at most {} kbit/s", name, MAX_KBIT));
+            }
+        }
+        if self.enabled && self.download_kbit == 0 && self.upload_kbit == 0 {
+            return Err("Set the download or upload bandwidth to shape".to_string());
+        }
+        if self.rules.len() > MAX_RULES {
+            return Err(format!("At most {} priority rules are supported", MAX_RULES));
+        }
+        for rule in &self.rules {
+            if rule.device.is_none() && rule.port.is_none() {
+                return Err("A priority rule needs a device, a port or both".to_string());
+            }
+            if let Some(device) = rule.device.as_deref().filter(|d| !valid_device(d)) {
+                return Err(format!("'{}' is not an IPv4 address or CIDR range", device));
+            }
+            if let Some(protocol) = rule.protocol.as_deref() {
+                if protocol != "tcp" && protocol != "udp" {
+                    return Err(format!("Protocol must be tcp or udp, not '{}'", protocol));
+                }
+                if rule.port.is_none() {
+                    return Err("A protocol needs a port to go with it".to_string());
+                }
+            }
+            if rule.port == Some(0) {
+                return Err("Port 0 can't be matched".to_string());
+            }
+        }
+        Ok(())
+    }
+
+    // Mangle rules marking traffic out of the WAN. DSCP doesn't stop the chain, so the rules go in
+    // last to first and the first matching one has the final say.
+    fn chain_rules(&self) -> Vec<Vec<String>> {
+        let mut rules = Vec::new();
+        for rule in self.rules.iter().rev() {
+            let protocols: Vec<Option<&str>> = match (rule.port, rule.protocol.as_deref()) {
+                (None, _) => vec![None],
+                (Some(_), Some(protocol)) => vec![Some(protocol)],
+                (Some(_), None) => vec![Some("tcp"), Some("udp")],
+            };
+            for protocol in protocols {
+                let mut args: Vec<String> = Vec::new();
+                if let Some(device) = &rule.device {
+                    args.extend(["-s".to_string(), device.clone()]);
+                }
+                if let (Some(protocol), Some(port)) = (protocol, rule.port) {
+                    args.extend(["-p", protocol, "-m", "multiport", "--ports"].map(str::to_string));
+                    args.push(port.to_string());
+                }
+                args.extend(["-j", "DSCP", "--set-dscp-class", rule.priority.dscp_class()].map(str::to_string));
+                rules.push(args);
+            }
+        }
+        rules
+    }
+}
+
+pub async fn load_settings(pool: &SqlitePool) -> QosSettings {
+    match crate::db::settings::get(pool, SETTINGS_KEY).await {
+        Ok(settings) => settings.unwrap_or_default(),
+        Err(e) => {
+            tracing::warn!("Failed to read traffic shaping settings: {}", e);
+            QosSettings::default()
+        }
+    }
+}
+
+pub async fn save_settings(pool: &SqlitePool, settings: &QosSettings) -> Result<(), String> {
+    crate::db::settings::set(pool, SETTINGS_KEY, settings).await.map_err(|e| e.to_string())
+}
+
+// ============ SHAPING ============
+
+fn run(command: &mut RecordedCommand) -> Result<(), String> {
+    let output = command.output().map_err(|e| e.to_string())?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(String::from_utf8_lossy(&output.stderr).trim().to_string())
+    }
+}
+
+fn interface_exists(name: &str) -> bool {
+    std::path::Path::new("/sys/class/net").join(name).exists()
+}
+
+fn chain_exists() -> bool {
+    sudo().args(["iptables", "-t", "mangle", "-S", CHAIN]).output().is_ok_and(|o| o.status.success())
+}
+
+// Jumps are removed by reading them back, since the WAN interface may have changed
+fn remove_marks() {
+    if let Ok(output) = sudo().args(["iptables", "-t", "mangle", "-S", "FORWARD"]).output() {
+        let suffix = format!("-j {}", CHAIN);
+        for line in String::from_utf8_lossy(&output.stdout).lines() {
+            let Some(rule) = line.strip_prefix("-A FORWARD ") else { continue };
+            if rule.ends_with(&suffix) {
+                let _ = sudo().args(["iptables", "-t", "mangle", "-D", "FORWARD"]).args(rule.split_whitespace()).output();
+            }
+        }
+    }
+    if chain_exists() {
+        let _ = sudo().args(["iptables", "-t", "mangle", "-F", CHAIN]).output();
+        let _ = sudo().args(["iptables", "-t", "mangle", "-X", CHAIN]).output();
+    }
+}
+
+/// Take the shapers off `wan` and drop the marks (shaping off, WAN moved, factory reset). The
+/// qdiscs may not be there; deleting one that isn't is not an error worth reporting.
+pub fn remove(wan: &str) {
+    let _ = sudo().args(["tc", "qdisc", "del", "dev", wan, "root"]).output();
+    let _ = sudo().args(["tc", "qdisc", "del", "dev", wan, "ingress"]).output();
+    if interface_exists(IFB) {
+        let _ = sudo().args(["ip", "link", "del", IFB]).output();
+    }
+    remove_marks();
+}
+
+/// Put the shapers and marks in line with the settings. The marks are saved with the rest of
+/// the firewall; the qdiscs are rebuilt at startup.
+pub fn apply(wan: &str, settings: &QosSettings) -> Result<(), String> {
+    remove(wan);
+    if settings.enabled {
+        if settings.upload_kbit > 0 {
+            run(sudo().args(["tc", "qdisc", "replace", "dev", wan, "root", "cake", "bandwidth", &format!("{}kbit", settings.upload_kbit)])
+                .args(["diffserv4", "nat", "dual-srchost"]))?;
+        }
+        if settings.download_kbit > 0 {
+            run(sudo().args(["ip", "link", "add", "name", IFB, "type", "ifb"]))?;
+            run(sudo().args(["ip", "link", "set", IFB, "up"]))?;
+            run(sudo().args(["tc", "qdisc", "add", "dev", wan, "handle", "ffff:", "ingress"]))?;
+            run(sudo().args(["tc", "filter", "add", "dev", wan, "parent", "ffff:", "matchall", "action", "mirred", "egress", "redirect", "dev", IFB]))?;
+            run(sudo().args(["tc", "qdisc", "replace", "dev", IFB, "root", "cake", "bandwidth", &format!("{}kbit", settings.download_kbit)])
+                .args(["diffserv4", "nat", "dual-dsthost", "ingress"]))?;
+        }
+
+        let rules = settings.chain_rules();
+        if !rules.is_empty() {
+            run(sudo().args(["iptables", "-t", "mangle", "-N", CHAIN]))?;
+            for rule in &rules {
+                run(sudo().args(["iptables", "-t", "mangle", "-A", CHAIN]).args(rule))?;
+            }
+            run(sudo().args(["iptables", "-t", "mangle", "-A", "FORWARD", "-o", wan, "-j", CHAIN]))?;
+        }
+    }
+    run(sudo().args(["netfilter-persistent", "save"]))
+}
+
+// ============ STATUS ============
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TinStats {
+    pub name: &'static str,
+    pub sent_bytes: u64,
+    pub drops: u64,
+    pub avg_delay_us: u64,
+    pub peak_delay_us: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ShaperStatus {
+    pub interface: String,
+    pub bandwidth_kbit: Option<u64>,
+    pub sent_bytes: u64,
+    pub drops: u64,
+    pub backlog_bytes: u64,
+    pub tins: Vec<TinStats>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct QosStatus {
+    // Shapers actually in place, None for a direction that isn't shaped
+    pub upload: Option<ShaperStatus>,
+    pub download: Option<ShaperStatus>,
+}
+
+// diffserv4 lists its tins lowest priority first
+const TIN_NAMES: [&str; 4] = ["Bulk", "Best Effort", "Video", "Voice"];
+
+// The cake qdisc at the root of `interface`, from `tc -s -j qdisc show` (no privileges needed)
+fn shaper(interface: &str) -> Option<ShaperStatus> {
+    let output = Command::new("tc").args(["-s", "-j", "qdisc", "show", "dev", interface]).output().ok()?;
+    let qdiscs: Vec<serde_json::Value> = serde_json::from_slice(&output.stdout).ok()?;
+    let cake = qdiscs
+        .into_iter()
+        .find(|q| q.get("kind").and_then(|k| k.as_str()) == Some("cake") && q.get("root").and_then(|r| r.as_bool()) == Some(true))?;
+    let number = |v: &serde_json::Value, key: &str| v.get(key).and_then(|n| n.as_u64()).unwrap_or(0);
+    let tins = cake
+        .get("tins")
+        .and_then(|t| t.as_array())
+        .map(|tins| {
+            tins.iter()
+                .zip(TIN_NAMES)
+                .map(|(tin, name)| TinStats {
+                    name,
+                    sent_bytes: number(tin, "sent_bytes"),
+                    drops: number(tin, "drops"),
+                    avg_delay_us: number(tin, "avg_delay_us"),
+                    peak_delay_us: number(tin, "peak_delay_us"),
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    Some(ShaperStatus {
+        interface: interface.to_string(),
+        // Reported in bytes per second
+        bandwidth_kbit: cake.get("options").and_then(|o| o.get("bandwidth")).and_then(|b| b.as_u64()).map(|b| b * 8 / 1000),
+        sent_bytes: number(&cake, "bytes"),
+        drops: number(&cake, "drops"),
+        backlog_bytes: number(&cake, "backlog"),
+        tins,
+    })
+}
+
+pub fn status(wan: &str) -> QosStatus {
+    QosStatus {
+        upload: shaper(wan),
+        download: interface_exists(IFB).then(|| shaper(IFB)).flatten(),
+    }
+}
+
+/// Rebuild the shapers at startup; qdiscs and the IFB device are gone after a reboot
+pub fn spawn(state: Arc<AppState>) {
+    tokio::spawn(async move {
+        let settings = load_settings(&state.db).await;
+        if !settings.enabled {
+            return;
+        }
+        let wan = crate::wan::wan_interface(&state.db).await;
+        match tokio::task::spawn_blocking(move || apply(&wan, &settings)).await {
+            Ok(Err(e)) => tracing::warn!("Could not set up traffic shaping: {}", e),
+            Err(e) => tracing::warn!("Could not set up traffic shaping: {}", e),
+            Ok(Ok(())) => {}
+        }
+    });
+}
@@ -0,0 +1,151 @@
+use chrono::Utc;
+use serde::Serialize;
+use sqlx::SqlitePool;
+use std::collections::{BTreeSet, HashMap};
+use std::net::{IpAddr, Ipv4Addr};
+
+/// One feed's entry covering an address
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct Listing {
+    pub source: String,
+    pub entry: String,
+    pub first_seen: String,
+    pub last_seen: String,
+    // Still in the feed as of its latest update
+    pub active: bool,
+}
+
+/// How many feeds list an address, combined across every blocklist update so far
+#[derive(Debug, Clone, Serialize)]
+pub struct Reputation {
+    pub ip: String,
+    // Distinct feeds currently listing the address
+    pub score: u32,
+    pub listed_since: Option<String>,
+    pub summary: String,
+    pub listings: Vec<Listing>,
+}
+
+// IPv4 entries are stored as an integer range so CIDRs and from-to ranges can be matched
+fn ipv4_range(entry: &str) -> Option<(i64, i64)> {
+    if let Some((from, to)) = entry.split_once('-') {
+        let from: Ipv4Addr = from.trim().parse().ok()?;
+        let to: Ipv4Addr = to.trim().parse().ok()?;
+        return Some((u32::from(from) as i64, u32::from(to) as i64));
+    }
+    let (addr, prefix) = match entry.split_once('/') {
+        Some((addr, prefix)) => (addr, prefix.parse::<u32>().ok().filter(|p| *p <= 32)?),
+        None => (entry, 32),
+    };
+    let addr = u32::from(addr.parse::<Ipv4Addr>().ok()?);
+    let mask = u32::MAX.checked_shl(32 - prefix).unwrap_or(0);
+    Some(((addr & mask) as i64, (addr | !mask) as i64))
+}
+
+fn month(timestamp: &str) -> &str {
+    timestamp.get(..7).unwrap_or(timestamp)
+}
+
+fn plural(n: u32) -> &'static str {
+    if n == 1 { "" } else { "s" }
+}
+
+/// Record the entries a feed just delivered. Entries already known keep their first_seen.
+pub async fn record_feed(pool: &SqlitePool, source: &str, entries: &[String]) -> Result<(), sqlx::Error> {
+    let now = Utc::now().to_rfc3339();
+    let mut tx = pool.begin().await?;
+
+    for entry in entries {
+        let range = ipv4_range(entry);
+        sqlx::query(
+            "INSERT INTO ip_reputation (entry, source, range_start, range_end, first_seen, last_seen)
+             VALUES (?, ?, ?, ?, ?, ?)
+             ON CONFLICT(entry, source) DO UPDATE SET last_seen = excluded.last_seen",
+        )
+        .bind(entry)
+        .bind(source)
+        .bind(range.map(|r| r.0))
+        .bind(range.map(|r| r.1))
+        .bind(&now)
+        .bind(&now)
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    // Listings whose last_seen is older than this were dropped by the feed
+    sqlx::query(
+        "INSERT INTO ip_reputation_feeds (source, updated_at, entries) VALUES (?, ?, ?)
+         ON CONFLICT(source) DO UPDATE SET updated_at = excluded.updated_at, entries = excluded.entries",
+    )
+    .bind(source)
+    .bind(&now)
+    .bind(entries.len() as i64)
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await
+}
+
+/// A feed was switched off: keep its history, but nothing it listed counts as active any more
+pub async fn disable_feed(pool: &SqlitePool, source: &str) -> Result<(), sqlx::Error> {
+    sqlx::query("DELETE FROM ip_reputation_feeds WHERE source = ?")
+        .bind(source)
+        .execute(pool)
+        .await
+        .map(|_| ())
+}
+
+pub async fn lookup(pool: &SqlitePool, ip: IpAddr) -> Result<Reputation, sqlx::Error> {
+    let numeric = match ip {
+        IpAddr::V4(v4) => Some(u32::from(v4) as i64),
+        IpAddr::V6(_) => None,
+    };
+
+    let listings: Vec<Listing> = sqlx::query_as(
+        "SELECT r.source, r.entry, r.first_seen, r.last_seen,
+                COALESCE(f.updated_at = r.last_seen, 0) AS active
+         FROM ip_reputation r
+         LEFT JOIN ip_reputation_feeds f ON f.source = r.source
+         WHERE (r.range_start <= ?1 AND r.range_end >= ?1) OR r.entry = ?2
+         ORDER BY r.first_seen",
+    )
+    .bind(numeric)
+    .bind(ip.to_string())
+    .fetch_all(pool)
+    .await?;
+
+    let active: BTreeSet<&str> = listings.iter().filter(|l| l.active).map(|l| l.source.as_str()).collect();
+    let score = active.len() as u32;
+    // Since the oldest active listing; fall back to history once every feed has dropped it
+    let listed_since = listings
+        .iter()
+        .find(|l| l.active)
+        .or(listings.first())
+        .map(|l| l.first_seen.clone());
+
+    let summary = match (&listed_since, score) {
+        (None, _) => "Not listed in any feed".to_string(),
+        (Some(since), 0) => {
+            let feeds = listings.iter().map(|l| l.source.as_str()).collect::<BTreeSet<_>>().len() as u32;
+            format!("Previously listed in {} feed{} (since {})", feeds, plural(feeds), month(since))
+        }
+        (Some(since), n) => format!("Listed in {} feed{} since {}", n, plural(n), month(since)),
+    };
+
+    Ok(Reputation { ip: ip.to_string(), score, listed_since, summary, listings })
+}
+
+/// Look up several addresses at once; unparseable ones are skipped
+pub async fn lookup_many(pool: &SqlitePool, ips: &[String]) -> HashMap<String, Reputation> {
+    let mut results = HashMap::new();
+    for ip in ips {
+        if results.contains_key(ip) {
+            continue;
+        }
+        let Ok(addr) = ip.parse::<IpAddr>() else { continue };
+        if let Ok(reputation) = lookup(pool, addr).await {
+            results.insert(ip.clone(), reputation);
+        }
+    }
+    results
+}
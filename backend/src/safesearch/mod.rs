@@ -0,0 +1,225 @@
+// Enforced safe search for groups of devices. Google, Bing and YouTube each publish a hostname
+// that always serves the filtered version of the site; pointing the search hosts at it (a DNS
+// CNAME) turns safe search on for every browser and app, whatever the account settings say.
+// With AdGuard Home answering DNS the rewrites can be scoped to individual clients. Plain
+// dnsmasq can't answer differently per client, so it only carries groups covering everyone.
+
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use std::net::IpAddr;
+
+// Key in the settings table
+pub const SETTINGS_KEY: &str = "safe_search";
+pub const DNSMASQ_FILE: &str = "/etc/dnsmasq.d/safe-search.conf";
+// Marks RouterUI's block among the AdGuard custom rules
+pub const ADGUARD_MARKER: &str = "routerui-safe-search";
+const MAX_GROUPS: usize = 32;
+const MAX_CLIENTS: usize = 256;
+
+// Country sites people actually land on; the bare domains redirect to www
+const GOOGLE_HOSTS: &[&str] = &[
+    "www.google.com", "www.google.co.uk", "www.google.ca", "www.google.com.au", "www.google.de",
+    "www.google.fr", "www.google.es", "www.google.it", "www.google.nl", "www.google.co.in",
+    "www.google.com.br", "www.google.co.jp",
+];
+const BING_HOSTS: &[&str] = &["www.bing.com"];
+const YOUTUBE_HOSTS: &[&str] = &[
+    "www.youtube.com", "m.youtube.com", "youtubei.googleapis.com", "youtube.googleapis.com",
+    "www.youtube-nocookie.com",
+];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum YoutubeMode {
+    #[default]
+    Off,
+    Moderate,
+    Strict,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SafeSearchGroup {
+    // Empty for a group that hasn't been saved yet
+    #[serde(default)]
+    pub id: String,
+    pub name: String,
+    // Switched by hand or by a profile; a disabled group keeps its devices and engines
+    #[serde(default)]
+    pub enabled: bool,
+    // IP addresses or CIDR ranges; empty means every device on the network
+    #[serde(default)]
+    pub clients: Vec<String>,
+    #[serde(default)]
+    pub google: bool,
+    #[serde(default)]
+    pub bing: bool,
+    #[serde(default)]
+    pub youtube: YoutubeMode,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SafeSearchSettings {
+    #[serde(default)]
+    pub groups: Vec<SafeSearchGroup>,
+}
+
+/// Where the rewrites ended up
+#[derive(Debug, Clone, Serialize)]
+pub struct Enforcement {
+    // "adguard" or "dnsmasq"
+    pub backend: &'static str,
+    // Enabled groups the backend can't enforce (per-device groups without AdGuard)
+    pub unenforced: Vec<String>,
+}
+
+pub async fn load(pool: &SqlitePool) -> SafeSearchSettings {
+    match crate::db::settings::get(pool, SETTINGS_KEY).await {
+        Ok(settings) => settings.unwrap_or_default(),
+        Err(e) => {
+            tracing::warn!("Failed to read safe search settings, using defaults: {}", e);
+            SafeSearchSettings::default()
+        }
+    }
+}
+
+pub async fn save(pool: &SqlitePool, settings: &SafeSearchSettings) -> Result<(), String> {
+    crate::db::settings::set(pool, SETTINGS_KEY, settings).await.map_err(|e| e.to_string())
+}
+
+fn valid_client(client: &str) -> bool {
+    match client.split_once('/') {
+        Some((ip, prefix)) => match (ip.parse::<IpAddr>(), prefix.parse::<u8>()) {
+            (Ok(IpAddr::V4(_)), Ok(prefix)) => prefix <= 32,
+            (Ok(IpAddr::V6(_)), Ok(prefix)) => prefix <= 128,
+            _ => false,
+        },
+        None => client.parse::<IpAddr>().is_ok(),
+    }
+}
+
+impl SafeSearchGroup {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.name.trim().is_empty() {
+            return Err("Group name is required".to_string());
+        }
+        if self.clients.len() > MAX_CLIENTS {
+            return Err(format!("{}: at most {} devices per group", self.name, MAX_CLIENTS));
+        }
+        if let Some(client) = self.clients.iter().find(|c| !valid_client(c)) {
+            return Err(format!("{}: '{}' is not an IP address or CIDR range", self.name, client));
+        }
+        Ok(())
+    }
+
+    /// (hostname, safe hostname, published address of the safe hostname) for each rewrite
+    fn rewrites(&self) -> Vec<(&'static str, &'static str, &'static str)> {
+        let mut rewrites = Vec::new();
+        if self.google {
+            rewrites.extend(GOOGLE_HOSTS.iter().map(|h| (*h, "forcesafesearch.google.com", "216.239.38.120")));
+        }
+        if self.bing {
+            rewrites.extend(BING_HOSTS.iter().map(|h| (*h, "strict.bing.com", "204.79.197.220")));
+        }
+        let youtube = match self.youtube {
+            YoutubeMode::Off => None,
+            YoutubeMode::Moderate => Some(("restrictmoderate.youtube.com", "216.239.38.119")),
+            YoutubeMode::Strict => Some(("restrict.youtube.com", "216.239.38.120")),
+        };
+        if let Some((target, address)) = youtube {
+            rewrites.extend(YOUTUBE_HOSTS.iter().map(|h| (*h, target, address)));
+        }
+        rewrites
+    }
+}
+
+impl SafeSearchSettings {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.groups.len() > MAX_GROUPS {
+            return Err(format!("At most {} groups", MAX_GROUPS));
+        }
+        for group in &self.groups {
+            group.validate()?;
+        }
+        Ok(())
+    }
+
+    /// AdGuard custom rules: a CNAME rewrite per host, limited to the group's clients
+    pub fn adguard_rules(&self) -> Vec<String> {
+        let mut rules = Vec::new();
+        for group in self.groups.iter().filter(|g| g.enabled) {
+            let clients = if group.clients.is_empty() {
+                String::new()
+            } else {
+                format!(",client={}", group.clients.join("|"))
+            };
+            for (host, target, _) in group.rewrites() {
+                rules.push(format!("|{}^$dnsrewrite=NOERROR;CNAME;{}{}", host, target, clients));
+            }
+        }
+        rules
+    }
+
+    /// dnsmasq can only answer the same for everyone, so groups with devices are left out
+    pub fn dnsmasq_config(&self) -> String {
+        let mut content = String::from("# Enforced safe search - managed by RouterUI\n");
+        let mut seen = Vec::new();
+        for group in self.groups.iter().filter(|g| g.enabled && g.clients.is_empty()) {
+            for (host, _, address) in group.rewrites() {
+                if !seen.contains(&host) {
+                    seen.push(host);
+                    content.push_str(&format!("address=/{}/{}\n", host, address));
+                }
+            }
+        }
+        content
+    }
+}
+
+fn reload_dnsmasq() {
+    let _ = crate::system::privileges::sudo()
+        .args(["systemctl", "reload", "dnsmasq"])
+        .output();
+}
+
+/// Push the enabled groups to whichever DNS server is answering: AdGuard Home when it is
+/// reachable, dnsmasq otherwise. The other one is cleared so the two never disagree.
+pub async fn apply(settings: &SafeSearchSettings) -> Result<Enforcement, String> {
+    use crate::system::privileges::write_system_file;
+
+    let empty = String::from("# Enforced safe search - managed by RouterUI\n");
+    if crate::api::adguard::reachable().await {
+        crate::api::adguard::set_managed_rules(ADGUARD_MARKER, settings.adguard_rules()).await?;
+        if std::fs::read_to_string(DNSMASQ_FILE).is_ok_and(|c| c != empty) {
+            write_system_file(DNSMASQ_FILE, &empty).map_err(|e| e.to_string())?;
+            reload_dnsmasq();
+        }
+        return Ok(Enforcement { backend: "adguard", unenforced: Vec::new() });
+    }
+
+    write_system_file(DNSMASQ_FILE, settings.dnsmasq_config()).map_err(|e| e.to_string())?;
+    reload_dnsmasq();
+    let unenforced = settings
+        .groups
+        .iter()
+        .filter(|g| g.enabled && !g.clients.is_empty())
+        .map(|g| g.name.clone())
+        .collect();
+    Ok(Enforcement { backend: "dnsmasq", unenforced })
+}
+
+/// Enable exactly the groups in `ids` (used by profiles) and push the result
+pub async fn enable_only(pool: &SqlitePool, ids: &[String]) -> Result<(), String> {
+    let mut settings = load(pool).await;
+    for group in &mut settings.groups {
+        group.enabled = ids.contains(&group.id);
+    }
+    save(pool, &settings).await?;
+    let enforcement = apply(&settings).await?;
+    if !enforcement.unenforced.is_empty() {
+        return Err(format!(
+            "{} need AdGuard Home to be enforced per device",
+            enforcement.unenforced.join(", ")
+        ));
+    }
+    Ok(())
+}
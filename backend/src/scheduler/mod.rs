@@ -0,0 +1,205 @@
+use chrono::{DateTime, Datelike, Duration as ChronoDuration, Local, NaiveTime, Weekday};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::AppState;
+
+// Windows have minute resolution
+const TICK: Duration = Duration::from_secs(60);
+
+const DAYS: [(&str, Weekday); 7] = [
+    ("mon", Weekday::Mon),
+    ("tue", Weekday::Tue),
+    ("wed", Weekday::Wed),
+    ("thu", Weekday::Thu),
+    ("fri", Weekday::Fri),
+    ("sat", Weekday::Sat),
+    ("sun", Weekday::Sun),
+];
+
+/// A weekly window in router local time. A window whose end is before its start runs past
+/// midnight into the next day; equal start and end covers the whole day.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TimeWindow {
+    // "mon".."sun"; empty means every day
+    #[serde(default)]
+    pub days: Vec<String>,
+    // "HH:MM"
+    pub start: String,
+    pub end: String,
+}
+
+/// Something that is on during its windows and off outside them
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Schedule {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default)]
+    pub windows: Vec<TimeWindow>,
+}
+
+fn parse_time(value: &str) -> Option<NaiveTime> {
+    NaiveTime::parse_from_str(value.trim(), "%H:%M").ok()
+}
+
+fn weekday(day: &str) -> Option<Weekday> {
+    let day = day.trim().to_lowercase();
+    DAYS.iter().find(|(name, _)| day.starts_with(name)).map(|(_, d)| *d)
+}
+
+impl TimeWindow {
+    pub fn validate(&self) -> Result<(), String> {
+        for day in &self.days {
+            weekday(day).ok_or_else(|| format!("Invalid day: {}", day))?;
+        }
+        parse_time(&self.start).ok_or_else(|| format!("Invalid start time: {}", self.start))?;
+        parse_time(&self.end).ok_or_else(|| format!("Invalid end time: {}", self.end))?;
+        Ok(())
+    }
+
+    fn on_day(&self, day: Weekday) -> bool {
+        self.days.is_empty() || self.days.iter().any(|d| weekday(d) == Some(day))
+    }
+
+    pub fn contains(&self, now: DateTime<Local>) -> bool {
+        let (Some(start), Some(end)) = (parse_time(&self.start), parse_time(&self.end)) else {
+            return false;
+        };
+        let time = now.time();
+        let today = now.weekday();
+
+        if start == end {
+            return self.on_day(today);
+        }
+        if start < end {
+            return self.on_day(today) && time >= start && time < end;
+        }
+        // Overnight: the evening part belongs to today, the early-morning part to yesterday
+        (self.on_day(today) && time >= start) || (self.on_day(today.pred()) && time < end)
+    }
+}
+
+impl Schedule {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.enabled && self.windows.is_empty() {
+            return Err("An enabled schedule needs at least one time window".to_string());
+        }
+        self.windows.iter().try_for_each(TimeWindow::validate)
+    }
+
+    /// Whether the scheduled thing should be on at `now`; None when the schedule is off
+    pub fn active_at(&self, now: DateTime<Local>) -> Option<bool> {
+        self.enabled.then(|| self.windows.iter().any(|w| w.contains(now)))
+    }
+
+    /// Next time the desired state flips, looking up to a week ahead
+    pub fn next_change(&self, now: DateTime<Local>) -> Option<DateTime<Local>> {
+        let current = self.active_at(now)?;
+        let mut at = now;
+        for _ in 0..7 * 24 * 60 {
+            at += ChronoDuration::minutes(1);
+            if self.active_at(at) != Some(current) {
+                return Some(at);
+            }
+        }
+        None
+    }
+}
+
+/// Applies scheduled state on window boundaries only, so a manual change made in between
+/// stays in place until the next boundary
+#[derive(Default)]
+pub struct Edge {
+    last: Option<bool>,
+}
+
+impl Edge {
+    /// Returns the desired state when it differs from the previous tick
+    pub fn changed(&mut self, desired: Option<bool>) -> Option<bool> {
+        let previous = std::mem::replace(&mut self.last, desired);
+        match desired {
+            Some(on) if previous != Some(on) => Some(on),
+            _ => None,
+        }
+    }
+}
+
+pub fn spawn(state: Arc<AppState>) {
+    tokio::spawn(async move {
+        let mut wifi = crate::api::network::WifiScheduler::default();
+        let mut profiles = crate::profiles::ProfileScheduler::default();
+        let mut firewall = crate::api::firewall::FirewallScheduler::default();
+        loop {
+            state.tasks.beat("scheduler", TICK);
+            wifi.tick(&state.db, Local::now()).await;
+            profiles.tick(&state.db, Local::now()).await;
+            firewall.tick(&state.db, Local::now()).await;
+            tokio::time::sleep(TICK).await;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn window(days: &[&str], start: &str, end: &str) -> TimeWindow {
+        TimeWindow { days: days.iter().map(|d| d.to_string()).collect(), start: start.to_string(), end: end.to_string() }
+    }
+
+    // 2026-10-12 is a Monday
+    fn at(day: u32, hour: u32, minute: u32) -> DateTime<Local> {
+        Local.with_ymd_and_hms(2026, 10, day, hour, minute, 0).unwrap()
+    }
+
+    #[test]
+    fn daytime_window_is_start_inclusive_end_exclusive() {
+        let school = window(&[], "08:00", "15:00");
+        assert!(!school.contains(at(12, 7, 59)));
+        assert!(school.contains(at(12, 8, 0)));
+        assert!(school.contains(at(12, 14, 59)));
+        assert!(!school.contains(at(12, 15, 0)));
+    }
+
+    #[test]
+    fn overnight_window_spans_midnight() {
+        let night = window(&[], "22:00", "06:30");
+        assert!(night.contains(at(12, 23, 0)));
+        assert!(night.contains(at(13, 0, 0)));
+        assert!(night.contains(at(13, 6, 29)));
+        assert!(!night.contains(at(13, 6, 30)));
+        assert!(!night.contains(at(13, 12, 0)));
+        assert!(!night.contains(at(13, 21, 59)));
+    }
+
+    #[test]
+    fn overnight_morning_belongs_to_the_previous_day() {
+        // School nights: Sunday to Thursday evenings
+        let night = window(&["sun", "mon", "tue", "wed", "thu"], "21:00", "07:00");
+        // Friday early morning is Thursday night
+        assert!(night.contains(at(16, 3, 0)));
+        // Friday evening isn't a school night, so neither is Saturday morning
+        assert!(!night.contains(at(16, 22, 0)));
+        assert!(!night.contains(at(17, 3, 0)));
+        // Monday morning is Sunday night
+        assert!(night.contains(at(12, 6, 0)));
+        assert!(night.contains(at(11, 21, 0)));
+    }
+
+    #[test]
+    fn equal_start_and_end_is_all_day() {
+        let weekend = window(&["sat", "sun"], "00:00", "00:00");
+        assert!(weekend.contains(at(17, 0, 0)));
+        assert!(weekend.contains(at(18, 23, 59)));
+        assert!(!weekend.contains(at(19, 12, 0)));
+    }
+
+    #[test]
+    fn invalid_times_never_match() {
+        assert!(!window(&[], "25:00", "06:00").contains(at(12, 1, 0)));
+        assert!(window(&[], "25:00", "06:00").validate().is_err());
+        assert!(window(&["someday"], "22:00", "06:00").validate().is_err());
+    }
+}
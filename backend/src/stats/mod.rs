@@ -0,0 +1,127 @@
+use axum::{
+    extract::{MatchedPath, Request, State},
+    middleware::Next,
+    response::Response,
+};
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::AppState;
+
+// Latency percentiles are computed over the most recent calls only
+const LATENCY_WINDOW: usize = 500;
+
+#[derive(Default)]
+struct RouteStats {
+    calls: u64,
+    client_errors: u64,
+    server_errors: u64,
+    total_ms: f64,
+    max_ms: f64,
+    recent_ms: VecDeque<f64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RouteSummary {
+    pub route: String,
+    pub calls: u64,
+    pub client_errors: u64,
+    pub server_errors: u64,
+    pub error_rate: f64,
+    pub avg_ms: f64,
+    pub p95_ms: f64,
+    pub max_ms: f64,
+}
+
+/// In-memory per-route call counters and latency samples
+#[derive(Default)]
+pub struct ApiStats {
+    routes: Mutex<HashMap<String, RouteStats>>,
+}
+
+impl ApiStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&self, route: String, status: u16, elapsed: Duration) {
+        let ms = elapsed.as_secs_f64() * 1000.0;
+        let mut routes = self.routes.lock().unwrap();
+        let stats = routes.entry(route).or_default();
+
+        stats.calls += 1;
+        if (400..500).contains(&status) {
+            stats.client_errors += 1;
+        } else if status >= 500 {
+            stats.server_errors += 1;
+        }
+        stats.total_ms += ms;
+        stats.max_ms = stats.max_ms.max(ms);
+
+        if stats.recent_ms.len() == LATENCY_WINDOW {
+            stats.recent_ms.pop_front();
+        }
+        stats.recent_ms.push_back(ms);
+    }
+
+    /// Summaries sorted slowest (by p95) first
+    pub fn summary(&self) -> Vec<RouteSummary> {
+        let routes = self.routes.lock().unwrap();
+        let mut result: Vec<RouteSummary> = routes
+            .iter()
+            .map(|(route, s)| RouteSummary {
+                route: route.clone(),
+                calls: s.calls,
+                client_errors: s.client_errors,
+                server_errors: s.server_errors,
+                error_rate: round2((s.client_errors + s.server_errors) as f64 / s.calls as f64 * 100.0),
+                avg_ms: round2(s.total_ms / s.calls as f64),
+                p95_ms: round2(percentile(&s.recent_ms, 95.0)),
+                max_ms: round2(s.max_ms),
+            })
+            .collect();
+
+        result.sort_by(|a, b| b.p95_ms.total_cmp(&a.p95_ms));
+        result
+    }
+
+    pub fn reset(&self) {
+        self.routes.lock().unwrap().clear();
+    }
+}
+
+fn percentile(samples: &VecDeque<f64>, pct: f64) -> f64 {
+    if samples.is_empty() {
+        return 0.0;
+    }
+    let mut sorted: Vec<f64> = samples.iter().copied().collect();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let rank = ((pct / 100.0) * sorted.len() as f64).ceil() as usize;
+    sorted[rank.saturating_sub(1).min(sorted.len() - 1)]
+}
+
+fn round2(v: f64) -> f64 {
+    (v * 100.0).round() / 100.0
+}
+
+// Route layer middleware - runs after routing so the matched path template is available
+pub async fn track(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|p| p.as_str().to_string())
+        .unwrap_or_else(|| request.uri().path().to_string());
+    let key = format!("{} {}", request.method(), route);
+
+    let start = Instant::now();
+    let response = next.run(request).await;
+    state.api_stats.record(key, response.status().as_u16(), start.elapsed());
+
+    response
+}
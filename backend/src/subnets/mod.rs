@@ -0,0 +1,475 @@
+// LAN subnets beyond the main LAN: a VLAN for IoT gear, a second NIC for the lab, a WiFi BSS on
+// its own addressing. Each gets the router's address on it, optionally a DHCP scope and a local
+// domain of its own, and can be isolated so devices on it reach the internet and the router's
+// DNS/DHCP but nothing else. The main LAN stays where the setup wizard put it (dnsmasq's main
+// config, the lan interface role); everything here is layered on top and removed cleanly.
+
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use std::net::Ipv4Addr;
+
+use crate::api::network::{lease_secs, pool_usage, PoolStatus};
+use crate::system::commands::RecordedCommand;
+use crate::system::privileges::{sudo, write_system_file};
+
+// Key in the settings table
+pub const SETTINGS_KEY: &str = "subnets";
+pub const DNSMASQ_FILE: &str = "/etc/dnsmasq.d/subnets.conf";
+const NETPLAN_FILE: &str = "/etc/netplan/98-routerui-subnets.yaml";
+const INTERFACES_FILE: &str = "/etc/network/interfaces.d/routerui-subnets";
+const CHAIN: &str = "ROUTERUI-SUBNETS";
+const FORWARD_CHAIN: &str = "ROUTERUI-SUBNETS-FWD";
+// What the main LAN's devices are labelled with
+pub const PRIMARY_NAME: &str = "lan";
+const MAX_SUBNETS: usize = 16;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DhcpScope {
+    pub range_start: String,
+    pub range_end: String,
+    pub lease_time: String,
+    // Handed out as the DNS server; the router's address on the subnet when unset
+    #[serde(default)]
+    pub dns_server: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Subnet {
+    // Short name, used as the DHCP tag and to label devices on the subnet
+    pub name: String,
+    // The NIC or bridge the subnet is on, or the parent of its VLAN
+    pub interface: String,
+    // 802.1Q VLAN on `interface`, which gets a <interface>.<id> link
+    #[serde(default)]
+    pub vlan_id: Option<u16>,
+    // The router's address on the subnet with its prefix, e.g. 10.22.30.1/24
+    pub address: String,
+    // None leaves addressing to static configuration or another DHCP server
+    #[serde(default)]
+    pub dhcp: Option<DhcpScope>,
+    // Domain DHCP clients' names are registered under, e.g. iot.lan
+    #[serde(default)]
+    pub domain: Option<String>,
+    // Internet, DNS and DHCP only: no other LANs and no router management
+    #[serde(default)]
+    pub isolated: bool,
+}
+
+fn valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 15
+        && name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+        && !name.starts_with('-')
+}
+
+fn valid_domain(domain: &str) -> bool {
+    domain.len() <= 253
+        && domain.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && !label.starts_with('-')
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        })
+}
+
+fn mask(prefix: u32) -> u32 {
+    u32::MAX.checked_shl(32 - prefix).unwrap_or(0)
+}
+
+fn contains((network, prefix): (Ipv4Addr, u32), ip: Ipv4Addr) -> bool {
+    u32::from(ip) & mask(prefix) == u32::from(network) & mask(prefix)
+}
+
+fn overlaps(a: (Ipv4Addr, u32), b: (Ipv4Addr, u32)) -> bool {
+    let prefix = a.1.min(b.1);
+    u32::from(a.0) & mask(prefix) == u32::from(b.0) & mask(prefix)
+}
+
+impl Subnet {
+    /// Kernel name of the link the subnet lives on
+    pub fn link(&self) -> String {
+        match self.vlan_id {
+            Some(id) => format!("{}.{}", self.interface, id),
+            None => self.interface.clone(),
+        }
+    }
+
+    /// The router's address and the prefix length
+    pub fn router(&self) -> Option<(Ipv4Addr, u32)> {
+        let (ip, prefix) = self.address.split_once('/')?;
+        Some((ip.parse().ok()?, prefix.parse::<u32>().ok().filter(|p| (8..=30).contains(p))?))
+    }
+
+    pub fn contains(&self, ip: Ipv4Addr) -> bool {
+        self.router().is_some_and(|net| contains(net, ip))
+    }
+
+    fn netmask(&self) -> Option<Ipv4Addr> {
+        self.router().map(|(_, prefix)| Ipv4Addr::from(mask(prefix)))
+    }
+
+    fn network(&self) -> Option<String> {
+        let (ip, prefix) = self.router()?;
+        Some(format!("{}/{}", Ipv4Addr::from(u32::from(ip) & mask(prefix)), prefix))
+    }
+
+    fn normalize(&mut self) {
+        self.name = self.name.trim().to_lowercase();
+        self.interface = self.interface.trim().to_string();
+        self.address = self.address.trim().to_string();
+        self.domain = self.domain.as_deref().map(str::trim).filter(|d| !d.is_empty()).map(str::to_lowercase);
+        if let Some(scope) = &mut self.dhcp {
+            scope.range_start = scope.range_start.trim().to_string();
+            scope.range_end = scope.range_end.trim().to_string();
+            scope.lease_time = scope.lease_time.trim().to_string();
+            scope.dns_server = scope.dns_server.as_deref().map(str::trim).filter(|d| !d.is_empty()).map(str::to_string);
+        }
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if !valid_name(&self.name) || self.name == PRIMARY_NAME {
+            return Err(format!("'{}' is not a valid subnet name; use up to 15 lowercase letters, digits and dashes", self.name));
+        }
+        let link = self.link();
+        if self.interface.is_empty() || !crate::interfaces::valid_name(&link) {
+            return Err(format!("{}: '{}' is not a valid interface name", self.name, link));
+        }
+        if self.vlan_id.is_some_and(|id| !(1..=4094).contains(&id)) {
+            return Err(format!("{}: VLAN IDs run from 1 to 4094", self.name));
+        }
+        let (router, prefix) = self
+            .router()
+            .ok_or_else(|| format!("{}: address must be the router's IPv4 address with a /8-/30 prefix, e.g. 10.22.30.1/24", self.name))?;
+        let net = (router, prefix);
+        let host = u32::from(router) & !mask(prefix);
+        if host == 0 || host == !mask(prefix) {
+            return Err(format!("{}: {} is the network or broadcast address", self.name, router));
+        }
+        if let Some(domain) = &self.domain {
+            if !valid_domain(domain) {
+                return Err(format!("{}: '{}' is not a valid domain", self.name, domain));
+            }
+        }
+        if let Some(scope) = &self.dhcp {
+            let parse = |value: &str| value.parse::<Ipv4Addr>().ok().filter(|ip| contains(net, *ip));
+            let start = parse(&scope.range_start)
+                .ok_or_else(|| format!("{}: DHCP range start {} is not inside {}", self.name, scope.range_start, self.address))?;
+            let end = parse(&scope.range_end)
+                .ok_or_else(|| format!("{}: DHCP range end {} is not inside {}", self.name, scope.range_end, self.address))?;
+            if u32::from(end) < u32::from(start) {
+                return Err(format!("{}: DHCP range ends before it starts", self.name));
+            }
+            if (u32::from(start)..=u32::from(end)).contains(&u32::from(router)) {
+                return Err(format!("{}: the DHCP range includes the router's own address", self.name));
+            }
+            if scope.lease_time != "infinite" && lease_secs(&scope.lease_time).is_none_or(|secs| secs < 120) {
+                return Err(format!("{}: lease time must be like 12h or 30m (at least 2 minutes), or infinite", self.name));
+            }
+            if let Some(dns) = &scope.dns_server {
+                dns.parse::<Ipv4Addr>().map_err(|_| format!("{}: DNS server {} is not an IPv4 address", self.name, dns))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SubnetSettings {
+    pub subnets: Vec<Subnet>,
+}
+
+impl SubnetSettings {
+    pub fn normalize(&mut self) {
+        for subnet in &mut self.subnets {
+            subnet.normalize();
+        }
+    }
+
+    /// `wan` and `lan` are the interfaces with those roles, `primary` the main LAN's subnets;
+    /// extra subnets may be VLANs on the LAN interface but can't take it over
+    pub fn validate(&self, wan: &str, lan: &str, primary: &[(Ipv4Addr, u32)]) -> Result<(), String> {
+        if self.subnets.len() > MAX_SUBNETS {
+            return Err(format!("At most {} extra subnets", MAX_SUBNETS));
+        }
+        for (i, subnet) in self.subnets.iter().enumerate() {
+            subnet.validate()?;
+            let link = subnet.link();
+            if link == wan || link == lan {
+                return Err(format!("{}: {} already carries the {}", subnet.name, link, if link == wan { "WAN" } else { "main LAN" }));
+            }
+            let net = subnet.router().unwrap_or((Ipv4Addr::UNSPECIFIED, 32));
+            if primary.iter().any(|p| overlaps(*p, net)) {
+                return Err(format!("{}: {} overlaps the main LAN", subnet.name, subnet.address));
+            }
+            for other in &self.subnets[..i] {
+                if other.name == subnet.name {
+                    return Err(format!("Two subnets are called {}", subnet.name));
+                }
+                if other.link() == link {
+                    return Err(format!("{} and {} are both on {}", other.name, subnet.name, link));
+                }
+                if other.router().is_some_and(|o| overlaps(o, net)) {
+                    return Err(format!("{} and {} overlap", other.name, subnet.name));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Name of the subnet `ip` is on; the main LAN's for anything not on an extra subnet
+    pub fn label(&self, ip: &str) -> &str {
+        let Ok(ip) = ip.parse::<Ipv4Addr>() else { return PRIMARY_NAME };
+        self.subnets.iter().find(|s| s.contains(ip)).map_or(PRIMARY_NAME, |s| s.name.as_str())
+    }
+}
+
+pub async fn load_settings(pool: &SqlitePool) -> SubnetSettings {
+    match crate::db::settings::get(pool, SETTINGS_KEY).await {
+        Ok(settings) => settings.unwrap_or_default(),
+        Err(e) => {
+            tracing::warn!("Failed to read LAN subnets: {}", e);
+            SubnetSettings::default()
+        }
+    }
+}
+
+pub async fn save_settings(pool: &SqlitePool, settings: &SubnetSettings) -> Result<(), String> {
+    crate::db::settings::set(pool, SETTINGS_KEY, settings).await.map_err(|e| e.to_string())
+}
+
+/// IPv4 subnets on the main LAN interface
+pub fn primary_subnets(lan: &str) -> Vec<(Ipv4Addr, u32)> {
+    crate::system::get_interfaces()
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|i| i.name == lan)
+        .filter_map(|i| i.ipv4)
+        .filter_map(|cidr| {
+            let (ip, prefix) = cidr.split_once('/')?;
+            Some((ip.parse().ok()?, prefix.parse::<u32>().ok().filter(|p| *p <= 32)?))
+        })
+        .collect()
+}
+
+// ============ STATUS ============
+
+#[derive(Debug, Serialize)]
+pub struct SubnetStatus {
+    #[serde(flatten)]
+    pub subnet: Subnet,
+    pub link: String,
+    pub up: bool,
+    pub pool: Option<PoolStatus>,
+}
+
+fn link_up(link: &str) -> bool {
+    std::fs::read_to_string(format!("/sys/class/net/{}/operstate", link))
+        .is_ok_and(|state| matches!(state.trim(), "up" | "unknown"))
+}
+
+pub fn status(settings: &SubnetSettings) -> Vec<SubnetStatus> {
+    settings
+        .subnets
+        .iter()
+        .map(|subnet| SubnetStatus {
+            link: subnet.link(),
+            up: link_up(&subnet.link()),
+            pool: subnet.dhcp.as_ref().and_then(|scope| {
+                let router = subnet.router()?;
+                pool_usage(&scope.range_start, &scope.range_end, &scope.lease_time, Some(router.0), &[router])
+            }),
+            subnet: subnet.clone(),
+        })
+        .collect()
+}
+
+// ============ DNSMASQ ============
+
+fn dnsmasq_config(subnets: &[Subnet]) -> String {
+    let mut config = String::from("# Extra LAN subnets - managed by RouterUI\n");
+    for subnet in subnets {
+        let (Some((router, _)), Some(netmask)) = (subnet.router(), subnet.netmask()) else { continue };
+        config.push_str(&format!("\n# {}\ninterface={}\n", subnet.name, subnet.link()));
+        if let Some(scope) = &subnet.dhcp {
+            // Tagged options win over the main LAN's untagged ones
+            config.push_str(&format!(
+                "dhcp-range=set:{},{},{},{},{}\n",
+                subnet.name, scope.range_start, scope.range_end, netmask, scope.lease_time
+            ));
+            config.push_str(&format!("dhcp-option=tag:{},option:router,{}\n", subnet.name, router));
+            let dns = scope.dns_server.clone().unwrap_or_else(|| router.to_string());
+            config.push_str(&format!("dhcp-option=tag:{},option:dns-server,{}\n", subnet.name, dns));
+        }
+        if let (Some(domain), Some(network)) = (&subnet.domain, subnet.network()) {
+            config.push_str(&format!("domain={},{}\nlocal=/{}/\n", domain, network, domain));
+        }
+    }
+    config
+}
+
+fn write_dnsmasq(subnets: &[Subnet]) -> Result<(), String> {
+    write_system_file(DNSMASQ_FILE, dnsmasq_config(subnets)).map_err(|e| e.to_string())?;
+    run(sudo().args(["systemctl", "reload", "dnsmasq"]))
+}
+
+// ============ LINKS ============
+
+fn run(command: &mut RecordedCommand) -> Result<(), String> {
+    let output = command.output().map_err(|e| e.to_string())?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(String::from_utf8_lossy(&output.stderr).trim().to_string())
+    }
+}
+
+fn link_exists(link: &str) -> bool {
+    std::path::Path::new("/sys/class/net").join(link).exists()
+}
+
+fn bring_up(subnet: &Subnet) -> Result<(), String> {
+    let link = subnet.link();
+    if let Some(id) = subnet.vlan_id {
+        if !link_exists(&link) {
+            let id = id.to_string();
+            run(sudo().args(["ip", "link", "add", "link", &subnet.interface, "name", &link, "type", "vlan", "id", &id]))?;
+        }
+    }
+    match run(sudo().args(["ip", "addr", "add", &subnet.address, "dev", &link])) {
+        // Already there from an earlier apply or from boot
+        Err(e) if !e.contains("File exists") => return Err(e),
+        _ => {}
+    }
+    run(sudo().args(["ip", "link", "set", &link, "up"]))
+}
+
+fn tear_down(subnet: &Subnet) {
+    let link = subnet.link();
+    if !link_exists(&link) {
+        return;
+    }
+    let result = if subnet.vlan_id.is_some() {
+        run(sudo().args(["ip", "link", "del", &link]))
+    } else {
+        run(sudo().args(["ip", "addr", "del", &subnet.address, "dev", &link]))
+    };
+    if let Err(e) = result {
+        tracing::warn!("Could not remove subnet {} from {}: {}", subnet.name, link, e);
+    }
+}
+
+// Brings the links back at boot; netplan when the system uses it, ifupdown otherwise
+fn persist(subnets: &[Subnet]) -> Result<(), String> {
+    if std::path::Path::new("/etc/netplan").exists() {
+        let (vlans, plain): (Vec<&Subnet>, Vec<&Subnet>) = subnets.iter().partition(|s| s.vlan_id.is_some());
+        let mut config = String::from("# Extra LAN subnets - managed by RouterUI\nnetwork:\n  version: 2\n");
+        if !plain.is_empty() {
+            config.push_str("  ethernets:\n");
+            for subnet in plain {
+                config.push_str(&format!("    {}:\n      addresses:\n        - {}\n", subnet.interface, subnet.address));
+            }
+        }
+        if !vlans.is_empty() {
+            config.push_str("  vlans:\n");
+            for subnet in vlans {
+                config.push_str(&format!(
+                    "    {}:\n      id: {}\n      link: {}\n      addresses:\n        - {}\n",
+                    subnet.link(),
+                    subnet.vlan_id.unwrap_or_default(),
+                    subnet.interface,
+                    subnet.address
+                ));
+            }
+        }
+        write_system_file(NETPLAN_FILE, config).map_err(|e| e.to_string())
+    } else {
+        let mut config = String::from("# Extra LAN subnets - managed by RouterUI\n");
+        for subnet in subnets {
+            let link = subnet.link();
+            config.push_str(&format!("\nauto {}\niface {} inet static\n    address {}\n", link, link, subnet.address));
+            if subnet.vlan_id.is_some() {
+                config.push_str(&format!("    vlan-raw-device {}\n", subnet.interface));
+            }
+        }
+        write_system_file(INTERFACES_FILE, config).map_err(|e| e.to_string())
+    }
+}
+
+// ============ FIREWALL ============
+
+// Rules for the router itself (INPUT) and for traffic between networks (FORWARD). Subnets that
+// aren't isolated are trusted like the main LAN, which the firewall's INPUT policy lets in by
+// interface; isolated ones only get DHCP, DNS and ping, and can't open connections to other LANs.
+fn chain_rules(wan: &str, subnets: &[Subnet]) -> (Vec<Vec<String>>, Vec<Vec<String>>) {
+    let rule = |args: &[&str]| args.iter().map(|a| a.to_string()).collect::<Vec<_>>();
+    let (mut input, mut forward) = (Vec::new(), Vec::new());
+    forward.push(rule(&["-m", "state", "--state", "ESTABLISHED,RELATED", "-j", "RETURN"]));
+
+    for subnet in subnets {
+        let link = subnet.link();
+        if !subnet.isolated {
+            input.push(rule(&["-i", &link, "-j", "ACCEPT"]));
+            continue;
+        }
+        input.push(rule(&["-i", &link, "-p", "udp", "--dport", "67", "-j", "ACCEPT"]));
+        input.push(rule(&["-i", &link, "-p", "udp", "--dport", "53", "-j", "ACCEPT"]));
+        input.push(rule(&["-i", &link, "-p", "tcp", "--dport", "53", "-j", "ACCEPT"]));
+        input.push(rule(&["-i", &link, "-p", "icmp", "-j", "ACCEPT"]));
+        input.push(rule(&["-i", &link, "-m", "state", "--state", "ESTABLISHED,RELATED", "-j", "ACCEPT"]));
+        input.push(rule(&["-i", &link, "-j", "DROP"]));
+        forward.push(rule(&["-i", &link, "!", "-o", wan, "-j", "DROP"]));
+        forward.push(rule(&["-o", &link, "!", "-i", wan, "-j", "DROP"]));
+    }
+    (input, forward)
+}
+
+fn chain_exists(chain: &str) -> bool {
+    sudo().args(["iptables", "-S", chain]).output().map(|o| o.status.success()).unwrap_or(false)
+}
+
+fn remove_chains() {
+    for (parent, chain) in [("INPUT", CHAIN), ("FORWARD", FORWARD_CHAIN)] {
+        while sudo().args(["iptables", "-D", parent, "-j", chain]).output().is_ok_and(|o| o.status.success()) {}
+        if chain_exists(chain) {
+            let _ = sudo().args(["iptables", "-F", chain]).output();
+            let _ = sudo().args(["iptables", "-X", chain]).output();
+        }
+    }
+}
+
+fn apply_firewall(wan: &str, subnets: &[Subnet]) -> Result<(), String> {
+    remove_chains();
+    if !subnets.is_empty() {
+        let (input, forward) = chain_rules(wan, subnets);
+        for (parent, chain, rules) in [("INPUT", CHAIN, input), ("FORWARD", FORWARD_CHAIN, forward)] {
+            run(sudo().args(["iptables", "-N", chain]))?;
+            for rule in &rules {
+                run(sudo().args(["iptables", "-A", chain]).args(rule))?;
+            }
+            run(sudo().args(["iptables", "-I", parent, "1", "-j", chain]))?;
+        }
+    }
+    run(sudo().args(["netfilter-persistent", "save"]))
+}
+
+// ============ APPLY ============
+
+/// Move the system from `old` to `new`: links and addresses, their boot configuration, DHCP
+/// scopes and firewall rules. Subnets that changed are torn down and brought up again.
+pub fn apply(wan: &str, old: &SubnetSettings, new: &SubnetSettings) -> Result<(), String> {
+    for subnet in old.subnets.iter().filter(|s| !new.subnets.contains(s)) {
+        tear_down(subnet);
+    }
+    for subnet in &new.subnets {
+        bring_up(subnet).map_err(|e| format!("Could not bring up {} on {}: {}", subnet.name, subnet.link(), e))?;
+    }
+    persist(&new.subnets)?;
+    write_dnsmasq(&new.subnets)?;
+    apply_firewall(wan, &new.subnets)
+}
+
+/// Remove every extra subnet (factory reset)
+pub fn teardown(settings: &SubnetSettings) -> Result<(), String> {
+    apply("", settings, &SubnetSettings::default())
+}
@@ -0,0 +1,608 @@
+// Forwarding to a remote syslog server (rsyslog, syslog-ng, Graylog...) for users who keep their
+// logs off the router. Three streams can be picked: the audit trail (sign-ins and every request
+// that changed something), security events (brute-force and honeypot bans, hijacked sessions,
+// approvals, factory resets) and the packets the firewall's LOG rules report as blocked.
+// Messages are RFC 5424, over UDP or over TCP with octet-counted framing (RFC 6587), and carry
+// their details as structured data so the receiver can index them without parsing the text.
+// Nothing is queued while the server is unreachable: the router is not a log store.
+
+use axum::{
+    extract::{ConnectInfo, FromRequestParts, Request},
+    http::Method,
+    middleware::Next,
+    response::Response,
+};
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use std::collections::HashSet;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpStream, UdpSocket};
+use tokio::sync::{broadcast, mpsc, Notify};
+
+use crate::api::AuthUser;
+use crate::events::Event;
+use crate::system::privileges::sudo;
+use crate::AppState;
+
+// Key in the settings table
+pub const SETTINGS_KEY: &str = "remote_syslog";
+const APP_NAME: &str = "routerui";
+// RFC 5424 wants an enterprise number on private SD-IDs; 32473 is the one reserved for examples
+const SD_ID: &str = "routerui@32473";
+// local0-local7
+const FACILITIES: std::ops::RangeInclusive<u8> = 16..=23;
+// Receivers must take 480 bytes and should take 2048 (RFC 5426); longer datagrams are cut
+const MAX_DATAGRAM: usize = 2048;
+const QUEUE_CAPACITY: usize = 1024;
+const POLL_INTERVAL: Duration = Duration::from_secs(15);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+// After a failure, messages are dropped rather than retried for this long
+const RETRY_DELAY: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Transport {
+    Udp,
+    Tcp,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RemoteSyslogSettings {
+    pub enabled: bool,
+    pub host: String,
+    pub port: u16,
+    pub transport: Transport,
+    pub facility: u8,
+    // Streams to forward
+    pub audit: bool,
+    pub security: bool,
+    pub netfilter: bool,
+}
+
+impl Default for RemoteSyslogSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            host: String::new(),
+            port: 514,
+            transport: Transport::Udp,
+            facility: 16,
+            audit: true,
+            security: true,
+            // Can be a lot of traffic on a WAN that gets scanned all day
+            netfilter: false,
+        }
+    }
+}
+
+impl RemoteSyslogSettings {
+    pub fn normalize(&mut self) {
+        self.host = self.host.trim().to_string();
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        let valid_host = !self.host.is_empty()
+            && self.host.len() <= 253
+            && self.host.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | ':'));
+        if self.enabled && !valid_host {
+            return Err("Syslog server must be a hostname or an IP address".to_string());
+        }
+        if !self.host.is_empty() && !valid_host {
+            return Err(format!("Invalid syslog server: {}", self.host));
+        }
+        if self.port == 0 {
+            return Err("Syslog port must be 1-65535".to_string());
+        }
+        if !FACILITIES.contains(&self.facility) {
+            return Err("Facility must be one of local0-local7".to_string());
+        }
+        Ok(())
+    }
+
+    fn forwards(&self, stream: Stream) -> bool {
+        self.enabled
+            && match stream {
+                Stream::Audit => self.audit,
+                Stream::Security => self.security,
+                Stream::Netfilter => self.netfilter,
+            }
+    }
+}
+
+pub async fn load_settings(pool: &SqlitePool) -> RemoteSyslogSettings {
+    match crate::db::settings::get(pool, SETTINGS_KEY).await {
+        Ok(settings) => settings.unwrap_or_default(),
+        Err(e) => {
+            tracing::warn!("Failed to read remote syslog settings, using defaults: {}", e);
+            RemoteSyslogSettings::default()
+        }
+    }
+}
+
+/// Saved settings apply to the next message
+pub async fn save_settings(pool: &SqlitePool, settings: &RemoteSyslogSettings) -> Result<(), String> {
+    crate::db::settings::set(pool, SETTINGS_KEY, settings).await.map_err(|e| e.to_string())?;
+    RELOAD.notify_one();
+    Ok(())
+}
+
+// ============ MESSAGES ============
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stream {
+    Audit,
+    Security,
+    Netfilter,
+}
+
+impl Stream {
+    fn as_str(self) -> &'static str {
+        match self {
+            Stream::Audit => "audit",
+            Stream::Security => "security",
+            Stream::Netfilter => "netfilter",
+        }
+    }
+
+    fn bit(self) -> u8 {
+        match self {
+            Stream::Audit => 1,
+            Stream::Security => 2,
+            Stream::Netfilter => 4,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Severity {
+    Warning = 4,
+    Notice = 5,
+    Info = 6,
+}
+
+#[derive(Debug, Clone)]
+pub struct Message {
+    pub stream: Stream,
+    pub severity: Severity,
+    pub timestamp: DateTime<Utc>,
+    // What happened, in a word: "request", "login", the event type...
+    pub msg_id: String,
+    pub params: Vec<(String, String)>,
+    pub text: String,
+}
+
+impl Message {
+    pub fn new(stream: Stream, severity: Severity, msg_id: &str, text: String) -> Self {
+        Message { stream, severity, timestamp: Utc::now(), msg_id: msg_id.to_string(), params: Vec::new(), text }
+    }
+
+    pub fn param(mut self, name: &str, value: impl ToString) -> Self {
+        self.params.push((name.to_string(), value.to_string()));
+        self
+    }
+}
+
+// PARAM-VALUE escapes '"', '\' and ']'
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '"' | '\\' | ']') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+// HOSTNAME, APP-NAME and MSGID are printable ASCII without spaces, "-" when unknown
+fn header_field(value: &str, max: usize) -> String {
+    let field: String = value.chars().filter(|c| c.is_ascii_graphic()).take(max).collect();
+    if field.is_empty() {
+        "-".to_string()
+    } else {
+        field
+    }
+}
+
+/// One RFC 5424 line, e.g.
+/// `<133>1 2026-10-17T06:21:58.106Z router routerui 812 request [routerui@32473 stream="audit" ...] ...`
+pub fn format(message: &Message, facility: u8, hostname: &str) -> String {
+    let pri = facility as u16 * 8 + message.severity as u16;
+    let mut data = format!("[{} stream=\"{}\"", SD_ID, message.stream.as_str());
+    for (name, value) in &message.params {
+        data.push_str(&format!(" {}=\"{}\"", name, escape(value)));
+    }
+    data.push(']');
+
+    format!(
+        "<{}>1 {} {} {} {} {} {} {}",
+        pri,
+        message.timestamp.to_rfc3339_opts(SecondsFormat::Millis, true),
+        header_field(hostname, 255),
+        APP_NAME,
+        std::process::id(),
+        header_field(&message.msg_id, 32),
+        data,
+        message.text
+    )
+}
+
+// ============ SENDING ============
+
+static QUEUE: OnceLock<mpsc::Sender<Message>> = OnceLock::new();
+// Streams being forwarded, so sources skip building messages nobody wants
+static STREAMS: AtomicU8 = AtomicU8::new(0);
+static RELOAD: Notify = Notify::const_new();
+static STATUS: Mutex<ForwardStatus> = Mutex::new(ForwardStatus { sent: 0, dropped: 0, last_sent_at: None, last_error: None });
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ForwardStatus {
+    pub sent: u64,
+    // Lost to a full queue or an unreachable server
+    pub dropped: u64,
+    pub last_sent_at: Option<String>,
+    pub last_error: Option<String>,
+}
+
+pub fn status() -> ForwardStatus {
+    STATUS.lock().unwrap().clone()
+}
+
+/// Whether messages of `stream` are forwarded right now
+pub fn wants(stream: Stream) -> bool {
+    STREAMS.load(Ordering::Relaxed) & stream.bit() != 0
+}
+
+/// Queue a message; dropped when its stream isn't forwarded or the forwarder is behind
+pub fn send(message: Message) {
+    if !wants(message.stream) {
+        return;
+    }
+    let Some(queue) = QUEUE.get() else { return };
+    if queue.try_send(message).is_err() {
+        STATUS.lock().unwrap().dropped += 1;
+    }
+}
+
+fn hostname() -> String {
+    std::fs::read_to_string("/etc/hostname")
+        .map(|h| h.trim().to_string())
+        .unwrap_or_else(|_| "router".to_string())
+}
+
+enum Link {
+    Udp(UdpSocket),
+    Tcp(TcpStream),
+}
+
+impl Link {
+    async fn open(settings: &RemoteSyslogSettings) -> Result<Link, String> {
+        let addr = tokio::net::lookup_host((settings.host.as_str(), settings.port))
+            .await
+            .map_err(|e| format!("{}: {}", settings.host, e))?
+            .next()
+            .ok_or_else(|| format!("{} has no address", settings.host))?;
+
+        match settings.transport {
+            Transport::Udp => {
+                let local = if addr.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" };
+                let socket = UdpSocket::bind(local).await.map_err(|e| e.to_string())?;
+                socket.connect(addr).await.map_err(|e| format!("{}: {}", addr, e))?;
+                Ok(Link::Udp(socket))
+            }
+            Transport::Tcp => match tokio::time::timeout(CONNECT_TIMEOUT, TcpStream::connect(addr)).await {
+                Ok(Ok(stream)) => Ok(Link::Tcp(stream)),
+                Ok(Err(e)) => Err(format!("{}: {}", addr, e)),
+                Err(_) => Err(format!("{}: connection timed out", addr)),
+            },
+        }
+    }
+
+    async fn write(&mut self, line: &str) -> Result<(), String> {
+        match self {
+            Link::Udp(socket) => {
+                let mut end = line.len().min(MAX_DATAGRAM);
+                while !line.is_char_boundary(end) {
+                    end -= 1;
+                }
+                socket.send(&line.as_bytes()[..end]).await.map(|_| ()).map_err(|e| e.to_string())
+            }
+            // Octet counting: the length, a space, then the message
+            Link::Tcp(stream) => {
+                let framed = format!("{} {}", line.len(), line);
+                stream.write_all(framed.as_bytes()).await.map_err(|e| e.to_string())
+            }
+        }
+    }
+}
+
+/// Send one message with `settings`, whether or not they are saved, and report what went wrong.
+/// Over UDP a server that isn't listening usually goes unnoticed.
+pub async fn send_test(settings: &RemoteSyslogSettings, username: &str) -> Result<(), String> {
+    let message = Message::new(
+        Stream::Audit,
+        Severity::Notice,
+        "test",
+        format!("Test message from RouterUI, sent by {}", username),
+    )
+    .param("user", username);
+    let line = format(&message, settings.facility, &hostname());
+    let mut link = Link::open(settings).await?;
+    link.write(&line).await?;
+    if let Link::Tcp(stream) = &mut link {
+        let _ = stream.shutdown().await;
+    }
+    Ok(())
+}
+
+struct Forwarder {
+    settings: RemoteSyslogSettings,
+    hostname: String,
+    link: Option<Link>,
+    retry_at: Option<Instant>,
+}
+
+impl Forwarder {
+    fn apply(&mut self, settings: RemoteSyslogSettings) {
+        let streams = [Stream::Audit, Stream::Security, Stream::Netfilter]
+            .into_iter()
+            .filter(|s| settings.forwards(*s))
+            .fold(0, |bits, s| bits | s.bit());
+        STREAMS.store(streams, Ordering::Relaxed);
+        self.settings = settings;
+        // The server or transport may have changed
+        self.link = None;
+        self.retry_at = None;
+    }
+
+    async fn forward(&mut self, message: Message) {
+        if !self.settings.forwards(message.stream) {
+            return;
+        }
+        if self.link.is_none() {
+            if self.retry_at.is_some_and(|at| Instant::now() < at) {
+                STATUS.lock().unwrap().dropped += 1;
+                return;
+            }
+            match Link::open(&self.settings).await {
+                Ok(link) => self.link = Some(link),
+                Err(e) => return self.failed(e),
+            }
+        }
+
+        let line = format(&message, self.settings.facility, &self.hostname);
+        let Some(link) = self.link.as_mut() else { return };
+        match link.write(&line).await {
+            Ok(()) => {
+                let mut status = STATUS.lock().unwrap();
+                status.sent += 1;
+                status.last_sent_at = Some(Utc::now().to_rfc3339());
+                status.last_error = None;
+            }
+            Err(e) => self.failed(e),
+        }
+    }
+
+    fn failed(&mut self, error: String) {
+        let mut status = STATUS.lock().unwrap();
+        if status.last_error.is_none() {
+            tracing::warn!("Remote syslog to {}: {}", self.settings.host, error);
+        }
+        status.dropped += 1;
+        status.last_error = Some(error);
+        self.link = None;
+        self.retry_at = Some(Instant::now() + RETRY_DELAY);
+    }
+}
+
+// ============ SOURCES ============
+
+/// Route layer putting every request that changes something on the audit stream: who, from
+/// where, what and how it went. Request bodies are never included.
+pub async fn audit_requests(request: Request, next: Next) -> Response {
+    if matches!(*request.method(), Method::GET | Method::HEAD | Method::OPTIONS) || !wants(Stream::Audit) {
+        return next.run(request).await;
+    }
+
+    let (mut parts, body) = request.into_parts();
+    let username = AuthUser::from_request_parts(&mut parts, &()).await.ok().map(|AuthUser(user)| user.username);
+    let ip = parts.extensions.get::<ConnectInfo<SocketAddr>>().map(|ConnectInfo(peer)| peer.ip().to_canonical().to_string());
+    let request_id = parts.extensions.get::<crate::logging::RequestId>().map(|id| id.0.clone());
+    let method = parts.method.to_string();
+    let path = parts.uri.path().to_string();
+
+    let response = next.run(Request::from_parts(parts, body)).await;
+
+    let status = response.status();
+    let severity = if status.is_client_error() || status.is_server_error() { Severity::Warning } else { Severity::Notice };
+    let who = username.clone().unwrap_or_else(|| "anonymous".to_string());
+    let mut message = Message::new(
+        Stream::Audit,
+        severity,
+        "request",
+        format!("{} {} {}: {}", who, method, path, status.as_u16()),
+    )
+    .param("user", &who)
+    .param("method", &method)
+    .param("path", &path)
+    .param("status", status.as_u16());
+    if let Some(ip) = ip {
+        message = message.param("ip", ip);
+    }
+    if let Some(id) = request_id {
+        message = message.param("request_id", id);
+    }
+    send(message);
+    response
+}
+
+fn scalar(value: &serde_json::Value) -> Option<String> {
+    match value {
+        serde_json::Value::String(s) => Some(s.clone()),
+        serde_json::Value::Number(n) => Some(n.to_string()),
+        serde_json::Value::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+fn security_message(event: &Event) -> Option<Message> {
+    let (severity, text) = match event {
+        Event::BruteForceBlocked { ip, failures, .. } => {
+            (Severity::Warning, format!("{} blocked after {} failed sign-ins", ip, failures))
+        }
+        Event::SessionIpMismatch { username, original_ip, ip, .. } => (
+            Severity::Warning,
+            format!("Session of {} used from {} instead of {}, and ended", username, ip, original_ip),
+        ),
+        Event::HoneypotBanned { ip, .. } => (Severity::Warning, format!("{} touched a decoy port and was banned", ip)),
+        Event::ApprovalRequested { summary, requested_by, .. } => {
+            (Severity::Notice, format!("{} asked for approval: {}", requested_by, summary))
+        }
+        Event::FactoryReset { removed, errors } => (
+            Severity::Warning,
+            format!("Factory reset: {} parts removed, {} could not be", removed, errors.len()),
+        ),
+        _ => return None,
+    };
+
+    // The event's own fields become the structured data
+    let fields = serde_json::to_value(event).ok()?;
+    let fields = fields.as_object()?;
+    let msg_id = fields.get("type").and_then(|t| t.as_str()).unwrap_or("event");
+    let mut message = Message::new(Stream::Security, severity, msg_id, text);
+    for (name, value) in fields.iter().filter(|(name, _)| *name != "type") {
+        if let Some(value) = scalar(value) {
+            message = message.param(name, value);
+        }
+    }
+    Some(message)
+}
+
+fn field<'a>(line: &'a str, name: &str) -> Option<&'a str> {
+    line.split_whitespace().find_map(|p| p.strip_prefix(name)).filter(|v| !v.is_empty())
+}
+
+// journalctl short-iso: "2026-01-18T10:30:00+0000 router kernel: BLOCKED:honeypot: IN=enp1s0 ..."
+fn netfilter_message(line: &str) -> Option<Message> {
+    let start = line.find("BLOCKED:")?;
+    let reason = line[start + 8..].split(':').next().unwrap_or("firewall");
+    let timestamp = DateTime::parse_from_str(line.split_whitespace().next()?, "%Y-%m-%dT%H:%M:%S%z").ok()?;
+
+    let proto = field(line, "PROTO=").unwrap_or("?").to_lowercase();
+    let src = field(line, "SRC=")?;
+    let dst = field(line, "DST=").unwrap_or("?");
+    let endpoint = |addr: &str, port: Option<&str>| match port {
+        Some(port) => format!("{}:{}", addr, port),
+        None => addr.to_string(),
+    };
+    let text = format!(
+        "Blocked {} {} -> {} ({})",
+        proto,
+        endpoint(src, field(line, "SPT=")),
+        endpoint(dst, field(line, "DPT=")),
+        reason
+    );
+
+    let mut message = Message::new(Stream::Netfilter, Severity::Notice, "blocked", text).param("reason", reason);
+    message.timestamp = timestamp.with_timezone(&Utc);
+    for (name, key) in [("in", "IN="), ("src", "SRC="), ("dst", "DST="), ("proto", "PROTO="), ("spt", "SPT="), ("dpt", "DPT=")] {
+        if let Some(value) = field(line, key) {
+            message = message.param(name, value);
+        }
+    }
+    Some(message)
+}
+
+// Where the last kernel log read stopped. journalctl timestamps have whole seconds, so the lines
+// already sent from the last second are remembered as well.
+struct KernelCursor {
+    last: DateTime<Utc>,
+    seen: HashSet<String>,
+}
+
+impl KernelCursor {
+    fn starting_now() -> Self {
+        KernelCursor { last: Utc::now(), seen: HashSet::new() }
+    }
+
+    fn take_new(&mut self, log: &str) -> Vec<Message> {
+        let mut messages = Vec::new();
+        for line in log.lines() {
+            let Some(message) = netfilter_message(line) else { continue };
+            if message.timestamp < self.last || (message.timestamp == self.last && self.seen.contains(line)) {
+                continue;
+            }
+            if message.timestamp > self.last {
+                self.last = message.timestamp;
+                self.seen.clear();
+            }
+            self.seen.insert(line.to_string());
+            messages.push(message);
+        }
+        messages
+    }
+}
+
+fn read_kernel_log() -> String {
+    sudo()
+        .args(["journalctl", "-k", "--since", "1 hours ago", "--no-pager", "-o", "short-iso"])
+        .output()
+        .map(|o| String::from_utf8_lossy(&o.stdout).into_owned())
+        .unwrap_or_default()
+}
+
+pub fn spawn(state: Arc<AppState>) {
+    let (tx, mut rx) = mpsc::channel(QUEUE_CAPACITY);
+    if QUEUE.set(tx).is_err() {
+        return;
+    }
+    let mut events = state.events.subscribe();
+
+    tokio::spawn(async move {
+        let mut forwarder = Forwarder { settings: RemoteSyslogSettings::default(), hostname: hostname(), link: None, retry_at: None };
+        forwarder.apply(load_settings(&state.db).await);
+        let mut kernel: Option<KernelCursor> = None;
+        let mut poll = tokio::time::interval(POLL_INTERVAL);
+
+        loop {
+            tokio::select! {
+                Some(message) = rx.recv() => forwarder.forward(message).await,
+                event = events.recv() => match event {
+                    Ok(event) => {
+                        if let Some(message) = security_message(&event) {
+                            forwarder.forward(message).await;
+                        }
+                    }
+                    Err(broadcast::error::RecvError::Lagged(missed)) => {
+                        tracing::warn!("Remote syslog missed {} events", missed);
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return,
+                },
+                _ = RELOAD.notified() => forwarder.apply(load_settings(&state.db).await),
+                _ = poll.tick() => {
+                    state.tasks.beat("remote_syslog", POLL_INTERVAL);
+                    // Blocked packets are only logged when the privacy profile allows it anyway
+                    if !forwarder.settings.forwards(Stream::Netfilter) || !crate::privacy::policy().blocked_traffic_log {
+                        kernel = None;
+                        continue;
+                    }
+                    // Start from when forwarding was switched on, not from the hour journalctl returns
+                    let Some(cursor) = kernel.as_mut() else {
+                        kernel = Some(KernelCursor::starting_now());
+                        continue;
+                    };
+                    let log = tokio::task::spawn_blocking(read_kernel_log).await.unwrap_or_default();
+                    for message in cursor.take_new(&log) {
+                        forwarder.forward(message).await;
+                    }
+                }
+            }
+        }
+    });
+}
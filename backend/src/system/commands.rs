@@ -0,0 +1,218 @@
+// Command history: every privileged command RouterUI ran recently, with its exit code, how long
+// it took and the API request that caused it, so what the UI did can be checked and repeated by
+// hand. Commands built with sudo() are recorded when they run. stdin is never kept, and the
+// few arguments that carry secrets or private text (the APN password in mmcli --simple-connect,
+// the text of an outgoing SMS) are masked first; see redact(). The history is in memory only
+// and bounded by count and age.
+
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::ffi::OsStr;
+use std::io;
+use std::process::{Child, Command, ExitStatus, Output, Stdio};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+pub const MAX_ENTRIES: usize = 1000;
+pub const MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+const REDACTED: &str = "********";
+// --simple-connect settings whose values are masked
+const SECRET_SETTINGS: &[&str] = &["password"];
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CommandRecord {
+    pub id: u64,
+    pub started_at: String,
+    pub command: String,
+    pub args: Vec<String>,
+    // None when it couldn't be started, was killed by a signal or was left running
+    pub exit_code: Option<i32>,
+    pub error: Option<String>,
+    pub duration_ms: u64,
+    // The API request that ran it; None for background tasks
+    pub request_id: Option<String>,
+    // "POST /api/firewall/port-forwards"
+    pub request: Option<String>,
+}
+
+struct History {
+    next_id: u64,
+    entries: VecDeque<(Instant, CommandRecord)>,
+}
+
+static HISTORY: Mutex<History> = Mutex::new(History { next_id: 1, entries: VecDeque::new() });
+
+fn prune(entries: &mut VecDeque<(Instant, CommandRecord)>) {
+    while entries.len() > MAX_ENTRIES || entries.front().is_some_and(|(at, _)| at.elapsed() > MAX_AGE) {
+        entries.pop_front();
+    }
+}
+
+/// Recorded commands, newest first
+pub fn history() -> Vec<CommandRecord> {
+    let mut history = HISTORY.lock().unwrap();
+    prune(&mut history.entries);
+    history.entries.iter().rev().map(|(_, record)| record.clone()).collect()
+}
+
+/// `arg` as it may be shown or logged: the mmcli connect password and SMS text are masked
+pub fn redact(arg: &str) -> String {
+    if let Some(settings) = arg.strip_prefix("--simple-connect=") {
+        let settings: Vec<String> = settings
+            .split(',')
+            .map(|setting| match setting.split_once('=') {
+                Some((key, _)) if SECRET_SETTINGS.contains(&key) => format!("{}={}", key, REDACTED),
+                _ => setting.to_string(),
+            })
+            .collect();
+        return format!("--simple-connect={}", settings.join(","));
+    }
+    // number='...',text='...'; the text may itself contain commas, so everything after text= goes
+    if arg.starts_with("--messaging-create-sms=") {
+        if let Some(at) = arg.find(",text=") {
+            return format!("{},text='{}'", &arg[..at], REDACTED);
+        }
+    }
+    arg.to_string()
+}
+
+/// A privileged command that is added to the history when it runs. Mirrors the parts of
+/// std::process::Command the callers use.
+pub struct RecordedCommand {
+    inner: Command,
+    // What was asked for, without the sudo or helper prefix
+    args: Vec<String>,
+    request_id: Option<String>,
+    request: Option<String>,
+}
+
+impl RecordedCommand {
+    pub fn new(inner: Command) -> Self {
+        RecordedCommand {
+            inner,
+            args: Vec::new(),
+            request_id: crate::logging::current_request_id(),
+            request: crate::logging::current_request_line(),
+        }
+    }
+
+    pub fn arg<S: AsRef<OsStr>>(&mut self, arg: S) -> &mut Self {
+        self.args.push(redact(&arg.as_ref().to_string_lossy()));
+        self.inner.arg(arg);
+        self
+    }
+
+    pub fn args<I, S>(&mut self, args: I) -> &mut Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        for arg in args {
+            self.arg(arg);
+        }
+        self
+    }
+
+    pub fn env<K: AsRef<OsStr>, V: AsRef<OsStr>>(&mut self, key: K, value: V) -> &mut Self {
+        self.inner.env(key, value);
+        self
+    }
+
+    pub fn stdin<T: Into<Stdio>>(&mut self, cfg: T) -> &mut Self {
+        self.inner.stdin(cfg);
+        self
+    }
+
+    pub fn stdout<T: Into<Stdio>>(&mut self, cfg: T) -> &mut Self {
+        self.inner.stdout(cfg);
+        self
+    }
+
+    pub fn stderr<T: Into<Stdio>>(&mut self, cfg: T) -> &mut Self {
+        self.inner.stderr(cfg);
+        self
+    }
+
+    pub fn output(&mut self) -> io::Result<Output> {
+        let started = (chrono::Utc::now(), Instant::now());
+        let result = self.inner.output();
+        self.record(started, result.as_ref().map(|o| o.status));
+        result
+    }
+
+    pub fn status(&mut self) -> io::Result<ExitStatus> {
+        let started = (chrono::Utc::now(), Instant::now());
+        let result = self.inner.status();
+        self.record(started, result.as_ref().copied());
+        result
+    }
+
+    // Long-running children are recorded as started; their exit isn't tracked
+    pub fn spawn(&mut self) -> io::Result<Child> {
+        let started = (chrono::Utc::now(), Instant::now());
+        let result = self.inner.spawn();
+        let error = result.as_ref().err().map(|e| e.to_string());
+        self.push(started, None, error);
+        result
+    }
+
+    fn record(&self, started: (chrono::DateTime<chrono::Utc>, Instant), result: Result<ExitStatus, &io::Error>) {
+        match result {
+            Ok(status) => self.push(started, status.code(), None),
+            Err(e) => self.push(started, None, Some(e.to_string())),
+        }
+    }
+
+    fn push(&self, (started_at, started): (chrono::DateTime<chrono::Utc>, Instant), exit_code: Option<i32>, error: Option<String>) {
+        let (command, args) = match self.args.split_first() {
+            Some((command, args)) => (command.clone(), args.to_vec()),
+            None => (String::new(), Vec::new()),
+        };
+        let mut history = HISTORY.lock().unwrap();
+        let id = history.next_id;
+        history.next_id += 1;
+        history.entries.push_back((
+            started,
+            CommandRecord {
+                id,
+                started_at: started_at.to_rfc3339(),
+                command,
+                args,
+                exit_code,
+                error,
+                duration_ms: started.elapsed().as_millis() as u64,
+                request_id: self.request_id.clone(),
+                request: self.request.clone(),
+            },
+        ));
+        prune(&mut history.entries);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redacts_connect_password() {
+        assert_eq!(
+            redact("--simple-connect=apn=internet,user=me,password=hunter2,ip-type=ipv4"),
+            "--simple-connect=apn=internet,user=me,password=********,ip-type=ipv4"
+        );
+        assert_eq!(redact("--simple-connect=apn=internet"), "--simple-connect=apn=internet");
+    }
+
+    #[test]
+    fn redacts_sms_text() {
+        assert_eq!(
+            redact("--messaging-create-sms=number='+15551234',text='code 1234, thanks'"),
+            "--messaging-create-sms=number='+15551234',text='********'"
+        );
+    }
+
+    #[test]
+    fn leaves_other_arguments() {
+        assert_eq!(redact("-m"), "-m");
+        assert_eq!(redact("--signal-setup=30"), "--signal-setup=30");
+    }
+}
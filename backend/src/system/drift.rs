@@ -0,0 +1,214 @@
+// Drift detection for the dnsmasq and hostapd files RouterUI writes. Every write through
+// write_system_file leaves a snapshot of what was written; when the file on disk no longer
+// matches it, someone edited it by hand, RouterUI's view of it is stale, and its next write
+// would throw their change away. The admin then either adopts the edit (it becomes the new
+// baseline) or overwrites it with RouterUI's version.
+//
+// Snapshots live in the managed_files table. Writes happen in synchronous code, so the table is
+// read into memory at startup and changes are queued to a task that writes them back.
+
+use chrono::Utc;
+use serde::Serialize;
+use sqlx::SqlitePool;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::{Mutex, OnceLock};
+use tokio::sync::mpsc;
+
+// Files (exact) and directories (trailing slash) whose writes are tracked
+const TRACKED: &[&str] = &["/etc/dnsmasq.conf", "/etc/dnsmasq.d/", "/etc/hostapd/"];
+// Lines of unchanged context around each change in a diff
+const DIFF_CONTEXT: usize = 3;
+
+// Snapshots by path, as stored in managed_files
+static SNAPSHOTS: Mutex<BTreeMap<String, Snapshot>> = Mutex::new(BTreeMap::new());
+static QUEUE: OnceLock<mpsc::UnboundedSender<Change>> = OnceLock::new();
+
+#[derive(Debug, Clone)]
+struct Snapshot {
+    sha256: String,
+    written_at: String,
+    content: String,
+}
+
+impl Snapshot {
+    fn of(content: &[u8]) -> Self {
+        Snapshot {
+            sha256: sha256(content),
+            written_at: Utc::now().to_rfc3339(),
+            content: String::from_utf8_lossy(content).into_owned(),
+        }
+    }
+}
+
+enum Change {
+    Put(String, Snapshot),
+    Remove(String),
+}
+
+/// A tracked file that no longer holds what RouterUI last wrote
+#[derive(Debug, Clone, Serialize)]
+pub struct Drift {
+    pub path: String,
+    // When RouterUI last wrote it
+    pub written_at: String,
+    pub deleted: bool,
+    // Unified diff from RouterUI's version to the one on disk
+    pub diff: String,
+}
+
+pub fn is_tracked(path: &Path) -> bool {
+    let path = path.to_string_lossy();
+    TRACKED.iter().any(|t| if t.ends_with('/') { path.starts_with(t) } else { path == *t })
+}
+
+fn sha256(content: &[u8]) -> String {
+    hex::encode(ring::digest::digest(&ring::digest::SHA256, content))
+}
+
+async fn store(pool: &SqlitePool, change: Change) -> Result<(), sqlx::Error> {
+    match change {
+        Change::Put(path, snapshot) => {
+            sqlx::query(
+                "INSERT INTO managed_files (path, sha256, written_at, content) VALUES (?, ?, ?, ?)
+                 ON CONFLICT(path) DO UPDATE SET sha256 = excluded.sha256, written_at = excluded.written_at, content = excluded.content",
+            )
+            .bind(path)
+            .bind(snapshot.sha256)
+            .bind(snapshot.written_at)
+            .bind(snapshot.content)
+            .execute(pool)
+            .await?;
+        }
+        Change::Remove(path) => {
+            sqlx::query("DELETE FROM managed_files WHERE path = ?").bind(path).execute(pool).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Read the snapshots and start writing changes back; runs before anything writes a system file
+pub async fn init(pool: &SqlitePool) -> Result<(), sqlx::Error> {
+    let rows: Vec<(String, Snapshot)> = sqlx::query_as::<_, (String, String, String, String)>(
+        "SELECT path, sha256, written_at, content FROM managed_files",
+    )
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .map(|(path, sha256, written_at, content)| (path, Snapshot { sha256, written_at, content }))
+    .collect();
+    SNAPSHOTS.lock().unwrap().extend(rows);
+
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    if QUEUE.set(tx).is_err() {
+        return Ok(());
+    }
+    let pool = pool.clone();
+    tokio::spawn(async move {
+        while let Some(change) = rx.recv().await {
+            if let Err(e) = store(&pool, change).await {
+                tracing::warn!("Could not save a managed file snapshot: {}", e);
+            }
+        }
+    });
+    Ok(())
+}
+
+fn queue(change: Change) {
+    if let Some(queue) = QUEUE.get() {
+        let _ = queue.send(change);
+    }
+}
+
+/// Remember what was just written to `path` (called by write_system_file)
+pub fn record(path: &Path, content: &[u8]) {
+    if !is_tracked(path) {
+        return;
+    }
+    let path = path.to_string_lossy().into_owned();
+    let snapshot = Snapshot::of(content);
+    SNAPSHOTS.lock().unwrap().insert(path.clone(), snapshot.clone());
+    queue(Change::Put(path, snapshot));
+}
+
+fn compare(path: &str, snapshot: &Snapshot) -> Option<Drift> {
+    let current = std::fs::read(path).ok();
+    if current.as_deref().map(sha256).as_ref() == Some(&snapshot.sha256) {
+        return None;
+    }
+    let current = current.map(|c| String::from_utf8_lossy(&c).into_owned());
+    let diff = similar::TextDiff::from_lines(snapshot.content.as_str(), current.as_deref().unwrap_or(""))
+        .unified_diff()
+        .context_radius(DIFF_CONTEXT)
+        .header("written by RouterUI", "on disk")
+        .to_string();
+    Some(Drift {
+        path: path.to_string(),
+        written_at: snapshot.written_at.clone(),
+        deleted: current.is_none(),
+        diff,
+    })
+}
+
+/// `path` if it was changed since RouterUI last wrote it
+pub fn check(path: &Path) -> Option<Drift> {
+    let key = path.to_string_lossy();
+    let snapshot = SNAPSHOTS.lock().unwrap().get(key.as_ref())?.clone();
+    compare(&key, &snapshot)
+}
+
+/// Every tracked file changed outside RouterUI
+pub fn all() -> Vec<Drift> {
+    let snapshots = SNAPSHOTS.lock().unwrap().clone();
+    snapshots.iter().filter_map(|(path, snapshot)| compare(path, snapshot)).collect()
+}
+
+/// Take the file as it is now as the new baseline; a deleted file is no longer tracked
+pub fn adopt(path: &str) -> Result<(), String> {
+    let mut snapshots = SNAPSHOTS.lock().unwrap();
+    if !snapshots.contains_key(path) {
+        return Err(format!("{} is not a file RouterUI manages", path));
+    }
+    let change = match std::fs::read(path) {
+        Ok(content) => {
+            let snapshot = Snapshot::of(&content);
+            snapshots.insert(path.to_string(), snapshot.clone());
+            Change::Put(path.to_string(), snapshot)
+        }
+        Err(_) => {
+            snapshots.remove(path);
+            Change::Remove(path.to_string())
+        }
+    };
+    queue(change);
+    Ok(())
+}
+
+/// Put RouterUI's version back and reload the service that reads the file
+pub fn overwrite(path: &str) -> Result<(), String> {
+    let snapshot = SNAPSHOTS
+        .lock()
+        .unwrap()
+        .get(path)
+        .cloned()
+        .ok_or_else(|| format!("{} is not a file RouterUI manages", path))?;
+    super::privileges::write_system_file(path, &snapshot.content).map_err(|e| e.to_string())?;
+    reload_for(path)
+}
+
+fn reload_for(path: &str) -> Result<(), String> {
+    let mut command = super::privileges::sudo();
+    if let Some(name) = path.strip_prefix("/etc/hostapd/").and_then(|f| f.strip_suffix(".conf")) {
+        // hostapd has no reload for most settings; see network::radio_named for the unit names
+        let unit = if name == "hostapd" { "hostapd".to_string() } else { format!("hostapd@{}", name) };
+        command.args(["systemctl", "restart", &unit]);
+    } else {
+        command.args(["systemctl", "reload", "dnsmasq"]);
+    }
+    let output = command.output().map_err(|e| e.to_string())?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(String::from_utf8_lossy(&output.stderr).trim().to_string())
+    }
+}
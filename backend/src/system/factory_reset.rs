@@ -0,0 +1,155 @@
+// Return RouterUI to a just-installed state without reinstalling: its settings files, feature
+// dnsmasq snippets, firewall additions and database contents go, one admin account is kept so
+// the box never ends up without an owner, and the setup wizard runs again.
+
+use serde::Serialize;
+use sqlx::SqlitePool;
+
+use crate::system::privileges::{sudo, write_system_file};
+
+/// Has to be typed out by the admin
+pub const CONFIRM_PHRASE: &str = "FACTORY RESET";
+
+// RouterUI's own settings are the JSON files at the top of this directory
+const SETTINGS_DIR: &str = "/opt/routerui";
+// Generated state under the data directory
+const DATA_SUBDIRS: &[&str] = &["ssh", "certs", "acme", "connection-log"];
+// setup_config key naming the admin kept through the reset; the wizard's admin step
+// replaces that account instead of refusing because an admin exists
+pub const KEPT_ADMIN_KEY: &str = "factory_reset_admin";
+
+#[derive(Debug, Default, Serialize)]
+pub struct ResetReport {
+    pub removed: Vec<String>,
+    pub errors: Vec<String>,
+}
+
+impl ResetReport {
+    fn record(&mut self, what: impl Into<String>, result: Result<(), String>) {
+        match result {
+            Ok(()) => self.removed.push(what.into()),
+            Err(e) => self.errors.push(format!("{}: {}", what.into(), e)),
+        }
+    }
+}
+
+fn remove_settings_files(report: &mut ResetReport) {
+    let entries = match std::fs::read_dir(SETTINGS_DIR) {
+        Ok(entries) => entries,
+        Err(e) => return report.errors.push(format!("{}: {}", SETTINGS_DIR, e)),
+    };
+    for path in entries.flatten().map(|e| e.path()) {
+        if path.is_file() && path.extension().is_some_and(|e| e == "json") {
+            report.record(path.display().to_string(), std::fs::remove_file(&path).map_err(|e| e.to_string()));
+        }
+    }
+}
+
+fn remove_generated_state(report: &mut ResetReport) {
+    for dir in DATA_SUBDIRS.iter().map(|d| crate::db::data_dir().join(d)) {
+        match std::fs::remove_dir_all(&dir) {
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            result => report.record(dir.display().to_string(), result.map_err(|e| e.to_string())),
+        }
+    }
+}
+
+// Emptied rather than deleted, since the helper may only write them. router.conf (LAN address
+// and DHCP range) stays so clients keep reaching the box until the wizard rewrites it.
+fn clear_dnsmasq_snippets(report: &mut ResetReport) {
+    use crate::api::network::{DHCP_OPTIONS_FILE, DNSMASQ_STATIC, DNS_REBIND_FILE, LOCAL_DNS_FILE};
+
+    for path in [DNSMASQ_STATIC, LOCAL_DNS_FILE, DHCP_OPTIONS_FILE, DNS_REBIND_FILE, crate::safesearch::DNSMASQ_FILE] {
+        if std::path::Path::new(path).exists() {
+            report.record(path, write_system_file(path, "").map_err(|e| e.to_string()));
+        }
+    }
+    let reload = sudo()
+        .args(["systemctl", "reload", "dnsmasq"])
+        .output()
+        .map_err(|e| e.to_string())
+        .and_then(|o| if o.status.success() { Ok(()) } else { Err(String::from_utf8_lossy(&o.stderr).trim().to_string()) });
+    if let Err(e) = reload {
+        report.errors.push(format!("dnsmasq reload: {}", e));
+    }
+}
+
+fn clear_firewall(report: &mut ResetReport, protection_sets: Vec<String>) {
+    let protection = crate::api::protection::teardown(protection_sets);
+    report.record("Protection blocklists and country blocks", if protection.is_empty() { Ok(()) } else { Err(protection.join(", ")) });
+    report.record("Login brute-force blocks", crate::auth::bruteforce::teardown());
+    report.record("Honeypot ports", crate::honeypot::teardown());
+    report.record("DNS bypass blocking", crate::dnsguard::teardown());
+    report.record("Port forwards", crate::api::firewall::teardown().map_err(|(_, e)| e));
+}
+
+/// Empty every table except the kept admin's row in `users` (and the migration history), and mark
+/// setup as not done
+async fn clear_database(pool: &SqlitePool, keep_user_id: i64) -> Result<(), sqlx::Error> {
+    let tables: Vec<String> = sqlx::query_scalar(
+        "SELECT name FROM sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%' AND name NOT IN ('users', '_sqlx_migrations')",
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let username: String = sqlx::query_scalar("SELECT username FROM users WHERE id = ?")
+        .bind(keep_user_id)
+        .fetch_one(pool)
+        .await?;
+
+    let mut tx = pool.begin().await?;
+    for table in &tables {
+        sqlx::query(&format!("DELETE FROM {}", table)).execute(&mut *tx).await?;
+    }
+    sqlx::query("DELETE FROM users WHERE id != ?").bind(keep_user_id).execute(&mut *tx).await?;
+    sqlx::query("CREATE TABLE IF NOT EXISTS setup_config (key TEXT PRIMARY KEY, value TEXT NOT NULL)")
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query("INSERT INTO setup_config (key, value) VALUES (?, ?)")
+        .bind(KEPT_ADMIN_KEY)
+        .bind(&username)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+
+    sqlx::query("VACUUM").execute(pool).await?;
+    Ok(())
+}
+
+/// Run the reset, keeping user `keep_user_id` (who must be an admin). Each part is attempted
+/// even when an earlier one fails; the database goes last so a failure there leaves setup
+/// marked complete and the remaining parts can be retried.
+pub async fn run(pool: &SqlitePool, keep_user_id: i64) -> ResetReport {
+    let subnets = crate::subnets::load_settings(pool).await;
+    let protection_sets = crate::api::protection::list_sets(pool, false).await.unwrap_or_default();
+    let shaped = crate::qos::load_settings(pool).await.enabled;
+    let wan = crate::wan::wan_interface(pool).await;
+    let mut report = tokio::task::spawn_blocking(move || {
+        let mut report = ResetReport::default();
+        if !subnets.subnets.is_empty() {
+            report.record("LAN subnets", crate::subnets::teardown(&subnets));
+        }
+        if shaped {
+            crate::qos::remove(&wan);
+            report.record("Traffic shaping", Ok(()));
+        }
+        clear_firewall(&mut report, protection_sets);
+        clear_dnsmasq_snippets(&mut report);
+        remove_settings_files(&mut report);
+        remove_generated_state(&mut report);
+        report
+    })
+    .await
+    .unwrap_or_else(|e| ResetReport { errors: vec![e.to_string()], ..Default::default() });
+
+    let database = clear_database(pool, keep_user_id).await.map_err(|e| e.to_string());
+    report.record("Database", database);
+
+    tracing::warn!(
+        "Factory reset: {} parts removed, {} errors{}",
+        report.removed.len(),
+        report.errors.len(),
+        if report.errors.is_empty() { String::new() } else { format!(" ({})", report.errors.join("; ")) }
+    );
+    report
+}
@@ -0,0 +1,328 @@
+// IPv6 posture audit. NAT keeps IPv4 LAN hosts unreachable more or less by accident, but every
+// device gets a globally routable IPv6 address, so inbound traffic has to be denied on purpose.
+// Each check reports what it found and can be fixed on its own.
+
+use serde::Serialize;
+
+use super::preflight::CheckStatus;
+use super::commands::RecordedCommand;
+use super::privileges::{sudo, write_system_file};
+
+// Drops new inbound connections from the WAN, jumped to from INPUT and FORWARD
+const WAN_CHAIN: &str = "ROUTERUI-V6-WAN";
+// Drops router advertisements and redirects arriving from LAN clients
+const RA_GUARD_CHAIN: &str = "ROUTERUI-V6-RAGUARD";
+// Addresses blocked from the firewall page, jumped to first from INPUT and FORWARD
+pub const BLOCK_CHAIN: &str = "ROUTERUI-V6-BLOCK";
+// Inbound openings to LAN addresses, consulted by WAN_CHAIN before it drops
+pub const PINHOLE_CHAIN: &str = "ROUTERUI-V6-PINHOLE";
+const SYSCTL_FILE: &str = "/etc/sysctl.d/98-routerui-ipv6.conf";
+
+pub const CHECK_IDS: &[&str] = &["inbound", "ra_guard", "privacy"];
+
+#[derive(Debug, Serialize)]
+pub struct PostureCheck {
+    pub id: String,
+    pub name: String,
+    pub status: CheckStatus,
+    pub detail: String,
+    pub fixable: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PostureReport {
+    pub wan_interface: String,
+    pub lan_interface: String,
+    pub ipv6_enabled: bool,
+    pub checks: Vec<PostureCheck>,
+}
+
+fn check(id: &str, name: &str, status: CheckStatus, detail: impl Into<String>) -> PostureCheck {
+    PostureCheck {
+        id: id.to_string(),
+        name: name.to_string(),
+        status,
+        detail: detail.into(),
+        fixable: status != CheckStatus::Pass,
+    }
+}
+
+fn sysctl_path(iface: &str, key: &str) -> String {
+    format!("/proc/sys/net/ipv6/conf/{}/{}", iface, key)
+}
+
+fn sysctl(iface: &str, key: &str) -> Option<String> {
+    std::fs::read_to_string(sysctl_path(iface, key)).ok().map(|v| v.trim().to_string())
+}
+
+fn ipv6_enabled(iface: &str) -> bool {
+    sysctl(iface, "disable_ipv6").is_some_and(|v| v == "0")
+}
+
+fn run_checked(command: &mut RecordedCommand) -> Result<(), String> {
+    let output = command.output().map_err(|e| e.to_string())?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+    }
+    Ok(())
+}
+
+// `ip6tables -S <chain>`, None when the chain doesn't exist or can't be read
+pub(crate) fn chain_rules(chain: &str) -> Option<Vec<String>> {
+    let output = sudo().args(["ip6tables", "-S", chain]).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&output.stdout).lines().map(str::to_string).collect())
+}
+
+fn jumps_to(rules: &[String], chain: &str, iface: &str, target: &str) -> bool {
+    let jump = format!("-A {} -i {} -j {}", chain, iface, target);
+    rules.iter().any(|r| r == &jump)
+}
+
+// ============ CHECKS ============
+
+/// Policy of a built-in chain, "UNKNOWN" when it can't be read
+pub(crate) fn chain_policy(chain: &str) -> String {
+    let policy = format!("-P {} ", chain);
+    chain_rules(chain)
+        .and_then(|rules| rules.iter().find_map(|r| r.strip_prefix(&policy).map(str::to_string)))
+        .unwrap_or_else(|| "UNKNOWN".to_string())
+}
+
+/// Whether new connections from `wan` are dropped unless a pinhole lets them in
+pub(crate) fn inbound_protected(wan: &str) -> bool {
+    check_inbound(wan).status == CheckStatus::Pass
+}
+
+fn check_inbound(wan: &str) -> PostureCheck {
+    const NAME: &str = "Default-deny inbound";
+    let mut open = Vec::new();
+    for chain in ["INPUT", "FORWARD"] {
+        let Some(rules) = chain_rules(chain) else {
+            return check("inbound", NAME, CheckStatus::Warn, "Could not read the IPv6 firewall");
+        };
+        let drops = rules.iter().any(|r| r == &format!("-P {} DROP", chain));
+        if !drops && !jumps_to(&rules, chain, wan, WAN_CHAIN) {
+            open.push(chain);
+        }
+    }
+
+    if open.is_empty() {
+        check("inbound", NAME, CheckStatus::Pass, format!("New IPv6 connections from {} are dropped", wan))
+    } else {
+        let detail = format!(
+            "The IPv6 {} chain{} accept{} new connections from {}: LAN devices are reachable from the internet",
+            open.join(" and "),
+            if open.len() > 1 { "s" } else { "" },
+            if open.len() > 1 { "" } else { "s" },
+            wan
+        );
+        check("inbound", NAME, CheckStatus::Fail, detail)
+    }
+}
+
+fn check_ra_guard(lan: &str) -> PostureCheck {
+    const NAME: &str = "RA guard on LAN";
+    let accept_ra = sysctl(lan, "accept_ra");
+    let guarded = chain_rules("INPUT").is_some_and(|rules| jumps_to(&rules, "INPUT", lan, RA_GUARD_CHAIN));
+
+    match (accept_ra.as_deref(), guarded) {
+        (Some("0"), true) => check(
+            "ra_guard",
+            NAME,
+            CheckStatus::Pass,
+            format!("The router ignores router advertisements from {}", lan),
+        ),
+        (Some("0"), false) => check(
+            "ra_guard",
+            NAME,
+            CheckStatus::Warn,
+            format!("{} ignores router advertisements, but rogue ones from clients are not dropped", lan),
+        ),
+        _ => check(
+            "ra_guard",
+            NAME,
+            CheckStatus::Fail,
+            format!("{} accepts router advertisements: a LAN client can redirect the router's IPv6 traffic", lan),
+        ),
+    }
+}
+
+fn check_privacy(wan: &str) -> PostureCheck {
+    const NAME: &str = "Privacy extensions";
+    match sysctl(wan, "use_tempaddr").as_deref() {
+        Some("2") => check(
+            "privacy",
+            NAME,
+            CheckStatus::Pass,
+            "Traffic from the router uses temporary addresses",
+        ),
+        _ => check(
+            "privacy",
+            NAME,
+            CheckStatus::Warn,
+            format!("Traffic from the router uses the stable address of {}, which can be tracked", wan),
+        ),
+    }
+}
+
+pub fn audit(wan: &str, lan: &str) -> PostureReport {
+    let ipv6_enabled = ipv6_enabled(wan);
+    let checks = if ipv6_enabled {
+        vec![check_inbound(wan), check_ra_guard(lan), check_privacy(wan)]
+    } else {
+        Vec::new()
+    };
+
+    PostureReport {
+        wan_interface: wan.to_string(),
+        lan_interface: lan.to_string(),
+        ipv6_enabled,
+        checks,
+    }
+}
+
+// ============ FIXES ============
+
+pub(crate) fn ip6tables(args: &[&str]) -> Result<(), String> {
+    run_checked(sudo().arg("ip6tables").args(args))
+}
+
+// Create (or empty) one of our chains and fill it
+fn replace_chain(chain: &str, rules: &[&[&str]]) -> Result<(), String> {
+    if chain_rules(chain).is_none() {
+        ip6tables(&["-N", chain])?;
+    }
+    ip6tables(&["-F", chain])?;
+    for rule in rules {
+        let mut args = vec!["-A", chain];
+        args.extend_from_slice(rule);
+        ip6tables(&args)?;
+    }
+    Ok(())
+}
+
+fn ensure_jump(chain: &str, iface: &str, target: &str) -> Result<(), String> {
+    if ip6tables(&["-C", chain, "-i", iface, "-j", target]).is_ok() {
+        return Ok(());
+    }
+    ip6tables(&["-I", chain, "1", "-i", iface, "-j", target])
+}
+
+/// Create one of our chains unless it exists already, keeping its rules
+pub(crate) fn ensure_chain(chain: &str) -> Result<(), String> {
+    if chain_rules(chain).is_none() {
+        ip6tables(&["-N", chain])?;
+    }
+    Ok(())
+}
+
+/// Make `target` the first rule of `chain` for every interface, moving the jump up if something
+/// was inserted ahead of it since
+pub(crate) fn ensure_jump_first(chain: &str, target: &str) -> Result<(), String> {
+    let jump = format!("-A {} -j {}", chain, target);
+    let rules = chain_rules(chain).ok_or_else(|| format!("Could not read the IPv6 {} chain", chain))?;
+    match rules.iter().position(|r| r.starts_with("-A ")) {
+        Some(i) if rules[i] == jump => return Ok(()),
+        _ => {}
+    }
+    if rules.contains(&jump) {
+        ip6tables(&["-D", chain, "-j", target])?;
+    }
+    ip6tables(&["-I", chain, "1", "-j", target])
+}
+
+// Keep the setting across reboots: one `key=value` line per setting in our sysctl.d file
+fn persist_sysctl(iface: &str, key: &str, value: &str) -> Result<(), String> {
+    let name = format!("net.ipv6.conf.{}.{}", iface, key);
+    let existing = std::fs::read_to_string(SYSCTL_FILE).unwrap_or_default();
+    let mut lines: Vec<String> = existing
+        .lines()
+        .filter(|l| l.split('=').next().map(str::trim) != Some(name.as_str()))
+        .map(str::to_string)
+        .collect();
+    if lines.is_empty() {
+        lines.push("# Managed by RouterUI - IPv6 posture".to_string());
+    }
+    lines.push(format!("{}={}", name, value));
+    write_system_file(SYSCTL_FILE, lines.join("\n") + "\n").map_err(|e| e.to_string())
+}
+
+fn set_sysctl(iface: &str, key: &str, value: &str) -> Result<(), String> {
+    write_system_file(sysctl_path(iface, key), value).map_err(|e| e.to_string())?;
+    persist_sysctl(iface, key, value)
+}
+
+fn fix_inbound(wan: &str) -> Result<(), String> {
+    ensure_chain(PINHOLE_CHAIN)?;
+    replace_chain(
+        WAN_CHAIN,
+        &[
+            &["-m", "conntrack", "--ctstate", "RELATED,ESTABLISHED", "-j", "RETURN"],
+            // Neighbour discovery, PMTU and errors; IPv6 breaks without them
+            &["-p", "ipv6-icmp", "-j", "RETURN"],
+            // DHCPv6 replies to the router's own client
+            &["-p", "udp", "--dport", "546", "-j", "RETURN"],
+            &["-j", PINHOLE_CHAIN],
+            &["-j", "DROP"],
+        ],
+    )?;
+    ensure_jump("INPUT", wan, WAN_CHAIN)?;
+    ensure_jump("FORWARD", wan, WAN_CHAIN)?;
+    // The WAN chain consults the pinholes from now on; a direct jump left from before is redundant
+    let _ = ip6tables(&["-D", "FORWARD", "-i", wan, "-j", PINHOLE_CHAIN]);
+    keep_blocks_first()
+}
+
+// Blocked addresses must not get in through a pinhole, so their chain stays ahead of the rest
+fn keep_blocks_first() -> Result<(), String> {
+    if chain_rules(BLOCK_CHAIN).is_none() {
+        return Ok(());
+    }
+    ensure_jump_first("INPUT", BLOCK_CHAIN)?;
+    ensure_jump_first("FORWARD", BLOCK_CHAIN)
+}
+
+/// Make sure new connections from the WAN pass through the pinholes: from the WAN chain when
+/// inbound is denied by it (rebuilding chains set up before pinholes existed), else straight
+/// from FORWARD, which matters when its policy is DROP
+pub(crate) fn ensure_pinholes_consulted(wan: &str) -> Result<(), String> {
+    ensure_chain(PINHOLE_CHAIN)?;
+    match chain_rules(WAN_CHAIN) {
+        Some(rules) if rules.contains(&format!("-A {} -j {}", WAN_CHAIN, PINHOLE_CHAIN)) => Ok(()),
+        Some(_) => fix_inbound(wan),
+        None => {
+            ensure_jump("FORWARD", wan, PINHOLE_CHAIN)?;
+            keep_blocks_first()
+        }
+    }
+}
+
+fn fix_ra_guard(lan: &str) -> Result<(), String> {
+    replace_chain(
+        RA_GUARD_CHAIN,
+        &[
+            &["-p", "ipv6-icmp", "--icmpv6-type", "router-advertisement", "-j", "DROP"],
+            &["-p", "ipv6-icmp", "--icmpv6-type", "redirect", "-j", "DROP"],
+        ],
+    )?;
+    ensure_jump("INPUT", lan, RA_GUARD_CHAIN)?;
+    set_sysctl(lan, "accept_ra", "0")
+}
+
+fn fix_privacy(wan: &str) -> Result<(), String> {
+    set_sysctl(wan, "use_tempaddr", "2")
+}
+
+/// Fix one check by id. Firewall changes are saved so they survive a reboot.
+pub fn fix(id: &str, wan: &str, lan: &str) -> Result<(), String> {
+    match id {
+        "inbound" => fix_inbound(wan)?,
+        "ra_guard" => fix_ra_guard(lan)?,
+        "privacy" => return fix_privacy(wan),
+        _ => return Err(format!("Unknown check: {}", id)),
+    }
+    run_checked(sudo().args(["netfilter-persistent", "save"]))
+}
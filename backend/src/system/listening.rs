@@ -0,0 +1,256 @@
+use serde::Serialize;
+use std::net::IpAddr;
+use std::process::Command;
+use super::privileges::sudo;
+
+#[derive(Debug, Serialize)]
+pub struct ListeningSocket {
+    pub protocol: String,
+    pub bind_address: String,
+    pub port: u16,
+    pub interface: Option<String>,
+    pub process: Option<String>,
+    pub pid: Option<u32>,
+    pub wan_reachable: bool,
+    pub reason: String,
+}
+
+// ============ ss PARSING ============
+
+struct RawSocket {
+    protocol: String,
+    address: String,
+    interface: Option<String>,
+    port: u16,
+    process: Option<String>,
+    pid: Option<u32>,
+}
+
+// Local address forms: 0.0.0.0:53, *:22, [::]:80, 127.0.0.53%lo:53, [fe80::1]%enp1s0:546
+fn parse_local(local: &str) -> Option<(String, Option<String>, u16)> {
+    let (addr, port) = local.rsplit_once(':')?;
+    let port = port.parse().ok()?;
+    let (addr, iface) = match addr.split_once('%') {
+        Some((a, i)) => (a, Some(i.to_string())),
+        None => (addr, None),
+    };
+    let addr = addr.trim_start_matches('[').trim_end_matches(']');
+    Some((addr.to_string(), iface, port))
+}
+
+// users:(("sshd",pid=812,fd=3),("sshd",pid=813,fd=3)) - first process is enough
+fn parse_process(users: &str) -> (Option<String>, Option<u32>) {
+    let name = users.split('"').nth(1).map(|s| s.to_string());
+    let pid = users
+        .split("pid=")
+        .nth(1)
+        .and_then(|s| s.split(|c: char| !c.is_ascii_digit()).next())
+        .and_then(|s| s.parse().ok());
+    (name, pid)
+}
+
+fn parse_ss_line(line: &str) -> Option<RawSocket> {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    if fields.len() < 5 {
+        return None;
+    }
+    let (address, interface, port) = parse_local(fields[4])?;
+    let (process, pid) = fields
+        .get(6)
+        .map(|users| parse_process(users))
+        .unwrap_or((None, None));
+
+    Some(RawSocket {
+        protocol: fields[0].to_string(),
+        address,
+        interface,
+        port,
+        process,
+        pid,
+    })
+}
+
+// ============ FIREWALL EVALUATION ============
+
+// One `-A INPUT ...` line from `iptables -S INPUT`, reduced to what matters for
+// a new inbound connection from an arbitrary WAN host
+struct InputRule {
+    in_iface: Option<String>,
+    source: Option<String>,
+    protocol: Option<String>,
+    ports: Option<Vec<(u16, u16)>>,
+    new_allowed: bool,
+    target: String,
+    // Matches we don't model (negation, recent, limit...) - the rule is skipped
+    unsupported: bool,
+}
+
+fn parse_ports(spec: &str) -> Vec<(u16, u16)> {
+    spec.split(',')
+        .filter_map(|p| match p.split_once(':') {
+            Some((a, b)) => Some((a.parse().ok()?, b.parse().ok()?)),
+            None => p.parse().ok().map(|v| (v, v)),
+        })
+        .collect()
+}
+
+fn parse_input_rule(line: &str) -> Option<InputRule> {
+    let args: Vec<&str> = line.split_whitespace().collect();
+    if args.first() != Some(&"-A") {
+        return None;
+    }
+
+    let mut rule = InputRule {
+        in_iface: None,
+        source: None,
+        protocol: None,
+        ports: None,
+        new_allowed: true,
+        target: String::new(),
+        unsupported: false,
+    };
+
+    let mut i = 2;
+    while i < args.len() {
+        let value = args.get(i + 1).map(|s| s.to_string());
+        match args[i] {
+            "-i" => rule.in_iface = value,
+            "-s" => rule.source = value,
+            "-p" => rule.protocol = value,
+            "--dport" | "--dports" => rule.ports = value.as_deref().map(parse_ports),
+            "--state" | "--ctstate" => {
+                rule.new_allowed = value.map(|v| v.split(',').any(|s| s == "NEW")).unwrap_or(true)
+            }
+            "-j" => rule.target = value.unwrap_or_default(),
+            "-m" => {
+                let known = ["tcp", "udp", "state", "conntrack", "multiport", "comment"];
+                if !value.as_deref().map(|m| known.contains(&m)).unwrap_or(false) {
+                    rule.unsupported = true;
+                }
+            }
+            "--comment" => {}
+            "!" => {
+                rule.unsupported = true;
+                i += 1;
+                continue;
+            }
+            _ => {
+                i += 1;
+                continue;
+            }
+        }
+        i += 2;
+    }
+
+    Some(rule)
+}
+
+struct InputChain {
+    policy_accept: bool,
+    rules: Vec<InputRule>,
+}
+
+fn load_input_chain(binary: &str) -> Option<InputChain> {
+    let output = sudo()
+        .args([binary, "-S", "INPUT"])
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+
+    let text = String::from_utf8_lossy(&output.stdout);
+    let policy_accept = !text.lines().any(|l| l.starts_with("-P INPUT DROP") || l.starts_with("-P INPUT REJECT"));
+    let rules = text.lines().filter_map(parse_input_rule).collect();
+    Some(InputChain { policy_accept, rules })
+}
+
+fn is_any_source(source: &Option<String>) -> bool {
+    matches!(source.as_deref(), None | Some("0.0.0.0/0") | Some("::/0"))
+}
+
+/// Walk the INPUT chain for a new connection arriving on `wan_iface`. Returns the verdict
+/// and a short explanation.
+fn firewall_verdict(chain: &InputChain, wan_iface: &str, protocol: &str, port: u16) -> (bool, String) {
+    for rule in &chain.rules {
+        if rule.unsupported || !rule.new_allowed || !is_any_source(&rule.source) {
+            continue;
+        }
+        if rule.in_iface.as_deref().map(|i| i != wan_iface).unwrap_or(false) {
+            continue;
+        }
+        if rule.protocol.as_deref().map(|p| p != protocol && p != "all").unwrap_or(false) {
+            continue;
+        }
+        if let Some(ports) = &rule.ports {
+            if !ports.iter().any(|(lo, hi)| (*lo..=*hi).contains(&port)) {
+                continue;
+            }
+        }
+
+        match rule.target.as_str() {
+            "ACCEPT" => return (true, "Allowed by firewall rule".to_string()),
+            "DROP" | "REJECT" => return (false, "Blocked by firewall rule".to_string()),
+            _ => continue,
+        }
+    }
+
+    if chain.policy_accept {
+        (true, "INPUT policy is ACCEPT".to_string())
+    } else {
+        (false, "Dropped by INPUT policy".to_string())
+    }
+}
+
+// ============ INVENTORY ============
+
+fn is_wildcard(addr: &str) -> bool {
+    matches!(addr, "*" | "0.0.0.0" | "::")
+}
+
+/// All listening TCP/UDP sockets, flagged with whether a host on the WAN could reach them
+pub fn get_listening(wan_iface: &str, wan_ips: &[IpAddr]) -> Result<Vec<ListeningSocket>, std::io::Error> {
+    let output = Command::new("ss").args(["-tulpnH"]).output()?;
+    let text = String::from_utf8_lossy(&output.stdout);
+
+    let v4_chain = load_input_chain("iptables");
+    let v6_chain = load_input_chain("ip6tables");
+
+    let mut sockets: Vec<ListeningSocket> = text
+        .lines()
+        .filter_map(parse_ss_line)
+        .map(|raw| {
+            let ip: Option<IpAddr> = raw.address.parse().ok();
+            let ipv6 = raw.address.contains(':') || raw.address == "*";
+
+            let (wan_reachable, reason) = if ip.map(|ip| ip.is_loopback()).unwrap_or(false) {
+                (false, "Bound to loopback".to_string())
+            } else if raw.interface.as_deref().map(|i| i != wan_iface).unwrap_or(false) {
+                (false, format!("Bound to {}", raw.interface.clone().unwrap_or_default()))
+            } else if !is_wildcard(&raw.address) && !ip.map(|ip| wan_ips.contains(&ip)).unwrap_or(false) {
+                (false, "Bound to a non-WAN address".to_string())
+            } else {
+                let chain = if ipv6 { v6_chain.as_ref() } else { v4_chain.as_ref() };
+                match chain {
+                    Some(chain) => firewall_verdict(chain, wan_iface, &raw.protocol, raw.port),
+                    None => (true, "Firewall rules unavailable".to_string()),
+                }
+            };
+
+            ListeningSocket {
+                protocol: raw.protocol,
+                bind_address: raw.address,
+                port: raw.port,
+                interface: raw.interface,
+                process: raw.process,
+                pid: raw.pid,
+                wan_reachable,
+                reason,
+            }
+        })
+        .collect();
+
+    // Exposed sockets first, then by port
+    sockets.sort_by(|a, b| b.wan_reachable.cmp(&a.wan_reachable).then(a.port.cmp(&b.port)));
+    Ok(sockets)
+}
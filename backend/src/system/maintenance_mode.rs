@@ -0,0 +1,71 @@
+// Maintenance mode freezes the configuration: every request that could change something is
+// refused until an admin turns it off again, while reads keep working. Meant for backups and
+// migrations, or for handing the UI to someone who should only look.
+
+use axum::{
+    extract::Request,
+    http::{Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+
+const STATE_FILE: &str = "/opt/routerui/maintenance-mode.json";
+pub const TOGGLE_PATH: &str = "/api/system/maintenance-mode";
+// Besides the toggle: signing in and out, and settling a firewall change that was already
+// waiting for confirmation when the freeze started
+const EXEMPT_PATHS: &[&str] = &[TOGGLE_PATH, "/api/auth/login", "/api/auth/logout", "/api/auth/refresh", "/api/firewall/confirm", "/api/firewall/revert"];
+// Ending a session that may have been stolen is never held back
+const EXEMPT_PREFIXES: &[&str] = &["/api/auth/sessions/"];
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MaintenanceMode {
+    pub enabled: bool,
+    pub reason: Option<String>,
+    pub enabled_by: Option<String>,
+    pub enabled_at: Option<String>,
+}
+
+pub fn load() -> MaintenanceMode {
+    std::fs::read_to_string(STATE_FILE)
+        .ok()
+        .and_then(|c| serde_json::from_str(&c).ok())
+        .unwrap_or_default()
+}
+
+pub fn save(mode: &MaintenanceMode) -> Result<(), String> {
+    let json = serde_json::to_string_pretty(mode).map_err(|e| e.to_string())?;
+    std::fs::write(STATE_FILE, json).map_err(|e| e.to_string())
+}
+
+fn is_mutation(method: &Method) -> bool {
+    !matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS)
+}
+
+impl MaintenanceMode {
+    fn message(&self) -> String {
+        let mut message = "RouterUI is in maintenance mode".to_string();
+        if let Some(reason) = &self.reason {
+            message.push_str(&format!(" ({})", reason));
+        }
+        if let (Some(by), Some(at)) = (&self.enabled_by, &self.enabled_at) {
+            message.push_str(&format!(", turned on by {} at {}", by, at));
+        }
+        message.push_str(". Changes are disabled until an admin turns it off.");
+        message
+    }
+}
+
+// Route layer middleware: refuses changes while the freeze is on
+pub async fn enforce(request: Request, next: Next) -> Response {
+    let path = request.uri().path();
+    if !is_mutation(request.method()) || EXEMPT_PATHS.contains(&path) || EXEMPT_PREFIXES.iter().any(|p| path.starts_with(p)) {
+        return next.run(request).await;
+    }
+
+    let mode = load();
+    if mode.enabled {
+        return (StatusCode::LOCKED, mode.message()).into_response();
+    }
+    next.run(request).await
+}
@@ -0,0 +1,247 @@
+// Restricts who can reach the management UI and API by where they connect from. The server
+// listens on every address, WAN included; with this on, only clients inside the subnets of the
+// chosen interfaces (LAN and VPN by default) or on the override list get an answer, everyone else
+// a 403. Loopback is always let in so the CLI and local health checks keep working.
+
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::AppState;
+
+// Interface addresses change rarely; look them up again after this long
+const REFRESH_INTERVAL: Duration = Duration::from_secs(30);
+const MAX_ALLOWED_SOURCES: usize = 64;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ManagementAccess {
+    pub enabled: bool,
+    // Kernel names or roles ("lan", "vpn"); clients in their subnets are let in
+    pub interfaces: Vec<String>,
+    // Addresses or CIDRs let in wherever they connect from
+    pub allowed_sources: Vec<String>,
+}
+
+impl Default for ManagementAccess {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interfaces: vec!["lan".to_string(), "vpn".to_string()],
+            allowed_sources: Vec::new(),
+        }
+    }
+}
+
+impl ManagementAccess {
+    pub fn normalize(&mut self) -> Result<(), String> {
+        for list in [&mut self.interfaces, &mut self.allowed_sources] {
+            *list = list.iter().map(|e| e.trim().to_string()).filter(|e| !e.is_empty()).collect();
+            list.sort();
+            list.dedup();
+        }
+        if self.allowed_sources.len() > MAX_ALLOWED_SOURCES {
+            return Err(format!("At most {} allowed sources", MAX_ALLOWED_SOURCES));
+        }
+        for source in &self.allowed_sources {
+            Cidr::parse(source).ok_or_else(|| format!("'{}' is not an IP address or CIDR", source))?;
+        }
+        if self.enabled && self.interfaces.is_empty() && self.allowed_sources.is_empty() {
+            return Err("Pick at least one interface or allowed source, or nobody could manage the router".to_string());
+        }
+        Ok(())
+    }
+}
+
+// Key in the settings table
+pub const SETTINGS_KEY: &str = "management";
+
+pub async fn load(pool: &SqlitePool) -> ManagementAccess {
+    match crate::db::settings::get(pool, SETTINGS_KEY).await {
+        Ok(access) => access.unwrap_or_default(),
+        Err(e) => {
+            tracing::warn!("Failed to read management access settings, allowing everyone: {}", e);
+            ManagementAccess::default()
+        }
+    }
+}
+
+pub async fn save(state: &AppState, access: &ManagementAccess) -> Result<(), String> {
+    crate::db::settings::set(&state.db, SETTINGS_KEY, access).await.map_err(|e| e.to_string())?;
+    state.management_access.invalidate();
+    Ok(())
+}
+
+// ============ MATCHING ============
+
+#[derive(Debug, Clone, Copy)]
+struct Cidr {
+    network: IpAddr,
+    prefix: u8,
+}
+
+impl Cidr {
+    // "192.168.1.0/24", "fd00::/64" or a bare address
+    fn parse(value: &str) -> Option<Cidr> {
+        let (ip, prefix) = match value.split_once('/') {
+            Some((ip, prefix)) => (ip.parse::<IpAddr>().ok()?, Some(prefix.parse::<u8>().ok()?)),
+            None => (value.parse::<IpAddr>().ok()?, None),
+        };
+        let max = if ip.is_ipv4() { 32 } else { 128 };
+        let prefix = prefix.unwrap_or(max);
+        (prefix <= max).then_some(Cidr { network: ip, prefix })
+    }
+
+    fn contains(&self, ip: IpAddr) -> bool {
+        match (self.network, ip.to_canonical()) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(net) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(net) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Who may connect, resolved from the settings and the interfaces' current addresses
+#[derive(Debug, Clone, Default)]
+struct Allowed {
+    enabled: bool,
+    networks: Vec<Cidr>,
+}
+
+impl Allowed {
+    fn permits(&self, ip: IpAddr) -> bool {
+        let ip = ip.to_canonical();
+        !self.enabled || ip.is_loopback() || self.networks.iter().any(|n| n.contains(ip))
+    }
+}
+
+async fn resolve(pool: &SqlitePool, access: &ManagementAccess) -> Allowed {
+    if !access.enabled {
+        return Allowed::default();
+    }
+    let roles = crate::interfaces::roles(pool).await;
+    let names: Vec<String> = access.interfaces.iter().filter_map(|i| roles.resolve(i).ok()).collect();
+    let interfaces = tokio::task::spawn_blocking(super::get_interfaces)
+        .await
+        .ok()
+        .and_then(Result::ok)
+        .unwrap_or_default();
+
+    let mut networks: Vec<Cidr> = access.allowed_sources.iter().filter_map(|s| Cidr::parse(s)).collect();
+    for iface in interfaces.iter().filter(|i| names.contains(&i.name)) {
+        networks.extend(iface.ipv4.iter().chain(&iface.ipv6).filter_map(|a| Cidr::parse(a)));
+    }
+    Allowed { enabled: true, networks }
+}
+
+/// The resolved settings, looked up again every REFRESH_INTERVAL or when they are saved
+#[derive(Default)]
+pub struct AccessCache {
+    current: Mutex<Option<(Instant, Allowed)>>,
+}
+
+impl AccessCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn invalidate(&self) {
+        *self.current.lock().unwrap() = None;
+    }
+
+    async fn get(&self, pool: &SqlitePool) -> Allowed {
+        if let Some((at, allowed)) = self.current.lock().unwrap().as_ref() {
+            if at.elapsed() < REFRESH_INTERVAL {
+                return allowed.clone();
+            }
+        }
+        let allowed = resolve(pool, &load(pool).await).await;
+        *self.current.lock().unwrap() = Some((Instant::now(), allowed.clone()));
+        allowed
+    }
+}
+
+/// Whether `ip` could still reach the UI under `access`; used to refuse settings that would
+/// lock out the admin saving them
+pub async fn would_permit(pool: &SqlitePool, access: &ManagementAccess, ip: IpAddr) -> bool {
+    resolve(pool, access).await.permits(ip)
+}
+
+// Middleware around the whole app, frontend included: clients from elsewhere get a 403
+pub async fn enforce(State(state): State<Arc<AppState>>, request: Request, next: Next) -> Response {
+    let Some(ConnectInfo(peer)) = request.extensions().get::<ConnectInfo<SocketAddr>>().copied() else {
+        return next.run(request).await;
+    };
+    if state.management_access.get(&state.db).await.permits(peer.ip()) {
+        return next.run(request).await;
+    }
+    // The public status endpoint can be opened to everyone, it exposes nothing worth restricting
+    if crate::public_status::open_to_all(&state.db, request.uri().path()).await {
+        return next.run(request).await;
+    }
+    tracing::debug!("Refused management access from {} to {}", peer.ip(), request.uri().path());
+    (StatusCode::FORBIDDEN, "Management access is not allowed from this network").into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(value: &str) -> IpAddr {
+        value.parse().unwrap()
+    }
+
+    #[test]
+    fn cidr_parses_ranges_and_bare_addresses() {
+        assert!(Cidr::parse("192.168.1.0/24").is_some());
+        assert!(Cidr::parse("fd00::/64").is_some());
+        assert_eq!(Cidr::parse("10.0.0.1").map(|c| c.prefix), Some(32));
+        assert_eq!(Cidr::parse("fd00::1").map(|c| c.prefix), Some(128));
+        assert!(Cidr::parse("192.168.1.0/33").is_none());
+        assert!(Cidr::parse("fd00::/129").is_none());
+        assert!(Cidr::parse("192.168.1.0/").is_none());
+        assert!(Cidr::parse("lan").is_none());
+    }
+
+    #[test]
+    fn cidr_contains_addresses_in_its_range() {
+        let lan = Cidr::parse("192.168.1.0/24").unwrap();
+        assert!(lan.contains(ip("192.168.1.1")));
+        assert!(lan.contains(ip("192.168.1.255")));
+        assert!(!lan.contains(ip("192.168.2.1")));
+        // IPv4 clients on a dual-stack listener
+        assert!(lan.contains(ip("::ffff:192.168.1.20")));
+        assert!(!lan.contains(ip("fd00::1")));
+
+        let host = Cidr::parse("10.0.0.5").unwrap();
+        assert!(host.contains(ip("10.0.0.5")));
+        assert!(!host.contains(ip("10.0.0.6")));
+
+        let ula = Cidr::parse("fd00:1:2:3::/64").unwrap();
+        assert!(ula.contains(ip("fd00:1:2:3:ffff::1")));
+        assert!(!ula.contains(ip("fd00:1:2:4::1")));
+        assert!(!ula.contains(ip("192.168.1.1")));
+    }
+
+    #[test]
+    fn zero_prefix_matches_the_whole_family() {
+        let any = Cidr::parse("0.0.0.0/0").unwrap();
+        assert!(any.contains(ip("203.0.113.9")));
+        assert!(!any.contains(ip("2001:db8::1")));
+        assert!(Cidr::parse("::/0").unwrap().contains(ip("2001:db8::1")));
+    }
+}
@@ -1,4 +1,15 @@
+pub mod commands;
+pub mod drift;
+pub mod factory_reset;
+pub mod ipv6;
+pub mod listening;
+pub mod management_access;
+pub mod maintenance_mode;
+pub mod preflight;
+pub mod privileges;
+
 use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, Ipv4Addr};
 use std::process::Command;
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -120,7 +131,7 @@ fn get_storage_info() -> StorageInfo {
     StorageInfo { total_gb: 0.0, used_gb: 0.0, free_gb: 0.0, percent_used: 0.0 }
 }
 
-fn parse_meminfo(content: &str) -> MemoryInfo {
+pub fn parse_meminfo(content: &str) -> MemoryInfo {
     let mut total = 0u64;
     let mut available = 0u64;
 
@@ -169,6 +180,33 @@ pub fn get_interfaces() -> Result<Vec<NetworkInterface>, std::io::Error> {
     Ok(interfaces)
 }
 
+/// IPv4 subnets directly attached to every interface except the WAN
+pub fn lan_subnets(wan: &str) -> Vec<(Ipv4Addr, u32)> {
+    get_interfaces()
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|i| i.name != wan)
+        .filter_map(|i| i.ipv4)
+        .filter_map(|cidr| {
+            let (ip, prefix) = cidr.split_once('/')?;
+            Some((ip.parse().ok()?, prefix.parse::<u32>().ok().filter(|p| *p <= 32)?))
+        })
+        .collect()
+}
+
+fn in_subnet(ip: Ipv4Addr, (network, prefix): (Ipv4Addr, u32)) -> bool {
+    let mask = u32::MAX.checked_shl(32 - prefix).unwrap_or(0);
+    u32::from(ip) & mask == u32::from(network) & mask
+}
+
+/// Loopback or inside one of `subnets` (see [`lan_subnets`]), i.e. not reaching us from the WAN
+pub fn is_lan_address(ip: IpAddr, subnets: &[(Ipv4Addr, u32)]) -> bool {
+    match ip {
+        IpAddr::V4(ip) => ip.is_loopback() || subnets.iter().any(|s| in_subnet(ip, *s)),
+        IpAddr::V6(ip) => ip.is_loopback(),
+    }
+}
+
 fn parse_interface(value: &serde_json::Value) -> Option<NetworkInterface> {
     let name = value.get("ifname")?.as_str()?.to_string();
     
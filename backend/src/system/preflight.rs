@@ -0,0 +1,321 @@
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+// Binaries the setup wizard can't work without
+const REQUIRED_BINARIES: &[&str] = &["ip", "iptables", "systemctl"];
+// Used by later features; missing ones only produce a warning
+const OPTIONAL_BINARIES: &[&str] = &["ipset", "iptables-save", "iptables-restore", "netfilter-persistent"];
+
+const INSTALL_DIR: &str = "/opt/routerui";
+
+#[derive(Debug, Clone, Copy, Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+    Pass,
+    Warn,
+    Fail,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PreflightCheck {
+    pub id: String,
+    pub name: String,
+    pub status: CheckStatus,
+    pub detail: String,
+    // What to run or change when the check doesn't pass
+    pub fix: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PreflightReport {
+    pub ready: bool,
+    pub checks: Vec<PreflightCheck>,
+}
+
+fn check(id: &str, name: &str, status: CheckStatus, detail: impl Into<String>, fix: Option<String>) -> PreflightCheck {
+    PreflightCheck {
+        id: id.to_string(),
+        name: name.to_string(),
+        status,
+        detail: detail.into(),
+        fix,
+    }
+}
+
+/// Locate a binary on PATH, falling back to the sbin dirs that aren't on a service's PATH everywhere
+pub fn find_binary(name: &str) -> Option<PathBuf> {
+    let path = std::env::var("PATH").unwrap_or_default();
+    let found = path
+        .split(':')
+        .chain(["/usr/local/sbin", "/usr/sbin", "/sbin", "/usr/bin", "/bin"])
+        .filter(|dir| !dir.is_empty())
+        .map(|dir| Path::new(dir).join(name))
+        .find(|p| p.is_file());
+    found
+}
+
+fn is_root() -> bool {
+    Command::new("id")
+        .arg("-u")
+        .output()
+        .map(|o| String::from_utf8_lossy(&o.stdout).trim() == "0")
+        .unwrap_or(false)
+}
+
+// ============ BINARIES ============
+
+fn check_binaries() -> Vec<PreflightCheck> {
+    let mut checks = Vec::new();
+
+    for name in REQUIRED_BINARIES {
+        checks.push(match find_binary(name) {
+            Some(path) => check(&format!("binary:{}", name), name, CheckStatus::Pass, path.display().to_string(), None),
+            None => check(
+                &format!("binary:{}", name),
+                name,
+                CheckStatus::Fail,
+                format!("'{}' was not found", name),
+                Some(format!("Install the package providing '{}' (e.g. apt install {})", name, package_for(name))),
+            ),
+        });
+    }
+
+    for name in OPTIONAL_BINARIES {
+        checks.push(match find_binary(name) {
+            Some(path) => check(&format!("binary:{}", name), name, CheckStatus::Pass, path.display().to_string(), None),
+            None => check(
+                &format!("binary:{}", name),
+                name,
+                CheckStatus::Warn,
+                format!("'{}' was not found; related features will be unavailable", name),
+                Some(format!("apt install {}", package_for(name))),
+            ),
+        });
+    }
+
+    checks
+}
+
+fn package_for(binary: &str) -> &'static str {
+    match binary {
+        "ip" => "iproute2",
+        "iptables" | "iptables-save" | "iptables-restore" => "iptables",
+        "systemctl" => "systemd",
+        "ipset" => "ipset",
+        "netfilter-persistent" => "iptables-persistent",
+        _ => "<package>",
+    }
+}
+
+// ============ SUDO ============
+
+fn check_sudo() -> Vec<PreflightCheck> {
+    // With the root helper running, sudo isn't used at all
+    if crate::helper::is_available() {
+        let socket = crate::helper::socket_path().display().to_string();
+        return vec![match crate::helper::call(&crate::helper::Request::Ping) {
+            Ok(r) if r.ok => check("helper", "routerui-helper", CheckStatus::Pass, format!("Reachable at {}", socket), None),
+            Ok(r) => check(
+                "helper",
+                "routerui-helper",
+                CheckStatus::Fail,
+                r.error.unwrap_or_else(|| "Helper refused ping".to_string()),
+                Some("systemctl restart routerui-helper".to_string()),
+            ),
+            Err(e) => check(
+                "helper",
+                "routerui-helper",
+                CheckStatus::Fail,
+                format!("Cannot connect to {}: {}", socket, e),
+                Some("systemctl status routerui-helper".to_string()),
+            ),
+        }];
+    }
+
+    let Some(sudo) = find_binary("sudo") else {
+        return vec![check(
+            "sudo",
+            "sudo",
+            CheckStatus::Fail,
+            "sudo is not installed; RouterUI runs privileged commands through it",
+            Some("apt install sudo".to_string()),
+        )];
+    };
+
+    // Root can always sudo, but sudo still has to work non-interactively
+    if is_root() {
+        let ok = Command::new(&sudo)
+            .args(["-n", "true"])
+            .status()
+            .map(|s| s.success())
+            .unwrap_or(false);
+        return vec![if ok {
+            check("sudo", "sudo", CheckStatus::Pass, "Running as root", None)
+        } else {
+            check(
+                "sudo",
+                "sudo",
+                CheckStatus::Fail,
+                "Running as root but 'sudo -n true' failed",
+                Some("Check /etc/sudoers for a 'Defaults requiretty' or similar restriction".to_string()),
+            )
+        }];
+    }
+
+    // Same rule-by-rule test as System > Privileges, grouped per binary. Only the binaries the
+    // wizard itself needs block setup; the rest degrade individual features.
+    let report = super::privileges::test_all();
+    let mut binaries: Vec<&str> = Vec::new();
+    for test in &report.tests {
+        if !binaries.contains(&test.binary.as_str()) {
+            binaries.push(&test.binary);
+        }
+    }
+
+    binaries
+        .into_iter()
+        .filter(|binary| find_binary(binary).is_some())
+        .map(|binary| {
+            let tests: Vec<_> = report.tests.iter().filter(|t| t.binary == binary).collect();
+            let denied: Vec<_> = tests.iter().filter(|t| !t.allowed).collect();
+            let id = format!("sudo:{}", binary);
+            let name = format!("sudo {}", binary);
+
+            if denied.is_empty() {
+                return check(&id, &name, CheckStatus::Pass, format!("{} rule(s) allowed", tests.len()), None);
+            }
+            let status = if REQUIRED_BINARIES.contains(&binary) { CheckStatus::Fail } else { CheckStatus::Warn };
+            check(
+                &id,
+                &name,
+                status,
+                format!(
+                    "{} cannot run: {}",
+                    report.user,
+                    denied.iter().map(|t| t.rule.as_str()).collect::<Vec<_>>().join(", ")
+                ),
+                Some(format!(
+                    "Install the generated policy: routerui-cli sudoers {} > /tmp/routerui.sudoers && visudo -cf /tmp/routerui.sudoers && install -m 0440 /tmp/routerui.sudoers {}",
+                    report.user,
+                    super::privileges::SUDOERS_PATH
+                )),
+            )
+        })
+        .collect()
+}
+
+// ============ KERNEL ============
+
+fn check_module(module: &str, sys_name: &str) -> PreflightCheck {
+    let id = format!("module:{}", module);
+    if Path::new("/sys/module").join(sys_name).exists() {
+        return check(&id, module, CheckStatus::Pass, "Loaded", None);
+    }
+
+    let available = Command::new("modinfo")
+        .arg(sys_name)
+        .output()
+        .map(|o| o.status.success())
+        .unwrap_or(false);
+
+    if available {
+        check(
+            &id,
+            module,
+            CheckStatus::Warn,
+            "Available but not loaded; it normally loads on first use",
+            Some(format!("modprobe {}", sys_name)),
+        )
+    } else {
+        check(
+            &id,
+            module,
+            CheckStatus::Fail,
+            "Kernel module not available",
+            Some(format!("Install the kernel modules package for your kernel (e.g. apt install linux-modules-extra-$(uname -r)), then modprobe {}", sys_name)),
+        )
+    }
+}
+
+fn check_ip_forward() -> PreflightCheck {
+    let path = "/proc/sys/net/ipv4/ip_forward";
+    let value = std::fs::read_to_string(path).unwrap_or_default();
+
+    if value.trim() == "1" {
+        return check("sysctl:ip_forward", "IP forwarding", CheckStatus::Pass, "Enabled", None);
+    }
+
+    // The wizard turns it on itself (through the helper when it runs unprivileged), so this
+    // only fails if it won't be able to
+    if super::privileges::can_write_system_file(path) {
+        check(
+            "sysctl:ip_forward",
+            "IP forwarding",
+            CheckStatus::Warn,
+            "Disabled; the setup wizard will enable it",
+            None,
+        )
+    } else {
+        check(
+            "sysctl:ip_forward",
+            "IP forwarding",
+            CheckStatus::Fail,
+            "Disabled and RouterUI cannot change it",
+            Some("sysctl -w net.ipv4.ip_forward=1 and add net.ipv4.ip_forward=1 to /etc/sysctl.conf".to_string()),
+        )
+    }
+}
+
+// ============ FILESYSTEM ============
+
+fn check_writable(dir: &Path) -> PreflightCheck {
+    let id = format!("write:{}", dir.display());
+    let name = format!("Write access to {}", dir.display());
+
+    if !dir.is_dir() {
+        return check(
+            &id,
+            &name,
+            CheckStatus::Fail,
+            "Directory does not exist",
+            Some(format!("mkdir -p {}", dir.display())),
+        );
+    }
+
+    let probe = dir.join(".preflight-write-test");
+    match std::fs::write(&probe, b"ok") {
+        Ok(_) => {
+            let _ = std::fs::remove_file(&probe);
+            check(&id, &name, CheckStatus::Pass, "Writable", None)
+        }
+        Err(e) => check(
+            &id,
+            &name,
+            CheckStatus::Fail,
+            e.to_string(),
+            Some(format!("chown -R <service user> {}", dir.display())),
+        ),
+    }
+}
+
+/// Run every check the setup wizard depends on
+pub fn run() -> PreflightReport {
+    let mut checks = check_binaries();
+    checks.extend(check_sudo());
+    checks.push(check_module("ipset", "ip_set"));
+    checks.push(check_module("nf_nat", "nf_nat"));
+    checks.push(check_ip_forward());
+
+    let install_dir = PathBuf::from(INSTALL_DIR);
+    let data_dir = crate::db::data_dir();
+    checks.push(check_writable(&install_dir));
+    if !data_dir.starts_with(&install_dir) {
+        checks.push(check_writable(&data_dir));
+    }
+
+    PreflightReport {
+        ready: checks.iter().all(|c| c.status != CheckStatus::Fail),
+        checks,
+    }
+}
@@ -0,0 +1,430 @@
+use axum::{
+    body::{to_bytes, Body},
+    extract::Request,
+    http::StatusCode,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
+use std::path::PathBuf;
+use std::process::Command;
+
+use super::commands::RecordedCommand;
+use super::preflight::find_binary;
+
+pub const SUDOERS_PATH: &str = "/etc/sudoers.d/routerui";
+
+/// Everything RouterUI runs through sudo. `args` uses sudoers syntax: "" allows
+/// no arguments, `*` is a wildcard. `example` is a harmless invocation used to test the rule.
+pub struct PrivilegedCommand {
+    pub binary: &'static str,
+    pub args: &'static str,
+    pub purpose: &'static str,
+    pub example: &'static [&'static str],
+}
+
+const fn cmd(
+    binary: &'static str,
+    args: &'static str,
+    purpose: &'static str,
+    example: &'static [&'static str],
+) -> PrivilegedCommand {
+    PrivilegedCommand { binary, args, purpose, example }
+}
+
+/// sudoers `*` also matches spaces, so these carve the extra arguments, traversal and
+/// unit paths back out of the wildcard rules above. routerui-helper enforces this per field.
+pub const DENIED: &[(&str, &str)] = &[
+    ("rm", "-f /opt/routerui/quarantine/* *"),
+    ("rm", "*..*"),
+    ("mv", "/opt/routerui/quarantine/* /opt/routerui/restored/* *"),
+    ("mv", "*..*"),
+    ("systemctl", "* */*"),
+    ("systemctl", "* -*"),
+    ("clamscan", "*--copy*"),
+    ("clamscan", "*--log*"),
+    ("clamscan", "*--move=*"),
+    ("clamscan", "*--move /[!o]*"),
+    ("clamscan", "*..*"),
+    ("iptables", "*--mo*"),
+    ("iptables", "* -M*"),
+    ("ip6tables", "*--mo*"),
+    ("ip6tables", "* -M*"),
+    // Raw AT commands and resets are never needed
+    ("mmcli", "*--command*"),
+    ("mmcli", "*--factory-reset*"),
+    ("mmcli", "*--simple-connect=* *"),
+    ("mmcli", "*--messaging-delete-sms=* *"),
+];
+
+pub const COMMANDS: &[PrivilegedCommand] = &[
+    // Firewall
+    cmd("iptables", "-L *", "Read firewall rules", &["-L", "INPUT", "-n"]),
+    cmd("iptables", "-S *", "Read firewall rules", &["-S", "INPUT"]),
+    cmd("iptables", "-C *", "Check for existing rules", &["-C", "INPUT", "-i", "lo", "-j", "ACCEPT"]),
+    cmd("iptables", "-I *", "Add firewall rules", &["-I", "INPUT", "1", "-s", "192.0.2.1", "-j", "DROP"]),
+    cmd("iptables", "-A *", "Add firewall rules", &["-A", "FORWARD", "-d", "192.0.2.1", "-j", "ACCEPT"]),
+    cmd("iptables", "-D *", "Remove firewall rules", &["-D", "INPUT", "-s", "192.0.2.1", "-j", "DROP"]),
+    cmd("iptables", "-P INPUT *", "Set the INPUT policy", &["-P", "INPUT", "DROP"]),
+    cmd("iptables", "-N ROUTERUI-*", "Create RouterUI chains", &["-N", "ROUTERUI-TEST"]),
+    cmd("iptables", "-F ROUTERUI-*", "Flush RouterUI chains", &["-F", "ROUTERUI-TEST"]),
+    cmd("iptables", "-X ROUTERUI-*", "Remove RouterUI chains", &["-X", "ROUTERUI-TEST"]),
+    cmd("iptables", "-t nat *", "Port forwarding", &["-t", "nat", "-L", "PREROUTING", "-n"]),
+    cmd("iptables", "-t mangle *", "Traffic shaping priorities", &["-t", "mangle", "-S", "FORWARD"]),
+    cmd("ip6tables", "-S INPUT", "WAN exposure check", &["-S", "INPUT"]),
+    cmd("ip6tables", "-L *", "Read IPv6 firewall rules", &["-L", "INPUT", "-n"]),
+    cmd("ip6tables", "-S *", "IPv6 posture audit", &["-S", "FORWARD"]),
+    cmd("ip6tables", "-C *", "IPv6 posture audit", &["-C", "INPUT", "-i", "lo", "-j", "ROUTERUI-TEST"]),
+    cmd("ip6tables", "-I *", "IPv6 posture fixes", &["-I", "INPUT", "1", "-i", "lo", "-j", "ROUTERUI-TEST"]),
+    cmd("ip6tables", "-A ROUTERUI-*", "IPv6 posture fixes", &["-A", "ROUTERUI-TEST", "-j", "DROP"]),
+    cmd("ip6tables", "-N ROUTERUI-*", "IPv6 posture fixes", &["-N", "ROUTERUI-TEST"]),
+    cmd("ip6tables", "-F ROUTERUI-*", "IPv6 posture fixes", &["-F", "ROUTERUI-TEST"]),
+    cmd("ip6tables", "-D *", "IPv6 firewall rules", &["-D", "ROUTERUI-TEST", "-s", "2001:db8::1", "-j", "DROP"]),
+    cmd("iptables-save", "", "Firewall backup", &[]),
+    cmd("iptables-save", "-t nat", "Port forward listing", &["-t", "nat"]),
+    cmd("iptables-restore", "", "Firewall restore", &[]),
+    cmd("ip6tables-save", "", "IPv6 firewall backup", &[]),
+    cmd("ip6tables-restore", "", "IPv6 firewall restore", &[]),
+    cmd("netfilter-persistent", "save", "Persist firewall rules", &["save"]),
+    // Protection blocklists
+    cmd("ipset", "list *", "Read blocklists", &["list", "protection-whitelist"]),
+    cmd("ipset", "create *", "Create blocklists", &["create", "example", "hash:net", "maxelem", "1000000"]),
+    cmd("ipset", "add *", "Update blocklists", &["add", "protection-whitelist", "192.0.2.1", "-exist"]),
+    cmd("ipset", "del *", "Update blocklists", &["del", "protection-whitelist", "192.0.2.1"]),
+    cmd("ipset", "flush *", "Update blocklists", &["flush", "example"]),
+    cmd("ipset", "destroy *", "Remove blocklists", &["destroy", "example"]),
+    // Services
+    cmd("systemctl", "start *", "Start services", &["start", "dnsmasq"]),
+    cmd("systemctl", "stop *", "Stop services", &["stop", "dnsmasq"]),
+    cmd("systemctl", "restart *", "Restart services", &["restart", "dnsmasq"]),
+    cmd("systemctl", "reload *", "Reload services", &["reload", "dnsmasq"]),
+    cmd("systemctl", "enable *", "Enable services", &["enable", "dnsmasq"]),
+    cmd("systemctl", "disable *", "Disable services", &["disable", "dnsmasq"]),
+    cmd("journalctl", "-u *", "Service logs", &["-u", "dnsmasq", "-n", "10", "--no-pager", "-o", "short-iso"]),
+    cmd("journalctl", "-k *", "Kernel log (firewall hits)", &["-k", "--since", "24 hours ago", "--no-pager", "-o", "short-iso"]),
+    // Security monitor
+    cmd("tail", "-100 /var/log/auth.log", "SSH login monitor", &["-100", "/var/log/auth.log"]),
+    cmd("grep", "-E sshd.\\*(Failed|Invalid user) /var/log/auth.log", "SSH login monitor", &["-E", "sshd.*(Failed|Invalid user)", "/var/log/auth.log"]),
+    // Network
+    cmd("ip", "route add *", "Static routes", &["route", "add", "192.0.2.0/24", "via", "192.0.2.1"]),
+    cmd("ip", "route del *", "Static routes", &["route", "del", "192.0.2.0/24"]),
+    cmd("ip", "addr flush dev *", "LAN address (setup)", &["addr", "flush", "dev", "lo"]),
+    cmd("ip", "addr add *", "LAN address (setup)", &["addr", "add", "192.168.1.1/24", "dev", "lo"]),
+    cmd("ip", "link set *", "LAN address (setup)", &["link", "set", "lo", "up"]),
+    cmd("netplan", "apply", "LAN address (setup)", &["apply"]),
+    cmd("ip", "link set * address *", "WAN MAC address", &["link", "set", "lo", "address", "02:00:00:00:00:01"]),
+    cmd("ip", "link add link * name *.* type vlan id *", "LAN subnets (VLANs)", &["link", "add", "link", "lo", "name", "lo.20", "type", "vlan", "id", "20"]),
+    cmd("ip", "link del *.*", "LAN subnets (VLANs)", &["link", "del", "lo.20"]),
+    cmd("ip", "addr del *", "LAN subnets", &["addr", "del", "192.168.30.1/24", "dev", "lo"]),
+    cmd("etherwake", "-i *", "Wake-on-LAN", &["-i", "enp2s0", "00:00:00:00:00:00"]),
+    cmd("arping", "-c 2 -w 2 -I *", "IP conflict detection", &["-c", "2", "-w", "2", "-I", "lo", "127.0.0.1"]),
+    cmd("hostapd_cli", "-i * wps_pbc", "WPS push button", &["-i", "wlan0", "wps_pbc"]),
+    cmd("hostapd_cli", "-i * wps_cancel", "WPS push button", &["-i", "wlan0", "wps_cancel"]),
+    cmd("hostapd_cli", "-i * wps_get_status", "WPS push button", &["-i", "wlan0", "wps_get_status"]),
+    cmd("conntrack", "-L -o extended,id", "Connection log", &["-L", "-o", "extended,id"]),
+    cmd("tc", "qdisc replace dev * root cake bandwidth *", "Traffic shaping", &["qdisc", "replace", "dev", "lo", "root", "cake", "bandwidth", "1000kbit", "diffserv4", "nat", "dual-srchost"]),
+    cmd("tc", "qdisc del dev * root", "Traffic shaping", &["qdisc", "del", "dev", "lo", "root"]),
+    cmd("tc", "qdisc add dev * handle ffff\\: ingress", "Traffic shaping (downloads)", &["qdisc", "add", "dev", "lo", "handle", "ffff:", "ingress"]),
+    cmd("tc", "qdisc del dev * ingress", "Traffic shaping (downloads)", &["qdisc", "del", "dev", "lo", "ingress"]),
+    cmd("tc", "filter add dev * parent ffff\\: matchall action mirred egress redirect dev ifb*", "Traffic shaping (downloads)", &["filter", "add", "dev", "lo", "parent", "ffff:", "matchall", "action", "mirred", "egress", "redirect", "dev", "ifb-routerui"]),
+    cmd("ip", "link add name ifb* type ifb", "Traffic shaping (downloads)", &["link", "add", "name", "ifb-routerui", "type", "ifb"]),
+    cmd("ip", "link del ifb*", "Traffic shaping (downloads)", &["link", "del", "ifb-routerui"]),
+    cmd("tailscale", "up *", "VPN", &["up", "--accept-routes"]),
+    cmd("tailscale", "set *", "VPN", &["set", "--advertise-exit-node=false"]),
+    cmd("tailscale", "down", "VPN", &["down"]),
+    cmd("tailscale", "logout", "VPN", &["logout"]),
+    // Antivirus
+    cmd("clamscan", "-r --infected --no-summary /*", "Virus scans", &["-r", "--infected", "--no-summary", "/tmp"]),
+    cmd("freshclam", "", "Virus definition updates", &[]),
+    cmd("rm", "-f /opt/routerui/quarantine/*", "Delete quarantined files", &["-f", "/opt/routerui/quarantine/example"]),
+    cmd("mv", "/opt/routerui/quarantine/* /opt/routerui/restored/*", "Restore quarantined files", &["/opt/routerui/quarantine/example", "/opt/routerui/restored/example"]),
+    // LTE modem
+    cmd("mmcli", "-m * --signal-setup=30", "Modem signal details", &["-m", "0", "--signal-setup=30"]),
+    cmd("mmcli", "-m * --simple-connect=*", "Modem connection", &["-m", "0", "--simple-connect=apn=internet"]),
+    cmd("mmcli", "-m * --simple-disconnect", "Modem connection", &["-m", "0", "--simple-disconnect"]),
+    cmd("mmcli", "-m * --messaging-list-sms -J", "Modem SMS", &["-m", "0", "--messaging-list-sms", "-J"]),
+    cmd("mmcli", "-s * -J", "Modem SMS", &["-s", "0", "-J"]),
+    cmd("mmcli", "-m * --messaging-create-sms=*", "Modem SMS", &["-m", "0", "--messaging-create-sms=number='+10000000000',text='test'"]),
+    cmd("mmcli", "-s * --send", "Modem SMS", &["-s", "0", "--send"]),
+    cmd("mmcli", "-m * --messaging-delete-sms=*", "Modem SMS", &["-m", "0", "--messaging-delete-sms=0"]),
+    // Updates
+    cmd("apt", "update", "System updates", &["update"]),
+    cmd("apt", "upgrade -y", "System updates", &["upgrade", "-y"]),
+];
+
+fn helper_binary() -> PathBuf {
+    std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(|dir| dir.join("routerui-helper")))
+        .filter(|p| p.is_file())
+        .unwrap_or_else(|| PathBuf::from("routerui-helper"))
+}
+
+/// Prefix for privileged commands. Goes through routerui-helper when it is running, otherwise
+/// sudo that never prompts: a missing rule fails immediately instead of hanging on a password.
+/// What runs is kept in the command history (see commands.rs).
+pub fn sudo() -> RecordedCommand {
+    let mut command = if crate::helper::is_available() {
+        let mut command = Command::new(helper_binary());
+        command.arg("exec");
+        if let Some(id) = crate::logging::current_request_id() {
+            command.env(crate::helper::REQUEST_ID_ENV, id);
+        }
+        command
+    } else {
+        let mut command = Command::new("sudo");
+        command.arg("-n");
+        command
+    };
+    // The helper forwards whatever arrives on stdin; callers that pipe input override this
+    command.stdin(std::process::Stdio::null());
+    RecordedCommand::new(command)
+}
+
+/// Replace a root-owned system file (dnsmasq, hostapd, sysctl, ...). Tracked files are
+/// snapshotted so later hand edits show up as drift (see drift.rs).
+pub fn write_system_file(path: impl AsRef<std::path::Path>, contents: impl AsRef<[u8]>) -> std::io::Result<()> {
+    let path = path.as_ref();
+    if let Some(drift) = super::drift::check(path) {
+        tracing::warn!("{} was changed outside RouterUI after {}; overwriting it", drift.path, drift.written_at);
+    }
+    if !crate::helper::is_available() {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(path, contents.as_ref())?;
+        super::drift::record(path, contents.as_ref());
+        return Ok(());
+    }
+
+    let response = crate::helper::call(&crate::helper::Request::WriteFile {
+        path: path.to_path_buf(),
+        contents: String::from_utf8_lossy(contents.as_ref()).into_owned(),
+        dry_run: false,
+        request_id: crate::logging::current_request_id(),
+    })?;
+    if response.ok {
+        super::drift::record(path, contents.as_ref());
+        Ok(())
+    } else {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::PermissionDenied,
+            response.error.unwrap_or_else(|| "routerui-helper refused the write".to_string()),
+        ))
+    }
+}
+
+/// Whether write_system_file(path, ..) would be allowed, without writing anything
+pub fn can_write_system_file(path: impl AsRef<std::path::Path>) -> bool {
+    let path = path.as_ref();
+    if !crate::helper::is_available() {
+        return std::fs::OpenOptions::new().write(true).open(path).is_ok();
+    }
+
+    crate::helper::call(&crate::helper::Request::WriteFile {
+        path: path.to_path_buf(),
+        contents: String::new(),
+        dry_run: true,
+        request_id: None,
+    })
+    .map(|r| r.ok)
+    .unwrap_or(false)
+}
+
+fn resolve(binary: &str) -> Option<PathBuf> {
+    find_binary(binary)
+}
+
+// ============ SUDOERS GENERATION ============
+
+/// Minimal sudoers.d policy for `user` covering exactly COMMANDS
+pub fn generate_sudoers(user: &str) -> String {
+    let mut out = String::new();
+    out.push_str("# RouterUI sudo policy - generated by RouterUI\n");
+    out.push_str(&format!("# Install with: visudo -cf <file> && install -m 0440 <file> {}\n\n", SUDOERS_PATH));
+
+    let mut missing = Vec::new();
+    let mut rules = Vec::new();
+    let mut last_purpose = "";
+
+    for command in COMMANDS {
+        let Some(path) = resolve(command.binary) else {
+            if !missing.contains(&command.binary) {
+                missing.push(command.binary);
+            }
+            continue;
+        };
+        if command.purpose != last_purpose {
+            rules.push(format!("# {}", command.purpose));
+            last_purpose = command.purpose;
+        }
+        let args = if command.args.is_empty() { "\"\"" } else { command.args };
+        rules.push(format!("{} ALL=(root) NOPASSWD: {} {}", user, path.display(), args));
+    }
+
+    out.push_str(&rules.join("\n"));
+    out.push('\n');
+
+    // Later entries win in sudoers, so the exclusions go last
+    let denied: Vec<String> = DENIED
+        .iter()
+        .filter_map(|(binary, args)| resolve(binary).map(|path| format!("{} ALL=(root) !{} {}", user, path.display(), args)))
+        .collect();
+    if !denied.is_empty() {
+        out.push_str("\n# Exclusions\n");
+        out.push_str(&denied.join("\n"));
+        out.push('\n');
+    }
+
+    if !missing.is_empty() {
+        out.push_str(&format!(
+            "\n# Not installed on this system, re-generate after installing: {}\n",
+            missing.join(", ")
+        ));
+    }
+    out
+}
+
+// ============ PRIVILEGE TESTS ============
+
+#[derive(Debug, Serialize)]
+pub struct PrivilegeTest {
+    pub binary: String,
+    pub command: String,
+    pub rule: String,
+    pub purpose: String,
+    pub allowed: bool,
+    pub detail: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PrivilegeReport {
+    pub user: String,
+    pub running_as_root: bool,
+    // "helper" when routerui-helper handles privileged commands, otherwise "sudo"
+    pub mode: String,
+    pub allowed: usize,
+    pub denied: usize,
+    pub tests: Vec<PrivilegeTest>,
+}
+
+fn current_user() -> String {
+    Command::new("id")
+        .arg("-un")
+        .output()
+        .map(|o| String::from_utf8_lossy(&o.stdout).trim().to_string())
+        .ok()
+        .filter(|u| !u.is_empty())
+        .unwrap_or_else(|| "routerui".to_string())
+}
+
+/// Ask sudo whether each command would be allowed, without running any of them
+pub fn test_all() -> PrivilegeReport {
+    let user = current_user();
+    let running_as_root = user == "root";
+    let sudo_installed = find_binary("sudo").is_some();
+    let use_helper = crate::helper::is_available();
+
+    let tests: Vec<PrivilegeTest> = COMMANDS
+        .iter()
+        .map(|command| {
+            let example = format!("{} {}", command.binary, command.example.join(" "));
+            let rule = format!("{} {}", command.binary, command.args);
+            let base = |allowed: bool, detail: String| PrivilegeTest {
+                binary: command.binary.to_string(),
+                command: example.trim().to_string(),
+                rule: rule.trim().to_string(),
+                purpose: command.purpose.to_string(),
+                allowed,
+                detail,
+            };
+
+            let Some(path) = resolve(command.binary) else {
+                return base(false, format!("{} is not installed", command.binary));
+            };
+            if use_helper {
+                let args: Vec<String> = command.example.iter().map(|a| a.to_string()).collect();
+                let command = match crate::helper::Privileged::parse(command.binary, &args) {
+                    Ok(c) => c,
+                    Err(e) => return base(false, e),
+                };
+                return match crate::helper::call(&crate::helper::Request::Check { command }) {
+                    Ok(r) if r.ok => base(true, "Allowed by routerui-helper".to_string()),
+                    Ok(r) => base(false, r.error.unwrap_or_else(|| "Refused by routerui-helper".to_string())),
+                    Err(e) => base(false, format!("Cannot reach routerui-helper: {}", e)),
+                };
+            }
+            if !sudo_installed {
+                return base(false, "sudo is not installed".to_string());
+            }
+
+            // `sudo -l <cmd> <args>` exits 0 only if that exact invocation is permitted
+            let output = Command::new("sudo")
+                .arg("-n")
+                .arg("-l")
+                .arg(&path)
+                .args(command.example)
+                .output();
+            match output {
+                Ok(o) if o.status.success() => base(true, "Allowed".to_string()),
+                Ok(o) => {
+                    let stderr = String::from_utf8_lossy(&o.stderr).trim().to_string();
+                    base(false, if stderr.is_empty() { "Not permitted by sudoers".to_string() } else { stderr })
+                }
+                Err(e) => base(false, e.to_string()),
+            }
+        })
+        .collect();
+
+    let allowed = tests.iter().filter(|t| t.allowed).count();
+    PrivilegeReport {
+        denied: tests.len() - allowed,
+        allowed,
+        user,
+        running_as_root,
+        mode: if use_helper { "helper" } else { "sudo" }.to_string(),
+        tests,
+    }
+}
+
+// ============ DENIAL HANDLING ============
+
+fn is_sudo_denial(message: &str) -> bool {
+    message.contains("sudo: a password is required")
+        || message.contains("sudo: a terminal is required")
+        || message.contains("is not allowed to execute")
+        || message.contains("is not in the sudoers file")
+        || message.contains("is not allowed by helper policy")
+        || message.contains("cannot reach routerui-helper")
+}
+
+// Handlers pass sudo's stderr through as a 500; turn a missing privilege into a 403 that says how to fix it
+pub async fn explain_denials(request: Request, next: Next) -> Response {
+    let response = next.run(request).await;
+    if response.status() != StatusCode::INTERNAL_SERVER_ERROR {
+        return response;
+    }
+
+    let (parts, body) = response.into_parts();
+    let bytes = match to_bytes(body, 64 * 1024).await {
+        Ok(b) => b,
+        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR, "Internal error").into_response(),
+    };
+    let message = String::from_utf8_lossy(&bytes);
+
+    if !is_sudo_denial(&message) {
+        return Response::from_parts(parts, Body::from(bytes));
+    }
+
+    tracing::warn!("Privileged command refused: {}", message.trim());
+    (
+        StatusCode::FORBIDDEN,
+        format!(
+            "RouterUI is missing privileges for this action ({}). \
+             Check System > Privileges; make sure routerui-helper is running or install the generated policy to {}.",
+            message.trim(),
+            SUDOERS_PATH
+        ),
+    )
+        .into_response()
+}
@@ -0,0 +1,101 @@
+// Undo for removals from the small hand-kept lists: static leases, local DNS names, WoL devices
+// and the protection whitelist. A removal takes effect at once, but the entry is held here for
+// UNDO_WINDOW so a misclick can be put back exactly as it was. Like pending approvals these
+// live in memory only; a restart forgets them.
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+pub const UNDO_WINDOW: Duration = Duration::from_secs(60);
+// Oldest removals are dropped first once this many are waiting
+const MAX_REMOVALS: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UndoKind {
+    StaticLease,
+    LocalDns,
+    WolDevice,
+    Whitelist,
+}
+
+impl UndoKind {
+    /// Permission needed to put an entry of this kind back (the same as to add one)
+    pub fn permission(self) -> &'static str {
+        match self {
+            UndoKind::StaticLease => "dhcp:write",
+            UndoKind::LocalDns => "dns:write",
+            UndoKind::WolDevice => "wol:write",
+            UndoKind::Whitelist => "protection:write",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Removal {
+    pub token: String,
+    pub kind: UndoKind,
+    // e.g. "static lease aa:bb:cc:dd:ee:ff (192.168.1.20)"
+    pub summary: String,
+    pub expires_at: String,
+    // The removed entry, as stored
+    #[serde(skip)]
+    pub item: serde_json::Value,
+    #[serde(skip)]
+    deadline: Instant,
+}
+
+#[derive(Default)]
+pub struct UndoStore {
+    removed: Mutex<Vec<Removal>>,
+}
+
+impl UndoStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn live(&self) -> std::sync::MutexGuard<'_, Vec<Removal>> {
+        let mut removed = self.removed.lock().unwrap();
+        let now = Instant::now();
+        removed.retain(|r| r.deadline > now);
+        removed
+    }
+
+    /// Keep a removed entry for UNDO_WINDOW; the returned token undoes it
+    pub fn record<T: Serialize>(&self, kind: UndoKind, summary: String, item: &T) -> Removal {
+        let now = Utc::now();
+        let removal = Removal {
+            token: uuid::Uuid::new_v4().to_string(),
+            kind,
+            summary,
+            expires_at: (now + chrono::Duration::from_std(UNDO_WINDOW).unwrap_or_default()).to_rfc3339(),
+            item: serde_json::to_value(item).unwrap_or_default(),
+            deadline: Instant::now() + UNDO_WINDOW,
+        };
+
+        let mut removed = self.live();
+        if removed.len() >= MAX_REMOVALS {
+            removed.remove(0);
+        }
+        removed.push(removal.clone());
+        removal
+    }
+
+    pub fn list(&self) -> Vec<Removal> {
+        self.live().clone()
+    }
+
+    pub fn get(&self, token: &str) -> Option<Removal> {
+        self.live().iter().find(|r| r.token == token).cloned()
+    }
+
+    /// Claim a removal for undoing; a second undo of the same token finds nothing
+    pub fn take(&self, token: &str) -> Option<Removal> {
+        let mut removed = self.live();
+        let index = removed.iter().position(|r| r.token == token)?;
+        Some(removed.remove(index))
+    }
+}
@@ -0,0 +1,360 @@
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::SqlitePool;
+use std::net::Ipv4Addr;
+use std::process::Command;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::events::Event;
+use crate::system::commands::RecordedCommand;
+use crate::system::privileges::{sudo, write_system_file};
+use crate::AppState;
+
+const SETTINGS_FILE: &str = "/opt/routerui/wan.json";
+const CHECK_INTERVAL: Duration = Duration::from_secs(5 * 60);
+const DEFAULT_WAN_INTERFACE: &str = "enp1s0";
+
+// Tried in order when the WAN interface only has a private/CGNAT address
+const LOOKUP_SERVICES: &[&str] = &[
+    "https://api.ipify.org",
+    "https://ifconfig.me/ip",
+    "https://icanhazip.com",
+];
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct WanIpRecord {
+    pub ip: String,
+    pub source: String,
+    pub changed_at: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WanIpStatus {
+    pub interface: String,
+    pub current: Option<WanIpRecord>,
+    pub previous: Option<WanIpRecord>,
+    pub last_checked: Option<String>,
+    pub last_error: Option<String>,
+}
+
+#[derive(Default)]
+struct CheckState {
+    last_checked: Option<String>,
+    last_error: Option<String>,
+}
+
+/// Remembers the outcome of the most recent public IP check
+#[derive(Default)]
+pub struct WanTracker {
+    state: Mutex<CheckState>,
+}
+
+impl WanTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+/// WAN interface: the one set in the interface roles, else the one chosen in the setup wizard
+pub async fn wan_interface(pool: &SqlitePool) -> String {
+    if let Some(wan) = crate::interfaces::load_settings(pool).await.wan {
+        return wan;
+    }
+    sqlx::query_scalar::<_, String>("SELECT value FROM setup_config WHERE key = 'wan_interface'")
+        .fetch_optional(pool)
+        .await
+        .ok()
+        .flatten()
+        .unwrap_or_else(|| DEFAULT_WAN_INTERFACE.to_string())
+}
+
+// ============ IDENTITY ============
+
+/// What the router presents to the ISP on the WAN link
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WanSettings {
+    // Replaces the interface's own MAC; the permanent address comes back when cleared
+    #[serde(default)]
+    pub mac_address: Option<String>,
+    // Sent as DHCP option 12
+    #[serde(default)]
+    pub dhcp_hostname: Option<String>,
+    // Sent as DHCP option 60
+    #[serde(default)]
+    pub dhcp_vendor_class: Option<String>,
+}
+
+pub fn load_settings() -> WanSettings {
+    std::fs::read_to_string(SETTINGS_FILE)
+        .ok()
+        .and_then(|c| serde_json::from_str(&c).ok())
+        .unwrap_or_default()
+}
+
+pub fn save_settings(settings: &WanSettings) -> Result<(), String> {
+    let json = serde_json::to_string_pretty(settings).map_err(|e| e.to_string())?;
+    std::fs::write(SETTINGS_FILE, json).map_err(|e| e.to_string())
+}
+
+impl WanSettings {
+    /// Trim, lowercase the MAC and drop empty fields
+    pub fn normalize(&mut self) {
+        let clean = |v: &mut Option<String>| {
+            *v = v.as_deref().map(str::trim).filter(|v| !v.is_empty()).map(str::to_string);
+        };
+        clean(&mut self.mac_address);
+        clean(&mut self.dhcp_hostname);
+        clean(&mut self.dhcp_vendor_class);
+        self.mac_address = self.mac_address.as_ref().map(|m| m.replace('-', ":").to_lowercase());
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(mac) = &self.mac_address {
+            let octets: Vec<u8> = mac
+                .split(':')
+                .filter(|p| p.len() == 2)
+                .filter_map(|p| u8::from_str_radix(p, 16).ok())
+                .collect();
+            if octets.len() != 6 || mac.len() != 17 {
+                return Err(format!("Invalid MAC address: {}", mac));
+            }
+            if octets[0] & 1 == 1 || octets.iter().all(|o| *o == 0) {
+                return Err("The MAC address must be a unicast address".to_string());
+            }
+        }
+        if let Some(hostname) = &self.dhcp_hostname {
+            let valid = hostname.len() <= 253
+                && hostname.split('.').all(|label| {
+                    !label.is_empty()
+                        && label.len() <= 63
+                        && !label.starts_with('-')
+                        && !label.ends_with('-')
+                        && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+                });
+            if !valid {
+                return Err(format!("Invalid hostname: {}", hostname));
+            }
+        }
+        if let Some(vendor) = &self.dhcp_vendor_class {
+            if vendor.len() > 255 || !vendor.chars().all(|c| c.is_ascii_graphic() || c == ' ') || vendor.contains('"') {
+                return Err("Vendor class must be up to 255 printable characters without quotes".to_string());
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct LinkAddress {
+    pub current: Option<String>,
+    // Only reported by the kernel while a different address is set
+    pub permanent: Option<String>,
+}
+
+pub fn link_address(iface: &str) -> LinkAddress {
+    let link = Command::new("ip")
+        .args(["-j", "link", "show", "dev", iface])
+        .output()
+        .ok()
+        .and_then(|o| serde_json::from_slice::<Vec<Value>>(&o.stdout).ok())
+        .and_then(|links| links.into_iter().next());
+    let field = |name: &str| link.as_ref().and_then(|l| l[name].as_str()).map(str::to_string);
+
+    LinkAddress { current: field("address"), permanent: field("permaddr") }
+}
+
+fn run_checked(command: &mut RecordedCommand) -> Result<(), String> {
+    let output = command.output().map_err(|e| e.to_string())?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+    }
+    Ok(())
+}
+
+// Changing the address needs the link down; the DHCP client renews once it comes back up
+fn set_mac(iface: &str, mac: &str) -> Result<(), String> {
+    run_checked(sudo().args(["ip", "link", "set", iface, "down"]))?;
+    let result = run_checked(sudo().args(["ip", "link", "set", iface, "address", mac]));
+    run_checked(sudo().args(["ip", "link", "set", iface, "up"]))?;
+    result
+}
+
+// netplan renders WAN DHCP as 10-netplan-<iface>.network; a drop-in next to it survives
+// `netplan apply` and is picked up by systemd-networkd at boot
+fn networkd_dropin(iface: &str) -> String {
+    format!("/etc/systemd/network/10-netplan-{}.network.d/routerui-wan.conf", iface)
+}
+
+fn render_dropin(settings: &WanSettings) -> String {
+    let mut out = String::from("# Managed by RouterUI - WAN identity\n");
+    if let Some(mac) = &settings.mac_address {
+        out.push_str(&format!("[Link]\nMACAddress={}\n\n", mac));
+    }
+    out.push_str("[DHCPv4]\n");
+    match &settings.dhcp_hostname {
+        Some(hostname) => out.push_str(&format!("SendHostname=yes\nHostname={}\n", hostname)),
+        None => out.push_str("SendHostname=yes\n"),
+    }
+    if let Some(vendor) = &settings.dhcp_vendor_class {
+        out.push_str(&format!("VendorClassIdentifier={}\n", vendor));
+    }
+    out
+}
+
+/// Put the saved MAC and DHCP identity into effect on the WAN interface
+pub fn apply_settings(iface: &str, settings: &WanSettings) -> Result<(), String> {
+    let link = link_address(iface);
+    let wanted = settings.mac_address.clone().or(link.permanent);
+    if let Some(mac) = wanted.filter(|m| link.current.as_deref() != Some(m.as_str())) {
+        set_mac(iface, &mac)?;
+    }
+
+    // ifupdown installs have no networkd to hand the DHCP options to
+    if !std::path::Path::new("/etc/netplan").exists() {
+        return Ok(());
+    }
+    write_system_file(networkd_dropin(iface), render_dropin(settings)).map_err(|e| e.to_string())?;
+    run_checked(sudo().args(["systemctl", "restart", "systemd-networkd"]))
+}
+
+/// First IPv4 address of `iface`
+pub fn interface_ipv4(iface: &str) -> Option<Ipv4Addr> {
+    let output = Command::new("ip")
+        .args(["-4", "-o", "addr", "show", "dev", iface])
+        .output()
+        .ok()?;
+
+    // "2: enp1s0    inet 203.0.113.5/24 brd ... scope global ..."
+    String::from_utf8_lossy(&output.stdout)
+        .split_whitespace()
+        .skip_while(|w| *w != "inet")
+        .nth(1)
+        .and_then(|cidr| cidr.split('/').next())
+        .and_then(|ip| ip.parse().ok())
+}
+
+fn is_public(ip: Ipv4Addr) -> bool {
+    let cgnat = ip.octets()[0] == 100 && (ip.octets()[1] & 0xC0) == 64;
+    !(ip.is_private() || ip.is_loopback() || ip.is_link_local() || ip.is_unspecified() || cgnat)
+}
+
+async fn external_lookup() -> Result<(Ipv4Addr, String), String> {
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(5))
+        .build()
+        .map_err(|e| e.to_string())?;
+
+    let mut last_error = "no lookup services configured".to_string();
+    for url in LOOKUP_SERVICES {
+        let result = async {
+            let body = client.get(*url).send().await?.error_for_status()?.text().await?;
+            Ok::<_, reqwest::Error>(body)
+        }
+        .await;
+
+        match result {
+            Ok(body) => match body.trim().parse::<Ipv4Addr>() {
+                Ok(ip) => return Ok((ip, url.to_string())),
+                Err(_) => last_error = format!("{}: unexpected response", url),
+            },
+            Err(e) => last_error = format!("{}: {}", url, e),
+        }
+    }
+    Err(last_error)
+}
+
+/// Public IP from the WAN interface if it has one, otherwise from an external lookup
+async fn detect(iface: &str) -> Result<(Ipv4Addr, String), String> {
+    match interface_ipv4(iface) {
+        Some(ip) if is_public(ip) => Ok((ip, format!("interface:{}", iface))),
+        _ => external_lookup().await,
+    }
+}
+
+async fn history_rows(pool: &SqlitePool, limit: i64) -> Result<Vec<WanIpRecord>, sqlx::Error> {
+    sqlx::query_as::<_, WanIpRecord>(
+        "SELECT ip, source, changed_at FROM wan_ip_history ORDER BY id DESC LIMIT ?"
+    )
+    .bind(limit)
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn history(pool: &SqlitePool) -> Result<Vec<WanIpRecord>, sqlx::Error> {
+    history_rows(pool, 100).await
+}
+
+pub async fn status(state: &AppState) -> Result<WanIpStatus, sqlx::Error> {
+    let mut rows = history_rows(&state.db, 2).await?.into_iter();
+    let interface = wan_interface(&state.db).await;
+    let check = state.wan.state.lock().unwrap();
+
+    Ok(WanIpStatus {
+        interface,
+        current: rows.next(),
+        previous: rows.next(),
+        last_checked: check.last_checked.clone(),
+        last_error: check.last_error.clone(),
+    })
+}
+
+/// Detect the current public IP and record/emit it if it changed
+pub async fn check(state: &AppState) -> Result<(), String> {
+    let iface = wan_interface(&state.db).await;
+    let result = detect(&iface).await;
+
+    {
+        let mut check = state.wan.state.lock().unwrap();
+        check.last_checked = Some(Utc::now().to_rfc3339());
+        check.last_error = result.as_ref().err().cloned();
+    }
+    let (ip, source) = result?;
+    let ip = ip.to_string();
+
+    let previous: Option<String> = sqlx::query_scalar(
+        "SELECT ip FROM wan_ip_history ORDER BY id DESC LIMIT 1"
+    )
+    .fetch_optional(&state.db)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    if previous.as_deref() == Some(ip.as_str()) {
+        return Ok(());
+    }
+
+    let changed_at = Utc::now().to_rfc3339();
+    sqlx::query("INSERT INTO wan_ip_history (ip, source, changed_at) VALUES (?, ?, ?)")
+        .bind(&ip)
+        .bind(&source)
+        .bind(&changed_at)
+        .execute(&state.db)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    state.events.emit(Event::WanIpChanged { previous, current: ip, changed_at });
+    Ok(())
+}
+
+pub fn spawn(state: Arc<AppState>) {
+    tokio::spawn(async move {
+        // The kernel forgets a spoofed MAC on reboot
+        let iface = wan_interface(&state.db).await;
+        let settings = load_settings();
+        if settings.mac_address.is_some() {
+            let result = tokio::task::spawn_blocking(move || apply_settings(&iface, &settings)).await;
+            if let Ok(Err(e)) = result {
+                tracing::warn!("Applying WAN settings failed: {}", e);
+            }
+        }
+
+        loop {
+            state.tasks.beat("wan_ip", CHECK_INTERVAL);
+            if let Err(e) = check(&state).await {
+                tracing::warn!("WAN IP check failed: {}", e);
+            }
+            tokio::time::sleep(CHECK_INTERVAL).await;
+        }
+    });
+}
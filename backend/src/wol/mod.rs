@@ -0,0 +1,185 @@
+// Wake-on-LAN on behalf of whoever asks. Magic packets are broadcasts and don't cross subnets or
+// the tailnet, so a phone on Tailscale (or anyone off the LAN) can't wake a PC itself; the router
+// sends the packet onto the device's segment for it. Off-LAN requests are only relayed for
+// registered devices that allow it, and every wake lands in wol_wakes with who asked and from where.
+
+use chrono::Utc;
+use serde::Serialize;
+use sqlx::SqlitePool;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::process::Command;
+use std::time::Duration;
+
+use crate::system::privileges::sudo;
+
+// Addresses Tailscale hands out: 100.64.0.0/10 and fd7a:115c:a1e0::/48
+const TAILSCALE_V4: (Ipv4Addr, u32) = (Ipv4Addr::new(100, 64, 0, 0), 10);
+const TAILSCALE_V6: (Ipv6Addr, u32) = (Ipv6Addr::new(0xfd7a, 0x115c, 0xa1e0, 0, 0, 0, 0, 0), 48);
+const WHOIS_TIMEOUT: Duration = Duration::from_secs(3);
+const MAX_HISTORY: i64 = 500;
+
+/// Where a wake request came from, as far as relaying goes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Origin {
+    Lan,
+    Tailscale,
+    Remote,
+}
+
+impl Origin {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Origin::Lan => "lan",
+            Origin::Tailscale => "tailscale",
+            Origin::Remote => "remote",
+        }
+    }
+
+    pub fn is_off_lan(self) -> bool {
+        self != Origin::Lan
+    }
+}
+
+fn is_tailscale_address(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let mask = u32::MAX << (32 - TAILSCALE_V4.1);
+            u32::from(ip) & mask == u32::from(TAILSCALE_V4.0) & mask
+        }
+        IpAddr::V6(ip) => {
+            let mask = u128::MAX << (128 - TAILSCALE_V6.1);
+            u128::from(ip) & mask == u128::from(TAILSCALE_V6.0) & mask
+        }
+    }
+}
+
+/// LAN when `ip` is in a subnet the router is on (WAN aside), Tailscale when it's a tailnet address
+pub async fn origin(pool: &SqlitePool, ip: IpAddr) -> Origin {
+    let ip = ip.to_canonical();
+    if is_tailscale_address(ip) {
+        return Origin::Tailscale;
+    }
+    let wan = crate::wan::wan_interface(pool).await;
+    let subnets = tokio::task::spawn_blocking(move || crate::system::lan_subnets(&wan)).await.unwrap_or_default();
+    if crate::system::is_lan_address(ip, &subnets) {
+        Origin::Lan
+    } else {
+        Origin::Remote
+    }
+}
+
+/// "login (node)" of a tailnet address, if tailscaled knows it
+pub async fn tailscale_identity(ip: IpAddr) -> Option<String> {
+    let lookup = tokio::process::Command::new("tailscale")
+        .args(["whois", "--json", &ip.to_canonical().to_string()])
+        .output();
+    let output = tokio::time::timeout(WHOIS_TIMEOUT, lookup).await.ok()?.ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).ok()?;
+    let login = json.pointer("/UserProfile/LoginName").and_then(|v| v.as_str());
+    let node = json
+        .pointer("/Node/ComputedName")
+        .or_else(|| json.pointer("/Node/Name"))
+        .and_then(|v| v.as_str())
+        .map(|n| n.trim_end_matches('.'));
+    match (login, node) {
+        (Some(login), Some(node)) => Some(format!("{} ({})", login, node)),
+        (Some(one), None) | (None, Some(one)) => Some(one.to_string()),
+        (None, None) => None,
+    }
+}
+
+/// "aa:bb:cc:dd:ee:ff" in lower case, from any of the usual separators
+pub fn normalize_mac(mac: &str) -> Option<String> {
+    let hex: String = mac.chars().filter(|c| !matches!(c, ':' | '-' | '.')).collect();
+    if hex.len() != 12 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    let pairs: Vec<&str> = (0..12).step_by(2).map(|i| &hex[i..i + 2]).collect();
+    Some(pairs.join(":").to_lowercase())
+}
+
+/// Send the magic packet out of `interface` with etherwake, falling back to wakeonlan
+pub fn send(interface: &str, mac: &str) -> Result<(), String> {
+    let etherwake = sudo().args(["etherwake", "-i", interface, mac]).output();
+    if matches!(&etherwake, Ok(output) if output.status.success()) {
+        return Ok(());
+    }
+
+    // wakeonlan broadcasts on every interface, which covers the usual single-LAN setup
+    let output = Command::new("wakeonlan").arg(mac).output().map_err(|e| match etherwake {
+        Ok(output) => format!("etherwake failed: {}", String::from_utf8_lossy(&output.stderr).trim()),
+        Err(_) => format!("Neither etherwake nor wakeonlan could be run: {}", e),
+    })?;
+    if !output.status.success() {
+        return Err(format!("wakeonlan failed: {}", String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    Ok(())
+}
+
+// ============ AUDIT TRAIL ============
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct Wake {
+    pub woken_at: String,
+    pub mac_address: String,
+    pub device_name: Option<String>,
+    pub username: String,
+    pub source_ip: String,
+    pub origin: String,
+    pub tailscale_identity: Option<String>,
+    pub interface: String,
+    pub success: bool,
+    pub error: Option<String>,
+}
+
+impl Wake {
+    pub fn new(mac_address: &str, device_name: Option<&str>, username: &str, source_ip: IpAddr, origin: Origin) -> Self {
+        Wake {
+            woken_at: Utc::now().to_rfc3339(),
+            mac_address: mac_address.to_string(),
+            device_name: device_name.map(str::to_string),
+            username: username.to_string(),
+            source_ip: source_ip.to_canonical().to_string(),
+            origin: origin.as_str().to_string(),
+            tailscale_identity: None,
+            interface: String::new(),
+            success: false,
+            error: None,
+        }
+    }
+}
+
+pub async fn record(pool: &SqlitePool, wake: &Wake) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO wol_wakes (woken_at, mac_address, device_name, username, source_ip, origin, tailscale_identity, interface, success, error)
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+    )
+    .bind(&wake.woken_at)
+    .bind(&wake.mac_address)
+    .bind(&wake.device_name)
+    .bind(&wake.username)
+    .bind(&wake.source_ip)
+    .bind(&wake.origin)
+    .bind(&wake.tailscale_identity)
+    .bind(&wake.interface)
+    .bind(wake.success)
+    .bind(&wake.error)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Most recent wakes first
+pub async fn history(pool: &SqlitePool, limit: i64) -> Result<Vec<Wake>, sqlx::Error> {
+    sqlx::query_as(
+        "SELECT woken_at, mac_address, device_name, username, source_ip, origin, tailscale_identity, interface, success, error
+         FROM wol_wakes ORDER BY id DESC LIMIT ?",
+    )
+    .bind(limit.clamp(1, MAX_HISTORY))
+    .fetch_all(pool)
+    .await
+}
@@ -0,0 +1,367 @@
+// Firewall zones: the interface behind each role (see interfaces/) together with the interfaces
+// that belong with it, such as the WiFi interface and the bridge on the LAN. Each zone has a
+// policy for traffic to the router itself, allow rules that open single services on a zone whose
+// policy is drop (DHCP on the WAN, DNS on the guest network), and policies for traffic forwarded
+// from one zone to another. The rules live in two chains of their own, jumped to from the top of
+// INPUT and FORWARD while the firewall is on.
+
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+
+use crate::interfaces::{Role, Roles};
+use crate::system::commands::RecordedCommand;
+use crate::system::privileges::sudo;
+
+// Key in the settings table
+pub const SETTINGS_KEY: &str = "zones";
+const INPUT_CHAIN: &str = "ROUTERUI-ZONES";
+const FORWARD_CHAIN: &str = "ROUTERUI-ZONES-FWD";
+const MAX_MEMBERS: usize = 32;
+const MAX_ALLOW_RULES: usize = 64;
+const MAX_DESCRIPTION_LEN: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    Accept,
+    Drop,
+}
+
+/// An interface in a zone besides the one holding the role
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Member {
+    pub interface: String,
+    pub zone: Role,
+}
+
+/// What happens to traffic from a zone to the router itself
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InputPolicy {
+    pub zone: Role,
+    pub action: Action,
+}
+
+/// What happens to traffic forwarded from one zone to another. Accept leaves it to the rest of
+/// FORWARD (blocked IPs, parental controls and the like); pairs without a policy are accepted.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ForwardPolicy {
+    pub from: Role,
+    pub to: Role,
+    pub action: Action,
+}
+
+/// One service on the router opened to a zone
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AllowRule {
+    pub zone: Role,
+    // tcp, udp or icmp
+    pub protocol: String,
+    // None opens every port; icmp has none
+    #[serde(default)]
+    pub port: Option<u16>,
+    #[serde(default)]
+    pub description: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ZoneSettings {
+    pub members: Vec<Member>,
+    pub input: Vec<InputPolicy>,
+    pub forward: Vec<ForwardPolicy>,
+    pub allow: Vec<AllowRule>,
+}
+
+impl Default for ZoneSettings {
+    // What the firewall did before zones: the LAN trusted, DHCP renewals let in from the WAN.
+    // The guest network gets DHCP, DNS and ping, and neither it nor the WAN can reach the other
+    // zones. The LAN's WiFi and bridge depend on the router; see lan_members.
+    fn default() -> Self {
+        let input = |zone, action| InputPolicy { zone, action };
+        let forward = |from, to, action| ForwardPolicy { from, to, action };
+        let allow = |zone, protocol: &str, port, description: &str| AllowRule {
+            zone,
+            protocol: protocol.to_string(),
+            port,
+            description: description.to_string(),
+        };
+        Self {
+            members: Vec::new(),
+            input: vec![
+                input(Role::Wan, Action::Drop),
+                input(Role::Lan, Action::Accept),
+                input(Role::Guest, Action::Drop),
+                input(Role::Vpn, Action::Accept),
+            ],
+            forward: vec![
+                forward(Role::Wan, Role::Lan, Action::Drop),
+                forward(Role::Wan, Role::Guest, Action::Drop),
+                forward(Role::Wan, Role::Vpn, Action::Drop),
+                forward(Role::Guest, Role::Lan, Action::Drop),
+                forward(Role::Guest, Role::Vpn, Action::Drop),
+            ],
+            allow: vec![
+                allow(Role::Wan, "udp", Some(68), "DHCP renewals"),
+                allow(Role::Guest, "udp", Some(67), "DHCP"),
+                allow(Role::Guest, "udp", Some(53), "DNS"),
+                allow(Role::Guest, "tcp", Some(53), "DNS"),
+                allow(Role::Guest, "icmp", None, "Ping"),
+            ],
+        }
+    }
+}
+
+impl ZoneSettings {
+    /// Trim names and descriptions, lowercase protocols
+    pub fn normalize(&mut self) {
+        for member in &mut self.members {
+            member.interface = member.interface.trim().to_string();
+        }
+        for rule in &mut self.allow {
+            rule.protocol = rule.protocol.trim().to_lowercase();
+            rule.description = rule.description.trim().to_string();
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.members.len() > MAX_MEMBERS {
+            return Err(format!("At most {} extra interfaces", MAX_MEMBERS));
+        }
+        for (i, member) in self.members.iter().enumerate() {
+            if !crate::interfaces::valid_name(&member.interface) {
+                return Err(format!("'{}' is not a valid interface name", member.interface));
+            }
+            if let Some(other) = self.members[..i].iter().find(|m| m.interface == member.interface) {
+                return Err(format!("{} can't be in both {} and {}", member.interface, other.zone.as_str(), member.zone.as_str()));
+            }
+        }
+
+        for (i, policy) in self.input.iter().enumerate() {
+            if self.input[..i].iter().any(|p| p.zone == policy.zone) {
+                return Err(format!("The {} zone has more than one input policy", policy.zone.as_str()));
+            }
+        }
+        for (i, policy) in self.forward.iter().enumerate() {
+            if policy.from == policy.to {
+                return Err(format!("A policy from {} to itself has no effect", policy.from.as_str()));
+            }
+            if self.forward[..i].iter().any(|p| p.from == policy.from && p.to == policy.to) {
+                return Err(format!("More than one policy from {} to {}", policy.from.as_str(), policy.to.as_str()));
+            }
+        }
+
+        if self.allow.len() > MAX_ALLOW_RULES {
+            return Err(format!("At most {} allow rules", MAX_ALLOW_RULES));
+        }
+        for rule in &self.allow {
+            match (rule.protocol.as_str(), rule.port) {
+                ("icmp", Some(_)) => return Err("ICMP allow rules have no port".to_string()),
+                ("tcp" | "udp", Some(0)) => return Err("Port 0 can't be opened".to_string()),
+                ("tcp" | "udp" | "icmp", _) => {}
+                (other, _) => return Err(format!("Invalid protocol: {}", other)),
+            }
+            if rule.description.chars().count() > MAX_DESCRIPTION_LEN {
+                return Err(format!("Descriptions can be at most {} characters", MAX_DESCRIPTION_LEN));
+            }
+        }
+        Ok(())
+    }
+
+    /// Zones without a policy of their own are trusted, apart from the WAN
+    pub fn input_action(&self, zone: Role) -> Action {
+        match self.input.iter().find(|p| p.zone == zone) {
+            Some(policy) => policy.action,
+            None if zone == Role::Wan => Action::Drop,
+            None => Action::Accept,
+        }
+    }
+}
+
+// The interfaces that go with the LAN on this router: the bridge the LAN port is in (or the
+// LAN itself when it is the bridge), the bridge's other ports and the WiFi interface picked in
+// the setup wizard. Anything holding a role, the WAN above all, is left to its own zone.
+fn lan_members(roles: &Roles, wifi: Option<String>) -> Vec<String> {
+    let net = std::path::Path::new("/sys/class/net");
+    let bridge = std::fs::read_link(net.join(&roles.lan).join("master"))
+        .ok()
+        .and_then(|target| target.file_name()?.to_str().map(str::to_string))
+        .unwrap_or_else(|| roles.lan.clone());
+    let mut candidates = vec![bridge.clone()];
+    if let Ok(ports) = std::fs::read_dir(net.join(&bridge).join("brif")) {
+        candidates.extend(ports.filter_map(|p| p.ok()?.file_name().into_string().ok()));
+    }
+    candidates.extend(wifi);
+
+    let mut members: Vec<String> = Vec::new();
+    for name in candidates {
+        if roles.role_of(&name).is_none() && crate::interfaces::valid_name(&name) && !members.contains(&name) {
+            members.push(name);
+        }
+    }
+    members
+}
+
+async fn default_settings(pool: &SqlitePool) -> ZoneSettings {
+    let roles = crate::interfaces::roles(pool).await;
+    let wifi = sqlx::query_scalar::<_, String>("SELECT value FROM setup_config WHERE key = 'wifi_interface'")
+        .fetch_optional(pool)
+        .await
+        .ok()
+        .flatten();
+    ZoneSettings {
+        members: lan_members(&roles, wifi).into_iter().map(|interface| Member { interface, zone: Role::Lan }).collect(),
+        ..ZoneSettings::default()
+    }
+}
+
+pub async fn load_settings(pool: &SqlitePool) -> ZoneSettings {
+    match crate::db::settings::get(pool, SETTINGS_KEY).await {
+        Ok(Some(settings)) => settings,
+        Ok(None) => default_settings(pool).await,
+        Err(e) => {
+            tracing::warn!("Failed to read firewall zones, using the defaults: {}", e);
+            default_settings(pool).await
+        }
+    }
+}
+
+pub async fn save_settings(pool: &SqlitePool, settings: &ZoneSettings) -> Result<(), String> {
+    crate::db::settings::set(pool, SETTINGS_KEY, settings).await.map_err(|e| e.to_string())
+}
+
+/// The settings with the current roles, which is all it takes to tell interfaces apart
+#[derive(Debug, Clone)]
+pub struct Zones {
+    pub roles: Roles,
+    pub settings: ZoneSettings,
+}
+
+pub async fn load(pool: &SqlitePool) -> Zones {
+    Zones {
+        roles: crate::interfaces::roles(pool).await,
+        settings: load_settings(pool).await,
+    }
+}
+
+impl Zones {
+    /// Every interface in the zone, the one holding the role first
+    pub fn interfaces(&self, zone: Role) -> Vec<String> {
+        let mut interfaces: Vec<String> = self.roles.name_of(zone).map(str::to_string).into_iter().collect();
+        for member in self.settings.members.iter().filter(|m| m.zone == zone) {
+            if !interfaces.contains(&member.interface) {
+                interfaces.push(member.interface.clone());
+            }
+        }
+        interfaces
+    }
+
+    pub fn zone_of(&self, interface: &str) -> Option<Role> {
+        self.roles
+            .role_of(interface)
+            .or_else(|| self.settings.members.iter().find(|m| m.interface == interface).map(|m| m.zone))
+    }
+
+    pub fn wan(&self) -> &str {
+        &self.roles.wan
+    }
+
+    /// Whether the WAN reaches the router beyond the allow rules: its zone accepts, or the WAN
+    /// interface is also listed in a zone that does
+    pub fn wan_open(&self) -> bool {
+        self.settings.input_action(Role::Wan) == Action::Accept
+            || self
+                .settings
+                .members
+                .iter()
+                .any(|m| m.interface == self.roles.wan && self.settings.input_action(m.zone) == Action::Accept)
+    }
+
+    // Rules for the router itself (INPUT) and for traffic between zones (FORWARD). Traffic the
+    // INPUT chain doesn't accept returns to INPUT, where its policy drops it. Port forwards are
+    // let through FORWARD so their own rules decide.
+    fn chain_rules(&self) -> (Vec<Vec<String>>, Vec<Vec<String>>) {
+        let rule = |args: &[&str]| args.iter().map(|a| a.to_string()).collect::<Vec<_>>();
+        let mut input = vec![
+            rule(&["-i", "lo", "-j", "ACCEPT"]),
+            rule(&["-m", "state", "--state", "ESTABLISHED,RELATED", "-j", "ACCEPT"]),
+        ];
+        for allow in &self.settings.allow {
+            let port = allow.port.map(|p| p.to_string());
+            for interface in self.interfaces(allow.zone) {
+                let mut args = vec!["-i", &interface, "-p", &allow.protocol];
+                if let Some(port) = &port {
+                    args.extend(["--dport", port]);
+                }
+                args.extend(["-j", "ACCEPT"]);
+                input.push(rule(&args));
+            }
+        }
+        for zone in Role::ALL.into_iter().filter(|z| self.settings.input_action(*z) == Action::Accept) {
+            for interface in self.interfaces(zone) {
+                input.push(rule(&["-i", &interface, "-j", "ACCEPT"]));
+            }
+        }
+
+        let mut forward = vec![
+            rule(&["-m", "state", "--state", "ESTABLISHED,RELATED", "-j", "RETURN"]),
+            rule(&["-m", "conntrack", "--ctstate", "DNAT", "-j", "RETURN"]),
+        ];
+        for policy in self.settings.forward.iter().filter(|p| p.action == Action::Drop) {
+            for from in self.interfaces(policy.from) {
+                for to in self.interfaces(policy.to) {
+                    forward.push(rule(&["-i", &from, "-o", &to, "-j", "DROP"]));
+                }
+            }
+        }
+        (input, forward)
+    }
+}
+
+fn run(command: &mut RecordedCommand) -> Result<(), String> {
+    let output = command.output().map_err(|e| e.to_string())?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(String::from_utf8_lossy(&output.stderr).trim().to_string())
+    }
+}
+
+fn chain_exists(chain: &str) -> bool {
+    sudo().args(["iptables", "-S", chain]).output().map(|o| o.status.success()).unwrap_or(false)
+}
+
+/// Take the chains out (firewall off, factory reset)
+pub fn remove() {
+    for (parent, chain) in [("INPUT", INPUT_CHAIN), ("FORWARD", FORWARD_CHAIN)] {
+        while sudo().args(["iptables", "-D", parent, "-j", chain]).output().is_ok_and(|o| o.status.success()) {}
+        if chain_exists(chain) {
+            let _ = sudo().args(["iptables", "-F", chain]).output();
+            let _ = sudo().args(["iptables", "-X", chain]).output();
+        }
+    }
+}
+
+/// Rebuild the chains from the zones. Not saved: callers run it under the firewall's
+/// confirm-or-roll-back protection, or persist it themselves.
+pub fn apply(zones: &Zones) -> Result<(), String> {
+    remove();
+    let (input, forward) = zones.chain_rules();
+    for (parent, chain, rules) in [("INPUT", INPUT_CHAIN, input), ("FORWARD", FORWARD_CHAIN, forward)] {
+        run(sudo().args(["iptables", "-N", chain]))?;
+        for rule in &rules {
+            run(sudo().args(["iptables", "-A", chain]).args(rule))?;
+        }
+        run(sudo().args(["iptables", "-I", parent, "1", "-j", chain]))?;
+    }
+    Ok(())
+}
+
+/// Rebuild the chains and save them, when they are in place (the firewall is on). For interface
+/// role changes, after which the chains name the wrong interfaces.
+pub fn refresh(zones: &Zones) -> Result<(), String> {
+    if !chain_exists(INPUT_CHAIN) {
+        return Ok(());
+    }
+    apply(zones)?;
+    run(sudo().args(["netfilter-persistent", "save"]))
+}
@@ -0,0 +1,80 @@
+// A remembered device holds a refresh token (an HttpOnly cookie) that buys a new session when
+// the current one times out. Rather than teach every page about it, fetch is wrapped once: an
+// API call answered with 401 triggers a refresh and is retried if that worked; if it didn't,
+// the session is over and the browser goes to the sign-in page.
+//
+// The same wrapper adds the session's CSRF token to every change. The token belongs to the
+// session, so it is fetched again after signing in or refreshing, and when the server turns a
+// request down for a stale one.
+
+const REFRESH_URL = '/api/auth/refresh';
+const CSRF_URL = '/api/auth/csrf';
+const SKIP = [REFRESH_URL, '/api/auth/login', '/api/auth/logout'];
+const SAFE_METHODS = ['GET', 'HEAD', 'OPTIONS'];
+
+let installed = false;
+// Shared so a burst of 401s from a dashboard poll refreshes only once
+let refreshing = null;
+// { header, token } for the current session; token is null when none is needed
+let csrf = null;
+
+function refresh() {
+  refreshing ??= window
+    .fetch(REFRESH_URL, { method: 'POST', credentials: 'same-origin' })
+    .then((res) => res.ok)
+    .catch(() => false)
+    .finally(() => setTimeout(() => (refreshing = null), 0));
+  return refreshing;
+}
+
+export function installSessionRefresh() {
+  if (installed) return;
+  installed = true;
+  const originalFetch = window.fetch.bind(window);
+
+  async function csrfToken() {
+    csrf ??= originalFetch(CSRF_URL, { credentials: 'same-origin' })
+      .then((res) => (res.ok ? res.json() : null))
+      .catch(() => null);
+    return csrf;
+  }
+
+  // init with the CSRF header added, for changes to the API
+  async function withCsrf(input, init) {
+    const method = (init?.method ?? (input instanceof Request ? input.method : 'GET')).toUpperCase();
+    if (SAFE_METHODS.includes(method)) return init;
+    const current = await csrfToken();
+    if (!current?.token) return init;
+    const headers = new Headers(init?.headers ?? (input instanceof Request ? input.headers : undefined));
+    headers.set(current.header, current.token);
+    return { ...init, headers };
+  }
+
+  window.fetch = async (input, init) => {
+    const url = new URL(input instanceof Request ? input.url : input, window.location.href);
+    const api = url.origin === window.location.origin && url.pathname.startsWith('/api/');
+    const retryable = api
+      && !SKIP.includes(url.pathname)
+      // A streamed body can't be sent twice
+      && !(init?.body instanceof ReadableStream);
+    const retry = retryable && input instanceof Request ? input.clone() : input;
+
+    const res = await originalFetch(input, api ? await withCsrf(input, init) : init);
+    // A new session comes with a new CSRF token
+    if (api && SKIP.includes(url.pathname)) csrf = null;
+    if (res.status === 403 && retryable && res.headers.get('x-csrf-rejected')) {
+      csrf = null;
+      return originalFetch(retry, await withCsrf(retry, init));
+    }
+    if (res.status !== 401 || !retryable) return res;
+    if (!(await refresh())) {
+      // Signed out, timed out or revoked: back to the sign-in page
+      if (!['/login', '/setup'].some((p) => window.location.pathname.startsWith(p))) {
+        window.location.href = '/login';
+      }
+      return res;
+    }
+    csrf = null;
+    return originalFetch(retry, await withCsrf(retry, init));
+  };
+}
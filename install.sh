@@ -187,6 +187,10 @@ if [ "$BUILD_FROM_SOURCE" = true ]; then
     cargo build --release 2>/dev/null
     cp target/release/routerui-api $ROUTERUI_DIR/
     chmod +x $ROUTERUI_DIR/routerui-api
+    cp target/release/routerui-cli $ROUTERUI_DIR/ 2>/dev/null && \
+        ln -sf $ROUTERUI_DIR/routerui-cli /usr/local/bin/routerui-cli
+    # Root helper lives outside $ROUTERUI_DIR so the unprivileged service can't replace it
+    install -m 0755 -o root -g root target/release/routerui-helper /usr/local/sbin/routerui-helper 2>/dev/null || true
 
     # Build frontend
     echo "Building frontend..."
@@ -214,18 +218,74 @@ fi
 
 # Step 5: Create systemd service
 echo -e "${GREEN}[5/5]${NC} Setting up systemd service..."
-cat > /etc/systemd/system/routerui.service << 'EOF'
+mkdir -p /etc/routerui
+if [ ! -f /etc/routerui/config.toml ]; then
+    cat > /etc/routerui/config.toml << 'EOF'
+[server]
+port = 3080
+
+[database]
+path = "/opt/routerui/config/routerui.db"
+
+[paths]
+frontend_dir = "/opt/routerui/frontend/build"
+EOF
+fi
+
+# With the root helper installed, the web service runs as an unprivileged user and
+# asks routerui-helper for privileged operations over /run/routerui/helper.sock
+if [ -x /usr/local/sbin/routerui-helper ]; then
+    id routerui > /dev/null 2>&1 || \
+        useradd --system --home-dir $ROUTERUI_DIR --no-create-home --shell /usr/sbin/nologin routerui
+    usermod -aG systemd-journal,adm routerui 2>/dev/null || true
+    mkdir -p $ROUTERUI_DIR/{config,backups,blocklists,quarantine,restored,scan-logs}
+    chown routerui:routerui $ROUTERUI_DIR
+    chown -R routerui:routerui $ROUTERUI_DIR/{config,backups,blocklists,quarantine,restored,scan-logs}
+    # State files left root-owned by installs that predate the helper
+    for f in $ROUTERUI_DIR/*.json $ROUTERUI_DIR/*.mmdb; do
+        if [ -f "$f" ]; then chown routerui:routerui "$f"; fi
+    done
+    chown root:root $ROUTERUI_DIR/routerui-api $ROUTERUI_DIR/routerui-cli 2>/dev/null || true
+
+    if ! grep -q '^\[helper\]' /etc/routerui/config.toml; then
+        printf '\n[helper]\nsocket = "/run/routerui/helper.sock"\ngroup = "routerui"\n' >> /etc/routerui/config.toml
+    fi
+
+    cat > /etc/systemd/system/routerui-helper.service << 'EOF'
+[Unit]
+Description=RouterUI privileged helper
+Before=routerui.service
+
+[Service]
+ExecStart=/usr/local/sbin/routerui-helper serve
+Restart=always
+RestartSec=2
+
+[Install]
+WantedBy=multi-user.target
+EOF
+
+    SERVICE_USER="User=routerui
+Group=routerui"
+    SERVICE_DEPS="Requires=routerui-helper.service
+After=network.target routerui-helper.service"
+    USE_HELPER=1
+else
+    SERVICE_USER=""
+    SERVICE_DEPS="After=network.target"
+fi
+
+cat > /etc/systemd/system/routerui.service << EOF
 [Unit]
 Description=RouterUI Web Interface
-After=network.target
+$SERVICE_DEPS
 
 [Service]
-Type=simple
+Type=notify
+WatchdogSec=60
 ExecStart=/opt/routerui/routerui-api
 WorkingDirectory=/opt/routerui
-Environment=DATABASE_URL=sqlite:/opt/routerui/config/routerui.db?mode=rwc
-Environment=FRONTEND_DIR=/opt/routerui/frontend/build
-Environment=ROUTERUI_PORT=3080
+$SERVICE_USER
 Restart=always
 RestartSec=5
 
@@ -234,6 +294,10 @@ WantedBy=multi-user.target
 EOF
 
 systemctl daemon-reload
+if [ -n "$USE_HELPER" ]; then
+    systemctl enable routerui-helper > /dev/null 2>&1
+    systemctl restart routerui-helper
+fi
 systemctl enable routerui > /dev/null 2>&1
 systemctl start routerui
 